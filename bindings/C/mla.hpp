@@ -35,9 +35,27 @@ enum class MLAStatus : uint64_t {
   MLA_STATUS_CONFIG_ERROR_PRIVATE_KEY_NOT_SET = 1310724,
   MLA_STATUS_CONFIG_ERROR_PRIVATE_KEY_NOT_FOUND = 1310725,
   MLA_STATUS_CONFIG_ERROR_ECIES_COMPUTATION_ERROR = 1310726,
+  MLA_STATUS_CONFIG_ERROR_INVALID_THRESHOLD = 1310727,
+  MLA_STATUS_CONFIG_ERROR_INVALID_REKEY_INTERVAL = 1310728,
+  MLA_STATUS_CONFIG_ERROR_INVALID_PADDING_SIZE = 1310729,
+  MLA_STATUS_CONFIG_ERROR_SIGNING_KEY_IS_MISSING = 1310730,
+  MLA_STATUS_CONFIG_ERROR_SIGNATURE_NOT_FOUND = 1310731,
+  MLA_STATUS_CONFIG_ERROR_INVALID_FEC_SHARDS = 1310732,
+  MLA_STATUS_CONFIG_ERROR_INVALID_CHECKPOINT_INTERVAL = 1310733,
+  MLA_STATUS_CONFIG_ERROR_INVALID_COMPRESSION_THREADS = 1310734,
+  MLA_STATUS_CONFIG_ERROR_ANONYMOUS_RECIPIENT_HINTS = 1310735,
+  MLA_STATUS_CONFIG_ERROR_NO_MATCHING_RECIPIENT_KEY = 1310736,
+  MLA_STATUS_CONFIG_ERROR_DETERMINISTIC_SEED_WITH_EXISTING_KEY = 1310737,
   MLA_STATUS_DUPLICATE_FILENAME = 1376256,
   MLA_STATUS_AUTHENTICATED_DECRYPTION_WRONG_TAG = 1441792,
   MLA_STATUS_HKDF_INVALID_KEY_LENGTH = 1507328,
+  MLA_STATUS_INVALID_SIGNATURE = 1572864,
+  MLA_STATUS_UNTRUSTED_SIGNATURE = 1638400,
+  MLA_STATUS_UNRECOVERABLE_CORRUPTION = 1703936,
+  MLA_STATUS_WRONG_BLOCK_TAG = 1769472,
+  MLA_STATUS_DECOMPRESSION_LIMIT_EXCEEDED = 1835008,
+  MLA_STATUS_TOO_MANY_ENTRIES = 1900544,
+  MLA_STATUS_FOOTER_TOO_LARGE = 1966080,
   MLA_STATUS_CURVE25519_PARSER_ERROR = 15794176,
 };
 
@@ -51,6 +69,22 @@ using MLAArchiveHandle = void*;
 
 using MLAArchiveFileHandle = void*;
 
+/// Read up to the given number of bytes in the buffer, and set the number of
+/// bytes read (0 at the end of the input)
+using MLAReadCallback = int32_t(*)(uint8_t*, uintptr_t, void*, uintptr_t*);
+
+/// Move to the given offset, relative to the start (0), the current position
+/// (1) or the end (2) of the input, as with SEEK_SET, SEEK_CUR and SEEK_END,
+/// and set the new position from the start of the input
+using MLASeekCallback = int32_t(*)(int64_t, int32_t, void*, uint64_t*);
+
+using MLAReaderConfigHandle = void*;
+
+using MLAReaderHandle = void*;
+
+/// Called with the NULL-terminated name of each file of an archive
+using MLAFileCallback = int32_t(*)(const char*, void*);
+
 extern "C" {
 
 /// Create a new configuration with default options, and return a handle to it.
@@ -111,4 +145,50 @@ MLAStatus mla_archive_file_close(MLAArchiveHandle archive, MLAArchiveFileHandle
 /// or an error code.
 MLAStatus mla_archive_close(MLAArchiveHandle *archive);
 
+/// Create a new configuration to read archives, without any key, and return
+/// a handle to it.
+MLAStatus mla_reader_config_new(MLAReaderConfigHandle *handle_out);
+
+/// Appends the given NULL-terminated private key, in PEM format, to an
+/// existing given configuration (referenced by the handle returned by
+/// mla_reader_config_new()).
+MLAStatus mla_reader_config_add_private_key(MLAReaderConfigHandle config, const char *private_key);
+
+/// Open an existing MLA archive using the given configuration, which is
+/// consumed and freed (its handle cannot be reused to open another archive).
+/// The archive is read through the read_callback, moving in it with the
+/// seek_callback. The context pointer can be used to hold any information,
+/// and is passed as an argument when any of the two callbacks are called.
+MLAStatus mla_roarchive_new(MLAReaderConfigHandle *config,
+                            MLAReadCallback read_callback,
+                            MLASeekCallback seek_callback,
+                            void *context,
+                            MLAReaderHandle *handle_out);
+
+/// Call file_callback with the name of each file of the archive identified
+/// by the handle returned by mla_roarchive_new(), in no particular order.
+/// The context pointer is passed as an argument of each call. A non-zero
+/// value returned by the callback stops the listing, with
+/// MLA_STATUS_IO_ERROR. Returns MLA_STATUS_SUCCESS on success, or an error
+/// code.
+MLAStatus mla_roarchive_list(MLAReaderHandle archive,
+                             MLAFileCallback file_callback,
+                             void *context);
+
+/// Stream the content of the file named by the given NULL-terminated string,
+/// in the archive identified by the handle returned by mla_roarchive_new(),
+/// to the write_callback. The context pointer is passed as an argument of
+/// each call. Returns MLA_STATUS_SUCCESS on success, MLA_STATUS_BAD_API_ARGUMENT
+/// if there is no such file, or an error code.
+MLAStatus mla_roarchive_file_read(MLAReaderHandle archive,
+                                  const char *file_name,
+                                  MLAWriteCallback write_callback,
+                                  void *context);
+
+/// Close the given archive, opened with mla_roarchive_new(), and free any
+/// allocated resource. The archive handle must be passed as a mutable
+/// reference so it is cleared and cannot be reused after free by accident.
+/// Returns MLA_STATUS_SUCCESS on success, or an error code.
+MLAStatus mla_roarchive_close(MLAReaderHandle *archive);
+
 } // extern "C"
@@ -1,13 +1,13 @@
 #![allow(clippy::not_unsafe_ptr_arg_deref)]
-use curve25519_parser::parse_openssl_25519_pubkeys_pem_many;
-use mla::config::ArchiveWriterConfig;
+use curve25519_parser::{parse_openssl_25519_privkey, parse_openssl_25519_pubkeys_pem_many};
+use mla::config::{ArchiveReaderConfig, ArchiveWriterConfig};
 use mla::errors::ConfigError;
 use mla::errors::Error as MLAError;
-use mla::ArchiveWriter;
 use mla::{ArchiveFileID, Layers};
+use mla::{ArchiveReader, ArchiveWriter};
 use std::convert::TryFrom;
-use std::ffi::{c_void, CStr};
-use std::io::Write;
+use std::ffi::{c_void, CStr, CString};
+use std::io::{Read, Seek, SeekFrom, Write};
 use std::os::raw::c_char;
 use std::ptr::null_mut;
 
@@ -41,13 +41,40 @@ pub enum MLAStatus {
     ConfigErrorPrivateKeyNotSet = 0x140004,
     ConfigErrorPrivateKeyNotFound = 0x140005,
     ConfigErrorECIESComputationError = 0x140006,
+    ConfigErrorInvalidThreshold = 0x140007,
+    ConfigErrorInvalidRekeyInterval = 0x140008,
+    ConfigErrorInvalidPaddingSize = 0x140009,
+    ConfigErrorSigningKeyIsMissing = 0x14000A,
+    ConfigErrorSignatureNotFound = 0x14000B,
+    ConfigErrorInvalidFecShards = 0x14000C,
+    ConfigErrorInvalidCheckpointInterval = 0x14000D,
+    ConfigErrorInvalidCompressionThreads = 0x14000E,
+    ConfigErrorAnonymousRecipientHints = 0x14000F,
+    ConfigErrorNoMatchingRecipientKey = 0x140010,
+    ConfigErrorDeterministicSeedWithExistingKey = 0x140011,
     DuplicateFilename = 0x150000,
     AuthenticatedDecryptionWrongTag = 0x160000,
     HKDFInvalidKeyLength = 0x170000,
+    InvalidSignature = 0x180000,
+    UntrustedSignature = 0x190000,
+    UnrecoverableCorruption = 0x1A0000,
+    WrongBlockTag = 0x1B0000,
+    DecompressionLimitExceeded = 0x1C0000,
+    TooManyEntries = 0x1D0000,
+    FooterTooLarge = 0x1E0000,
     Curve25519ParserError = 0xF10000,
 }
 pub type MLAWriteCallback = extern "C" fn(*const u8, usize, *mut c_void) -> i32;
 pub type MLAFlushCallback = extern "C" fn(*mut c_void) -> i32;
+/// Read up to the given number of bytes in the buffer, and set the number of
+/// bytes read (0 at the end of the input)
+pub type MLAReadCallback = extern "C" fn(*mut u8, usize, *mut c_void, *mut usize) -> i32;
+/// Move to the given offset, relative to the start (0), the current position
+/// (1) or the end (2) of the input, as with SEEK_SET, SEEK_CUR and SEEK_END,
+/// and set the new position from the start of the input
+pub type MLASeekCallback = extern "C" fn(i64, i32, *mut c_void, *mut u64) -> i32;
+/// Called with the NULL-terminated name of each file of an archive
+pub type MLAFileCallback = extern "C" fn(*const c_char, *mut c_void) -> i32;
 
 impl From<MLAError> for MLAStatus {
     fn from(err: MLAError) -> Self {
@@ -92,9 +119,49 @@ impl From<MLAError> for MLAStatus {
             MLAError::ConfigError(ConfigError::ECIESComputationError) => {
                 MLAStatus::ConfigErrorECIESComputationError
             }
+            MLAError::ConfigError(ConfigError::InvalidThreshold) => {
+                MLAStatus::ConfigErrorInvalidThreshold
+            }
+            MLAError::ConfigError(ConfigError::InvalidRekeyInterval) => {
+                MLAStatus::ConfigErrorInvalidRekeyInterval
+            }
+            MLAError::ConfigError(ConfigError::InvalidPaddingSize) => {
+                MLAStatus::ConfigErrorInvalidPaddingSize
+            }
+            MLAError::ConfigError(ConfigError::SigningKeyIsMissing) => {
+                MLAStatus::ConfigErrorSigningKeyIsMissing
+            }
+            MLAError::ConfigError(ConfigError::SignatureNotFound) => {
+                MLAStatus::ConfigErrorSignatureNotFound
+            }
+            MLAError::ConfigError(ConfigError::InvalidFecShards) => {
+                MLAStatus::ConfigErrorInvalidFecShards
+            }
+            MLAError::ConfigError(ConfigError::InvalidCheckpointInterval) => {
+                MLAStatus::ConfigErrorInvalidCheckpointInterval
+            }
+            MLAError::ConfigError(ConfigError::InvalidCompressionThreads) => {
+                MLAStatus::ConfigErrorInvalidCompressionThreads
+            }
+            MLAError::ConfigError(ConfigError::AnonymousRecipientHints) => {
+                MLAStatus::ConfigErrorAnonymousRecipientHints
+            }
+            MLAError::ConfigError(ConfigError::NoMatchingRecipientKey) => {
+                MLAStatus::ConfigErrorNoMatchingRecipientKey
+            }
+            MLAError::ConfigError(ConfigError::DeterministicSeedWithExistingKey) => {
+                MLAStatus::ConfigErrorDeterministicSeedWithExistingKey
+            }
             MLAError::DuplicateFilename => MLAStatus::DuplicateFilename,
             MLAError::AuthenticatedDecryptionWrongTag => MLAStatus::AuthenticatedDecryptionWrongTag,
             MLAError::HKDFInvalidKeyLength => MLAStatus::HKDFInvalidKeyLength,
+            MLAError::InvalidSignature => MLAStatus::InvalidSignature,
+            MLAError::UntrustedSignature => MLAStatus::UntrustedSignature,
+            MLAError::UnrecoverableCorruption => MLAStatus::UnrecoverableCorruption,
+            MLAError::WrongBlockTag => MLAStatus::WrongBlockTag,
+            MLAError::DecompressionLimitExceeded => MLAStatus::DecompressionLimitExceeded,
+            MLAError::TooManyEntries => MLAStatus::TooManyEntries,
+            MLAError::FooterTooLarge => MLAStatus::FooterTooLarge,
         }
     }
 }
@@ -105,6 +172,8 @@ impl From<MLAError> for MLAStatus {
 pub type MLAConfigHandle = *mut c_void;
 pub type MLAArchiveHandle = *mut c_void;
 pub type MLAArchiveFileHandle = *mut c_void;
+pub type MLAReaderConfigHandle = *mut c_void;
+pub type MLAReaderHandle = *mut c_void;
 
 // Internal struct definition to create a Write-able from function pointers
 
@@ -130,6 +199,44 @@ impl Write for CallbackOutput {
     }
 }
 
+// Internal struct definition to create a Read-able and Seek-able from
+// function pointers
+
+struct CallbackInput {
+    read_callback: MLAReadCallback,
+    seek_callback: MLASeekCallback,
+    context: *mut c_void,
+}
+
+impl Read for CallbackInput {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        let mut bytes_read: usize = 0;
+        match (self.read_callback)(buf.as_mut_ptr(), buf.len(), self.context, &mut bytes_read) {
+            0 if bytes_read <= buf.len() => Ok(bytes_read),
+            0 => Err(std::io::Error::from(std::io::ErrorKind::InvalidData)),
+            e => Err(std::io::Error::from_raw_os_error(e)),
+        }
+    }
+}
+
+impl Seek for CallbackInput {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, std::io::Error> {
+        let (offset, whence) = match pos {
+            SeekFrom::Start(offset) => match i64::try_from(offset) {
+                Ok(offset) => (offset, 0),
+                Err(_) => return Err(std::io::Error::from(std::io::ErrorKind::InvalidInput)),
+            },
+            SeekFrom::Current(offset) => (offset, 1),
+            SeekFrom::End(offset) => (offset, 2),
+        };
+        let mut position: u64 = 0;
+        match (self.seek_callback)(offset, whence, self.context, &mut position) {
+            0 => Ok(position),
+            e => Err(std::io::Error::from_raw_os_error(e)),
+        }
+    }
+}
+
 // The actual C API exposed to external callers
 
 /// Create a new configuration with default options, and return a handle to it.
@@ -386,3 +493,210 @@ pub extern "C" fn mla_archive_close(archive: *mut MLAArchiveHandle) -> MLAStatus
         Err(e) => MLAStatus::from(e),
     }
 }
+
+/// Create a new configuration to read archives, without any key, and return
+/// a handle to it.
+#[no_mangle]
+pub extern "C" fn mla_reader_config_new(handle_out: *mut MLAReaderConfigHandle) -> MLAStatus {
+    if handle_out.is_null() {
+        return MLAStatus::BadAPIArgument;
+    }
+
+    let ptr = Box::into_raw(Box::new(ArchiveReaderConfig::new()));
+    unsafe {
+        *handle_out = ptr as MLAReaderConfigHandle;
+    }
+    MLAStatus::Success
+}
+
+/// Appends the given NULL-terminated private key, in PEM format, to an
+/// existing given configuration (referenced by the handle returned by
+/// mla_reader_config_new()).
+#[no_mangle]
+pub extern "C" fn mla_reader_config_add_private_key(
+    config: MLAReaderConfigHandle,
+    private_key: *const c_char,
+) -> MLAStatus {
+    if config.is_null() || private_key.is_null() {
+        return MLAStatus::BadAPIArgument;
+    }
+
+    let mut config = unsafe { Box::from_raw(config as *mut ArchiveReaderConfig) };
+
+    let private_key = unsafe { CStr::from_ptr(private_key) }.to_bytes();
+    let res = match parse_openssl_25519_privkey(private_key) {
+        Ok(private_key) => {
+            config.add_private_keys(&[private_key]);
+            MLAStatus::Success
+        }
+        Err(_) => MLAStatus::Curve25519ParserError,
+    };
+
+    Box::leak(config);
+    res
+}
+
+/// Open an existing MLA archive using the given configuration, which is
+/// consumed and freed (its handle cannot be reused to open another archive).
+/// The archive is read through the read_callback, moving in it with the
+/// seek_callback. The context pointer can be used to hold any information,
+/// and is passed as an argument when any of the two callbacks are called.
+#[no_mangle]
+pub extern "C" fn mla_roarchive_new(
+    config: *mut MLAReaderConfigHandle,
+    read_callback: Option<MLAReadCallback>,
+    seek_callback: Option<MLASeekCallback>,
+    context: *mut c_void,
+    handle_out: *mut MLAReaderHandle,
+) -> MLAStatus {
+    let (read_callback, seek_callback) = match (read_callback, seek_callback) {
+        (Some(read_callback), Some(seek_callback)) => (read_callback, seek_callback),
+        _ => return MLAStatus::BadAPIArgument,
+    };
+    if config.is_null() || handle_out.is_null() {
+        return MLAStatus::BadAPIArgument;
+    }
+    let config_ptr = unsafe { *(config as *mut *mut ArchiveReaderConfig) };
+    if config_ptr.is_null() {
+        return MLAStatus::BadAPIArgument;
+    }
+    // Avoid any use-after-free of this handle by the caller
+    unsafe {
+        *config = null_mut();
+    }
+    let config = unsafe { Box::from_raw(config_ptr) };
+
+    let input = CallbackInput {
+        read_callback,
+        seek_callback,
+        context,
+    };
+
+    let mla: ArchiveReader<CallbackInput> = match ArchiveReader::from_config(input, *config) {
+        Ok(mla) => mla,
+        Err(e) => {
+            return MLAStatus::from(e);
+        }
+    };
+
+    let ptr = Box::into_raw(Box::new(mla));
+    unsafe {
+        *handle_out = ptr as MLAReaderHandle;
+    }
+    MLAStatus::Success
+}
+
+/// Call file_callback with the name of each file of the archive identified
+/// by the handle returned by mla_roarchive_new(), in no particular order.
+/// The context pointer is passed as an argument of each call. A non-zero
+/// value returned by the callback stops the listing, with
+/// MLA_STATUS_IO_ERROR. Returns MLA_STATUS_SUCCESS on success, or an error
+/// code.
+#[no_mangle]
+pub extern "C" fn mla_roarchive_list(
+    archive: MLAReaderHandle,
+    file_callback: Option<MLAFileCallback>,
+    context: *mut c_void,
+) -> MLAStatus {
+    let file_callback = match file_callback {
+        Some(file_callback) => file_callback,
+        None => return MLAStatus::BadAPIArgument,
+    };
+    if archive.is_null() {
+        return MLAStatus::BadAPIArgument;
+    }
+
+    let archive = unsafe { Box::from_raw(archive as *mut ArchiveReader<CallbackInput>) };
+    let res = match archive.list_files() {
+        Ok(fnames) => {
+            let mut res = MLAStatus::Success;
+            for fname in fnames {
+                // Names are UTF-8 strings, which may hold a NULL character
+                let fname = match CString::new(fname.as_bytes()) {
+                    Ok(fname) => fname,
+                    Err(_) => continue,
+                };
+                let e = file_callback(fname.as_ptr(), context);
+                if e != 0 {
+                    res = MLAStatus::from(MLAError::IOError(std::io::Error::from_raw_os_error(e)));
+                    break;
+                }
+            }
+            res
+        }
+        Err(e) => MLAStatus::from(e),
+    };
+    Box::leak(archive);
+    res
+}
+
+/// Stream the content of the file named by the given NULL-terminated string,
+/// in the archive identified by the handle returned by mla_roarchive_new(),
+/// to the write_callback. The context pointer is passed as an argument of
+/// each call. Returns MLA_STATUS_SUCCESS on success, MLA_STATUS_BAD_API_ARGUMENT
+/// if there is no such file, or an error code.
+#[no_mangle]
+pub extern "C" fn mla_roarchive_file_read(
+    archive: MLAReaderHandle,
+    file_name: *const c_char,
+    write_callback: Option<MLAWriteCallback>,
+    context: *mut c_void,
+) -> MLAStatus {
+    let write_callback = match write_callback {
+        Some(write_callback) => write_callback,
+        None => return MLAStatus::BadAPIArgument,
+    };
+    if archive.is_null() || file_name.is_null() {
+        return MLAStatus::BadAPIArgument;
+    }
+    let file_name = unsafe { CStr::from_ptr(file_name) }.to_string_lossy();
+
+    let mut archive = unsafe { Box::from_raw(archive as *mut ArchiveReader<CallbackInput>) };
+    let res = match archive.get_file(file_name.to_string()) {
+        Ok(Some(file)) => {
+            let mut data = file.data;
+            let mut buf = vec![0u8; 64 * 1024];
+            loop {
+                match data.read(&mut buf) {
+                    Ok(0) => break MLAStatus::Success,
+                    Ok(n) => match write_callback(buf.as_ptr(), n, context) {
+                        0 => continue,
+                        e => {
+                            break MLAStatus::from(MLAError::IOError(
+                                std::io::Error::from_raw_os_error(e),
+                            ))
+                        }
+                    },
+                    Err(e) => break MLAStatus::from(MLAError::IOError(e)),
+                }
+            }
+        }
+        Ok(None) => MLAStatus::BadAPIArgument,
+        Err(e) => MLAStatus::from(e),
+    };
+    Box::leak(archive);
+    res
+}
+
+/// Close the given archive, opened with mla_roarchive_new(), and free any
+/// allocated resource. The archive handle must be passed as a mutable
+/// reference so it is cleared and cannot be reused after free by accident.
+/// Returns MLA_STATUS_SUCCESS on success, or an error code.
+#[no_mangle]
+pub extern "C" fn mla_roarchive_close(archive: *mut MLAReaderHandle) -> MLAStatus {
+    if archive.is_null() {
+        return MLAStatus::BadAPIArgument;
+    }
+    let handle = unsafe { *archive };
+    if handle.is_null() {
+        return MLAStatus::BadAPIArgument;
+    }
+
+    // Avoid any use-after-free of this handle by the caller
+    unsafe {
+        *archive = null_mut();
+    }
+
+    drop(unsafe { Box::from_raw(handle as *mut ArchiveReader<CallbackInput>) });
+    MLAStatus::Success
+}
@@ -0,0 +1,50 @@
+using System;
+using System.IO;
+using System.Linq;
+using System.Text;
+using Mla;
+using Xunit;
+
+namespace Mla.Tests
+{
+    public class ArchiveTests
+    {
+        private static readonly string PublicKey = File.ReadAllText("test_ed25519_pub.pem");
+        private static readonly string PrivateKey = File.ReadAllText("test_ed25519.pem");
+
+        [Fact]
+        public void WriteThenRead()
+        {
+            var big = Enumerable.Range(0, 5 * 1024 * 1024).Select(i => (byte)(i % 251)).ToArray();
+            var archive = new MemoryStream();
+            using (var writer = new MlaArchiveWriter(archive, new[] { PublicKey }))
+            {
+                writer.AddFile("hello.txt", Encoding.UTF8.GetBytes("Hello, World!\n"));
+                writer.AddFile("big.bin", new MemoryStream(big));
+            }
+
+            archive.Position = 0;
+            using (var reader = new MlaArchiveReader(archive, new[] { PrivateKey }))
+            {
+                Assert.Equal(new[] { "big.bin", "hello.txt" }, reader.ListFiles().OrderBy(name => name));
+                Assert.Equal("Hello, World!\n", Encoding.UTF8.GetString(reader.ReadFile("hello.txt")));
+                Assert.Equal(big, reader.ReadFile("big.bin"));
+                Assert.Throws<FileNotFoundException>(() => reader.ReadFile("missing"));
+            }
+        }
+
+        [Fact]
+        public void WrongKey()
+        {
+            var archive = new MemoryStream();
+            using (var writer = new MlaArchiveWriter(archive, new[] { PublicKey }))
+            {
+                writer.AddFile("hello.txt", Encoding.UTF8.GetBytes("Hello, World!\n"));
+            }
+
+            archive.Position = 0;
+            Assert.Throws<MlaException>(() => new MlaArchiveReader(archive, Array.Empty<string>()));
+            Assert.Throws<MlaException>(() => new MlaArchiveWriter(new MemoryStream(), new[] { "not a key" }));
+        }
+    }
+}
@@ -0,0 +1,177 @@
+using System;
+using System.Collections.Generic;
+using System.IO;
+using System.Runtime.InteropServices;
+
+namespace Mla
+{
+    /// <summary>
+    /// Reader of an existing MLA archive, from a seekable <see cref="Stream"/>
+    /// </summary>
+    public sealed class MlaArchiveReader : IDisposable
+    {
+        // EIO, reported to the library when the input, or a destination, fails
+        private const int IoError = 5;
+
+        private readonly Stream input;
+        // Kept as fields, so that they are not collected while in use by the library
+        private readonly Native.ReadCallback readCallback;
+        private readonly Native.SeekCallback seekCallback;
+        private IntPtr handle;
+        // Error raised by a stream, if any, reported once the library returns
+        private Exception streamError;
+
+        /// <summary>
+        /// Open the archive read from <paramref name="input"/>, with the private keys
+        /// <paramref name="privateKeys"/>, the content of PEM files as accepted by <c>mlar -k</c>
+        /// </summary>
+        public MlaArchiveReader(Stream input, IEnumerable<string> privateKeys)
+        {
+            this.input = input ?? throw new ArgumentNullException(nameof(input));
+            if (!input.CanSeek)
+            {
+                throw new ArgumentException("The input must be seekable", nameof(input));
+            }
+            readCallback = Read;
+            seekCallback = Seek;
+
+            Native.Check(Native.mla_reader_config_new(out var config), "Configuration creation");
+            foreach (var privateKey in privateKeys)
+            {
+                Native.Check(Native.mla_reader_config_add_private_key(config, Native.ToCString(privateKey)), "Private key addition");
+            }
+            // The configuration is consumed, even on failure
+            Call(Native.mla_roarchive_new(ref config, readCallback, seekCallback, IntPtr.Zero, out handle), "Archive opening");
+        }
+
+        /// <summary>Names of the files of the archive, in no particular order</summary>
+        public IReadOnlyList<string> ListFiles()
+        {
+            EnsureOpen();
+            var names = new List<string>();
+            Native.FileCallback fileCallback = (fileName, context) =>
+            {
+                names.Add(PtrToStringUtf8(fileName));
+                return 0;
+            };
+            Call(Native.mla_roarchive_list(handle, fileCallback, IntPtr.Zero), "Archive listing");
+            GC.KeepAlive(fileCallback);
+            return names;
+        }
+
+        /// <summary>Write the content of the file <paramref name="name"/> to <paramref name="destination"/></summary>
+        /// <exception cref="FileNotFoundException">There is no such file in the archive</exception>
+        public void ReadFile(string name, Stream destination)
+        {
+            EnsureOpen();
+            Native.WriteCallback writeCallback = (buffer, length, context) =>
+            {
+                try
+                {
+                    var data = new byte[(int)length];
+                    Marshal.Copy(buffer, data, 0, data.Length);
+                    destination.Write(data, 0, data.Length);
+                    return 0;
+                }
+                catch (Exception e)
+                {
+                    streamError = e;
+                    return IoError;
+                }
+            };
+            var status = Native.mla_roarchive_file_read(handle, Native.ToCString(name), writeCallback, IntPtr.Zero);
+            GC.KeepAlive(writeCallback);
+            if (status == Native.BadApiArgument)
+            {
+                throw new FileNotFoundException("No such file in the archive", name);
+            }
+            Call(status, "File read");
+        }
+
+        /// <summary>Content of the file <paramref name="name"/></summary>
+        /// <exception cref="FileNotFoundException">There is no such file in the archive</exception>
+        public byte[] ReadFile(string name)
+        {
+            using (var content = new MemoryStream())
+            {
+                ReadFile(name, content);
+                return content.ToArray();
+            }
+        }
+
+        /// <summary>Release the archive</summary>
+        public void Dispose()
+        {
+            if (handle != IntPtr.Zero)
+            {
+                Native.Check(Native.mla_roarchive_close(ref handle), "Archive close");
+            }
+        }
+
+        private int Read(IntPtr buffer, UIntPtr length, IntPtr context, out UIntPtr bytesRead)
+        {
+            bytesRead = UIntPtr.Zero;
+            try
+            {
+                var data = new byte[(int)length];
+                var count = input.Read(data, 0, data.Length);
+                Marshal.Copy(data, 0, buffer, count);
+                bytesRead = (UIntPtr)count;
+                return 0;
+            }
+            catch (Exception e)
+            {
+                streamError = e;
+                return IoError;
+            }
+        }
+
+        private int Seek(long offset, int whence, IntPtr context, out ulong newPosition)
+        {
+            newPosition = 0;
+            try
+            {
+                // As SEEK_SET, SEEK_CUR and SEEK_END
+                var origin = whence == 0 ? SeekOrigin.Begin : whence == 1 ? SeekOrigin.Current : SeekOrigin.End;
+                newPosition = (ulong)input.Seek(offset, origin);
+                return 0;
+            }
+            catch (Exception e)
+            {
+                streamError = e;
+                return IoError;
+            }
+        }
+
+        private static string PtrToStringUtf8(IntPtr value)
+        {
+            var length = 0;
+            while (Marshal.ReadByte(value, length) != 0)
+            {
+                length++;
+            }
+            var bytes = new byte[length];
+            Marshal.Copy(value, bytes, 0, length);
+            return System.Text.Encoding.UTF8.GetString(bytes);
+        }
+
+        private void Call(ulong status, string operation)
+        {
+            if (streamError != null)
+            {
+                var error = streamError;
+                streamError = null;
+                throw new IOException($"{operation} failed on a stream", error);
+            }
+            Native.Check(status, operation);
+        }
+
+        private void EnsureOpen()
+        {
+            if (handle == IntPtr.Zero)
+            {
+                throw new ObjectDisposedException(nameof(MlaArchiveReader));
+            }
+        }
+    }
+}
@@ -0,0 +1,149 @@
+using System;
+using System.Collections.Generic;
+using System.IO;
+using System.Runtime.InteropServices;
+
+namespace Mla
+{
+    /// <summary>
+    /// Writer of a new MLA archive, streamed to a <see cref="Stream"/>
+    /// </summary>
+    /// <remarks>
+    /// The archive is only complete once <see cref="Close"/> is called
+    /// </remarks>
+    public sealed class MlaArchiveWriter : IDisposable
+    {
+        // EIO, reported to the library when the output fails
+        private const int IoError = 5;
+        private const int BufferSize = 64 * 1024;
+
+        private readonly Stream output;
+        // Kept as fields, so that they are not collected while in use by the library
+        private readonly Native.WriteCallback writeCallback;
+        private readonly Native.FlushCallback flushCallback;
+        private IntPtr handle;
+        // Error raised by the output, if any, reported once the library returns
+        private Exception outputError;
+
+        /// <summary>
+        /// Start an archive written to <paramref name="output"/>, readable with the private keys
+        /// of <paramref name="publicKeys"/>, the content of PEM files as accepted by <c>mlar -p</c>
+        /// </summary>
+        /// <param name="compressionLevel">From 0 to 11, denser but slower for bigger values</param>
+        public MlaArchiveWriter(Stream output, IEnumerable<string> publicKeys, uint? compressionLevel = null)
+        {
+            this.output = output ?? throw new ArgumentNullException(nameof(output));
+            writeCallback = Write;
+            flushCallback = Flush;
+
+            Native.Check(Native.mla_config_default_new(out var config), "Configuration creation");
+            foreach (var publicKey in publicKeys)
+            {
+                Native.Check(Native.mla_config_add_public_keys(config, Native.ToCString(publicKey)), "Public key addition");
+            }
+            if (compressionLevel.HasValue)
+            {
+                Native.Check(Native.mla_config_set_compression_level(config, compressionLevel.Value), "Compression level setting");
+            }
+            // The configuration is consumed, even on failure
+            Call(Native.mla_archive_new(ref config, writeCallback, flushCallback, IntPtr.Zero, out handle), "Archive creation");
+        }
+
+        /// <summary>Add the file <paramref name="name"/>, with the content of <paramref name="content"/> until its end</summary>
+        public void AddFile(string name, Stream content)
+        {
+            EnsureOpen();
+            Call(Native.mla_archive_file_new(handle, Native.ToCString(name), out var file), "File creation");
+            var buffer = new byte[BufferSize];
+            int count;
+            while ((count = content.Read(buffer, 0, buffer.Length)) > 0)
+            {
+                Append(file, buffer, count);
+            }
+            Call(Native.mla_archive_file_close(handle, ref file), "File close");
+        }
+
+        /// <summary>Add the file <paramref name="name"/>, with the content <paramref name="content"/></summary>
+        public void AddFile(string name, byte[] content)
+        {
+            using (var stream = new MemoryStream(content, false))
+            {
+                AddFile(name, stream);
+            }
+        }
+
+        /// <summary>Finalize the archive, and flush the output</summary>
+        public void Close()
+        {
+            EnsureOpen();
+            // The handle is released, even on failure
+            Call(Native.mla_archive_close(ref handle), "Archive close");
+        }
+
+        /// <summary>Finalize the archive, if not already done</summary>
+        public void Dispose()
+        {
+            if (handle != IntPtr.Zero)
+            {
+                Close();
+            }
+        }
+
+        private unsafe void Append(IntPtr file, byte[] buffer, int count)
+        {
+            fixed (byte* data = buffer)
+            {
+                Call(Native.mla_archive_file_append(handle, file, data, (ulong)count), "File write");
+            }
+        }
+
+        private int Write(IntPtr buffer, UIntPtr length, IntPtr context)
+        {
+            try
+            {
+                var data = new byte[(int)length];
+                Marshal.Copy(buffer, data, 0, data.Length);
+                output.Write(data, 0, data.Length);
+                return 0;
+            }
+            catch (Exception e)
+            {
+                outputError = e;
+                return IoError;
+            }
+        }
+
+        private int Flush(IntPtr context)
+        {
+            try
+            {
+                output.Flush();
+                return 0;
+            }
+            catch (Exception e)
+            {
+                outputError = e;
+                return IoError;
+            }
+        }
+
+        private void Call(ulong status, string operation)
+        {
+            if (outputError != null)
+            {
+                var error = outputError;
+                outputError = null;
+                throw new IOException($"{operation} failed while writing the output", error);
+            }
+            Native.Check(status, operation);
+        }
+
+        private void EnsureOpen()
+        {
+            if (handle == IntPtr.Zero)
+            {
+                throw new ObjectDisposedException(nameof(MlaArchiveWriter));
+            }
+        }
+    }
+}
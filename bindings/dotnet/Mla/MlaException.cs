@@ -0,0 +1,17 @@
+using System;
+
+namespace Mla
+{
+    /// <summary>Error returned by the MLA library</summary>
+    public class MlaException : Exception
+    {
+        /// <summary>Status code, one of the MLA_STATUS_* values of mla.h</summary>
+        public ulong Status { get; }
+
+        internal MlaException(string operation, ulong status)
+            : base($"{operation} failed with status 0x{status:X}")
+        {
+            Status = status;
+        }
+    }
+}
@@ -0,0 +1,98 @@
+using System;
+using System.Runtime.InteropServices;
+
+namespace Mla
+{
+    /// <summary>
+    /// Functions of the C bindings (bindings/C/mla.h), from the "mla" library
+    /// (mla.dll, libmla.so or libmla.dylib)
+    /// </summary>
+    internal static class Native
+    {
+        private const string Library = "mla";
+
+        internal const ulong Success = 0;
+        internal const ulong BadApiArgument = 0x120000;
+
+        [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+        internal delegate int WriteCallback(IntPtr buffer, UIntPtr length, IntPtr context);
+
+        [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+        internal delegate int FlushCallback(IntPtr context);
+
+        [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+        internal delegate int ReadCallback(IntPtr buffer, UIntPtr length, IntPtr context, out UIntPtr bytesRead);
+
+        [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+        internal delegate int SeekCallback(long offset, int whence, IntPtr context, out ulong newPosition);
+
+        [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+        internal delegate int FileCallback(IntPtr fileName, IntPtr context);
+
+        // Writer
+
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        internal static extern ulong mla_config_default_new(out IntPtr handleOut);
+
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        internal static extern ulong mla_config_add_public_keys(IntPtr config, byte[] publicKeys);
+
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        internal static extern ulong mla_config_set_compression_level(IntPtr config, uint level);
+
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        internal static extern ulong mla_archive_new(ref IntPtr config, WriteCallback writeCallback, FlushCallback flushCallback, IntPtr context, out IntPtr handleOut);
+
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        internal static extern ulong mla_archive_file_new(IntPtr archive, byte[] fileName, out IntPtr handleOut);
+
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        internal static extern unsafe ulong mla_archive_file_append(IntPtr archive, IntPtr file, byte* buffer, ulong length);
+
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        internal static extern ulong mla_archive_flush(IntPtr archive);
+
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        internal static extern ulong mla_archive_file_close(IntPtr archive, ref IntPtr file);
+
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        internal static extern ulong mla_archive_close(ref IntPtr archive);
+
+        // Reader
+
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        internal static extern ulong mla_reader_config_new(out IntPtr handleOut);
+
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        internal static extern ulong mla_reader_config_add_private_key(IntPtr config, byte[] privateKey);
+
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        internal static extern ulong mla_roarchive_new(ref IntPtr config, ReadCallback readCallback, SeekCallback seekCallback, IntPtr context, out IntPtr handleOut);
+
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        internal static extern ulong mla_roarchive_list(IntPtr archive, FileCallback fileCallback, IntPtr context);
+
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        internal static extern ulong mla_roarchive_file_read(IntPtr archive, byte[] fileName, WriteCallback writeCallback, IntPtr context);
+
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        internal static extern ulong mla_roarchive_close(ref IntPtr archive);
+
+        /// <summary>NULL-terminated UTF-8 version of <paramref name="value"/></summary>
+        internal static byte[] ToCString(string value)
+        {
+            var bytes = new byte[System.Text.Encoding.UTF8.GetByteCount(value) + 1];
+            System.Text.Encoding.UTF8.GetBytes(value, 0, value.Length, bytes, 0);
+            return bytes;
+        }
+
+        /// <summary>Throw a <see cref="MlaException"/> unless <paramref name="status"/> is a success</summary>
+        internal static void Check(ulong status, string operation)
+        {
+            if (status != Success)
+            {
+                throw new MlaException(operation, status);
+            }
+        }
+    }
+}
@@ -0,0 +1,26 @@
+/// <reference types="node" />
+import { Readable } from 'stream'
+
+/** File of an archive, as listed by `list` */
+export interface Entry {
+  name: string
+  /** Size of the file, in bytes */
+  size: number
+  /** Hash of the file content, hex-encoded, with the hash algorithm of the archive */
+  hash: string
+}
+
+/** Create the archive `path`, for the recipients of `publicKeys` (PEM contents), with the files `files`, named as given */
+export function create(path: string, publicKeys: Array<string>, files: Array<string>): Promise<void>
+/** List the files of the archive `path`, sorted by name */
+export function list(path: string, privateKeys: Array<string>): Promise<Array<Entry>>
+/** Read the content of the file `name` of the archive `path`, or only `length` bytes from `offset` */
+export function readFile(path: string, name: string, privateKeys: Array<string>, offset?: number | null, length?: number | null): Promise<Buffer>
+/**
+ * Extract the files of the archive `path`, or only `files`, in `outputDir`, and return the number of files extracted
+ *
+ * Files whose name would lead outside of `outputDir` are skipped
+ */
+export function extract(path: string, outputDir: string, privateKeys: Array<string>, files?: Array<string> | null): Promise<number>
+/** Readable stream on the content of the file `name` of the archive `path`, read by parts of `chunkSize` bytes */
+export function createReadStream(path: string, name: string, privateKeys: Array<string>, options?: { chunkSize?: number }): Readable
@@ -0,0 +1,34 @@
+'use strict'
+
+const { Readable } = require('stream')
+const native = require('./mla.node')
+
+// Size of the parts read at once by createReadStream
+const DEFAULT_CHUNK_SIZE = 4 * 1024 * 1024
+
+/**
+ * Readable stream on the content of the file `name` of the archive `path`,
+ * read by parts of `chunkSize` bytes
+ */
+function createReadStream (path, name, privateKeys, { chunkSize = DEFAULT_CHUNK_SIZE } = {}) {
+  let offset = 0
+  return new Readable({
+    read () {
+      native.readFile(path, name, privateKeys, offset, chunkSize).then(
+        (chunk) => {
+          offset += chunk.length
+          this.push(chunk.length > 0 ? chunk : null)
+        },
+        (err) => this.destroy(err)
+      )
+    }
+  })
+}
+
+module.exports = {
+  create: native.create,
+  list: native.list,
+  readFile: native.readFile,
+  extract: native.extract,
+  createReadStream
+}
@@ -0,0 +1,249 @@
+//! Node.js bindings of MLA
+//!
+//! Each function runs on the libuv thread pool, and returns a Promise. Keys
+//! are given as the content of PEM files, as accepted by `mlar` with `-k` and
+//! `-p`
+use curve25519_parser::{parse_openssl_25519_privkey, parse_openssl_25519_pubkeys_pem_many};
+use mla::config::{ArchiveReaderConfig, ArchiveWriterConfig};
+use mla::{ArchiveReader, ArchiveWriter};
+use napi::bindgen_prelude::{AsyncTask, Buffer};
+use napi::{Env, Error, Result, Task};
+use napi_derive::napi;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+
+fn to_error<E: std::fmt::Debug>(err: E) -> Error {
+    Error::from_reason(format!("{:?}", err))
+}
+
+fn open_archive(path: &str, private_keys: &[String]) -> Result<ArchiveReader<'static, File>> {
+    let mut config = ArchiveReaderConfig::new();
+    for private_key in private_keys {
+        let private_key = parse_openssl_25519_privkey(private_key.as_bytes()).map_err(to_error)?;
+        config.add_private_keys(&[private_key]);
+    }
+    let file = File::open(path).map_err(to_error)?;
+    ArchiveReader::from_config(file, config).map_err(to_error)
+}
+
+/// Path of `file_name` once extracted in `output_dir`, or None if it would
+/// be outside of it (see `mlar extract`)
+fn get_extracted_path(output_dir: &Path, file_name: &str) -> Option<PathBuf> {
+    let mut file_dst = output_dir.to_path_buf();
+    for part in Path::new(file_name).components() {
+        match part {
+            Component::Prefix(..) | Component::RootDir | Component::CurDir => continue,
+            Component::ParentDir => return None,
+            Component::Normal(part) => file_dst.push(part),
+        }
+    }
+    Some(file_dst)
+}
+
+/// File of an archive, as listed by `list`
+#[napi(object)]
+pub struct Entry {
+    pub name: String,
+    /// Size of the file, in bytes
+    pub size: i64,
+    /// Hash of the file content, hex-encoded, with the hash algorithm of the
+    /// archive
+    pub hash: String,
+}
+
+pub struct ListTask {
+    path: String,
+    private_keys: Vec<String>,
+}
+
+impl Task for ListTask {
+    type Output = Vec<Entry>;
+    type JsValue = Vec<Entry>;
+
+    fn compute(&mut self) -> Result<Self::Output> {
+        let mut mla = open_archive(&self.path, &self.private_keys)?;
+        let mut entries: Vec<Entry> = mla
+            .iter_entries()
+            .map_err(to_error)?
+            .map(|entry| Entry {
+                name: entry.name,
+                size: entry.size as i64,
+                hash: hex::encode(entry.hash),
+            })
+            .collect();
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(entries)
+    }
+
+    fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+        Ok(output)
+    }
+}
+
+/// List the files of the archive `path`, sorted by name
+#[napi]
+pub fn list(path: String, private_keys: Vec<String>) -> AsyncTask<ListTask> {
+    AsyncTask::new(ListTask { path, private_keys })
+}
+
+pub struct ReadTask {
+    path: String,
+    private_keys: Vec<String>,
+    name: String,
+    offset: Option<i64>,
+    length: Option<i64>,
+}
+
+impl Task for ReadTask {
+    type Output = Vec<u8>;
+    type JsValue = Buffer;
+
+    fn compute(&mut self) -> Result<Self::Output> {
+        let mut mla = open_archive(&self.path, &self.private_keys)?;
+        let mut file = match mla.get_file(self.name.clone()).map_err(to_error)? {
+            Some(file) => file,
+            None => return Err(Error::from_reason(format!("No such file: {}", self.name))),
+        };
+        let offset = std::cmp::min(self.offset.unwrap_or(0).max(0) as u64, file.size);
+        let length = match self.length {
+            Some(length) => std::cmp::min(length.max(0) as u64, file.size - offset),
+            None => file.size - offset,
+        };
+        file.data.seek(SeekFrom::Start(offset)).map_err(to_error)?;
+        let mut content = Vec::with_capacity(length as usize);
+        file.data
+            .take(length)
+            .read_to_end(&mut content)
+            .map_err(to_error)?;
+        Ok(content)
+    }
+
+    fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+        Ok(output.into())
+    }
+}
+
+/// Read the content of the file `name` of the archive `path`, or only
+/// `length` bytes from `offset`
+#[napi]
+pub fn read_file(
+    path: String,
+    name: String,
+    private_keys: Vec<String>,
+    offset: Option<i64>,
+    length: Option<i64>,
+) -> AsyncTask<ReadTask> {
+    AsyncTask::new(ReadTask {
+        path,
+        private_keys,
+        name,
+        offset,
+        length,
+    })
+}
+
+pub struct ExtractTask {
+    path: String,
+    private_keys: Vec<String>,
+    output_dir: String,
+    files: Option<Vec<String>>,
+}
+
+impl Task for ExtractTask {
+    type Output = u32;
+    type JsValue = u32;
+
+    fn compute(&mut self) -> Result<Self::Output> {
+        let mut mla = open_archive(&self.path, &self.private_keys)?;
+        let fnames: Vec<String> = match &self.files {
+            Some(files) => files.clone(),
+            None => mla.list_files().map_err(to_error)?.cloned().collect(),
+        };
+        let output_dir = Path::new(&self.output_dir);
+        let mut extracted = 0;
+        for fname in fnames {
+            let path = match get_extracted_path(output_dir, &fname) {
+                Some(path) => path,
+                None => continue,
+            };
+            let mut file = match mla.get_file(fname.clone()).map_err(to_error)? {
+                Some(file) => file,
+                None => return Err(Error::from_reason(format!("No such file: {}", fname))),
+            };
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).map_err(to_error)?;
+            }
+            let mut dest = File::create(&path).map_err(to_error)?;
+            io::copy(&mut file.data, &mut dest).map_err(to_error)?;
+            extracted += 1;
+        }
+        Ok(extracted)
+    }
+
+    fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+        Ok(output)
+    }
+}
+
+/// Extract the files of the archive `path`, or only `files`, in
+/// `output_dir`, and return the number of files extracted
+///
+/// Files whose name would lead outside of `output_dir` are skipped
+#[napi]
+pub fn extract(
+    path: String,
+    output_dir: String,
+    private_keys: Vec<String>,
+    files: Option<Vec<String>>,
+) -> AsyncTask<ExtractTask> {
+    AsyncTask::new(ExtractTask {
+        path,
+        private_keys,
+        output_dir,
+        files,
+    })
+}
+
+pub struct CreateTask {
+    path: String,
+    public_keys: Vec<String>,
+    files: Vec<String>,
+}
+
+impl Task for CreateTask {
+    type Output = ();
+    type JsValue = ();
+
+    fn compute(&mut self) -> Result<Self::Output> {
+        let mut config = ArchiveWriterConfig::new();
+        for public_keys in &self.public_keys {
+            let public_keys =
+                parse_openssl_25519_pubkeys_pem_many(public_keys.as_bytes()).map_err(to_error)?;
+            config.add_public_keys(&public_keys);
+        }
+        let dest = File::create(&self.path).map_err(to_error)?;
+        let mut mla = ArchiveWriter::from_config(dest, config).map_err(to_error)?;
+        for fname in &self.files {
+            let file = File::open(fname).map_err(to_error)?;
+            let size = file.metadata().map_err(to_error)?.len();
+            mla.add_file(fname, size, file).map_err(to_error)?;
+        }
+        mla.finalize().map_err(to_error)
+    }
+
+    fn resolve(&mut self, _env: Env, _output: Self::Output) -> Result<Self::JsValue> {
+        Ok(())
+    }
+}
+
+/// Create the archive `path`, for the recipients of `public_keys`, with the
+/// files `files`, named as given
+#[napi]
+pub fn create(path: String, public_keys: Vec<String>, files: Vec<String>) -> AsyncTask<CreateTask> {
+    AsyncTask::new(CreateTask {
+        path,
+        public_keys,
+        files,
+    })
+}
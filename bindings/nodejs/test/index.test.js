@@ -0,0 +1,50 @@
+'use strict'
+
+const assert = require('assert')
+const fs = require('fs')
+const os = require('os')
+const path = require('path')
+const test = require('node:test')
+const mla = require('..')
+
+const samples = path.join(__dirname, '..', '..', '..', 'samples')
+const publicKey = fs.readFileSync(path.join(samples, 'test_ed25519_pub.pem'), 'utf8')
+const privateKey = fs.readFileSync(path.join(samples, 'test_ed25519.pem'), 'utf8')
+
+test('create, list, read and extract', async () => {
+  const dir = fs.mkdtempSync(path.join(os.tmpdir(), 'mla-'))
+  const small = path.join(dir, 'small.txt')
+  const big = path.join(dir, 'big.bin')
+  fs.writeFileSync(small, 'Hello, World!\n')
+  const content = Buffer.alloc(5 * 1024 * 1024)
+  for (let i = 0; i < content.length; i++) {
+    content[i] = i % 251
+  }
+  fs.writeFileSync(big, content)
+
+  const archive = path.join(dir, 'test.mla')
+  await mla.create(archive, [publicKey], [small, big])
+
+  const entries = await mla.list(archive, [privateKey])
+  assert.deepStrictEqual(entries.map((entry) => [entry.name, entry.size]), [
+    [big, content.length],
+    [small, 14]
+  ])
+
+  assert.strictEqual((await mla.readFile(archive, small, [privateKey])).toString(), 'Hello, World!\n')
+  assert.strictEqual((await mla.readFile(archive, small, [privateKey], 7, 5)).toString(), 'World')
+  await assert.rejects(mla.readFile(archive, 'missing', [privateKey]))
+  await assert.rejects(mla.list(archive, []))
+
+  const chunks = []
+  for await (const chunk of mla.createReadStream(archive, big, [privateKey], { chunkSize: 1024 * 1024 })) {
+    chunks.push(chunk)
+  }
+  assert.ok(Buffer.concat(chunks).equals(content))
+
+  const output = path.join(dir, 'output')
+  assert.strictEqual(await mla.extract(archive, output, [privateKey], [small]), 1)
+  assert.strictEqual(fs.readFileSync(path.join(output, small), 'utf8'), 'Hello, World!\n')
+
+  fs.rmSync(dir, { recursive: true })
+})
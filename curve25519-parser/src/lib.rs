@@ -6,18 +6,23 @@ use der_parser::oid::Oid;
 use der_parser::*;
 use nom::IResult;
 
-use std::convert::{From, TryInto};
+use std::convert::{From, TryFrom, TryInto};
 
+use curve25519_dalek::constants::ED25519_BASEPOINT_TABLE;
 use curve25519_dalek::edwards::CompressedEdwardsY;
 use curve25519_dalek::montgomery::MontgomeryPoint;
+use curve25519_dalek::scalar::Scalar;
 use sha2::{Digest, Sha512};
 // Re-export x25519_dalek structures for convenience
 pub use x25519_dalek::{PublicKey, StaticSecret};
 
+use pkcs8::EncryptedPrivateKeyInfo;
 use rand_core::{CryptoRng, RngCore};
 
 use std::fmt;
 
+mod openssh;
+
 const ED_25519_OID: Oid<'static> = oid!(1.3.101 .112);
 const X_25519_OID: Oid<'static> = oid!(1.3.101 .110);
 
@@ -32,6 +37,12 @@ pub enum Curve25519ParserError {
     UnknownOid,
     InvalidData,
     InvalidPEMTag,
+    /// The private key is protected by a passphrase, see
+    /// `parse_openssl_25519_privkey_encrypted`
+    EncryptedPrivateKey,
+    /// The private key can't be decrypted (wrong passphrase, unsupported
+    /// algorithm, etc.)
+    DecryptionError,
 }
 impl From<der_parser::error::BerError> for Curve25519ParserError {
     fn from(error: der_parser::error::BerError) -> Self {
@@ -113,6 +124,21 @@ fn parse_25519_private(
 
 const TAG_OCTETSTRING: u8 = 4;
 
+/// From RFC8032, get the X25519 private key corresponding to an Ed25519 `seed`
+fn ed25519_seed_to_x25519(seed: &[u8]) -> StaticSecret {
+    let mut key_data = [0u8; 32];
+    key_data.copy_from_slice(&Sha512::digest(seed)[0..32]);
+    StaticSecret::from(key_data)
+}
+
+/// Get the X25519 public key corresponding to an Ed25519 public key
+fn ed25519_public_to_x25519(data: &[u8; 32]) -> Result<PublicKey, Curve25519ParserError> {
+    match CompressedEdwardsY::from_slice(data).decompress() {
+        Some(edwards_val) => Ok(PublicKey::from(edwards_val.to_montgomery().to_bytes())),
+        None => Err(Curve25519ParserError::InvalidData),
+    }
+}
+
 /// Parse a DER ED25519 or X25519 private key, and return the corresponding
 /// `x25519_dalek::StaticSecret`
 pub fn parse_openssl_25519_privkey_der(data: &[u8]) -> Result<StaticSecret, Curve25519ParserError> {
@@ -123,17 +149,16 @@ pub fn parse_openssl_25519_privkey_der(data: &[u8]) -> Result<StaticSecret, Curv
     if data.len() != 34 || data[0] != TAG_OCTETSTRING || data[1] != 32 {
         return Err(Curve25519ParserError::InvalidData);
     }
-    let mut key_data = [0u8; 32];
-
     let read_oid = private.header.tag.as_oid()?;
     if read_oid == &ED_25519_OID {
-        key_data.copy_from_slice(&Sha512::digest(&data[2..34])[0..32]);
+        Ok(ed25519_seed_to_x25519(&data[2..34]))
     } else if read_oid == &X_25519_OID {
+        let mut key_data = [0u8; 32];
         key_data.copy_from_slice(&data[2..34]);
+        Ok(StaticSecret::from(key_data))
     } else {
-        return Err(Curve25519ParserError::UnknownOid);
+        Err(Curve25519ParserError::UnknownOid)
     }
-    Ok(StaticSecret::from(key_data))
 }
 
 // ---- Public key ----
@@ -203,11 +228,7 @@ pub fn parse_openssl_25519_pubkey_der(data: &[u8]) -> Result<PublicKey, Curve255
         .map_err(|_| Curve25519ParserError::InvalidData)?;
     let read_oid = ed25519_public.header.tag.as_oid()?;
     if read_oid == &ED_25519_OID {
-        if let Some(edwards_val) = CompressedEdwardsY::from_slice(&data).decompress() {
-            Ok(PublicKey::from(edwards_val.to_montgomery().to_bytes()))
-        } else {
-            Err(Curve25519ParserError::InvalidData)
-        }
+        ed25519_public_to_x25519(&data)
     } else if read_oid == &X_25519_OID {
         Ok(PublicKey::from(MontgomeryPoint(data).to_bytes()))
     } else {
@@ -215,16 +236,54 @@ pub fn parse_openssl_25519_pubkey_der(data: &[u8]) -> Result<PublicKey, Curve255
     }
 }
 
+// ---- Raw ----
+
+/// Size of a raw X25519 key, as used by other ECDH tools
+pub const RAW_KEY_SIZE: usize = 32;
+
+/// Interpret `data` as a raw X25519 key, if it has the expected size
+///
+/// As a DER key is always longer, there is no ambiguity with other formats
+fn as_raw_key(data: &[u8]) -> Option<[u8; RAW_KEY_SIZE]> {
+    data.try_into().ok()
+}
+
 // ---- PEM ----
 
 const PUBLIC_TAG: &[u8] = b"PUBLIC KEY";
 const PRIVATE_TAG: &[u8] = b"PRIVATE KEY";
+const ENCRYPTED_PRIVATE_TAG: &[u8] = b"ENCRYPTED PRIVATE KEY";
 
-/// Parse an OpenSSL Ed25519 or X25519 public key, either in PEM or DER format
+/// Parse an OpenSSL Ed25519 or X25519 public key, either in PEM or DER format,
+/// an OpenSSH Ed25519 public key (`ssh-ed25519 AAAA...`), or a raw X25519
+/// public key (32 bytes)
+///
+/// The public key can also be preceded by the private key in the same file,
+/// as made by `mlar keygen --combined`
 pub fn parse_openssl_25519_pubkey(data: &[u8]) -> Result<PublicKey, Curve25519ParserError> {
+    if let Some(raw) = as_raw_key(data) {
+        return Ok(PublicKey::from(raw));
+    }
+    if data.starts_with(openssh::PUBLIC_PREFIX) {
+        return ed25519_public_to_x25519(&openssh::parse_public(data)?);
+    }
     if let Ok(pem_data) = pem::parse(data) {
         // First, try as a PEM
         if pem_data.tag.as_bytes() != PUBLIC_TAG {
+            // Look for the public key of a combined file
+            if let Some(pem_data) = pem::parse_many(data)
+                .into_iter()
+                .find(|pem_data| pem_data.tag.as_bytes() == PUBLIC_TAG)
+            {
+                return parse_openssl_25519_pubkey_der(&pem_data.contents);
+            }
+            let text = String::from_utf8_lossy(data);
+            if let Some(line) = text
+                .lines()
+                .find(|line| line.as_bytes().starts_with(openssh::PUBLIC_PREFIX))
+            {
+                return ed25519_public_to_x25519(&openssh::parse_public(line.as_bytes())?);
+            }
             return Err(Curve25519ParserError::InvalidPEMTag);
         }
         parse_openssl_25519_pubkey_der(&pem_data.contents)
@@ -234,20 +293,76 @@ pub fn parse_openssl_25519_pubkey(data: &[u8]) -> Result<PublicKey, Curve25519Pa
     }
 }
 
-/// Parse an OpenSSL Ed25519 or X25519 private key, either in PEM or DER format
+/// Parse an OpenSSL Ed25519 or X25519 private key, either in PEM or DER
+/// format, an OpenSSH Ed25519 private key, or a raw X25519 private key (32
+/// bytes)
+///
+/// Return `Curve25519ParserError::EncryptedPrivateKey` if the key is protected
+/// by a passphrase
 pub fn parse_openssl_25519_privkey(data: &[u8]) -> Result<StaticSecret, Curve25519ParserError> {
+    if let Some(raw) = as_raw_key(data) {
+        return Ok(StaticSecret::from(raw));
+    }
     if let Ok(pem_data) = pem::parse(data) {
         // First, try as a PEM
+        if pem_data.tag.as_bytes() == ENCRYPTED_PRIVATE_TAG {
+            return Err(Curve25519ParserError::EncryptedPrivateKey);
+        }
+        if pem_data.tag.as_bytes() == openssh::PRIVATE_TAG {
+            return Ok(ed25519_seed_to_x25519(&openssh::parse_private(
+                &pem_data.contents,
+                None,
+            )?));
+        }
         if pem_data.tag.as_bytes() != PRIVATE_TAG {
             return Err(Curve25519ParserError::InvalidPEMTag);
         }
         parse_openssl_25519_privkey_der(&pem_data.contents)
     } else {
         // Fallback to DER format
-        parse_openssl_25519_privkey_der(data)
+        parse_openssl_25519_privkey_der(data).map_err(|err| {
+            if EncryptedPrivateKeyInfo::try_from(data).is_ok() {
+                Curve25519ParserError::EncryptedPrivateKey
+            } else {
+                err
+            }
+        })
     }
 }
 
+/// Parse an OpenSSL Ed25519 or X25519 private key protected by `passphrase`
+/// (PKCS#8 `EncryptedPrivateKeyInfo`, as made by `openssl pkey -aes256`),
+/// either in PEM or DER format, or an encrypted OpenSSH Ed25519 private key
+pub fn parse_openssl_25519_privkey_encrypted(
+    data: &[u8],
+    passphrase: &[u8],
+) -> Result<StaticSecret, Curve25519ParserError> {
+    let pem_data;
+    let der = if let Ok(parsed) = pem::parse(data) {
+        // First, try as a PEM
+        if parsed.tag.as_bytes() == openssh::PRIVATE_TAG {
+            return Ok(ed25519_seed_to_x25519(&openssh::parse_private(
+                &parsed.contents,
+                Some(passphrase),
+            )?));
+        }
+        if parsed.tag.as_bytes() != ENCRYPTED_PRIVATE_TAG {
+            return Err(Curve25519ParserError::InvalidPEMTag);
+        }
+        pem_data = parsed;
+        pem_data.contents.as_slice()
+    } else {
+        // Fallback to DER format
+        data
+    };
+    let encrypted =
+        EncryptedPrivateKeyInfo::try_from(der).map_err(|_| Curve25519ParserError::InvalidData)?;
+    let decrypted = encrypted
+        .decrypt(passphrase)
+        .map_err(|_| Curve25519ParserError::DecryptionError)?;
+    parse_openssl_25519_privkey_der(decrypted.as_bytes())
+}
+
 /// Parse several contiguous OpenSSL Ed25519 org X25519 public keys in PEM format
 pub fn parse_openssl_25519_pubkeys_pem_many(
     data: &[u8],
@@ -293,6 +408,47 @@ impl KeyPair {
         };
         pem::encode(&out)
     }
+
+    /// Raw X25519 public key, without any encoding
+    pub fn public_as_raw(&self) -> [u8; RAW_KEY_SIZE] {
+        let mut out = [0u8; RAW_KEY_SIZE];
+        out.copy_from_slice(&self.public_der[PUB_KEY_PREFIX.len()..]);
+        out
+    }
+
+    /// Raw X25519 private key, without any encoding
+    pub fn private_as_raw(&self) -> [u8; RAW_KEY_SIZE] {
+        let mut out = [0u8; RAW_KEY_SIZE];
+        out.copy_from_slice(&self.private_der[PRIV_KEY_PREFIX.len()..]);
+        out
+    }
+}
+
+impl From<&StaticSecret> for KeyPair {
+    /// Get the keypair of an existing private key, in DER format
+    fn from(priv_key: &StaticSecret) -> Self {
+        let private = priv_key.to_bytes();
+
+        // Get the corresponding public key
+        let pubkey = PublicKey::from(priv_key);
+
+        // Get the public data bytes
+
+        let public = pubkey.as_bytes();
+
+        let mut private_der = [0u8; PRIV_KEY_PREFIX.len() + 32];
+        private_der[..PRIV_KEY_PREFIX.len()].copy_from_slice(PRIV_KEY_PREFIX);
+        private_der[PRIV_KEY_PREFIX.len()..].copy_from_slice(&private);
+
+        let mut public_der = [0u8; PUB_KEY_PREFIX.len() + 32];
+        public_der[..PUB_KEY_PREFIX.len()].copy_from_slice(PUB_KEY_PREFIX);
+        public_der[PUB_KEY_PREFIX.len()..].copy_from_slice(&public[..]);
+
+        KeyPair {
+            public_der,
+            private_der,
+        }
+    }
 }
 
 /// Generate a keypair, in DER format
@@ -304,26 +460,144 @@ where
     let mut private = [0u8; 32];
     csprng.fill_bytes(&mut private);
 
-    // Get the corresponding public key
-    let priv_key = StaticSecret::from(private);
-    let pubkey = PublicKey::from(&priv_key);
+    Some(KeyPair::from(&StaticSecret::from(private)))
+}
+
+/// Ed25519 key-pair, made to be exported in OpenSSH format, which has no
+/// X25519 key type
+pub struct Ed25519KeyPair {
+    seed: [u8; 32],
+    public: [u8; 32],
+    /// Random value, used by OpenSSH to verify the private key decryption
+    check: u32,
+}
+
+impl Ed25519KeyPair {
+    /// Public key, as an OpenSSH public key line (`ssh-ed25519 AAAA...`)
+    pub fn public_as_openssh(&self) -> String {
+        openssh::encode_public(&self.public)
+    }
+
+    /// Private key, as an unencrypted OpenSSH private key
+    pub fn private_as_openssh(&self) -> String {
+        let out = pem::Pem {
+            tag: String::from_utf8_lossy(openssh::PRIVATE_TAG).to_string(),
+            contents: openssh::encode_private(&self.seed, &self.public, self.check),
+        };
+        // OpenSSH does not support CRLF line endings
+        pem::encode(&out).replace("\r\n", "\n")
+    }
+}
+
+/// Generate an Ed25519 keypair
+pub fn generate_ed25519_keypair<T>(csprng: &mut T) -> Ed25519KeyPair
+where
+    T: RngCore + CryptoRng,
+{
+    let mut seed = [0u8; 32];
+    csprng.fill_bytes(&mut seed);
+
+    Ed25519KeyPair {
+        seed,
+        public: ed25519_seed_to_public(&seed),
+        check: csprng.next_u32(),
+    }
+}
+
+/// From RFC8032, get the Ed25519 public key corresponding to `seed`
+fn ed25519_seed_to_public(seed: &[u8; 32]) -> [u8; 32] {
+    // The secret scalar is `clamping(Sha512(seed)[0..32])`
+    let mut scalar = [0u8; 32];
+    scalar.copy_from_slice(&Sha512::digest(seed)[0..32]);
+    scalar[0] &= 248;
+    scalar[31] &= 127;
+    scalar[31] |= 64;
+    (&Scalar::from_bits(scalar) * &ED25519_BASEPOINT_TABLE)
+        .compress()
+        .to_bytes()
+}
+
+// ---- Ed25519 signing keys ----
 
-    // Get the public data bytes
+/// Size of an Ed25519 seed, or public key
+pub const ED25519_KEY_SIZE: usize = 32;
 
-    let public = pubkey.as_bytes();
+/// Parse a DER Ed25519 private key, and return its seed
+fn parse_openssl_ed25519_privkey_der(
+    data: &[u8],
+) -> Result<[u8; ED25519_KEY_SIZE], Curve25519ParserError> {
+    let (_remain, (_header, private)) = parse_25519_private(data)?;
+    if private.header.tag.as_oid()? != &ED_25519_OID {
+        return Err(Curve25519ParserError::UnknownOid);
+    }
+    let data = private.data.content.as_slice()?;
+    // data[0] == TAG_OCTETSTRING(4)
+    // data[1] == LENGTH
+    if data.len() != 34 || data[0] != TAG_OCTETSTRING || data[1] != 32 {
+        return Err(Curve25519ParserError::InvalidData);
+    }
+    let mut seed = [0u8; ED25519_KEY_SIZE];
+    seed.copy_from_slice(&data[2..34]);
+    Ok(seed)
+}
 
-    let mut private_der = [0u8; PRIV_KEY_PREFIX.len() + 32];
-    private_der[..PRIV_KEY_PREFIX.len()].copy_from_slice(PRIV_KEY_PREFIX);
-    private_der[PRIV_KEY_PREFIX.len()..].copy_from_slice(&private);
+/// Parse a DER Ed25519 public key, and return its raw bytes
+fn parse_openssl_ed25519_pubkey_der(
+    data: &[u8],
+) -> Result<[u8; ED25519_KEY_SIZE], Curve25519ParserError> {
+    let (_remain, (_header, public)) = parse_25519_public(data)?;
+    if public.header.tag.as_oid()? != &ED_25519_OID {
+        return Err(Curve25519ParserError::UnknownOid);
+    }
+    public
+        .data
+        .content
+        .as_slice()?
+        .try_into()
+        .map_err(|_| Curve25519ParserError::InvalidData)
+}
 
-    let mut public_der = [0u8; PUB_KEY_PREFIX.len() + 32];
-    public_der[..PUB_KEY_PREFIX.len()].copy_from_slice(PUB_KEY_PREFIX);
-    public_der[PUB_KEY_PREFIX.len()..].copy_from_slice(&public[..]);
+/// Parse an OpenSSL Ed25519 private key, either in PEM or DER format, or an
+/// unencrypted OpenSSH Ed25519 private key, and return its seed
+///
+/// Unlike `parse_openssl_25519_privkey`, the key is kept as an Ed25519 key, to
+/// be used for signatures
+pub fn parse_openssl_ed25519_privkey(
+    data: &[u8],
+) -> Result<[u8; ED25519_KEY_SIZE], Curve25519ParserError> {
+    if let Ok(pem_data) = pem::parse(data) {
+        if pem_data.tag.as_bytes() == ENCRYPTED_PRIVATE_TAG {
+            return Err(Curve25519ParserError::EncryptedPrivateKey);
+        }
+        if pem_data.tag.as_bytes() == openssh::PRIVATE_TAG {
+            return openssh::parse_private(&pem_data.contents, None);
+        }
+        if pem_data.tag.as_bytes() != PRIVATE_TAG {
+            return Err(Curve25519ParserError::InvalidPEMTag);
+        }
+        parse_openssl_ed25519_privkey_der(&pem_data.contents)
+    } else {
+        parse_openssl_ed25519_privkey_der(data)
+    }
+}
 
-    Some(KeyPair {
-        public_der,
-        private_der,
-    })
+/// Parse an OpenSSL Ed25519 public key, either in PEM or DER format, or an
+/// OpenSSH Ed25519 public key (`ssh-ed25519 AAAA...`), and return its raw
+/// bytes
+pub fn parse_openssl_ed25519_pubkey(
+    data: &[u8],
+) -> Result<[u8; ED25519_KEY_SIZE], Curve25519ParserError> {
+    if data.starts_with(openssh::PUBLIC_PREFIX) {
+        return openssh::parse_public(data);
+    }
+    if let Ok(pem_data) = pem::parse(data) {
+        if pem_data.tag.as_bytes() != PUBLIC_TAG {
+            return Err(Curve25519ParserError::InvalidPEMTag);
+        }
+        parse_openssl_ed25519_pubkey_der(&pem_data.contents)
+    } else {
+        parse_openssl_ed25519_pubkey_der(data)
+    }
 }
 
 #[cfg(test)]
@@ -348,6 +622,18 @@ mod tests {
     // openssl pkey -in test_ed25519.der -inform DER -out test_ed25519.pem -outform PEM
     static PEM_PRIV: &[u8] = include_bytes!("../../samples/test_ed25519.pem");
 
+    // openssl pkey -in test_x25519.pem -aes256 -passout pass:passphrase -out test_x25519_encrypted.pem
+    static PEM_PRIV_ENCRYPTED: &[u8] = include_bytes!("../../samples/test_x25519_encrypted.pem");
+
+    // ssh-keygen -t ed25519 -N "" -C "mla test" -f test_ed25519_openssh
+    static OPENSSH_PRIV: &[u8] = include_bytes!("../../samples/test_ed25519_openssh");
+    static OPENSSH_PUB: &[u8] = include_bytes!("../../samples/test_ed25519_openssh.pub");
+    // ssh-keygen -t ed25519 -N "passphrase" -C "mla test" -f test_ed25519_openssh_encrypted
+    static OPENSSH_PRIV_ENCRYPTED: &[u8] =
+        include_bytes!("../../samples/test_ed25519_openssh_encrypted");
+    static OPENSSH_PUB_ENCRYPTED: &[u8] =
+        include_bytes!("../../samples/test_ed25519_openssh_encrypted.pub");
+
     // Many[0] is PEM_PUB
     static PEM_PUB_MANY: &[u8] = include_bytes!("../../samples/test_25519_pub_many.pem");
 
@@ -383,6 +669,94 @@ mod tests {
         assert_eq!(priv_key_der.to_bytes(), priv_key_pem.to_bytes());
     }
 
+    #[test]
+    fn parse_encrypted_privkey() {
+        let priv_key = parse_openssl_25519_privkey_der(X_DER_PRIV).unwrap();
+        match parse_openssl_25519_privkey(PEM_PRIV_ENCRYPTED) {
+            Err(Curve25519ParserError::EncryptedPrivateKey) => {}
+            _ => panic!("The key is expected to be detected as encrypted"),
+        }
+        let decrypted =
+            parse_openssl_25519_privkey_encrypted(PEM_PRIV_ENCRYPTED, b"passphrase").unwrap();
+        assert_eq!(decrypted.to_bytes(), priv_key.to_bytes());
+
+        // DER format
+        let der = pem::parse(PEM_PRIV_ENCRYPTED).unwrap().contents;
+        match parse_openssl_25519_privkey(&der) {
+            Err(Curve25519ParserError::EncryptedPrivateKey) => {}
+            _ => panic!("The key is expected to be detected as encrypted"),
+        }
+        let decrypted = parse_openssl_25519_privkey_encrypted(&der, b"passphrase").unwrap();
+        assert_eq!(decrypted.to_bytes(), priv_key.to_bytes());
+
+        // Wrong passphrase
+        match parse_openssl_25519_privkey_encrypted(PEM_PRIV_ENCRYPTED, b"wrong") {
+            Err(Curve25519ParserError::DecryptionError) => {}
+            _ => panic!("The decryption is expected to fail"),
+        }
+    }
+
+    #[test]
+    fn parse_openssh_keys() {
+        let priv_key = parse_openssl_25519_privkey(OPENSSH_PRIV).unwrap();
+        let pub_key = parse_openssl_25519_pubkey(OPENSSH_PUB).unwrap();
+        assert_eq!(PublicKey::from(&priv_key).as_bytes(), pub_key.as_bytes());
+
+        // Passphrase-protected key
+        match parse_openssl_25519_privkey(OPENSSH_PRIV_ENCRYPTED) {
+            Err(Curve25519ParserError::EncryptedPrivateKey) => {}
+            _ => panic!("The key is expected to be detected as encrypted"),
+        }
+        let priv_key =
+            parse_openssl_25519_privkey_encrypted(OPENSSH_PRIV_ENCRYPTED, b"passphrase").unwrap();
+        let pub_key = parse_openssl_25519_pubkey(OPENSSH_PUB_ENCRYPTED).unwrap();
+        assert_eq!(PublicKey::from(&priv_key).as_bytes(), pub_key.as_bytes());
+        match parse_openssl_25519_privkey_encrypted(OPENSSH_PRIV_ENCRYPTED, b"wrong") {
+            Err(Curve25519ParserError::DecryptionError) => {}
+            _ => panic!("The decryption is expected to fail"),
+        }
+    }
+
+    #[test]
+    fn parse_ed25519_signing_keys() {
+        for (priv_data, pub_data) in &[
+            (PEM_PRIV, PEM_PUB),
+            (ED_DER_PRIV, ED_DER_PUB),
+            (OPENSSH_PRIV, OPENSSH_PUB),
+        ] {
+            let seed = parse_openssl_ed25519_privkey(priv_data).unwrap();
+            let public = parse_openssl_ed25519_pubkey(pub_data).unwrap();
+            assert_eq!(ed25519_seed_to_public(&seed), public);
+        }
+
+        // X25519 keys can't be used for signatures
+        match parse_openssl_ed25519_privkey(X_DER_PRIV) {
+            Err(Curve25519ParserError::UnknownOid) => {}
+            _ => panic!("An X25519 key is expected to be refused"),
+        }
+        match parse_openssl_ed25519_pubkey(X_DER_PUB) {
+            Err(Curve25519ParserError::UnknownOid) => {}
+            _ => panic!("An X25519 key is expected to be refused"),
+        }
+    }
+
+    #[test]
+    fn exports_openssh() {
+        let mut csprng = OsRng {};
+        let keypair = generate_ed25519_keypair(&mut csprng);
+
+        let priv_key =
+            parse_openssl_25519_privkey(keypair.private_as_openssh().as_bytes()).unwrap();
+        let pub_key = parse_openssl_25519_pubkey(keypair.public_as_openssh().as_bytes()).unwrap();
+        assert_eq!(PublicKey::from(&priv_key).as_bytes(), pub_key.as_bytes());
+
+        // Both keys in the same file
+        let combined = keypair.private_as_openssh() + &keypair.public_as_openssh();
+        let priv_key = parse_openssl_25519_privkey(combined.as_bytes()).unwrap();
+        let pub_key = parse_openssl_25519_pubkey(combined.as_bytes()).unwrap();
+        assert_eq!(PublicKey::from(&priv_key).as_bytes(), pub_key.as_bytes());
+    }
+
     #[test]
     fn parse_many_pubkeys() {
         let pub_keys_pem = parse_openssl_25519_pubkeys_pem_many(PEM_PUB).unwrap();
@@ -426,5 +800,40 @@ mod tests {
                 .to_bytes(),
             &priv_key.to_bytes()
         );
+
+        // Both keys in the same file
+        let combined = priv_pem_key + &pub_pem_key;
+        assert_eq!(
+            parse_openssl_25519_pubkey(combined.as_bytes())
+                .unwrap()
+                .as_bytes(),
+            pub_key.as_bytes()
+        );
+    }
+
+    #[test]
+    fn raw_keys() {
+        // Raw keys are the last 32 bytes of the DER X25519 keys
+        let priv_key = parse_openssl_25519_privkey(&X_DER_PRIV[X_DER_PRIV.len() - 32..]).unwrap();
+        let pub_key = parse_openssl_25519_pubkey(&X_DER_PUB[X_DER_PUB.len() - 32..]).unwrap();
+        assert_eq!(
+            priv_key.to_bytes(),
+            parse_openssl_25519_privkey_der(X_DER_PRIV)
+                .unwrap()
+                .to_bytes()
+        );
+        assert_eq!(PublicKey::from(&priv_key).as_bytes(), pub_key.as_bytes());
+
+        let mut csprng = OsRng {};
+        let keypair = generate_keypair(&mut csprng).unwrap();
+        let priv_key = parse_openssl_25519_privkey(&keypair.private_as_raw()).unwrap();
+        let pub_key = parse_openssl_25519_pubkey(&keypair.public_as_raw()).unwrap();
+        assert_eq!(PublicKey::from(&priv_key).as_bytes(), pub_key.as_bytes());
+        assert_eq!(
+            pub_key.as_bytes(),
+            parse_openssl_25519_pubkey_der(&keypair.public_der)
+                .unwrap()
+                .as_bytes()
+        );
     }
 }
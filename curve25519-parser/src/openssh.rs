@@ -0,0 +1,208 @@
+//! OpenSSH Ed25519 keys, as made by `ssh-keygen -t ed25519`
+//!
+//! Public key format (one line): `ssh-ed25519 <base64 blob> [comment]`, with:
+//! ```ascii-art
+//! [string "ssh-ed25519"][string public key (32 bytes)]
+//! ```
+//!
+//! Private key format (PEM, tag "OPENSSH PRIVATE KEY"), from OpenSSH's
+//! PROTOCOL.key:
+//! ```ascii-art
+//! ["openssh-key-v1\0"][string ciphername][string kdfname][string kdfoptions]
+//! [u32 number of keys (1)][string public key blob][string private section]
+//! ```
+//! The private section, encrypted if `ciphername` is not "none":
+//! ```ascii-art
+//! [u32 check][u32 check][string "ssh-ed25519"][string public key (32 bytes)]
+//! [string seed (32 bytes) and public key (32 bytes)][string comment][padding]
+//! ```
+//! Strings are prefixed by their length, as a big endian u32.
+
+use aes::cipher::{KeyIvInit, StreamCipher};
+use std::convert::TryInto;
+
+use crate::Curve25519ParserError;
+
+pub(crate) const PRIVATE_TAG: &[u8] = b"OPENSSH PRIVATE KEY";
+pub(crate) const PUBLIC_PREFIX: &[u8] = b"ssh-ed25519 ";
+
+const AUTH_MAGIC: &[u8] = b"openssh-key-v1\0";
+const KEY_TYPE: &[u8] = b"ssh-ed25519";
+const KEY_SIZE: usize = 32;
+/// Supported cipher for encrypted keys (`ssh-keygen` default)
+const CIPHER_AES256_CTR: &[u8] = b"aes256-ctr";
+const KDF_BCRYPT: &[u8] = b"bcrypt";
+/// AES-256 key and CTR IV sizes
+const AES_KEY_SIZE: usize = 32;
+const AES_IV_SIZE: usize = 16;
+
+type Aes256Ctr = ctr::Ctr128BE<aes::Aes256>;
+
+/// Cursor over OpenSSH wire-encoded data
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], Curve25519ParserError> {
+        if self.data.len() < len {
+            return Err(Curve25519ParserError::InvalidData);
+        }
+        let (bytes, remain) = self.data.split_at(len);
+        self.data = remain;
+        Ok(bytes)
+    }
+
+    fn read_u32(&mut self) -> Result<u32, Curve25519ParserError> {
+        // Safe to use unwrap() because exactly 4 bytes are read
+        Ok(u32::from_be_bytes(self.read_bytes(4)?.try_into().unwrap()))
+    }
+
+    fn read_string(&mut self) -> Result<&'a [u8], Curve25519ParserError> {
+        let len = self.read_u32()? as usize;
+        self.read_bytes(len)
+    }
+
+    /// Read a `[string "ssh-ed25519"][string public key]` blob
+    fn read_public(&mut self) -> Result<[u8; KEY_SIZE], Curve25519ParserError> {
+        if self.read_string()? != KEY_TYPE {
+            return Err(Curve25519ParserError::UnknownOid);
+        }
+        self.read_string()?
+            .try_into()
+            .map_err(|_| Curve25519ParserError::InvalidData)
+    }
+}
+
+/// Parse an OpenSSH Ed25519 public key line, and return the Ed25519 public key
+pub(crate) fn parse_public(data: &[u8]) -> Result<[u8; KEY_SIZE], Curve25519ParserError> {
+    let line = std::str::from_utf8(data).map_err(|_| Curve25519ParserError::InvalidData)?;
+    let mut fields = line.split_whitespace();
+    if fields.next().map(str::as_bytes) != Some(KEY_TYPE) {
+        return Err(Curve25519ParserError::UnknownOid);
+    }
+    let blob = fields
+        .next()
+        .and_then(|blob| base64::decode(blob).ok())
+        .ok_or(Curve25519ParserError::InvalidData)?;
+    Reader { data: &blob }.read_public()
+}
+
+/// Parse an OpenSSH Ed25519 private key (PEM decoded `contents`), decrypted
+/// with `passphrase` if needed, and return the Ed25519 seed
+pub(crate) fn parse_private(
+    contents: &[u8],
+    passphrase: Option<&[u8]>,
+) -> Result<[u8; KEY_SIZE], Curve25519ParserError> {
+    let mut reader = Reader { data: contents };
+    if reader.read_bytes(AUTH_MAGIC.len())? != AUTH_MAGIC {
+        return Err(Curve25519ParserError::InvalidData);
+    }
+    let ciphername = reader.read_string()?;
+    let kdfname = reader.read_string()?;
+    let kdfoptions = reader.read_string()?;
+    if reader.read_u32()? != 1 {
+        // Only one key per file is supported, as made by `ssh-keygen`
+        return Err(Curve25519ParserError::InvalidData);
+    }
+    let public = Reader {
+        data: reader.read_string()?,
+    }
+    .read_public()?;
+    let mut private_section = reader.read_string()?.to_vec();
+
+    match (ciphername, passphrase) {
+        (b"none", _) => {}
+        (_, None) => return Err(Curve25519ParserError::EncryptedPrivateKey),
+        (CIPHER_AES256_CTR, Some(passphrase)) => {
+            if kdfname != KDF_BCRYPT {
+                return Err(Curve25519ParserError::DecryptionError);
+            }
+            let mut options = Reader { data: kdfoptions };
+            let salt = options.read_string()?;
+            let rounds = options.read_u32()?;
+            let mut key_iv = [0u8; AES_KEY_SIZE + AES_IV_SIZE];
+            bcrypt_pbkdf::bcrypt_pbkdf(passphrase, salt, rounds, &mut key_iv)
+                .map_err(|_| Curve25519ParserError::DecryptionError)?;
+            let mut cipher =
+                Aes256Ctr::new(key_iv[..AES_KEY_SIZE].into(), key_iv[AES_KEY_SIZE..].into());
+            cipher.apply_keystream(&mut private_section);
+        }
+        _ => return Err(Curve25519ParserError::DecryptionError),
+    }
+
+    let mut private = Reader {
+        data: &private_section,
+    };
+    // Both check values are equal only if the decryption is successful
+    if private.read_u32()? != private.read_u32()? {
+        return Err(Curve25519ParserError::DecryptionError);
+    }
+    if private.read_public()? != public {
+        return Err(Curve25519ParserError::InvalidData);
+    }
+    let seed_and_public = private.read_string()?;
+    if seed_and_public.len() != 2 * KEY_SIZE || seed_and_public[KEY_SIZE..] != public {
+        return Err(Curve25519ParserError::InvalidData);
+    }
+    let mut seed = [0u8; KEY_SIZE];
+    seed.copy_from_slice(&seed_and_public[..KEY_SIZE]);
+    Ok(seed)
+}
+
+// ---- Export ----
+
+/// Block size of the unencrypted private section
+const BLOCK_SIZE: usize = 8;
+
+fn write_string(out: &mut Vec<u8>, data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(data);
+}
+
+fn public_blob(public: &[u8; KEY_SIZE]) -> Vec<u8> {
+    let mut blob = Vec::new();
+    write_string(&mut blob, KEY_TYPE);
+    write_string(&mut blob, public);
+    blob
+}
+
+/// Export an Ed25519 public key as an OpenSSH public key line
+pub(crate) fn encode_public(public: &[u8; KEY_SIZE]) -> String {
+    format!("ssh-ed25519 {}\n", base64::encode(public_blob(public)))
+}
+
+/// Export an Ed25519 key as an unencrypted OpenSSH private key (PEM decoded
+/// contents). `check` is a random value, used by OpenSSH to verify the
+/// decryption
+pub(crate) fn encode_private(
+    seed: &[u8; KEY_SIZE],
+    public: &[u8; KEY_SIZE],
+    check: u32,
+) -> Vec<u8> {
+    let mut private_section = Vec::new();
+    private_section.extend_from_slice(&check.to_be_bytes());
+    private_section.extend_from_slice(&check.to_be_bytes());
+    write_string(&mut private_section, KEY_TYPE);
+    write_string(&mut private_section, public);
+    let mut seed_and_public = [0u8; 2 * KEY_SIZE];
+    seed_and_public[..KEY_SIZE].copy_from_slice(seed);
+    seed_and_public[KEY_SIZE..].copy_from_slice(public);
+    write_string(&mut private_section, &seed_and_public);
+    // Empty comment
+    write_string(&mut private_section, b"");
+    let mut padding = 1;
+    while private_section.len() % BLOCK_SIZE != 0 {
+        private_section.push(padding);
+        padding += 1;
+    }
+
+    let mut out = AUTH_MAGIC.to_vec();
+    write_string(&mut out, b"none");
+    write_string(&mut out, b"none");
+    write_string(&mut out, b"");
+    out.extend_from_slice(&1u32.to_be_bytes());
+    write_string(&mut out, &public_blob(public));
+    write_string(&mut out, &private_section);
+    out
+}
@@ -1,13 +1,39 @@
+use crate::crypto::hash::HashAlgorithm;
 use crate::errors::ConfigError;
-use crate::layers::compress::CompressionConfig;
+use crate::layers::compress::{
+    CompressionConfig, CompressionPersistentConfig, CompressionReaderConfig,
+};
 use crate::layers::encrypt::{
-    EncryptionConfig, EncryptionPersistentConfig, EncryptionReaderConfig,
+    EncryptionConfig, EncryptionPersistentConfig, EncryptionPersistentConfigV1,
+    EncryptionPersistentConfigV18, EncryptionPersistentConfigV2, EncryptionPersistentConfigV3,
+    EncryptionPersistentConfigV4, EncryptionPersistentConfigV5, EncryptionPersistentConfigV6,
+    EncryptionReaderConfig,
 };
-use crate::Layers;
+use crate::layers::fec::FecConfig;
+use crate::layers::sign::{SignatureConfig, SignatureReaderConfig};
+use crate::{Layers, BINCODE_MAX_DESERIALIZE, FILENAME_MAX_SIZE};
+use rand::SeedableRng;
+use rand_chacha::ChaChaRng;
 use serde::{Deserialize, Serialize};
 
 /// This module implements the configuration capabilities of MLA Archive
 
+/// Size of the seed of a deterministic archive, see
+/// `ArchiveWriterConfig::set_deterministic_seed`
+pub const SEED_SIZE: usize = 32;
+
+// Streams of the CSPRNG seeded for a deterministic archive, one per usage
+pub(crate) const KEY_STREAM: u64 = 0;
+pub(crate) const RECIPIENTS_STREAM: u64 = 1;
+pub(crate) const PADDING_STREAM: u64 = 2;
+
+/// CSPRNG derived from `seed`, on the given `stream`
+pub(crate) fn seeded_rng(seed: [u8; SEED_SIZE], stream: u64) -> ChaChaRng {
+    let mut rng = ChaChaRng::from_seed(seed);
+    rng.set_stream(stream);
+    rng
+}
+
 /// User's configuration used to prepare an archive
 pub struct ArchiveWriterConfig {
     layers_enabled: Layers,
@@ -15,6 +41,34 @@ pub struct ArchiveWriterConfig {
     // Layers specifics
     pub(crate) compress: CompressionConfig,
     pub(crate) encrypt: EncryptionConfig,
+    pub(crate) sign: SignatureConfig,
+    pub(crate) fec: FecConfig,
+
+    // File storage specifics
+    /// If set, the archive data is padded to a multiple of this size
+    pub(crate) padding: Option<u64>,
+    /// If set, index checkpoints are written in the archive data at this
+    /// interval
+    pub(crate) checkpoint: Option<CheckpointInterval>,
+    /// Algorithm of the hash of each file content
+    pub(crate) hash_algorithm: HashAlgorithm,
+    /// If set, identical file contents are only stored once
+    pub(crate) deduplication: bool,
+    /// If set, the randomness is derived from this seed, for reproducible
+    /// archives
+    pub(crate) seed: Option<[u8; SEED_SIZE]>,
+    /// Free-form comment stored in the header
+    pub(crate) comment: Option<String>,
+}
+
+/// Interval between two index checkpoints, see
+/// `ArchiveWriterConfig::with_checkpoint_interval`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckpointInterval {
+    /// Every given number of bytes of file content
+    Bytes(u64),
+    /// Every given number of ended files
+    Entries(u64),
 }
 
 /// Internal configuration stored in the header, to be reloaded
@@ -24,6 +78,257 @@ pub struct ArchivePersistentConfig {
 
     // Layers specifics
     pub encrypt: Option<EncryptionPersistentConfig>,
+    /// Absent before format version 13, meaning Brotli
+    pub compress: Option<CompressionPersistentConfig>,
+    /// Absent before format version 15, meaning SHA-256
+    pub hash_algorithm: HashAlgorithm,
+    /// Absent before format version 18
+    pub comment: Option<String>,
+}
+
+/// Internal configuration stored in the header of format version 18 archives
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ArchivePersistentConfigV18 {
+    layers_enabled: Layers,
+    encrypt: Option<EncryptionPersistentConfigV18>,
+    compress: Option<CompressionPersistentConfig>,
+    hash_algorithm: HashAlgorithm,
+    comment: Option<String>,
+}
+
+impl From<ArchivePersistentConfigV18> for ArchivePersistentConfig {
+    fn from(config: ArchivePersistentConfigV18) -> Self {
+        ArchivePersistentConfig {
+            layers_enabled: config.layers_enabled,
+            encrypt: config.encrypt.map(|encrypt| encrypt.into()),
+            compress: config.compress,
+            hash_algorithm: config.hash_algorithm,
+            comment: config.comment,
+        }
+    }
+}
+
+impl From<&ArchivePersistentConfig> for ArchivePersistentConfigV18 {
+    /// The key hints are dropped
+    fn from(config: &ArchivePersistentConfig) -> Self {
+        ArchivePersistentConfigV18 {
+            layers_enabled: config.layers_enabled,
+            encrypt: config.encrypt.as_ref().map(|encrypt| encrypt.into()),
+            compress: config.compress.clone(),
+            hash_algorithm: config.hash_algorithm,
+            comment: config.comment.clone(),
+        }
+    }
+}
+
+/// Internal configuration stored in the header of format version 15 to 17
+/// archives
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ArchivePersistentConfigV17 {
+    layers_enabled: Layers,
+    encrypt: Option<EncryptionPersistentConfigV18>,
+    compress: Option<CompressionPersistentConfig>,
+    hash_algorithm: HashAlgorithm,
+}
+
+impl From<ArchivePersistentConfigV17> for ArchivePersistentConfig {
+    fn from(config: ArchivePersistentConfigV17) -> Self {
+        ArchivePersistentConfig {
+            layers_enabled: config.layers_enabled,
+            encrypt: config.encrypt.map(|encrypt| encrypt.into()),
+            compress: config.compress,
+            hash_algorithm: config.hash_algorithm,
+            comment: None,
+        }
+    }
+}
+
+impl From<&ArchivePersistentConfig> for ArchivePersistentConfigV17 {
+    /// The comment and the key hints are dropped
+    fn from(config: &ArchivePersistentConfig) -> Self {
+        ArchivePersistentConfigV17 {
+            layers_enabled: config.layers_enabled,
+            encrypt: config.encrypt.as_ref().map(|encrypt| encrypt.into()),
+            compress: config.compress.clone(),
+            hash_algorithm: config.hash_algorithm,
+        }
+    }
+}
+
+/// Internal configuration stored in the header of format version 13 and 14
+/// archives
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ArchivePersistentConfigV14 {
+    layers_enabled: Layers,
+    encrypt: Option<EncryptionPersistentConfigV18>,
+    compress: Option<CompressionPersistentConfig>,
+}
+
+impl From<ArchivePersistentConfigV14> for ArchivePersistentConfig {
+    fn from(config: ArchivePersistentConfigV14) -> Self {
+        ArchivePersistentConfig {
+            layers_enabled: config.layers_enabled,
+            encrypt: config.encrypt.map(|encrypt| encrypt.into()),
+            compress: config.compress,
+            hash_algorithm: HashAlgorithm::Sha256,
+            comment: None,
+        }
+    }
+}
+
+impl From<&ArchivePersistentConfig> for ArchivePersistentConfigV14 {
+    /// The hash algorithm is dropped, so it must be SHA-256
+    fn from(config: &ArchivePersistentConfig) -> Self {
+        ArchivePersistentConfigV14 {
+            layers_enabled: config.layers_enabled,
+            encrypt: config.encrypt.as_ref().map(|encrypt| encrypt.into()),
+            compress: config.compress.clone(),
+        }
+    }
+}
+
+/// Internal configuration stored in the header of format version 7 to 12
+/// archives
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ArchivePersistentConfigV12 {
+    layers_enabled: Layers,
+    encrypt: Option<EncryptionPersistentConfigV18>,
+}
+
+impl From<ArchivePersistentConfigV12> for ArchivePersistentConfig {
+    fn from(config: ArchivePersistentConfigV12) -> Self {
+        ArchivePersistentConfig {
+            layers_enabled: config.layers_enabled,
+            encrypt: config.encrypt.map(|encrypt| encrypt.into()),
+            compress: None,
+            hash_algorithm: HashAlgorithm::Sha256,
+            comment: None,
+        }
+    }
+}
+
+impl From<&ArchivePersistentConfig> for ArchivePersistentConfigV12 {
+    /// The compression algorithm is dropped, so it must be Brotli
+    fn from(config: &ArchivePersistentConfig) -> Self {
+        ArchivePersistentConfigV12 {
+            layers_enabled: config.layers_enabled,
+            encrypt: config.encrypt.as_ref().map(|encrypt| encrypt.into()),
+        }
+    }
+}
+
+/// Internal configuration stored in the header of format version 1 archives
+#[derive(Deserialize)]
+pub(crate) struct ArchivePersistentConfigV1 {
+    layers_enabled: Layers,
+    encrypt: Option<EncryptionPersistentConfigV1>,
+}
+
+impl From<ArchivePersistentConfigV1> for ArchivePersistentConfig {
+    fn from(config: ArchivePersistentConfigV1) -> Self {
+        ArchivePersistentConfig {
+            layers_enabled: config.layers_enabled,
+            encrypt: config.encrypt.map(|encrypt| encrypt.into()),
+            compress: None,
+            hash_algorithm: HashAlgorithm::Sha256,
+            comment: None,
+        }
+    }
+}
+
+/// Internal configuration stored in the header of format version 2 archives
+#[derive(Deserialize)]
+pub(crate) struct ArchivePersistentConfigV2 {
+    layers_enabled: Layers,
+    encrypt: Option<EncryptionPersistentConfigV2>,
+}
+
+impl From<ArchivePersistentConfigV2> for ArchivePersistentConfig {
+    fn from(config: ArchivePersistentConfigV2) -> Self {
+        ArchivePersistentConfig {
+            layers_enabled: config.layers_enabled,
+            encrypt: config.encrypt.map(|encrypt| encrypt.into()),
+            compress: None,
+            hash_algorithm: HashAlgorithm::Sha256,
+            comment: None,
+        }
+    }
+}
+
+/// Internal configuration stored in the header of format version 3 archives
+#[derive(Deserialize)]
+pub(crate) struct ArchivePersistentConfigV3 {
+    layers_enabled: Layers,
+    encrypt: Option<EncryptionPersistentConfigV3>,
+}
+
+impl From<ArchivePersistentConfigV3> for ArchivePersistentConfig {
+    fn from(config: ArchivePersistentConfigV3) -> Self {
+        ArchivePersistentConfig {
+            layers_enabled: config.layers_enabled,
+            encrypt: config.encrypt.map(|encrypt| encrypt.into()),
+            compress: None,
+            hash_algorithm: HashAlgorithm::Sha256,
+            comment: None,
+        }
+    }
+}
+
+/// Internal configuration stored in the header of format version 4 archives
+#[derive(Deserialize)]
+pub(crate) struct ArchivePersistentConfigV4 {
+    layers_enabled: Layers,
+    encrypt: Option<EncryptionPersistentConfigV4>,
+}
+
+impl From<ArchivePersistentConfigV4> for ArchivePersistentConfig {
+    fn from(config: ArchivePersistentConfigV4) -> Self {
+        ArchivePersistentConfig {
+            layers_enabled: config.layers_enabled,
+            encrypt: config.encrypt.map(|encrypt| encrypt.into()),
+            compress: None,
+            hash_algorithm: HashAlgorithm::Sha256,
+            comment: None,
+        }
+    }
+}
+
+/// Internal configuration stored in the header of format version 5 archives
+#[derive(Deserialize)]
+pub(crate) struct ArchivePersistentConfigV5 {
+    layers_enabled: Layers,
+    encrypt: Option<EncryptionPersistentConfigV5>,
+}
+
+impl From<ArchivePersistentConfigV5> for ArchivePersistentConfig {
+    fn from(config: ArchivePersistentConfigV5) -> Self {
+        ArchivePersistentConfig {
+            layers_enabled: config.layers_enabled,
+            encrypt: config.encrypt.map(|encrypt| encrypt.into()),
+            compress: None,
+            hash_algorithm: HashAlgorithm::Sha256,
+            comment: None,
+        }
+    }
+}
+
+/// Internal configuration stored in the header of format version 6 archives
+#[derive(Deserialize)]
+pub(crate) struct ArchivePersistentConfigV6 {
+    layers_enabled: Layers,
+    encrypt: Option<EncryptionPersistentConfigV6>,
+}
+
+impl From<ArchivePersistentConfigV6> for ArchivePersistentConfig {
+    fn from(config: ArchivePersistentConfigV6) -> Self {
+        ArchivePersistentConfig {
+            layers_enabled: config.layers_enabled,
+            encrypt: config.encrypt.map(|encrypt| encrypt.into()),
+            compress: None,
+            hash_algorithm: HashAlgorithm::Sha256,
+            comment: None,
+        }
+    }
 }
 
 pub type ConfigResult<'a> = Result<&'a mut ArchiveWriterConfig, ConfigError>;
@@ -35,6 +340,14 @@ impl ArchiveWriterConfig {
             layers_enabled: Layers::EMPTY,
             compress: CompressionConfig::default(),
             encrypt: EncryptionConfig::default(),
+            sign: SignatureConfig::default(),
+            fec: FecConfig::default(),
+            padding: None,
+            checkpoint: None,
+            hash_algorithm: HashAlgorithm::default(),
+            deduplication: false,
+            seed: None,
+            comment: None,
         }
     }
 
@@ -56,6 +369,90 @@ impl ArchiveWriterConfig {
         self
     }
 
+    /// Pad the archive data, footer included, to a multiple of `bucket_size`
+    /// bytes, to avoid leaking the total size of the files
+    ///
+    /// Padding is made of random bytes, skipped by readers. As it is added
+    /// before the compression and encryption layers, the final archive size is
+    /// only approximately a multiple of `bucket_size` when compression is
+    /// enabled.
+    pub fn set_padding(&mut self, bucket_size: u64) -> &mut ArchiveWriterConfig {
+        self.padding = Some(bucket_size);
+        self
+    }
+
+    /// Write a checkpoint of the archive index in the archive data, at the
+    /// given `interval`
+    ///
+    /// Each checkpoint lists the files started or ended since the previous
+    /// one, and the ones still opened, with their size so far. If the writer
+    /// is interrupted before the archive is finalized, the fail-safe reader
+    /// relies on them to recover the name of files whose start is damaged.
+    /// The interval must not be 0
+    pub fn with_checkpoint_interval(&mut self, interval: CheckpointInterval) -> ConfigResult {
+        match interval {
+            CheckpointInterval::Bytes(0) | CheckpointInterval::Entries(0) => {
+                Err(ConfigError::InvalidCheckpointInterval)
+            }
+            _ => {
+                self.checkpoint = Some(interval);
+                Ok(self)
+            }
+        }
+    }
+
+    /// Set the algorithm of the hash of each file content (SHA-256 by
+    /// default)
+    ///
+    /// It is recorded in the archive header, so readers do not need it.
+    /// BLAKE3 is much faster on big files
+    pub fn set_hash_algorithm(&mut self, algorithm: HashAlgorithm) -> &mut ArchiveWriterConfig {
+        self.hash_algorithm = algorithm;
+        self
+    }
+
+    /// Only store once identical file contents (disabled by default)
+    ///
+    /// Files added with `ArchiveWriter::add_file_seekable` are then hashed
+    /// first. If a file with the same size and hash is already stored, the
+    /// new name refers to its data in the archive index, which readers
+    /// resolve transparently. The repair process only recovers the first name
+    /// of each content
+    pub fn set_deduplication(&mut self, deduplication: bool) -> &mut ArchiveWriterConfig {
+        self.deduplication = deduplication;
+        self
+    }
+
+    /// Produce a reproducible archive: given the same `seed`, configuration
+    /// and files, added in the same order, the archive is byte-identical
+    ///
+    /// The symmetric encryption key and nonce, the randomness used to store
+    /// them for each recipient, and the padding are derived from `seed`
+    /// instead of the system entropy. As a consequence, when encryption is
+    /// enabled, `seed` must be kept as secret as the archive content, and
+    /// must not be reused for a different content, which would then be
+    /// encrypted with the same key and nonce
+    ///
+    /// For the same reason, a seed can't be used to add files to an existing
+    /// archive, with `ArchiveWriter::from_archive` or `from_archive_config`,
+    /// which fail with `ConfigError::DeterministicSeedWithExistingKey`
+    pub fn set_deterministic_seed(&mut self, seed: [u8; SEED_SIZE]) -> &mut ArchiveWriterConfig {
+        self.seed = Some(seed);
+        self.encrypt.set_seed(seed);
+        self
+    }
+
+    /// Store a free-form `comment` in the archive header, such as a case ID
+    /// or a JSON object describing the acquisition, given back by
+    /// `ArchiveReader::comment`
+    ///
+    /// The header is neither encrypted nor compressed: the comment can be
+    /// read without any key, and must not contain sensitive data
+    pub fn set_comment(&mut self, comment: &str) -> &mut ArchiveWriterConfig {
+        self.comment = Some(comment.to_string());
+        self
+    }
+
     /// Get the persistent version, to be stored in the header
     pub fn to_persistent(&self) -> Result<ArchivePersistentConfig, ConfigError> {
         Ok(ArchivePersistentConfig {
@@ -67,6 +464,15 @@ impl ArchiveWriterConfig {
                     None
                 }
             },
+            compress: {
+                if self.is_layers_enabled(Layers::COMPRESS) {
+                    Some(self.compress.to_persistent())
+                } else {
+                    None
+                }
+            },
+            hash_algorithm: self.hash_algorithm,
+            comment: self.comment.clone(),
         })
     }
 
@@ -80,6 +486,12 @@ impl ArchiveWriterConfig {
         if self.is_layers_enabled(Layers::ENCRYPT) {
             self.encrypt.check()?;
         }
+        if self.is_layers_enabled(Layers::SIGN) {
+            self.sign.check()?;
+        }
+        if self.padding == Some(0) {
+            return Err(ConfigError::InvalidPaddingSize);
+        }
         Ok(())
     }
 }
@@ -93,17 +505,73 @@ impl std::default::Default for ArchiveWriterConfig {
             layers_enabled: Layers::default(),
             compress: CompressionConfig::default(),
             encrypt: EncryptionConfig::default(),
+            sign: SignatureConfig::default(),
+            fec: FecConfig::default(),
+            padding: None,
+            checkpoint: None,
+            hash_algorithm: HashAlgorithm::default(),
+            deduplication: false,
+            seed: None,
+            comment: None,
         }
     }
 }
 
 /// User's configuration used to read an archive
-#[derive(Default)]
+///
+/// It can be cloned to open several readers of the same archive, for
+/// instance from different threads
+#[derive(Default, Clone)]
 pub struct ArchiveReaderConfig {
     pub layers_enabled: Layers,
 
     // Layers specifics
     pub encrypt: EncryptionReaderConfig,
+    pub sign: SignatureReaderConfig,
+    pub compress: CompressionReaderConfig,
+
+    /// Limits on the archive structure
+    pub limits: ReaderLimits,
+}
+
+/// Limits on the structure of an archive, checked while opening it, before
+/// the corresponding data is loaded, and on the memory used to read it
+///
+/// They are meant for archives from untrusted sources, or too big for the
+/// reading host. By default, nothing is limited beyond the format own bounds
+#[derive(Default, Clone)]
+pub struct ReaderLimits {
+    /// Maximum number of entries
+    pub(crate) max_entries: Option<u64>,
+    /// Maximum length of a file name, in bytes
+    pub(crate) max_filename_size: Option<u64>,
+    /// Maximum size of the footer (the index of the entries), in bytes
+    pub(crate) max_footer_size: Option<u64>,
+    /// Whether values out of the format specification are rejected
+    pub(crate) strict: bool,
+    /// Memory available for the footer and the buffers of an entry, in bytes
+    pub(crate) memory_budget: Option<u64>,
+}
+
+impl ReaderLimits {
+    /// Maximum length of a file name, with the format one in strict mode
+    pub(crate) fn filename_max_size(&self) -> Option<u64> {
+        match self.max_filename_size {
+            Some(size) if self.strict => Some(std::cmp::min(size, FILENAME_MAX_SIZE)),
+            Some(size) => Some(size),
+            None if self.strict => Some(FILENAME_MAX_SIZE),
+            None => None,
+        }
+    }
+
+    /// Bound on the lengths read while deserializing a footer of `len` bytes
+    pub(crate) fn footer_bincode_limit(&self, len: u64) -> u64 {
+        if self.strict {
+            len
+        } else {
+            BINCODE_MAX_DESERIALIZE
+        }
+    }
 }
 
 impl ArchiveReaderConfig {
@@ -112,9 +580,61 @@ impl ArchiveReaderConfig {
         Self {
             layers_enabled: Layers::EMPTY,
             encrypt: EncryptionReaderConfig::default(),
+            sign: SignatureReaderConfig::default(),
+            compress: CompressionReaderConfig::default(),
+            limits: ReaderLimits::default(),
         }
     }
 
+    /// Refuse archives with more than `count` entries, failing with
+    /// `Error::TooManyEntries`
+    pub fn set_max_entries(&mut self, count: u64) -> &mut ArchiveReaderConfig {
+        self.limits.max_entries = Some(count);
+        self
+    }
+
+    /// Refuse archives with a file name longer than `size` bytes, failing
+    /// with `Error::FilenameTooLong`
+    pub fn set_max_filename_size(&mut self, size: u64) -> &mut ArchiveReaderConfig {
+        self.limits.max_filename_size = Some(size);
+        self
+    }
+
+    /// Refuse archives whose footer, listing the entries, is larger than
+    /// `size` bytes, failing with `Error::FooterTooLarge`
+    pub fn set_max_footer_size(&mut self, size: u64) -> &mut ArchiveReaderConfig {
+        self.limits.max_footer_size = Some(size);
+        self
+    }
+
+    /// Enable the strict parsing: lengths and offsets read from the archive
+    /// are checked against the format specification and the actual sizes
+    /// before being used, so nothing is allocated beyond the data available
+    ///
+    /// Out of specification values are rejected with
+    /// `Error::DeserializationError`, or `Error::FilenameTooLong`
+    pub fn set_strict(&mut self, strict: bool) -> &mut ArchiveReaderConfig {
+        self.limits.strict = strict;
+        self
+    }
+
+    /// Bound the memory used to read the archive to about `size` bytes, for
+    /// archives with a huge number of entries
+    ///
+    /// If the footer, listing the entries, is larger than `size`, it is left
+    /// in the archive: entries are then read from it when needed, through an
+    /// index kept in memory if it fits in `size`. Per entry buffers, such as
+    /// the one holding content copied from another file, are capped too.
+    ///
+    /// In this case, methods giving information on every entry at once
+    /// (`ArchiveReader::list_files`, `get_hashes`, linear extraction, etc.)
+    /// fail with `Error::MissingMetadata`: `ArchiveReader::for_each_entry`
+    /// goes through the entries instead
+    pub fn set_memory_budget(&mut self, size: u64) -> &mut ArchiveReaderConfig {
+        self.limits.memory_budget = Some(size);
+        self
+    }
+
     pub fn load_persistent(
         &mut self,
         config: ArchivePersistentConfig,
@@ -130,6 +650,12 @@ impl ArchiveReaderConfig {
                 }
             }
         }
+        if let Some(to_load) = config.compress {
+            self.compress.load_persistent(to_load);
+        }
+        if !self.layers_enabled.contains(Layers::SIGN) && !self.sign.trusted_keys.is_empty() {
+            return Err(ConfigError::SignatureNotFound);
+        }
         Ok(self)
     }
 }
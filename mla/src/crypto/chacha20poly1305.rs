@@ -0,0 +1,182 @@
+use crate::crypto::aesgcm::Tag;
+use crate::Error;
+
+use chacha20::stream_cipher::{NewStreamCipher, SyncStreamCipher, SyncStreamCipherSeek};
+use chacha20::ChaCha20;
+use generic_array::GenericArray;
+use poly1305::{
+    universal_hash::{NewUniversalHash, UniversalHash},
+    Poly1305,
+};
+
+pub const BLOCK_SIZE: usize = poly1305::BLOCK_SIZE;
+/// Size of a ChaCha20 block, the first one being used for the Poly1305 key
+const CHACHA20_BLOCK_SIZE: u64 = 64;
+
+/// ChaCha20-Poly1305, as in RFC 8439, with the same streaming API than
+/// `AesGcm256`
+pub struct ChaCha20Poly1305 {
+    cipher: ChaCha20,
+    /// Poly1305, for data authentication
+    poly1305: Poly1305,
+    /// Size of the authenticated data, in bytes
+    associated_data_len: u64,
+    /// Encrypted data not yet authenticated, unaligned with the BLOCK_SIZE (see
+    /// `AesGcm256`)
+    current_block: Vec<u8>,
+    /// Number of bytes encrypted
+    bytes_encrypted: u64,
+}
+
+impl ChaCha20Poly1305 {
+    pub fn new(
+        key: &[u8],
+        nonce: &[u8],
+        associated_data: &[u8],
+    ) -> Result<ChaCha20Poly1305, Error> {
+        let mut cipher = ChaCha20::new_var(key, nonce)?;
+
+        // The Poly1305 key is made of the first 32 bytes of the first block
+        let mut poly1305_key = poly1305::Key::default();
+        cipher.apply_keystream(&mut poly1305_key);
+        let mut poly1305 = Poly1305::new(&poly1305_key);
+        poly1305.update_padded(associated_data);
+
+        // The rest of the first block is ignored
+        cipher.seek(CHACHA20_BLOCK_SIZE);
+
+        Ok(ChaCha20Poly1305 {
+            cipher,
+            poly1305,
+            associated_data_len: associated_data.len() as u64,
+            current_block: Vec::with_capacity(BLOCK_SIZE),
+            bytes_encrypted: 0,
+        })
+    }
+
+    pub fn encrypt(&mut self, mut buffer: &mut [u8]) {
+        self.bytes_encrypted += buffer.len() as u64;
+
+        // Finish the current block, if any
+        if !self.current_block.is_empty() {
+            if (self.current_block.len() + buffer.len()) < BLOCK_SIZE {
+                self.cipher.apply_keystream(buffer);
+                self.current_block.extend_from_slice(buffer);
+                return;
+            } else {
+                let (in_block, out_block) =
+                    buffer.split_at_mut(BLOCK_SIZE - self.current_block.len());
+                self.cipher.apply_keystream(in_block);
+                self.current_block.extend_from_slice(in_block);
+                self.poly1305
+                    .update(GenericArray::from_slice(self.current_block.as_slice()));
+                self.current_block.clear();
+
+                buffer = out_block;
+            }
+        }
+
+        let mut chunks = buffer.chunks_exact_mut(BLOCK_SIZE);
+
+        // Interleaved Poly1305 update
+        for chunk in &mut chunks {
+            self.cipher.apply_keystream(chunk);
+            self.poly1305.update(GenericArray::from_slice(chunk));
+        }
+
+        let rem = chunks.into_remainder();
+        if !rem.is_empty() {
+            self.cipher.apply_keystream(rem);
+            self.current_block.extend_from_slice(rem);
+        }
+    }
+
+    /// Compute the tag, once the encrypted data has been authenticated
+    fn finalize(mut poly1305: Poly1305, associated_data_len: u64, data_len: u64) -> Tag {
+        // Compute "le64(len(associated data)) || le64(len(bytes encrypted))"
+        let mut block = GenericArray::default();
+        block[..8].copy_from_slice(&associated_data_len.to_le_bytes());
+        block[8..].copy_from_slice(&data_len.to_le_bytes());
+        poly1305.update(&block);
+        poly1305.finalize().into_bytes()
+    }
+
+    /// Finalize encryption and returns the associated tag
+    // Force a move, to avoid further calls to `encrypt`
+    pub fn into_tag(mut self) -> Tag {
+        // Finish the current block, if any
+        self.poly1305.update_padded(&self.current_block);
+        Self::finalize(
+            self.poly1305,
+            self.associated_data_len,
+            self.bytes_encrypted,
+        )
+    }
+
+    /// Decrypt without considering the associated data
+    /// /!\ this mode of decryption is unauthenticated, use it carefully
+    pub fn decrypt_unauthenticated(&mut self, buffer: &mut [u8]) {
+        self.cipher.apply_keystream(buffer);
+    }
+
+    /// Decrypt and compute the associated tag
+    pub fn decrypt(&mut self, buffer: &mut [u8]) -> Tag {
+        let mut chunks = buffer.chunks_exact_mut(BLOCK_SIZE);
+
+        // Interleaved Poly1305 update
+        for chunk in &mut chunks {
+            self.poly1305.update(GenericArray::from_slice(chunk));
+            self.cipher.apply_keystream(chunk);
+        }
+
+        let rem = chunks.into_remainder();
+        if !rem.is_empty() {
+            self.poly1305.update_padded(rem);
+            self.cipher.apply_keystream(rem);
+        }
+
+        Self::finalize(
+            self.poly1305.clone(),
+            self.associated_data_len,
+            buffer.len() as u64,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex_literal::hex;
+
+    // From RFC 8439, section 2.8.2
+    const KEY: [u8; 32] = hex!("808182838485868788898a8b8c8d8e8f909192939495969798999a9b9c9d9e9f");
+    const NONCE: [u8; 12] = hex!("070000004041424344454647");
+    const AAD: [u8; 12] = hex!("50515253c0c1c2c3c4c5c6c7");
+    const PLAINTEXT: &[u8; 114] = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+    const CIPHERTEXT: [u8; 114] = hex!(
+        "d31a8d34648e60db7b86afbc53ef7ec2a4aded51296e08fea9e2b5a736ee62d6"
+        "3dbea45e8ca9671282fafb69da92728b1a71de0a9e060b2905d6a5b67ecd3b36"
+        "92ddbd7f2d778b8c9803aee328091b58fab324e4fad675945585808b4831d7bc"
+        "3ff4def08e4b7a9de576d26586cec64b6116"
+    );
+    const TAG: [u8; 16] = hex!("1ae10b594f09e26a7e902ecbd0600691");
+
+    #[test]
+    fn rfc8439_vector() {
+        // Encrypt in several parts, unaligned with the block size
+        let mut cipher = ChaCha20Poly1305::new(&KEY, &NONCE, &AAD).unwrap();
+        let mut buffer = PLAINTEXT.to_vec();
+        let (first, rest) = buffer.split_at_mut(5);
+        cipher.encrypt(first);
+        let (second, third) = rest.split_at_mut(40);
+        cipher.encrypt(second);
+        cipher.encrypt(third);
+        assert_eq!(&buffer[..], &CIPHERTEXT[..]);
+        assert_eq!(cipher.into_tag().as_slice(), &TAG);
+
+        let mut cipher = ChaCha20Poly1305::new(&KEY, &NONCE, &AAD).unwrap();
+        let mut buffer = CIPHERTEXT.to_vec();
+        assert_eq!(cipher.decrypt(&mut buffer).as_slice(), &TAG);
+        assert_eq!(&buffer[..], &PLAINTEXT[..]);
+    }
+}
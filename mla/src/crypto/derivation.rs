@@ -0,0 +1,77 @@
+use crate::errors::Error;
+use hkdf::Hkdf;
+use sha2::Sha256;
+use x25519_dalek::StaticSecret;
+use zeroize::Zeroize;
+
+const KEY_SIZE: usize = 32;
+const DERIVATION_SALT: &[u8; 19] = b"MLA KEY DERIVATION ";
+
+/// Derive a sub-key from `parent`, for the given `label`
+fn derive_child(parent: &StaticSecret, label: &[u8]) -> Result<StaticSecret, Error> {
+    let mut parent_bytes = parent.to_bytes();
+    let hkdf: Hkdf<Sha256> = Hkdf::new(Some(DERIVATION_SALT), &parent_bytes);
+    parent_bytes.zeroize();
+    let mut output = [0u8; KEY_SIZE];
+    hkdf.expand(label, &mut output)?;
+    let child = StaticSecret::from(output);
+    output.zeroize();
+    Ok(child)
+}
+
+/// Deterministically derive a private key from the `root` private key,
+/// through the `path` of labels (for instance, `["case-42", "host-1"]`)
+///
+/// Each label derives a sub-key from the previous one, using HKDF-SHA256. The
+/// owner of `root`, or of any intermediate key, can then recompute the private
+/// key of archives made with the derived public key, while the derived keys
+/// give no access to their parent
+pub fn derive_private_key(root: &StaticSecret, path: &[&str]) -> Result<StaticSecret, Error> {
+    let mut key = root.clone();
+    for label in path {
+        key = derive_child(&key, label.as_bytes())?;
+    }
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+    use x25519_dalek::PublicKey;
+
+    #[test]
+    fn derivation() {
+        let mut csprng = ChaChaRng::from_entropy();
+        let root = StaticSecret::new(&mut csprng);
+
+        // An empty path is the root itself
+        let derived = derive_private_key(&root, &[]).unwrap();
+        assert_eq!(derived.to_bytes(), root.to_bytes());
+
+        // Deterministic
+        let derived = derive_private_key(&root, &["case", "host"]).unwrap();
+        let derived2 = derive_private_key(&root, &["case", "host"]).unwrap();
+        assert_eq!(derived.to_bytes(), derived2.to_bytes());
+
+        // Derivation from an intermediate key
+        let intermediate = derive_private_key(&root, &["case"]).unwrap();
+        let derived2 = derive_private_key(&intermediate, &["host"]).unwrap();
+        assert_eq!(derived.to_bytes(), derived2.to_bytes());
+
+        // Different paths give different keys
+        let others = [
+            derive_private_key(&root, &["case"]).unwrap(),
+            derive_private_key(&root, &["host", "case"]).unwrap(),
+            derive_private_key(&root, &["casehost"]).unwrap(),
+            derive_private_key(&root, &["case", "host2"]).unwrap(),
+        ];
+        for other in others.iter() {
+            assert_ne!(
+                PublicKey::from(other).as_bytes(),
+                PublicKey::from(&derived).as_bytes()
+            );
+        }
+    }
+}
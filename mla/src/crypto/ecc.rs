@@ -27,13 +27,13 @@ fn derive_key(
     Ok(output)
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 struct KeyAndTag {
     key: [u8; KEY_SIZE],
     tag: [u8; aesgcm::TAG_LENGTH],
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct MultiRecipientPersistent {
     /// Ephemeral public key
     public: [u8; 32],
@@ -53,6 +53,21 @@ pub(crate) fn store_key_for_multi_recipients<T>(
     key: &[u8; KEY_SIZE],
     csprng: &mut T,
 ) -> Result<MultiRecipientPersistent, Error>
+where
+    T: RngCore + CryptoRng,
+{
+    let keys = vec![*key; recipients.len()];
+    store_keys_for_multi_recipients(recipients, &keys, csprng)
+}
+
+/// Perform ECIES with several recipients, each one getting its own key of
+/// `keys` (for instance, a share of the common key), and return a serializable
+/// structure
+pub(crate) fn store_keys_for_multi_recipients<T>(
+    recipients: &[PublicKey],
+    keys: &[[u8; KEY_SIZE]],
+    csprng: &mut T,
+) -> Result<MultiRecipientPersistent, Error>
 where
     T: RngCore + CryptoRng,
 {
@@ -62,7 +77,7 @@ where
 
     let public = PublicKey::from(&ephemeral);
     let mut encrypted_keys = Vec::new();
-    for recipient in recipients.iter() {
+    for (recipient, key) in recipients.iter().zip(keys.iter()) {
         // Perform an ECIES to obtain the common key
         let dh_key = derive_key(&ephemeral, recipient, KEY_SIZE)?;
 
@@ -93,17 +108,26 @@ pub(crate) fn retrieve_key(
     persist: &MultiRecipientPersistent,
     private_key: &StaticSecret,
 ) -> Result<Option<[u8; KEY_SIZE]>, Error> {
+    Ok(retrieve_key_with_index(persist, private_key)?.map(|(_index, key)| key))
+}
+
+/// Same as `retrieve_key`, but also return the index of the recipient
+/// corresponding to `private_key`
+pub(crate) fn retrieve_key_with_index(
+    persist: &MultiRecipientPersistent,
+    private_key: &StaticSecret,
+) -> Result<Option<(usize, [u8; KEY_SIZE])>, Error> {
     // Perform an ECIES to obtain the common key
     let key = derive_key(private_key, &PublicKey::from(persist.public), KEY_SIZE)?;
 
     // Try to find the correct key using the tag validation
-    for keytag in persist.encrypted_keys.iter() {
+    for (index, keytag) in persist.encrypted_keys.iter().enumerate() {
         let mut cipher = aesgcm::AesGcm256::new(key.as_slice(), ECIES_NONCE, b"")?;
         let mut data = [0u8; KEY_SIZE];
         data.copy_from_slice(&keytag.key);
         let tag = cipher.decrypt(&mut data);
         if tag.ct_eq(&keytag.tag).unwrap_u8() == 1 {
-            return Ok(Some(data));
+            return Ok(Some((index, data)));
         }
     }
     Ok(None)
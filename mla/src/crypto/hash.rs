@@ -1,17 +1,95 @@
-use sha2::{Digest, Sha256};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
 use std::io;
-use std::io::Read;
+use std::io::{Read, Write};
 
-pub(crate) type Sha256Hash = [u8; 32];
+/// Algorithm of the hash of each file content, recorded in the header
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    Sha512,
+    /// Much faster on big files
+    Blake3,
+}
+
+impl HashAlgorithm {
+    /// Size of the hashes, in bytes
+    pub fn hash_size(&self) -> usize {
+        match self {
+            HashAlgorithm::Sha256 => 32,
+            HashAlgorithm::Sha512 => 64,
+            HashAlgorithm::Blake3 => blake3::OUT_LEN,
+        }
+    }
+
+    /// Usual name of the algorithm
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Sha512 => "sha512",
+            HashAlgorithm::Blake3 => "blake3",
+        }
+    }
+}
+
+/// Hash of a file content, computed with one of `HashAlgorithm`
+///
+/// Data can also be given through `Write`, for instance with `io::copy`
+#[derive(Clone, Debug)]
+pub enum FileHasher {
+    Sha256(Sha256),
+    Sha512(Sha512),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl FileHasher {
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha256 => FileHasher::Sha256(Sha256::new()),
+            HashAlgorithm::Sha512 => FileHasher::Sha512(Sha512::new()),
+            HashAlgorithm::Blake3 => FileHasher::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            FileHasher::Sha256(hasher) => hasher.update(data),
+            FileHasher::Sha512(hasher) => hasher.update(data),
+            FileHasher::Blake3(hasher) => {
+                hasher.update(data);
+            }
+        }
+    }
+
+    pub fn finalize(self) -> Vec<u8> {
+        match self {
+            FileHasher::Sha256(hasher) => hasher.finalize().to_vec(),
+            FileHasher::Sha512(hasher) => hasher.finalize().to_vec(),
+            FileHasher::Blake3(hasher) => hasher.finalize().as_bytes().to_vec(),
+        }
+    }
+}
+
+impl Write for FileHasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
 
 pub(crate) struct HashWrapperReader<'a, R: Read> {
     /// Wrapper over a `impl Read` updating `hash` on each call to `read`
     inner: R,
-    hash: &'a mut Sha256,
+    hash: &'a mut FileHasher,
 }
 
 impl<'a, R: Read> HashWrapperReader<'a, R> {
-    pub(crate) fn new(inner: R, hash: &'a mut Sha256) -> Self {
+    pub(crate) fn new(inner: R, hash: &'a mut FileHasher) -> Self {
         Self { inner, hash }
     }
 }
@@ -0,0 +1,321 @@
+//! Hybrid post-quantum key encapsulation, combining X25519 and ML-KEM-768
+//!
+//! The shared key is wrapped with a key derived from both shared secrets, so
+//! it stays confidential as long as one of the two algorithms is not broken
+
+use crate::crypto::aesgcm;
+use crate::crypto::aesgcm::ConstantTimeEq;
+use crate::errors::Error;
+use hkdf::Hkdf;
+use ml_kem::kem::{Decapsulate, KeyExport};
+use ml_kem::{ml_kem_768, DecapsulationKey768, EncapsulationKey768, Seed, B32};
+use rand::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::convert::{TryFrom, TryInto};
+use x25519_dalek::{PublicKey, StaticSecret};
+use zeroize::Zeroize;
+
+const KEY_SIZE: usize = 32;
+const X25519_KEY_SIZE: usize = 32;
+const MLKEM_SEED_SIZE: usize = 64;
+const MLKEM_PUBLIC_KEY_SIZE: usize = 1184;
+const HYBRID_DERIVE_KEY_INFO: &[u8; 21] = b"HYBRID KEY DERIVATION";
+// As the key is derived from an ephemeral secret, it is used only once
+const HYBRID_NONCE: &[u8; 12] = b"HYBRID NONCE";
+
+/// Size of a serialized `HybridPrivateKey`
+pub const HYBRID_PRIVATE_KEY_SIZE: usize = X25519_KEY_SIZE + MLKEM_SEED_SIZE;
+/// Size of a serialized `HybridPublicKey`
+pub const HYBRID_PUBLIC_KEY_SIZE: usize = X25519_KEY_SIZE + MLKEM_PUBLIC_KEY_SIZE;
+
+/// Key encapsulation mechanism used, recorded in the header
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum HybridKem {
+    X25519MlKem768,
+}
+
+/// Hybrid private key: an X25519 private key and an ML-KEM-768 seed
+#[derive(Clone)]
+pub struct HybridPrivateKey {
+    x25519: StaticSecret,
+    mlkem_seed: [u8; MLKEM_SEED_SIZE],
+}
+
+impl Drop for HybridPrivateKey {
+    fn drop(&mut self) {
+        self.mlkem_seed.zeroize();
+    }
+}
+
+/// Hybrid public key: an X25519 public key and an ML-KEM-768 encapsulation key
+#[derive(Clone)]
+pub struct HybridPublicKey {
+    x25519: PublicKey,
+    mlkem: EncapsulationKey768,
+}
+
+impl HybridPrivateKey {
+    /// Generate a new hybrid private key
+    pub fn generate<T>(csprng: &mut T) -> Self
+    where
+        T: RngCore + CryptoRng,
+    {
+        let mut mlkem_seed = [0u8; MLKEM_SEED_SIZE];
+        csprng.fill_bytes(&mut mlkem_seed);
+        HybridPrivateKey {
+            x25519: StaticSecret::new(csprng),
+            mlkem_seed,
+        }
+    }
+
+    /// Load a private key serialized by `to_bytes`
+    pub fn from_bytes(data: &[u8]) -> Result<Self, Error> {
+        if data.len() != HYBRID_PRIVATE_KEY_SIZE {
+            return Err(Error::InvalidECCKeyFormat);
+        }
+        let mut x25519 = [0u8; X25519_KEY_SIZE];
+        x25519.copy_from_slice(&data[..X25519_KEY_SIZE]);
+        let mut mlkem_seed = [0u8; MLKEM_SEED_SIZE];
+        mlkem_seed.copy_from_slice(&data[X25519_KEY_SIZE..]);
+        let key = HybridPrivateKey {
+            x25519: StaticSecret::from(x25519),
+            mlkem_seed,
+        };
+        x25519.zeroize();
+        mlkem_seed.zeroize();
+        Ok(key)
+    }
+
+    /// Serialize as `[X25519 private key][ML-KEM-768 seed]`
+    pub fn to_bytes(&self) -> [u8; HYBRID_PRIVATE_KEY_SIZE] {
+        let mut out = [0u8; HYBRID_PRIVATE_KEY_SIZE];
+        out[..X25519_KEY_SIZE].copy_from_slice(&self.x25519.to_bytes());
+        out[X25519_KEY_SIZE..].copy_from_slice(&self.mlkem_seed);
+        out
+    }
+
+    fn mlkem(&self) -> DecapsulationKey768 {
+        DecapsulationKey768::from_seed(Seed::from(self.mlkem_seed))
+    }
+
+    /// Return the corresponding public key
+    pub fn public_key(&self) -> HybridPublicKey {
+        HybridPublicKey {
+            x25519: PublicKey::from(&self.x25519),
+            mlkem: self.mlkem().encapsulation_key().clone(),
+        }
+    }
+}
+
+impl HybridPublicKey {
+    /// Load a public key serialized by `to_bytes`
+    pub fn from_bytes(data: &[u8]) -> Result<Self, Error> {
+        if data.len() != HYBRID_PUBLIC_KEY_SIZE {
+            return Err(Error::InvalidECCKeyFormat);
+        }
+        let x25519: [u8; X25519_KEY_SIZE] = data[..X25519_KEY_SIZE]
+            .try_into()
+            .map_err(|_| Error::InvalidECCKeyFormat)?;
+        let mlkem = data[X25519_KEY_SIZE..]
+            .try_into()
+            .ok()
+            .and_then(|mlkem| EncapsulationKey768::new(&mlkem).ok())
+            .ok_or(Error::InvalidECCKeyFormat)?;
+        Ok(HybridPublicKey {
+            x25519: PublicKey::from(x25519),
+            mlkem,
+        })
+    }
+
+    /// Serialize as `[X25519 public key][ML-KEM-768 encapsulation key]`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HYBRID_PUBLIC_KEY_SIZE);
+        out.extend_from_slice(self.x25519.as_bytes());
+        out.extend_from_slice(&self.mlkem.to_bytes());
+        out
+    }
+}
+
+/// Derive the key-wrapping key from both shared secrets, bound to the X25519
+/// public keys involved
+fn derive_key(
+    mlkem_shared: &[u8],
+    x25519_shared: &[u8],
+    ephemeral: &PublicKey,
+    recipient: &PublicKey,
+) -> Result<[u8; KEY_SIZE], Error> {
+    let mut ikm = Vec::with_capacity(KEY_SIZE + X25519_KEY_SIZE * 3);
+    ikm.extend_from_slice(mlkem_shared);
+    ikm.extend_from_slice(x25519_shared);
+    ikm.extend_from_slice(ephemeral.as_bytes());
+    ikm.extend_from_slice(recipient.as_bytes());
+    let hkdf: Hkdf<Sha256> = Hkdf::new(None, &ikm);
+    ikm.zeroize();
+    let mut output = [0u8; KEY_SIZE];
+    hkdf.expand(HYBRID_DERIVE_KEY_INFO, &mut output)?;
+    Ok(output)
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct HybridKeyAndTag {
+    /// ML-KEM-768 ciphertext, for this recipient
+    mlkem_ciphertext: Vec<u8>,
+    key: [u8; KEY_SIZE],
+    tag: [u8; aesgcm::TAG_LENGTH],
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct HybridPersistent {
+    pub kem: HybridKem,
+    /// Ephemeral X25519 public key
+    public: [u8; X25519_KEY_SIZE],
+    encrypted_keys: Vec<HybridKeyAndTag>,
+}
+
+impl HybridPersistent {
+    pub fn count_keys(&self) -> usize {
+        self.encrypted_keys.len()
+    }
+}
+
+/// Share a common `key` with several hybrid recipients, and return a
+/// serializable structure (Key-wrapping made thanks to AesGcm256)
+pub(crate) fn store_key_for_hybrid_recipients<T>(
+    recipients: &[HybridPublicKey],
+    key: &[u8; KEY_SIZE],
+    csprng: &mut T,
+) -> Result<HybridPersistent, Error>
+where
+    T: RngCore + CryptoRng,
+{
+    let ephemeral = StaticSecret::new(&mut *csprng);
+    let public = PublicKey::from(&ephemeral);
+    let mut encrypted_keys = Vec::new();
+    for recipient in recipients.iter() {
+        let mut randomness = [0u8; KEY_SIZE];
+        csprng.fill_bytes(&mut randomness);
+        let (mlkem_ciphertext, mut mlkem_shared) = recipient
+            .mlkem
+            .encapsulate_deterministic(&B32::from(randomness));
+        randomness.zeroize();
+        let mut x25519_shared = ephemeral.diffie_hellman(&recipient.x25519);
+        let mut wrapping_key = derive_key(
+            &mlkem_shared,
+            x25519_shared.as_bytes(),
+            &public,
+            &recipient.x25519,
+        )?;
+        mlkem_shared.zeroize();
+        x25519_shared.zeroize();
+
+        let mut cipher = aesgcm::AesGcm256::new(&wrapping_key, HYBRID_NONCE, b"")?;
+        wrapping_key.zeroize();
+        let mut encrypted_key = *key;
+        cipher.encrypt(&mut encrypted_key);
+        let mut tag = [0u8; aesgcm::TAG_LENGTH];
+        tag.copy_from_slice(&cipher.into_tag());
+        encrypted_keys.push(HybridKeyAndTag {
+            mlkem_ciphertext: mlkem_ciphertext.to_vec(),
+            key: encrypted_key,
+            tag,
+        });
+    }
+
+    Ok(HybridPersistent {
+        kem: HybridKem::X25519MlKem768,
+        public: *public.as_bytes(),
+        encrypted_keys,
+    })
+}
+
+/// Try to recover the shared key from the `HybridPersistent`, using the private
+/// key `private_key`
+pub(crate) fn retrieve_key_hybrid(
+    persist: &HybridPersistent,
+    private_key: &HybridPrivateKey,
+) -> Result<Option<[u8; KEY_SIZE]>, Error> {
+    let ephemeral = PublicKey::from(persist.public);
+    let recipient = PublicKey::from(&private_key.x25519);
+    let mut x25519_shared = private_key.x25519.diffie_hellman(&ephemeral);
+    let mlkem = private_key.mlkem();
+
+    let mut result = None;
+    for keytag in persist.encrypted_keys.iter() {
+        let ciphertext = match ml_kem_768::Ciphertext::try_from(keytag.mlkem_ciphertext.as_slice())
+        {
+            Ok(ciphertext) => ciphertext,
+            Err(_) => return Err(Error::DeserializationError),
+        };
+        // On a wrong private key, ML-KEM returns a pseudo-random shared secret
+        let mut mlkem_shared = mlkem.decapsulate(&ciphertext);
+        let mut wrapping_key = derive_key(
+            &mlkem_shared,
+            x25519_shared.as_bytes(),
+            &ephemeral,
+            &recipient,
+        )?;
+        mlkem_shared.zeroize();
+
+        let mut cipher = aesgcm::AesGcm256::new(&wrapping_key, HYBRID_NONCE, b"")?;
+        wrapping_key.zeroize();
+        let mut data = keytag.key;
+        let tag = cipher.decrypt(&mut data);
+        if tag.ct_eq(&keytag.tag).unwrap_u8() == 1 {
+            result = Some(data);
+            break;
+        }
+    }
+    x25519_shared.zeroize();
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaChaRng;
+
+    #[test]
+    fn keys_serialization() {
+        let mut csprng = ChaChaRng::from_entropy();
+        let private_key = HybridPrivateKey::generate(&mut csprng);
+        let public_key = private_key.public_key();
+
+        let private_key2 = HybridPrivateKey::from_bytes(&private_key.to_bytes()).unwrap();
+        assert_eq!(private_key2.to_bytes(), private_key.to_bytes());
+        let public_key2 = HybridPublicKey::from_bytes(&public_key.to_bytes()).unwrap();
+        assert_eq!(public_key2.to_bytes(), public_key.to_bytes());
+        assert_eq!(public_key.to_bytes().len(), HYBRID_PUBLIC_KEY_SIZE);
+
+        assert!(HybridPrivateKey::from_bytes(&[0u8; 12]).is_err());
+        assert!(HybridPublicKey::from_bytes(&[0u8; 12]).is_err());
+    }
+
+    #[test]
+    fn hybrid_recipients() {
+        let mut csprng = ChaChaRng::from_entropy();
+        let private_keys: Vec<HybridPrivateKey> = (0..3)
+            .map(|_| HybridPrivateKey::generate(&mut csprng))
+            .collect();
+        let public_keys: Vec<HybridPublicKey> =
+            private_keys.iter().map(|key| key.public_key()).collect();
+
+        let key = csprng.gen::<[u8; KEY_SIZE]>();
+        let persist = store_key_for_hybrid_recipients(&public_keys, &key, &mut csprng).unwrap();
+        assert_eq!(persist.count_keys(), 3);
+        assert_eq!(persist.kem, HybridKem::X25519MlKem768);
+
+        // Ensure each recipient can retrieve the shared key
+        for private_key in private_keys.iter() {
+            let ret_key = retrieve_key_hybrid(&persist, private_key).unwrap().unwrap();
+            assert_eq!(ret_key, key);
+        }
+
+        // Ensure another recipient does not obtain the shared key
+        let fake_recipient = HybridPrivateKey::generate(&mut csprng);
+        assert!(retrieve_key_hybrid(&persist, &fake_recipient)
+            .unwrap()
+            .is_none());
+    }
+}
@@ -1,3 +1,8 @@
 pub mod aesgcm;
+pub mod chacha20poly1305;
+pub mod derivation;
 pub mod ecc;
 pub mod hash;
+pub mod hybrid;
+pub mod password;
+pub(crate) mod shamir;
@@ -0,0 +1,144 @@
+use crate::crypto::aesgcm;
+use crate::crypto::aesgcm::ConstantTimeEq;
+use crate::errors::Error;
+use argon2::{Algorithm, Argon2, Params, Version};
+use rand::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroize;
+
+const KEY_SIZE: usize = 32;
+const SALT_SIZE: usize = 16;
+// As the salt is random, the derived key is used only once
+const PASSWORD_NONCE: &[u8; 12] = b"PASSWD NONCE";
+/// Maximum Argon2 parameters accepted from a header, to avoid DoS on
+/// malformed files (memory cost is in KiB, i.e. at most 1 GiB is allocated)
+const MAX_M_COST: u32 = 1024 * 1024;
+const MAX_T_COST: u32 = 64;
+const MAX_P_COST: u32 = 64;
+
+/// Argon2id parameters, salt and the shared key wrapped with the key derived
+/// from the password
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PasswordPersistent {
+    /// Memory cost, in KiB
+    m_cost: u32,
+    /// Number of iterations
+    t_cost: u32,
+    /// Degree of parallelism
+    p_cost: u32,
+    salt: [u8; SALT_SIZE],
+    key: [u8; KEY_SIZE],
+    tag: [u8; aesgcm::TAG_LENGTH],
+}
+
+fn derive_key(
+    password: &[u8],
+    salt: &[u8],
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+) -> Result<[u8; KEY_SIZE], Error> {
+    if m_cost > MAX_M_COST || t_cost > MAX_T_COST || p_cost > MAX_P_COST {
+        return Err(Error::DeserializationError);
+    }
+    let params = Params::new(m_cost, t_cost, p_cost, Some(KEY_SIZE))
+        .map_err(|_| Error::DeserializationError)?;
+    let mut output = [0u8; KEY_SIZE];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(password, salt, &mut output)
+        .map_err(|_| Error::DeserializationError)?;
+    Ok(output)
+}
+
+/// Wrap the shared `key` with a key derived from `password` with Argon2id, and
+/// return a serializable structure
+///
+/// The recommended Argon2id parameters of the `argon2` crate are used
+pub(crate) fn store_key_for_password<T>(
+    password: &[u8],
+    key: &[u8; KEY_SIZE],
+    csprng: &mut T,
+) -> Result<PasswordPersistent, Error>
+where
+    T: RngCore + CryptoRng,
+{
+    let mut salt = [0u8; SALT_SIZE];
+    csprng.fill_bytes(&mut salt);
+    let (m_cost, t_cost, p_cost) = (
+        Params::DEFAULT_M_COST,
+        Params::DEFAULT_T_COST,
+        Params::DEFAULT_P_COST,
+    );
+
+    let mut password_key = derive_key(password, &salt, m_cost, t_cost, p_cost)?;
+    let mut cipher = aesgcm::AesGcm256::new(&password_key, PASSWORD_NONCE, b"")?;
+    password_key.zeroize();
+    let mut encrypted_key = [0u8; KEY_SIZE];
+    encrypted_key.copy_from_slice(key);
+    cipher.encrypt(&mut encrypted_key);
+    let mut tag = [0u8; aesgcm::TAG_LENGTH];
+    tag.copy_from_slice(&cipher.into_tag());
+
+    Ok(PasswordPersistent {
+        m_cost,
+        t_cost,
+        p_cost,
+        salt,
+        key: encrypted_key,
+        tag,
+    })
+}
+
+/// Try to recover the shared key from the `PasswordPersistent`, using `password`
+pub(crate) fn retrieve_key_with_password(
+    persist: &PasswordPersistent,
+    password: &[u8],
+) -> Result<Option<[u8; KEY_SIZE]>, Error> {
+    let mut password_key = derive_key(
+        password,
+        &persist.salt,
+        persist.m_cost,
+        persist.t_cost,
+        persist.p_cost,
+    )?;
+    let mut cipher = aesgcm::AesGcm256::new(&password_key, PASSWORD_NONCE, b"")?;
+    password_key.zeroize();
+    let mut data = [0u8; KEY_SIZE];
+    data.copy_from_slice(&persist.key);
+    let tag = cipher.decrypt(&mut data);
+    if tag.ct_eq(&persist.tag).unwrap_u8() == 1 {
+        Ok(Some(data))
+    } else {
+        data.zeroize();
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaChaRng;
+
+    #[test]
+    fn password_wrapping() {
+        let mut csprng = ChaChaRng::from_entropy();
+        let key = csprng.gen::<[u8; KEY_SIZE]>();
+        let persist = store_key_for_password(b"password", &key, &mut csprng).unwrap();
+
+        assert_eq!(
+            retrieve_key_with_password(&persist, b"password")
+                .unwrap()
+                .unwrap(),
+            key
+        );
+        assert!(retrieve_key_with_password(&persist, b"wrong password")
+            .unwrap()
+            .is_none());
+
+        // Parameters from a malformed header are bounded
+        let mut malformed = persist;
+        malformed.m_cost = MAX_M_COST + 1;
+        assert!(retrieve_key_with_password(&malformed, b"password").is_err());
+    }
+}
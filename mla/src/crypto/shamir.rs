@@ -0,0 +1,145 @@
+//! Shamir's secret sharing over GF(2^8), applied on each byte of a key
+//!
+//! Share `i` (starting at 0) is the evaluation, at `x = i + 1`, of random
+//! polynomials of degree `threshold - 1` whose constant terms are the bytes of
+//! the secret. Any `threshold` shares recover the secret, while fewer shares
+//! give no information on it.
+//!
+//! Arithmetic is made without secret-dependent branches or table lookups.
+
+use rand::{CryptoRng, RngCore};
+use zeroize::Zeroize;
+
+const KEY_SIZE: usize = 32;
+/// Maximum number of shares, as `x = 0` is reserved for the secret
+pub(crate) const MAX_SHARES: usize = 255;
+
+/// Multiplication in GF(2^8), modulo x^8 + x^4 + x^3 + x + 1 (as in AES)
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut result = 0u8;
+    for _ in 0..8 {
+        // Add `a` if the lowest bit of `b` is set
+        result ^= a & 0u8.wrapping_sub(b & 1);
+        // Reduce if the highest bit of `a` is set
+        let carry = 0u8.wrapping_sub(a >> 7);
+        a = (a << 1) ^ (0x1b & carry);
+        b >>= 1;
+    }
+    result
+}
+
+/// Inverse in GF(2^8), computed as `a^254` (`a` must not be 0)
+fn gf_inv(a: u8) -> u8 {
+    let mut result = 1u8;
+    let mut power = a;
+    // 254 = 0b1111_1110
+    for bit in 0..8 {
+        if (254 >> bit) & 1 == 1 {
+            result = gf_mul(result, power);
+        }
+        power = gf_mul(power, power);
+    }
+    result
+}
+
+/// Split `secret` in `count` shares, any `threshold` of them recovering it
+pub(crate) fn split<T>(
+    secret: &[u8; KEY_SIZE],
+    threshold: usize,
+    count: usize,
+    csprng: &mut T,
+) -> Vec<[u8; KEY_SIZE]>
+where
+    T: RngCore + CryptoRng,
+{
+    assert!(0 < threshold && threshold <= count && count <= MAX_SHARES);
+
+    // coefficients[j] are the coefficients of degree j + 1, for each byte
+    let mut coefficients = vec![[0u8; KEY_SIZE]; threshold - 1];
+    for coefficient in coefficients.iter_mut() {
+        csprng.fill_bytes(coefficient);
+    }
+
+    let mut shares = vec![[0u8; KEY_SIZE]; count];
+    for (index, share) in shares.iter_mut().enumerate() {
+        let x = (index + 1) as u8;
+        for byte in 0..KEY_SIZE {
+            // Horner's method
+            let mut value = 0u8;
+            for coefficient in coefficients.iter().rev() {
+                value = gf_mul(value, x) ^ coefficient[byte];
+            }
+            share[byte] = gf_mul(value, x) ^ secret[byte];
+        }
+    }
+    coefficients.zeroize();
+    shares
+}
+
+/// Recover the secret from `shares`, given as `(share index, share)` with
+/// distinct indexes
+///
+/// The result is only meaningful if at least `threshold` shares are given
+pub(crate) fn combine(shares: &[(usize, [u8; KEY_SIZE])]) -> [u8; KEY_SIZE] {
+    let mut secret = [0u8; KEY_SIZE];
+    for (i, (index_i, share)) in shares.iter().enumerate() {
+        let x_i = (*index_i + 1) as u8;
+        // Lagrange basis polynomial for x_i, evaluated at 0:
+        // prod(x_j / (x_j - x_i)), with substraction being a XOR
+        let mut numerator = 1u8;
+        let mut denominator = 1u8;
+        for (j, (index_j, _)) in shares.iter().enumerate() {
+            if i != j {
+                let x_j = (*index_j + 1) as u8;
+                numerator = gf_mul(numerator, x_j);
+                denominator = gf_mul(denominator, x_j ^ x_i);
+            }
+        }
+        let basis = gf_mul(numerator, gf_inv(denominator));
+        for byte in 0..KEY_SIZE {
+            secret[byte] ^= gf_mul(share[byte], basis);
+        }
+    }
+    secret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaChaRng;
+
+    #[test]
+    fn gf_arithmetic() {
+        // From FIPS-197, section 4.2
+        assert_eq!(gf_mul(0x57, 0x83), 0xc1);
+        assert_eq!(gf_mul(0x57, 0x13), 0xfe);
+        for a in 1..=255u8 {
+            assert_eq!(gf_mul(a, gf_inv(a)), 1);
+        }
+    }
+
+    #[test]
+    fn split_and_combine() {
+        let mut csprng = ChaChaRng::from_entropy();
+        let secret = csprng.gen::<[u8; KEY_SIZE]>();
+        let shares = split(&secret, 3, 5, &mut csprng);
+        assert_eq!(shares.len(), 5);
+
+        // Any 3 shares, in any order, recover the secret
+        for (a, b, c) in [(0, 1, 2), (4, 2, 0), (1, 3, 4), (3, 0, 2)].iter() {
+            let subset = [(*a, shares[*a]), (*b, shares[*b]), (*c, shares[*c])];
+            assert_eq!(combine(&subset), secret);
+        }
+
+        // Less shares are not enough
+        let subset = [(0, shares[0]), (1, shares[1])];
+        assert_ne!(combine(&subset), secret);
+
+        // A threshold of 1 gives the secret in each share
+        let shares = split(&secret, 1, 3, &mut csprng);
+        for share in shares.iter() {
+            assert_eq!(share, &secret);
+        }
+    }
+}
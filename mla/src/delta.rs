@@ -0,0 +1,293 @@
+// Delta encoding of a file content against a similar base file
+//
+// The base is cut in chunks of `DELTA_CHUNK_SIZE` bytes, indexed by a
+// rolling hash (as in rsync) and a strong one. The new content is then
+// scanned with a window of the same size, sliding one byte at a time until
+// it matches a chunk of the base. Matching chunks become copies of the base,
+// and the bytes in between are kept as literals.
+use std::collections::HashMap;
+use std::io::{self, Read};
+
+use crate::Error;
+
+/// Size of the base chunks looked for in the new content
+const DELTA_CHUNK_SIZE: usize = 4096;
+/// Amount of new content read at once
+const DELTA_READ_SIZE: u64 = 64 * 1024;
+/// Maximum size of the literals given at once
+const DELTA_LITERAL_SIZE: usize = 1024 * 1024;
+/// Maximum size of the copies given at once
+const DELTA_COPY_SIZE: usize = 4 * 1024 * 1024;
+/// Size of the strong hash of a chunk
+const STRONG_HASH_SIZE: usize = 16;
+
+/// Weak hash of a window, which can be slid one byte further in constant time
+#[derive(Clone, Copy)]
+struct RollingHash {
+    a: u32,
+    b: u32,
+}
+
+impl RollingHash {
+    fn new(data: &[u8]) -> Self {
+        let mut a = 0u32;
+        let mut b = 0u32;
+        for (i, byte) in data.iter().enumerate() {
+            a = a.wrapping_add(*byte as u32);
+            b = b.wrapping_add(((data.len() - i) as u32).wrapping_mul(*byte as u32));
+        }
+        Self { a, b }
+    }
+
+    /// Hash of the window without `outgoing`, its first byte, and followed by
+    /// `incoming`
+    fn roll(self, outgoing: u8, incoming: u8) -> Self {
+        let a = self
+            .a
+            .wrapping_sub(outgoing as u32)
+            .wrapping_add(incoming as u32);
+        let b = self
+            .b
+            .wrapping_sub((DELTA_CHUNK_SIZE as u32).wrapping_mul(outgoing as u32))
+            .wrapping_add(a);
+        Self { a, b }
+    }
+
+    fn value(&self) -> u32 {
+        (self.b << 16) | (self.a & 0xffff)
+    }
+}
+
+fn strong_hash(data: &[u8]) -> [u8; STRONG_HASH_SIZE] {
+    let mut hash = [0u8; STRONG_HASH_SIZE];
+    hash.copy_from_slice(&blake3::hash(data).as_bytes()[..STRONG_HASH_SIZE]);
+    hash
+}
+
+/// Chunks of a base content, by rolling hash
+pub(crate) struct DeltaIndex {
+    /// Rolling hash -> (offset in the base, strong hash) of the chunks
+    chunks: HashMap<u32, Vec<(u64, [u8; STRONG_HASH_SIZE])>>,
+}
+
+impl DeltaIndex {
+    /// Index the whole content of `base`, read once
+    pub(crate) fn new<R: Read>(mut base: R) -> io::Result<Self> {
+        let mut chunks: HashMap<u32, Vec<(u64, [u8; STRONG_HASH_SIZE])>> = HashMap::new();
+        let mut chunk = Vec::with_capacity(DELTA_CHUNK_SIZE);
+        let mut offset = 0;
+        loop {
+            chunk.clear();
+            (&mut base)
+                .take(DELTA_CHUNK_SIZE as u64)
+                .read_to_end(&mut chunk)?;
+            if chunk.len() < DELTA_CHUNK_SIZE {
+                // The last, partial, chunk is not indexed
+                break;
+            }
+            chunks
+                .entry(RollingHash::new(&chunk).value())
+                .or_default()
+                .push((offset, strong_hash(&chunk)));
+            offset += DELTA_CHUNK_SIZE as u64;
+        }
+        Ok(Self { chunks })
+    }
+
+    /// Offset in the base of a chunk equal to `data`, whose rolling hash is
+    /// `weak`
+    fn find(&self, weak: u32, data: &[u8]) -> Option<u64> {
+        let candidates = self.chunks.get(&weak)?;
+        let strong = strong_hash(data);
+        candidates
+            .iter()
+            .find(|(_, candidate)| *candidate == strong)
+            .map(|(offset, _)| *offset)
+    }
+}
+
+/// Part of a content, as produced by `delta_encode`
+pub(crate) enum DeltaOp<'a> {
+    /// Bytes not found in the base
+    Literal(&'a [u8]),
+    /// Bytes found in the base, at `offset`
+    Copy { offset: u64, data: &'a [u8] },
+}
+
+/// Cut the content of `src` in parts copied from the base indexed in `index`,
+/// and literal ones, given in order to `emit`
+///
+/// Chunks found one after the other in the base are given as a single copy,
+/// of up to `DELTA_COPY_SIZE` bytes
+pub(crate) fn delta_encode<R: Read, F: FnMut(DeltaOp) -> Result<(), Error>>(
+    index: &DeltaIndex,
+    mut src: R,
+    mut emit: F,
+) -> Result<(), Error> {
+    let mut buf: Vec<u8> = Vec::new();
+    // Start, in `buf`, of the bytes not emitted yet
+    let mut pending = 0;
+    // Offset in the base of the bytes not emitted yet, if they are a copy.
+    // Otherwise, they are literal
+    let mut copy: Option<u64> = None;
+    // Start, in `buf`, of the window
+    let mut pos = 0;
+    // Hash of the window, if already known
+    let mut rolling: Option<RollingHash> = None;
+    let mut eof = false;
+    loop {
+        if !eof && buf.len() < pos + DELTA_CHUNK_SIZE {
+            // Forget the bytes already emitted, and read further
+            buf.drain(..pending);
+            pos -= pending;
+            pending = 0;
+            if (&mut src).take(DELTA_READ_SIZE).read_to_end(&mut buf)? == 0 {
+                eof = true;
+            }
+            continue;
+        }
+        if buf.len() < pos + DELTA_CHUNK_SIZE {
+            // Not enough content left for a whole chunk
+            break;
+        }
+
+        let window = &buf[pos..pos + DELTA_CHUNK_SIZE];
+        let hash = match rolling {
+            Some(hash) => hash,
+            None => RollingHash::new(window),
+        };
+        if let Some(offset) = index.find(hash.value(), window) {
+            match copy {
+                Some(start)
+                    if start + (pos - pending) as u64 == offset
+                        && pos - pending < DELTA_COPY_SIZE => {}
+                Some(start) => {
+                    emit(DeltaOp::Copy {
+                        offset: start,
+                        data: &buf[pending..pos],
+                    })?;
+                    copy = Some(offset);
+                    pending = pos;
+                }
+                None => {
+                    if pos > pending {
+                        emit(DeltaOp::Literal(&buf[pending..pos]))?;
+                    }
+                    copy = Some(offset);
+                    pending = pos;
+                }
+            }
+            pos += DELTA_CHUNK_SIZE;
+            rolling = None;
+        } else {
+            if let Some(start) = copy.take() {
+                emit(DeltaOp::Copy {
+                    offset: start,
+                    data: &buf[pending..pos],
+                })?;
+                pending = pos;
+            }
+            // Slide the window one byte further. The hash is computed again
+            // if the next byte is not read yet
+            rolling = buf
+                .get(pos + DELTA_CHUNK_SIZE)
+                .map(|incoming| hash.roll(buf[pos], *incoming));
+            pos += 1;
+            if pos - pending >= DELTA_LITERAL_SIZE {
+                emit(DeltaOp::Literal(&buf[pending..pos]))?;
+                pending = pos;
+            }
+        }
+    }
+    if let Some(start) = copy {
+        emit(DeltaOp::Copy {
+            offset: start,
+            data: &buf[pending..pos],
+        })?;
+        pending = pos;
+    }
+    if buf.len() > pending {
+        emit(DeltaOp::Literal(&buf[pending..]))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{RngCore, SeedableRng};
+    use rand_chacha::ChaChaRng;
+
+    /// Rebuild the content from the result of `delta_encode`, and return the
+    /// number of bytes copied from `base`, and the number of copies
+    fn roundtrip(base: &[u8], content: &[u8]) -> (usize, usize) {
+        let index = DeltaIndex::new(base).unwrap();
+        let mut rebuilt = Vec::new();
+        let mut copied = 0;
+        let mut copies = 0;
+        delta_encode(&index, content, |op| {
+            match op {
+                DeltaOp::Literal(data) => rebuilt.extend_from_slice(data),
+                DeltaOp::Copy { offset, data } => {
+                    let offset = offset as usize;
+                    assert_eq!(&base[offset..offset + data.len()], data);
+                    rebuilt.extend_from_slice(data);
+                    copied += data.len();
+                    copies += 1;
+                }
+            }
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(rebuilt, content);
+        (copied, copies)
+    }
+
+    #[test]
+    fn rolling_hash() {
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let mut data = vec![0u8; DELTA_CHUNK_SIZE + 100];
+        rng.fill_bytes(&mut data);
+        let mut hash = RollingHash::new(&data[..DELTA_CHUNK_SIZE]);
+        for pos in 1..100 {
+            hash = hash.roll(data[pos - 1], data[pos - 1 + DELTA_CHUNK_SIZE]);
+            assert_eq!(
+                hash.value(),
+                RollingHash::new(&data[pos..pos + DELTA_CHUNK_SIZE]).value()
+            );
+        }
+    }
+
+    #[test]
+    fn delta_roundtrip() {
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let mut base = vec![0u8; 50 * DELTA_CHUNK_SIZE];
+        rng.fill_bytes(&mut base);
+
+        // Identical content is entirely copied, at once
+        assert_eq!(roundtrip(&base, &base), (base.len(), 1));
+
+        // Shifted content, as in a rotated log, is found anyway
+        let mut content = vec![0u8; 1234];
+        rng.fill_bytes(&mut content);
+        content.extend_from_slice(&base[500..]);
+        let (copied, copies) = roundtrip(&base, &content);
+        assert!(copied >= base.len() - 500 - 2 * DELTA_CHUNK_SIZE);
+        assert_eq!(copies, 1);
+
+        // Reordered content needs several copies
+        let mut content = base[25 * DELTA_CHUNK_SIZE..].to_vec();
+        content.extend_from_slice(&base[..25 * DELTA_CHUNK_SIZE]);
+        assert_eq!(roundtrip(&base, &content), (base.len(), 2));
+
+        // Unrelated content is kept as is
+        let mut content = vec![0u8; 10 * DELTA_CHUNK_SIZE + 1];
+        rng.fill_bytes(&mut content);
+        assert_eq!(roundtrip(&base, &content).0, 0);
+
+        // Small contents, and bases, are handled
+        assert_eq!(roundtrip(&base, &base[..100]), (0, 0));
+        assert_eq!(roundtrip(&base[..100], &base), (0, 0));
+        assert_eq!(roundtrip(&base, &[]), (0, 0));
+    }
+}
@@ -21,7 +21,8 @@ pub enum Error {
     /// An error has occurred while converting into UTF8. This error could
     /// happens while parsing the block filename
     UTF8ConversionError(std::string::FromUtf8Error),
-    /// Filenames have a limited size `FILENAME_MAX_SIZE`
+    /// Filenames have a limited size `FILENAME_MAX_SIZE`, or the one set in
+    /// the reader configuration
     FilenameTooLong,
     /// The writer state is not in the expected state for the current operation
     WrongArchiveWriterState {
@@ -60,6 +61,24 @@ pub enum Error {
     AuthenticatedDecryptionWrongTag,
     /// Unable to expand while using the HKDF
     HKDFInvalidKeyLength,
+    /// The archive signature does not match its content
+    InvalidSignature,
+    /// The archive is signed by a key which is not a trusted one
+    UntrustedSignature,
+    /// Too many shards of a forward error correction group are damaged to
+    /// rebuild its data
+    UnrecoverableCorruption,
+    /// The checksum of a block header does not match its content. Is the
+    /// deserialization starting at the beginning of a block?
+    WrongBlockTag,
+    /// More data is decompressed than allowed by the reader configuration,
+    /// for an entry or relatively to the compressed data, as with a
+    /// decompression bomb
+    DecompressionLimitExceeded,
+    /// The archive has more entries than allowed by the reader configuration
+    TooManyEntries,
+    /// The archive footer is larger than allowed by the reader configuration
+    FooterTooLarge,
 }
 
 impl fmt::Display for Error {
@@ -154,6 +173,9 @@ pub enum FailSafeReadError {
     ContentForUnknownFile(ArchiveFileID),
     /// Termination of an unknwown file
     EOFForUnknownFile(ArchiveFileID),
+    /// Content copied from another file, which is not recovered. The
+    /// following content of the file is ignored
+    ContentCopiedFromFile(ArchiveFileID),
     /// Wraps an already existing error and indicates which files are not
     /// finished (a file can be finished but uncompleted)
     UnfinishedFiles {
@@ -194,11 +216,39 @@ pub enum ConfigError {
     IncoherentPersistentConfig,
     // Compression specifics
     CompressionLevelOutOfRange,
+    /// There must be at least one compression thread
+    InvalidCompressionThreads,
     // Encryption specifics
     EncryptionKeyIsMissing,
     PrivateKeyNotSet,
     PrivateKeyNotFound,
     ECIESComputationError,
+    /// The threshold must be between 1 and the number of public keys (at most
+    /// 255), without a password
+    InvalidThreshold,
+    /// The rekeying interval must not be 0
+    InvalidRekeyInterval,
+    /// Recipient key hints can't be stored for anonymous recipients
+    AnonymousRecipientHints,
+    /// The archive records recipient key hints, and none of them match the
+    /// private keys given
+    NoMatchingRecipientKey,
+    /// The padding bucket size must not be 0
+    InvalidPaddingSize,
+    /// The checkpoint interval must not be 0
+    InvalidCheckpointInterval,
+    /// A deterministic seed can't be used with the key of an existing
+    /// archive, as the nonce derived from it could already be used with
+    /// this key
+    DeterministicSeedWithExistingKey,
+    // Signature specifics
+    SigningKeyIsMissing,
+    /// Trusted public keys are set, but the archive is not signed
+    SignatureNotFound,
+    // Forward error correction specifics
+    /// There must be at least one data shard and one parity shard, and at
+    /// most 256 shards in a group
+    InvalidFecShards,
 }
 
 impl fmt::Display for ConfigError {
@@ -1,8 +1,12 @@
 /// Helpers for common operation with MLA Archives
-use super::{ArchiveFileBlock, ArchiveFileID, ArchiveReader, ArchiveWriter, Error};
+use super::{
+    has_block_tags, ArchiveFileBlock, ArchiveFileID, ArchiveReader, ArchiveWriter,
+    BlocksToFileReader, Error, FooterRef,
+};
 use std::collections::HashMap;
+use std::fs::File;
 use std::hash::BuildHasher;
-use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::io::{self, IoSlice, Read, Seek, SeekFrom, Write};
 
 /// Extract an Archive linearly.
 ///
@@ -21,48 +25,111 @@ use std::io::{self, Read, Seek, SeekFrom, Write};
 pub fn linear_extract<W1: Write, R: Read + Seek, S: BuildHasher>(
     archive: &mut ArchiveReader<R>,
     export: &mut HashMap<&String, W1, S>,
+) -> Result<(), Error> {
+    linear_extract_with_progress(archive, export, |_, _| {})
+}
+
+/// Extract an Archive linearly, as `linear_extract`, reporting progress
+///
+/// `progress` is called with a filename and a number of bytes, each time
+/// content of one of the extracted files is read.
+pub fn linear_extract_with_progress<
+    W1: Write,
+    R: Read + Seek,
+    S: BuildHasher,
+    F: FnMut(&str, u64),
+>(
+    archive: &mut ArchiveReader<R>,
+    export: &mut HashMap<&String, W1, S>,
+    mut progress: F,
 ) -> Result<(), Error> {
     // Seek at the beginning
     archive.src.seek(SeekFrom::Start(0))?;
+    // As files are interleaved, the per entry limit is enforced here, from
+    // the length of their blocks
+    archive.src.end_entry();
+    let max_entry_size = archive.config.compress.max_entry_size();
+    let mut sizes: HashMap<ArchiveFileID, u64> = HashMap::new();
+    let tagged = has_block_tags(archive.info.format_version);
+    let shared = SharedContents::from_archive(archive);
+    let files_info = match &archive.metadata {
+        Some(footer) => &footer.files_info,
+        None => return Err(Error::MissingMetadata),
+    };
 
     // Use a BufReader to cache, by merging them into one bigger read, small
     // read calls (like the ones on ArchiveFileBlock reading)
     let mut src = io::BufReader::new(&mut archive.src);
 
-    // Associate an ID in the archive to the corresponding filename
-    // Do not directly associate to the writer to keep an easier fn API
-    let mut id2filename: HashMap<ArchiveFileID, String> = HashMap::new();
+    // Associate an ID in the archive to the corresponding filenames
+    // Do not directly associate to the writers to keep an easier fn API
+    let mut id2filenames: HashMap<ArchiveFileID, Vec<String>> = HashMap::new();
 
     'read_block: loop {
-        match ArchiveFileBlock::from(&mut src)? {
+        match ArchiveFileBlock::from(&mut src, tagged)? {
             ArchiveFileBlock::FileStart { filename, id } => {
-                // If the starting file is meant to be extracted, get the
-                // corresponding writer
-                if export.contains_key(&filename) {
-                    id2filename.insert(id, filename.clone());
+                // If the starting file, or one sharing its content, is meant
+                // to be extracted, get the corresponding writers
+                let filenames: Vec<String> = shared
+                    .names(filename)
+                    .into_iter()
+                    .filter(|fname| export.contains_key(fname))
+                    .collect();
+                if !filenames.is_empty() {
+                    id2filenames.insert(id, filenames);
                 }
             }
             ArchiveFileBlock::EndOfFile { id, .. } => {
-                // Drop the corresponding writer
-                id2filename.remove(&id);
+                // Drop the corresponding writers
+                id2filenames.remove(&id);
             }
             ArchiveFileBlock::FileContent { length, id, .. } => {
-                // Write a block to the corresponding output, if any
+                // Write a block to the corresponding outputs, if any
 
                 let copy_src = &mut (&mut src).take(length);
                 // Is the file considered?
                 let mut extracted: bool = false;
-                if let Some(fname) = id2filename.get(&id) {
-                    if let Some(writer) = export.get_mut(fname) {
-                        io::copy(copy_src, writer)?;
-                        extracted = true;
-                    }
+                if let Some(fnames) = id2filenames.get(&id) {
+                    check_entry_size(&mut sizes, id, length, max_entry_size)?;
+                    extracted = export_content(&mut *copy_src, fnames, export, &mut progress)?;
                 };
                 if !extracted {
                     // Exhaust the block to Sink to forward the reader
                     io::copy(copy_src, &mut io::sink())?;
                 }
             }
+            ArchiveFileBlock::FileCopy {
+                id,
+                base,
+                offset,
+                length,
+            } => {
+                // Read the copied content, if the file is considered
+                if let Some(fnames) = id2filenames.get(&id) {
+                    check_entry_size(&mut sizes, id, length, max_entry_size)?;
+                    let position = src.stream_position()?;
+                    let copied = BlocksToFileReader::open_copied(
+                        &mut src,
+                        FooterRef::Loaded(files_info),
+                        tagged,
+                        base,
+                        offset,
+                        length,
+                        Vec::new(),
+                    )?;
+                    export_content(copied.take(length), fnames, export, &mut progress)?;
+                    // Get back after the block
+                    src.seek(SeekFrom::Start(position))?;
+                }
+            }
+            ArchiveFileBlock::Padding { length } | ArchiveFileBlock::Checkpoint { length, .. } => {
+                // Exhaust the padding or checkpoint to Sink to forward the
+                // reader
+                io::copy(&mut (&mut src).take(length), &mut io::sink())?;
+            }
+            ArchiveFileBlock::FileMetadata { .. } => {
+                // Only the content is extracted
+            }
             ArchiveFileBlock::EndOfArchiveData {} => {
                 // Proper termination
                 break 'read_block;
@@ -72,6 +139,274 @@ pub fn linear_extract<W1: Write, R: Read + Seek, S: BuildHasher>(
     Ok(())
 }
 
+/// Account for `length` more bytes of the file `id`, checking them against
+/// `max_entry_size`, if any
+fn check_entry_size(
+    sizes: &mut HashMap<ArchiveFileID, u64>,
+    id: ArchiveFileID,
+    length: u64,
+    max_entry_size: Option<u64>,
+) -> Result<(), Error> {
+    if let Some(max_entry_size) = max_entry_size {
+        let size = sizes.entry(id).or_insert(0);
+        *size = size.saturating_add(length);
+        if *size > max_entry_size {
+            return Err(Error::DecompressionLimitExceeded);
+        }
+    }
+    Ok(())
+}
+
+/// Write the content read from `src` to the writers of `fnames` in `export`,
+/// reporting progress. Return whether any writer has been found
+fn export_content<R: Read, W1: Write, S: BuildHasher, F: FnMut(&str, u64)>(
+    src: R,
+    fnames: &[String],
+    export: &mut HashMap<&String, W1, S>,
+    progress: &mut F,
+) -> Result<bool, Error> {
+    let mut callback = |count| {
+        for fname in fnames {
+            progress(fname, count);
+        }
+    };
+    let mut reader = ProgressReader::new(src, Some(&mut callback));
+    if let [fname] = fnames {
+        if let Some(writer) = export.get_mut(fname) {
+            io::copy(&mut reader, writer)?;
+            return Ok(true);
+        }
+        Ok(false)
+    } else {
+        let writers = export
+            .iter_mut()
+            .filter(|(fname, _)| fnames.contains(**fname))
+            .map(|(_, writer)| writer)
+            .collect();
+        io::copy(&mut reader, &mut FanOutWriter { writers })?;
+        Ok(true)
+    }
+}
+
+/// Copy files of an Archive linearly into another one.
+///
+/// `new_name` is called on each filename of `archive`, and returns the name
+/// to use in `dest`. Files for which it returns `None` are not copied.
+///
+/// As for `linear_extract`, the archive is read once, block per block. Blocks
+/// of kept files are directly forwarded to `dest`, so interleaving is kept
+/// and no file has to be fully opened on its own.
+///
+/// As filenames are stored in both the footer and the files' first block,
+/// this is also the way to rename files.
+///
+/// Content copied from another file (see `ArchiveWriter::add_file_delta`)
+/// keeps being copied if this file is copied too. Otherwise, it is stored.
+pub fn linear_copy<'a, W: Write, R: Read + Seek, F: FnMut(&str) -> Option<String>>(
+    archive: &mut ArchiveReader<R>,
+    dest: &mut ArchiveWriter<'a, W>,
+    mut new_name: F,
+) -> Result<(), Error> {
+    // Seek at the beginning
+    archive.src.seek(SeekFrom::Start(0))?;
+    // The whole archive is read, files being interleaved
+    archive.src.end_entry();
+    let tagged = has_block_tags(archive.info.format_version);
+    let shared = SharedContents::from_archive(archive);
+    let files_info = match &archive.metadata {
+        Some(footer) => &footer.files_info,
+        None => return Err(Error::MissingMetadata),
+    };
+
+    // Use a BufReader to cache, by merging them into one bigger read, small
+    // read calls (like the ones on ArchiveFileBlock reading)
+    let mut src = io::BufReader::new(&mut archive.src);
+
+    // Associate an ID in the archive to the corresponding ID in `dest`
+    let mut id2newid: HashMap<ArchiveFileID, ArchiveFileID> = HashMap::new();
+    // Associate the start of a file in the archive, as referred to by
+    // `FileCopy` blocks, to its ID in `dest`
+    let mut start2newid: HashMap<u64, ArchiveFileID> = HashMap::new();
+
+    'read_block: loop {
+        match ArchiveFileBlock::from(&mut src, tagged)? {
+            ArchiveFileBlock::FileStart { filename, id } => {
+                let start = files_info
+                    .get(&filename)
+                    .and_then(|file_info| file_info.offsets.first().copied());
+                // Files sharing the content are kept sharing it in `dest`
+                let mut new_id = None;
+                for filename in shared.names(filename) {
+                    if let Some(new_filename) = new_name(&filename) {
+                        match new_id {
+                            None => new_id = Some(dest.start_file(&new_filename)?),
+                            Some(new_id) => dest.add_file_alias(&new_filename, new_id)?,
+                        }
+                    }
+                }
+                if let Some(new_id) = new_id {
+                    id2newid.insert(id, new_id);
+                    if let Some(start) = start {
+                        start2newid.insert(start, new_id);
+                    }
+                }
+            }
+            ArchiveFileBlock::EndOfFile { id, .. } => {
+                if let Some(new_id) = id2newid.remove(&id) {
+                    dest.end_file(new_id)?;
+                }
+            }
+            ArchiveFileBlock::FileContent { length, id, .. } => {
+                let copy_src = &mut (&mut src).take(length);
+                if let Some(new_id) = id2newid.get(&id) {
+                    dest.append_file_content(*new_id, length, copy_src)?;
+                } else {
+                    // Exhaust the block to Sink to forward the reader
+                    io::copy(copy_src, &mut io::sink())?;
+                }
+            }
+            ArchiveFileBlock::FileCopy {
+                id,
+                base,
+                offset,
+                length,
+            } => {
+                if let Some(new_id) = id2newid.get(&id) {
+                    // The copied content is read anyway, for the file hash
+                    let position = src.stream_position()?;
+                    let copied = BlocksToFileReader::open_copied(
+                        &mut src,
+                        FooterRef::Loaded(files_info),
+                        tagged,
+                        base,
+                        offset,
+                        length,
+                        Vec::new(),
+                    )?
+                    .take(length);
+                    match start2newid.get(&base) {
+                        Some(new_base) => {
+                            dest.append_file_copy(*new_id, *new_base, offset, length, copied)?
+                        }
+                        None => dest.append_file_content(*new_id, length, copied)?,
+                    }
+                    // Get back after the block
+                    src.seek(SeekFrom::Start(position))?;
+                }
+            }
+            ArchiveFileBlock::FileMetadata { id, metadata } => {
+                if let Some(new_id) = id2newid.get(&id) {
+                    dest.append_file_metadata(*new_id, &metadata)?;
+                }
+            }
+            ArchiveFileBlock::Padding { length } | ArchiveFileBlock::Checkpoint { length, .. } => {
+                // Padding and checkpoints are not copied, `dest` has its own
+                io::copy(&mut (&mut src).take(length), &mut io::sink())?;
+            }
+            ArchiveFileBlock::EndOfArchiveData => {
+                // Proper termination
+                break 'read_block;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Names of the files sharing the same content, see
+/// `ArchiveWriterConfig::set_deduplication`
+struct SharedContents {
+    /// Names of each shared content
+    groups: Vec<Vec<String>>,
+    /// Filename -> Index of its content in `groups`
+    group_of: HashMap<String, usize>,
+}
+
+impl SharedContents {
+    /// Files sharing their content have the same `EndOfFile` block
+    fn from_archive<R: Read + Seek>(archive: &ArchiveReader<R>) -> Self {
+        let mut by_eof: HashMap<u64, Vec<String>> = HashMap::new();
+        if let Some(footer) = &archive.metadata {
+            for (filename, file_info) in &footer.files_info {
+                by_eof
+                    .entry(file_info.eof_offset)
+                    .or_default()
+                    .push(filename.clone());
+            }
+        }
+        let mut groups = Vec::new();
+        let mut group_of = HashMap::new();
+        for (_, filenames) in by_eof {
+            if filenames.len() > 1 {
+                for filename in &filenames {
+                    group_of.insert(filename.clone(), groups.len());
+                }
+                groups.push(filenames);
+            }
+        }
+        Self { groups, group_of }
+    }
+
+    /// `filename`, as found in the archive data, then the names of the other
+    /// files sharing its content
+    fn names(&self, filename: String) -> Vec<String> {
+        let others = match self.group_of.get(&filename) {
+            Some(group) => self.groups[*group]
+                .iter()
+                .filter(|other| **other != filename)
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        };
+        let mut names = vec![filename];
+        names.extend(others);
+        names
+    }
+}
+
+/// Writes the same data to each of `writers`
+struct FanOutWriter<'b, W: Write> {
+    writers: Vec<&'b mut W>,
+}
+
+impl<'b, W: Write> Write for FanOutWriter<'b, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for writer in self.writers.iter_mut() {
+            writer.write_all(buf)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        for writer in self.writers.iter_mut() {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Calls `callback`, if any, with the number of bytes read from `inner` on
+/// each read
+pub(crate) struct ProgressReader<'b, R: Read, F: ?Sized + FnMut(u64)> {
+    inner: R,
+    callback: Option<&'b mut F>,
+}
+
+impl<'b, R: Read, F: ?Sized + FnMut(u64)> ProgressReader<'b, R, F> {
+    pub(crate) fn new(inner: R, callback: Option<&'b mut F>) -> Self {
+        Self { inner, callback }
+    }
+}
+
+impl<'b, R: Read, F: ?Sized + FnMut(u64)> Read for ProgressReader<'b, R, F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        if let Some(callback) = &mut self.callback {
+            callback(count as u64);
+        }
+        Ok(count)
+    }
+}
+
 /// Provides a Write interface on an ArchiveWriter file
 ///
 /// This interface is meant to be used in situations where length of the data
@@ -95,11 +430,136 @@ impl<'a, 'b, W: Write> Write for StreamWriter<'a, 'b, W> {
         Ok(buf.len())
     }
 
+    /// The slices are added as a single block
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        let data: Vec<u8> = bufs.iter().flat_map(|buf| buf.iter().copied()).collect();
+        self.write(&data)
+    }
+
     fn flush(&mut self) -> io::Result<()> {
         self.archive.flush()
     }
 }
 
+/// Amount of data gathered by an `EntryWriter` before being added to the
+/// archive as a single block
+const ENTRY_WRITER_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Write interface on a file of an ArchiveWriter, as returned by
+/// `ArchiveWriter::start_entry`
+///
+/// Unlike `StreamWriter`, small writes, such as from event-driven producers,
+/// are gathered in a buffer to avoid creating a block for each of them. The
+/// file must be ended with `finish`; otherwise, the buffered data is lost and
+/// the file is left unfinished
+pub struct EntryWriter<'a, 'b, W: Write> {
+    archive: &'b mut ArchiveWriter<'a, W>,
+    file_id: ArchiveFileID,
+    buffer: Vec<u8>,
+}
+
+impl<'a, 'b, W: Write> EntryWriter<'a, 'b, W> {
+    pub(crate) fn new(archive: &'b mut ArchiveWriter<'a, W>, file_id: ArchiveFileID) -> Self {
+        Self {
+            archive,
+            file_id,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Add the buffered data to the archive
+    fn write_buffer(&mut self) -> Result<(), Error> {
+        self.archive.append_file_content(
+            self.file_id,
+            self.buffer.len() as u64,
+            self.buffer.as_slice(),
+        )?;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    /// Add the remaining data to the archive, and end the file
+    pub fn finish(mut self) -> Result<(), Error> {
+        self.write_buffer()?;
+        self.archive.end_file(self.file_id)
+    }
+}
+
+impl<'a, 'b, W: Write> Write for EntryWriter<'a, 'b, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.buffer.is_empty() && buf.len() >= ENTRY_WRITER_BUFFER_SIZE {
+            // Big enough to be added as is
+            self.archive
+                .append_file_content(self.file_id, buf.len() as u64, buf)?;
+            return Ok(buf.len());
+        }
+        let count = std::cmp::min(buf.len(), ENTRY_WRITER_BUFFER_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..count]);
+        if self.buffer.len() == ENTRY_WRITER_BUFFER_SIZE {
+            self.write_buffer()?;
+        }
+        Ok(count)
+    }
+
+    /// The slices are gathered in turn, as with `write`
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        let mut written = 0;
+        for buf in bufs {
+            let count = match self.write(buf) {
+                Ok(count) => count,
+                // Reported on the next call, as some data is already written
+                Err(_) if written > 0 => break,
+                Err(err) => return Err(err),
+            };
+            written += count;
+            if count < buf.len() {
+                break;
+            }
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_buffer()?;
+        self.archive.flush()
+    }
+}
+
+/// `File` destination whose `flush` waits for the data to reach the storage
+/// device
+///
+/// With it, `ArchiveWriter::sync` only returns once the archive written so far
+/// is durable
+pub struct DurableFile {
+    file: File,
+}
+
+impl DurableFile {
+    pub fn new(file: File) -> Self {
+        Self { file }
+    }
+
+    /// Unwraps the inner file
+    pub fn into_inner(self) -> File {
+        self.file
+    }
+}
+
+impl Write for DurableFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        self.file.write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        self.file.sync_data()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -154,6 +614,296 @@ mod tests {
         assert_eq!(export.get(&files[0].0).unwrap(), &files[0].1);
     }
 
+    #[test]
+    fn linear_extract_progress() {
+        // Build an archive with 3 interleaved files
+        let (mla, key, files) = build_archive(None, true);
+
+        // Prepare the reader
+        let dest = Cursor::new(mla.into_raw());
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_read = ArchiveReader::from_config(dest, config).unwrap();
+
+        // Only extracted files are reported
+        let mut export: HashMap<&String, Vec<u8>> = HashMap::new();
+        export.insert(&files[0].0, Vec::new());
+        export.insert(&files[2].0, Vec::new());
+        let mut reported: HashMap<String, u64> = HashMap::new();
+        linear_extract_with_progress(&mut mla_read, &mut export, |fname, count| {
+            *reported.entry(fname.to_string()).or_insert(0) += count;
+        })
+        .expect("Extract error");
+
+        assert_eq!(reported.len(), 2);
+        for (fname, content) in &[&files[0], &files[2]] {
+            assert_eq!(reported[fname], content.len() as u64);
+            assert_eq!(export.get(fname).unwrap(), content);
+        }
+    }
+
+    #[test]
+    fn filtered_linear_copy() {
+        // Build an archive with 3 interleaved files
+        let (mla, key, files) = build_archive(None, true);
+
+        // Prepare the reader
+        let dest = Cursor::new(mla.into_raw());
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_read = ArchiveReader::from_config(dest, config).unwrap();
+
+        // Copy all files but the second one, and rename the third one
+        let mut mla_w = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new())
+            .expect("Writer init failed");
+        linear_copy(&mut mla_read, &mut mla_w, |fname| {
+            if fname == files[1].0 {
+                None
+            } else if fname == files[2].0 {
+                Some("renamed".to_string())
+            } else {
+                Some(fname.to_string())
+            }
+        })
+        .expect("Copy error");
+        mla_w.finalize().unwrap();
+
+        // Check the resulting archive
+        let buf = Cursor::new(mla_w.into_raw());
+        let mut mla_copy = ArchiveReader::from_config(buf, ArchiveReaderConfig::new()).unwrap();
+        let mut sorted_list: Vec<String> = mla_copy.list_files().unwrap().cloned().collect();
+        sorted_list.sort();
+        assert_eq!(sorted_list, vec![files[0].0.clone(), "renamed".to_string()]);
+        for (fname, (orig_fname, content)) in &[
+            (files[0].0.clone(), &files[0]),
+            ("renamed".to_string(), &files[2]),
+        ] {
+            let mut rez = Vec::new();
+            mla_copy
+                .get_file(fname.clone())
+                .unwrap()
+                .unwrap()
+                .data
+                .read_to_end(&mut rez)
+                .unwrap();
+            assert_eq!(&rez, content);
+            assert_eq!(
+                mla_copy.get_hash(fname).unwrap(),
+                mla_read.get_hash(orig_fname).unwrap()
+            );
+        }
+
+        // Linear extraction relies on names stored in blocks
+        let renamed = "renamed".to_string();
+        let mut export: HashMap<&String, Vec<u8>> = HashMap::new();
+        export.insert(&renamed, Vec::new());
+        linear_extract(&mut mla_copy, &mut export).expect("Extract error");
+        assert_eq!(export.get(&renamed).unwrap(), &files[2].1);
+    }
+
+    #[test]
+    fn deduplicated_linear_extract_and_copy() {
+        // Build an archive whose "a" and "c" files share their content
+        let mut config = ArchiveWriterConfig::new();
+        config.set_deduplication(true);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        let files: Vec<(String, Vec<u8>)> = vec![
+            ("a".to_string(), vec![1, 2, 3]),
+            ("b".to_string(), vec![4, 5, 6]),
+            ("c".to_string(), vec![1, 2, 3]),
+        ];
+        for (fname, content) in &files {
+            mla.add_file_seekable(
+                fname,
+                content.len() as u64,
+                Cursor::new(content.as_slice()),
+                &FileOptions::default(),
+            )
+            .unwrap();
+        }
+        mla.finalize().unwrap();
+        let dest = Cursor::new(mla.into_raw());
+        let mut mla_read = ArchiveReader::from_config(dest, ArchiveReaderConfig::new()).unwrap();
+
+        // Each name gets the shared content
+        let mut export: HashMap<&String, Vec<u8>> =
+            files.iter().map(|(fname, _)| (fname, Vec::new())).collect();
+        let mut reported: HashMap<String, u64> = HashMap::new();
+        linear_extract_with_progress(&mut mla_read, &mut export, |fname, count| {
+            *reported.entry(fname.to_string()).or_insert(0) += count;
+        })
+        .expect("Extract error");
+        for (fname, content) in &files {
+            assert_eq!(export.get(fname).unwrap(), content);
+            assert_eq!(reported[fname], content.len() as u64);
+        }
+
+        // Only "c" is extracted, even if the content is stored for "a"
+        let mut export: HashMap<&String, Vec<u8>> = HashMap::new();
+        export.insert(&files[2].0, Vec::new());
+        linear_extract(&mut mla_read, &mut export).expect("Extract error");
+        assert_eq!(export.get(&files[2].0).unwrap(), &files[2].1);
+
+        // The content is still stored if the name it is stored with is not
+        // copied
+        let mut mla_w = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new())
+            .expect("Writer init failed");
+        linear_copy(&mut mla_read, &mut mla_w, |fname| {
+            if fname == "a" {
+                None
+            } else {
+                Some(fname.to_string())
+            }
+        })
+        .expect("Copy error");
+        mla_w.finalize().unwrap();
+        let buf = Cursor::new(mla_w.into_raw());
+        let mut mla_copy = ArchiveReader::from_config(buf, ArchiveReaderConfig::new()).unwrap();
+        assert_eq!(mla_copy.list_files().unwrap().count(), 2);
+        let mut rez = Vec::new();
+        mla_copy
+            .get_file("c".to_string())
+            .unwrap()
+            .unwrap()
+            .data
+            .read_to_end(&mut rez)
+            .unwrap();
+        assert_eq!(rez, files[2].1);
+
+        // Renamed files keep sharing their content
+        let mut mla_w = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new())
+            .expect("Writer init failed");
+        linear_copy(&mut mla_read, &mut mla_w, |fname| {
+            Some(fname.to_uppercase())
+        })
+        .expect("Copy error");
+        assert_eq!(mla_w.files_info["A"], mla_w.files_info["C"]);
+        mla_w.finalize().unwrap();
+        let buf = Cursor::new(mla_w.into_raw());
+        let mut mla_copy = ArchiveReader::from_config(buf, ArchiveReaderConfig::new()).unwrap();
+        for (fname, content) in &files {
+            let mut rez = Vec::new();
+            mla_copy
+                .get_file(fname.to_uppercase())
+                .unwrap()
+                .unwrap()
+                .data
+                .read_to_end(&mut rez)
+                .unwrap();
+            assert_eq!(&rez, content);
+        }
+    }
+
+    #[test]
+    fn delta_linear_extract_and_copy() {
+        use rand::{RngCore, SeedableRng};
+        use rand_chacha::ChaChaRng;
+
+        // Build an archive whose "b" file is mostly copied from "a"
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let mut base = vec![0u8; 100 * 1024];
+        rng.fill_bytes(&mut base);
+        let mut content = vec![0u8; 1000];
+        rng.fill_bytes(&mut content);
+        content.extend_from_slice(&base[..80 * 1024]);
+        let files: Vec<(String, Vec<u8>)> =
+            vec![("a".to_string(), base), ("b".to_string(), content)];
+
+        let mut mla = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+        mla.add_file("a", files[0].1.len() as u64, files[0].1.as_slice())
+            .unwrap();
+        mla.add_file_delta(
+            "b",
+            files[1].1.len() as u64,
+            files[1].1.as_slice(),
+            "a",
+            files[0].1.as_slice(),
+        )
+        .unwrap();
+        mla.finalize().unwrap();
+        let dest = Cursor::new(mla.into_raw());
+        let mut mla_read = ArchiveReader::from_config(dest, ArchiveReaderConfig::new()).unwrap();
+
+        // The copied content is extracted, even without its original file
+        let mut export: HashMap<&String, Vec<u8>> = HashMap::new();
+        export.insert(&files[1].0, Vec::new());
+        let mut reported = 0;
+        linear_extract_with_progress(&mut mla_read, &mut export, |_, count| reported += count)
+            .expect("Extract error");
+        assert_eq!(export.get(&files[1].0).unwrap(), &files[1].1);
+        assert_eq!(reported, files[1].1.len() as u64);
+
+        // Copies are kept if the original file is copied too, otherwise the
+        // content is stored
+        let mut sizes = Vec::new();
+        for keep_base in &[true, false] {
+            let mut mla_w = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new())
+                .expect("Writer init failed");
+            linear_copy(&mut mla_read, &mut mla_w, |fname| {
+                if fname == "a" && !keep_base {
+                    None
+                } else {
+                    Some(fname.to_string())
+                }
+            })
+            .expect("Copy error");
+            mla_w.finalize().unwrap();
+            let buf = mla_w.into_raw();
+            sizes.push(buf.len());
+            let mut mla_copy =
+                ArchiveReader::from_config(Cursor::new(buf), ArchiveReaderConfig::new()).unwrap();
+            let mut rez = Vec::new();
+            mla_copy
+                .get_file("b".to_string())
+                .unwrap()
+                .unwrap()
+                .data
+                .read_to_end(&mut rez)
+                .unwrap();
+            assert_eq!(rez, files[1].1);
+            assert_eq!(
+                mla_copy.get_hash("b").unwrap(),
+                mla_read.get_hash("b").unwrap()
+            );
+        }
+        // Random content is not compressed: only copies make it smaller
+        assert!(sizes[0] < files[0].1.len() + files[1].1.len() / 2);
+        assert!(sizes[1] >= files[1].1.len());
+    }
+
+    #[test]
+    fn entry_writer() {
+        let mut mla = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new())
+            .expect("Writer init failed");
+
+        // Many small writes, and a big one, interleaved with another file
+        let content: Vec<u8> = (0..(3 * ENTRY_WRITER_BUFFER_SIZE))
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let mut entry = mla.start_entry("my_file").unwrap();
+        for chunk in content[..ENTRY_WRITER_BUFFER_SIZE + 10].chunks(7) {
+            entry.write_all(chunk).unwrap();
+        }
+        entry.flush().unwrap();
+        entry
+            .write_all(&content[ENTRY_WRITER_BUFFER_SIZE + 10..])
+            .unwrap();
+        entry.finish().unwrap();
+        mla.start_entry("empty").unwrap().finish().unwrap();
+        mla.finalize().unwrap();
+
+        let dest = mla.into_raw();
+        let buf = Cursor::new(dest.as_slice());
+        let mut mla_read = ArchiveReader::from_config(buf, ArchiveReaderConfig::new()).unwrap();
+        let mut file = mla_read.get_file("my_file".to_string()).unwrap().unwrap();
+        assert_eq!(file.size, content.len() as u64);
+        let mut read = Vec::new();
+        file.data.read_to_end(&mut read).unwrap();
+        assert_eq!(read, content);
+        let file = mla_read.get_file("empty".to_string()).unwrap().unwrap();
+        assert_eq!(file.size, 0);
+    }
+
     #[test]
     fn stream_writer() {
         let file = Vec::new();
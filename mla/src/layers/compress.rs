@@ -3,10 +3,13 @@ use serde::{Deserialize, Serialize};
 
 use crate::layers::traits::{LayerFailSafeReader, LayerReader, LayerWriter};
 use crate::{Error, BINCODE_MAX_DESERIALIZE};
+use std::collections::HashMap;
 use std::io;
-use std::io::{Read, Seek, SeekFrom, Write};
+use std::io::{BufRead, IoSlice, Read, Seek, SeekFrom, Write};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
 
-use crate::config::{ArchiveWriterConfig, ConfigResult};
+use crate::config::{ArchiveReaderConfig, ArchiveWriterConfig, ConfigResult};
 use crate::errors::ConfigError;
 
 // ---------- Config ----------
@@ -17,7 +20,7 @@ use crate::errors::ConfigError;
 ///
 /// According to benchmarking on compression of representative data, 4MB seems
 /// to be a good choice
-const UNCOMPRESSED_DATA_SIZE: u32 = 4 * 1024 * 1024;
+pub const UNCOMPRESSED_DATA_SIZE: u32 = 4 * 1024 * 1024;
 
 /// A bigger value means a better compression ratio, but a slower compression
 ///
@@ -28,18 +31,147 @@ const DEFAULT_COMPRESSION_LEVEL: u32 = 5;
 /// Default value which seems advised by brotli libraries
 const BROTLI_LOG_WINDOW: u32 = 22;
 
+/// Size of the beginning of a block compressed to estimate whether the block
+/// is worth compressing, in "store incompressible" mode
+const SAMPLE_SIZE: usize = 64 * 1024;
+
+/// In "store incompressible" mode, a block is stored as is if its sample is not
+/// compressed below this percentage of its size
+const INCOMPRESSIBLE_RATIO_PERCENT: u64 = 95;
+
+/// Algorithm used to compress the data, recorded in the header
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub enum CompressionAlgorithm {
+    /// Dense, but slow compression
+    #[default]
+    Brotli,
+    /// Fast compression, close to the I/O speed, with a lower ratio
+    Lz4,
+    /// Densest, but slowest compression, for long-term storage
+    Xz,
+}
+
 pub struct CompressionConfig {
     compression_level: u32,
+    algorithm: CompressionAlgorithm,
+    store_incompressible: bool,
+    /// Whether blocks start with their kind (format version 14 and later)
+    block_kinds: bool,
+    /// Number of threads compressing the blocks
+    threads: usize,
 }
 
 impl std::default::Default for CompressionConfig {
     fn default() -> Self {
         CompressionConfig {
             compression_level: DEFAULT_COMPRESSION_LEVEL,
+            algorithm: CompressionAlgorithm::default(),
+            store_incompressible: false,
+            block_kinds: true,
+            threads: 1,
+        }
+    }
+}
+
+impl CompressionConfig {
+    pub fn to_persistent(&self) -> CompressionPersistentConfig {
+        CompressionPersistentConfig {
+            algorithm: self.algorithm,
+        }
+    }
+
+    /// Use the algorithm of `persistent`, to compress new data of an existing
+    /// archive. Archives without it are compressed with Brotli
+    pub(crate) fn reuse_algorithm(&mut self, persistent: Option<&CompressionPersistentConfig>) {
+        self.algorithm = persistent
+            .map(|persistent| persistent.algorithm)
+            .unwrap_or_default();
+    }
+
+    /// Set whether blocks start with their kind, to continue the compressed
+    /// stream of an existing archive. Without it, every block is compressed
+    pub(crate) fn set_block_kinds(&mut self, block_kinds: bool) {
+        self.block_kinds = block_kinds;
+    }
+
+    /// Whether blocks are compressed by several threads, and only written
+    /// once complete
+    pub(crate) fn is_parallel(&self) -> bool {
+        self.threads > 1
+    }
+}
+
+/// Compression configuration stored in the header, to be reloaded
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CompressionPersistentConfig {
+    pub algorithm: CompressionAlgorithm,
+}
+
+/// Compression configuration used to read an archive, loaded from its header
+#[derive(Clone)]
+pub struct CompressionReaderConfig {
+    algorithm: CompressionAlgorithm,
+    /// Whether blocks start with their kind (format version 14 and later)
+    block_kinds: bool,
+    /// Maximum number of bytes read from an entry, if limited
+    max_entry_size: Option<u64>,
+    /// Maximum ratio between the decompressed and the compressed data, if
+    /// limited
+    max_expansion_ratio: Option<u32>,
+}
+
+impl std::default::Default for CompressionReaderConfig {
+    fn default() -> Self {
+        CompressionReaderConfig {
+            algorithm: CompressionAlgorithm::default(),
+            block_kinds: true,
+            max_entry_size: None,
+            max_expansion_ratio: None,
         }
     }
 }
 
+impl CompressionReaderConfig {
+    pub fn load_persistent(&mut self, persistent: CompressionPersistentConfig) {
+        self.algorithm = persistent.algorithm;
+    }
+
+    /// Set whether blocks start with their kind, depending on the format
+    /// version of the archive
+    pub(crate) fn set_block_kinds(&mut self, block_kinds: bool) {
+        self.block_kinds = block_kinds;
+    }
+
+    pub(crate) fn max_entry_size(&self) -> Option<u64> {
+        self.max_entry_size
+    }
+}
+
+impl ArchiveReaderConfig {
+    /// Limit the number of decompressed bytes read from a single entry
+    /// (see `ArchiveReader::get_file`), failing with
+    /// `Error::DecompressionLimitExceeded` beyond
+    ///
+    /// Unlike the sizes recorded in the archive, this does not rely on the
+    /// archive content, which may be forged
+    pub fn set_max_entry_size(&mut self, size: u64) -> &mut ArchiveReaderConfig {
+        self.compress.max_entry_size = Some(size);
+        self
+    }
+
+    /// Limit the ratio between the data decompressed and the compressed data
+    /// it comes from, failing with `Error::DecompressionLimitExceeded`
+    /// beyond, such as for a decompression bomb
+    ///
+    /// The ratio is computed over the whole archive reading, and only checked
+    /// once more than a compressed block (`UNCOMPRESSED_DATA_SIZE` bytes) is
+    /// decompressed, so small archives of very repetitive data are still read
+    pub fn set_max_expansion_ratio(&mut self, ratio: u32) -> &mut ArchiveReaderConfig {
+        self.compress.max_expansion_ratio = Some(ratio);
+        self
+    }
+}
+
 impl ArchiveWriterConfig {
     /// Set the compression level
     /// compression level (0-11); bigger values cause denser, but slower compression
@@ -51,6 +183,269 @@ impl ArchiveWriterConfig {
             Ok(self)
         }
     }
+
+    /// Set the algorithm used to compress the data (Brotli by default)
+    ///
+    /// The compression level is ignored by LZ4, and capped to 9, the highest
+    /// XZ preset, by XZ
+    pub fn set_compression_algorithm(
+        &mut self,
+        algorithm: CompressionAlgorithm,
+    ) -> &mut ArchiveWriterConfig {
+        self.compress.algorithm = algorithm;
+        self
+    }
+
+    /// Store as is the blocks which are barely compressible, such as already
+    /// compressed or encrypted content, instead of compressing them
+    ///
+    /// Whether a block is worth compressing is estimated by compressing its
+    /// first 64KB
+    pub fn set_store_incompressible(
+        &mut self,
+        store_incompressible: bool,
+    ) -> &mut ArchiveWriterConfig {
+        self.compress.store_incompressible = store_incompressible;
+        self
+    }
+
+    /// Set the number of threads compressing the blocks (1 by default)
+    ///
+    /// With several threads, independent blocks are compressed at the same
+    /// time, and written in order. The archive is the same, but up to two
+    /// blocks per thread are kept in memory
+    pub fn with_compression_threads(&mut self, threads: usize) -> ConfigResult {
+        if threads == 0 {
+            Err(ConfigError::InvalidCompressionThreads)
+        } else {
+            self.compress.threads = threads;
+            Ok(self)
+        }
+    }
+}
+
+// ---------- Blocks ----------
+
+/// Kind of a block, written before it since format version 14
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum BlockKind {
+    /// Compressed with the algorithm of the archive
+    Compressed = 0,
+    /// Stored as is
+    Stored = 1,
+}
+
+fn read_block_kind<R: Read>(src: &mut R) -> io::Result<BlockKind> {
+    match src.read_u8()? {
+        0 => Ok(BlockKind::Compressed),
+        1 => Ok(BlockKind::Stored),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "[Compression Layer] Unknown block kind",
+        )),
+    }
+}
+
+// ---------- Algorithms ----------
+
+/// Compressor of a block, depending on the algorithm
+// There is only one at a time, so the size of the Brotli state is not an issue
+#[allow(clippy::large_enum_variant)]
+enum Compressor<W: Write> {
+    Brotli(brotli::CompressorWriter<W>),
+    Lz4(lz4_flex::frame::FrameEncoder<W>),
+    Xz(xz2::write::XzEncoder<W>),
+    Stored(W),
+}
+
+impl<W: Write> Compressor<W> {
+    fn new(inner: W, compression_level: u32, algorithm: CompressionAlgorithm) -> io::Result<Self> {
+        Ok(match algorithm {
+            CompressionAlgorithm::Brotli => Compressor::Brotli(brotli::CompressorWriter::new(
+                inner,
+                0,
+                compression_level,
+                BROTLI_LOG_WINDOW,
+            )),
+            CompressionAlgorithm::Lz4 => Compressor::Lz4(lz4_flex::frame::FrameEncoder::new(inner)),
+            CompressionAlgorithm::Xz => {
+                // A dictionary bigger than a block is useless, and costs memory
+                let mut options =
+                    xz2::stream::LzmaOptions::new_preset(std::cmp::min(compression_level, 9))?;
+                options.dict_size(UNCOMPRESSED_DATA_SIZE);
+                let mut filters = xz2::stream::Filters::new();
+                filters.lzma2(&options);
+                let stream =
+                    xz2::stream::Stream::new_stream_encoder(&filters, xz2::stream::Check::Crc64)?;
+                Compressor::Xz(xz2::write::XzEncoder::new_stream(inner, stream))
+            }
+        })
+    }
+
+    /// End the compressed block, and return the inner writer
+    fn finish(self) -> io::Result<W> {
+        match self {
+            Compressor::Brotli(compress) => Ok(compress.into_inner()),
+            Compressor::Lz4(compress) => compress.finish().map_err(io::Error::from),
+            Compressor::Xz(compress) => compress.finish(),
+            Compressor::Stored(inner) => Ok(inner),
+        }
+    }
+}
+
+impl<W: Write> Write for Compressor<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Compressor::Brotli(compress) => compress.write(buf),
+            Compressor::Lz4(compress) => compress.write(buf),
+            Compressor::Xz(compress) => compress.write(buf),
+            Compressor::Stored(inner) => inner.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Compressor::Brotli(compress) => compress.flush(),
+            Compressor::Lz4(compress) => compress.flush(),
+            Compressor::Xz(compress) => compress.flush(),
+            Compressor::Stored(inner) => inner.flush(),
+        }
+    }
+}
+
+/// Decoder of a single XZ stream, ending at the end of the stream
+///
+/// Unlike `xz2::bufread::XzDecoder`, reading once the stream is over returns
+/// no data instead of failing on the bytes following it
+struct XzBlockDecoder<R: BufRead> {
+    inner: R,
+    stream: xz2::stream::Stream,
+    done: bool,
+}
+
+impl<R: BufRead> XzBlockDecoder<R> {
+    fn new(inner: R) -> io::Result<Self> {
+        Ok(Self {
+            inner,
+            stream: xz2::stream::Stream::new_stream_decoder(u64::MAX, 0)?,
+            done: false,
+        })
+    }
+
+    fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: BufRead> Read for XzBlockDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while !self.done {
+            let input = self.inner.fill_buf()?;
+            let eof = input.is_empty();
+            let before_out = self.stream.total_out();
+            let before_in = self.stream.total_in();
+            let action = if eof {
+                xz2::stream::Action::Finish
+            } else {
+                xz2::stream::Action::Run
+            };
+            let status = self.stream.process(input, buf, action)?;
+            let read = (self.stream.total_out() - before_out) as usize;
+            let consumed = (self.stream.total_in() - before_in) as usize;
+            self.inner.consume(consumed);
+
+            self.done = status == xz2::stream::Status::StreamEnd;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            if self.done {
+                break;
+            }
+            if eof {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "[Compression Layer] Truncated XZ stream",
+                ));
+            }
+            if consumed == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "[Compression Layer] Corrupted XZ stream",
+                ));
+            }
+        }
+        Ok(0)
+    }
+}
+
+/// Decompressor of a block, depending on the algorithm
+#[allow(clippy::large_enum_variant)]
+enum Decompressor<R: Read> {
+    Brotli(brotli::Decompressor<R>),
+    /// The LZ4 decoder goes on with the next frame, if any, so it is limited
+    /// to the block
+    Lz4(lz4_flex::frame::FrameDecoder<io::Take<R>>),
+    /// The XZ decoder buffers its input, so it is limited to the block too
+    Xz(XzBlockDecoder<io::BufReader<io::Take<R>>>),
+    Stored(io::Take<R>),
+}
+
+impl<R: Read> Decompressor<R> {
+    /// Decompress a block of `compressed_size` bytes
+    fn new(inner: R, compressed_size: u32, algorithm: CompressionAlgorithm) -> io::Result<Self> {
+        Ok(match algorithm {
+            CompressionAlgorithm::Brotli => {
+                Decompressor::Brotli(brotli::Decompressor::new(inner, compressed_size as usize))
+            }
+            CompressionAlgorithm::Lz4 => Decompressor::Lz4(lz4_flex::frame::FrameDecoder::new(
+                inner.take(compressed_size as u64),
+            )),
+            CompressionAlgorithm::Xz => Decompressor::Xz(XzBlockDecoder::new(io::BufReader::new(
+                inner.take(compressed_size as u64),
+            ))?),
+        })
+    }
+
+    /// Decompress a block of unknown size, for the fail-safe reader
+    ///
+    /// Once the block is fully read, `inner` is at the start of the next one
+    fn new_fail_safe(inner: R, algorithm: CompressionAlgorithm) -> io::Result<Self> {
+        Ok(match algorithm {
+            // Use a block size of `1` to ensure the decompression will stop on
+            // the first byte of the next CompressionBlock
+            CompressionAlgorithm::Brotli => {
+                Decompressor::Brotli(brotli::Decompressor::new(inner, 1))
+            }
+            CompressionAlgorithm::Lz4 => {
+                Decompressor::Lz4(lz4_flex::frame::FrameDecoder::new(inner.take(u64::MAX)))
+            }
+            // Use a buffer of `1` byte, to stop on the first byte of the next
+            // CompressionBlock too
+            CompressionAlgorithm::Xz => Decompressor::Xz(XzBlockDecoder::new(
+                io::BufReader::with_capacity(1, inner.take(u64::MAX)),
+            )?),
+        })
+    }
+
+    fn into_inner(self) -> R {
+        match self {
+            Decompressor::Brotli(decompressor) => decompressor.into_inner(),
+            Decompressor::Lz4(decompressor) => decompressor.into_inner().into_inner(),
+            Decompressor::Xz(decompressor) => decompressor.into_inner().into_inner().into_inner(),
+            Decompressor::Stored(inner) => inner.into_inner(),
+        }
+    }
+}
+
+impl<R: Read> Read for Decompressor<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Decompressor::Brotli(decompressor) => decompressor.read(buf),
+            Decompressor::Lz4(decompressor) => decompressor.read(buf),
+            Decompressor::Xz(decompressor) => decompressor.read(buf),
+            Decompressor::Stored(inner) => inner.read(buf),
+        }
+    }
 }
 
 // ---------- Reader ----------
@@ -64,7 +459,7 @@ enum CompressionLayerReaderState<R: Read> {
     InData {
         read: u32,
         uncompressed_size: u32,
-        decompressor: brotli::Decompressor<R>,
+        decompressor: Decompressor<R>,
     },
     /// Empty is a placeholder to allow state replacement
     Empty,
@@ -75,7 +470,7 @@ pub struct SizesInfo {
     /// Ordered list of chunk compressed size; only set at init
     pub compressed_sizes: Vec<u32>,
     /// Last block uncompressed size
-    last_block_size: u32,
+    pub last_block_size: u32,
 }
 
 impl SizesInfo {
@@ -108,6 +503,8 @@ impl SizesInfo {
 
 pub struct CompressionLayerReader<'a, R: 'a + Read> {
     state: CompressionLayerReaderState<Box<dyn 'a + LayerReader<'a, R>>>,
+    algorithm: CompressionAlgorithm,
+    block_kinds: bool,
     pub sizes_info: Option<SizesInfo>,
     /// Position in the under-layer (uncompressed stream)
     // /!\ Due to the decompressor having a block size of the compressed size,
@@ -128,6 +525,14 @@ pub struct CompressionLayerReader<'a, R: 'a + Read> {
     // corrected with `sizes_info` may seems unsync; `underlayer_pos` is the one
     // to trust.
     underlayer_pos: u64,
+    max_entry_size: Option<u64>,
+    max_expansion_ratio: Option<u32>,
+    /// Bytes decompressed, and compressed bytes they come from, since the
+    /// creation of the layer
+    decompressed: u64,
+    compressed: u64,
+    /// Bytes read since the start of the current entry, if any
+    entry_read: Option<u64>,
 }
 
 impl<R: Read> CompressionLayerReaderState<R> {
@@ -144,15 +549,53 @@ impl<R: Read> CompressionLayerReaderState<R> {
 }
 
 impl<'a, R: 'a + Read> CompressionLayerReader<'a, R> {
-    pub fn new(mut inner: Box<dyn 'a + LayerReader<'a, R>>) -> Result<Self, Error> {
+    pub fn new(
+        mut inner: Box<dyn 'a + LayerReader<'a, R>>,
+        config: &CompressionReaderConfig,
+    ) -> Result<Self, Error> {
         let underlayer_pos = inner.seek(SeekFrom::Current(0))? as u64;
         Ok(Self {
             state: CompressionLayerReaderState::Ready(inner),
+            algorithm: config.algorithm,
+            block_kinds: config.block_kinds,
             sizes_info: None,
             underlayer_pos,
+            max_entry_size: config.max_entry_size,
+            max_expansion_ratio: config.max_expansion_ratio,
+            decompressed: 0,
+            compressed: 0,
+            entry_read: None,
         })
     }
 
+    /// Account for the compressed block at `uncompressed_pos`, about to be
+    /// decompressed
+    fn count_compressed_block(&mut self, uncompressed_pos: u64) {
+        if let Some(sizes_info) = &self.sizes_info {
+            self.compressed += sizes_info.compressed_block_size_at(uncompressed_pos) as u64;
+        }
+    }
+
+    /// Account for `count` decompressed bytes, `read` being whether they are
+    /// returned to the caller rather than skipped, and check the limits
+    fn count_decompressed(&mut self, count: u64, read: bool) -> Result<(), Error> {
+        self.decompressed += count;
+        if let (Some(entry_read), true) = (&mut self.entry_read, read) {
+            *entry_read += count;
+            if self.max_entry_size.is_some_and(|max| *entry_read > max) {
+                return Err(Error::DecompressionLimitExceeded);
+            }
+        }
+        if let Some(ratio) = self.max_expansion_ratio {
+            if self.decompressed > UNCOMPRESSED_DATA_SIZE as u64
+                && self.decompressed > self.compressed.saturating_mul(ratio as u64)
+            {
+                return Err(Error::DecompressionLimitExceeded);
+            }
+        }
+        Ok(())
+    }
+
     /// Returns whether `uncompressed_pos` is in the data stream
     /// If no index is used, always return `true`
     fn pos_in_stream(&self, uncompressed_pos: u64) -> bool {
@@ -169,9 +612,9 @@ impl<'a, R: 'a + Read> CompressionLayerReader<'a, R> {
     /// `uncompressed_pos` must be a compressed block's starting position
     fn new_decompressor_at<S: Read + Seek>(
         &self,
-        inner: S,
+        mut inner: S,
         uncompressed_pos: u64,
-    ) -> Result<brotli::Decompressor<S>, Error> {
+    ) -> Result<Decompressor<S>, Error> {
         // Ensure it's a starting position
         if uncompressed_pos % (UNCOMPRESSED_DATA_SIZE as u64) != 0 {
             return Err(Error::BadAPIArgument(
@@ -187,11 +630,19 @@ impl<'a, R: 'a + Read> CompressionLayerReader<'a, R> {
 
         match &self.sizes_info {
             Some(sizes_info) => {
+                let mut compressed_size = sizes_info.compressed_block_size_at(uncompressed_pos);
+                let mut kind = BlockKind::Compressed;
+                if self.block_kinds {
+                    kind = read_block_kind(&mut inner)?;
+                    compressed_size = compressed_size.saturating_sub(1);
+                }
                 // Use index for faster decompression
-                Ok(brotli::Decompressor::new(
-                    inner,
-                    sizes_info.compressed_block_size_at(uncompressed_pos) as usize,
-                ))
+                Ok(match kind {
+                    BlockKind::Compressed => {
+                        Decompressor::new(inner, compressed_size, self.algorithm)?
+                    }
+                    BlockKind::Stored => Decompressor::Stored(inner.take(compressed_size as u64)),
+                })
             }
             None => Err(Error::MissingMetadata),
         }
@@ -278,6 +729,15 @@ impl<'a, R: 'a + Read + Seek> LayerReader<'a, R> for CompressionLayerReader<'a,
         self.state.into_inner().into_raw()
     }
 
+    /// Reset the number of bytes read for the per entry limit
+    fn start_entry(&mut self) {
+        self.entry_read = Some(0);
+    }
+
+    fn end_entry(&mut self) {
+        self.entry_read = None;
+    }
+
     fn initialize(&mut self) -> Result<(), Error> {
         match &mut self.state {
             CompressionLayerReaderState::Ready(inner) => {
@@ -326,6 +786,7 @@ impl<'a, R: 'a + Read + Seek> Read for CompressionLayerReader<'a, R> {
             CompressionLayerReaderState::Ready(mut inner) => {
                 self.sync_inner_with_uncompressed_pos(&mut inner, self.underlayer_pos)?;
                 let decompressor = self.new_decompressor_at(inner, self.underlayer_pos)?;
+                self.count_compressed_block(self.underlayer_pos);
                 let uncompressed_size = self.uncompressed_block_size_at(self.underlayer_pos)?;
                 self.state = CompressionLayerReaderState::InData {
                     read: 0,
@@ -358,6 +819,7 @@ impl<'a, R: 'a + Read + Seek> Read for CompressionLayerReader<'a, R> {
                     uncompressed_size,
                     decompressor,
                 };
+                self.count_decompressed(read_add as u64, true)?;
                 Ok(read_add)
             }
             CompressionLayerReaderState::Empty => Err(Error::WrongReaderState(
@@ -382,6 +844,25 @@ impl<'a, R: Read + Seek> Seek for CompressionLayerReader<'a, R> {
                         let inside_block = pos % (UNCOMPRESSED_DATA_SIZE as u64);
                         let rounded_pos = pos - inside_block;
 
+                        // Moving forward inside the current block only needs
+                        // to advance its decompressor
+                        if let CompressionLayerReaderState::InData {
+                            read, decompressor, ..
+                        } = &mut self.state
+                        {
+                            let block_start = self.underlayer_pos - *read as u64;
+                            if rounded_pos == block_start && pos >= self.underlayer_pos {
+                                let skipped = io::copy(
+                                    &mut decompressor.take(pos - self.underlayer_pos),
+                                    &mut io::sink(),
+                                )?;
+                                *read = inside_block as u32;
+                                self.underlayer_pos = pos;
+                                self.count_decompressed(skipped, false)?;
+                                return Ok(pos);
+                            }
+                        }
+
                         // Move the underlayer at the start of the block
                         let old_state =
                             std::mem::replace(&mut self.state, CompressionLayerReaderState::Empty);
@@ -391,15 +872,18 @@ impl<'a, R: Read + Seek> Seek for CompressionLayerReader<'a, R> {
                         // New decompressor at the start of the block
                         let mut decompressor = self.new_decompressor_at(inner, rounded_pos)?;
                         let uncompressed_size = self.uncompressed_block_size_at(rounded_pos)?;
+                        self.count_compressed_block(rounded_pos);
 
                         // Move forward inside the block to reach the expected position
-                        io::copy(&mut (&mut decompressor).take(inside_block), &mut io::sink())?;
+                        let skipped =
+                            io::copy(&mut (&mut decompressor).take(inside_block), &mut io::sink())?;
                         self.state = CompressionLayerReaderState::InData {
                             read: inside_block as u32,
                             uncompressed_size,
                             decompressor,
                         };
                         self.underlayer_pos = pos;
+                        self.count_decompressed(skipped, false)?;
                         Ok(pos)
                     }
                     SeekFrom::Current(pos) => {
@@ -409,10 +893,6 @@ impl<'a, R: Read + Seek> Seek for CompressionLayerReader<'a, R> {
                         } else {
                             self.seek(SeekFrom::Start((pos + self.underlayer_pos as i64) as u64))
                         }
-
-                        // TODO: Possible optimization:
-                        // - if pos is positive and inside the current block,
-                        // just advance the decompressor
                     }
                     SeekFrom::End(pos) => {
                         if pos > 0 {
@@ -462,12 +942,203 @@ impl<W: Write> Write for WriterWithCount<W> {
     }
 }
 
+/// Parameters of the blocks compression, shared with the compression threads
+#[derive(Clone, Copy)]
+struct BlockParams {
+    compression_level: u32,
+    algorithm: CompressionAlgorithm,
+    store_incompressible: bool,
+    block_kinds: bool,
+}
+
+impl BlockParams {
+    fn from_config(config: &CompressionConfig) -> Self {
+        Self {
+            compression_level: config.compression_level,
+            algorithm: config.algorithm,
+            store_incompressible: config.store_incompressible && config.block_kinds,
+            block_kinds: config.block_kinds,
+        }
+    }
+
+    /// Start a new block of kind `kind` on `inner`
+    fn start_block<I: Write>(
+        &self,
+        inner: I,
+        kind: BlockKind,
+    ) -> io::Result<Compressor<WriterWithCount<I>>> {
+        let mut inner_count = WriterWithCount::new(inner);
+        if self.block_kinds {
+            inner_count.write_u8(kind as u8)?;
+        }
+        match kind {
+            BlockKind::Compressed => {
+                Compressor::new(inner_count, self.compression_level, self.algorithm)
+            }
+            BlockKind::Stored => Ok(Compressor::Stored(inner_count)),
+        }
+    }
+
+    /// Start a new block on `inner`, beginning with `sample`, and stored as is
+    /// if `sample` is barely compressible
+    fn start_sampled_block<I: Write>(
+        &self,
+        inner: I,
+        sample: &[u8],
+    ) -> io::Result<Compressor<WriterWithCount<I>>> {
+        let mut estimate = Compressor::new(
+            WriterWithCount::new(io::sink()),
+            self.compression_level,
+            self.algorithm,
+        )?;
+        estimate.write_all(sample)?;
+        let compressed_size = estimate.finish()?.pos as u64;
+        let kind = if compressed_size * 100 >= sample.len() as u64 * INCOMPRESSIBLE_RATIO_PERCENT {
+            BlockKind::Stored
+        } else {
+            BlockKind::Compressed
+        };
+        let mut compress = self.start_block(inner, kind)?;
+        compress.write_all(sample)?;
+        Ok(compress)
+    }
+
+    /// Compress the whole block `data`, including its kind, in memory
+    fn compress_block(&self, data: &[u8], stored: bool) -> io::Result<Vec<u8>> {
+        let mut already_written = 0;
+        let mut compress = if stored {
+            self.start_block(Vec::new(), BlockKind::Stored)?
+        } else if self.store_incompressible {
+            already_written = std::cmp::min(SAMPLE_SIZE, data.len());
+            self.start_sampled_block(Vec::new(), &data[..already_written])?
+        } else {
+            self.start_block(Vec::new(), BlockKind::Compressed)?
+        };
+        compress.write_all(&data[already_written..])?;
+        Ok(compress.finish()?.into_inner())
+    }
+}
+
+/// Pool of threads compressing whole blocks, whose results are given back in
+/// the blocks order
+struct CompressionPool {
+    /// Blocks to compress, with their index and whether they are stored as is
+    jobs: Option<mpsc::Sender<(usize, Vec<u8>, bool)>>,
+    results: mpsc::Receiver<(usize, io::Result<Vec<u8>>)>,
+    workers: Vec<thread::JoinHandle<()>>,
+    /// Compressed blocks received before the ones preceding them
+    received: HashMap<usize, Vec<u8>>,
+    /// Number of blocks submitted so far
+    submitted: usize,
+    /// Index of the next block to give back
+    next: usize,
+    /// Maximum number of blocks in the pool, bounding the memory used
+    max_pending: usize,
+}
+
+impl CompressionPool {
+    /// Instantiate a pool if several threads are configured
+    fn from_config(config: &CompressionConfig) -> Option<Self> {
+        if config.threads <= 1 {
+            return None;
+        }
+        let params = BlockParams::from_config(config);
+        let (jobs, jobs_recv) = mpsc::channel::<(usize, Vec<u8>, bool)>();
+        let (results_send, results) = mpsc::channel();
+        let jobs_recv = Arc::new(Mutex::new(jobs_recv));
+        let workers = (0..config.threads)
+            .map(|_| {
+                let jobs_recv = Arc::clone(&jobs_recv);
+                let results_send = results_send.clone();
+                thread::spawn(move || loop {
+                    let job = match jobs_recv.lock() {
+                        Ok(jobs_recv) => jobs_recv.recv(),
+                        Err(_) => return,
+                    };
+                    // Stop once the pool is dropped
+                    let (index, data, stored) = match job {
+                        Ok(job) => job,
+                        Err(_) => return,
+                    };
+                    let result = params.compress_block(&data, stored);
+                    if results_send.send((index, result)).is_err() {
+                        return;
+                    }
+                })
+            })
+            .collect();
+        Some(Self {
+            jobs: Some(jobs),
+            results,
+            workers,
+            received: HashMap::new(),
+            submitted: 0,
+            next: 0,
+            max_pending: 2 * config.threads,
+        })
+    }
+
+    fn submit(&mut self, data: Vec<u8>, stored: bool) -> io::Result<()> {
+        let sent = match &self.jobs {
+            Some(jobs) => jobs.send((self.submitted, data, stored)).is_ok(),
+            None => false,
+        };
+        if !sent {
+            return Err(io::Error::other(
+                "[Compression Layer] The compression threads stopped",
+            ));
+        }
+        self.submitted += 1;
+        Ok(())
+    }
+
+    /// Give back the next compressed block, if it is available. If `wait`, or
+    /// if too many blocks are pending, wait for it
+    fn next_block(&mut self, wait: bool) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            if let Some(data) = self.received.remove(&self.next) {
+                self.next += 1;
+                return Ok(Some(data));
+            }
+            let pending = self.submitted - self.next;
+            if pending == 0 {
+                return Ok(None);
+            }
+            let (index, result) = if wait || pending >= self.max_pending {
+                self.results.recv().map_err(|_| {
+                    io::Error::other("[Compression Layer] The compression threads stopped")
+                })?
+            } else {
+                match self.results.try_recv() {
+                    Ok(received) => received,
+                    Err(_) => return Ok(None),
+                }
+            };
+            self.received.insert(index, result?);
+        }
+    }
+}
+
+impl Drop for CompressionPool {
+    fn drop(&mut self) {
+        // Closing the jobs channel stops the threads
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            // A panicking thread has nothing more to give back
+            let _ = worker.join();
+        }
+    }
+}
+
 enum CompressionLayerWriterState<W: Write> {
     /// Ready contains the real inner destination
     Ready(W),
     /// How many uncompressed bytes have already been written for the current
     /// block
-    InData(u32, brotli::CompressorWriter<WriterWithCount<W>>),
+    InData(u32, Compressor<WriterWithCount<W>>),
+    /// First bytes of the current block, not written yet, as the kind of the
+    /// block depends on them
+    Sampling(Vec<u8>, W),
     /// Empty is a placeholder to allow state replacement
     Empty,
 }
@@ -486,6 +1157,9 @@ enum CompressionLayerWriterState<W: Write> {
 /// Cons:
 /// * if the index is lost, a slow decompression with a block size of 1 is
 /// needed to found the CompressedBlock boundaries
+///
+/// As blocks are independent, they can be compressed by several threads.
+/// They are then buffered, and written in order once compressed
 pub struct CompressionLayerWriter<'a, W: 'a + Write> {
     state: CompressionLayerWriterState<Box<dyn 'a + LayerWriter<'a, W>>>,
     // Ordered list of compressed size of block of `UNCOMPRESSED_DATA_SIZE`
@@ -496,16 +1170,26 @@ pub struct CompressionLayerWriter<'a, W: 'a + Write> {
     // / C])`, with `C = UNCOMPRESSED_DATA_SIZE`
     compressed_sizes: Vec<u32>,
     // From config
-    compression_level: u32,
+    params: BlockParams,
+    /// Set while the data written is better stored as is
+    store_hint: bool,
+    /// With several threads, blocks are compressed by this pool
+    pool: Option<CompressionPool>,
+    /// With several threads, uncompressed data of the current block, not
+    /// submitted to the pool yet
+    block: Vec<u8>,
+    /// With several threads, whether the current block is stored as is
+    block_stored: bool,
 }
 
 impl<W: Write> CompressionLayerWriterState<W> {
     fn into_inner(self) -> W {
         match self {
             CompressionLayerWriterState::Ready(inner) => inner,
-            CompressionLayerWriterState::InData(_written, compress) => {
-                compress.into_inner().into_inner()
-            }
+            CompressionLayerWriterState::InData(_written, compress) => compress
+                .finish()
+                .expect("[Writer] Unable to end the compressed block")
+                .into_inner(),
             // `panic!` explicitly called to avoid propagating an error which
             // must never happens (ie, calling `into_inner` in an inconsistent
             // internal state)
@@ -522,34 +1206,153 @@ impl<'a, W: 'a + Write> CompressionLayerWriter<'a, W> {
         Self {
             state: CompressionLayerWriterState::Ready(inner),
             compressed_sizes: Vec::new(),
-            compression_level: config.compression_level,
+            params: BlockParams::from_config(config),
+            store_hint: false,
+            pool: CompressionPool::from_config(config),
+            block: Vec::new(),
+            block_stored: false,
         }
     }
+
+    /// Instantiate a writer continuing a compressed stream, whose first
+    /// blocks, of sizes `compressed_sizes`, have already been written to `inner`
+    pub(crate) fn resume(
+        inner: Box<dyn 'a + LayerWriter<'a, W>>,
+        config: &CompressionConfig,
+        compressed_sizes: Vec<u32>,
+    ) -> CompressionLayerWriter<'a, W> {
+        Self {
+            state: CompressionLayerWriterState::Ready(inner),
+            compressed_sizes,
+            params: BlockParams::from_config(config),
+            store_hint: false,
+            pool: CompressionPool::from_config(config),
+            block: Vec::new(),
+            block_stored: false,
+        }
+    }
+
+    /// If the current block is still sampled, choose its kind from the bytes
+    /// received so far, and write them
+    fn end_sampling(&mut self) -> io::Result<()> {
+        let old_state = std::mem::replace(&mut self.state, CompressionLayerWriterState::Empty);
+        self.state = match old_state {
+            CompressionLayerWriterState::Sampling(sample, inner) => {
+                let compress = self.params.start_sampled_block(inner, &sample)?;
+                CompressionLayerWriterState::InData(sample.len() as u32, compress)
+            }
+            state => state,
+        };
+        Ok(())
+    }
+
+    /// With several threads, add the beginning of `buf` to the current block,
+    /// submitting it first if it is complete
+    fn write_parallel(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // A complete block is only submitted on the next write, so that the
+        // last block is known on finalization
+        if self.block.len() == UNCOMPRESSED_DATA_SIZE as usize {
+            self.submit_block()?;
+        }
+        if self.block.is_empty() {
+            self.block_stored = self.store_hint && self.params.block_kinds;
+        } else if self.store_hint
+            && self.params.store_incompressible
+            && self.block.len() < SAMPLE_SIZE
+        {
+            // As with a single thread, the hint still applies while sampling
+            self.block_stored = true;
+        }
+        let size = std::cmp::min(
+            UNCOMPRESSED_DATA_SIZE as usize - self.block.len(),
+            buf.len(),
+        );
+        self.block.extend_from_slice(&buf[..size]);
+        Ok(size)
+    }
+
+    /// Submit the current block to the pool, and write the blocks already
+    /// compressed
+    fn submit_block(&mut self) -> io::Result<()> {
+        let data = std::mem::replace(
+            &mut self.block,
+            Vec::with_capacity(UNCOMPRESSED_DATA_SIZE as usize),
+        );
+        if let Some(pool) = &mut self.pool {
+            pool.submit(data, self.block_stored)?;
+        }
+        self.write_compressed_blocks(false)
+    }
+
+    /// Write the blocks compressed by the pool, in order. If `wait`, wait for
+    /// every submitted block
+    fn write_compressed_blocks(&mut self, wait: bool) -> io::Result<()> {
+        let (pool, inner) = match (&mut self.pool, &mut self.state) {
+            (Some(pool), CompressionLayerWriterState::Ready(inner)) => (pool, inner),
+            (None, _) => return Ok(()),
+            _ => {
+                return Err(Error::WrongReaderState(
+                    "[Compression Layer] The pool is used out of the Ready state".to_string(),
+                )
+                .into())
+            }
+        };
+        while let Some(data) = pool.next_block(wait)? {
+            inner.write_all(&data)?;
+            self.compressed_sizes.push(data.len() as u32);
+        }
+        Ok(())
+    }
+
+    /// With several threads, submit the current block, even if incomplete,
+    /// and write every block
+    fn end_parallel(&mut self) -> io::Result<()> {
+        if self.pool.is_none() {
+            return Ok(());
+        }
+        if !self.block.is_empty() {
+            self.submit_block()?;
+        }
+        self.write_compressed_blocks(true)
+    }
 }
 
 impl<'a, W: 'a + Write> LayerWriter<'a, W> for CompressionLayerWriter<'a, W> {
-    fn into_inner(self) -> Option<Box<dyn 'a + LayerWriter<'a, W>>> {
+    fn into_inner(mut self) -> Option<Box<dyn 'a + LayerWriter<'a, W>>> {
+        self.end_sampling()
+            .expect("[Writer] Unable to end the sampled block");
+        self.end_parallel()
+            .expect("[Writer] Unable to write the compressed blocks");
         Some(self.state.into_inner())
     }
 
-    fn into_raw(self: Box<Self>) -> W {
+    fn into_raw(mut self: Box<Self>) -> W {
+        self.end_sampling()
+            .expect("[Writer] Unable to end the sampled block");
+        self.end_parallel()
+            .expect("[Writer] Unable to write the compressed blocks");
         self.state.into_inner().into_raw()
     }
 
     fn finalize(&mut self) -> Result<(), Error> {
+        self.end_sampling()?;
+        let mut last_block_size = 0;
+        if self.pool.is_some() {
+            last_block_size = self.block.len() as u32;
+            self.end_parallel()?;
+        }
         // Use this mem::replace trick to be able to get back the compressor
         // inner and freely move from CompressionLayerWriterState to others
         let old_state = std::mem::replace(&mut self.state, CompressionLayerWriterState::Empty);
-        let mut last_block_size = 0;
         let mut inner = match old_state {
             CompressionLayerWriterState::Ready(inner) => inner,
             CompressionLayerWriterState::InData(written, compress) => {
-                let inner_count = compress.into_inner();
+                let inner_count = compress.finish()?;
                 self.compressed_sizes.push(inner_count.pos);
                 last_block_size = written;
                 inner_count.into_inner()
             }
-            CompressionLayerWriterState::Empty => {
+            CompressionLayerWriterState::Sampling(..) | CompressionLayerWriterState::Empty => {
                 // Should never happens, except if an error already occurs before
                 return Err(Error::WrongReaderState("[Compression Layer] bad state in finalization, an error may already occurs before".to_string()));
             }
@@ -590,22 +1393,38 @@ impl<'a, W: 'a + Write> LayerWriter<'a, W> for CompressionLayerWriter<'a, W> {
         self.state = CompressionLayerWriterState::Ready(inner);
         Ok(())
     }
+
+    /// Blocks starting while the hint is set are stored as is. As blocks have
+    /// a fixed uncompressed size, the current block is not ended early
+    fn set_store_hint(&mut self, store: bool) {
+        self.store_hint = store;
+    }
 }
 
 impl<'a, W: 'a + Write> Write for CompressionLayerWriter<'a, W> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.pool.is_some() {
+            return self.write_parallel(buf);
+        }
         // Use this mem::replace trick to be able to get back the compressor
         // inner and freely move from CompressionLayerWriterState to others
         let old_state = std::mem::replace(&mut self.state, CompressionLayerWriterState::Empty);
         match old_state {
             CompressionLayerWriterState::Ready(inner) => {
-                let inner_count = WriterWithCount::new(inner);
-                let mut compress = brotli::CompressorWriter::new(
-                    inner_count,
-                    0,
-                    self.compression_level,
-                    BROTLI_LOG_WINDOW,
-                );
+                if self.params.store_incompressible && !self.store_hint {
+                    // Wait for a sample of the block to choose its kind
+                    self.state = CompressionLayerWriterState::Sampling(
+                        Vec::with_capacity(SAMPLE_SIZE),
+                        inner,
+                    );
+                    return self.write(buf);
+                }
+                let kind = if self.store_hint && self.params.block_kinds {
+                    BlockKind::Stored
+                } else {
+                    BlockKind::Compressed
+                };
+                let mut compress = self.params.start_block(inner, kind)?;
                 let size = std::cmp::min(UNCOMPRESSED_DATA_SIZE as usize, buf.len());
                 let written = compress.write(&buf[..size])?;
                 self.state = CompressionLayerWriterState::InData(written as u32, compress);
@@ -618,7 +1437,7 @@ impl<'a, W: 'a + Write> Write for CompressionLayerWriter<'a, W> {
                     ).into());
                 }
                 if written == UNCOMPRESSED_DATA_SIZE {
-                    let inner_count = compress.into_inner();
+                    let inner_count = compress.finish()?;
                     self.compressed_sizes.push(inner_count.pos);
                     self.state = CompressionLayerWriterState::Ready(inner_count.into_inner());
                     // Start a new block, fill it with new values!
@@ -630,17 +1449,60 @@ impl<'a, W: 'a + Write> Write for CompressionLayerWriter<'a, W> {
                     CompressionLayerWriterState::InData(written + written_add as u32, compress);
                 Ok(written_add)
             }
+            CompressionLayerWriterState::Sampling(sample, inner) if self.store_hint => {
+                // No need to go on sampling
+                let mut compress = self.params.start_block(inner, BlockKind::Stored)?;
+                compress.write_all(&sample)?;
+                self.state = CompressionLayerWriterState::InData(sample.len() as u32, compress);
+                self.write(buf)
+            }
+            CompressionLayerWriterState::Sampling(mut sample, inner) => {
+                let size = std::cmp::min(SAMPLE_SIZE - sample.len(), buf.len());
+                sample.extend_from_slice(&buf[..size]);
+                if sample.len() == SAMPLE_SIZE {
+                    let compress = self.params.start_sampled_block(inner, &sample)?;
+                    self.state = CompressionLayerWriterState::InData(sample.len() as u32, compress);
+                } else {
+                    self.state = CompressionLayerWriterState::Sampling(sample, inner);
+                }
+                Ok(size)
+            }
             CompressionLayerWriterState::Empty => {
                 Err(Error::WrongReaderState("[Compression Layer] On write, should never happens, unless an error already occurs before".to_string()).into())
             }
         }
     }
 
+    /// Slices are compressed in turn, in a single call from the layer above
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        let mut written = 0;
+        for buf in bufs {
+            if buf.is_empty() {
+                continue;
+            }
+            let count = match self.write(buf) {
+                Ok(count) => count,
+                // Reported on the next call, as some data is already written
+                Err(_) if written > 0 => break,
+                Err(err) => return Err(err),
+            };
+            written += count;
+            if count < buf.len() {
+                break;
+            }
+        }
+        Ok(written)
+    }
+
     fn flush(&mut self) -> io::Result<()> {
+        // Data must reach the inner layer, even if the sample is not complete
+        self.end_sampling()?;
+        // With several threads, only the complete blocks can be written
+        self.write_compressed_blocks(true)?;
         match &mut self.state {
             CompressionLayerWriterState::Ready(inner) => inner.flush(),
             CompressionLayerWriterState::InData(_written, compress) => compress.flush(),
-            CompressionLayerWriterState::Empty => {
+            CompressionLayerWriterState::Sampling(..) | CompressionLayerWriterState::Empty => {
                 // Should never happens, except if an error already occurs before
                 Err(Error::WrongReaderState("[Compression Layer] On flush, should never happens, unless an error already occurs before".to_string()).into())
             }
@@ -652,12 +1514,19 @@ impl<'a, W: 'a + Write> Write for CompressionLayerWriter<'a, W> {
 
 pub struct CompressionLayerFailSafeReader<'a, R: 'a + Read> {
     state: CompressionLayerReaderState<Box<dyn 'a + LayerFailSafeReader<'a, R>>>,
+    algorithm: CompressionAlgorithm,
+    block_kinds: bool,
 }
 
 impl<'a, R: 'a + Read> CompressionLayerFailSafeReader<'a, R> {
-    pub fn new(inner: Box<dyn 'a + LayerFailSafeReader<'a, R>>) -> Result<Self, Error> {
+    pub fn new(
+        inner: Box<dyn 'a + LayerFailSafeReader<'a, R>>,
+        config: &CompressionReaderConfig,
+    ) -> Result<Self, Error> {
         Ok(Self {
             state: CompressionLayerReaderState::Ready(inner),
+            algorithm: config.algorithm,
+            block_kinds: config.block_kinds,
         })
     }
 }
@@ -683,14 +1552,21 @@ impl<'a, R: 'a + Read> Read for CompressionLayerFailSafeReader<'a, R> {
         // inner and freely move from CompressionLayerReaderState to others
         let old_state = std::mem::replace(&mut self.state, CompressionLayerReaderState::Empty);
         match old_state {
-            CompressionLayerReaderState::Ready(inner) => {
+            CompressionLayerReaderState::Ready(mut inner) => {
                 // Default values, for "repair" mode
 
-                // Use a block size of `1` to ensure the decompression
-                // will stop on the first byte of the next CompressionBlock.
-                // This is slower, but we don't have index, and
-                // therefore we don't know the compressed block size
-                let decompressor = brotli::Decompressor::new(inner, 1);
+                let mut kind = BlockKind::Compressed;
+                if self.block_kinds {
+                    kind = read_block_kind(&mut inner)?;
+                }
+                let decompressor = match kind {
+                    // We don't have index, and therefore we don't know the
+                    // compressed block size
+                    BlockKind::Compressed => Decompressor::new_fail_safe(inner, self.algorithm)?,
+                    BlockKind::Stored => {
+                        Decompressor::Stored(inner.take(UNCOMPRESSED_DATA_SIZE as u64))
+                    }
+                };
                 self.state = CompressionLayerReaderState::InData {
                     read: 0,
                     // Default values, for "repair" mode
@@ -713,9 +1589,10 @@ impl<'a, R: 'a + Read> Read for CompressionLayerFailSafeReader<'a, R> {
                 if read == uncompressed_size {
                     // Consume the rest of the current decompressor. Due to the
                     // brotli implementation, a few bytes might remains, even if
-                    // we already obtain the expected number of bytes. Thanks to
-                    // the brotli format, the decompressor is able to stop at
-                    // the end of the current block.
+                    // we already obtain the expected number of bytes, as for
+                    // the end of a LZ4 frame or XZ stream. Thanks to these
+                    // formats, the decompressor is able to stop at the end of
+                    // the current block.
                     io::copy(&mut decompressor, &mut io::sink())?;
                     // Start a new block, fill it with new values
                     self.state = CompressionLayerReaderState::Ready(decompressor.into_inner());
@@ -792,6 +1669,7 @@ mod tests {
         let file = comp.into_raw();
 
         let mut src = Cursor::new(file.as_slice());
+        assert_eq!(read_block_kind(&mut src).unwrap(), BlockKind::Compressed);
         let mut reader = brotli::Decompressor::new(&mut src, 0);
         let mut buf = Vec::new();
         reader.read_to_end(&mut buf).unwrap();
@@ -830,12 +1708,14 @@ mod tests {
         // reality, we will have index with compressed size)
         let now = Instant::now();
 
+        assert_eq!(read_block_kind(&mut src).unwrap(), BlockKind::Compressed);
         let mut reader = brotli::Decompressor::new(&mut src, 1);
         let mut buf = vec![0; UNCOMPRESSED_DATA_SIZE as usize];
         reader.read_exact(&mut buf).expect("First buffer");
         assert_eq!(buf.len(), UNCOMPRESSED_DATA_SIZE as usize);
         assert_eq!(buf.as_slice(), &bytes[..(UNCOMPRESSED_DATA_SIZE as usize)]);
 
+        assert_eq!(read_block_kind(&mut src).unwrap(), BlockKind::Compressed);
         let mut reader = brotli::Decompressor::new(&mut src, 1);
         let mut buf2 = vec![0; UNCOMPRESSED_DATA_SIZE as usize];
         reader.read_exact(&mut buf2).expect("Second buffer");
@@ -845,6 +1725,7 @@ mod tests {
             &bytes[(UNCOMPRESSED_DATA_SIZE as usize)..((UNCOMPRESSED_DATA_SIZE * 2) as usize)]
         );
 
+        assert_eq!(read_block_kind(&mut src).unwrap(), BlockKind::Compressed);
         let mut reader = brotli::Decompressor::new(&mut src, 1);
         let mut buf3 = vec![0; SIZE - buf.len() - buf2.len() as usize];
         reader.read_exact(&mut buf3).expect("Last buffer");
@@ -879,8 +1760,13 @@ mod tests {
             comp.finalize().unwrap();
             let file = comp.into_raw();
             let buf = Cursor::new(file.as_slice());
-            let mut decomp =
-                Box::new(CompressionLayerReader::new(Box::new(RawLayerReader::new(buf))).unwrap());
+            let mut decomp = Box::new(
+                CompressionLayerReader::new(
+                    Box::new(RawLayerReader::new(buf)),
+                    &CompressionReaderConfig::default(),
+                )
+                .unwrap(),
+            );
             decomp.initialize().unwrap();
             let mut buf = Vec::new();
             decomp.read_to_end(&mut buf).unwrap();
@@ -912,9 +1798,10 @@ mod tests {
             comp.finalize().unwrap();
             let file = comp.into_raw();
             let mut decomp = Box::new(
-                CompressionLayerFailSafeReader::new(Box::new(RawLayerFailSafeReader::new(
-                    file.as_slice(),
-                )))
+                CompressionLayerFailSafeReader::new(
+                    Box::new(RawLayerFailSafeReader::new(file.as_slice())),
+                    &CompressionReaderConfig::default(),
+                )
                 .unwrap(),
             );
             let mut buf = Vec::new();
@@ -952,9 +1839,10 @@ mod tests {
             let stop = file.len() / 2;
 
             let mut decomp = Box::new(
-                CompressionLayerFailSafeReader::new(Box::new(RawLayerFailSafeReader::new(
-                    &file[..stop],
-                )))
+                CompressionLayerFailSafeReader::new(
+                    Box::new(RawLayerFailSafeReader::new(&file[..stop])),
+                    &CompressionReaderConfig::default(),
+                )
                 .unwrap(),
             );
             let mut buf = Vec::new();
@@ -995,8 +1883,13 @@ mod tests {
 
         let file = comp.into_raw();
         let buf = Cursor::new(file.as_slice());
-        let mut decomp =
-            Box::new(CompressionLayerReader::new(Box::new(RawLayerReader::new(buf))).unwrap());
+        let mut decomp = Box::new(
+            CompressionLayerReader::new(
+                Box::new(RawLayerReader::new(buf)),
+                &CompressionReaderConfig::default(),
+            )
+            .unwrap(),
+        );
         decomp.initialize().unwrap();
 
         // Check the footer has been correctly re-read
@@ -1021,8 +1914,13 @@ mod tests {
 
             let file = comp.into_raw();
             let buf = Cursor::new(file.as_slice());
-            let mut decomp =
-                Box::new(CompressionLayerReader::new(Box::new(RawLayerReader::new(buf))).unwrap());
+            let mut decomp = Box::new(
+                CompressionLayerReader::new(
+                    Box::new(RawLayerReader::new(buf)),
+                    &CompressionReaderConfig::default(),
+                )
+                .unwrap(),
+            );
             decomp.initialize().unwrap();
 
             // Seek in the first block
@@ -1073,6 +1971,181 @@ mod tests {
         }
     }
 
+    #[test]
+    fn compress_lz4() {
+        compress_with_algorithm(CompressionAlgorithm::Lz4);
+    }
+
+    #[test]
+    fn compress_xz() {
+        compress_with_algorithm(CompressionAlgorithm::Xz);
+    }
+
+    fn compress_with_algorithm(algorithm: CompressionAlgorithm) {
+        let config = CompressionConfig {
+            algorithm,
+            ..CompressionConfig::default()
+        };
+        let reader_config = CompressionReaderConfig {
+            algorithm,
+            ..CompressionReaderConfig::default()
+        };
+
+        for data in vec![get_data(), get_uncompressable_data()] {
+            let bytes = data.as_slice();
+
+            let file = Vec::new();
+            let mut comp = Box::new(CompressionLayerWriter::new(
+                Box::new(RawLayerWriter::new(file)),
+                &config,
+            ));
+            comp.write_all(bytes).unwrap();
+            comp.finalize().unwrap();
+            let file = comp.into_raw();
+
+            // Read, then seek in the second block
+            let mut decomp = Box::new(
+                CompressionLayerReader::new(
+                    Box::new(RawLayerReader::new(Cursor::new(file.as_slice()))),
+                    &reader_config,
+                )
+                .unwrap(),
+            );
+            decomp.initialize().unwrap();
+            let mut buf = Vec::new();
+            decomp.read_to_end(&mut buf).unwrap();
+            assert_eq!(buf.as_slice(), bytes);
+            let pos = decomp
+                .seek(SeekFrom::Start((UNCOMPRESSED_DATA_SIZE + 4).into()))
+                .unwrap();
+            let mut buf = [0u8; 5];
+            decomp.read_exact(&mut buf).unwrap();
+            assert_eq!(&buf, &bytes[pos as usize..(pos + 5) as usize]);
+
+            // Without the index
+            let mut decomp = Box::new(
+                CompressionLayerFailSafeReader::new(
+                    Box::new(RawLayerFailSafeReader::new(file.as_slice())),
+                    &reader_config,
+                )
+                .unwrap(),
+            );
+            let mut buf = Vec::new();
+            decomp.read_to_end(&mut buf).unwrap();
+            assert_eq!(buf.as_slice(), bytes);
+        }
+    }
+
+    #[test]
+    fn compress_store_incompressible() {
+        let config = CompressionConfig {
+            store_incompressible: true,
+            ..CompressionConfig::default()
+        };
+
+        // Random data, then compressible data
+        let mut data = get_uncompressable_data();
+        data.extend_from_slice(&get_data()[..UNCOMPRESSED_DATA_SIZE as usize]);
+        let bytes = data.as_slice();
+
+        let file = Vec::new();
+        let mut comp = Box::new(CompressionLayerWriter::new(
+            Box::new(RawLayerWriter::new(file)),
+            &config,
+        ));
+        comp.write_all(bytes).unwrap();
+        comp.finalize().unwrap();
+        // Random blocks are stored, with their kind
+        assert_eq!(comp.compressed_sizes[0], UNCOMPRESSED_DATA_SIZE + 1);
+        assert_eq!(comp.compressed_sizes[1], UNCOMPRESSED_DATA_SIZE + 1);
+        // Compressible ones are compressed
+        assert!(comp.compressed_sizes[3] < UNCOMPRESSED_DATA_SIZE / 2);
+        let file = comp.into_raw();
+
+        // Read, then seek in a stored block
+        let mut decomp = Box::new(
+            CompressionLayerReader::new(
+                Box::new(RawLayerReader::new(Cursor::new(file.as_slice()))),
+                &CompressionReaderConfig::default(),
+            )
+            .unwrap(),
+        );
+        decomp.initialize().unwrap();
+        let mut buf = Vec::new();
+        decomp.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf.as_slice(), bytes);
+        let pos = decomp
+            .seek(SeekFrom::Start((UNCOMPRESSED_DATA_SIZE + 4).into()))
+            .unwrap();
+        let mut buf = [0u8; 5];
+        decomp.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, &bytes[pos as usize..(pos + 5) as usize]);
+
+        // Without the index
+        let mut decomp = Box::new(
+            CompressionLayerFailSafeReader::new(
+                Box::new(RawLayerFailSafeReader::new(file.as_slice())),
+                &CompressionReaderConfig::default(),
+            )
+            .unwrap(),
+        );
+        let mut buf = Vec::new();
+        decomp.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf.as_slice(), bytes);
+    }
+
+    #[test]
+    fn compress_parallel() {
+        // Random data, then compressible data
+        let mut data = get_uncompressable_data();
+        data.extend_from_slice(&get_data());
+        let bytes = data.as_slice();
+
+        for store_incompressible in &[false, true] {
+            let compress = |threads| {
+                let config = CompressionConfig {
+                    store_incompressible: *store_incompressible,
+                    threads,
+                    ..CompressionConfig::default()
+                };
+                let mut comp = Box::new(CompressionLayerWriter::new(
+                    Box::new(RawLayerWriter::new(Vec::new())),
+                    &config,
+                ));
+                // Small writes, to cross the blocks boundaries
+                for chunk in bytes.chunks(100_000) {
+                    comp.write_all(chunk).unwrap();
+                }
+                comp.finalize().unwrap();
+                let compressed_sizes = comp.compressed_sizes.clone();
+                (compressed_sizes, comp.into_raw())
+            };
+
+            // Blocks compressed by several threads are the same, in order,
+            // whatever the number of threads. A single thread compresses
+            // them as they are written, so its output may differ
+            let (sizes, file) = compress(2);
+            let (sizes_parallel, file_parallel) = compress(3);
+            assert_eq!(sizes_parallel, sizes);
+            assert_eq!(file_parallel, file);
+
+            let (_, file_single) = compress(1);
+            for file in &[file_single, file_parallel] {
+                let mut decomp = Box::new(
+                    CompressionLayerReader::new(
+                        Box::new(RawLayerReader::new(Cursor::new(file.as_slice()))),
+                        &CompressionReaderConfig::default(),
+                    )
+                    .unwrap(),
+                );
+                decomp.initialize().unwrap();
+                let mut buf = Vec::new();
+                decomp.read_to_end(&mut buf).unwrap();
+                assert_eq!(buf.as_slice(), bytes);
+            }
+        }
+    }
+
     #[test]
     fn sizes_info() {
         let sizes_info = SizesInfo {
@@ -1137,14 +2210,24 @@ mod tests {
         // Check content
         let buf = Cursor::new(file.as_slice());
         let mut buf_out = Vec::new();
-        let mut decomp =
-            Box::new(CompressionLayerReader::new(Box::new(RawLayerReader::new(buf))).unwrap());
+        let mut decomp = Box::new(
+            CompressionLayerReader::new(
+                Box::new(RawLayerReader::new(buf)),
+                &CompressionReaderConfig::default(),
+            )
+            .unwrap(),
+        );
         decomp.initialize().unwrap();
         decomp.read_to_end(&mut buf_out).unwrap();
         let buf2 = Cursor::new(file2.as_slice());
         let mut buf2_out = Vec::new();
-        let mut decomp =
-            Box::new(CompressionLayerReader::new(Box::new(RawLayerReader::new(buf2))).unwrap());
+        let mut decomp = Box::new(
+            CompressionLayerReader::new(
+                Box::new(RawLayerReader::new(buf2)),
+                &CompressionReaderConfig::default(),
+            )
+            .unwrap(),
+        );
         decomp.initialize().unwrap();
         decomp.read_to_end(&mut buf2_out).unwrap();
         assert_eq!(buf_out, buf2_out);
@@ -1,16 +1,34 @@
 use crate::crypto::aesgcm::{AesGcm256, ConstantTimeEq, Tag, TAG_LENGTH};
-use crate::crypto::ecc::{retrieve_key, store_key_for_multi_recipients, MultiRecipientPersistent};
+use crate::crypto::chacha20poly1305::ChaCha20Poly1305;
+use crate::crypto::ecc::{
+    retrieve_key, retrieve_key_with_index, store_key_for_multi_recipients,
+    store_keys_for_multi_recipients, MultiRecipientPersistent,
+};
+use crate::crypto::hybrid::{
+    retrieve_key_hybrid, store_key_for_hybrid_recipients, HybridPersistent, HybridPrivateKey,
+    HybridPublicKey,
+};
+use crate::crypto::password::{
+    retrieve_key_with_password, store_key_for_password, PasswordPersistent,
+};
+use crate::crypto::shamir;
 
 use crate::layers::traits::{LayerFailSafeReader, LayerReader, LayerWriter};
 use crate::Error;
 use std::io;
-use std::io::{BufReader, Cursor, Read, Seek, SeekFrom, Write};
+use std::io::{Cursor, IoSlice, Read, Seek, SeekFrom, Write};
 
-use crate::config::{ArchiveReaderConfig, ArchiveWriterConfig};
+use crate::config::{
+    seeded_rng, ArchiveReaderConfig, ArchiveWriterConfig, KEY_STREAM, RECIPIENTS_STREAM, SEED_SIZE,
+};
 use crate::errors::ConfigError;
+use hkdf::Hkdf;
+use rand::seq::SliceRandom;
 use rand::{Rng, SeedableRng};
 use rand_chacha::ChaChaRng;
+use sha2::{Digest, Sha256};
 use x25519_dalek::{PublicKey, StaticSecret};
+use zeroize::Zeroize;
 
 use serde::{Deserialize, Serialize};
 
@@ -18,11 +36,30 @@ const CIPHER_BUF_SIZE: u64 = 4096;
 const KEY_SIZE: usize = 32;
 // This is the size of the nonce taken as input
 const NONCE_SIZE: usize = 8;
-const CHUNK_SIZE: u64 = 128 * 1024;
+/// Size of the encrypted chunks, each with its own tag
+pub const CHUNK_SIZE: u64 = 128 * 1024;
 
 // This is the Nonce as expected by AesGcm
 const NONCE_AES_SIZE: usize = 96 / 8;
 type Nonce = [u8; NONCE_AES_SIZE];
+const REKEY_DERIVE_KEY_INFO: &[u8; 9] = b"MLA REKEY";
+/// Size of a recipient key hint, see `key_hint`
+pub const KEY_HINT_SIZE: usize = 8;
+const KEY_HINT_INFO: &[u8; 12] = b"MLA KEY HINT";
+
+/// Return the hint of a recipient public key (X25519, or hybrid), stored in
+/// the header with `ArchiveWriterConfig::set_recipient_hints`:
+/// `SHA-256("MLA KEY HINT" . public_key)[..8]`
+///
+/// It only tells readers which private keys are worth trying
+pub fn key_hint(public_key: &[u8]) -> [u8; KEY_HINT_SIZE] {
+    let mut hasher = Sha256::new();
+    hasher.update(KEY_HINT_INFO);
+    hasher.update(public_key);
+    let mut hint = [0u8; KEY_HINT_SIZE];
+    hint.copy_from_slice(&hasher.finalize()[..KEY_HINT_SIZE]);
+    hint
+}
 
 /// Build nonce according to a given state
 ///
@@ -42,22 +79,363 @@ fn build_nonce(nonce_prefix: [u8; NONCE_SIZE], current_ctr: u32) -> Nonce {
     nonce
 }
 
+/// Authenticated cipher used to encrypt the data, recorded in the header
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub enum Cipher {
+    #[default]
+    AesGcm256,
+    ChaCha20Poly1305,
+}
+
+/// Return the key of the chunk `chunk_number`
+///
+/// Without a rekeying schedule, it is `key`. Otherwise, a new key is derived
+/// from `key` for each group of `rekey_interval` chunks, with:
+/// `HKDF(SHA-256, key, "MLA REKEY" . u32.as_big_endian(chunk_number / rekey_interval))`
+fn chunk_key(
+    key: &[u8; KEY_SIZE],
+    rekey_interval: Option<u32>,
+    chunk_number: u32,
+) -> Result<[u8; KEY_SIZE], Error> {
+    match rekey_interval {
+        // An interval of 0 is refused by `EncryptionConfig::check`, but may
+        // come from a malformed header
+        Some(0) => Err(ConfigError::InvalidRekeyInterval.into()),
+        Some(rekey_interval) => {
+            let hkdf: Hkdf<Sha256> = Hkdf::new(None, key);
+            let mut info = REKEY_DERIVE_KEY_INFO.to_vec();
+            info.extend_from_slice(&(chunk_number / rekey_interval).to_be_bytes());
+            let mut output = [0u8; KEY_SIZE];
+            hkdf.expand(&info, &mut output)?;
+            Ok(output)
+        }
+        None => Ok(*key),
+    }
+}
+
+/// Instance of a `Cipher`, for one chunk
+enum ChunkCipher {
+    AesGcm256(AesGcm256),
+    ChaCha20Poly1305(ChaCha20Poly1305),
+}
+
+impl ChunkCipher {
+    /// Prepare the cipher of the chunk `chunk_number`
+    fn new(
+        cipher: Cipher,
+        key: &[u8; KEY_SIZE],
+        rekey_interval: Option<u32>,
+        nonce_prefix: [u8; NONCE_SIZE],
+        chunk_number: u32,
+    ) -> Result<Self, Error> {
+        let mut key = chunk_key(key, rekey_interval, chunk_number)?;
+        let nonce = build_nonce(nonce_prefix, chunk_number);
+        let chunk_cipher = match cipher {
+            Cipher::AesGcm256 => ChunkCipher::AesGcm256(AesGcm256::new(&key, &nonce, b"")?),
+            Cipher::ChaCha20Poly1305 => {
+                ChunkCipher::ChaCha20Poly1305(ChaCha20Poly1305::new(&key, &nonce, b"")?)
+            }
+        };
+        key.zeroize();
+        Ok(chunk_cipher)
+    }
+
+    fn encrypt(&mut self, buffer: &mut [u8]) {
+        match self {
+            ChunkCipher::AesGcm256(cipher) => cipher.encrypt(buffer),
+            ChunkCipher::ChaCha20Poly1305(cipher) => cipher.encrypt(buffer),
+        }
+    }
+
+    fn into_tag(self) -> Tag {
+        match self {
+            ChunkCipher::AesGcm256(cipher) => cipher.into_tag(),
+            ChunkCipher::ChaCha20Poly1305(cipher) => cipher.into_tag(),
+        }
+    }
+
+    fn decrypt(&mut self, buffer: &mut [u8]) -> Tag {
+        match self {
+            ChunkCipher::AesGcm256(cipher) => cipher.decrypt(buffer),
+            ChunkCipher::ChaCha20Poly1305(cipher) => cipher.decrypt(buffer),
+        }
+    }
+
+    fn decrypt_unauthenticated(&mut self, buffer: &mut [u8]) {
+        match self {
+            ChunkCipher::AesGcm256(cipher) => cipher.decrypt_unauthenticated(buffer),
+            ChunkCipher::ChaCha20Poly1305(cipher) => cipher.decrypt_unauthenticated(buffer),
+        }
+    }
+}
+
 // ---------- Config ----------
 
 /// Configuration stored in the header, to be reloaded
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct EncryptionPersistentConfig {
     pub multi_recipient: MultiRecipientPersistent,
     nonce: [u8; NONCE_SIZE],
+    /// Key wrapped with a password, if any
+    pub password: Option<PasswordPersistent>,
+    /// If set, recipients of `multi_recipient` only get a share of the key,
+    /// and this number of shares is required to recover it
+    pub threshold: Option<u8>,
+    /// Key shared with escrow recipients, if any
+    pub escrow: Option<EscrowPersistent>,
+    /// Key shared with hybrid (X25519 + ML-KEM) recipients, if any
+    pub hybrid: Option<HybridPersistent>,
+    /// Cipher used to encrypt the data
+    pub cipher: Cipher,
+    /// If set, a new key is derived for each group of this number of chunks
+    pub rekey_interval: Option<u32>,
+    /// Hints of the recipient public keys (see `key_hint`), escrow and hybrid
+    /// ones included, if stored. Absent before format version 19
+    pub key_hints: Option<Vec<[u8; KEY_HINT_SIZE]>>,
+}
+
+/// Configuration stored in the header of format version 7 to 18 archives,
+/// before recipient key hints
+#[derive(Serialize, Deserialize)]
+pub(crate) struct EncryptionPersistentConfigV18 {
+    multi_recipient: MultiRecipientPersistent,
+    nonce: [u8; NONCE_SIZE],
+    password: Option<PasswordPersistent>,
+    threshold: Option<u8>,
+    escrow: Option<EscrowPersistent>,
+    hybrid: Option<HybridPersistent>,
+    cipher: Cipher,
+    rekey_interval: Option<u32>,
+}
+
+impl From<EncryptionPersistentConfigV18> for EncryptionPersistentConfig {
+    fn from(config: EncryptionPersistentConfigV18) -> Self {
+        EncryptionPersistentConfig {
+            multi_recipient: config.multi_recipient,
+            nonce: config.nonce,
+            password: config.password,
+            threshold: config.threshold,
+            escrow: config.escrow,
+            hybrid: config.hybrid,
+            cipher: config.cipher,
+            rekey_interval: config.rekey_interval,
+            key_hints: None,
+        }
+    }
+}
+
+impl From<&EncryptionPersistentConfig> for EncryptionPersistentConfigV18 {
+    /// The key hints are dropped
+    fn from(config: &EncryptionPersistentConfig) -> Self {
+        EncryptionPersistentConfigV18 {
+            multi_recipient: config.multi_recipient.clone(),
+            nonce: config.nonce,
+            password: config.password.clone(),
+            threshold: config.threshold,
+            escrow: config.escrow.clone(),
+            hybrid: config.hybrid.clone(),
+            cipher: config.cipher,
+            rekey_interval: config.rekey_interval,
+        }
+    }
+}
+
+/// Key shared with escrow (recovery) recipients, always able to decrypt the
+/// archive
+#[derive(Serialize, Deserialize, Clone)]
+pub struct EscrowPersistent {
+    /// Escrow public keys, kept for audit
+    pub public_keys: Vec<[u8; KEY_SIZE]>,
+    multi_recipient: MultiRecipientPersistent,
+}
+
+/// Configuration stored in the header of format version 1 archives, before
+/// password support
+#[derive(Deserialize)]
+pub(crate) struct EncryptionPersistentConfigV1 {
+    multi_recipient: MultiRecipientPersistent,
+    nonce: [u8; NONCE_SIZE],
+}
+
+impl From<EncryptionPersistentConfigV1> for EncryptionPersistentConfig {
+    fn from(config: EncryptionPersistentConfigV1) -> Self {
+        EncryptionPersistentConfig {
+            multi_recipient: config.multi_recipient,
+            nonce: config.nonce,
+            password: None,
+            threshold: None,
+            escrow: None,
+            hybrid: None,
+            cipher: Cipher::AesGcm256,
+            rekey_interval: None,
+            key_hints: None,
+        }
+    }
+}
+
+/// Configuration stored in the header of format version 2 archives, before
+/// threshold support
+#[derive(Deserialize)]
+pub(crate) struct EncryptionPersistentConfigV2 {
+    multi_recipient: MultiRecipientPersistent,
+    nonce: [u8; NONCE_SIZE],
+    password: Option<PasswordPersistent>,
+}
+
+impl From<EncryptionPersistentConfigV2> for EncryptionPersistentConfig {
+    fn from(config: EncryptionPersistentConfigV2) -> Self {
+        EncryptionPersistentConfig {
+            multi_recipient: config.multi_recipient,
+            nonce: config.nonce,
+            password: config.password,
+            threshold: None,
+            escrow: None,
+            hybrid: None,
+            cipher: Cipher::AesGcm256,
+            rekey_interval: None,
+            key_hints: None,
+        }
+    }
+}
+
+/// Configuration stored in the header of format version 3 archives, before
+/// escrow support
+#[derive(Deserialize)]
+pub(crate) struct EncryptionPersistentConfigV3 {
+    multi_recipient: MultiRecipientPersistent,
+    nonce: [u8; NONCE_SIZE],
+    password: Option<PasswordPersistent>,
+    threshold: Option<u8>,
+}
+
+impl From<EncryptionPersistentConfigV3> for EncryptionPersistentConfig {
+    fn from(config: EncryptionPersistentConfigV3) -> Self {
+        EncryptionPersistentConfig {
+            multi_recipient: config.multi_recipient,
+            nonce: config.nonce,
+            password: config.password,
+            threshold: config.threshold,
+            escrow: None,
+            hybrid: None,
+            cipher: Cipher::AesGcm256,
+            rekey_interval: None,
+            key_hints: None,
+        }
+    }
+}
+
+/// Configuration stored in the header of format version 4 archives, before
+/// hybrid recipients support
+#[derive(Deserialize)]
+pub(crate) struct EncryptionPersistentConfigV4 {
+    multi_recipient: MultiRecipientPersistent,
+    nonce: [u8; NONCE_SIZE],
+    password: Option<PasswordPersistent>,
+    threshold: Option<u8>,
+    escrow: Option<EscrowPersistent>,
+}
+
+/// Configuration stored in the header of format version 6 archives, before
+/// rekeying support
+#[derive(Deserialize)]
+pub(crate) struct EncryptionPersistentConfigV6 {
+    multi_recipient: MultiRecipientPersistent,
+    nonce: [u8; NONCE_SIZE],
+    password: Option<PasswordPersistent>,
+    threshold: Option<u8>,
+    escrow: Option<EscrowPersistent>,
+    hybrid: Option<HybridPersistent>,
+    cipher: Cipher,
+}
+
+impl From<EncryptionPersistentConfigV6> for EncryptionPersistentConfig {
+    fn from(config: EncryptionPersistentConfigV6) -> Self {
+        EncryptionPersistentConfig {
+            multi_recipient: config.multi_recipient,
+            nonce: config.nonce,
+            password: config.password,
+            threshold: config.threshold,
+            escrow: config.escrow,
+            hybrid: config.hybrid,
+            cipher: config.cipher,
+            rekey_interval: None,
+            key_hints: None,
+        }
+    }
+}
+
+/// Configuration stored in the header of format version 5 archives, before
+/// cipher agility
+#[derive(Deserialize)]
+pub(crate) struct EncryptionPersistentConfigV5 {
+    multi_recipient: MultiRecipientPersistent,
+    nonce: [u8; NONCE_SIZE],
+    password: Option<PasswordPersistent>,
+    threshold: Option<u8>,
+    escrow: Option<EscrowPersistent>,
+    hybrid: Option<HybridPersistent>,
+}
+
+impl From<EncryptionPersistentConfigV5> for EncryptionPersistentConfig {
+    fn from(config: EncryptionPersistentConfigV5) -> Self {
+        EncryptionPersistentConfig {
+            multi_recipient: config.multi_recipient,
+            nonce: config.nonce,
+            password: config.password,
+            threshold: config.threshold,
+            escrow: config.escrow,
+            hybrid: config.hybrid,
+            cipher: Cipher::AesGcm256,
+            rekey_interval: None,
+            key_hints: None,
+        }
+    }
+}
+
+impl From<EncryptionPersistentConfigV4> for EncryptionPersistentConfig {
+    fn from(config: EncryptionPersistentConfigV4) -> Self {
+        EncryptionPersistentConfig {
+            multi_recipient: config.multi_recipient,
+            nonce: config.nonce,
+            password: config.password,
+            threshold: config.threshold,
+            escrow: config.escrow,
+            hybrid: None,
+            cipher: Cipher::AesGcm256,
+            rekey_interval: None,
+            key_hints: None,
+        }
+    }
 }
 
 pub struct EncryptionConfig {
     /// Public keys with which to encrypt the symmetric encryption key below
     ecc_keys: Vec<PublicKey>,
+    /// Escrow public keys, always getting the symmetric encryption key below
+    escrow_keys: Vec<PublicKey>,
+    /// Hybrid public keys with which to encrypt the symmetric encryption key
+    /// below
+    hybrid_keys: Vec<HybridPublicKey>,
+    /// Password with which to encrypt the symmetric encryption key below
+    password: Option<Vec<u8>>,
+    /// Number of `ecc_keys` recipients required to recover the symmetric
+    /// encryption key below, if set
+    threshold: Option<u8>,
+    /// Cipher used to encrypt the data
+    cipher: Cipher,
+    /// Number of chunks encrypted with the same derived key, if set
+    rekey_interval: Option<u32>,
+    /// If set, nothing identifying the recipients is stored in the header
+    anonymous: bool,
+    /// If set, hints of the recipient public keys are stored in the header
+    recipient_hints: bool,
     /// Symmetric encryption Key
     key: [u8; KEY_SIZE],
     /// Symmetric encryption nonce
     nonce: [u8; NONCE_SIZE],
+    /// If set, the randomness is derived from this seed instead of the system
+    /// entropy, see `ArchiveWriterConfig::set_deterministic_seed`
+    seed: Option<[u8; SEED_SIZE]>,
 }
 
 impl std::default::Default for EncryptionConfig {
@@ -82,8 +460,17 @@ impl std::default::Default for EncryptionConfig {
         let nonce = csprng.gen::<[u8; NONCE_SIZE]>();
         EncryptionConfig {
             ecc_keys: Vec::new(),
+            escrow_keys: Vec::new(),
+            hybrid_keys: Vec::new(),
+            password: None,
+            threshold: None,
+            cipher: Cipher::default(),
+            rekey_interval: None,
+            anonymous: false,
+            recipient_hints: false,
             key,
             nonce,
+            seed: None,
         }
     }
 }
@@ -91,26 +478,143 @@ impl std::default::Default for EncryptionConfig {
 impl EncryptionConfig {
     /// Consistency check
     pub fn check(&self) -> Result<(), ConfigError> {
-        if self.ecc_keys.is_empty() {
-            Err(ConfigError::EncryptionKeyIsMissing)
-        } else {
-            Ok(())
+        if self.ecc_keys.is_empty() && self.hybrid_keys.is_empty() && self.password.is_none() {
+            return Err(ConfigError::EncryptionKeyIsMissing);
         }
+        if let Some(threshold) = self.threshold {
+            // A password, or a hybrid recipient, would bypass the threshold
+            if threshold == 0
+                || threshold as usize > self.ecc_keys.len()
+                || self.ecc_keys.len() > shamir::MAX_SHARES
+                || self.password.is_some()
+                || !self.hybrid_keys.is_empty()
+            {
+                return Err(ConfigError::InvalidThreshold);
+            }
+        }
+        if self.rekey_interval == Some(0) {
+            return Err(ConfigError::InvalidRekeyInterval);
+        }
+        if self.anonymous && self.recipient_hints {
+            return Err(ConfigError::AnonymousRecipientHints);
+        }
+        Ok(())
+    }
+
+    /// Derive the symmetric encryption key and nonce, and the randomness used
+    /// to store them for each recipient, from `seed`
+    pub(crate) fn set_seed(&mut self, seed: [u8; SEED_SIZE]) {
+        let mut csprng = seeded_rng(seed, KEY_STREAM);
+        self.key = csprng.gen::<[u8; KEY_SIZE]>();
+        self.nonce = csprng.gen::<[u8; NONCE_SIZE]>();
+        self.seed = Some(seed);
     }
 
     pub fn to_persistent(&self) -> Result<EncryptionPersistentConfig, ConfigError> {
-        let mut rng = ChaChaRng::from_entropy();
-        if let Ok(multi_recipient) =
-            store_key_for_multi_recipients(&self.ecc_keys, &self.key, &mut rng)
-        {
+        let mut rng = match self.seed {
+            Some(seed) => seeded_rng(seed, RECIPIENTS_STREAM),
+            None => ChaChaRng::from_entropy(),
+        };
+        // Without identifying material, key slots are in a random order, so
+        // that their position does not tell the recipients apart either
+        let mut ecc_keys = self.ecc_keys.clone();
+        let mut escrow_keys = self.escrow_keys.clone();
+        let mut hybrid_keys = self.hybrid_keys.clone();
+        if self.anonymous {
+            ecc_keys.shuffle(&mut rng);
+            escrow_keys.shuffle(&mut rng);
+            hybrid_keys.shuffle(&mut rng);
+        }
+        let password = match &self.password {
+            Some(password) => match store_key_for_password(password, &self.key, &mut rng) {
+                Ok(password) => Some(password),
+                Err(_) => return Err(ConfigError::ECIESComputationError),
+            },
+            None => None,
+        };
+        let multi_recipient = match self.threshold {
+            Some(threshold) => {
+                // Each recipient only gets a share of the key
+                self.check()?;
+                let shares = shamir::split(&self.key, threshold as usize, ecc_keys.len(), &mut rng);
+                store_keys_for_multi_recipients(&ecc_keys, &shares, &mut rng)
+            }
+            None => store_key_for_multi_recipients(&ecc_keys, &self.key, &mut rng),
+        };
+        let escrow = if escrow_keys.is_empty() {
+            None
+        } else {
+            // Escrow recipients always get the whole key, even with a threshold
+            match store_key_for_multi_recipients(&escrow_keys, &self.key, &mut rng) {
+                Ok(multi_recipient) => Some(EscrowPersistent {
+                    // Escrow public keys are only recorded for audit
+                    public_keys: if self.anonymous {
+                        Vec::new()
+                    } else {
+                        escrow_keys.iter().map(|key| *key.as_bytes()).collect()
+                    },
+                    multi_recipient,
+                }),
+                Err(_) => return Err(ConfigError::ECIESComputationError),
+            }
+        };
+        let key_hints = if self.recipient_hints {
+            let mut key_hints: Vec<[u8; KEY_HINT_SIZE]> = ecc_keys
+                .iter()
+                .chain(escrow_keys.iter())
+                .map(|key| key_hint(key.as_bytes()))
+                .chain(hybrid_keys.iter().map(|key| key_hint(&key.to_bytes())))
+                .collect();
+            // Hints are not in the key slots order
+            key_hints.sort_unstable();
+            Some(key_hints)
+        } else {
+            None
+        };
+        let hybrid = if hybrid_keys.is_empty() {
+            None
+        } else {
+            match store_key_for_hybrid_recipients(&hybrid_keys, &self.key, &mut rng) {
+                Ok(hybrid) => Some(hybrid),
+                Err(_) => return Err(ConfigError::ECIESComputationError),
+            }
+        };
+        if let Ok(multi_recipient) = multi_recipient {
             Ok(EncryptionPersistentConfig {
                 multi_recipient,
                 nonce: self.nonce,
+                password,
+                threshold: self.threshold,
+                escrow,
+                hybrid,
+                cipher: self.cipher,
+                rekey_interval: self.rekey_interval,
+                key_hints,
             })
         } else {
             Err(ConfigError::ECIESComputationError)
         }
     }
+
+    /// Use `key`, already shared with the recipients of `persistent`, instead
+    /// of the generated one, and return the corresponding persistent version
+    ///
+    /// The cipher and rekeying schedule of `persistent` are used, but the
+    /// generated nonce is kept, so the same key is never used with an already
+    /// used nonce
+    pub(crate) fn reuse_key(
+        &mut self,
+        key: [u8; KEY_SIZE],
+        persistent: EncryptionPersistentConfig,
+    ) -> EncryptionPersistentConfig {
+        self.key = key;
+        self.cipher = persistent.cipher;
+        self.rekey_interval = persistent.rekey_interval;
+        EncryptionPersistentConfig {
+            nonce: self.nonce,
+            ..persistent
+        }
+    }
 }
 
 impl ArchiveWriterConfig {
@@ -120,6 +624,81 @@ impl ArchiveWriterConfig {
         self
     }
 
+    /// Set escrow public keys, which are always able to decrypt the archive, in
+    /// addition to the other recipients (and regardless of the threshold)
+    ///
+    /// Escrow public keys are recorded in the header, for audit
+    pub fn add_escrow_keys(&mut self, keys: &[PublicKey]) -> &mut ArchiveWriterConfig {
+        self.encrypt.escrow_keys.extend_from_slice(keys);
+        self
+    }
+
+    /// Set hybrid public keys to use, combining X25519 and the post-quantum
+    /// ML-KEM-768
+    ///
+    /// The archive stays confidential as long as one of the two algorithms is
+    /// not broken
+    pub fn add_hybrid_public_keys(&mut self, keys: &[HybridPublicKey]) -> &mut ArchiveWriterConfig {
+        self.encrypt.hybrid_keys.extend_from_slice(keys);
+        self
+    }
+
+    /// Set a password from which a key, able to decrypt the archive, is
+    /// derived with Argon2id
+    ///
+    /// It can be used alongside, or instead of, public keys
+    pub fn set_password(&mut self, password: &[u8]) -> &mut ArchiveWriterConfig {
+        self.encrypt.password = Some(password.to_vec());
+        self
+    }
+
+    /// Require the private keys of `threshold` recipients, among the public
+    /// keys set, to decrypt the archive (Shamir's secret sharing of the key)
+    ///
+    /// It can't be used alongside a password, which would bypass it
+    pub fn set_threshold(&mut self, threshold: u8) -> &mut ArchiveWriterConfig {
+        self.encrypt.threshold = Some(threshold);
+        self
+    }
+
+    /// Store nothing identifying the recipients in the header: escrow public
+    /// keys are not recorded, and key slots are shuffled
+    ///
+    /// Having the archive then does not tell which keys can open it. Readers
+    /// try their private keys against every key slot, as usual
+    pub fn set_anonymous_recipients(&mut self, anonymous: bool) -> &mut ArchiveWriterConfig {
+        self.encrypt.anonymous = anonymous;
+        self
+    }
+
+    /// Store a short hint of each recipient public key in the header (see
+    /// `key_hint`)
+    ///
+    /// Readers with many private keys then only try the ones matching a hint,
+    /// and can tell when the archive is not encrypted to any of them
+    /// (`ConfigError::NoMatchingRecipientKey`). It can't be used with
+    /// anonymous recipients
+    pub fn set_recipient_hints(&mut self, hints: bool) -> &mut ArchiveWriterConfig {
+        self.encrypt.recipient_hints = hints;
+        self
+    }
+
+    /// Set the cipher used to encrypt the data (AES-256-GCM by default)
+    pub fn set_cipher(&mut self, cipher: Cipher) -> &mut ArchiveWriterConfig {
+        self.encrypt.cipher = cipher;
+        self
+    }
+
+    /// Derive a new key every `size` bytes of data (rounded up to the 128 KiB
+    /// encryption chunks), to bound the amount of data encrypted with one key
+    ///
+    /// Keys are derived from the archive key, so recipients are unchanged
+    pub fn set_rekey_interval(&mut self, size: u64) -> &mut ArchiveWriterConfig {
+        let chunks = size.div_ceil(CHUNK_SIZE);
+        self.encrypt.rekey_interval = Some(std::cmp::min(chunks, u32::MAX as u64) as u32);
+        self
+    }
+
     /// Return the key used for encryption
     pub fn encryption_key(&self) -> &[u8; KEY_SIZE] {
         &self.encrypt.key
@@ -131,18 +710,31 @@ impl ArchiveWriterConfig {
     }
 }
 
+#[derive(Clone)]
 pub struct EncryptionReaderConfig {
     /// Private key(s) to use
     private_keys: Vec<StaticSecret>,
+    /// Hybrid private key(s) to use
+    hybrid_private_keys: Vec<HybridPrivateKey>,
+    /// Password to use
+    password: Option<Vec<u8>>,
     /// Symmetric encryption key and nonce, if decrypted successfully from header
     encrypt_parameters: Option<([u8; KEY_SIZE], [u8; NONCE_SIZE])>,
+    /// Cipher used to encrypt the data, from header
+    cipher: Cipher,
+    /// Rekeying schedule, from header
+    rekey_interval: Option<u32>,
 }
 
 impl std::default::Default for EncryptionReaderConfig {
     fn default() -> Self {
         Self {
             private_keys: Vec::new(),
+            hybrid_private_keys: Vec::new(),
+            password: None,
             encrypt_parameters: None,
+            cipher: Cipher::default(),
+            rekey_interval: None,
         }
     }
 }
@@ -152,19 +744,84 @@ impl EncryptionReaderConfig {
         &mut self,
         config: EncryptionPersistentConfig,
     ) -> Result<(), ConfigError> {
-        if self.private_keys.is_empty() {
+        if self.private_keys.is_empty()
+            && self.hybrid_private_keys.is_empty()
+            && self.password.is_none()
+        {
             return Err(ConfigError::PrivateKeyNotSet);
         }
-        for private_key in &self.private_keys {
-            match retrieve_key(&config.multi_recipient, private_key) {
-                Ok(Some(key)) => {
+        self.cipher = config.cipher;
+        self.rekey_interval = config.rekey_interval;
+
+        // With key hints, only the matching private keys are tried
+        let mut private_keys: Vec<&StaticSecret> = self.private_keys.iter().collect();
+        let mut hybrid_private_keys: Vec<&HybridPrivateKey> =
+            self.hybrid_private_keys.iter().collect();
+        if let Some(key_hints) = &config.key_hints {
+            private_keys
+                .retain(|key| key_hints.contains(&key_hint(PublicKey::from(*key).as_bytes())));
+            hybrid_private_keys
+                .retain(|key| key_hints.contains(&key_hint(&key.public_key().to_bytes())));
+            let password = self.password.is_some() && config.password.is_some();
+            if private_keys.is_empty() && hybrid_private_keys.is_empty() && !password {
+                return Err(ConfigError::NoMatchingRecipientKey);
+            }
+        }
+
+        if let Some(threshold) = config.threshold {
+            // Gather shares from distinct recipients
+            let mut shares: Vec<(usize, [u8; KEY_SIZE])> = Vec::new();
+            for private_key in &private_keys {
+                if let Ok(Some((index, share))) =
+                    retrieve_key_with_index(&config.multi_recipient, private_key)
+                {
+                    if !shares.iter().any(|(known, _)| *known == index) {
+                        shares.push((index, share));
+                    }
+                }
+            }
+            if threshold > 0 && shares.len() >= threshold as usize {
+                let key = shamir::combine(&shares[..threshold as usize]);
+                self.encrypt_parameters = Some((key, config.nonce));
+            }
+        } else {
+            for private_key in &private_keys {
+                match retrieve_key(&config.multi_recipient, private_key) {
+                    Ok(Some(key)) => {
+                        self.encrypt_parameters = Some((key, config.nonce));
+                        break;
+                    }
+                    _ => {
+                        continue;
+                    }
+                };
+            }
+        }
+
+        if let (None, Some(hybrid)) = (self.encrypt_parameters, &config.hybrid) {
+            for private_key in &hybrid_private_keys {
+                if let Ok(Some(key)) = retrieve_key_hybrid(hybrid, private_key) {
                     self.encrypt_parameters = Some((key, config.nonce));
                     break;
                 }
-                _ => {
-                    continue;
+            }
+        }
+
+        if let (None, Some(escrow)) = (self.encrypt_parameters, &config.escrow) {
+            for private_key in &private_keys {
+                if let Ok(Some(key)) = retrieve_key(&escrow.multi_recipient, private_key) {
+                    self.encrypt_parameters = Some((key, config.nonce));
+                    break;
                 }
-            };
+            }
+        }
+
+        if let (None, Some(password), Some(password_persistent)) =
+            (self.encrypt_parameters, &self.password, &config.password)
+        {
+            if let Ok(Some(key)) = retrieve_key_with_password(password_persistent, password) {
+                self.encrypt_parameters = Some((key, config.nonce));
+            }
         }
 
         if self.encrypt_parameters.is_none() {
@@ -181,17 +838,40 @@ impl ArchiveReaderConfig {
         self
     }
 
+    /// Set hybrid private keys to use
+    pub fn add_hybrid_private_keys(
+        &mut self,
+        keys: &[HybridPrivateKey],
+    ) -> &mut ArchiveReaderConfig {
+        self.encrypt.hybrid_private_keys.extend_from_slice(keys);
+        self
+    }
+
+    /// Set the password to use, for archives protected by a password
+    pub fn set_password(&mut self, password: &[u8]) -> &mut ArchiveReaderConfig {
+        self.encrypt.password = Some(password.to_vec());
+        self
+    }
+
     /// Retrieve key and nonce used for encryption
     pub fn get_encrypt_parameters(&self) -> Option<([u8; KEY_SIZE], [u8; NONCE_SIZE])> {
         self.encrypt.encrypt_parameters
     }
+
+    /// Retrieve the cipher used for encryption
+    pub fn get_cipher(&self) -> Cipher {
+        self.encrypt.cipher
+    }
 }
 
 // ---------- Writer ----------
 
 pub struct EncryptionLayerWriter<'a, W: 'a + Write> {
     inner: Box<dyn 'a + LayerWriter<'a, W>>,
-    cipher: AesGcm256,
+    cipher: ChunkCipher,
+    /// Cipher algorithm and rekeying schedule, to renew `cipher`
+    cipher_kind: Cipher,
+    rekey_interval: Option<u32>,
     /// Symmetric encryption Key
     key: [u8; KEY_SIZE],
     /// Symmetric encryption nonce prefix, see `build_nonce`
@@ -209,7 +889,15 @@ impl<'a, W: 'a + Write> EncryptionLayerWriter<'a, W> {
             inner,
             key: config.key,
             nonce_prefix: config.nonce,
-            cipher: AesGcm256::new(&config.key, &build_nonce(config.nonce, 0), b"")?,
+            cipher: ChunkCipher::new(
+                config.cipher,
+                &config.key,
+                config.rekey_interval,
+                config.nonce,
+                0,
+            )?,
+            cipher_kind: config.cipher,
+            rekey_interval: config.rekey_interval,
             current_chunk_offset: 0,
             current_ctr: 0,
         })
@@ -219,10 +907,12 @@ impl<'a, W: 'a + Write> EncryptionLayerWriter<'a, W> {
         // Prepare a new cipher
         self.current_ctr += 1;
         self.current_chunk_offset = 0;
-        let cipher = AesGcm256::new(
+        let cipher = ChunkCipher::new(
+            self.cipher_kind,
             &self.key,
-            &build_nonce(self.nonce_prefix, self.current_ctr),
-            b"",
+            self.rekey_interval,
+            self.nonce_prefix,
+            self.current_ctr,
         )?;
         let old_cipher = std::mem::replace(&mut self.cipher, cipher);
         Ok(old_cipher.into_tag())
@@ -249,8 +939,14 @@ impl<'a, W: 'a + Write> LayerWriter<'a, W> for EncryptionLayerWriter<'a, W> {
 }
 
 impl<'a, W: Write> Write for EncryptionLayerWriter<'a, W> {
-    #[allow(clippy::comparison_chain)]
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_vectored(&[IoSlice::new(buf)])
+    }
+
+    /// Slices are gathered, up to the end of the current chunk, to be
+    /// encrypted with a single cipher call and written at once
+    #[allow(clippy::comparison_chain)]
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
         if self.current_chunk_offset > CHUNK_SIZE {
             // Should never happen
             return Err(
@@ -264,13 +960,19 @@ impl<'a, W: Write> Write for EncryptionLayerWriter<'a, W> {
         }
 
         // StreamingCipher is working in place, so we use a temporary buffer
+        let total: u64 = bufs.iter().map(|buf| buf.len() as u64).sum();
         let size = std::cmp::min(
-            std::cmp::min(CIPHER_BUF_SIZE, buf.len() as u64),
+            std::cmp::min(CIPHER_BUF_SIZE, total),
             CHUNK_SIZE - self.current_chunk_offset,
         );
         let mut buf_tmp = Vec::with_capacity(size as usize);
-        let buf_src = BufReader::new(buf);
-        io::copy(&mut buf_src.take(size), &mut buf_tmp)?;
+        for buf in bufs {
+            let count = std::cmp::min(size as usize - buf_tmp.len(), buf.len());
+            buf_tmp.extend_from_slice(&buf[..count]);
+            if buf_tmp.len() == size as usize {
+                break;
+            }
+        }
         self.cipher.encrypt(&mut buf_tmp);
         self.inner.write_all(&buf_tmp)?;
         self.current_chunk_offset += size;
@@ -288,7 +990,9 @@ impl<'a, W: Write> Write for EncryptionLayerWriter<'a, W> {
 // keep the struct separated for any possible future difference
 pub struct EncryptionLayerReader<'a, R: Read + Seek> {
     inner: Box<dyn 'a + LayerReader<'a, R>>,
-    cipher: AesGcm256,
+    cipher: ChunkCipher,
+    cipher_kind: Cipher,
+    rekey_interval: Option<u32>,
     key: [u8; KEY_SIZE],
     nonce: [u8; NONCE_SIZE],
     chunk_cache: Cursor<Vec<u8>>,
@@ -303,7 +1007,9 @@ impl<'a, R: 'a + Read + Seek> EncryptionLayerReader<'a, R> {
         match config.encrypt_parameters {
             Some((key, nonce)) => Ok(Self {
                 inner,
-                cipher: AesGcm256::new(&key, &build_nonce(nonce, 0), b"")?,
+                cipher: ChunkCipher::new(config.cipher, &key, config.rekey_interval, nonce, 0)?,
+                cipher_kind: config.cipher,
+                rekey_interval: config.rekey_interval,
                 key,
                 nonce,
                 chunk_cache: Cursor::new(Vec::with_capacity(CHUNK_SIZE as usize)),
@@ -316,10 +1022,12 @@ impl<'a, R: 'a + Read + Seek> EncryptionLayerReader<'a, R> {
     /// Load the `self.current_chunk_number` chunk in cache
     /// Assume the inner layer is in the correct position
     fn load_in_cache(&mut self) -> Result<Option<()>, Error> {
-        self.cipher = AesGcm256::new(
+        self.cipher = ChunkCipher::new(
+            self.cipher_kind,
             &self.key,
-            &build_nonce(self.nonce, self.current_chunk_number),
-            b"",
+            self.rekey_interval,
+            self.nonce,
+            self.current_chunk_number,
         )?;
 
         // Clear current, now useless, allocated memory
@@ -456,12 +1164,12 @@ impl<'a, R: 'a + Read + Seek> Seek for EncryptionLayerReader<'a, R> {
                 }
 
                 // The last chunk always have a TAG at its end, and might not be
-                // CHUNK_SIZE long -> we need to remove the TAG size while
-                // converting from tag-aware position to tag-unaware position
+                // CHUNK_SIZE long -> we need to remove the TAG size of every
+                // chunk, the last one included (even if it is CHUNK_SIZE long),
+                // while converting from tag-aware position to tag-unaware position
                 let end_inner_pos = self.inner.seek(SeekFrom::End(0))?;
-                let cur_chunk = end_inner_pos / CHUNK_TAG_SIZE;
-                let cur_chunk_pos = end_inner_pos % CHUNK_TAG_SIZE;
-                let end_pos = cur_chunk * CHUNK_SIZE + cur_chunk_pos - TAG_LENGTH as u64;
+                let chunks_count = end_inner_pos.div_ceil(CHUNK_TAG_SIZE);
+                let end_pos = end_inner_pos - chunks_count * TAG_LENGTH as u64;
                 self.seek(SeekFrom::Start((pos + end_pos as i64) as u64))
             }
         }
@@ -472,7 +1180,9 @@ impl<'a, R: 'a + Read + Seek> Seek for EncryptionLayerReader<'a, R> {
 
 pub struct EncryptionLayerFailSafeReader<'a, R: Read> {
     inner: Box<dyn 'a + LayerFailSafeReader<'a, R>>,
-    cipher: AesGcm256,
+    cipher: ChunkCipher,
+    cipher_kind: Cipher,
+    rekey_interval: Option<u32>,
     key: [u8; KEY_SIZE],
     nonce: [u8; NONCE_SIZE],
     current_chunk_number: u32,
@@ -487,7 +1197,9 @@ impl<'a, R: 'a + Read> EncryptionLayerFailSafeReader<'a, R> {
         match config.encrypt_parameters {
             Some((key, nonce)) => Ok(Self {
                 inner,
-                cipher: AesGcm256::new(&key, &build_nonce(nonce, 0), b"")?,
+                cipher: ChunkCipher::new(config.cipher, &key, config.rekey_interval, nonce, 0)?,
+                cipher_kind: config.cipher,
+                rekey_interval: config.rekey_interval,
                 key,
                 nonce,
                 current_chunk_number: 0,
@@ -518,15 +1230,17 @@ impl<'a, R: Read> Read for EncryptionLayerFailSafeReader<'a, R> {
             )?;
             self.current_chunk_number += 1;
             self.current_chunk_offset = 0;
-            self.cipher = AesGcm256::new(
+            self.cipher = ChunkCipher::new(
+                self.cipher_kind,
                 &self.key,
-                &build_nonce(self.nonce, self.current_chunk_number),
-                b"",
+                self.rekey_interval,
+                self.nonce,
+                self.current_chunk_number,
             )?;
             return self.read(buf);
         }
 
-        // AesGcm256 is working in place, so we use a temporary buffer
+        // Ciphers are working in place, so we use a temporary buffer
         let mut buf_tmp = [0u8; CIPHER_BUF_SIZE as usize];
         let size = std::cmp::min(CIPHER_BUF_SIZE as usize, buf.len());
         // Read at most the chunk size, to detect when renewal is needed
@@ -539,6 +1253,97 @@ impl<'a, R: Read> Read for EncryptionLayerFailSafeReader<'a, R> {
     }
 }
 
+// ---------- Stream Reader ----------
+
+/// Sequential reader, as `EncryptionLayerFailSafeReader`, but strict: each
+/// chunk is read entirely and its tag checked before any of its content is
+/// returned. A wrong tag, or a truncated chunk, is an error
+pub struct EncryptionLayerStreamReader<'a, R: Read> {
+    inner: Box<dyn 'a + LayerFailSafeReader<'a, R>>,
+    cipher_kind: Cipher,
+    rekey_interval: Option<u32>,
+    key: [u8; KEY_SIZE],
+    nonce: [u8; NONCE_SIZE],
+    chunk_cache: Cursor<Vec<u8>>,
+    /// Number of the next chunk to load in cache
+    next_chunk_number: u32,
+}
+
+impl<'a, R: 'a + Read> EncryptionLayerStreamReader<'a, R> {
+    pub fn new(
+        inner: Box<dyn 'a + LayerFailSafeReader<'a, R>>,
+        config: &EncryptionReaderConfig,
+    ) -> Result<Self, Error> {
+        match config.encrypt_parameters {
+            Some((key, nonce)) => Ok(Self {
+                inner,
+                cipher_kind: config.cipher,
+                rekey_interval: config.rekey_interval,
+                key,
+                nonce,
+                chunk_cache: Cursor::new(Vec::new()),
+                next_chunk_number: 0,
+            }),
+            None => Err(Error::PrivateKeyNeeded),
+        }
+    }
+
+    /// Load the next chunk in cache, once authenticated. Returns `None` if the
+    /// inner layer is at the end of the stream
+    fn load_next_chunk(&mut self) -> Result<Option<()>, Error> {
+        let mut cipher = ChunkCipher::new(
+            self.cipher_kind,
+            &self.key,
+            self.rekey_interval,
+            self.nonce,
+            self.next_chunk_number,
+        )?;
+
+        let mut data = Vec::with_capacity(CHUNK_TAG_SIZE as usize);
+        let data_and_tag_read = (&mut self.inner)
+            .take(CHUNK_TAG_SIZE)
+            .read_to_end(&mut data)?;
+        if data_and_tag_read == 0 {
+            return Ok(None);
+        }
+        // The last chunk may be shorter, but always ends with its tag
+        if data_and_tag_read < TAG_LENGTH {
+            return Err(Error::AuthenticatedDecryptionWrongTag);
+        }
+        let tag = data.split_off(data_and_tag_read - TAG_LENGTH);
+
+        let expected_tag = cipher.decrypt(data.as_mut_slice());
+        if expected_tag.ct_eq(&tag).unwrap_u8() != 1 {
+            return Err(Error::AuthenticatedDecryptionWrongTag);
+        }
+        self.chunk_cache = Cursor::new(data);
+        self.next_chunk_number += 1;
+        Ok(Some(()))
+    }
+}
+
+impl<'a, R: 'a + Read> LayerFailSafeReader<'a, R> for EncryptionLayerStreamReader<'a, R> {
+    fn into_inner(self) -> Option<Box<dyn 'a + LayerFailSafeReader<'a, R>>> {
+        Some(self.inner)
+    }
+
+    fn into_raw(self: Box<Self>) -> R {
+        self.inner.into_raw()
+    }
+}
+
+impl<'a, R: 'a + Read> Read for EncryptionLayerStreamReader<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.chunk_cache.position() == self.chunk_cache.get_ref().len() as u64
+            && self.load_next_chunk()?.is_none()
+        {
+            // No more byte in the inner layer
+            return Ok(0);
+        }
+        self.chunk_cache.read(buf)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -561,8 +1366,17 @@ mod tests {
                 Box::new(RawLayerWriter::new(file)),
                 &EncryptionConfig {
                     ecc_keys: Vec::new(),
+                    escrow_keys: Vec::new(),
+                    hybrid_keys: Vec::new(),
+                    password: None,
+                    threshold: None,
+                    cipher: Cipher::default(),
+                    rekey_interval: None,
+                    anonymous: false,
+                    recipient_hints: false,
                     key: KEY,
                     nonce: NONCE,
+                    seed: None,
                 },
             )
             .unwrap(),
@@ -585,7 +1399,11 @@ mod tests {
         let buf = Cursor::new(out.as_slice());
         let config = EncryptionReaderConfig {
             private_keys: Vec::new(),
+            hybrid_private_keys: Vec::new(),
+            password: None,
             encrypt_parameters: Some((KEY, NONCE)),
+            cipher: Cipher::default(),
+            rekey_interval: None,
         };
         let mut encrypt_r =
             EncryptionLayerReader::new(Box::new(RawLayerReader::new(buf)), &config).unwrap();
@@ -602,7 +1420,11 @@ mod tests {
 
         let config = EncryptionReaderConfig {
             private_keys: Vec::new(),
+            hybrid_private_keys: Vec::new(),
+            password: None,
             encrypt_parameters: Some((KEY, NONCE)),
+            cipher: Cipher::default(),
+            rekey_interval: None,
         };
         let mut encrypt_r = EncryptionLayerFailSafeReader::new(
             Box::new(RawLayerFailSafeReader::new(out.as_slice())),
@@ -625,7 +1447,11 @@ mod tests {
 
         let config = EncryptionReaderConfig {
             private_keys: Vec::new(),
+            hybrid_private_keys: Vec::new(),
+            password: None,
             encrypt_parameters: Some((KEY, NONCE)),
+            cipher: Cipher::default(),
+            rekey_interval: None,
         };
         let mut encrypt_r = EncryptionLayerFailSafeReader::new(
             Box::new(RawLayerFailSafeReader::new(&out[..stop])),
@@ -638,6 +1464,43 @@ mod tests {
         assert_eq!(output.as_slice(), &FAKE_FILE[..stop]);
     }
 
+    #[test]
+    fn encrypt_stream_layer() {
+        let file = Vec::new();
+        let out = encrypt_write(file);
+
+        let config = EncryptionReaderConfig {
+            private_keys: Vec::new(),
+            hybrid_private_keys: Vec::new(),
+            password: None,
+            encrypt_parameters: Some((KEY, NONCE)),
+            cipher: Cipher::default(),
+            rekey_interval: None,
+        };
+        let mut encrypt_r = EncryptionLayerStreamReader::new(
+            Box::new(RawLayerFailSafeReader::new(out.as_slice())),
+            &config,
+        )
+        .unwrap();
+        let mut output = Vec::new();
+        encrypt_r.read_to_end(&mut output).unwrap();
+        // Unlike the fail-safe reader, the tag is not part of the output
+        assert_eq!(output, FAKE_FILE);
+
+        // Nothing is returned from an altered or a truncated chunk
+        let mut altered = out.clone();
+        altered[0] ^= 1;
+        for src in [altered.as_slice(), &out[..out.len() / 2]] {
+            let mut encrypt_r = EncryptionLayerStreamReader::new(
+                Box::new(RawLayerFailSafeReader::new(src)),
+                &config,
+            )
+            .unwrap();
+            let mut buf = [0u8; 1];
+            assert!(encrypt_r.read(&mut buf).is_err());
+        }
+    }
+
     #[test]
     fn seek_encrypt() {
         // First, encrypt a dummy file
@@ -648,7 +1511,11 @@ mod tests {
         let buf = Cursor::new(out.as_slice());
         let config = EncryptionReaderConfig {
             private_keys: Vec::new(),
+            hybrid_private_keys: Vec::new(),
+            password: None,
             encrypt_parameters: Some((KEY, NONCE)),
+            cipher: Cipher::default(),
+            rekey_interval: None,
         };
         let mut encrypt_r =
             EncryptionLayerReader::new(Box::new(RawLayerReader::new(buf)), &config).unwrap();
@@ -681,8 +1548,17 @@ mod tests {
                 Box::new(RawLayerWriter::new(file)),
                 &EncryptionConfig {
                     ecc_keys: Vec::new(),
+                    escrow_keys: Vec::new(),
+                    hybrid_keys: Vec::new(),
+                    password: None,
+                    threshold: None,
+                    cipher: Cipher::default(),
+                    rekey_interval: None,
+                    anonymous: false,
+                    recipient_hints: false,
                     key: KEY,
                     nonce: NONCE,
+                    seed: None,
                 },
             )
             .unwrap(),
@@ -705,7 +1581,11 @@ mod tests {
         let buf = Cursor::new(out.as_slice());
         let config = EncryptionReaderConfig {
             private_keys: Vec::new(),
+            hybrid_private_keys: Vec::new(),
+            password: None,
             encrypt_parameters: Some((KEY, NONCE)),
+            cipher: Cipher::default(),
+            rekey_interval: None,
         };
         let mut encrypt_r =
             EncryptionLayerReader::new(Box::new(RawLayerReader::new(buf)), &config).unwrap();
@@ -720,5 +1600,26 @@ mod tests {
         let mut output = Vec::new();
         encrypt_r.read_to_end(&mut output).unwrap();
         assert_eq!(output.as_slice(), &data[CHUNK_SIZE as usize..]);
+
+        // The end is right after the last full chunk
+        let pos = encrypt_r.seek(SeekFrom::End(0)).unwrap();
+        assert_eq!(pos, length as u64);
+    }
+
+    #[test]
+    fn rekey_schedule() {
+        // Without schedule, the archive key is used for every chunk
+        assert_eq!(chunk_key(&KEY, None, 5).unwrap(), KEY);
+
+        // With a schedule, keys change every `rekey_interval` chunks
+        let epoch0 = chunk_key(&KEY, Some(2), 0).unwrap();
+        assert_ne!(epoch0, KEY);
+        assert_eq!(chunk_key(&KEY, Some(2), 1).unwrap(), epoch0);
+        let epoch1 = chunk_key(&KEY, Some(2), 2).unwrap();
+        assert_ne!(epoch1, epoch0);
+        assert_eq!(chunk_key(&KEY, Some(2), 3).unwrap(), epoch1);
+
+        // An invalid schedule is refused
+        assert!(chunk_key(&KEY, Some(0), 0).is_err());
     }
 }
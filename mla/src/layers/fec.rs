@@ -0,0 +1,762 @@
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use crc32fast::hash as crc32;
+use reed_solomon_erasure::galois_8::ReedSolomon;
+use std::io;
+use std::io::{IoSlice, Read, Seek, SeekFrom, Write};
+
+use crate::config::{ArchiveWriterConfig, ConfigResult};
+use crate::errors::ConfigError;
+use crate::layers::traits::{LayerFailSafeReader, LayerReader, LayerWriter};
+use crate::Error;
+
+// ---------- Config ----------
+
+/// Size of the data carried by each shard
+const SHARD_SIZE: u32 = 4096;
+/// Size of the CRC32 following each shard, used to spot the damaged ones
+const CHECKSUM_SIZE: usize = 4;
+/// Number of copies of the layer header and footer, each with its own
+/// checksum. They are small, but the whole layer depends on them
+const COPIES: usize = 3;
+/// Size of a header copy: data shards and parity shards counts, shard size,
+/// checksum
+const HEADER_SIZE: usize = 1 + 1 + 4 + CHECKSUM_SIZE;
+/// Size of a footer copy: data size, checksum
+const FOOTER_SIZE: usize = 8 + CHECKSUM_SIZE;
+
+/// By default, groups of 32 data shards are protected by 4 parity shards, for
+/// a 12.5% overhead. Up to 4 damaged shards, such as a 16KiB burst, can then
+/// be rebuilt in each group
+const DEFAULT_DATA_SHARDS: u8 = 32;
+const DEFAULT_PARITY_SHARDS: u8 = 4;
+
+pub struct FecConfig {
+    data_shards: u8,
+    parity_shards: u8,
+}
+
+impl std::default::Default for FecConfig {
+    fn default() -> Self {
+        FecConfig {
+            data_shards: DEFAULT_DATA_SHARDS,
+            parity_shards: DEFAULT_PARITY_SHARDS,
+        }
+    }
+}
+
+impl ArchiveWriterConfig {
+    /// Set the number of data shards in each forward error correction group,
+    /// and the number of parity shards protecting them
+    ///
+    /// Up to `parity_shards` damaged shards can be rebuilt in each group. There
+    /// must be at least one of each, and at most 256 shards in a group
+    pub fn with_fec_shards(&mut self, data_shards: u8, parity_shards: u8) -> ConfigResult {
+        if data_shards == 0 || parity_shards == 0 || data_shards as u32 + parity_shards as u32 > 256
+        {
+            Err(ConfigError::InvalidFecShards)
+        } else {
+            self.fec.data_shards = data_shards;
+            self.fec.parity_shards = parity_shards;
+            Ok(self)
+        }
+    }
+}
+
+/// Layout of the groups, stored in the layer header
+#[derive(Clone, Copy)]
+struct Geometry {
+    data_shards: usize,
+    parity_shards: usize,
+    shard_size: usize,
+}
+
+impl Geometry {
+    fn to_bytes(self) -> Vec<u8> {
+        let mut out = vec![self.data_shards as u8, self.parity_shards as u8];
+        out.extend_from_slice(&(self.shard_size as u32).to_le_bytes());
+        out
+    }
+
+    fn from_bytes(mut src: &[u8]) -> Result<Self, Error> {
+        let data_shards = src.read_u8()? as usize;
+        let parity_shards = src.read_u8()? as usize;
+        let shard_size = src.read_u32::<LittleEndian>()? as usize;
+        if shard_size == 0 {
+            return Err(Error::DeserializationError);
+        }
+        Ok(Geometry {
+            data_shards,
+            parity_shards,
+            shard_size,
+        })
+    }
+
+    fn codec(&self) -> Result<ReedSolomon, Error> {
+        ReedSolomon::new(self.data_shards, self.parity_shards)
+            .map_err(|_| ConfigError::InvalidFecShards.into())
+    }
+
+    /// Size of a shard, checksum included
+    fn stored_shard_size(&self) -> usize {
+        self.shard_size + CHECKSUM_SIZE
+    }
+
+    /// Size of the data of a whole group
+    fn group_data_size(&self) -> u64 {
+        (self.data_shards * self.shard_size) as u64
+    }
+
+    /// Size of a whole group, parity shards and checksums included
+    fn group_stored_size(&self) -> u64 {
+        ((self.data_shards + self.parity_shards) * self.stored_shard_size()) as u64
+    }
+
+    /// Number of data shards needed for `size` bytes of data
+    fn data_shards_for(&self, size: u64) -> usize {
+        size.div_ceil(self.shard_size as u64) as usize
+    }
+
+    /// Split `stored` shards, with their checksums, dropping the damaged or
+    /// truncated ones
+    fn check_shards(&self, stored: &[u8]) -> Vec<Option<Vec<u8>>> {
+        stored
+            .chunks(self.stored_shard_size())
+            .map(|chunk| {
+                if chunk.len() != self.stored_shard_size() {
+                    return None;
+                }
+                let (shard, mut checksum) = chunk.split_at(self.shard_size);
+                match checksum.read_u32::<LittleEndian>() {
+                    Ok(checksum) if checksum == crc32(shard) => Some(shard.to_vec()),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    /// Rebuild the data of a group made of `data_count` data shards, from its
+    /// `shards` (data then parity, `None` if damaged)
+    ///
+    /// On failure, return the data up to the first damaged shard
+    fn rebuild(
+        &self,
+        codec: &ReedSolomon,
+        mut shards: Vec<Option<Vec<u8>>>,
+        data_count: usize,
+    ) -> Result<Vec<u8>, Vec<u8>> {
+        shards.resize(data_count + self.parity_shards, None);
+        if shards[..data_count].iter().any(Option::is_none) {
+            // Data shards absent from a partial group are known to be zeros
+            let parity = shards.split_off(data_count);
+            let mut all_shards = shards.clone();
+            all_shards.resize(self.data_shards, Some(vec![0; self.shard_size]));
+            all_shards.extend(parity);
+            if codec.reconstruct_data(&mut all_shards).is_err() {
+                return Err(shards
+                    .into_iter()
+                    .take_while(Option::is_some)
+                    .flatten()
+                    .flatten()
+                    .collect());
+            }
+            shards = all_shards;
+        }
+        Ok(shards
+            .into_iter()
+            .take(data_count)
+            .flatten()
+            .flatten()
+            .collect())
+    }
+}
+
+/// Write `content` `COPIES` times, with its checksum
+fn write_copies<W: Write>(dest: &mut W, content: &[u8]) -> io::Result<()> {
+    for _ in 0..COPIES {
+        dest.write_all(content)?;
+        dest.write_u32::<LittleEndian>(crc32(content))?;
+    }
+    Ok(())
+}
+
+/// Return the first copy, of `size` bytes, with a correct checksum
+fn read_copies(src: &[u8], size: usize) -> Option<&[u8]> {
+    src.chunks_exact(size).find_map(|chunk| {
+        let (content, mut checksum) = chunk.split_at(size - CHECKSUM_SIZE);
+        match checksum.read_u32::<LittleEndian>() {
+            Ok(checksum) if checksum == crc32(content) => Some(content),
+            _ => None,
+        }
+    })
+}
+
+// ---------- Writer ----------
+
+/// Split the data in groups of shards, each group followed by its Reed-Solomon
+/// parity shards. Every shard has a checksum, so damaged ones are rebuilt from
+/// the others of their group
+///
+/// The layer starts with its header, giving the group layout, and ends with
+/// its footer, giving the data size. The last group is shortened to the data
+/// shards actually used
+pub struct FecLayerWriter<'a, W: 'a + Write> {
+    inner: Box<dyn 'a + LayerWriter<'a, W>>,
+    geometry: Geometry,
+    codec: ReedSolomon,
+    /// Data of the current group
+    group: Vec<u8>,
+    /// Total size of the data written so far
+    data_size: u64,
+}
+
+impl<'a, W: 'a + Write> FecLayerWriter<'a, W> {
+    pub fn new(
+        mut inner: Box<dyn 'a + LayerWriter<'a, W>>,
+        config: &FecConfig,
+    ) -> Result<Self, Error> {
+        let geometry = Geometry {
+            data_shards: config.data_shards as usize,
+            parity_shards: config.parity_shards as usize,
+            shard_size: SHARD_SIZE as usize,
+        };
+        let codec = geometry.codec()?;
+        write_copies(&mut inner, &geometry.to_bytes())?;
+        Ok(Self {
+            inner,
+            geometry,
+            codec,
+            group: Vec::with_capacity(geometry.group_data_size() as usize),
+            data_size: 0,
+        })
+    }
+
+    /// Write the current group, with its parity shards
+    fn write_group(&mut self) -> Result<(), Error> {
+        let geometry = self.geometry;
+        let data_count = geometry.data_shards_for(self.group.len() as u64);
+        self.group.resize(data_count * geometry.shard_size, 0);
+
+        // Data shards absent from a partial group are zeros
+        let mut shards: Vec<Vec<u8>> = self
+            .group
+            .chunks(geometry.shard_size)
+            .map(|chunk| chunk.to_vec())
+            .collect();
+        shards.resize(
+            geometry.data_shards + geometry.parity_shards,
+            vec![0; geometry.shard_size],
+        );
+        self.codec
+            .encode(&mut shards)
+            .map_err(|_| Error::AssertionError("[FEC] Unable to encode".to_string()))?;
+
+        for shard in shards[..data_count]
+            .iter()
+            .chain(&shards[geometry.data_shards..])
+        {
+            self.inner.write_all(shard)?;
+            self.inner.write_u32::<LittleEndian>(crc32(shard))?;
+        }
+        self.group.clear();
+        Ok(())
+    }
+}
+
+impl<'a, W: 'a + Write> LayerWriter<'a, W> for FecLayerWriter<'a, W> {
+    fn into_inner(self) -> Option<Box<dyn 'a + LayerWriter<'a, W>>> {
+        Some(self.inner)
+    }
+
+    fn into_raw(self: Box<Self>) -> W {
+        self.inner.into_raw()
+    }
+
+    fn finalize(&mut self) -> Result<(), Error> {
+        if !self.group.is_empty() {
+            self.write_group()?;
+        }
+        write_copies(&mut self.inner, &self.data_size.to_le_bytes())?;
+
+        // Recursive call
+        self.inner.finalize()
+    }
+}
+
+impl<'a, W: 'a + Write> Write for FecLayerWriter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_vectored(&[IoSlice::new(buf)])
+    }
+
+    /// Slices are gathered in the current group, up to its end
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        let room = self.geometry.group_data_size() as usize - self.group.len();
+        let mut size = 0;
+        for buf in bufs {
+            let count = std::cmp::min(room - size, buf.len());
+            self.group.extend_from_slice(&buf[..count]);
+            size += count;
+            if size == room {
+                break;
+            }
+        }
+        self.data_size += size as u64;
+        if self.group.len() == self.geometry.group_data_size() as usize {
+            self.write_group()?;
+        }
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // Only whole groups can be written
+        self.inner.flush()
+    }
+}
+
+// ---------- Reader ----------
+
+/// Read the data group by group, rebuilding the damaged shards on the fly
+pub struct FecLayerReader<'a, R: Read + Seek> {
+    inner: Box<dyn 'a + LayerReader<'a, R>>,
+    geometry: Geometry,
+    codec: ReedSolomon,
+    /// Size of the data, from the footer
+    data_size: u64,
+    position: u64,
+    /// Number and data of the last group read
+    cache: Option<(u64, Vec<u8>)>,
+}
+
+impl<'a, R: 'a + Read + Seek> FecLayerReader<'a, R> {
+    pub fn new(mut inner: Box<dyn 'a + LayerReader<'a, R>>) -> Result<Self, Error> {
+        let mut header = [0u8; HEADER_SIZE * COPIES];
+        inner.seek(SeekFrom::Start(0))?;
+        inner.read_exact(&mut header)?;
+        let geometry = Geometry::from_bytes(
+            read_copies(&header, HEADER_SIZE).ok_or(Error::DeserializationError)?,
+        )?;
+        let codec = geometry.codec()?;
+
+        let mut footer = [0u8; FOOTER_SIZE * COPIES];
+        let end = inner.seek(SeekFrom::End(-((FOOTER_SIZE * COPIES) as i64)))?;
+        inner.read_exact(&mut footer)?;
+        let data_size = read_copies(&footer, FOOTER_SIZE)
+            .ok_or(Error::DeserializationError)?
+            .read_u64::<LittleEndian>()?;
+
+        // Ensure the layer size matches its footer
+        let groups = data_size / geometry.group_data_size();
+        let last_data_count = geometry.data_shards_for(data_size % geometry.group_data_size());
+        let last_group_size = if last_data_count > 0 {
+            ((last_data_count + geometry.parity_shards) * geometry.stored_shard_size()) as u64
+        } else {
+            0
+        };
+        if end
+            != (HEADER_SIZE * COPIES) as u64
+                + groups * geometry.group_stored_size()
+                + last_group_size
+        {
+            return Err(Error::DeserializationError);
+        }
+
+        inner.seek(SeekFrom::Start(0))?;
+        Ok(Self {
+            inner,
+            geometry,
+            codec,
+            data_size,
+            position: 0,
+            cache: None,
+        })
+    }
+
+    /// Get the data of the group `number`, rebuilding it if needed
+    fn read_group(&mut self, number: u64) -> Result<Vec<u8>, Error> {
+        let geometry = self.geometry;
+        let group_start = number * geometry.group_data_size();
+        let group_size = std::cmp::min(geometry.group_data_size(), self.data_size - group_start);
+        let data_count = geometry.data_shards_for(group_size);
+
+        // Parity shards are only read if a data shard is damaged
+        self.inner.seek(SeekFrom::Start(
+            (HEADER_SIZE * COPIES) as u64 + number * geometry.group_stored_size(),
+        ))?;
+        let mut stored = vec![0u8; data_count * geometry.stored_shard_size()];
+        self.inner.read_exact(&mut stored)?;
+        let mut shards = geometry.check_shards(&stored);
+        if shards.iter().any(Option::is_none) {
+            let mut parity = vec![0u8; geometry.parity_shards * geometry.stored_shard_size()];
+            self.inner.read_exact(&mut parity)?;
+            shards.extend(geometry.check_shards(&parity));
+        }
+
+        let mut data = geometry
+            .rebuild(&self.codec, shards, data_count)
+            .map_err(|_| Error::UnrecoverableCorruption)?;
+        data.truncate(group_size as usize);
+        Ok(data)
+    }
+}
+
+impl<'a, R: 'a + Read + Seek> LayerReader<'a, R> for FecLayerReader<'a, R> {
+    fn into_inner(self) -> Option<Box<dyn 'a + LayerReader<'a, R>>> {
+        Some(self.inner)
+    }
+
+    fn into_raw(self: Box<Self>) -> R {
+        self.inner.into_raw()
+    }
+
+    fn initialize(&mut self) -> Result<(), Error> {
+        // Recursive call
+        self.inner.initialize()
+    }
+}
+
+impl<'a, R: 'a + Read + Seek> Read for FecLayerReader<'a, R> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.data_size {
+            return Ok(0);
+        }
+        let number = self.position / self.geometry.group_data_size();
+        let data = match self.cache.take() {
+            Some((cached, data)) if cached == number => data,
+            _ => self.read_group(number)?,
+        };
+        let offset = (self.position - number * self.geometry.group_data_size()) as usize;
+        let size = std::cmp::min(into.len(), data.len() - offset);
+        into[..size].copy_from_slice(&data[offset..offset + size]);
+        self.position += size as u64;
+        self.cache = Some((number, data));
+        Ok(size)
+    }
+}
+
+impl<'a, R: 'a + Read + Seek> Seek for FecLayerReader<'a, R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.position = match pos {
+            SeekFrom::Start(pos) => pos,
+            SeekFrom::Current(value) => (self.position as i64 + value) as u64,
+            SeekFrom::End(value) => (self.data_size as i64 + value) as u64,
+        };
+        Ok(self.position)
+    }
+}
+
+// ---------- Fail-Safe Reader ----------
+
+/// Read the data group by group, rebuilding the damaged shards on the fly
+///
+/// The data size is only known at the end, from the footer. When a group
+/// cannot be rebuilt, the data is returned up to its first damaged shard
+pub struct FecLayerFailSafeReader<'a, R: Read> {
+    inner: Box<dyn 'a + LayerFailSafeReader<'a, R>>,
+    geometry: Geometry,
+    codec: ReedSolomon,
+    /// Rebuilt data, not yet returned
+    data: Vec<u8>,
+    offset: usize,
+    /// Stored data read ahead, not yet rebuilt
+    pending: Vec<u8>,
+    /// Size of the data rebuilt so far
+    data_size: u64,
+    /// Set once the last group has been rebuilt, with the error ending the
+    /// data if any
+    end: Option<Option<Error>>,
+}
+
+impl<'a, R: 'a + Read> FecLayerFailSafeReader<'a, R> {
+    pub fn new(mut inner: Box<dyn 'a + LayerFailSafeReader<'a, R>>) -> Result<Self, Error> {
+        let mut header = [0u8; HEADER_SIZE * COPIES];
+        inner.read_exact(&mut header)?;
+        let geometry = Geometry::from_bytes(
+            read_copies(&header, HEADER_SIZE).ok_or(Error::DeserializationError)?,
+        )?;
+        let codec = geometry.codec()?;
+        Ok(Self {
+            inner,
+            geometry,
+            codec,
+            data: Vec::new(),
+            offset: 0,
+            pending: Vec::new(),
+            data_size: 0,
+            end: None,
+        })
+    }
+
+    /// Rebuild the next group in `self.data`
+    fn next_group(&mut self) -> io::Result<()> {
+        let geometry = self.geometry;
+        let group_stored_size = geometry.group_stored_size() as usize;
+        let footer_size = FOOTER_SIZE * COPIES;
+
+        // Read ahead the footer, to know if this is the last group
+        while self.pending.len() < group_stored_size + footer_size {
+            let mut buf = vec![0u8; group_stored_size + footer_size - self.pending.len()];
+            let read = match self.inner.read(&mut buf) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                // Keep what has been read so far
+                Err(_) => break,
+            };
+            self.pending.extend_from_slice(&buf[..read]);
+        }
+
+        let (stored, data_count, group_size, last) =
+            if self.pending.len() == group_stored_size + footer_size {
+                let rest = self.pending.split_off(group_stored_size);
+                let stored = std::mem::replace(&mut self.pending, rest);
+                let size = geometry.group_data_size();
+                (stored, geometry.data_shards, Some(size), false)
+            } else {
+                let stored = std::mem::take(&mut self.pending);
+                let footer = stored
+                    .len()
+                    .checked_sub(footer_size)
+                    .and_then(|start| read_copies(&stored[start..], FOOTER_SIZE))
+                    .and_then(|mut footer| footer.read_u64::<LittleEndian>().ok())
+                    .and_then(|data_size| data_size.checked_sub(self.data_size))
+                    .filter(|size| *size <= geometry.group_data_size());
+                match footer {
+                    Some(size) => {
+                        let data_count = geometry.data_shards_for(size);
+                        let mut stored = stored;
+                        stored.truncate(stored.len() - footer_size);
+                        (stored, data_count, Some(size), true)
+                    }
+                    // Truncated archive: only keep the intact data shards
+                    None => {
+                        let data_count = std::cmp::min(
+                            stored.len() / geometry.stored_shard_size(),
+                            geometry.data_shards,
+                        );
+                        (stored, data_count, None, true)
+                    }
+                }
+            };
+
+        let shards = geometry.check_shards(&stored);
+        let (mut data, error) = match group_size {
+            Some(_) => match geometry.rebuild(&self.codec, shards, data_count) {
+                Ok(data) => (data, None),
+                Err(data) => (data, Some(Error::UnrecoverableCorruption)),
+            },
+            None => {
+                let data = shards
+                    .into_iter()
+                    .take(data_count)
+                    .take_while(Option::is_some)
+                    .flatten()
+                    .flatten()
+                    .collect();
+                let err = io::Error::new(io::ErrorKind::UnexpectedEof, "Truncated FEC layer");
+                (data, Some(Error::IOError(err)))
+            }
+        };
+        if let Some(size) = group_size {
+            data.truncate(size as usize);
+        }
+        self.data_size += data.len() as u64;
+        self.data = data;
+        self.offset = 0;
+        if last || error.is_some() {
+            self.end = Some(error);
+        }
+        Ok(())
+    }
+}
+
+impl<'a, R: 'a + Read> LayerFailSafeReader<'a, R> for FecLayerFailSafeReader<'a, R> {
+    fn into_inner(self) -> Option<Box<dyn 'a + LayerFailSafeReader<'a, R>>> {
+        Some(self.inner)
+    }
+
+    fn into_raw(self: Box<Self>) -> R {
+        self.inner.into_raw()
+    }
+}
+
+impl<'a, R: 'a + Read> Read for FecLayerFailSafeReader<'a, R> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        while self.offset == self.data.len() {
+            match self.end.take() {
+                None => self.next_group()?,
+                Some(None) => {
+                    self.end = Some(None);
+                    return Ok(0);
+                }
+                Some(Some(err)) => {
+                    // Report the error once, then behave as the end of data
+                    self.end = Some(None);
+                    return Err(err.into());
+                }
+            }
+        }
+        let size = std::cmp::min(into.len(), self.data.len() - self.offset);
+        into[..size].copy_from_slice(&self.data[self.offset..self.offset + size]);
+        self.offset += size;
+        Ok(size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::layers::raw::{RawLayerFailSafeReader, RawLayerReader, RawLayerWriter};
+    use rand::{RngCore, SeedableRng};
+    use rand_chacha::ChaChaRng;
+    use std::io::Cursor;
+
+    fn get_data(size: usize) -> Vec<u8> {
+        let mut data = vec![0u8; size];
+        ChaChaRng::seed_from_u64(0).fill_bytes(&mut data);
+        data
+    }
+
+    fn build(data: &[u8], config: &FecConfig) -> Vec<u8> {
+        let mut fec_w = Box::new(
+            FecLayerWriter::new(Box::new(RawLayerWriter::new(Vec::new())), config).unwrap(),
+        );
+        fec_w.write_all(data).unwrap();
+        fec_w.finalize().unwrap();
+        fec_w.into_raw()
+    }
+
+    fn read_all(buf: &[u8]) -> Result<Vec<u8>, io::Error> {
+        let mut fec_r = FecLayerReader::new(Box::new(RawLayerReader::new(Cursor::new(buf))))?;
+        fec_r.initialize()?;
+        let mut output = Vec::new();
+        fec_r.read_to_end(&mut output)?;
+        Ok(output)
+    }
+
+    fn read_all_failsafe(buf: &[u8]) -> (Vec<u8>, io::Result<usize>) {
+        let mut fec_r =
+            FecLayerFailSafeReader::new(Box::new(RawLayerFailSafeReader::new(buf))).unwrap();
+        let mut output = Vec::new();
+        let res = fec_r.read_to_end(&mut output);
+        (output, res)
+    }
+
+    /// Offset, in the layer, of the shard `shard` of the group `group`
+    fn shard_offset(group: usize, shard: usize) -> usize {
+        let config = FecConfig::default();
+        let stored_shard_size = SHARD_SIZE as usize + CHECKSUM_SIZE;
+        HEADER_SIZE * COPIES
+            + (group * (config.data_shards + config.parity_shards) as usize + shard)
+                * stored_shard_size
+    }
+
+    #[test]
+    fn fec_layer() {
+        let config = FecConfig::default();
+        // Several groups, the last one being partial
+        for size in &[0, 1, 4096, 200_000, 32 * 4096 * 2] {
+            let data = get_data(*size);
+            let buf = build(&data, &config);
+            assert_eq!(read_all(&buf).unwrap(), data);
+            let (output, res) = read_all_failsafe(&buf);
+            assert!(res.is_ok());
+            assert_eq!(output, data);
+        }
+
+        // Seek
+        let data = get_data(200_000);
+        let buf = build(&data, &config);
+        let mut fec_r =
+            FecLayerReader::new(Box::new(RawLayerReader::new(Cursor::new(buf.as_slice()))))
+                .unwrap();
+        fec_r.initialize().unwrap();
+        let mut output = Vec::new();
+        fec_r.seek(SeekFrom::End(-2)).unwrap();
+        fec_r.read_to_end(&mut output).unwrap();
+        assert_eq!(output.as_slice(), &data[data.len() - 2..]);
+        fec_r.seek(SeekFrom::Start(140_000)).unwrap();
+        fec_r.seek(SeekFrom::Current(2)).unwrap();
+        let mut output = [0u8; 10_000];
+        fec_r.read_exact(&mut output).unwrap();
+        assert_eq!(&output[..], &data[140_002..150_002]);
+    }
+
+    #[test]
+    fn fec_layer_repair() {
+        let config = FecConfig::default();
+        let data = get_data(200_000);
+        let buf = build(&data, &config);
+
+        // Damage as many shards as there are parity shards, in each group,
+        // along with the header and footer copies, except the last ones
+        let mut damaged = buf.clone();
+        for shard in 10..14 {
+            damaged[shard_offset(0, shard) + 100] ^= 1;
+        }
+        // The last (partial) group has its own parity shards
+        damaged[shard_offset(1, 0) + 5000] ^= 1;
+        for i in 0..COPIES - 1 {
+            damaged[i * HEADER_SIZE] ^= 1;
+            damaged[buf.len() - (COPIES - i) * FOOTER_SIZE] ^= 1;
+        }
+        assert_eq!(read_all(&damaged).unwrap(), data);
+        let (output, res) = read_all_failsafe(&damaged);
+        assert!(res.is_ok());
+        assert_eq!(output, data);
+
+        // A damaged parity shard does not matter
+        let mut damaged = buf.clone();
+        damaged[shard_offset(0, 33) + 1] ^= 1;
+        assert_eq!(read_all(&damaged).unwrap(), data);
+
+        // Too many damaged shards
+        let mut damaged = buf;
+        for shard in 10..15 {
+            damaged[shard_offset(0, shard) + 100] ^= 1;
+        }
+        match read_all(&damaged) {
+            Err(err) => assert!(format!("{}", err).contains("UnrecoverableCorruption")),
+            Ok(_) => panic!("Expected an UnrecoverableCorruption error"),
+        }
+        // The fail-safe reader returns the data up to the first damaged shard
+        let (output, res) = read_all_failsafe(&damaged);
+        assert!(res.is_err());
+        assert_eq!(output.as_slice(), &data[..10 * SHARD_SIZE as usize]);
+    }
+
+    #[test]
+    fn fec_layer_truncated() {
+        let config = FecConfig::default();
+        let data = get_data(200_000);
+        let buf = build(&data, &config);
+
+        // The fail-safe reader returns the intact shards
+        let truncated = &buf[..shard_offset(1, 3) + 10];
+        assert!(read_all(truncated).is_err());
+        let (output, res) = read_all_failsafe(truncated);
+        assert!(res.is_err());
+        assert_eq!(output.as_slice(), &data[..(32 + 3) * SHARD_SIZE as usize]);
+    }
+
+    #[test]
+    fn fec_config() {
+        let mut config = ArchiveWriterConfig::new();
+        assert!(config.with_fec_shards(0, 1).is_err());
+        assert!(config.with_fec_shards(1, 0).is_err());
+        assert!(config.with_fec_shards(200, 57).is_err());
+        assert!(config.with_fec_shards(200, 56).is_ok());
+
+        // Custom layout, stored in the layer header
+        let data = get_data(50_000);
+        let buf = build(
+            &data,
+            &FecConfig {
+                data_shards: 2,
+                parity_shards: 1,
+            },
+        );
+        let mut damaged = buf;
+        damaged[HEADER_SIZE * COPIES + 10] ^= 1;
+        assert_eq!(read_all(&damaged).unwrap(), data);
+    }
+}
@@ -1,5 +1,7 @@
 pub mod compress;
 pub mod encrypt;
+pub mod fec;
 pub mod position;
 pub mod raw;
+pub mod sign;
 pub mod traits;
@@ -1,5 +1,5 @@
 use std::io;
-use std::io::Write;
+use std::io::{IoSlice, Write};
 
 use crate::layers::traits::LayerWriter;
 use crate::Error;
@@ -28,6 +28,12 @@ impl<'a, W: 'a + Write> PositionLayerWriter<'a, W> {
         self.pos = 0;
         before
     }
+
+    /// Force the current position, for instance when some bytes have been
+    /// written to the inner layer without using this layer
+    pub(crate) fn set_position(&mut self, pos: u64) {
+        self.pos = pos;
+    }
 }
 
 impl<'a, W: 'a + Write> LayerWriter<'a, W> for PositionLayerWriter<'a, W> {
@@ -45,6 +51,10 @@ impl<'a, W: 'a + Write> LayerWriter<'a, W> for PositionLayerWriter<'a, W> {
         // Recursive call
         self.inner.finalize()
     }
+
+    fn set_store_hint(&mut self, store: bool) {
+        self.inner.set_store_hint(store)
+    }
 }
 
 impl<'a, W: 'a + Write> Write for PositionLayerWriter<'a, W> {
@@ -55,6 +65,13 @@ impl<'a, W: 'a + Write> Write for PositionLayerWriter<'a, W> {
         Ok(written)
     }
 
+    /// Wrapper on inner
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        let written = self.inner.write_vectored(bufs)?;
+        self.pos += written as u64;
+        Ok(written)
+    }
+
     /// Wrapper on inner
     fn flush(&mut self) -> io::Result<()> {
         self.inner.flush()
@@ -1,5 +1,5 @@
 use std::io;
-use std::io::{Read, Seek, SeekFrom, Write};
+use std::io::{IoSlice, Read, Seek, SeekFrom, Write};
 
 use crate::layers::traits::{LayerFailSafeReader, LayerReader, LayerWriter};
 use crate::Error;
@@ -38,6 +38,11 @@ impl<W: Write> Write for RawLayerWriter<W> {
         self.inner.write(buf)
     }
 
+    /// Wrapper on inner
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        self.inner.write_vectored(bufs)
+    }
+
     /// Wrapper on inner
     fn flush(&mut self) -> io::Result<()> {
         self.inner.flush()
@@ -0,0 +1,416 @@
+use std::convert::TryFrom;
+use std::io;
+use std::io::{IoSlice, Read, Seek, SeekFrom, Write};
+
+use crate::config::{ArchiveReaderConfig, ArchiveWriterConfig};
+use crate::errors::ConfigError;
+use crate::layers::traits::{LayerFailSafeReader, LayerReader, LayerWriter};
+use crate::Error;
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signature, PUBLIC_KEY_LENGTH};
+use sha2::{Digest, Sha512};
+
+/// Size of the signature
+const SIGNATURE_SIZE: usize = 64;
+/// Size of the trailer, made of the signer public key followed by the
+/// signature
+const TRAILER_SIZE: u64 = (PUBLIC_KEY_LENGTH + SIGNATURE_SIZE) as u64;
+/// Ed25519ph context, to avoid reusing a signature in another protocol
+const SIGNATURE_CONTEXT: &[u8] = b"MLA SIGNATURE";
+/// Size of the buffer used to hash the archive while verifying it
+const HASH_BUF_SIZE: usize = 4096;
+
+// ---------- Config ----------
+
+#[derive(Default)]
+pub struct SignatureConfig {
+    /// Private key with which to sign the archive
+    signing_key: Option<SecretKey>,
+}
+
+impl SignatureConfig {
+    /// Consistency check
+    pub fn check(&self) -> Result<(), ConfigError> {
+        if self.signing_key.is_none() {
+            return Err(ConfigError::SigningKeyIsMissing);
+        }
+        Ok(())
+    }
+}
+
+impl ArchiveWriterConfig {
+    /// Set the private key with which to sign the archive
+    pub fn set_signature_key(&mut self, key: &SecretKey) -> &mut ArchiveWriterConfig {
+        // Safe to use unwrap() because `key` has the expected length
+        self.sign.signing_key = Some(SecretKey::from_bytes(key.as_bytes()).unwrap());
+        self
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct SignatureReaderConfig {
+    /// Public keys accepted as signers. If empty, any valid signature is
+    /// accepted
+    pub(crate) trusted_keys: Vec<PublicKey>,
+}
+
+impl ArchiveReaderConfig {
+    /// Add public keys whose signatures are trusted
+    ///
+    /// Once set, only archives signed by one of these keys can be read
+    pub fn add_trusted_public_keys(&mut self, keys: &[PublicKey]) -> &mut ArchiveReaderConfig {
+        self.sign.trusted_keys.extend_from_slice(keys);
+        self
+    }
+}
+
+/// Hash of the signed content: the archive header, then the data of the
+/// layers above
+fn signed_hash(header: &[u8]) -> Sha512 {
+    let mut hash = Sha512::new();
+    hash.update(header);
+    hash
+}
+
+// ---------- Writer ----------
+
+/// Sign the whole archive, header included, using Ed25519ph
+///
+/// The signer public key and the signature are appended as a trailer on
+/// finalization
+pub struct SignatureLayerWriter<'a, W: 'a + Write> {
+    inner: Box<dyn 'a + LayerWriter<'a, W>>,
+    keypair: Keypair,
+    hash: Sha512,
+}
+
+impl<'a, W: 'a + Write> SignatureLayerWriter<'a, W> {
+    /// `header` is the serialized archive header, already written in `inner`
+    pub fn new(
+        inner: Box<dyn 'a + LayerWriter<'a, W>>,
+        config: &SignatureConfig,
+        header: &[u8],
+    ) -> Result<Self, Error> {
+        let secret = match &config.signing_key {
+            // Safe to use unwrap() because `key` has the expected length
+            Some(key) => SecretKey::from_bytes(key.as_bytes()).unwrap(),
+            None => return Err(ConfigError::SigningKeyIsMissing.into()),
+        };
+        let public = PublicKey::from(&secret);
+        Ok(Self {
+            inner,
+            keypair: Keypair { secret, public },
+            hash: signed_hash(header),
+        })
+    }
+}
+
+impl<'a, W: 'a + Write> LayerWriter<'a, W> for SignatureLayerWriter<'a, W> {
+    fn into_inner(self) -> Option<Box<dyn 'a + LayerWriter<'a, W>>> {
+        Some(self.inner)
+    }
+
+    fn into_raw(self: Box<Self>) -> W {
+        self.inner.into_raw()
+    }
+
+    fn finalize(&mut self) -> Result<(), Error> {
+        // Write the trailer
+        let hash = std::mem::replace(&mut self.hash, Sha512::new());
+        let signature = self
+            .keypair
+            .sign_prehashed(hash, Some(SIGNATURE_CONTEXT))
+            .map_err(|_| Error::InvalidSignature)?;
+        self.inner.write_all(self.keypair.public.as_bytes())?;
+        self.inner.write_all(&signature.to_bytes())?;
+
+        // Recursive call
+        self.inner.finalize()
+    }
+}
+
+impl<'a, W: Write> Write for SignatureLayerWriter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hash.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        let written = self.inner.write_vectored(bufs)?;
+        // Hash the slices, up to the amount written
+        let mut remaining = written;
+        for buf in bufs {
+            let count = std::cmp::min(remaining, buf.len());
+            self.hash.update(&buf[..count]);
+            remaining -= count;
+            if remaining == 0 {
+                break;
+            }
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// ---------- Reader ----------
+
+/// Check the archive signature on initialization, and hide the trailer to the
+/// layers above
+pub struct SignatureLayerReader<'a, R: Read + Seek> {
+    inner: Box<dyn 'a + LayerReader<'a, R>>,
+    trusted_keys: Vec<PublicKey>,
+    /// Serialized archive header, part of the signed content
+    header: Vec<u8>,
+    /// Size of the data, trailer excluded
+    data_size: u64,
+    position: u64,
+    /// Public key of the signer, once the signature has been verified
+    signer: Option<PublicKey>,
+}
+
+impl<'a, R: 'a + Read + Seek> SignatureLayerReader<'a, R> {
+    /// `header` is the serialized archive header, read before `inner`
+    pub fn new(
+        mut inner: Box<dyn 'a + LayerReader<'a, R>>,
+        config: &SignatureReaderConfig,
+        header: Vec<u8>,
+    ) -> Result<Self, Error> {
+        let data_size = inner
+            .seek(SeekFrom::End(0))?
+            .checked_sub(TRAILER_SIZE)
+            .ok_or(Error::InvalidSignature)?;
+        inner.seek(SeekFrom::Start(0))?;
+        Ok(Self {
+            inner,
+            trusted_keys: config.trusted_keys.clone(),
+            header,
+            data_size,
+            position: 0,
+            signer: None,
+        })
+    }
+
+    /// Public key of the signer, available once the layer is initialized
+    pub fn signer(&self) -> Option<PublicKey> {
+        self.signer
+    }
+
+    /// Check the trailer signature over the whole archive
+    fn verify(&mut self) -> Result<PublicKey, Error> {
+        let mut trailer = [0u8; TRAILER_SIZE as usize];
+        self.inner.seek(SeekFrom::Start(self.data_size))?;
+        self.inner.read_exact(&mut trailer)?;
+        let public = PublicKey::from_bytes(&trailer[..PUBLIC_KEY_LENGTH])
+            .map_err(|_| Error::InvalidSignature)?;
+        let signature = Signature::try_from(&trailer[PUBLIC_KEY_LENGTH..])
+            .map_err(|_| Error::InvalidSignature)?;
+        if !self.trusted_keys.is_empty() && !self.trusted_keys.contains(&public) {
+            return Err(Error::UntrustedSignature);
+        }
+
+        let mut hash = signed_hash(&self.header);
+        let mut buf = [0u8; HASH_BUF_SIZE];
+        let mut remaining = self.data_size;
+        self.inner.seek(SeekFrom::Start(0))?;
+        while remaining > 0 {
+            let size = std::cmp::min(remaining, HASH_BUF_SIZE as u64) as usize;
+            self.inner.read_exact(&mut buf[..size])?;
+            hash.update(&buf[..size]);
+            remaining -= size as u64;
+        }
+        public
+            .verify_prehashed(hash, Some(SIGNATURE_CONTEXT), &signature)
+            .map_err(|_| Error::InvalidSignature)?;
+        Ok(public)
+    }
+}
+
+impl<'a, R: 'a + Read + Seek> LayerReader<'a, R> for SignatureLayerReader<'a, R> {
+    fn into_inner(self) -> Option<Box<dyn 'a + LayerReader<'a, R>>> {
+        Some(self.inner)
+    }
+
+    fn into_raw(self: Box<Self>) -> R {
+        self.inner.into_raw()
+    }
+
+    fn initialize(&mut self) -> Result<(), Error> {
+        // Recursive call
+        self.inner.initialize()?;
+
+        // Verifying requires reading the whole archive, only do it once
+        if self.signer.is_none() {
+            self.signer = Some(self.verify()?);
+        }
+        self.seek(SeekFrom::Start(0))?;
+        Ok(())
+    }
+}
+
+impl<'a, R: 'a + Read + Seek> Read for SignatureLayerReader<'a, R> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        // Do not read the trailer
+        let size = std::cmp::min(
+            self.data_size.saturating_sub(self.position),
+            into.len() as u64,
+        ) as usize;
+        let read = self.inner.read(&mut into[..size])?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<'a, R: 'a + Read + Seek> Seek for SignatureLayerReader<'a, R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(pos) => pos,
+            SeekFrom::Current(value) => (self.position as i64 + value) as u64,
+            SeekFrom::End(value) => (self.data_size as i64 + value) as u64,
+        };
+        self.position = self.inner.seek(SeekFrom::Start(position))?;
+        Ok(self.position)
+    }
+}
+
+// ---------- Fail-Safe Reader ----------
+
+/// The signature cannot be checked on a truncated archive. As the trailer is
+/// after the end of the archive data, it is left as is
+pub struct SignatureLayerFailSafeReader<'a, R: Read> {
+    inner: Box<dyn 'a + LayerFailSafeReader<'a, R>>,
+}
+
+impl<'a, R: 'a + Read> SignatureLayerFailSafeReader<'a, R> {
+    pub fn new(inner: Box<dyn 'a + LayerFailSafeReader<'a, R>>) -> Self {
+        Self { inner }
+    }
+}
+
+impl<'a, R: 'a + Read> LayerFailSafeReader<'a, R> for SignatureLayerFailSafeReader<'a, R> {
+    fn into_inner(self) -> Option<Box<dyn 'a + LayerFailSafeReader<'a, R>>> {
+        Some(self.inner)
+    }
+
+    fn into_raw(self: Box<Self>) -> R {
+        self.inner.into_raw()
+    }
+}
+
+impl<'a, R: Read> Read for SignatureLayerFailSafeReader<'a, R> {
+    /// Wrapper on inner
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::layers::raw::{RawLayerReader, RawLayerWriter};
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+    use std::io::Cursor;
+
+    static HEADER: &[u8] = b"header";
+    static DATA: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+
+    fn signing_key(seed: u64) -> SecretKey {
+        let mut csprng = ChaChaRng::seed_from_u64(seed);
+        SecretKey::generate(&mut csprng)
+    }
+
+    fn build_signed(key: &SecretKey) -> Vec<u8> {
+        let mut config = ArchiveWriterConfig::new();
+        config.set_signature_key(key);
+        let file = Vec::new();
+        let mut sign_w = Box::new(
+            SignatureLayerWriter::new(Box::new(RawLayerWriter::new(file)), &config.sign, HEADER)
+                .unwrap(),
+        );
+        sign_w.write_all(DATA).unwrap();
+        sign_w.finalize().unwrap();
+        sign_w.into_raw()
+    }
+
+    fn open_signed<'a>(
+        buf: &'a [u8],
+        trusted_keys: &[PublicKey],
+    ) -> Result<SignatureLayerReader<'a, Cursor<&'a [u8]>>, Error> {
+        let mut config = ArchiveReaderConfig::new();
+        config.add_trusted_public_keys(trusted_keys);
+        let mut sign_r = SignatureLayerReader::new(
+            Box::new(RawLayerReader::new(Cursor::new(buf))),
+            &config.sign,
+            HEADER.to_vec(),
+        )?;
+        sign_r.initialize()?;
+        Ok(sign_r)
+    }
+
+    #[test]
+    fn sign_layer() {
+        let key = signing_key(0);
+        let public = PublicKey::from(&key);
+        let buf = build_signed(&key);
+        assert_eq!(buf.len() as u64, DATA.len() as u64 + TRAILER_SIZE);
+
+        let mut sign_r = open_signed(&buf, &[]).unwrap();
+        assert_eq!(sign_r.signer(), Some(public));
+        let mut output = Vec::new();
+        sign_r.read_to_end(&mut output).unwrap();
+        assert_eq!(output.as_slice(), DATA);
+
+        // The trailer is hidden
+        let mut output = Vec::new();
+        sign_r.seek(SeekFrom::End(-2)).unwrap();
+        sign_r.read_to_end(&mut output).unwrap();
+        assert_eq!(output.as_slice(), &DATA[DATA.len() - 2..]);
+        sign_r.seek(SeekFrom::Start(3)).unwrap();
+        sign_r.seek(SeekFrom::Current(2)).unwrap();
+        let mut output = [0u8; 2];
+        sign_r.read_exact(&mut output).unwrap();
+        assert_eq!(&output, &DATA[5..7]);
+
+        // Trusted keys
+        assert!(open_signed(&buf, &[public]).is_ok());
+        let other = PublicKey::from(&signing_key(1));
+        match open_signed(&buf, &[other]).err() {
+            Some(Error::UntrustedSignature) => {}
+            _ => panic!("Expected an UntrustedSignature error"),
+        }
+    }
+
+    #[test]
+    fn sign_layer_tampered() {
+        let key = signing_key(0);
+        let mut buf = build_signed(&key);
+        buf[3] ^= 1;
+        match open_signed(&buf, &[]).err() {
+            Some(Error::InvalidSignature) => {}
+            _ => panic!("Expected an InvalidSignature error"),
+        }
+
+        // The header is signed too
+        let buf = build_signed(&key);
+        let mut sign_r = SignatureLayerReader::new(
+            Box::new(RawLayerReader::new(Cursor::new(buf.as_slice()))),
+            &SignatureReaderConfig::default(),
+            b"other header".to_vec(),
+        )
+        .unwrap();
+        match sign_r.initialize() {
+            Err(Error::InvalidSignature) => {}
+            _ => panic!("Expected an InvalidSignature error"),
+        }
+
+        // Truncated, without the whole trailer
+        match open_signed(&buf[..buf.len() - 1], &[]).err() {
+            Some(Error::InvalidSignature) => {}
+            _ => panic!("Expected an InvalidSignature error"),
+        }
+    }
+}
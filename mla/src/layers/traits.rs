@@ -16,6 +16,13 @@ pub trait LayerWriter<'a, W: Write>: Write {
     /// This method is responsible of recursively calling (postfix) `finalize`
     /// on inner layer if any
     fn finalize(&mut self) -> Result<(), Error>;
+
+    /// Hint whether the data written next is better stored as is than
+    /// compressed
+    ///
+    /// Only used by the compression layer; the layers above it forward the
+    /// hint
+    fn set_store_hint(&mut self, _store: bool) {}
 }
 
 /// Trait to be implemented by layer readers
@@ -33,6 +40,16 @@ pub trait LayerReader<'a, R: Read + Seek>: Read + Seek {
     /// This method is responsible of recursively calling (postfix) `initialize`
     /// on inner layer if any
     fn initialize(&mut self) -> Result<(), Error>;
+
+    /// Mark the start of the reading of a new entry
+    ///
+    /// Only used by the compression layer, the outermost one, for its per
+    /// entry limit
+    fn start_entry(&mut self) {}
+
+    /// Mark the following reads as not part of a single entry, such as for
+    /// a linear extraction
+    fn end_entry(&mut self) {}
 }
 
 /// Trait to be implemented by layer for their fail-safe mode reading
@@ -1,47 +1,103 @@
-use std::collections::HashMap;
-use std::convert::{TryFrom, TryInto};
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::convert::TryFrom;
+use std::hash::{Hash, Hasher};
 use std::io;
-use std::io::{Read, Seek, SeekFrom, Write};
+use std::io::{IoSlice, Read, Seek, SeekFrom, Write};
 #[macro_use]
 extern crate bitflags;
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaChaRng;
 use serde::{Deserialize, Serialize};
 
 pub mod layers;
 use crate::layers::compress::{
     CompressionLayerFailSafeReader, CompressionLayerReader, CompressionLayerWriter,
+    UNCOMPRESSED_DATA_SIZE,
 };
 use crate::layers::encrypt::{
-    EncryptionLayerFailSafeReader, EncryptionLayerReader, EncryptionLayerWriter,
+    EncryptionLayerFailSafeReader, EncryptionLayerReader, EncryptionLayerStreamReader,
+    EncryptionLayerWriter,
 };
+use crate::layers::fec::{FecLayerFailSafeReader, FecLayerReader, FecLayerWriter};
 use crate::layers::position::PositionLayerWriter;
 use crate::layers::raw::{RawLayerFailSafeReader, RawLayerReader, RawLayerWriter};
+use crate::layers::sign::{
+    SignatureLayerFailSafeReader, SignatureLayerReader, SignatureLayerWriter,
+};
 use crate::layers::traits::{LayerFailSafeReader, LayerReader, LayerWriter};
 pub mod errors;
-use crate::errors::{Error, FailSafeReadError};
+use crate::errors::{ConfigError, Error, FailSafeReadError};
 
 pub mod config;
-use crate::config::{ArchivePersistentConfig, ArchiveReaderConfig, ArchiveWriterConfig};
+use crate::config::{
+    seeded_rng, ArchivePersistentConfig, ArchivePersistentConfigV1, ArchivePersistentConfigV12,
+    ArchivePersistentConfigV14, ArchivePersistentConfigV17, ArchivePersistentConfigV18,
+    ArchivePersistentConfigV2, ArchivePersistentConfigV3, ArchivePersistentConfigV4,
+    ArchivePersistentConfigV5, ArchivePersistentConfigV6, ArchiveReaderConfig, ArchiveWriterConfig,
+    CheckpointInterval, ReaderLimits, PADDING_STREAM,
+};
 
 #[doc(hidden)]
 pub mod crypto;
-use crate::crypto::hash::{HashWrapperReader, Sha256Hash};
-use sha2::{Digest, Sha256};
+use crate::crypto::hash::{FileHasher, HashAlgorithm, HashWrapperReader};
 use x25519_dalek::PublicKey;
 
 pub mod helpers;
+use crate::helpers::ProgressReader;
+
+pub mod volumes;
+
+mod delta;
+use crate::delta::{delta_encode, DeltaIndex, DeltaOp};
 
 // -------- Constants --------
 
 const MLA_MAGIC: &[u8; 3] = b"MLA";
-const MLA_FORMAT_VERSION: u32 = 1;
+const MLA_FORMAT_VERSION: u32 = 19;
+/// Last format version without block tags
+const UNTAGGED_FORMAT_VERSION: u32 = 10;
+/// Last format version without the choice of the compression algorithm
+const BROTLI_ONLY_FORMAT_VERSION: u32 = 12;
+/// Last format version without the kind of each compressed block
+const UNKINDED_FORMAT_VERSION: u32 = 13;
+/// Last format version without the choice of the hash algorithm (always
+/// SHA-256)
+const SHA256_ONLY_FORMAT_VERSION: u32 = 14;
+/// Last format version without `FileCopy` blocks
+const COPYLESS_FORMAT_VERSION: u32 = 15;
+/// Last format version without `FileMetadata` blocks
+const METADATALESS_FORMAT_VERSION: u32 = 16;
+/// Last format version without the archive comment
+const COMMENTLESS_FORMAT_VERSION: u32 = 17;
+/// Last format version without the recipient key hints
+const HINTLESS_FORMAT_VERSION: u32 = 18;
+/// Size of the hashes written in `EndOfFile` blocks. Others are written in
+/// `SizedEndOfFile` blocks
+const END_OF_FILE_HASH_SIZE: usize = 32;
 /// Maximum number of UTF-8 characters supported in each file's "name" (which is free
 /// to be used as a filename, an absolute path, or... ?). 32KiB was chosen because it
 /// supports any path a Windows NT, Linux, FreeBSD, OpenBSD, or NetBSD kernel supports.
 const FILENAME_MAX_SIZE: u64 = 65536;
+/// Maximum size of the serialized metadata of a file, leaving room for
+/// the extended attributes (up to 64KiB on Linux) and future fields
+const METADATA_MAX_SIZE: u64 = 256 * 1024;
 /// Maximum allowed object size (in bytes) to deserialize in-memory, to avoid DoS on
 /// malformed files
 pub(crate) const BINCODE_MAX_DESERIALIZE: u64 = 512 * 1024 * 1024;
+/// Minimal size of a serialized footer entry: the name length, the offsets
+/// count, the size and the EndOfFile offset
+const FOOTER_ENTRY_MIN_SIZE: u64 = 4 * 8;
+/// Size of the `Padding` block type and length
+const PADDING_BLOCK_HEADER_SIZE: u64 = 1 + 8;
+/// Size of the `EndOfArchiveData` block, without tag
+const END_OF_ARCHIVE_DATA_SIZE: u64 = 1;
+/// Size of the tag following each block header
+const BLOCK_TAG_SIZE: u64 = 4;
+/// Size of the buffer used to generate padding bytes
+const PADDING_BUF_SIZE: usize = 4096;
 
 bitflags! {
     /// Available layers. Order is relevant:
@@ -49,12 +105,16 @@ bitflags! {
     /// [File to blocks decomposition]
     /// [Compression (COMPRESS)]
     /// [Encryption (ENCRYPT)]
+    /// [Signature (SIGN)]
+    /// [Forward error correction (FEC)]
     /// [Raw File I/O]
     /// ```
     #[derive(Serialize, Deserialize)]
     pub struct Layers: u8 {
         const ENCRYPT = 0b0000_0001;
         const COMPRESS = 0b0000_0010;
+        const SIGN = 0b0000_0100;
+        const FEC = 0b0000_1000;
         /// Recommended layering
         const DEFAULT = Self::ENCRYPT.bits | Self::COMPRESS.bits;
         /// No additional layer (ie, for debugging purpose)
@@ -86,13 +146,90 @@ impl ArchiveHeader {
             return Err(Error::WrongMagic);
         }
         let format_version = src.read_u32::<LittleEndian>()?;
-        if format_version != MLA_FORMAT_VERSION {
-            return Err(Error::UnsupportedVersion);
-        }
-        let config: ArchivePersistentConfig = match bincode::config()
-            .limit(BINCODE_MAX_DESERIALIZE)
-            .deserialize_from(src)
-        {
+        let mut bincode_config = bincode::config();
+        bincode_config.limit(BINCODE_MAX_DESERIALIZE);
+        let config: Result<ArchivePersistentConfig, _> = match format_version {
+            MLA_FORMAT_VERSION => bincode_config.deserialize_from(src),
+            // Format version 18 lacks the recipient key hints
+            18 => bincode_config
+                .deserialize_from::<_, ArchivePersistentConfigV18>(src)
+                .map(|config| config.into()),
+            // Format version 17 also lacks the comment
+            17 => bincode_config
+                .deserialize_from::<_, ArchivePersistentConfigV17>(src)
+                .map(|config| config.into()),
+            // Format version 16 has the same header, and also lacks the
+            // `FileMetadata` blocks
+            16 => bincode_config
+                .deserialize_from::<_, ArchivePersistentConfigV17>(src)
+                .map(|config| config.into()),
+            // Format version 15 also lacks the `FileCopy` blocks
+            15 => bincode_config
+                .deserialize_from::<_, ArchivePersistentConfigV17>(src)
+                .map(|config| config.into()),
+            // Format version 14 also lacks the choice of the hash algorithm (always
+            // SHA-256)
+            14 => bincode_config
+                .deserialize_from::<_, ArchivePersistentConfigV14>(src)
+                .map(|config| config.into()),
+            // Format version 13 has the same header, and also lacks the kind
+            // of the compressed blocks
+            13 => bincode_config
+                .deserialize_from::<_, ArchivePersistentConfigV14>(src)
+                .map(|config| config.into()),
+            // Format version 12 also lacks the choice of the compression
+            // algorithm (always Brotli)
+            12 => bincode_config
+                .deserialize_from::<_, ArchivePersistentConfigV12>(src)
+                .map(|config| config.into()),
+            // Format version 11 has the same header, and also lacks the
+            // checkpoint blocks
+            11 => bincode_config
+                .deserialize_from::<_, ArchivePersistentConfigV12>(src)
+                .map(|config| config.into()),
+            // Format version 10 also lacks the block tags
+            10 => bincode_config
+                .deserialize_from::<_, ArchivePersistentConfigV12>(src)
+                .map(|config| config.into()),
+            // Format version 9 also lacks the forward error correction layer
+            9 => bincode_config
+                .deserialize_from::<_, ArchivePersistentConfigV12>(src)
+                .map(|config| config.into()),
+            // Format version 8 also lacks the signature layer
+            8 => bincode_config
+                .deserialize_from::<_, ArchivePersistentConfigV12>(src)
+                .map(|config| config.into()),
+            // Format version 7 also lacks the padding blocks
+            7 => bincode_config
+                .deserialize_from::<_, ArchivePersistentConfigV12>(src)
+                .map(|config| config.into()),
+            // Format version 6 also lacks the rekeying support
+            6 => bincode_config
+                .deserialize_from::<_, ArchivePersistentConfigV6>(src)
+                .map(|config| config.into()),
+            // Format version 5 also lacks the cipher agility
+            5 => bincode_config
+                .deserialize_from::<_, ArchivePersistentConfigV5>(src)
+                .map(|config| config.into()),
+            // Format version 4 also lacks the hybrid recipients support
+            4 => bincode_config
+                .deserialize_from::<_, ArchivePersistentConfigV4>(src)
+                .map(|config| config.into()),
+            // Format version 3 also lacks the escrow support
+            3 => bincode_config
+                .deserialize_from::<_, ArchivePersistentConfigV3>(src)
+                .map(|config| config.into()),
+            // Format version 2 also lacks the threshold support
+            2 => bincode_config
+                .deserialize_from::<_, ArchivePersistentConfigV2>(src)
+                .map(|config| config.into()),
+            // Format version 1 also lacks the password support
+            1 => bincode_config
+                .deserialize_from::<_, ArchivePersistentConfigV1>(src)
+                .map(|config| config.into()),
+            _ => return Err(Error::UnsupportedVersion),
+        };
+        let config = match config {
             Ok(config) => config,
             _ => {
                 return Err(Error::DeserializationError);
@@ -107,17 +244,72 @@ impl ArchiveHeader {
     fn dump<T: Write>(&self, dest: &mut T) -> Result<(), Error> {
         dest.write_all(MLA_MAGIC)?;
         dest.write_u32::<LittleEndian>(self.format_version)?;
-        if bincode::config()
-            .limit(BINCODE_MAX_DESERIALIZE)
-            .serialize_into(dest, &self.config)
-            .is_err()
-        {
+        let mut bincode_config = bincode::config();
+        bincode_config.limit(BINCODE_MAX_DESERIALIZE);
+        let result = if self.format_version > HINTLESS_FORMAT_VERSION {
+            bincode_config.serialize_into(dest, &self.config)
+        } else if self.format_version > COMMENTLESS_FORMAT_VERSION {
+            // Older format versions, kept when adding files to an existing
+            // archive, lack the key hints
+            bincode_config.serialize_into(dest, &ArchivePersistentConfigV18::from(&self.config))
+        } else if self.format_version > SHA256_ONLY_FORMAT_VERSION {
+            // Older format versions, kept when adding files to an existing
+            // archive, lack the comment
+            bincode_config.serialize_into(dest, &ArchivePersistentConfigV17::from(&self.config))
+        } else if self.format_version > BROTLI_ONLY_FORMAT_VERSION {
+            // Older format versions, kept when adding files to an existing
+            // archive, lack the hash algorithm
+            bincode_config.serialize_into(dest, &ArchivePersistentConfigV14::from(&self.config))
+        } else {
+            // Older format versions, kept when adding files to an existing
+            // archive, lack the compression algorithm
+            bincode_config.serialize_into(dest, &ArchivePersistentConfigV12::from(&self.config))
+        };
+        if result.is_err() {
             return Err(Error::SerializationError);
         }
         Ok(())
     }
 }
 
+/// Amount of a block content written along with the block header
+const DUMP_HEAD_SIZE: u64 = 64 * 1024;
+
+/// Write all of `bufs` to `dest` through `write_vectored`, so the layers can
+/// process them at once, such as with a single cipher call
+fn write_all_vectored<W: Write + ?Sized>(
+    dest: &mut W,
+    mut bufs: &mut [IoSlice<'_>],
+) -> io::Result<()> {
+    // Skip the leading empty slices
+    IoSlice::advance_slices(&mut bufs, 0);
+    while !bufs.is_empty() {
+        match dest.write_vectored(bufs) {
+            Ok(0) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::WriteZero,
+                    "failed to write whole buffer",
+                ))
+            }
+            Ok(count) => IoSlice::advance_slices(&mut bufs, count),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+/// Get back the serialized header of `src`, which has just been read
+///
+/// The header is part of the signed content
+fn read_header_bytes<R: Read + Seek>(src: &mut R) -> Result<Vec<u8>, Error> {
+    let header_size = src.stream_position()?;
+    src.seek(SeekFrom::Start(0))?;
+    let mut header = vec![0u8; header_size as usize];
+    src.read_exact(&mut header)?;
+    Ok(header)
+}
+
 // -------- MLA Format Footer --------
 
 pub struct ArchiveFooter {
@@ -131,17 +323,15 @@ impl ArchiveFooter {
     /// [files_info][files_info length]
     /// ```
 
-    /// Performs zero-copy serialization of a footer
-    fn serialize_into<W: Write>(
-        mut dest: W,
-        files_info: &HashMap<String, ArchiveFileID>,
-        ids_info: &HashMap<ArchiveFileID, FileInfo>,
-    ) -> Result<(), Error> {
-        let mut serialization_len = 0;
-
-        // Combine `files_info` and `ids_info` to ArchiveFooter.files_info,
-        // avoiding copies (only references)
-        let mut tmp: HashMap<&String, &FileInfo> = HashMap::new();
+    /// Combine `files_info` and `ids_info` to ArchiveFooter.files_info,
+    /// avoiding copies (only references)
+    fn combine<'b>(
+        files_info: &'b HashMap<String, ArchiveFileID>,
+        ids_info: &'b HashMap<ArchiveFileID, FileInfo>,
+    ) -> Result<BTreeMap<&'b String, &'b FileInfo>, Error> {
+        // Sorted by name, for the serialization to be reproducible. It is
+        // read back as any map
+        let mut tmp: BTreeMap<&String, &FileInfo> = BTreeMap::new();
         for (k, i) in files_info {
             let v = ids_info.get(i).ok_or_else(|| {
                 Error::WrongWriterState(
@@ -150,6 +340,30 @@ impl ArchiveFooter {
             })?;
             tmp.insert(k, v);
         }
+        Ok(tmp)
+    }
+
+    /// Size of the serialized footer, its length included
+    fn serialized_size(
+        files_info: &HashMap<String, ArchiveFileID>,
+        ids_info: &HashMap<ArchiveFileID, FileInfo>,
+    ) -> Result<u64, Error> {
+        let tmp = Self::combine(files_info, ids_info)?;
+        match bincode::serialized_size(&tmp) {
+            Ok(size) => Ok(size + 4),
+            Err(_) => Err(Error::SerializationError),
+        }
+    }
+
+    /// Performs zero-copy serialization of a footer
+    fn serialize_into<W: Write>(
+        mut dest: W,
+        files_info: &HashMap<String, ArchiveFileID>,
+        ids_info: &HashMap<ArchiveFileID, FileInfo>,
+    ) -> Result<(), Error> {
+        let mut serialization_len = 0;
+
+        let tmp = Self::combine(files_info, ids_info)?;
 
         if bincode::config()
             .limit(BINCODE_MAX_DESERIALIZE)
@@ -172,25 +386,275 @@ impl ArchiveFooter {
 
     /// Parses and instantiates a footer from serialized data
     pub fn deserialize_from<R: Read + Seek>(mut src: R) -> Result<ArchiveFooter, Error> {
+        let limits = ReaderLimits::default();
+        let (start, len) = Self::locate(&mut src, &limits)?;
+        Self::deserialize_at(src, start, len, &limits)
+    }
+
+    /// Return the position and the length of the serialized footer, checked
+    /// against `limits`
+    pub(crate) fn locate<R: Read + Seek>(
+        mut src: R,
+        limits: &ReaderLimits,
+    ) -> Result<(u64, u64), Error> {
         // Read the footer length
         let pos = src.seek(SeekFrom::End(-4))?;
         let len = src.read_u32::<LittleEndian>()? as u64;
+        if limits.max_footer_size.is_some_and(|max| len > max) {
+            return Err(Error::FooterTooLarge);
+        }
+        let start = pos.checked_sub(len).ok_or(Error::DeserializationError)?;
+
+        // The serialized map starts with its number of entries
+        if limits.max_entries.is_some() || limits.strict {
+            src.seek(SeekFrom::Start(start))?;
+            let count = src.read_u64::<LittleEndian>()?;
+            if limits.max_entries.is_some_and(|max| count > max) {
+                return Err(Error::TooManyEntries);
+            }
+            if limits.strict && count.saturating_mul(FOOTER_ENTRY_MIN_SIZE) > len {
+                return Err(Error::DeserializationError);
+            }
+        }
+        Ok((start, len))
+    }
 
+    /// Parses the footer of `len` bytes at `start`, as located by `locate`
+    pub(crate) fn deserialize_at<R: Read + Seek>(
+        mut src: R,
+        start: u64,
+        len: u64,
+        limits: &ReaderLimits,
+    ) -> Result<ArchiveFooter, Error> {
         // Prepare for deserialization
-        src.seek(SeekFrom::Start(pos - len))?;
+        src.seek(SeekFrom::Start(start))?;
 
-        // Read files_info
+        // Read files_info. In strict mode, lengths read are bounded by the
+        // footer size, before any allocation
+        let mut footer_src = src.take(len);
         let files_info: HashMap<String, FileInfo> = match bincode::config()
-            .limit(BINCODE_MAX_DESERIALIZE)
-            .deserialize_from(&mut src.take(len))
+            .limit(limits.footer_bincode_limit(len))
+            .deserialize_from(&mut footer_src)
         {
             Ok(finfo) => finfo,
             _ => {
                 return Err(Error::DeserializationError);
             }
         };
+
+        for (filename, file_info) in &files_info {
+            Self::check_entry(filename, file_info, start, limits)?;
+        }
+        // In strict mode, the whole footer is used
+        if limits.strict && footer_src.limit() != 0 {
+            return Err(Error::DeserializationError);
+        }
         Ok(ArchiveFooter { files_info })
     }
+
+    /// Check an entry of the footer starting at `start` against `limits`
+    fn check_entry(
+        filename: &str,
+        file_info: &FileInfo,
+        start: u64,
+        limits: &ReaderLimits,
+    ) -> Result<(), Error> {
+        if let Some(max) = limits.filename_max_size() {
+            if filename.len() as u64 > max {
+                return Err(Error::FilenameTooLong);
+            }
+        }
+        if limits.strict {
+            // The file blocks are before the footer
+            let in_data = |offset: &u64| *offset < start;
+            if file_info.offsets.is_empty()
+                || !file_info.offsets.iter().all(in_data)
+                || !in_data(&file_info.eof_offset)
+            {
+                return Err(Error::DeserializationError);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Read a footer entry, a filename and its `FileInfo` as serialized in the
+/// footer map
+fn read_footer_entry<R: Read>(src: R, limit: u64) -> Result<(String, FileInfo), Error> {
+    match bincode::config().limit(limit).deserialize_from(src) {
+        Ok(entry) => Ok(entry),
+        Err(_) => Err(Error::DeserializationError),
+    }
+}
+
+/// Read the footer entry at `pos` in `src`, returning it with the position
+/// of the next one
+fn read_footer_entry_at<R: Read + Seek>(
+    src: &mut R,
+    pos: u64,
+) -> Result<(String, FileInfo, u64), Error> {
+    src.seek(SeekFrom::Start(pos))?;
+    let (filename, file_info) = read_footer_entry(&mut *src, BINCODE_MAX_DESERIALIZE)?;
+    let next = pos + footer_entry_size(&filename, &file_info)?;
+    Ok((filename, file_info, next))
+}
+
+fn footer_entry_size(filename: &str, file_info: &FileInfo) -> Result<u64, Error> {
+    match bincode::serialized_size(&(filename, file_info)) {
+        Ok(size) => Ok(size),
+        Err(_) => Err(Error::SerializationError),
+    }
+}
+
+fn name_hash(filename: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    filename.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Footer left in the archive, for a reader with a memory budget (see
+/// `ArchiveReaderConfig::set_memory_budget`): its entries are read from the
+/// archive when needed
+#[derive(Debug)]
+pub(crate) struct LazyFooter {
+    /// Position of the first entry, right after the number of entries
+    start: u64,
+    /// Number of entries
+    count: u64,
+    /// Index of the entries, if it fits in the memory budget. Otherwise,
+    /// entries are looked up by reading the footer
+    index: Option<LazyFooterIndex>,
+}
+
+/// Positions of the entries of a `LazyFooter`
+#[derive(Debug)]
+struct LazyFooterIndex {
+    /// (Hash of the filename, position of the entry), sorted
+    by_name: Vec<(u64, u64)>,
+    /// (Offset of the FileStart, position of the entry), sorted
+    by_start: Vec<(u64, u64)>,
+}
+
+/// Memory used by a `LazyFooterIndex` for each entry
+const LAZY_INDEX_ENTRY_SIZE: u64 = 4 * 8;
+
+impl LazyFooter {
+    /// Check the footer of `len` bytes at `start` (see
+    /// `ArchiveFooter::locate`), without keeping it, and index its entries if
+    /// the index fits in `budget`
+    fn open<R: Read + Seek>(
+        src: &mut R,
+        start: u64,
+        len: u64,
+        limits: &ReaderLimits,
+        budget: u64,
+    ) -> Result<Self, Error> {
+        src.seek(SeekFrom::Start(start))?;
+        let count = src.read_u64::<LittleEndian>()?;
+        let indexed = count.saturating_mul(LAZY_INDEX_ENTRY_SIZE) <= budget;
+        let mut by_name = Vec::new();
+        let mut by_start = Vec::new();
+
+        // Entries are read one by one, and dropped once checked
+        let limit = limits.footer_bincode_limit(len);
+        let mut entries = io::BufReader::new((&mut *src).take(len.saturating_sub(8)));
+        let mut pos = start + 8;
+        for _ in 0..count {
+            let (filename, file_info) = read_footer_entry(&mut entries, limit)?;
+            ArchiveFooter::check_entry(&filename, &file_info, start, limits)?;
+            if indexed {
+                by_name.push((name_hash(&filename), pos));
+                if let Some(first) = file_info.offsets.first() {
+                    by_start.push((*first, pos));
+                }
+            }
+            pos += footer_entry_size(&filename, &file_info)?;
+        }
+        // In strict mode, the whole footer is used
+        if limits.strict && pos != start + len {
+            return Err(Error::DeserializationError);
+        }
+
+        let index = if indexed {
+            by_name.sort_unstable();
+            by_start.sort_unstable();
+            Some(LazyFooterIndex { by_name, by_start })
+        } else {
+            None
+        };
+        Ok(LazyFooter {
+            start: start + 8,
+            count,
+            index,
+        })
+    }
+
+    /// Return the entry of `filename`, if any
+    fn find<R: Read + Seek>(&self, src: &mut R, filename: &str) -> Result<Option<FileInfo>, Error> {
+        match &self.index {
+            Some(index) => {
+                let hash = name_hash(filename);
+                let first = index.by_name.partition_point(|(key, _)| *key < hash);
+                for (_, pos) in index.by_name[first..]
+                    .iter()
+                    .take_while(|(key, _)| *key == hash)
+                {
+                    let (name, file_info, _) = read_footer_entry_at(src, *pos)?;
+                    if name == filename {
+                        return Ok(Some(file_info));
+                    }
+                }
+                Ok(None)
+            }
+            None => self.scan(src, |name, _| name == filename),
+        }
+    }
+
+    /// Return the entry of the file whose FileStart is at `start`, if any
+    fn find_by_start<R: Read + Seek>(
+        &self,
+        src: &mut R,
+        start: u64,
+    ) -> Result<Option<FileInfo>, Error> {
+        match &self.index {
+            Some(index) => {
+                let first = index.by_start.partition_point(|(key, _)| *key < start);
+                match index.by_start.get(first) {
+                    Some((key, pos)) if *key == start => {
+                        Ok(Some(read_footer_entry_at(src, *pos)?.1))
+                    }
+                    _ => Ok(None),
+                }
+            }
+            None => self.scan(src, |_, file_info| {
+                file_info.offsets.first() == Some(&start)
+            }),
+        }
+    }
+
+    /// Return the first entry, in the footer order, matching `predicate`
+    fn scan<R: Read + Seek, F: FnMut(&str, &FileInfo) -> bool>(
+        &self,
+        src: &mut R,
+        mut predicate: F,
+    ) -> Result<Option<FileInfo>, Error> {
+        src.seek(SeekFrom::Start(self.start))?;
+        let mut entries = io::BufReader::new(src);
+        for _ in 0..self.count {
+            let (filename, file_info) = read_footer_entry(&mut entries, BINCODE_MAX_DESERIALIZE)?;
+            if predicate(&filename, &file_info) {
+                return Ok(Some(file_info));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Entries of the footer, loaded in memory or left in the archive
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum FooterRef<'a> {
+    Loaded(&'a HashMap<String, FileInfo>),
+    Lazy(&'a LazyFooter),
 }
 
 // -------- Writer --------
@@ -201,7 +665,12 @@ impl ArchiveFooter {
 enum ArchiveFileBlockType {
     FileStart = 0x00,
     FileContent = 0x01,
+    Padding = 0x02,
+    Checkpoint = 0x03,
+    FileCopy = 0x04,
+    FileMetadata = 0x05,
 
+    SizedEndOfFile = 0xFD,
     EndOfArchiveData = 0xFE,
     EndOfFile = 0xFF,
 }
@@ -214,8 +683,18 @@ impl TryFrom<u8> for ArchiveFileBlockType {
             Ok(ArchiveFileBlockType::FileStart)
         } else if value == ArchiveFileBlockType::FileContent as u8 {
             Ok(ArchiveFileBlockType::FileContent)
+        } else if value == ArchiveFileBlockType::Padding as u8 {
+            Ok(ArchiveFileBlockType::Padding)
+        } else if value == ArchiveFileBlockType::Checkpoint as u8 {
+            Ok(ArchiveFileBlockType::Checkpoint)
+        } else if value == ArchiveFileBlockType::FileCopy as u8 {
+            Ok(ArchiveFileBlockType::FileCopy)
+        } else if value == ArchiveFileBlockType::FileMetadata as u8 {
+            Ok(ArchiveFileBlockType::FileMetadata)
         } else if value == ArchiveFileBlockType::EndOfFile as u8 {
             Ok(ArchiveFileBlockType::EndOfFile)
+        } else if value == ArchiveFileBlockType::SizedEndOfFile as u8 {
+            Ok(ArchiveFileBlockType::SizedEndOfFile)
         } else if value == ArchiveFileBlockType::EndOfArchiveData as u8 {
             Ok(ArchiveFileBlockType::EndOfArchiveData)
         } else {
@@ -242,61 +721,210 @@ pub enum ArchiveFileBlock<T: Read> {
         data: Option<T>,
         id: ArchiveFileID,
     },
-    /// End of file (last block) - contains the SHA256 of the whole file
-    EndOfFile { id: ArchiveFileID, hash: Sha256Hash },
+    /// End of file (last block) - contains the hash of the whole file, with
+    /// the hash algorithm of the archive (SHA256 before format version 15)
+    ///
+    /// A 32 bytes hash is written in a `EndOfFile` block, others in a
+    /// `SizedEndOfFile` block, also holding the hash size
+    EndOfFile { id: ArchiveFileID, hash: Vec<u8> },
+    /// Padding, not related to any file, made of `length` random bytes
+    /// As for `FileContent`, on parsing, the data is just next to it
+    Padding { length: u64 },
+    /// Checkpoint of the archive index, made of `length` bytes of serialized
+    /// `CheckpointEntry`. As for `FileContent`, on parsing, the data is just
+    /// next to it
+    Checkpoint { length: u64, data: Option<T> },
+    /// File content, made of `length` bytes of the file starting at offset
+    /// `base` in the archive (its `FileStart`), from `offset` in it
+    ///
+    /// This file must be ended before this block. Unlike `FileContent`, no
+    /// data follows
+    FileCopy {
+        id: ArchiveFileID,
+        base: u64,
+        offset: u64,
+        length: u64,
+    },
+    /// Filesystem metadata of the file, right after its `FileStart`
+    FileMetadata {
+        id: ArchiveFileID,
+        metadata: FileMetadata,
+    },
     /// End of archive data (no more files after that)
     EndOfArchiveData,
 }
 
+/// File listed in a `Checkpoint` block
+#[derive(Serialize, Deserialize, Debug)]
+struct CheckpointEntry {
+    id: ArchiveFileID,
+    filename: String,
+    /// Size of the content written so far
+    size: u64,
+    /// Whether the file is ended
+    ended: bool,
+}
+
+/// Wraps a reader, computing the CRC32 of the bytes read through it
+struct Crc32Reader<'a, T: Read> {
+    inner: &'a mut T,
+    hasher: crc32fast::Hasher,
+}
+
+impl<'a, T: Read> Read for Crc32Reader<'a, T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        self.hasher.update(&buf[..count]);
+        Ok(count)
+    }
+}
+
+/// Since format version 11, each block header is followed by a tag, the CRC32
+/// of the header
+fn has_block_tags(format_version: u32) -> bool {
+    format_version > UNTAGGED_FORMAT_VERSION
+}
+
+/// Since format version 14, each compressed block starts with its kind,
+/// compressed or stored
+fn has_block_kinds(format_version: u32) -> bool {
+    format_version > UNKINDED_FORMAT_VERSION
+}
+
 impl<T> ArchiveFileBlock<T>
 where
     T: Read,
 {
-    fn dump<U: Write>(&mut self, dest: &mut U) -> Result<(), Error> {
+    /// Write the block in `dest`, with a tag after its header if `tagged`
+    fn dump<U: Write>(&mut self, dest: &mut U, tagged: bool) -> Result<(), Error> {
+        let mut header = Vec::new();
         match self {
             ArchiveFileBlock::FileStart { filename, id } => {
-                dest.write_u8(ArchiveFileBlockType::FileStart as u8)?;
-                dest.write_u64::<LittleEndian>(*id)?;
+                header.write_u8(ArchiveFileBlockType::FileStart as u8)?;
+                header.write_u64::<LittleEndian>(*id)?;
                 let bytes = filename.as_bytes();
                 let length = bytes.len() as u64;
                 if length > FILENAME_MAX_SIZE {
                     return Err(Error::FilenameTooLong);
                 }
-                dest.write_u64::<LittleEndian>(length)?;
-                dest.write_all(&bytes)?;
-                Ok(())
+                header.write_u64::<LittleEndian>(length)?;
+                header.write_all(bytes)?;
             }
             ArchiveFileBlock::FileContent { length, data, id } => {
-                dest.write_u8(ArchiveFileBlockType::FileContent as u8)?;
-                dest.write_u64::<LittleEndian>(*id)?;
-                dest.write_u64::<LittleEndian>(*length)?;
-                match data {
-                    None => {
-                        return Err(Error::AssertionError(String::from(
-                            "Data missing in file content",
-                        )));
-                    }
-                    Some(content) => {
-                        // TODO check length
-                        io::copy(&mut content.take(*length), dest)?;
-                    }
+                if data.is_none() {
+                    return Err(Error::AssertionError(String::from(
+                        "Data missing in file content",
+                    )));
                 }
-                Ok(())
+                header.write_u8(ArchiveFileBlockType::FileContent as u8)?;
+                header.write_u64::<LittleEndian>(*id)?;
+                header.write_u64::<LittleEndian>(*length)?;
+            }
+            ArchiveFileBlock::EndOfFile { id, hash } if hash.len() == END_OF_FILE_HASH_SIZE => {
+                header.write_u8(ArchiveFileBlockType::EndOfFile as u8)?;
+                header.write_u64::<LittleEndian>(*id)?;
+                header.write_all(hash)?;
             }
             ArchiveFileBlock::EndOfFile { id, hash } => {
-                dest.write_u8(ArchiveFileBlockType::EndOfFile as u8)?;
-                dest.write_u64::<LittleEndian>(*id)?;
-                dest.write_all(hash)?;
-                Ok(())
+                header.write_u8(ArchiveFileBlockType::SizedEndOfFile as u8)?;
+                header.write_u64::<LittleEndian>(*id)?;
+                let size = u8::try_from(hash.len()).map_err(|_| {
+                    Error::AssertionError(String::from("Hash too long for an EndOfFile"))
+                })?;
+                header.write_u8(size)?;
+                header.write_all(hash)?;
+            }
+            ArchiveFileBlock::Padding { length } => {
+                header.write_u8(ArchiveFileBlockType::Padding as u8)?;
+                header.write_u64::<LittleEndian>(*length)?;
+            }
+            ArchiveFileBlock::Checkpoint { length, data } => {
+                if data.is_none() {
+                    return Err(Error::AssertionError(String::from(
+                        "Data missing in checkpoint",
+                    )));
+                }
+                header.write_u8(ArchiveFileBlockType::Checkpoint as u8)?;
+                header.write_u64::<LittleEndian>(*length)?;
+            }
+            ArchiveFileBlock::FileCopy {
+                id,
+                base,
+                offset,
+                length,
+            } => {
+                header.write_u8(ArchiveFileBlockType::FileCopy as u8)?;
+                header.write_u64::<LittleEndian>(*id)?;
+                header.write_u64::<LittleEndian>(*base)?;
+                header.write_u64::<LittleEndian>(*offset)?;
+                header.write_u64::<LittleEndian>(*length)?;
+            }
+            ArchiveFileBlock::FileMetadata { id, metadata } => {
+                let bytes = match bincode::serialize(metadata) {
+                    Ok(bytes) => bytes,
+                    Err(_) => return Err(Error::SerializationError),
+                };
+                if bytes.len() as u64 > METADATA_MAX_SIZE {
+                    return Err(Error::SerializationError);
+                }
+                header.write_u8(ArchiveFileBlockType::FileMetadata as u8)?;
+                header.write_u64::<LittleEndian>(*id)?;
+                header.write_u64::<LittleEndian>(bytes.len() as u64)?;
+                header.write_all(&bytes)?;
             }
             ArchiveFileBlock::EndOfArchiveData => {
-                dest.write_u8(ArchiveFileBlockType::EndOfArchiveData as u8)?;
-                Ok(())
+                header.write_u8(ArchiveFileBlockType::EndOfArchiveData as u8)?;
+            }
+        }
+        if tagged {
+            let tag = crc32fast::hash(&header);
+            header.write_u32::<LittleEndian>(tag)?;
+        }
+
+        match self {
+            ArchiveFileBlock::FileContent {
+                length,
+                data: Some(content),
+                ..
+            }
+            | ArchiveFileBlock::Checkpoint {
+                length,
+                data: Some(content),
+            } => {
+                // TODO check length
+                // The header goes through the layers along with the start of
+                // the content, so small blocks are handled in a single call
+                let mut head = Vec::with_capacity(std::cmp::min(*length, DUMP_HEAD_SIZE) as usize);
+                content
+                    .by_ref()
+                    .take(std::cmp::min(*length, DUMP_HEAD_SIZE))
+                    .read_to_end(&mut head)?;
+                write_all_vectored(dest, &mut [IoSlice::new(&header), IoSlice::new(&head)])?;
+                io::copy(&mut content.take(*length - head.len() as u64), dest)?;
+            }
+            // `Padding` content is written by `ArchiveWriter::pad`
+            _ => dest.write_all(&header)?,
+        }
+        Ok(())
+    }
+
+    /// Read a block from `src`, checking the tag after its header if `tagged`
+    fn from(src: &mut T, tagged: bool) -> Result<Self, Error> {
+        let mut header = Crc32Reader {
+            inner: src,
+            hasher: crc32fast::Hasher::new(),
+        };
+        let block = Self::parse(&mut header)?;
+        if tagged {
+            let expected = header.hasher.finalize();
+            if src.read_u32::<LittleEndian>()? != expected {
+                return Err(Error::WrongBlockTag);
             }
         }
+        Ok(block)
     }
 
-    fn from(src: &mut T) -> Result<Self, Error> {
+    fn parse<U: Read>(src: &mut U) -> Result<Self, Error> {
         let byte = src.read_u8()?;
         match ArchiveFileBlockType::try_from(byte)? {
             ArchiveFileBlockType::FileStart => {
@@ -326,10 +954,53 @@ where
             }
             ArchiveFileBlockType::EndOfFile => {
                 let id = src.read_u64::<LittleEndian>()?;
-                let mut hash = Sha256Hash::default();
+                let mut hash = vec![0u8; END_OF_FILE_HASH_SIZE];
+                src.read_exact(&mut hash)?;
+                Ok(ArchiveFileBlock::EndOfFile { id, hash })
+            }
+            ArchiveFileBlockType::SizedEndOfFile => {
+                let id = src.read_u64::<LittleEndian>()?;
+                let size = src.read_u8()?;
+                let mut hash = vec![0u8; size as usize];
                 src.read_exact(&mut hash)?;
                 Ok(ArchiveFileBlock::EndOfFile { id, hash })
             }
+            ArchiveFileBlockType::Padding => {
+                let length = src.read_u64::<LittleEndian>()?;
+                // As for FileContent, the data is left in `src`
+                Ok(ArchiveFileBlock::Padding { length })
+            }
+            ArchiveFileBlockType::Checkpoint => {
+                let length = src.read_u64::<LittleEndian>()?;
+                // As for FileContent, the data is left in `src`
+                Ok(ArchiveFileBlock::Checkpoint { length, data: None })
+            }
+            ArchiveFileBlockType::FileCopy => {
+                let id = src.read_u64::<LittleEndian>()?;
+                let base = src.read_u64::<LittleEndian>()?;
+                let offset = src.read_u64::<LittleEndian>()?;
+                let length = src.read_u64::<LittleEndian>()?;
+                Ok(ArchiveFileBlock::FileCopy {
+                    id,
+                    base,
+                    offset,
+                    length,
+                })
+            }
+            ArchiveFileBlockType::FileMetadata => {
+                let id = src.read_u64::<LittleEndian>()?;
+                let length = src.read_u64::<LittleEndian>()?;
+                if length > METADATA_MAX_SIZE {
+                    return Err(Error::DeserializationError);
+                }
+                let mut bytes = vec![0u8; length as usize];
+                src.read_exact(&mut bytes)?;
+                let metadata = match FileMetadata::from_bytes(&bytes) {
+                    Ok(metadata) => metadata,
+                    Err(_) => return Err(Error::DeserializationError),
+                };
+                Ok(ArchiveFileBlock::FileMetadata { id, metadata })
+            }
             ArchiveFileBlockType::EndOfArchiveData => Ok(ArchiveFileBlock::EndOfArchiveData),
         }
     }
@@ -340,7 +1011,7 @@ pub enum ArchiveWriterState {
     /// Initialized, with files opened
     OpenedFiles {
         ids: Vec<ArchiveFileID>,
-        hashes: HashMap<ArchiveFileID, Sha256>,
+        hashes: HashMap<ArchiveFileID, FileHasher>,
     },
     /// File finalized, no more change allowed
     Finalized,
@@ -416,13 +1087,97 @@ macro_rules! check_state_file_opened {
     }};
 }
 
+/// Filesystem metadata of a file, see `FileOptions::set_metadata`
+///
+/// Each field is optional, as the source of a file may not provide it
+#[derive(Default, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileMetadata {
+    /// Last modification time, in seconds since the Unix epoch
+    pub mtime: Option<i64>,
+    /// Unix permission bits, such as 0o644, without the file type
+    pub mode: Option<u32>,
+    /// Unix ID of the owner
+    pub uid: Option<u32>,
+    /// Unix ID of the owner group
+    pub gid: Option<u32>,
+    /// Extended attributes, name -> value, such as SELinux labels
+    /// (`security.selinux`) or capabilities (`security.capability`)
+    pub xattrs: BTreeMap<Vec<u8>, Vec<u8>>,
+    /// Creation time, in seconds since the Unix epoch
+    pub btime: Option<i64>,
+    /// Windows file attributes, such as `FILE_ATTRIBUTE_HIDDEN` (0x2)
+    pub attributes: Option<u32>,
+}
+
+impl FileMetadata {
+    /// Deserialize a `FileMetadata` block content
+    ///
+    /// Fields added after the first ones are optional, as blocks written
+    /// before them end earlier
+    fn from_bytes(bytes: &[u8]) -> Result<Self, bincode::Error> {
+        let mut config = bincode::config();
+        config.limit(METADATA_MAX_SIZE);
+        let mut src = bytes;
+        let (mtime, mode, uid, gid) = config.deserialize_from(&mut src)?;
+        let mut metadata = Self {
+            mtime,
+            mode,
+            uid,
+            gid,
+            ..Self::default()
+        };
+        if !src.is_empty() {
+            metadata.xattrs = config.deserialize_from(&mut src)?;
+        }
+        if !src.is_empty() {
+            let (btime, attributes) = config.deserialize_from(&mut src)?;
+            metadata.btime = btime;
+            metadata.attributes = attributes;
+        }
+        Ok(metadata)
+    }
+}
+
+/// Options of a file added to an archive, for
+/// `ArchiveWriter::add_file_with_options`
+#[derive(Default, Clone, Debug)]
+pub struct FileOptions {
+    store: bool,
+    metadata: Option<FileMetadata>,
+}
+
+impl FileOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store the file content as is, instead of compressing it, for instance
+    /// for already compressed files
+    ///
+    /// The compression layer works on blocks of 4MB shared by the files: the
+    /// blocks starting in the file content are stored. The ones also holding
+    /// other files, and the content of a file being stored in an archive
+    /// whose format version predates stored blocks, may still be compressed
+    pub fn set_store(&mut self, store: bool) -> &mut FileOptions {
+        self.store = store;
+        self
+    }
+
+    /// Record the filesystem `metadata` of the file, given back by
+    /// `ArchiveReader::get_file_metadata`
+    ///
+    /// It is ignored if the archive format version predates metadata, when
+    /// adding files to an existing archive
+    pub fn set_metadata(&mut self, metadata: FileMetadata) -> &mut FileOptions {
+        self.metadata = Some(metadata);
+        self
+    }
+}
+
 pub struct ArchiveWriter<'a, W: 'a + Write> {
     /// MLA Archive format writer
     ///
     /// Configuration
-    // config is not used for now after archive creation,
-    // but it could in the future
-    #[allow(dead_code)]
     config: ArchiveWriterConfig,
     ///
     /// Internals part:
@@ -448,6 +1203,31 @@ pub struct ArchiveWriter<'a, W: 'a + Write> {
     next_id: ArchiveFileID,
     /// Current file being written (for continuous block detection)
     current_id: ArchiveFileID,
+    /// Called with the number of bytes of file content added, as they are
+    /// read
+    progress: Option<Box<dyn 'a + FnMut(u64)>>,
+    /// Whether block headers are followed by a tag. Only unset when adding
+    /// files to an archive in a format version without them
+    block_tags: bool,
+    /// Files to list in the next checkpoint: the ones started since the
+    /// previous checkpoint, and the ones still opened at that time
+    checkpoint_files: HashMap<ArchiveFileID, String>,
+    /// Bytes of file content, or ended files, since the previous checkpoint,
+    /// depending on the checkpoint interval
+    since_checkpoint: u64,
+    /// Opened files whose content is better stored than compressed
+    stored_ids: HashSet<ArchiveFileID>,
+    /// (Size, hash) -> ID of the ended file with this content, if
+    /// deduplication is enabled
+    content_ids: HashMap<(u64, Vec<u8>), ArchiveFileID>,
+    /// Whether file content can be copied from another file, with `FileCopy`
+    /// blocks. Only unset when adding files to an archive in a format
+    /// version without them
+    copy_blocks: bool,
+    /// Whether the filesystem metadata of files can be recorded, with
+    /// `FileMetadata` blocks. Only unset when adding files to an archive in a
+    /// format version without them
+    metadata_blocks: bool,
 }
 
 // This is an unstable feature for now (`Vec.remove_item`), use a function
@@ -460,21 +1240,145 @@ pub fn vec_remove_item<T: std::cmp::PartialEq>(vec: &mut Vec<T>, item: &T) -> Op
     Some(vec.remove(pos))
 }
 
+/// Read the header of the archive `src`, and get the header of a new archive
+/// sharing its layers and recipients
+///
+/// `reader_config` is loaded with the header of `src`, and `config` is updated
+/// to match the new header. The format version of `src` is also returned
+fn header_from_archive<R: Read>(
+    src: &mut R,
+    config: &mut ArchiveWriterConfig,
+    reader_config: &mut ArchiveReaderConfig,
+) -> Result<(ArchiveHeader, u32), Error> {
+    if config.seed.is_some() {
+        return Err(ConfigError::DeterministicSeedWithExistingKey.into());
+    }
+    let header = ArchiveHeader::from(src)?;
+    let src_format_version = header.format_version;
+    let layers_enabled = header.config.layers_enabled;
+    let recipients = header.config.encrypt.clone();
+    // The comment is kept, unless a new one is given
+    let comment = config
+        .comment
+        .clone()
+        .or_else(|| header.config.comment.clone());
+    // New data is compressed and hashed with the same algorithms
+    config
+        .compress
+        .reuse_algorithm(header.config.compress.as_ref());
+    config.hash_algorithm = header.config.hash_algorithm;
+    reader_config.load_persistent(header.config)?;
+    reader_config
+        .compress
+        .set_block_kinds(has_block_kinds(src_format_version));
+    config.set_layers(layers_enabled);
+
+    let header = ArchiveHeader {
+        format_version: MLA_FORMAT_VERSION,
+        config: ArchivePersistentConfig {
+            layers_enabled,
+            encrypt: match recipients {
+                Some(persistent) if layers_enabled.contains(Layers::ENCRYPT) => {
+                    let (key, _nonce) = reader_config
+                        .get_encrypt_parameters()
+                        .ok_or(Error::PrivateKeyNeeded)?;
+                    Some(config.encrypt.reuse_key(key, persistent))
+                }
+                _ => None,
+            },
+            compress: if layers_enabled.contains(Layers::COMPRESS) {
+                Some(config.compress.to_persistent())
+            } else {
+                None
+            },
+            hash_algorithm: config.hash_algorithm,
+            comment,
+        },
+    };
+    Ok((header, src_format_version))
+}
+
+/// On a finalized archive data stream `src`, get the footer, the position of
+/// the EndOfArchiveData, and the next unused ArchiveFileID
+fn read_archive_data_end<T: Read + Seek>(
+    src: &mut T,
+    tagged: bool,
+) -> Result<(ArchiveFooter, u64, ArchiveFileID), Error> {
+    let footer = ArchiveFooter::deserialize_from(&mut *src)?;
+
+    // Data: [...][EndOfArchiveData][footer][footer length]
+    let pos = src.seek(SeekFrom::End(-4))?;
+    let len = src.read_u32::<LittleEndian>()? as u64;
+    let end_of_archive_data_size = if tagged {
+        END_OF_ARCHIVE_DATA_SIZE + BLOCK_TAG_SIZE
+    } else {
+        END_OF_ARCHIVE_DATA_SIZE
+    };
+    let data_size = pos
+        .checked_sub(len + end_of_archive_data_size)
+        .ok_or(Error::DeserializationError)?;
+    src.seek(SeekFrom::Start(data_size))?;
+    match ArchiveFileBlock::from(src, tagged)? {
+        ArchiveFileBlock::EndOfArchiveData => {}
+        _ => {
+            return Err(Error::WrongBlockSubFileType);
+        }
+    }
+
+    // ArchiveFileIDs are attributed incrementally on file start, so the last
+    // started file has the greatest one
+    let next_id = match footer
+        .files_info
+        .values()
+        .filter_map(|file_info| file_info.offsets.first())
+        .max()
+    {
+        Some(offset) => {
+            src.seek(SeekFrom::Start(*offset))?;
+            match ArchiveFileBlock::from(src, tagged)? {
+                ArchiveFileBlock::FileStart { id, .. } => id + 1,
+                _ => {
+                    return Err(Error::WrongBlockSubFileType);
+                }
+            }
+        }
+        None => 0,
+    };
+    Ok((footer, data_size, next_id))
+}
+
 impl<'a, W: Write> ArchiveWriter<'a, W> {
     pub fn from_config(dest: W, config: ArchiveWriterConfig) -> Result<Self, Error> {
         // Ensure config is correct
         config.check()?;
 
         // Write archive header
-        let mut dest: Box<dyn LayerWriter<W>> = Box::new(RawLayerWriter::new(dest));
+        let mut header = Vec::new();
         ArchiveHeader {
             format_version: MLA_FORMAT_VERSION,
             config: config.to_persistent()?,
-            // TODO public_key hashes for easier decryption
         }
-        .dump(&mut dest)?;
+        .dump(&mut header)?;
+        let mut dest: Box<dyn LayerWriter<W>> = Box::new(RawLayerWriter::new(dest));
+        dest.write_all(&header)?;
+
+        Self::with_layers(dest, config, &header)
+    }
 
+    /// Enable layers on `dest`, whose serialized `header` has already been
+    /// written, and build an archive without any file
+    fn with_layers(
+        mut dest: Box<dyn 'a + LayerWriter<'a, W>>,
+        config: ArchiveWriterConfig,
+        header: &[u8],
+    ) -> Result<Self, Error> {
         // Enable layers depending on user option
+        if config.is_layers_enabled(Layers::FEC) {
+            dest = Box::new(FecLayerWriter::new(dest, &config.fec)?);
+        }
+        if config.is_layers_enabled(Layers::SIGN) {
+            dest = Box::new(SignatureLayerWriter::new(dest, &config.sign, header)?);
+        }
         if config.is_layers_enabled(Layers::ENCRYPT) {
             dest = Box::new(EncryptionLayerWriter::new(dest, &config.encrypt)?);
         }
@@ -498,6 +1402,14 @@ impl<'a, W: Write> ArchiveWriter<'a, W> {
             ids_info: HashMap::new(),
             next_id: 0,
             current_id: 0,
+            progress: None,
+            block_tags: true,
+            checkpoint_files: HashMap::new(),
+            since_checkpoint: 0,
+            stored_ids: HashSet::new(),
+            content_ids: HashMap::new(),
+            copy_blocks: true,
+            metadata_blocks: true,
         })
     }
 
@@ -507,35 +1419,282 @@ impl<'a, W: Write> ArchiveWriter<'a, W> {
         Self::from_config(dest, config)
     }
 
-    pub fn finalize(&mut self) -> Result<(), Error> {
-        // Check final state (empty ids, empty hashes)
-        check_state!(self.state, OpenedFiles);
-        match &mut self.state {
-            ArchiveWriterState::OpenedFiles { ids, hashes } => {
-                if !ids.is_empty() || !hashes.is_empty() {
-                    return Err(Error::WrongWriterState(
-                        "[Finalize] At least one file is still open".to_string(),
-                    ));
-                }
-            }
-            _ => {
-                // Never happens, due to `check_state!`
-                return Err(Error::WrongWriterState(
-                    "[Finalize] State have changes inside finalize".to_string(),
-                ));
-            }
-        }
-        self.state = ArchiveWriterState::Finalized;
-
-        // Mark the end of the data
+    /// Create a new archive, without any file, sharing the layers and
+    /// recipients of the archive `src`
+    ///
+    /// As for `from_archive`, `config` only provides the parameters for new
+    /// data, and `reader_config` must provide a private key if `src` is
+    /// encrypted.
+    pub fn from_archive_config<R: Read + Seek>(
+        dest: W,
+        mut config: ArchiveWriterConfig,
+        mut src: R,
+        mut reader_config: ArchiveReaderConfig,
+    ) -> Result<Self, Error> {
+        src.seek(SeekFrom::Start(0))?;
+        let (header, _) = header_from_archive(&mut src, &mut config, &mut reader_config)?;
 
-        // Use std::io::Empty as a readable placeholder type
-        ArchiveFileBlock::EndOfArchiveData::<std::io::Empty> {}.dump(&mut self.dest)?;
+        // Write archive header
+        let mut header_bytes = Vec::new();
+        header.dump(&mut header_bytes)?;
+        let mut dest: Box<dyn LayerWriter<W>> = Box::new(RawLayerWriter::new(dest));
+        dest.write_all(&header_bytes)?;
 
-        ArchiveFooter::serialize_into(&mut self.dest, &self.files_info, &self.ids_info)?;
+        Self::with_layers(dest, config, &header_bytes)
+    }
 
-        // Recursive call
-        self.dest.finalize()?;
+    /// Reopen the finalized archive `src`, to add new files to it
+    ///
+    /// The resulting archive, written in `dest`, contains the files of `src`
+    /// and shares its layers and recipients. `config` only provides the
+    /// parameters for new data, such as the compression level.
+    ///
+    /// Existing data is not recompressed: only the last compressed block is
+    /// rebuilt. If `src` is encrypted, `reader_config` must provide a
+    /// corresponding private key; data is then encrypted again using the same
+    /// key, with a fresh nonce.
+    pub fn from_archive<'b, R: 'b + Read + Seek>(
+        dest: W,
+        mut config: ArchiveWriterConfig,
+        mut src: R,
+        mut reader_config: ArchiveReaderConfig,
+    ) -> Result<Self, Error> {
+        src.seek(SeekFrom::Start(0))?;
+        let (mut header, src_format_version) =
+            header_from_archive(&mut src, &mut config, &mut reader_config)?;
+        let layers_enabled = header.config.layers_enabled;
+        if src_format_version <= HINTLESS_FORMAT_VERSION {
+            header.format_version = HINTLESS_FORMAT_VERSION;
+        }
+        if src_format_version <= COMMENTLESS_FORMAT_VERSION {
+            header.format_version = COMMENTLESS_FORMAT_VERSION;
+        }
+        // Existing blocks are kept as is, so new ones must share their framing
+        let metadata_blocks = src_format_version > METADATALESS_FORMAT_VERSION;
+        if !metadata_blocks {
+            header.format_version = METADATALESS_FORMAT_VERSION;
+        }
+        let copy_blocks = src_format_version > COPYLESS_FORMAT_VERSION;
+        if !copy_blocks {
+            header.format_version = COPYLESS_FORMAT_VERSION;
+        }
+        if src_format_version <= SHA256_ONLY_FORMAT_VERSION {
+            header.format_version = SHA256_ONLY_FORMAT_VERSION;
+        }
+        if !has_block_kinds(src_format_version) {
+            header.format_version = UNKINDED_FORMAT_VERSION;
+            config.compress.set_block_kinds(false);
+        }
+        let tagged = has_block_tags(src_format_version);
+        if !tagged {
+            header.format_version = UNTAGGED_FORMAT_VERSION;
+            // This format version lacks the checkpoint blocks too
+            config.checkpoint = None;
+        }
+        let src_header_bytes = read_header_bytes(&mut src)?;
+
+        // Pin the current position (after header) as the new 0
+        let mut raw_src = Box::new(RawLayerReader::new(src));
+        raw_src.reset_position()?;
+        let mut src: Box<dyn 'b + LayerReader<'b, R>> = raw_src;
+        if layers_enabled.contains(Layers::FEC) {
+            src = Box::new(FecLayerReader::new(src)?);
+        }
+        if layers_enabled.contains(Layers::SIGN) {
+            src = Box::new(SignatureLayerReader::new(
+                src,
+                &reader_config.sign,
+                src_header_bytes,
+            )?);
+        }
+        if layers_enabled.contains(Layers::ENCRYPT) {
+            src = Box::new(EncryptionLayerReader::new(src, &reader_config.encrypt)?);
+        }
+
+        // Existing data, up to the EndOfArchiveData excluded, is made of:
+        // - `kept_size` bytes of `src`, rewritten as is
+        // - `tail`, the uncompressed content of the last compressed block, to
+        // compress again with the new data
+        let (footer, data_size, next_id, kept_size, tail, compressed_sizes) =
+            if layers_enabled.contains(Layers::COMPRESS) {
+                let mut comp_src = CompressionLayerReader::new(src, &reader_config.compress)?;
+                comp_src.initialize()?;
+                let (footer, data_size, next_id) = read_archive_data_end(&mut comp_src, tagged)?;
+
+                let block_num = (data_size / UNCOMPRESSED_DATA_SIZE as u64) as usize;
+                let block_start = block_num as u64 * UNCOMPRESSED_DATA_SIZE as u64;
+                let mut tail = Vec::new();
+                comp_src.seek(SeekFrom::Start(block_start))?;
+                (&mut comp_src)
+                    .take(data_size - block_start)
+                    .read_to_end(&mut tail)?;
+
+                let mut compressed_sizes = comp_src
+                    .sizes_info
+                    .take()
+                    .ok_or(Error::MissingMetadata)?
+                    .compressed_sizes;
+                compressed_sizes.truncate(block_num);
+                let kept_size = compressed_sizes.iter().map(|v| *v as u64).sum();
+
+                src = comp_src.into_inner().ok_or_else(|| {
+                    Error::AssertionError("[from_archive] Missing inner layer".to_string())
+                })?;
+                (
+                    footer,
+                    data_size,
+                    next_id,
+                    kept_size,
+                    tail,
+                    compressed_sizes,
+                )
+            } else {
+                src.initialize()?;
+                let (footer, data_size, next_id) = read_archive_data_end(&mut src, tagged)?;
+                (
+                    footer,
+                    data_size,
+                    next_id,
+                    data_size,
+                    Vec::new(),
+                    Vec::new(),
+                )
+            };
+
+        // Write archive header
+        let mut header_bytes = Vec::new();
+        header.dump(&mut header_bytes)?;
+        let mut dest: Box<dyn LayerWriter<W>> = Box::new(RawLayerWriter::new(dest));
+        dest.write_all(&header_bytes)?;
+
+        // Rebuild the layers, then copy the existing data
+        if layers_enabled.contains(Layers::FEC) {
+            dest = Box::new(FecLayerWriter::new(dest, &config.fec)?);
+        }
+        if layers_enabled.contains(Layers::SIGN) {
+            dest = Box::new(SignatureLayerWriter::new(
+                dest,
+                &config.sign,
+                &header_bytes,
+            )?);
+        }
+        if layers_enabled.contains(Layers::ENCRYPT) {
+            dest = Box::new(EncryptionLayerWriter::new(dest, &config.encrypt)?);
+        }
+        src.seek(SeekFrom::Start(0))?;
+        io::copy(&mut (&mut src).take(kept_size), &mut dest)?;
+        if layers_enabled.contains(Layers::COMPRESS) {
+            dest = Box::new(CompressionLayerWriter::resume(
+                dest,
+                &config.compress,
+                compressed_sizes,
+            ));
+            dest.write_all(&tail)?;
+        }
+
+        // Upper layer must be a PositionLayer
+        let mut final_dest = Box::new(PositionLayerWriter::new(dest));
+        final_dest.set_position(data_size);
+
+        // Already existing files are not opened anymore. As only the
+        // ArchiveFileIDs of opened files are of interest, use placeholders IDs
+        // for them, always lower than `next_id`
+        let mut files_info = HashMap::new();
+        let mut ids_info = HashMap::new();
+        for (placeholder_id, (filename, file_info)) in footer.files_info.into_iter().enumerate() {
+            files_info.insert(filename, placeholder_id as ArchiveFileID);
+            ids_info.insert(placeholder_id as ArchiveFileID, file_info);
+        }
+
+        Ok(ArchiveWriter {
+            config,
+            dest: final_dest,
+            state: ArchiveWriterState::OpenedFiles {
+                ids: Vec::new(),
+                hashes: HashMap::new(),
+            },
+            files_info,
+            ids_info,
+            next_id,
+            current_id: next_id,
+            progress: None,
+            block_tags: tagged,
+            checkpoint_files: HashMap::new(),
+            since_checkpoint: 0,
+            stored_ids: HashSet::new(),
+            content_ids: HashMap::new(),
+            copy_blocks,
+            metadata_blocks,
+        })
+    }
+
+    pub fn finalize(&mut self) -> Result<(), Error> {
+        // Check final state (empty ids, empty hashes)
+        check_state!(self.state, OpenedFiles);
+        match &mut self.state {
+            ArchiveWriterState::OpenedFiles { ids, hashes } => {
+                if !ids.is_empty() || !hashes.is_empty() {
+                    return Err(Error::WrongWriterState(
+                        "[Finalize] At least one file is still open".to_string(),
+                    ));
+                }
+            }
+            _ => {
+                // Never happens, due to `check_state!`
+                return Err(Error::WrongWriterState(
+                    "[Finalize] State have changes inside finalize".to_string(),
+                ));
+            }
+        }
+        self.state = ArchiveWriterState::Finalized;
+
+        if let Some(bucket_size) = self.config.padding {
+            self.pad(bucket_size)?;
+        }
+
+        // Mark the end of the data
+
+        // Use std::io::Empty as a readable placeholder type
+        ArchiveFileBlock::EndOfArchiveData::<std::io::Empty> {}
+            .dump(&mut self.dest, self.block_tags)?;
+
+        ArchiveFooter::serialize_into(&mut self.dest, &self.files_info, &self.ids_info)?;
+
+        // Recursive call
+        self.dest.finalize()?;
+        Ok(())
+    }
+
+    /// Add a `Padding` block, so the archive data ends on a multiple of
+    /// `bucket_size`, once the `EndOfArchiveData` and the footer are written
+    fn pad(&mut self, bucket_size: u64) -> Result<(), Error> {
+        let tags_size = if self.block_tags {
+            2 * BLOCK_TAG_SIZE
+        } else {
+            0
+        };
+        let unpadded = self.dest.position()
+            + PADDING_BLOCK_HEADER_SIZE
+            + END_OF_ARCHIVE_DATA_SIZE
+            + tags_size
+            + ArchiveFooter::serialized_size(&self.files_info, &self.ids_info)?;
+        let length = (bucket_size - unpadded % bucket_size) % bucket_size;
+        ArchiveFileBlock::Padding::<std::io::Empty> { length }
+            .dump(&mut self.dest, self.block_tags)?;
+
+        // Random, to stay incompressible
+        let mut rng = match self.config.seed {
+            Some(seed) => seeded_rng(seed, PADDING_STREAM),
+            None => ChaChaRng::from_entropy(),
+        };
+        let mut buf = [0u8; PADDING_BUF_SIZE];
+        let mut remaining = length;
+        while remaining > 0 {
+            let count = std::cmp::min(remaining, PADDING_BUF_SIZE as u64) as usize;
+            rng.fill_bytes(&mut buf[..count]);
+            self.dest.write_all(&buf[..count])?;
+            remaining -= count as u64;
+        }
         Ok(())
     }
 
@@ -585,6 +1744,28 @@ impl<'a, W: Write> ArchiveWriter<'a, W> {
     }
 
     pub fn start_file(&mut self, filename: &str) -> Result<ArchiveFileID, Error> {
+        self.start_file_with_options(filename, &FileOptions::default())
+    }
+
+    /// Start the file `filename`, whose content is then given through the
+    /// returned `Write` handle, by chunks of any size, until its `finish`
+    ///
+    /// Unlike `add_file`, the size of the content does not have to be known
+    /// beforehand
+    pub fn start_entry<'c>(
+        &'c mut self,
+        filename: &str,
+    ) -> Result<helpers::EntryWriter<'a, 'c, W>, Error> {
+        let id = self.start_file(filename)?;
+        Ok(helpers::EntryWriter::new(self, id))
+    }
+
+    /// As `start_file`, with per-file `options`
+    pub fn start_file_with_options(
+        &mut self,
+        filename: &str,
+        options: &FileOptions,
+    ) -> Result<ArchiveFileID, Error> {
         check_state!(self.state, OpenedFiles);
 
         if self.files_info.contains_key(filename) {
@@ -596,6 +1777,12 @@ impl<'a, W: Write> ArchiveWriter<'a, W> {
         self.next_id += 1;
         self.current_id = id;
         self.files_info.insert(filename.to_string(), id);
+        if self.config.checkpoint.is_some() {
+            self.checkpoint_files.insert(id, filename.to_string());
+        }
+        if options.store {
+            self.stored_ids.insert(id);
+        }
 
         // Save the current position
         self.ids_info.insert(
@@ -611,12 +1798,12 @@ impl<'a, W: Write> ArchiveWriter<'a, W> {
             filename: filename.to_string(),
             id,
         }
-        .dump(&mut self.dest)?;
+        .dump(&mut self.dest, self.block_tags)?;
 
         match &mut self.state {
             ArchiveWriterState::OpenedFiles { ids, hashes } => {
                 ids.push(id);
-                hashes.insert(id, Sha256::default());
+                hashes.insert(id, FileHasher::new(self.config.hash_algorithm));
             }
             _ => {
                 // Never happens, due to `check_state!`
@@ -625,9 +1812,40 @@ impl<'a, W: Write> ArchiveWriter<'a, W> {
                 ));
             }
         }
+        if let Some(metadata) = &options.metadata {
+            self.append_file_metadata(id, metadata)?;
+        }
         Ok(id)
     }
 
+    /// Record the filesystem `metadata` of the file `id`, with a
+    /// `FileMetadata` block
+    ///
+    /// Readers look for it right after the `FileStart` block, so it must be
+    /// called right after the file is started. If the archive format version
+    /// predates `FileMetadata` blocks, nothing is recorded
+    pub(crate) fn append_file_metadata(
+        &mut self,
+        id: ArchiveFileID,
+        metadata: &FileMetadata,
+    ) -> Result<(), Error> {
+        check_state!(self.state, OpenedFiles);
+        if !self.metadata_blocks {
+            return Ok(());
+        }
+        if self.current_id != id {
+            return Err(Error::WrongWriterState(
+                "[AppendFileMetadata] The file must have just been started".to_string(),
+            ));
+        }
+        ArchiveFileBlock::FileMetadata::<std::io::Empty> {
+            id,
+            metadata: metadata.clone(),
+        }
+        .dump(&mut self.dest, self.block_tags)?;
+        Ok(())
+    }
+
     pub fn append_file_content<U: Read>(
         &mut self,
         id: ArchiveFileID,
@@ -643,14 +1861,30 @@ impl<'a, W: Write> ArchiveWriter<'a, W> {
 
         self.mark_continuous_block(id)?;
         self.extend_file_size(id, size)?;
+        let src = ProgressReader::new(src, self.progress.as_deref_mut());
         let src = self.state.wrap_with_hash(id, src)?;
 
+        let store = self.stored_ids.contains(&id);
+        if store {
+            self.dest.set_store_hint(true);
+        }
         ArchiveFileBlock::FileContent {
             id,
             length: size,
             data: Some(src),
         }
-        .dump(&mut self.dest)
+        .dump(&mut self.dest, self.block_tags)?;
+        if store {
+            self.dest.set_store_hint(false);
+        }
+
+        if let Some(CheckpointInterval::Bytes(interval)) = self.config.checkpoint {
+            self.since_checkpoint += size;
+            if self.since_checkpoint >= interval {
+                self.checkpoint()?;
+            }
+        }
+        Ok(())
     }
 
     pub fn end_file(&mut self, id: ArchiveFileID) -> Result<(), Error> {
@@ -662,10 +1896,7 @@ impl<'a, W: Write> ArchiveWriter<'a, W> {
                     Error::WrongWriterState("[EndFile] Unable to retrieve the hash".to_string())
                 })?;
                 vec_remove_item(ids, &id);
-                hash.finalize().try_into().map_err(
-                    // Never happens, as hash is a Sha256, to a Sha256Hash
-                    |_| Error::WrongWriterState("[EndFile] Hash with wrong size".to_string()),
-                )?
+                hash.finalize()
             }
             _ => {
                 // Never happens, due to `check_state_file_opened!`
@@ -675,20 +1906,319 @@ impl<'a, W: Write> ArchiveWriter<'a, W> {
             }
         };
 
+        self.stored_ids.remove(&id);
         self.mark_continuous_block(id)?;
         self.mark_eof(id)?;
+        if self.config.deduplication {
+            let size = match self.ids_info.get(&id) {
+                Some(file_info) => file_info.size,
+                None => {
+                    return Err(Error::WrongWriterState(
+                        "[EndFile] Unable to find the ID".to_string(),
+                    ))
+                }
+            };
+            self.content_ids.entry((size, hash.clone())).or_insert(id);
+        }
         // Use std::io::Empty as a readable placeholder type
-        ArchiveFileBlock::EndOfFile::<std::io::Empty> { id, hash }.dump(&mut self.dest)?;
+        ArchiveFileBlock::EndOfFile::<std::io::Empty> { id, hash }
+            .dump(&mut self.dest, self.block_tags)?;
+
+        if let Some(CheckpointInterval::Entries(interval)) = self.config.checkpoint {
+            self.since_checkpoint += 1;
+            if self.since_checkpoint >= interval {
+                self.checkpoint()?;
+            }
+        }
+        Ok(())
+    }
 
+    /// Write a `Checkpoint` block, listing `checkpoint_files`, and flush it
+    fn checkpoint(&mut self) -> Result<(), Error> {
+        let opened = match &self.state {
+            ArchiveWriterState::OpenedFiles { ids, .. } => ids,
+            _ => {
+                return Err(Error::WrongWriterState(
+                    "[Checkpoint] Wrong state".to_string(),
+                ))
+            }
+        };
+        let mut entries = Vec::with_capacity(self.checkpoint_files.len());
+        for (id, filename) in &self.checkpoint_files {
+            let size = match self.ids_info.get(id) {
+                Some(file_info) => file_info.size,
+                None => {
+                    return Err(Error::WrongWriterState(
+                        "[Checkpoint] Unable to find the ID".to_string(),
+                    ))
+                }
+            };
+            entries.push(CheckpointEntry {
+                id: *id,
+                filename: filename.clone(),
+                size,
+                ended: !opened.contains(id),
+            });
+        }
+        entries.sort_by_key(|entry| entry.id);
+        // Keep opened files for the next checkpoint
+        self.checkpoint_files.retain(|id, _| opened.contains(id));
+        self.since_checkpoint = 0;
+
+        let data = match bincode::serialize(&entries) {
+            Ok(data) => data,
+            Err(_) => return Err(Error::SerializationError),
+        };
+        ArchiveFileBlock::Checkpoint {
+            length: data.len() as u64,
+            data: Some(data.as_slice()),
+        }
+        .dump(&mut self.dest, self.block_tags)?;
+        // Following blocks are not continuous with the previous ones anymore.
+        // `next_id` is not used by any file yet
+        self.current_id = self.next_id;
+        self.dest.flush()?;
         Ok(())
     }
 
     pub fn add_file<U: Read>(&mut self, filename: &str, size: u64, src: U) -> Result<(), Error> {
-        let id = self.start_file(filename)?;
+        self.add_file_with_options(filename, size, src, &FileOptions::default())
+    }
+
+    /// As `add_file`, with per-file `options`
+    pub fn add_file_with_options<U: Read>(
+        &mut self,
+        filename: &str,
+        size: u64,
+        src: U,
+        options: &FileOptions,
+    ) -> Result<(), Error> {
+        let id = self.start_file_with_options(filename, options)?;
         self.append_file_content(id, size, src)?;
         self.end_file(id)
     }
 
+    /// Add the file `filename`, whose content is read from `src` until its
+    /// end
+    ///
+    /// Unlike `add_file`, the size does not have to be known beforehand, such
+    /// as for a pipe or a network stream. It is recorded in the footer once
+    /// `src` is exhausted
+    pub fn add_file_unknown_size<U: Read>(&mut self, filename: &str, src: U) -> Result<(), Error> {
+        self.add_file_unknown_size_with_options(filename, src, &FileOptions::default())
+    }
+
+    /// As `add_file_unknown_size`, with per-file `options`
+    pub fn add_file_unknown_size_with_options<U: Read>(
+        &mut self,
+        filename: &str,
+        mut src: U,
+        options: &FileOptions,
+    ) -> Result<(), Error> {
+        let id = self.start_file_with_options(filename, options)?;
+        let mut writer = helpers::EntryWriter::new(self, id);
+        io::copy(&mut src, &mut writer)?;
+        writer.finish()
+    }
+
+    /// As `add_file_with_options`, for a source which can be read twice
+    ///
+    /// If deduplication is enabled (see
+    /// `ArchiveWriterConfig::set_deduplication`), `src` is hashed first. If a
+    /// file with the same content is already stored, `filename` refers to
+    /// its data instead of storing it again
+    pub fn add_file_seekable<U: Read + Seek>(
+        &mut self,
+        filename: &str,
+        size: u64,
+        mut src: U,
+        options: &FileOptions,
+    ) -> Result<(), Error> {
+        if self.config.deduplication {
+            check_state!(self.state, OpenedFiles);
+            if self.files_info.contains_key(filename) {
+                return Err(Error::DuplicateFilename);
+            }
+
+            let start = src.stream_position()?;
+            let mut hasher = FileHasher::new(self.config.hash_algorithm);
+            io::copy(&mut (&mut src).take(size), &mut hasher)?;
+            if let Some(id) = self.content_ids.get(&(size, hasher.finalize())) {
+                self.add_file_alias(filename, *id)?;
+                // The content is considered added
+                if let Some(progress) = self.progress.as_mut() {
+                    progress(size);
+                }
+                return Ok(());
+            }
+            src.seek(SeekFrom::Start(start))?;
+        }
+        self.add_file_with_options(filename, size, src, options)
+    }
+
+    /// Make `filename` refer to the content of the file `id`, without storing
+    /// it again
+    pub(crate) fn add_file_alias(
+        &mut self,
+        filename: &str,
+        id: ArchiveFileID,
+    ) -> Result<(), Error> {
+        check_state!(self.state, OpenedFiles);
+        if self.files_info.contains_key(filename) {
+            return Err(Error::DuplicateFilename);
+        }
+        self.files_info.insert(filename.to_string(), id);
+        Ok(())
+    }
+
+    /// As `add_file`, storing only the differences between the content and
+    /// the one of `base`, an already added file
+    ///
+    /// `base_src` provides the content of `base`, read once to index it. The
+    /// parts of `src` found in it, even at another place, refer to the data
+    /// of `base` instead of being stored again. This is meant for similar
+    /// files, such as rotated logs or successive snapshots of a disk.
+    ///
+    /// Parts copied from another file are lost by a repair
+    /// (`ArchiveFailSafeReader`), which then only recovers the beginning of
+    /// the file. If the archive format version predates copies, the whole
+    /// content is stored.
+    pub fn add_file_delta<U: Read, B: Read>(
+        &mut self,
+        filename: &str,
+        size: u64,
+        src: U,
+        base: &str,
+        base_src: B,
+    ) -> Result<(), Error> {
+        self.add_file_delta_with_options(
+            filename,
+            size,
+            src,
+            base,
+            base_src,
+            &FileOptions::default(),
+        )
+    }
+
+    /// As `add_file_delta`, with per-file `options`
+    pub fn add_file_delta_with_options<U: Read, B: Read>(
+        &mut self,
+        filename: &str,
+        size: u64,
+        src: U,
+        base: &str,
+        base_src: B,
+        options: &FileOptions,
+    ) -> Result<(), Error> {
+        let base_id = match self.files_info.get(base) {
+            Some(id) => *id,
+            None => {
+                return Err(Error::BadAPIArgument(format!(
+                    "[AddFileDelta] Unknown base file {}",
+                    base
+                )))
+            }
+        };
+        let index = DeltaIndex::new(base_src)?;
+
+        let id = self.start_file_with_options(filename, options)?;
+        delta_encode(&index, src.take(size), |op| match op {
+            DeltaOp::Literal(data) => self.append_file_content(id, data.len() as u64, data),
+            DeltaOp::Copy { offset, data } => {
+                self.append_file_copy(id, base_id, offset, data.len() as u64, data)
+            }
+        })?;
+        self.end_file(id)
+    }
+
+    /// Append to the file `id` the `length` bytes of the ended file `base_id`
+    /// starting at `offset`, with a `FileCopy` block
+    ///
+    /// `src` provides these bytes, to compute the file hash. If the archive
+    /// format version predates `FileCopy` blocks, they are stored as with
+    /// `append_file_content`
+    pub(crate) fn append_file_copy<U: Read>(
+        &mut self,
+        id: ArchiveFileID,
+        base_id: ArchiveFileID,
+        offset: u64,
+        length: u64,
+        src: U,
+    ) -> Result<(), Error> {
+        check_state_file_opened!(&self.state, &id);
+        if !self.copy_blocks {
+            return self.append_file_content(id, length, src);
+        }
+
+        if length == 0 {
+            // Avoid creating 0-sized block
+            return Ok(());
+        }
+
+        // The base must be ended, so that a reader never copies data it is
+        // still looking for
+        if let ArchiveWriterState::OpenedFiles { ids, .. } = &self.state {
+            if ids.contains(&base_id) {
+                return Err(Error::WrongWriterState(
+                    "[AppendFileCopy] The base file must be ended".to_string(),
+                ));
+            }
+        }
+        let base_info = match self.ids_info.get(&base_id) {
+            Some(file_info) => file_info,
+            None => {
+                return Err(Error::WrongWriterState(
+                    "[AppendFileCopy] Unable to find the base ID".to_string(),
+                ))
+            }
+        };
+        if offset
+            .checked_add(length)
+            .is_none_or(|end| end > base_info.size)
+        {
+            return Err(Error::WrongWriterState(
+                "[AppendFileCopy] Copy outside of the base file".to_string(),
+            ));
+        }
+        let base = base_info.offsets[0];
+
+        self.mark_continuous_block(id)?;
+        self.extend_file_size(id, length)?;
+        // The copied bytes are only read to update the hash
+        let src = ProgressReader::new(src, self.progress.as_deref_mut());
+        let src = self.state.wrap_with_hash(id, src)?;
+        if io::copy(&mut src.take(length), &mut io::sink())? != length {
+            return Err(Error::IOError(io::ErrorKind::UnexpectedEof.into()));
+        }
+
+        // Use std::io::Empty as a readable placeholder type
+        ArchiveFileBlock::FileCopy::<std::io::Empty> {
+            id,
+            base,
+            offset,
+            length,
+        }
+        .dump(&mut self.dest, self.block_tags)?;
+
+        if let Some(CheckpointInterval::Bytes(interval)) = self.config.checkpoint {
+            self.since_checkpoint += length;
+            if self.since_checkpoint >= interval {
+                self.checkpoint()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Set a callback, called with the number of bytes of file content added
+    /// to the archive, as they are read from sources
+    ///
+    /// This is meant for progress reporting, including while adding a single
+    /// big file
+    pub fn set_progress_callback<F: 'a + FnMut(u64)>(&mut self, callback: F) {
+        self.progress = Some(Box::new(callback));
+    }
+
     /// Unwraps the inner writer
     pub fn into_raw(self) -> W {
         self.dest.into_raw()
@@ -697,6 +2227,37 @@ impl<'a, W: Write> ArchiveWriter<'a, W> {
     pub fn flush(&mut self) -> io::Result<()> {
         self.dest.flush()
     }
+
+    /// Make everything written so far recoverable, even if the archive is
+    /// never finalized (crash, power loss, etc.)
+    ///
+    /// The data buffered by the layers, such as the current compressed block,
+    /// is written out, then the destination is flushed. On return, a repair
+    /// (`ArchiveFailSafeReader`) of the data written to the destination gives
+    /// back every file ended so far, and the content added so far to the
+    /// others.
+    ///
+    /// Durability is up to the destination `flush`: with a `File`, whose
+    /// `flush` does not wait for the storage device, use
+    /// `helpers::DurableFile`.
+    ///
+    /// The forward error correction layer only writes whole groups of shards,
+    /// and the compression with several threads only whole blocks, so an
+    /// error is returned if either is enabled.
+    pub fn sync(&mut self) -> Result<(), Error> {
+        if self.config.is_layers_enabled(Layers::FEC) {
+            return Err(Error::WrongWriterState(
+                "[Sync] The forward error correction layer only writes whole groups".to_string(),
+            ));
+        }
+        if self.config.is_layers_enabled(Layers::COMPRESS) && self.config.compress.is_parallel() {
+            return Err(Error::WrongWriterState(
+                "[Sync] The compression with several threads only writes whole blocks".to_string(),
+            ));
+        }
+        self.dest.flush()?;
+        Ok(())
+    }
 }
 
 // -------- Reader --------
@@ -709,14 +2270,42 @@ pub struct ArchiveFile<T: Read> {
     pub size: u64,
 }
 
+impl<T: Read> ArchiveFile<T> {
+    /// Buffered reader of the file content, implementing `BufRead`, and
+    /// `Seek` if `data` does
+    ///
+    /// The buffer holds up to a compressed block (`UNCOMPRESSED_DATA_SIZE`
+    /// bytes), so line-oriented consumers go through the layers once per
+    /// block instead of once per line
+    pub fn into_buf_reader(self) -> io::BufReader<T> {
+        let capacity = std::cmp::min(self.size, UNCOMPRESSED_DATA_SIZE as u64);
+        io::BufReader::with_capacity(capacity as usize, self.data)
+    }
+}
+
 #[derive(PartialEq, Debug)]
 enum BlocksToFileReaderState {
     // Remaining size
     InFile(usize),
+    // Remaining size of a `FileCopy` block, whose next byte is at `offset` in
+    // the file starting at `base`
+    InCopy {
+        base: u64,
+        offset: u64,
+        remaining: u64,
+    },
     Ready,
     Finish,
 }
 
+/// Content of a single file of an archive, implementing `Read` and `Seek`
+///
+/// Seeking only reads the headers of the file blocks, from the last one
+/// already located. Through the compression layer, the compressed block
+/// containing the target position is then found from the block sizes index,
+/// and decompressed from its start instead of from the start of the archive.
+/// Arbitrary offsets can thus be reached, such as for partial parsing of
+/// databases or range requests
 #[derive(Debug)]
 pub struct BlocksToFileReader<'a, R: Read + Seek> {
     /// This structure wraps the internals to get back a file's content
@@ -727,16 +2316,91 @@ pub struct BlocksToFileReader<'a, R: Read + Seek> {
     /// position in `offsets` of the last offset used
     current_offset: usize,
     /// List of offsets of continuous blocks corresponding to where the file can be read
-    offsets: &'a [u64],
+    offsets: Cow<'a, [u64]>,
+    /// Size of the file, in bytes
+    size: u64,
+    /// Current position in the file
+    pos: u64,
+    /// `FileContent` and `FileCopy` blocks already located by seeks, in the
+    /// file order
+    known_blocks: Vec<KnownBlock>,
+    /// Whether block headers are followed by a tag
+    tagged: bool,
+    /// Files of the archive, to find the ones content is copied from
+    footer: FooterRef<'a>,
+    /// Content read ahead from the file a `FileCopy` block refers to
+    copy_cache: Option<CopyCache>,
+    /// Size of the content read ahead in `copy_cache`
+    copy_read_ahead: u64,
+}
+
+/// Location of a `FileContent` or `FileCopy` block, used to seek in a file
+#[derive(Debug, Clone, Copy)]
+struct KnownBlock {
+    /// Position in the file of the block's first byte
+    file_pos: u64,
+    /// Position in `src` of the block's data, right after its header
+    data_offset: u64,
+    length: u64,
+    /// Index in `offsets` of the continuous blocks containing this one
+    current_offset: usize,
+    /// For a `FileCopy` block, the start of the file the content is copied
+    /// from, and the offset in it
+    copy: Option<(u64, u64)>,
+}
+
+impl KnownBlock {
+    /// Position in `src` of the next block
+    fn end_offset(&self) -> u64 {
+        match self.copy {
+            // No data follows a `FileCopy` header
+            Some(_) => self.data_offset,
+            None => self.data_offset + self.length,
+        }
+    }
+}
+
+/// Blocks of a file located by the seeks of a `BlocksToFileReader`
+///
+/// They can be given to a later reader of the same file, through
+/// `BlocksToFileReader::reuse_located_blocks`, so that its seeks do not walk
+/// again through the block headers from the start of the file
+#[derive(Debug, Default)]
+pub struct LocatedBlocks {
+    /// Start of the file in the archive
+    start: u64,
+    blocks: Vec<KnownBlock>,
+}
+
+/// Size of the content read at once from the file a `FileCopy` block refers
+/// to. Reading it by big parts avoids going back and forth in the archive
+const COPY_READ_AHEAD_SIZE: u64 = 1024 * 1024;
+
+/// Content of a file `FileCopy` blocks refer to, read ahead
+#[derive(Debug)]
+struct CopyCache {
+    /// Start of the file in the archive
+    base: u64,
+    /// Blocks of the file already located, to read further in it
+    known_blocks: Vec<KnownBlock>,
+    /// Offset in the file of `data`
+    offset: u64,
+    data: Vec<u8>,
 }
 
 impl<'a, R: Read + Seek> BlocksToFileReader<'a, R> {
-    fn new(src: &'a mut R, offsets: &'a [u64]) -> Result<BlocksToFileReader<'a, R>, Error> {
+    fn new(
+        src: &'a mut R,
+        offsets: Cow<'a, [u64]>,
+        size: u64,
+        tagged: bool,
+        footer: FooterRef<'a>,
+    ) -> Result<BlocksToFileReader<'a, R>, Error> {
         // Set the inner layer at the start of the file
         src.seek(SeekFrom::Start(offsets[0]))?;
 
         // Read file information header
-        let id = match ArchiveFileBlock::from(src)? {
+        let id = match ArchiveFileBlock::from(src, tagged)? {
             ArchiveFileBlock::FileStart { id, .. } => id,
             _ => {
                 return Err(Error::WrongReaderState(
@@ -751,9 +2415,274 @@ impl<'a, R: Read + Seek> BlocksToFileReader<'a, R> {
             id,
             current_offset: 0,
             offsets,
+            size,
+            pos: 0,
+            known_blocks: Vec::new(),
+            tagged,
+            footer,
+            copy_cache: None,
+            copy_read_ahead: COPY_READ_AHEAD_SIZE,
         })
     }
 
+    /// Blocks of this file located so far, to be given to a later reader of
+    /// the same file (see `reuse_located_blocks`)
+    pub fn into_located_blocks(self) -> LocatedBlocks {
+        LocatedBlocks {
+            start: self.offsets[0],
+            blocks: self.known_blocks,
+        }
+    }
+
+    /// Reuse `located`, obtained from a previous reader of the same file
+    /// with `into_located_blocks`, instead of locating again its blocks
+    ///
+    /// Blocks located in another file are ignored
+    pub fn reuse_located_blocks(&mut self, located: LocatedBlocks) {
+        if located.start == self.offsets[0] && self.known_blocks.is_empty() {
+            self.known_blocks = located.blocks;
+        }
+    }
+
+    /// Place `self.src` at `pos` in the `known` block
+    fn seek_in_block(&mut self, known: &KnownBlock, pos: u64) -> Result<(), Error> {
+        let in_block = pos - known.file_pos;
+        self.current_offset = known.current_offset;
+        match known.copy {
+            Some((base, offset)) => {
+                self.src.seek(SeekFrom::Start(known.data_offset))?;
+                self.state = BlocksToFileReaderState::InCopy {
+                    base,
+                    offset: offset + in_block,
+                    remaining: known.length - in_block,
+                };
+            }
+            None => {
+                self.src
+                    .seek(SeekFrom::Start(known.data_offset + in_block))?;
+                self.state = BlocksToFileReaderState::InFile((known.length - in_block) as usize);
+            }
+        }
+        self.pos = pos;
+        Ok(())
+    }
+
+    /// Open the file starting at `base` in the archive, from which a
+    /// `FileCopy` block just read from `src` copies `length` bytes, and place
+    /// it at `offset`
+    ///
+    /// `known_blocks` are the blocks of this file already located, if any.
+    /// The caller is expected to place `src` back after the block once done
+    pub(crate) fn open_copied(
+        src: &'a mut R,
+        footer: FooterRef<'a>,
+        tagged: bool,
+        base: u64,
+        offset: u64,
+        length: u64,
+        known_blocks: Vec<KnownBlock>,
+    ) -> Result<BlocksToFileReader<'a, R>, Error> {
+        let position = src.stream_position()?;
+        let file_info = match footer {
+            FooterRef::Loaded(files_info) => files_info
+                .values()
+                .find(|file_info| file_info.offsets.first() == Some(&base))
+                .map(Cow::Borrowed),
+            FooterRef::Lazy(lazy) => lazy.find_by_start(&mut *src, base)?.map(Cow::Owned),
+        }
+        .ok_or_else(|| {
+            Error::WrongReaderState("[BlocksToFileReader] Copy from an unknown file".to_string())
+        })?;
+        // The copied file must be ended before, so that copies always refer
+        // to earlier data
+        if file_info.eof_offset >= position {
+            return Err(Error::WrongReaderState(
+                "[BlocksToFileReader] Copy from a file not ended yet".to_string(),
+            ));
+        }
+        if offset
+            .checked_add(length)
+            .is_none_or(|end| end > file_info.size)
+        {
+            return Err(Error::WrongReaderState(
+                "[BlocksToFileReader] Copy outside of the copied file".to_string(),
+            ));
+        }
+
+        let size = file_info.size;
+        let offsets = match file_info {
+            Cow::Borrowed(file_info) => Cow::Borrowed(file_info.offsets.as_slice()),
+            Cow::Owned(file_info) => Cow::Owned(file_info.offsets),
+        };
+        let mut reader = BlocksToFileReader::new(src, offsets, size, tagged, footer)?;
+        reader.known_blocks = known_blocks;
+        reader.seek_to(offset)?;
+        Ok(reader)
+    }
+
+    /// Read, in `into`, the content of the file starting at `base` in the
+    /// archive, from `offset`
+    ///
+    /// The content is read ahead, up to `remaining` bytes, and `self.src` is
+    /// left at its position
+    fn read_copy(
+        &mut self,
+        base: u64,
+        offset: u64,
+        remaining: u64,
+        into: &mut [u8],
+    ) -> Result<usize, Error> {
+        let cached = match &self.copy_cache {
+            Some(cache) => {
+                cache.base == base
+                    && cache.offset <= offset
+                    && offset < cache.offset + cache.data.len() as u64
+            }
+            None => false,
+        };
+        if !cached {
+            let position = self.src.stream_position()?;
+            let known_blocks = match self.copy_cache.take() {
+                Some(cache) if cache.base == base => cache.known_blocks,
+                _ => Vec::new(),
+            };
+            let mut reader = BlocksToFileReader::open_copied(
+                &mut *self.src,
+                self.footer,
+                self.tagged,
+                base,
+                offset,
+                remaining,
+                known_blocks,
+            )?;
+            reader.copy_read_ahead = self.copy_read_ahead;
+            let mut data = Vec::new();
+            (&mut reader)
+                .take(std::cmp::min(remaining, self.copy_read_ahead))
+                .read_to_end(&mut data)?;
+            self.copy_cache = Some(CopyCache {
+                base,
+                known_blocks: reader.known_blocks,
+                offset,
+                data,
+            });
+            self.src.seek(SeekFrom::Start(position))?;
+        }
+
+        let cache = self.copy_cache.as_ref().ok_or_else(|| {
+            Error::AssertionError("[BlocksToFileReader] Copy cache missing".to_string())
+        })?;
+        let start = (offset - cache.offset) as usize;
+        let count = std::cmp::min(
+            std::cmp::min(into.len() as u64, remaining),
+            (cache.data.len() - start) as u64,
+        ) as usize;
+        into[..count].copy_from_slice(&cache.data[start..start + count]);
+        Ok(count)
+    }
+
+    /// Move to `pos` in the file
+    ///
+    /// Blocks are walked from the last one already located, reading only
+    /// their headers, and remembered for later seeks
+    fn seek_to(&mut self, pos: u64) -> Result<(), Error> {
+        if pos >= self.size {
+            // Nothing more to read
+            self.state = BlocksToFileReaderState::Finish;
+            self.pos = pos;
+            return Ok(());
+        }
+
+        // Use an already located block, if any
+        if let Some(known) = self.known_blocks.iter().rev().find(|b| b.file_pos <= pos) {
+            if pos < known.file_pos + known.length {
+                let known = *known;
+                return self.seek_in_block(&known, pos);
+            }
+        }
+
+        // Otherwise, walk from the block following the last located one
+        match self.known_blocks.last() {
+            Some(last) => {
+                let file_pos = last.file_pos + last.length;
+                self.src.seek(SeekFrom::Start(last.end_offset()))?;
+                self.current_offset = last.current_offset;
+                self.walk_to(file_pos, pos)
+            }
+            None => {
+                // Skip the FileStart
+                self.src.seek(SeekFrom::Start(self.offsets[0]))?;
+                ArchiveFileBlock::from(&mut self.src, self.tagged)?;
+                self.current_offset = 0;
+                self.walk_to(0, pos)
+            }
+        }
+    }
+
+    /// Walk blocks from `self.src`, starting at `file_pos` in the file, until
+    /// the one containing `pos`
+    fn walk_to(&mut self, mut file_pos: u64, pos: u64) -> Result<(), Error> {
+        loop {
+            match ArchiveFileBlock::from(&mut self.src, self.tagged)? {
+                ArchiveFileBlock::FileContent { length, id, .. } if id == self.id => {
+                    let known = KnownBlock {
+                        file_pos,
+                        data_offset: self.src.stream_position()?,
+                        length,
+                        current_offset: self.current_offset,
+                        copy: None,
+                    };
+                    if pos < file_pos + length {
+                        self.seek_in_block(&known, pos)?;
+                        self.known_blocks.push(known);
+                        return Ok(());
+                    }
+                    self.src.seek(SeekFrom::Start(known.end_offset()))?;
+                    file_pos += length;
+                    self.known_blocks.push(known);
+                }
+                ArchiveFileBlock::FileCopy {
+                    id,
+                    base,
+                    offset,
+                    length,
+                } if id == self.id => {
+                    let known = KnownBlock {
+                        file_pos,
+                        data_offset: self.src.stream_position()?,
+                        length,
+                        current_offset: self.current_offset,
+                        copy: Some((base, offset)),
+                    };
+                    if pos < file_pos + length {
+                        self.seek_in_block(&known, pos)?;
+                        self.known_blocks.push(known);
+                        return Ok(());
+                    }
+                    file_pos += length;
+                    self.known_blocks.push(known);
+                }
+                ArchiveFileBlock::FileMetadata { id, .. } if id == self.id => {
+                    // Continuous with the following blocks
+                }
+                ArchiveFileBlock::EndOfFile { id, .. } if id == self.id => {
+                    return Err(Error::WrongReaderState(
+                        "[BlocksToFileReader] File is smaller than expected".to_string(),
+                    ));
+                }
+                ArchiveFileBlock::EndOfArchiveData => {
+                    return Err(Error::WrongReaderState(
+                        "[BlocksToFileReader] Try to read the end of the archive".to_string(),
+                    ));
+                }
+                _ => {
+                    // Block of another file
+                    self.move_to_next_block()?;
+                }
+            }
+        }
+    }
+
     /// Move `self.src` to the next continuous block
     fn move_to_next_block(&mut self) -> Result<(), Error> {
         self.current_offset += 1;
@@ -773,7 +2702,7 @@ impl<'a, T: Read + Seek> Read for BlocksToFileReader<'a, T> {
         let (remaining, count) = match self.state {
             BlocksToFileReaderState::Ready => {
                 // Start a new block FileContent
-                match ArchiveFileBlock::from(&mut self.src)? {
+                match ArchiveFileBlock::from(&mut self.src, self.tagged)? {
                     ArchiveFileBlock::FileContent { length, id, .. } => {
                         if id != self.id {
                             self.move_to_next_block()?;
@@ -801,6 +2730,33 @@ impl<'a, T: Read + Seek> Read for BlocksToFileReader<'a, T> {
                         )
                         .into());
                     }
+                    ArchiveFileBlock::FileCopy {
+                        id,
+                        base,
+                        offset,
+                        length,
+                    } => {
+                        if id != self.id {
+                            self.move_to_next_block()?;
+                            return self.read(into);
+                        }
+                        self.state = BlocksToFileReaderState::InCopy {
+                            base,
+                            offset,
+                            remaining: length,
+                        };
+                        return self.read(into);
+                    }
+                    ArchiveFileBlock::FileMetadata { id, .. } => {
+                        if id != self.id {
+                            self.move_to_next_block()?;
+                        }
+                        return self.read(into);
+                    }
+                    ArchiveFileBlock::Padding { .. } | ArchiveFileBlock::Checkpoint { .. } => {
+                        self.move_to_next_block()?;
+                        return self.read(into);
+                    }
                     ArchiveFileBlock::EndOfArchiveData => {
                         return Err(Error::WrongReaderState(
                             "[BlocksToFileReader] Try to read the end of the archive".to_string(),
@@ -813,6 +2769,35 @@ impl<'a, T: Read + Seek> Read for BlocksToFileReader<'a, T> {
                 let count = self.src.by_ref().take(remaining as u64).read(into)?;
                 (remaining - count, count)
             }
+            BlocksToFileReaderState::InCopy {
+                base,
+                offset,
+                remaining,
+            } => {
+                if remaining == 0 {
+                    // Empty copy
+                    self.state = BlocksToFileReaderState::Ready;
+                    return self.read(into);
+                }
+                let count = self.read_copy(base, offset, remaining, into)?;
+                if count == 0 && !into.is_empty() {
+                    return Err(Error::WrongReaderState(
+                        "[BlocksToFileReader] Copied file is smaller than expected".to_string(),
+                    )
+                    .into());
+                }
+                self.state = if remaining > count as u64 {
+                    BlocksToFileReaderState::InCopy {
+                        base,
+                        offset: offset + count as u64,
+                        remaining: remaining - count as u64,
+                    }
+                } else {
+                    BlocksToFileReaderState::Ready
+                };
+                self.pos += count as u64;
+                return Ok(count);
+            }
             BlocksToFileReaderState::Finish => {
                 return Ok(0);
             }
@@ -823,12 +2808,42 @@ impl<'a, T: Read + Seek> Read for BlocksToFileReader<'a, T> {
             // remaining is 0 (> never happens thanks to take)
             self.state = BlocksToFileReaderState::Ready;
         }
+        self.pos += count as u64;
         Ok(count)
     }
 }
 
-#[derive(Serialize, Deserialize)]
-#[cfg_attr(test, derive(PartialEq, Debug))]
+impl<'a, T: Read + Seek> Seek for BlocksToFileReader<'a, T> {
+    /// Seek in the file content
+    ///
+    /// As blocks are not indexed in the archive, seeking forward past the
+    /// already visited blocks implies reading the headers of the next ones
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(pos) => pos as i128,
+            SeekFrom::Current(delta) => self.pos as i128 + delta as i128,
+            SeekFrom::End(delta) => self.size as i128 + delta as i128,
+        };
+        if new_pos < 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Seek before the start of the file",
+            ));
+        }
+        let new_pos = new_pos as u64;
+        if new_pos != self.pos {
+            self.seek_to(new_pos)?;
+        }
+        Ok(new_pos)
+    }
+}
+
+/// Reader of a single file of an `ArchiveReader`, as returned by
+/// `ArchiveReader::get_file`
+pub type ArchiveEntryReader<'a, 'b, R> = BlocksToFileReader<'a, Box<dyn 'b + LayerReader<'b, R>>>;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq))]
 pub struct FileInfo {
     /// File information to save in the footer
     ///
@@ -843,15 +2858,105 @@ pub struct FileInfo {
     eof_offset: u64,
 }
 
+/// Information on a file of the archive, see `ArchiveReader::get_entry_info`
+/// and `ArchiveReader::iter_entries`
+#[derive(Clone, Debug)]
+pub struct EntryInfo {
+    /// Name of the file
+    pub name: String,
+    /// Size of the file, in bytes
+    pub size: u64,
+    /// Hash of the file, computed with the hash algorithm of the archive
+    pub hash: Vec<u8>,
+    /// Offsets of the continuous chunks of blocks of the file, in the
+    /// archive data before layers are applied
+    pub offsets: Vec<u64>,
+    /// Filesystem metadata of the file, if recorded
+    pub metadata: Option<FileMetadata>,
+}
+
+/// Archive characteristics, from its header and layers
+pub struct ArchiveInfo {
+    /// Format version of the archive
+    pub format_version: u32,
+    /// Layers enabled on the archive
+    pub layers_enabled: Layers,
+    /// Number of recipients the archive is encrypted for, if encrypted
+    pub recipients_count: Option<usize>,
+    /// Size of the compressed blocks, if compressed. Each block contains
+    /// `UNCOMPRESSED_DATA_SIZE` bytes of data, except the last one
+    pub compressed_sizes: Option<Vec<u32>>,
+    /// Uncompressed size of the last compressed block, if compressed
+    pub last_block_size: Option<u32>,
+    /// Public key of the signer, if signed
+    pub signer: Option<ed25519_dalek::PublicKey>,
+    /// Algorithm of the hash of each file content
+    pub hash_algorithm: HashAlgorithm,
+    /// Comment stored in the header, if any
+    pub comment: Option<String>,
+}
+
+impl ArchiveInfo {
+    /// Total size of the compressed data, if compressed
+    pub fn compressed_size(&self) -> Option<u64> {
+        self.compressed_sizes
+            .as_ref()
+            .map(|sizes| sizes.iter().map(|v| *v as u64).sum())
+    }
+
+    /// Estimate the compressed size of the data in the ranges [start, end) of
+    /// `extents`, if compressed, such as the ones of
+    /// `ArchiveReader::get_files_extents`
+    ///
+    /// Each compressed block is considered as evenly compressed
+    pub fn compressed_size_of(&self, extents: &[(u64, u64)]) -> Option<u64> {
+        extents.iter().try_fold(0, |total, (start, end)| {
+            Some(total + self.compressed_size_between(*start, *end)?)
+        })
+    }
+
+    /// Estimate the compressed size of the data in [start, end), if compressed
+    ///
+    /// Each compressed block is considered as evenly compressed
+    fn compressed_size_between(&self, start: u64, end: u64) -> Option<u64> {
+        let (sizes, last_block_size) = match (&self.compressed_sizes, self.last_block_size) {
+            (Some(sizes), Some(last_block_size)) => (sizes, last_block_size),
+            _ => return None,
+        };
+        let block_size = UNCOMPRESSED_DATA_SIZE as u64;
+        let mut total = 0;
+        for (index, compressed_size) in sizes.iter().enumerate() {
+            let block_start = index as u64 * block_size;
+            let uncompressed_size = if index == sizes.len() - 1 {
+                last_block_size as u64
+            } else {
+                block_size
+            };
+            let low = std::cmp::max(start, block_start);
+            let high = std::cmp::min(end, block_start + uncompressed_size);
+            if high > low {
+                total += (high - low) * *compressed_size as u64 / uncompressed_size;
+            }
+        }
+        Some(total)
+    }
+}
+
 pub struct ArchiveReader<'a, R: 'a + Read + Seek> {
     /// MLA Archive format Reader
 
     /// User's reading configuration
     pub config: ArchiveReaderConfig,
+    /// Archive characteristics
+    info: ArchiveInfo,
     /// Source
     src: Box<dyn 'a + LayerReader<'a, R>>,
     /// Metadata (from footer if any)
     metadata: Option<ArchiveFooter>,
+    /// Footer left in the archive instead, with a memory budget
+    lazy_footer: Option<LazyFooter>,
+    /// Position in the source of the archive data, right after the header
+    data_offset: u64,
 }
 
 impl<'b, R: 'b + Read + Seek> ArchiveReader<'b, R> {
@@ -859,7 +2964,26 @@ impl<'b, R: 'b + Read + Seek> ArchiveReader<'b, R> {
         // Make sure we read the archive header from the start
         src.seek(SeekFrom::Start(0))?;
         let header = ArchiveHeader::from(&mut src)?;
+        let mut info = ArchiveInfo {
+            format_version: header.format_version,
+            layers_enabled: header.config.layers_enabled,
+            recipients_count: header
+                .config
+                .encrypt
+                .as_ref()
+                .map(|encrypt| encrypt.multi_recipient.count_keys()),
+            compressed_sizes: None,
+            last_block_size: None,
+            signer: None,
+            hash_algorithm: header.config.hash_algorithm,
+            comment: header.config.comment.clone(),
+        };
         config.load_persistent(header.config)?;
+        config
+            .compress
+            .set_block_kinds(has_block_kinds(header.format_version));
+        let header_bytes = read_header_bytes(&mut src)?;
+        let data_offset = header_bytes.len() as u64;
 
         // Pin the current position (after header) as the new 0
         let mut raw_src = Box::new(RawLayerReader::new(src));
@@ -867,24 +2991,67 @@ impl<'b, R: 'b + Read + Seek> ArchiveReader<'b, R> {
 
         // Enable layers depending on user option. Order is relevant
         let mut src: Box<dyn 'b + LayerReader<'b, R>> = raw_src;
+        if config.layers_enabled.contains(Layers::FEC) {
+            src = Box::new(FecLayerReader::new(src)?);
+        }
+        if config.layers_enabled.contains(Layers::SIGN) {
+            // Check the signature right away, to report the signer
+            let mut src_sign =
+                Box::new(SignatureLayerReader::new(src, &config.sign, header_bytes)?);
+            src_sign.initialize()?;
+            info.signer = src_sign.signer();
+            src = src_sign;
+        }
         if config.layers_enabled.contains(Layers::ENCRYPT) {
             src = Box::new(EncryptionLayerReader::new(src, &config.encrypt)?);
         }
         if config.layers_enabled.contains(Layers::COMPRESS) {
-            src = Box::new(CompressionLayerReader::new(src)?);
+            let mut src_compress = Box::new(CompressionLayerReader::new(src, &config.compress)?);
+            src_compress.initialize()?;
+            if let Some(sizes_info) = &src_compress.sizes_info {
+                info.compressed_sizes = Some(sizes_info.compressed_sizes.clone());
+                info.last_block_size = Some(sizes_info.last_block_size);
+            }
+            src = src_compress;
+        } else {
+            src.initialize()?;
         }
-        src.initialize()?;
 
-        // Read the footer
-        let metadata = Some(ArchiveFooter::deserialize_from(&mut src)?);
+        // Read the footer, or only index it if it does not fit in the memory
+        // budget
+        let (start, len) = ArchiveFooter::locate(&mut src, &config.limits)?;
+        let (metadata, lazy_footer) = match config.limits.memory_budget {
+            Some(budget) if len > budget => (
+                None,
+                Some(LazyFooter::open(
+                    &mut src,
+                    start,
+                    len,
+                    &config.limits,
+                    budget,
+                )?),
+            ),
+            _ => (
+                Some(ArchiveFooter::deserialize_at(
+                    &mut src,
+                    start,
+                    len,
+                    &config.limits,
+                )?),
+                None,
+            ),
+        };
 
         // Reset the position for further uses
         src.seek(SeekFrom::Start(0))?;
 
         Ok(ArchiveReader {
             config,
+            info,
             src,
             metadata,
+            lazy_footer,
+            data_offset,
         })
     }
 
@@ -903,123 +3070,887 @@ impl<'b, R: 'b + Read + Seek> ArchiveReader<'b, R> {
         }
     }
 
-    pub fn get_hash(&mut self, filename: &str) -> Result<Option<Sha256Hash>, Error> {
-        if let Some(ArchiveFooter { files_info }) = &self.metadata {
-            // Get file relative information
-            let file_info = match files_info.get(filename) {
-                None => return Ok(None),
-                Some(finfo) => finfo,
-            };
-            // Set the inner layer at the start of the EoF tag
-            self.src.seek(SeekFrom::Start(file_info.eof_offset))?;
+    /// Return filenames present in the archive, in the order of their first
+    /// block
+    ///
+    /// Reading files in this order avoids seeking back and forth; the archive
+    /// is then read linearly, except for interleaved files
+    pub fn list_files_archive_order(&self) -> Result<Vec<&String>, Error> {
+        if let Some(ArchiveFooter { files_info, .. }) = &self.metadata {
+            let mut files: Vec<(&String, &FileInfo)> = files_info.iter().collect();
+            files.sort_by_key(|(_, file_info)| file_info.offsets.first().copied());
+            Ok(files.into_iter().map(|(fname, _)| fname).collect())
+        } else {
+            Err(Error::MissingMetadata)
+        }
+    }
 
-            // Return the file hash
-            match ArchiveFileBlock::from(&mut self.src)? {
-                ArchiveFileBlock::EndOfFile { hash, .. } => Ok(Some(hash)),
-                _ => Err(Error::WrongReaderState(
-                    "[ArchiveReader] eof_offset must point to a EoF".to_string(),
-                )),
-            }
+    /// Return the archive characteristics, from its header and layers
+    pub fn get_info(&self) -> &ArchiveInfo {
+        &self.info
+    }
+
+    /// Return the comment stored in the archive header, see
+    /// `ArchiveWriterConfig::set_comment`
+    pub fn comment(&self) -> Option<&str> {
+        self.info.comment.as_deref()
+    }
+
+    /// Return the sum of the files size, in bytes
+    pub fn get_files_size(&self) -> Result<u64, Error> {
+        if let Some(ArchiveFooter { files_info, .. }) = &self.metadata {
+            Ok(files_info.values().map(|f| f.size).sum())
         } else {
             Err(Error::MissingMetadata)
         }
     }
 
-    #[allow(clippy::type_complexity)]
-    pub fn get_file<'a>(
-        &'a mut self,
-        filename: String,
-    ) -> Result<Option<ArchiveFile<BlocksToFileReader<Box<dyn 'b + LayerReader<'b, R>>>>>, Error>
-    {
-        if let Some(ArchiveFooter { files_info }) = &self.metadata {
-            // Get file relative information
-            let file_info = match files_info.get(&filename) {
+    /// Return the offset of the first block of `filename` in the archive
+    /// data, before layers are applied
+    pub fn get_file_offset(&self, filename: &str) -> Result<Option<u64>, Error> {
+        if let Some(ArchiveFooter { files_info, .. }) = &self.metadata {
+            Ok(files_info
+                .get(filename)
+                .and_then(|file_info| file_info.offsets.first().copied()))
+        } else {
+            Err(Error::MissingMetadata)
+        }
+    }
+
+    /// Estimate the size taken by `filename` once compressed
+    ///
+    /// The estimation is based on the compression rate of the compressed
+    /// blocks spanned by the file, from its first block to its end.
+    /// Interleaved files are then overestimated, as they span other files
+    /// data. Without compression, this is the size of the file.
+    pub fn get_compressed_size(&self, filename: &str) -> Result<Option<u64>, Error> {
+        if let Some(ArchiveFooter { files_info, .. }) = &self.metadata {
+            let file_info = match files_info.get(filename) {
                 None => return Ok(None),
                 Some(finfo) => finfo,
             };
-            if file_info.offsets.is_empty() {
-                return Err(Error::WrongReaderState(
-                    "[ArchiveReader] A file must have at least one offset".to_string(),
-                ));
-            }
-
-            // Instantiate the file representation
-            let reader = BlocksToFileReader::new(&mut self.src, &file_info.offsets)?;
-            Ok(Some(ArchiveFile {
-                filename,
-                data: reader,
-                size: file_info.size,
-            }))
+            let start = match file_info.offsets.first() {
+                None => return Ok(Some(0)),
+                Some(start) => *start,
+            };
+            Ok(Some(
+                self.info
+                    .compressed_size_between(start, file_info.eof_offset)
+                    .unwrap_or(file_info.size),
+            ))
         } else {
             Err(Error::MissingMetadata)
         }
     }
-}
 
-// This code is very similar with MLAArchiveReader
+    /// Return, for each file, the ranges [start, end) of the archive data,
+    /// before layers are applied, holding its blocks
+    ///
+    /// They are computed from the footer only, without reading the data: a
+    /// block ends where the next one, of any file, starts. Unlike
+    /// `get_compressed_size`, interleaved files are then not overestimated.
+    /// Padding and checkpoint blocks are accounted to the block preceding
+    /// them, and the end of each file is left out
+    pub fn get_files_extents(&self) -> Result<HashMap<String, Vec<(u64, u64)>>, Error> {
+        if let Some(ArchiveFooter { files_info, .. }) = &self.metadata {
+            let mut boundaries: Vec<u64> = files_info
+                .values()
+                .flat_map(|file_info| {
+                    file_info
+                        .offsets
+                        .iter()
+                        .copied()
+                        .chain(std::iter::once(file_info.eof_offset))
+                })
+                .collect();
+            boundaries.sort_unstable();
+            boundaries.dedup();
+            Ok(files_info
+                .iter()
+                .map(|(fname, file_info)| {
+                    let mut extents: Vec<(u64, u64)> = Vec::new();
+                    // The end of the file may start a run of its blocks, if
+                    // it follows blocks of another file
+                    let starts = file_info
+                        .offsets
+                        .iter()
+                        .filter(|start| **start < file_info.eof_offset);
+                    for start in starts {
+                        // At least the end of the file follows
+                        let end = boundaries[boundaries.partition_point(|offset| offset <= start)];
+                        match extents.last_mut() {
+                            Some(last) if last.1 == *start => last.1 = end,
+                            _ => extents.push((*start, end)),
+                        }
+                    }
+                    (fname.clone(), extents)
+                })
+                .collect())
+        } else {
+            Err(Error::MissingMetadata)
+        }
+    }
 
-pub struct ArchiveFailSafeReader<'a, R: 'a + Read> {
-    /// MLA Archive format Reader (fail-safe)
+    /// Return the ranges [start, end) of the source, as given to
+    /// `from_config`, holding the content of `filename`, in order
+    ///
+    /// Without any layer, the content is stored as is: it can then be copied
+    /// straight from the source, such as with `copy_file_range`, instead of
+    /// being read through `get_file`. Only the block headers are read.
+    ///
+    /// Return None if the file is not in the archive, if layers are enabled,
+    /// or if some of its content is deduplicated (`FileCopy` blocks)
+    pub fn get_file_ranges(&mut self, filename: &str) -> Result<Option<Vec<(u64, u64)>>, Error> {
+        if !self.config.layers_enabled.is_empty() {
+            return Ok(None);
+        }
+        let data_offset = self.data_offset;
+        let mut file = match self.get_file(filename.to_string())? {
+            Some(file) => file,
+            None => return Ok(None),
+        };
+        if file.size == 0 {
+            return Ok(Some(Vec::new()));
+        }
+        // Locate every block, up to the one holding the last byte
+        file.data.seek_to(file.size - 1)?;
+        let mut ranges: Vec<(u64, u64)> = Vec::new();
+        for block in &file.data.known_blocks {
+            if block.copy.is_some() {
+                return Ok(None);
+            }
+            let start = data_offset + block.data_offset;
+            let end = start + block.length;
+            match ranges.last_mut() {
+                Some(last) if last.1 == start => last.1 = end,
+                _ => ranges.push((start, end)),
+            }
+        }
+        Ok(Some(ranges))
+    }
 
-    /// User's reading configuration
-    // config is not used for now after reader creation,
-    // but it could in the future
-    #[allow(dead_code)]
-    config: ArchiveReaderConfig,
-    /// Source
-    src: Box<dyn 'a + LayerFailSafeReader<'a, R>>,
-}
+    /// Return the hash of a file, computed with the hash algorithm of the
+    /// archive (see `ArchiveInfo::hash_algorithm`)
+    pub fn get_hash(&mut self, filename: &str) -> Result<Option<Vec<u8>>, Error> {
+        // Get file relative information
+        let eof_offset = match self.find_file_info(filename)? {
+            None => return Ok(None),
+            Some(finfo) => finfo.eof_offset,
+        };
+        Ok(Some(self.read_hash_at(eof_offset)?))
+    }
 
-// Size of the repaired file blocks
-const CACHE_SIZE: usize = 8 * 1024 * 1024; // 8MB
+    /// Return the footer information on `filename`, read from the archive if
+    /// the footer has been left there (see
+    /// `ArchiveReaderConfig::set_memory_budget`)
+    fn find_file_info(&mut self, filename: &str) -> Result<Option<FileInfo>, Error> {
+        match (&self.metadata, &self.lazy_footer) {
+            (Some(ArchiveFooter { files_info }), _) => Ok(files_info.get(filename).cloned()),
+            (None, Some(lazy_footer)) => lazy_footer.find(&mut self.src, filename),
+            (None, None) => Err(Error::MissingMetadata),
+        }
+    }
 
-/// Used to update the error state only if it was NoError
-/// ```
-/// update_error!(error_var, FailSafeReadError::...)
-/// ``̀
-macro_rules! update_error {
-    ( $x:ident = $y:expr ) => {
-        #[allow(clippy::single_match)]
-        match $x {
-            FailSafeReadError::NoError => {
-                $x = $y;
-            }
-            _ => {}
+    /// Return the hash held by the EoF tag at `eof_offset`
+    fn read_hash_at(&mut self, eof_offset: u64) -> Result<Vec<u8>, Error> {
+        // Set the inner layer at the start of the EoF tag
+        self.src.seek(SeekFrom::Start(eof_offset))?;
+        let tagged = has_block_tags(self.info.format_version);
+        match ArchiveFileBlock::from(&mut self.src, tagged)? {
+            ArchiveFileBlock::EndOfFile { hash, .. } => Ok(hash),
+            _ => Err(Error::WrongReaderState(
+                "[ArchiveReader] eof_offset must point to a EoF".to_string(),
+            )),
         }
-    };
-}
+    }
 
-impl<'b, R: 'b + Read> ArchiveFailSafeReader<'b, R> {
-    pub fn from_config(mut src: R, mut config: ArchiveReaderConfig) -> Result<Self, Error> {
-        let header = ArchiveHeader::from(&mut src)?;
-        config.load_persistent(header.config)?;
+    /// Return the hash of every file
+    ///
+    /// The hashes are read in the archive order, so that each compressed
+    /// block holding them is only decompressed once. On big archives, it is
+    /// much faster than calling `get_hash` for each file
+    pub fn get_hashes(&mut self) -> Result<HashMap<String, Vec<u8>>, Error> {
+        let mut eof_offsets: Vec<(u64, String)> = match &self.metadata {
+            Some(ArchiveFooter { files_info }) => files_info
+                .iter()
+                .map(|(filename, file_info)| (file_info.eof_offset, filename.clone()))
+                .collect(),
+            None => return Err(Error::MissingMetadata),
+        };
+        eof_offsets.sort();
 
-        // Enable layers depending on user option. Order is relevant
-        let mut src: Box<dyn 'b + LayerFailSafeReader<'b, R>> =
-            Box::new(RawLayerFailSafeReader::new(src));
-        if config.layers_enabled.contains(Layers::ENCRYPT) {
-            src = Box::new(EncryptionLayerFailSafeReader::new(src, &config.encrypt)?);
+        let mut hashes = HashMap::with_capacity(eof_offsets.len());
+        for (eof_offset, filename) in eof_offsets {
+            let hash = self.read_hash_at(eof_offset)?;
+            hashes.insert(filename, hash);
         }
-        if config.layers_enabled.contains(Layers::COMPRESS) {
-            src = Box::new(CompressionLayerFailSafeReader::new(src)?);
+        Ok(hashes)
+    }
+
+    /// Return the filesystem metadata of a file, if recorded (see
+    /// `FileOptions::set_metadata`)
+    ///
+    /// Files sharing their content (see
+    /// `ArchiveWriterConfig::set_deduplication`) share their metadata too
+    pub fn get_file_metadata(&mut self, filename: &str) -> Result<Option<FileMetadata>, Error> {
+        let start = match self
+            .find_file_info(filename)?
+            .and_then(|file_info| file_info.offsets.first().copied())
+        {
+            None => return Ok(None),
+            Some(start) => start,
+        };
+        self.read_metadata_at(start)
+    }
+
+    /// Return the metadata, if any, of the file whose FileStart is at `start`
+    fn read_metadata_at(&mut self, start: u64) -> Result<Option<FileMetadata>, Error> {
+        if self.info.format_version <= METADATALESS_FORMAT_VERSION {
+            return Ok(None);
         }
 
-        Ok(Self { config, src })
+        // The metadata, if any, follows the FileStart
+        self.src.seek(SeekFrom::Start(start))?;
+        let tagged = has_block_tags(self.info.format_version);
+        let id = match ArchiveFileBlock::from(&mut self.src, tagged)? {
+            ArchiveFileBlock::FileStart { id, .. } => id,
+            _ => {
+                return Err(Error::WrongReaderState(
+                    "[ArchiveReader] A file must start with a FileStart".to_string(),
+                ))
+            }
+        };
+        match ArchiveFileBlock::from(&mut self.src, tagged)? {
+            ArchiveFileBlock::FileMetadata {
+                id: metadata_id,
+                metadata,
+            } if metadata_id == id => Ok(Some(metadata)),
+            _ => Ok(None),
+        }
     }
 
-    pub fn new(src: R) -> Result<Self, Error> {
-        Self::from_config(src, ArchiveReaderConfig::new())
+    /// Return the information on `filename`: its size and location, from the
+    /// footer, with its hash and metadata
+    ///
+    /// Unlike `get_file`, no `ArchiveFile` is built: only the blocks holding
+    /// the hash and the metadata are read, not the file content
+    pub fn get_entry_info(&mut self, filename: &str) -> Result<Option<EntryInfo>, Error> {
+        match self.find_file_info(filename)? {
+            Some(file_info) => Ok(Some(self.read_entry_info(filename.to_string(), file_info)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Build the information on the file `filename`, reading its hash and
+    /// metadata
+    fn read_entry_info(
+        &mut self,
+        filename: String,
+        file_info: FileInfo,
+    ) -> Result<EntryInfo, Error> {
+        let mut entry = EntryInfo {
+            name: filename,
+            size: file_info.size,
+            hash: Vec::new(),
+            offsets: file_info.offsets,
+            metadata: None,
+        };
+        if let Some(start) = entry.offsets.first() {
+            entry.metadata = self.read_metadata_at(*start)?;
+        }
+        entry.hash = self.read_hash_at(file_info.eof_offset)?;
+        Ok(entry)
+    }
+
+    /// Return the information on every file: its size and location, from the
+    /// footer, with its hash and metadata
+    ///
+    /// Files are given in the archive order, so that each compressed block
+    /// is only decompressed once. As for `get_hashes`, it is much faster than
+    /// calling `get_file` for each file
+    pub fn iter_entries(&mut self) -> Result<impl Iterator<Item = EntryInfo>, Error> {
+        let mut entries: Vec<(EntryInfo, u64)> = match &self.metadata {
+            Some(ArchiveFooter { files_info }) => files_info
+                .iter()
+                .map(|(filename, file_info)| {
+                    let entry = EntryInfo {
+                        name: filename.clone(),
+                        size: file_info.size,
+                        hash: Vec::new(),
+                        offsets: file_info.offsets.clone(),
+                        metadata: None,
+                    };
+                    (entry, file_info.eof_offset)
+                })
+                .collect(),
+            None => return Err(Error::MissingMetadata),
+        };
+        entries.sort_by_key(|(entry, eof_offset)| (entry.offsets.first().copied(), *eof_offset));
+
+        for (entry, eof_offset) in entries.iter_mut() {
+            if let Some(start) = entry.offsets.first() {
+                entry.metadata = self.read_metadata_at(*start)?;
+            }
+            entry.hash = self.read_hash_at(*eof_offset)?;
+        }
+        Ok(entries.into_iter().map(|(entry, _)| entry))
+    }
+
+    /// Call `callback` with the information on every file, as given by
+    /// `iter_entries`
+    ///
+    /// If the footer has been left in the archive (see
+    /// `ArchiveReaderConfig::set_memory_budget`), entries are read one at a
+    /// time, in the footer order, instead of being all loaded
+    pub fn for_each_entry<F: FnMut(EntryInfo) -> Result<(), Error>>(
+        &mut self,
+        mut callback: F,
+    ) -> Result<(), Error> {
+        let (mut pos, count) = match &self.lazy_footer {
+            Some(lazy_footer) => (lazy_footer.start, lazy_footer.count),
+            None => {
+                for entry in self.iter_entries()? {
+                    callback(entry)?;
+                }
+                return Ok(());
+            }
+        };
+        for _ in 0..count {
+            let (filename, file_info, next) = read_footer_entry_at(&mut self.src, pos)?;
+            pos = next;
+            callback(self.read_entry_info(filename, file_info)?)?;
+        }
+        Ok(())
+    }
+
+    /// Get the file `filename`, whose `data` can be read and seeked (see
+    /// `BlocksToFileReader`)
+    pub fn get_file<'a>(
+        &'a mut self,
+        filename: String,
+    ) -> Result<Option<ArchiveFile<ArchiveEntryReader<'a, 'b, R>>>, Error> {
+        // Get file relative information
+        let (offsets, size, footer) = match (&self.metadata, &self.lazy_footer) {
+            (Some(ArchiveFooter { files_info }), _) => match files_info.get(&filename) {
+                None => return Ok(None),
+                Some(finfo) => (
+                    Cow::Borrowed(finfo.offsets.as_slice()),
+                    finfo.size,
+                    FooterRef::Loaded(files_info),
+                ),
+            },
+            (None, Some(lazy_footer)) => match lazy_footer.find(&mut self.src, &filename)? {
+                None => return Ok(None),
+                Some(finfo) => (
+                    Cow::Owned(finfo.offsets),
+                    finfo.size,
+                    FooterRef::Lazy(lazy_footer),
+                ),
+            },
+            (None, None) => return Err(Error::MissingMetadata),
+        };
+        if offsets.is_empty() {
+            return Err(Error::WrongReaderState(
+                "[ArchiveReader] A file must have at least one offset".to_string(),
+            ));
+        }
+
+        // Instantiate the file representation
+        self.src.start_entry();
+        let mut reader = BlocksToFileReader::new(
+            &mut self.src,
+            offsets,
+            size,
+            has_block_tags(self.info.format_version),
+            footer,
+        )?;
+        if let Some(budget) = self.config.limits.memory_budget {
+            reader.copy_read_ahead = budget.clamp(1, COPY_READ_AHEAD_SIZE);
+        }
+        Ok(Some(ArchiveFile {
+            filename,
+            data: reader,
+            size,
+        }))
+    }
+
+    /// Compute the hash of a file content with `algorithm`
+    ///
+    /// Unlike `get_hash`, the content is actually read, without being kept
+    /// in memory. The result can then be checked against the recorded hash,
+    /// or be computed with another algorithm than the archive one
+    pub fn hash_file(
+        &mut self,
+        filename: &str,
+        algorithm: HashAlgorithm,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let mut file = match self.get_file(filename.to_string())? {
+            Some(file) => file,
+            None => return Ok(None),
+        };
+        let mut hasher = FileHasher::new(algorithm);
+        io::copy(&mut file.data, &mut hasher)?;
+        Ok(Some(hasher.finalize()))
+    }
+
+    /// Read the first `length` bytes of a file content, or all of it if it
+    /// is shorter, such as to look for a magic number
+    ///
+    /// Only the beginning of the file is read, and decompressed, instead of
+    /// its whole content
+    pub fn read_file_head(
+        &mut self,
+        filename: &str,
+        length: usize,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let file = match self.get_file(filename.to_string())? {
+            Some(file) => file,
+            None => return Ok(None),
+        };
+        let mut head = Vec::with_capacity(length);
+        file.data.take(length as u64).read_to_end(&mut head)?;
+        Ok(Some(head))
+    }
+
+    /// Read the last `length` bytes of a file content, or all of it if it is
+    /// shorter, such as to preview the end of a log
+    ///
+    /// The file is seeked from its end: only the headers of the preceding
+    /// blocks are read, and the content is decompressed from the compressed
+    /// block containing the start of the tail
+    pub fn read_file_tail(
+        &mut self,
+        filename: &str,
+        length: usize,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let mut file = match self.get_file(filename.to_string())? {
+            Some(file) => file,
+            None => return Ok(None),
+        };
+        let length = std::cmp::min(length as u64, file.size);
+        file.data.seek(SeekFrom::End(-(length as i64)))?;
+        let mut tail = Vec::with_capacity(length as usize);
+        file.data.read_to_end(&mut tail)?;
+        Ok(Some(tail))
+    }
+
+    /// Compute the hash of every file content with `algorithm`, as
+    /// `hash_file`
+    ///
+    /// The archive is read once, linearly (see `helpers::linear_extract`),
+    /// instead of seeking to each block of each file. On big archives, it is
+    /// much faster than calling `hash_file` for each file
+    pub fn hash_files(
+        &mut self,
+        algorithm: HashAlgorithm,
+    ) -> Result<HashMap<String, Vec<u8>>, Error> {
+        let fnames: Vec<String> = self.list_files()?.cloned().collect();
+        let mut hashers: HashMap<&String, FileHasher> = fnames
+            .iter()
+            .map(|fname| (fname, FileHasher::new(algorithm)))
+            .collect();
+        helpers::linear_extract(self, &mut hashers)?;
+        Ok(hashers
+            .into_iter()
+            .map(|(fname, hasher)| (fname.clone(), hasher.finalize()))
+            .collect())
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl<'b> ArchiveReader<'b, io::Cursor<memmap2::Mmap>> {
+    /// Read the archive `file` through a memory mapping of it
+    ///
+    /// Reads are then memory copies instead of system calls, which speeds up
+    /// random accesses, such as hashing or extracting many small files. As
+    /// with any memory mapping, `file` must not be modified while mapped: on
+    /// most platforms, reading a truncated part ends the process (SIGBUS)
+    pub fn from_mmap(file: &std::fs::File, config: ArchiveReaderConfig) -> Result<Self, Error> {
+        // SAFETY: the mapping is read-only, and the caller must ensure the
+        // file is not modified while it is in use
+        let mmap = unsafe { memmap2::Mmap::map(file)? };
+        Self::from_config(io::Cursor::new(mmap), config)
+    }
+
+    /// Open the archive at `path` and read it through a memory mapping, as
+    /// with `from_mmap`
+    pub fn open_mmap<P: AsRef<std::path::Path>>(
+        path: P,
+        config: ArchiveReaderConfig,
+    ) -> Result<Self, Error> {
+        Self::from_mmap(&std::fs::File::open(path)?, config)
+    }
+}
+
+/// Source of independent readers of the same archive, to read several files
+/// concurrently
+///
+/// An `ArchiveReader` reads one file at a time, through `&mut self`. Instead,
+/// each thread can get its own reader from a shared pool, over a new source
+/// given by `open` (such as the archive file, opened again)
+pub struct ArchiveReaderPool<F> {
+    open: F,
+    config: ArchiveReaderConfig,
+}
+
+impl<R: Read + Seek, F: Fn() -> Result<R, Error>> ArchiveReaderPool<F> {
+    /// Build a pool of readers of the archive given by `open`, each with
+    /// `config`
+    pub fn new(open: F, config: ArchiveReaderConfig) -> Self {
+        Self { open, config }
+    }
+
+    /// Open a new reader, independent of the others
+    ///
+    /// As for `ArchiveReader::from_config`, the header and the footer are
+    /// read, and the signature, if any, is checked again
+    pub fn reader<'a>(&self) -> Result<ArchiveReader<'a, R>, Error>
+    where
+        R: 'a,
+    {
+        ArchiveReader::from_config((self.open)()?, self.config.clone())
+    }
+}
+
+/// Recovery status of a file, after a fail-safe conversion
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileRecoveryStatus {
+    /// Fully recovered, with a content matching its hash
+    Recovered,
+    /// Only the given number of bytes, from its beginning, is recovered
+    Truncated(u64),
+    /// Fully read, but with a content not matching its hash, or recovered
+    /// with its beginning replaced by zeros
+    Corrupted,
+    /// Expected, but not found in the recovered data
+    Missing,
+}
+
+/// Outcome of a fail-safe conversion, file per file
+#[derive(Debug)]
+pub struct RecoveryReport {
+    /// Reason the conversion terminates, as returned by `convert_to_archive`
+    pub status: FailSafeReadError,
+    /// Files found, in the order of the archive, with their recovery status
+    pub files: Vec<(String, FileRecoveryStatus)>,
+}
+
+impl RecoveryReport {
+    /// Report as `Missing` the `filenames` not found during the conversion
+    ///
+    /// The fail-safe reader does not rely on the archive index, so files whose
+    /// start is lost are unknown to it. If the index is still readable, its
+    /// filenames can be provided here
+    pub fn add_missing<'a, I: IntoIterator<Item = &'a String>>(&mut self, filenames: I) {
+        for filename in filenames {
+            if !self.files.iter().any(|(name, _)| name == filename) {
+                self.files
+                    .push((filename.clone(), FileRecoveryStatus::Missing));
+            }
+        }
+    }
+}
+
+/// Destination of the files recovered by `ArchiveFailSafeReader`
+trait FailSafeOutput {
+    /// Whether the file would be recovered, if found
+    fn is_selected(&self, _filename: &str) -> bool {
+        true
+    }
+    /// Start a new file, returning its ID, or `None` if it must be skipped
+    fn start_file(&mut self, filename: &str) -> Result<Option<ArchiveFileID>, Error>;
+    fn append_file_content(&mut self, id: ArchiveFileID, data: &[u8]) -> Result<(), Error>;
+    /// Record the filesystem metadata of a file, right after its start
+    fn append_file_metadata(
+        &mut self,
+        _id: ArchiveFileID,
+        _metadata: &FileMetadata,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+    fn end_file(&mut self, id: ArchiveFileID) -> Result<(), Error>;
+}
+
+/// Recover files into a new archive, only those accepted by `filter`
+struct ArchiveOutput<'a, 'b, W: Write, F: Fn(&str) -> bool> {
+    writer: &'a mut ArchiveWriter<'b, W>,
+    filter: F,
+}
+
+impl<'a, 'b, W: Write, F: Fn(&str) -> bool> FailSafeOutput for ArchiveOutput<'a, 'b, W, F> {
+    fn is_selected(&self, filename: &str) -> bool {
+        (self.filter)(filename)
+    }
+
+    fn start_file(&mut self, filename: &str) -> Result<Option<ArchiveFileID>, Error> {
+        if !self.is_selected(filename) {
+            return Ok(None);
+        }
+        Ok(Some(self.writer.start_file(filename)?))
+    }
+
+    fn append_file_content(&mut self, id: ArchiveFileID, data: &[u8]) -> Result<(), Error> {
+        self.writer.append_file_content(id, data.len() as u64, data)
+    }
+
+    fn append_file_metadata(
+        &mut self,
+        id: ArchiveFileID,
+        metadata: &FileMetadata,
+    ) -> Result<(), Error> {
+        self.writer.append_file_metadata(id, metadata)
+    }
+
+    fn end_file(&mut self, id: ArchiveFileID) -> Result<(), Error> {
+        self.writer.end_file(id)
+    }
+}
+
+/// Recover files into the writers returned by `open_file`
+struct ExtractOutput<W: Write, F: FnMut(&str) -> Result<Option<W>, Error>> {
+    open_file: F,
+    /// Writers of the files being recovered
+    id2writer: HashMap<ArchiveFileID, W>,
+    /// Names of the files already started
+    filenames: HashSet<String>,
+    next_id: ArchiveFileID,
+}
+
+impl<W: Write, F: FnMut(&str) -> Result<Option<W>, Error>> FailSafeOutput for ExtractOutput<W, F> {
+    fn start_file(&mut self, filename: &str) -> Result<Option<ArchiveFileID>, Error> {
+        if self.filenames.contains(filename) {
+            return Err(Error::DuplicateFilename);
+        }
+        match (self.open_file)(filename)? {
+            Some(writer) => {
+                let id = self.next_id;
+                self.next_id += 1;
+                self.id2writer.insert(id, writer);
+                self.filenames.insert(filename.to_string());
+                Ok(Some(id))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn append_file_content(&mut self, id: ArchiveFileID, data: &[u8]) -> Result<(), Error> {
+        match self.id2writer.get_mut(&id) {
+            Some(writer) => Ok(writer.write_all(data)?),
+            None => Err(Error::WrongWriterState(
+                "[append_file_content] Unknown file ID".to_string(),
+            )),
+        }
+    }
+
+    fn end_file(&mut self, id: ArchiveFileID) -> Result<(), Error> {
+        match self.id2writer.remove(&id) {
+            Some(mut writer) => Ok(writer.flush()?),
+            None => Err(Error::WrongWriterState(
+                "[end_file] Unknown file ID".to_string(),
+            )),
+        }
+    }
+}
+
+// This code is very similar with MLAArchiveReader
+
+pub struct ArchiveFailSafeReader<'a, R: 'a + Read> {
+    /// MLA Archive format Reader (fail-safe)
+
+    /// User's reading configuration
+    // config is not used for now after reader creation,
+    // but it could in the future
+    #[allow(dead_code)]
+    config: ArchiveReaderConfig,
+    /// Source
+    src: Box<dyn 'a + LayerFailSafeReader<'a, R>>,
+    /// Whether block headers are followed by a tag
+    tagged: bool,
+    /// Algorithm of the hash of each file content
+    hash_algorithm: HashAlgorithm,
+}
+
+// Size of the repaired file blocks
+const CACHE_SIZE: usize = 8 * 1024 * 1024; // 8MB
+
+/// Used to update the error state only if it was NoError
+/// ```
+/// update_error!(error_var, FailSafeReadError::...)
+/// ``̀
+macro_rules! update_error {
+    ( $x:ident = $y:expr ) => {
+        #[allow(clippy::single_match)]
+        match $x {
+            FailSafeReadError::NoError => {
+                $x = $y;
+            }
+            _ => {}
+        }
+    };
+}
+
+/// Reader keeping the bytes of the block header being read, so they can be
+/// read again to look for the next intact block one byte further
+struct ResyncReader<R: Read> {
+    inner: R,
+    /// Bytes to read again, before reading `inner`
+    replay: VecDeque<u8>,
+    /// Bytes read since `start_block`, if recording
+    recorded: Option<Vec<u8>>,
+}
+
+impl<R: Read> ResyncReader<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            replay: VecDeque::new(),
+            recorded: None,
+        }
+    }
+
+    /// Start recording the bytes of a block header
+    fn start_block(&mut self) {
+        self.recorded = Some(Vec::new());
+    }
+
+    /// Stop recording, the block header being intact
+    fn end_block(&mut self) {
+        self.recorded = None;
+    }
+
+    /// Stop recording, and read again the recorded bytes but the first one.
+    /// Returns `false` if there was no recorded byte
+    fn skip_byte(&mut self) -> bool {
+        match self.recorded.take() {
+            Some(recorded) if !recorded.is_empty() => {
+                for byte in recorded.into_iter().skip(1).rev() {
+                    self.replay.push_front(byte);
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl<R: Read> Read for ResyncReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = if self.replay.is_empty() {
+            self.inner.read(buf)?
+        } else {
+            self.replay.read(buf)?
+        };
+        if let Some(recorded) = &mut self.recorded {
+            recorded.extend_from_slice(&buf[..count]);
+        }
+        Ok(count)
+    }
+}
+
+impl<'b, R: 'b + Read> ArchiveFailSafeReader<'b, R> {
+    pub fn from_config(mut src: R, mut config: ArchiveReaderConfig) -> Result<Self, Error> {
+        let header = ArchiveHeader::from(&mut src)?;
+        let tagged = has_block_tags(header.format_version);
+        let hash_algorithm = header.config.hash_algorithm;
+        config.load_persistent(header.config)?;
+        config
+            .compress
+            .set_block_kinds(has_block_kinds(header.format_version));
+
+        // Enable layers depending on user option. Order is relevant
+        let mut src: Box<dyn 'b + LayerFailSafeReader<'b, R>> =
+            Box::new(RawLayerFailSafeReader::new(src));
+        if config.layers_enabled.contains(Layers::FEC) {
+            src = Box::new(FecLayerFailSafeReader::new(src)?);
+        }
+        if config.layers_enabled.contains(Layers::SIGN) {
+            src = Box::new(SignatureLayerFailSafeReader::new(src));
+        }
+        if config.layers_enabled.contains(Layers::ENCRYPT) {
+            src = Box::new(EncryptionLayerFailSafeReader::new(src, &config.encrypt)?);
+        }
+        if config.layers_enabled.contains(Layers::COMPRESS) {
+            src = Box::new(CompressionLayerFailSafeReader::new(src, &config.compress)?);
+        }
+
+        Ok(Self {
+            config,
+            src,
+            tagged,
+            hash_algorithm,
+        })
+    }
+
+    pub fn new(src: R) -> Result<Self, Error> {
+        Self::from_config(src, ArchiveReaderConfig::new())
     }
 
     /// Fail-safe / best-effort conversion of the current archive to a correct
     /// one. On success, returns the reason conversion terminates (ideally,
     /// EndOfOriginalArchiveData)
-    #[allow(clippy::cognitive_complexity)]
+    ///
+    /// If blocks are tagged (format version 11 and later), a damaged block is
+    /// skipped, and the conversion goes on with the next intact block. The
+    /// first error met is then returned, and files with skipped parts are
+    /// reported as unfinished
     pub fn convert_to_archive<W: Write>(
         &mut self,
         output: &mut ArchiveWriter<W>,
     ) -> Result<FailSafeReadError, Error> {
+        Ok(self.convert_to_archive_with_report(output)?.status)
+    }
+
+    /// Fail-safe conversion, as `convert_to_archive`, also reporting the
+    /// recovery status of each file found
+    pub fn convert_to_archive_with_report<W: Write>(
+        &mut self,
+        output: &mut ArchiveWriter<W>,
+    ) -> Result<RecoveryReport, Error> {
+        self.convert_to_archive_filtered(output, |_| true)
+    }
+
+    /// Fail-safe conversion, as `convert_to_archive_with_report`, of only the
+    /// files whose name is accepted by `filter`
+    ///
+    /// The content of other files is skipped without being decoded further,
+    /// and they are absent from the report
+    pub fn convert_to_archive_filtered<W: Write, F: Fn(&str) -> bool>(
+        &mut self,
+        output: &mut ArchiveWriter<W>,
+        filter: F,
+    ) -> Result<RecoveryReport, Error> {
+        let report = self.recover_to(&mut ArchiveOutput {
+            writer: output,
+            filter,
+        })?;
+        output.finalize()?;
+        Ok(report)
+    }
+
+    /// Fail-safe extraction, writing the content of each file to the writer
+    /// returned by `open_file` for its name, instead of a new archive
+    ///
+    /// Files for which `open_file` returns `None` are skipped, as with
+    /// `convert_to_archive_filtered`. As for a conversion, the recovered part
+    /// of truncated and corrupted files is written. Lost files listed in a
+    /// checkpoint are reported as missing, without calling `open_file`
+    pub fn extract_with_report<W: Write, F: FnMut(&str) -> Result<Option<W>, Error>>(
+        &mut self,
+        open_file: F,
+    ) -> Result<RecoveryReport, Error> {
+        self.recover_to(&mut ExtractOutput {
+            open_file,
+            id2writer: HashMap::new(),
+            filenames: HashSet::new(),
+            next_id: 0,
+        })
+    }
+
+    #[allow(clippy::cognitive_complexity)]
+    fn recover_to<O: FailSafeOutput>(&mut self, output: &mut O) -> Result<RecoveryReport, Error> {
         let mut error = FailSafeReadError::NoError;
+        let tagged = self.tagged;
+        let mut src = ResyncReader::new(&mut self.src);
 
         // Associate an id retrieved from the archive to repair, to the
         // corresponding output file id
@@ -1028,14 +3959,67 @@ impl<'b, R: 'b + Read> ArchiveFailSafeReader<'b, R> {
         let mut id_failsafe2filename: HashMap<ArchiveFileID, String> = HashMap::new();
         // List of IDs from the archive already fully added
         let mut id_failsafe_done = Vec::new();
+        // List of IDs from the archive whose content is known to be damaged,
+        // ignored from now on
+        let mut id_failsafe_damaged = Vec::new();
         // Associate an id retrieved from the archive with its ongoing Hash
-        let mut id_failsafe2hash: HashMap<ArchiveFileID, Sha256> = HashMap::new();
+        let mut id_failsafe2hash: HashMap<ArchiveFileID, FileHasher> = HashMap::new();
+        // List of IDs from the archive, in the order files are started
+        let mut id_failsafe_order = Vec::new();
+        // Associate an id retrieved from the archive with the size of its
+        // content recovered so far
+        let mut id_failsafe2size: HashMap<ArchiveFileID, u64> = HashMap::new();
+        // List of IDs from the archive skipped by `output`, whose content is
+        // ignored
+        let mut id_failsafe_ignored = Vec::new();
+        // List of IDs from the archive started from a checkpoint, their
+        // `FileStart` being lost, whose beginning is replaced by zeros
+        let mut id_failsafe_partial = Vec::new();
+        // Filenames of ended files, listed in a checkpoint but never found
+        let mut checkpoint_missing = Vec::new();
+
+        // Skip `$length` bytes of block data, or stop the `$label` loop if
+        // they cannot be read
+        macro_rules! skip_data {
+            ( $label:lifetime, $length:expr ) => {
+                match io::copy(&mut (&mut src).take($length), &mut io::sink()) {
+                    Ok(count) if count == $length => {}
+                    Ok(_) => {
+                        update_error!(error = FailSafeReadError::UnexpectedEOFOnNextBlock);
+                        break $label;
+                    }
+                    Err(err) => {
+                        update_error!(error = FailSafeReadError::IOErrorOnNextBlock(err));
+                        break $label;
+                    }
+                }
+            };
+        }
+
+        // On an inconsistency, stop if blocks are not tagged, as the following
+        // ones are not trustworthy. Otherwise, skip `$length` bytes of block
+        // data and go on with the next block of the `$label` loop
+        macro_rules! on_inconsistency {
+            ( $label:lifetime, $length:expr ) => {
+                if !tagged {
+                    break $label;
+                }
+                skip_data!($label, $length);
+                continue $label;
+            };
+        }
 
         'read_block: loop {
-            match ArchiveFileBlock::from(&mut self.src) {
+            src.start_block();
+            match ArchiveFileBlock::from(&mut src, tagged) {
                 Err(Error::IOError(err)) => {
                     if let std::io::ErrorKind::UnexpectedEof = err.kind() {
                         update_error!(error = FailSafeReadError::UnexpectedEOFOnNextBlock);
+                        // A damaged header may also claim more bytes than
+                        // available
+                        if tagged && src.skip_byte() {
+                            continue 'read_block;
+                        }
                         break;
                     }
                     update_error!(error = FailSafeReadError::IOErrorOnNextBlock(err));
@@ -1043,37 +4027,56 @@ impl<'b, R: 'b + Read> ArchiveFailSafeReader<'b, R> {
                 }
                 Err(err) => {
                     update_error!(error = FailSafeReadError::ErrorOnNextBlock(err));
+                    // Look for the next intact block, one byte further
+                    if tagged && src.skip_byte() {
+                        continue 'read_block;
+                    }
                     break;
                 }
                 Ok(block) => {
+                    src.end_block();
                     match block {
                         ArchiveFileBlock::FileStart { filename, id } => {
-                            if let Some(_id_output) = id_failsafe2id_output.get(&id) {
+                            if id_failsafe2id_output.contains_key(&id)
+                                || id_failsafe_ignored.contains(&id)
+                            {
                                 update_error!(error = FailSafeReadError::ArchiveFileIDReuse(id));
-                                break 'read_block;
+                                on_inconsistency!('read_block, 0);
                             }
                             if id_failsafe_done.contains(&id) {
                                 update_error!(
                                     error = FailSafeReadError::ArchiveFileIDAlreadyClose(id)
                                 );
-                                break 'read_block;
+                                on_inconsistency!('read_block, 0);
                             }
 
-                            id_failsafe2filename.insert(id, filename.clone());
                             let id_output = match output.start_file(&filename) {
                                 Err(Error::DuplicateFilename) => {
                                     update_error!(
                                         error = FailSafeReadError::FilenameReuse(filename)
                                     );
-                                    break 'read_block;
+                                    on_inconsistency!('read_block, 0);
                                 }
                                 Err(err) => {
                                     return Err(err);
                                 }
-                                Ok(id) => id,
+                                Ok(Some(id)) => id,
+                                Ok(None) => {
+                                    id_failsafe_ignored.push(id);
+                                    continue 'read_block;
+                                }
                             };
+                            id_failsafe2filename.insert(id, filename);
                             id_failsafe2id_output.insert(id, id_output);
-                            id_failsafe2hash.insert(id, Sha256::default());
+                            id_failsafe2hash.insert(id, FileHasher::new(self.hash_algorithm));
+                            id_failsafe_order.push(id);
+                            id_failsafe2size.insert(id, 0);
+                        }
+                        ArchiveFileBlock::FileContent { length, id, .. }
+                            if id_failsafe_ignored.contains(&id) =>
+                        {
+                            // Content of a file not selected, to skip
+                            skip_data!('read_block, length);
                         }
                         ArchiveFileBlock::FileContent { length, id, .. } => {
                             let id_output = match id_failsafe2id_output.get(&id) {
@@ -1082,14 +4085,14 @@ impl<'b, R: 'b + Read> ArchiveFailSafeReader<'b, R> {
                                     update_error!(
                                         error = FailSafeReadError::ContentForUnknownFile(id)
                                     );
-                                    break 'read_block;
+                                    on_inconsistency!('read_block, length);
                                 }
                             };
-                            if id_failsafe_done.contains(&id) {
+                            if id_failsafe_done.contains(&id) || id_failsafe_damaged.contains(&id) {
                                 update_error!(
                                     error = FailSafeReadError::ArchiveFileIDAlreadyClose(id)
                                 );
-                                break 'read_block;
+                                on_inconsistency!('read_block, length);
                             }
                             let fname = id_failsafe2filename.get(&id).expect(
                                 "`id_failsafe2filename` not more sync with `id_failsafe2id_output`",
@@ -1097,8 +4100,11 @@ impl<'b, R: 'b + Read> ArchiveFailSafeReader<'b, R> {
                             let hash = id_failsafe2hash.get_mut(&id).expect(
                                 "`id_failsafe2hash` not more sync with `id_failsafe2id_output`",
                             );
+                            let size = id_failsafe2size.get_mut(&id).expect(
+                                "`id_failsafe2size` not more sync with `id_failsafe2id_output`",
+                            );
 
-                            let src = &mut (&mut self.src).take(length as u64);
+                            let src = &mut (&mut src).take(length);
                             'content: loop {
                                 let mut buf = Vec::with_capacity(CACHE_SIZE);
                                 'buf_fill: loop {
@@ -1114,11 +4120,9 @@ impl<'b, R: 'b + Read> ArchiveFailSafeReader<'b, R> {
                                         }
                                         Err(err) => {
                                             // Stop reconstruction
-                                            output.append_file_content(
-                                                id_output,
-                                                buf.len() as u64,
-                                                buf.as_slice(),
-                                            )?;
+                                            output
+                                                .append_file_content(id_output, buf.as_slice())?;
+                                            *size += buf.len() as u64;
                                             update_error!(
                                                 error = FailSafeReadError::ErrorInFile(
                                                     err,
@@ -1133,11 +4137,8 @@ impl<'b, R: 'b + Read> ArchiveFailSafeReader<'b, R> {
                                         break 'buf_fill;
                                     }
                                 }
-                                output.append_file_content(
-                                    id_output,
-                                    buf.len() as u64,
-                                    buf.as_slice(),
-                                )?;
+                                output.append_file_content(id_output, buf.as_slice())?;
+                                *size += buf.len() as u64;
                                 hash.update(buf.as_slice());
                                 if buf.len() < CACHE_SIZE {
                                     // EOF
@@ -1145,31 +4146,87 @@ impl<'b, R: 'b + Read> ArchiveFailSafeReader<'b, R> {
                                 }
                             }
                         }
-                        ArchiveFileBlock::EndOfFile { id, hash } => {
-                            let id_output = match id_failsafe2id_output.get(&id) {
-                                Some(id_output) => *id_output,
-                                None => {
+                        ArchiveFileBlock::FileCopy { id, .. }
+                            if id_failsafe_ignored.contains(&id) =>
+                        {
+                            // Content of a file not selected, or already
+                            // truncated
+                        }
+                        ArchiveFileBlock::FileCopy { id, .. } => {
+                            if !id_failsafe2id_output.contains_key(&id) {
+                                update_error!(error = FailSafeReadError::ContentForUnknownFile(id));
+                                on_inconsistency!('read_block, 0);
+                            }
+                            if id_failsafe_done.contains(&id) || id_failsafe_damaged.contains(&id) {
+                                update_error!(
+                                    error = FailSafeReadError::ArchiveFileIDAlreadyClose(id)
+                                );
+                                on_inconsistency!('read_block, 0);
+                            }
+                            // The copied file is not read again, so the
+                            // content recovered so far is kept as a truncated
+                            // file, and its following blocks are ignored
+                            update_error!(error = FailSafeReadError::ContentCopiedFromFile(id));
+                            id_failsafe_ignored.push(id);
+                        }
+                        ArchiveFileBlock::FileMetadata { id, .. }
+                            if id_failsafe_ignored.contains(&id) => {}
+                        ArchiveFileBlock::FileMetadata { id, metadata } => {
+                            let id_output = match id_failsafe2id_output.get(&id) {
+                                Some(id_output) => *id_output,
+                                None => {
+                                    update_error!(
+                                        error = FailSafeReadError::ContentForUnknownFile(id)
+                                    );
+                                    on_inconsistency!('read_block, 0);
+                                }
+                            };
+                            if id_failsafe_done.contains(&id) || id_failsafe_damaged.contains(&id) {
+                                update_error!(
+                                    error = FailSafeReadError::ArchiveFileIDAlreadyClose(id)
+                                );
+                                on_inconsistency!('read_block, 0);
+                            }
+                            match output.append_file_metadata(id_output, &metadata) {
+                                Ok(()) => {}
+                                // Not right after the start of the file
+                                Err(err @ Error::WrongWriterState(_)) => {
+                                    update_error!(error = FailSafeReadError::ErrorOnNextBlock(err));
+                                    on_inconsistency!('read_block, 0);
+                                }
+                                Err(err) => return Err(err),
+                            }
+                        }
+                        ArchiveFileBlock::EndOfFile { id, .. }
+                            if id_failsafe_ignored.contains(&id) => {}
+                        ArchiveFileBlock::EndOfFile { id, hash } => {
+                            let id_output = match id_failsafe2id_output.get(&id) {
+                                Some(id_output) => *id_output,
+                                None => {
                                     update_error!(error = FailSafeReadError::EOFForUnknownFile(id));
-                                    break 'read_block;
+                                    on_inconsistency!('read_block, 0);
                                 }
                             };
-                            if id_failsafe_done.contains(&id) {
+                            if id_failsafe_done.contains(&id) || id_failsafe_damaged.contains(&id) {
                                 update_error!(
                                     error = FailSafeReadError::ArchiveFileIDAlreadyClose(id)
                                 );
-                                break 'read_block;
+                                on_inconsistency!('read_block, 0);
                             }
                             match id_failsafe2hash.remove(&id) {
                                 Some(hash_archive) => {
                                     let computed_hash = hash_archive.finalize();
-                                    if computed_hash.as_slice() != hash {
+                                    if computed_hash != hash {
                                         update_error!(
                                             error = FailSafeReadError::HashDiffers {
-                                                expected: Vec::from(computed_hash.as_slice()),
-                                                obtained: Vec::from(&hash[..]),
+                                                expected: computed_hash,
+                                                obtained: hash,
                                             }
                                         );
-                                        break 'read_block;
+                                        // The file is kept, but reported as
+                                        // unfinished
+                                        id_failsafe_damaged.push(id);
+                                        on_inconsistency!('read_block, 0);
                                     }
                                 }
                                 None => {
@@ -1184,6 +4241,109 @@ impl<'b, R: 'b + Read> ArchiveFailSafeReader<'b, R> {
                             output.end_file(id_output)?;
                             id_failsafe_done.push(id);
                         }
+                        ArchiveFileBlock::Padding { length } => {
+                            // Meaningless data, to skip
+                            skip_data!('read_block, length);
+                        }
+                        ArchiveFileBlock::Checkpoint { length, .. } => {
+                            if length > BINCODE_MAX_DESERIALIZE {
+                                update_error!(
+                                    error = FailSafeReadError::ErrorOnNextBlock(
+                                        Error::DeserializationError
+                                    )
+                                );
+                                skip_data!('read_block, length);
+                                continue 'read_block;
+                            }
+                            let mut data = Vec::new();
+                            match (&mut src).take(length).read_to_end(&mut data) {
+                                Ok(count) if count as u64 == length => {}
+                                Ok(_) => {
+                                    update_error!(
+                                        error = FailSafeReadError::UnexpectedEOFOnNextBlock
+                                    );
+                                    break 'read_block;
+                                }
+                                Err(err) => {
+                                    update_error!(
+                                        error = FailSafeReadError::IOErrorOnNextBlock(err)
+                                    );
+                                    break 'read_block;
+                                }
+                            }
+                            let entries: Vec<CheckpointEntry> = match bincode::config()
+                                .limit(BINCODE_MAX_DESERIALIZE)
+                                .deserialize(&data)
+                            {
+                                Ok(entries) => entries,
+                                Err(_) => {
+                                    // Only the block header is tagged
+                                    update_error!(
+                                        error = FailSafeReadError::ErrorOnNextBlock(
+                                            Error::DeserializationError
+                                        )
+                                    );
+                                    continue 'read_block;
+                                }
+                            };
+
+                            // Files unknown so far have lost their `FileStart`
+                            for entry in entries {
+                                if id_failsafe2id_output.contains_key(&entry.id)
+                                    || id_failsafe_ignored.contains(&entry.id)
+                                    || id_failsafe_done.contains(&entry.id)
+                                {
+                                    continue;
+                                }
+                                if entry.ended {
+                                    // Nothing more will be found for it
+                                    if output.is_selected(&entry.filename)
+                                        && !checkpoint_missing.contains(&entry.filename)
+                                    {
+                                        checkpoint_missing.push(entry.filename);
+                                    }
+                                    continue;
+                                }
+                                let id_output = match output.start_file(&entry.filename) {
+                                    Err(Error::DuplicateFilename) => {
+                                        update_error!(
+                                            error =
+                                                FailSafeReadError::FilenameReuse(entry.filename)
+                                        );
+                                        continue;
+                                    }
+                                    Err(err) => {
+                                        return Err(err);
+                                    }
+                                    Ok(Some(id)) => id,
+                                    Ok(None) => {
+                                        id_failsafe_ignored.push(entry.id);
+                                        continue;
+                                    }
+                                };
+                                // Keep the following content at its place in
+                                // the file
+                                let zeros = vec![
+                                    0u8;
+                                    std::cmp::min(entry.size, CACHE_SIZE as u64)
+                                        as usize
+                                ];
+                                let mut remaining = entry.size;
+                                while remaining > 0 {
+                                    let count = std::cmp::min(remaining, zeros.len() as u64);
+                                    output
+                                        .append_file_content(id_output, &zeros[..count as usize])?;
+                                    remaining -= count;
+                                }
+                                id_failsafe2filename.insert(entry.id, entry.filename);
+                                id_failsafe2id_output.insert(entry.id, id_output);
+                                id_failsafe2hash
+                                    .insert(entry.id, FileHasher::new(self.hash_algorithm));
+                                id_failsafe_order.push(entry.id);
+                                id_failsafe2size.insert(entry.id, entry.size);
+                                id_failsafe_partial.push(entry.id);
+                            }
+                        }
                         ArchiveFileBlock::EndOfArchiveData => {
                             // Expected end
                             update_error!(error = FailSafeReadError::EndOfOriginalArchiveData);
@@ -1194,6 +4354,24 @@ impl<'b, R: 'b + Read> ArchiveFailSafeReader<'b, R> {
             };
         }
 
+        // Status of each file found
+        let files = id_failsafe_order
+            .iter()
+            .map(|id| {
+                let status = if id_failsafe_done.contains(id) {
+                    FileRecoveryStatus::Recovered
+                } else if id_failsafe_damaged.contains(id) || id_failsafe_partial.contains(id) {
+                    FileRecoveryStatus::Corrupted
+                } else {
+                    FileRecoveryStatus::Truncated(id_failsafe2size[id])
+                };
+                let fname = id_failsafe2filename
+                    .get(id)
+                    .expect("`id_failsafe2filename` not more sync with `id_failsafe_order`");
+                (fname.clone(), status)
+            })
+            .collect();
+
         let mut unfinished_files = Vec::new();
 
         // Clean-up files still opened
@@ -1219,20 +4397,235 @@ impl<'b, R: 'b + Read> ArchiveFailSafeReader<'b, R> {
             };
         }
 
-        output.finalize()?;
-        Ok(error)
+        let mut report = RecoveryReport {
+            status: error,
+            files,
+        };
+        report.add_missing(&checkpoint_missing);
+        Ok(report)
+    }
+}
+
+/// Strict reader of an archive consumed sequentially, from any `Read` (a
+/// pipe, a socket)
+///
+/// Unlike `ArchiveReader`, no seek is needed: the footer and the index are
+/// not used, and files are given as they appear in the archive. Unlike
+/// `ArchiveFailSafeReader`, nothing is recovered: the first inconsistency,
+/// or a file whose content does not match its hash, is an error.
+///
+/// The tag of each encrypted chunk is checked before any of its content is
+/// used, and each file is checked against its hash before being given back.
+/// Signed archives are refused, as the signature covers the whole archive and
+/// could only be checked once every file is extracted. Content shared by
+/// deduplicated files cannot be read again, and is an error too
+pub struct ArchiveStreamReader<'a, R: 'a + Read> {
+    /// Source
+    src: Box<dyn 'a + LayerFailSafeReader<'a, R>>,
+    /// Whether block headers are followed by a tag
+    tagged: bool,
+    /// Algorithm of the hash of each file content
+    hash_algorithm: HashAlgorithm,
+}
+
+impl<'b, R: 'b + Read> ArchiveStreamReader<'b, R> {
+    pub fn from_config(mut src: R, mut config: ArchiveReaderConfig) -> Result<Self, Error> {
+        let header = ArchiveHeader::from(&mut src)?;
+        let tagged = has_block_tags(header.format_version);
+        let hash_algorithm = header.config.hash_algorithm;
+        config.load_persistent(header.config)?;
+        config
+            .compress
+            .set_block_kinds(has_block_kinds(header.format_version));
+        if config.layers_enabled.contains(Layers::SIGN) {
+            return Err(Error::WrongReaderState(
+                "[ArchiveStreamReader] Signed archives cannot be read sequentially".to_string(),
+            ));
+        }
+
+        // Same layers as `ArchiveFailSafeReader`, but the encrypted chunks are
+        // authenticated
+        let mut src: Box<dyn 'b + LayerFailSafeReader<'b, R>> =
+            Box::new(RawLayerFailSafeReader::new(src));
+        if config.layers_enabled.contains(Layers::FEC) {
+            src = Box::new(FecLayerFailSafeReader::new(src)?);
+        }
+        if config.layers_enabled.contains(Layers::ENCRYPT) {
+            src = Box::new(EncryptionLayerStreamReader::new(src, &config.encrypt)?);
+        }
+        if config.layers_enabled.contains(Layers::COMPRESS) {
+            src = Box::new(CompressionLayerFailSafeReader::new(src, &config.compress)?);
+        }
+
+        Ok(Self {
+            src,
+            tagged,
+            hash_algorithm,
+        })
+    }
+
+    pub fn new(src: R) -> Result<Self, Error> {
+        Self::from_config(src, ArchiveReaderConfig::new())
+    }
+
+    /// Read the whole archive, writing the content of each file to the
+    /// writer returned by `open_file` for its name, as soon as the file
+    /// starts. Files for which `open_file` returns `None` are skipped
+    ///
+    /// Files may be interleaved, so several writers can be opened at once.
+    /// Once a file ends and its hash is checked, its writer is flushed and
+    /// given to `close_file`, with the file metadata if any. Until then, the
+    /// content written is not checked: the writers of files never closed, on
+    /// error, are only dropped. Returns the names of the files closed, in the
+    /// order they end
+    pub fn extract<W, F, C>(
+        &mut self,
+        mut open_file: F,
+        mut close_file: C,
+    ) -> Result<Vec<String>, Error>
+    where
+        W: Write,
+        F: FnMut(&str) -> Result<Option<W>, Error>,
+        C: FnMut(&str, W, Option<&FileMetadata>) -> Result<(), Error>,
+    {
+        // Files being written, with their name and their ongoing hash
+        let mut id2file: HashMap<ArchiveFileID, (String, W, FileHasher)> = HashMap::new();
+        let mut id2metadata: HashMap<ArchiveFileID, FileMetadata> = HashMap::new();
+        // Files skipped, whose content is ignored
+        let mut ignored = HashSet::new();
+        // Files ended
+        let mut done = HashSet::new();
+        let mut filenames = HashSet::new();
+        let mut extracted = Vec::new();
+
+        loop {
+            match ArchiveFileBlock::from(&mut self.src, self.tagged)? {
+                ArchiveFileBlock::FileStart { filename, id } => {
+                    if id2file.contains_key(&id) || ignored.contains(&id) || done.contains(&id) {
+                        return Err(Error::WrongReaderState(
+                            "[ArchiveStreamReader] File ID reused".to_string(),
+                        ));
+                    }
+                    if !filenames.insert(filename.clone()) {
+                        return Err(Error::DuplicateFilename);
+                    }
+                    match open_file(&filename)? {
+                        Some(writer) => {
+                            id2file.insert(
+                                id,
+                                (filename, writer, FileHasher::new(self.hash_algorithm)),
+                            );
+                        }
+                        None => {
+                            ignored.insert(id);
+                        }
+                    }
+                }
+                ArchiveFileBlock::FileContent { length, id, .. } => match id2file.get_mut(&id) {
+                    Some((_, writer, hasher)) => {
+                        let mut buf = vec![0u8; std::cmp::min(length, CACHE_SIZE as u64) as usize];
+                        let mut remaining = length;
+                        while remaining > 0 {
+                            let count = std::cmp::min(remaining, buf.len() as u64) as usize;
+                            self.src.read_exact(&mut buf[..count])?;
+                            hasher.update(&buf[..count]);
+                            writer.write_all(&buf[..count])?;
+                            remaining -= count as u64;
+                        }
+                    }
+                    None if ignored.contains(&id) => {
+                        let skipped = io::copy(&mut (&mut self.src).take(length), &mut io::sink())?;
+                        if skipped != length {
+                            return Err(Error::IOError(io::ErrorKind::UnexpectedEof.into()));
+                        }
+                    }
+                    None => {
+                        return Err(Error::WrongReaderState(
+                            "[ArchiveStreamReader] Content for an unknown or ended file"
+                                .to_string(),
+                        ))
+                    }
+                },
+                ArchiveFileBlock::FileCopy { .. } => {
+                    return Err(Error::WrongReaderState(
+                        "[ArchiveStreamReader] Deduplicated content cannot be read sequentially"
+                            .to_string(),
+                    ));
+                }
+                ArchiveFileBlock::FileMetadata { id, metadata } => {
+                    if id2file.contains_key(&id) {
+                        id2metadata.insert(id, metadata);
+                    } else if !ignored.contains(&id) {
+                        return Err(Error::WrongReaderState(
+                            "[ArchiveStreamReader] Metadata for an unknown or ended file"
+                                .to_string(),
+                        ));
+                    }
+                }
+                ArchiveFileBlock::EndOfFile { id, hash } => {
+                    if ignored.remove(&id) {
+                        done.insert(id);
+                        continue;
+                    }
+                    let (filename, mut writer, hasher) = match id2file.remove(&id) {
+                        Some(file) => file,
+                        None => {
+                            return Err(Error::WrongReaderState(
+                                "[ArchiveStreamReader] End of an unknown or ended file".to_string(),
+                            ))
+                        }
+                    };
+                    if hasher.finalize() != hash {
+                        return Err(Error::WrongReaderState(format!(
+                            "[ArchiveStreamReader] Content of \"{}\" does not match its hash",
+                            filename
+                        )));
+                    }
+                    writer.flush()?;
+                    close_file(&filename, writer, id2metadata.remove(&id).as_ref())?;
+                    done.insert(id);
+                    extracted.push(filename);
+                }
+                ArchiveFileBlock::Padding { length }
+                | ArchiveFileBlock::Checkpoint { length, .. } => {
+                    // Only useful to a fail-safe or an indexed reader
+                    let skipped = io::copy(&mut (&mut self.src).take(length), &mut io::sink())?;
+                    if skipped != length {
+                        return Err(Error::IOError(io::ErrorKind::UnexpectedEof.into()));
+                    }
+                }
+                ArchiveFileBlock::EndOfArchiveData => {
+                    if !id2file.is_empty() || !ignored.is_empty() {
+                        return Err(Error::WrongReaderState(
+                            "[ArchiveStreamReader] Archive ends with unfinished files".to_string(),
+                        ));
+                    }
+                    return Ok(extracted);
+                }
+            }
+        }
     }
 }
 
 #[cfg(test)]
 pub(crate) mod tests {
     use super::*;
-    use curve25519_parser::{parse_openssl_25519_privkey, parse_openssl_25519_pubkey};
+    use crate::crypto::hybrid::{HybridKem, HybridPrivateKey, HybridPublicKey};
+    use crate::errors::ConfigError;
+    use crate::helpers::StreamWriter;
+    use crate::layers::compress::CompressionAlgorithm;
+    use crate::layers::encrypt::{key_hint, Cipher, CHUNK_SIZE};
+    use curve25519_parser::{
+        parse_openssl_25519_privkey, parse_openssl_25519_pubkey, parse_openssl_ed25519_privkey,
+        parse_openssl_ed25519_pubkey,
+    };
     use hex;
     use rand::distributions::{Distribution, Standard};
     use rand::{RngCore, SeedableRng};
     use rand_chacha::ChaChaRng;
-    use std::io::{Cursor, Empty, Read};
+    use sha2::{Digest, Sha256};
+    use std::convert::TryInto;
+    use std::io::{Cursor, Empty, Read, Seek, SeekFrom};
     use x25519_dalek::{PublicKey, StaticSecret};
 
     #[test]
@@ -1242,6 +4635,9 @@ pub(crate) mod tests {
             config: ArchivePersistentConfig {
                 layers_enabled: Layers::default(),
                 encrypt: None,
+                compress: None,
+                hash_algorithm: HashAlgorithm::default(),
+                comment: Some("case 42".to_string()),
             },
         };
         let mut buf = Vec::new();
@@ -1250,20 +4646,32 @@ pub(crate) mod tests {
 
         let header_rebuild = ArchiveHeader::from(&mut buf.as_slice()).unwrap();
         assert_eq!(header_rebuild.config.layers_enabled, Layers::default());
+        assert_eq!(header_rebuild.config.comment.as_deref(), Some("case 42"));
+
+        // Older format versions lack the comment
+        let header = ArchiveHeader {
+            format_version: COMMENTLESS_FORMAT_VERSION,
+            ..header
+        };
+        let mut buf = Vec::new();
+        header.dump(&mut buf).unwrap();
+        let header_rebuild = ArchiveHeader::from(&mut buf.as_slice()).unwrap();
+        assert_eq!(header_rebuild.format_version, COMMENTLESS_FORMAT_VERSION);
+        assert_eq!(header_rebuild.config.comment, None);
     }
 
     #[test]
     fn dump_block() {
         let mut buf = Vec::new();
         let id = 0;
-        let hash = Sha256Hash::default();
+        let hash = vec![0u8; 32];
 
         // std::io::Empty is used because a type with Read is needed
         ArchiveFileBlock::FileStart::<Empty> {
             id,
             filename: String::from("foobaré.exe"),
         }
-        .dump(&mut buf)
+        .dump(&mut buf, true)
         .unwrap();
 
         let fake_content = vec![1, 2, 3, 4];
@@ -1272,52 +4680,88 @@ pub(crate) mod tests {
             length: fake_content.len() as u64,
             data: Some(fake_content.as_slice()),
         };
-        block.dump(&mut buf).unwrap();
+        block.dump(&mut buf, true).unwrap();
 
         // std::io::Empty is used because a type with Read is needed
         ArchiveFileBlock::EndOfFile::<Empty> { id, hash }
-            .dump(&mut buf)
+            .dump(&mut buf, true)
             .unwrap();
 
         println!("{:?}", buf);
     }
 
+    #[test]
+    fn block_tags() {
+        let mut buf = Vec::new();
+        ArchiveFileBlock::FileStart::<Empty> {
+            id: 1,
+            filename: String::from("foobar"),
+        }
+        .dump(&mut buf, true)
+        .unwrap();
+        // Type, id, filename length, filename, then the tag
+        assert_eq!(buf.len(), 1 + 8 + 8 + 6 + BLOCK_TAG_SIZE as usize);
+
+        match ArchiveFileBlock::<&[u8]>::from(&mut buf.as_slice(), true).unwrap() {
+            ArchiveFileBlock::FileStart { id, filename } => {
+                assert_eq!(id, 1);
+                assert_eq!(filename, "foobar");
+            }
+            _ => panic!("Expected a FileStart"),
+        }
+
+        // A damaged header is detected, even if it still makes sense
+        buf[1] ^= 1;
+        match ArchiveFileBlock::<&[u8]>::from(&mut buf.as_slice(), true) {
+            Err(Error::WrongBlockTag) => {}
+            _ => panic!("Expected a WrongBlockTag error"),
+        }
+    }
+
     #[test]
     fn blocks_to_file() {
         // Create several blocks
         let mut buf = Vec::new();
         let id = 0;
-        let hash = Sha256Hash::default();
+        let hash = vec![0u8; 32];
 
         let mut block = ArchiveFileBlock::FileStart::<&[u8]> {
             id,
             filename: String::from("foobar"),
         };
-        block.dump(&mut buf).unwrap();
+        block.dump(&mut buf, true).unwrap();
         let fake_content = vec![1, 2, 3, 4];
         let mut block = ArchiveFileBlock::FileContent {
             id,
             length: fake_content.len() as u64,
             data: Some(fake_content.as_slice()),
         };
-        block.dump(&mut buf).unwrap();
+        block.dump(&mut buf, true).unwrap();
         let fake_content2 = vec![5, 6, 7, 8];
         let mut block = ArchiveFileBlock::FileContent {
             id,
             length: fake_content2.len() as u64,
             data: Some(fake_content2.as_slice()),
         };
-        block.dump(&mut buf).unwrap();
+        block.dump(&mut buf, true).unwrap();
 
         // std::io::Empty is used because a type with Read is needed
         ArchiveFileBlock::EndOfFile::<Empty> { id, hash }
-            .dump(&mut buf)
+            .dump(&mut buf, true)
             .unwrap();
 
         let mut data_source = std::io::Cursor::new(buf);
         let offsets = [0];
-        let mut reader =
-            BlocksToFileReader::new(&mut data_source, &offsets).expect("BlockToFileReader failed");
+        let size = (fake_content.len() + fake_content2.len()) as u64;
+        let files_info = HashMap::new();
+        let mut reader = BlocksToFileReader::new(
+            &mut data_source,
+            Cow::Borrowed(&offsets),
+            size,
+            true,
+            FooterRef::Loaded(&files_info),
+        )
+        .expect("BlockToFileReader failed");
         let mut output = Vec::new();
         reader.read_to_end(&mut output).unwrap();
         assert_eq!(output.len(), fake_content.len() + fake_content2.len());
@@ -1733,301 +5177,3749 @@ pub(crate) mod tests {
     }
 
     #[test]
-    fn check_file_size() {
-        // Build an archive with 3 non-interleaved files and another with
-        // interleaved files
-        for interleaved in &[false, true] {
-            let (mla, key, files) = build_archive(None, *interleaved);
+    fn decompression_limits() {
+        // Very repetitive content, spanning several compressed blocks
+        let mut config = ArchiveWriterConfig::new();
+        config.set_layers(Layers::COMPRESS);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        let small = vec![0u8; 1000];
+        let big = vec![0u8; 3 * UNCOMPRESSED_DATA_SIZE as usize];
+        mla.add_file("small", small.len() as u64, small.as_slice())
+            .unwrap();
+        mla.add_file("big", big.len() as u64, big.as_slice())
+            .unwrap();
+        mla.finalize().unwrap();
+        let dest = mla.into_raw();
 
-            for (fname, data) in &files {
-                let id = mla.files_info.get(fname).unwrap();
-                let size = mla.ids_info.get(id).unwrap().size;
-                assert_eq!(size, data.len() as u64);
-            }
+        let read_file = |config: ArchiveReaderConfig, fname: &str| -> io::Result<Vec<u8>> {
+            let mut mla_read =
+                ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).unwrap();
+            let mut file = mla_read.get_file(fname.to_string()).unwrap().unwrap();
+            let mut content = Vec::new();
+            file.data.read_to_end(&mut content)?;
+            Ok(content)
+        };
 
-            let dest = mla.into_raw();
-            let buf = Cursor::new(dest.as_slice());
-            let mut config = ArchiveReaderConfig::new();
-            config.add_private_keys(std::slice::from_ref(&key));
-            let mut mla_read = ArchiveReader::from_config(buf, config).unwrap();
+        // Without limits
+        assert_eq!(read_file(ArchiveReaderConfig::new(), "big").unwrap(), big);
 
-            for (fname, data) in &files {
-                let mla_file = mla_read.get_file(fname.to_string()).unwrap().unwrap();
-                assert_eq!(mla_file.size, data.len() as u64);
-            }
-        }
+        // Per entry limit
+        let mut config = ArchiveReaderConfig::new();
+        config.set_max_entry_size(UNCOMPRESSED_DATA_SIZE as u64);
+        assert_eq!(read_file(config, "small").unwrap(), small);
+        let mut config = ArchiveReaderConfig::new();
+        config.set_max_entry_size(UNCOMPRESSED_DATA_SIZE as u64);
+        assert!(read_file(config, "big").is_err());
+
+        // Expansion ratio
+        let mut config = ArchiveReaderConfig::new();
+        config.set_max_expansion_ratio(10);
+        assert_eq!(read_file(config, "small").unwrap(), small);
+        let mut config = ArchiveReaderConfig::new();
+        config.set_max_expansion_ratio(10);
+        assert!(read_file(config, "big").is_err());
+
+        // Linear extraction
+        let mut config = ArchiveReaderConfig::new();
+        config.set_max_entry_size(UNCOMPRESSED_DATA_SIZE as u64);
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).unwrap();
+        let fname = "big".to_string();
+        let mut export: HashMap<&String, Vec<u8>> = HashMap::new();
+        export.insert(&fname, Vec::new());
+        assert!(matches!(
+            helpers::linear_extract(&mut mla_read, &mut export),
+            Err(Error::DecompressionLimitExceeded)
+        ));
     }
 
     #[test]
-    fn failsafe_detect_integrity() {
-        // Build an archive with 3 files
-        let (mla, _key, files) = build_archive(Some(Layers::DEBUG), false);
-
-        // Swap the first 2 bytes of file1
+    fn structural_limits() {
+        let (mla, _key, files) = build_archive(Some(Layers::EMPTY), false);
         let mut dest = mla.into_raw();
-        let expect = files[0].1.as_slice();
-        let pos: Vec<usize> = dest
-            .iter()
-            .enumerate()
-            .filter_map(|e| {
-                if e.0 + expect.len() < dest.len() && &dest[e.0..e.0 + expect.len()] == expect {
-                    Some(e.0)
-                } else {
-                    None
-                }
-            })
-            .collect();
-        dest.swap(pos[0], pos[0] + 1);
-
-        // Prepare the failsafe reader
-        let mut mla_fsread =
-            ArchiveFailSafeReader::from_config(dest.as_slice(), ArchiveReaderConfig::new())
-                .unwrap();
+        let open = |dest: &[u8], config: ArchiveReaderConfig| {
+            ArchiveReader::from_config(Cursor::new(dest), config).map(|_| ())
+        };
 
-        // Prepare the writer
-        let dest_w = Vec::new();
-        let mut mla_w = ArchiveWriter::from_config(dest_w, ArchiveWriterConfig::new())
-            .expect("Writer init failed");
+        // Within the limits
+        let mut config = ArchiveReaderConfig::new();
+        config
+            .set_max_entries(files.len() as u64)
+            .set_max_filename_size(8)
+            .set_max_footer_size(1024)
+            .set_strict(true);
+        open(&dest, config).unwrap();
 
-        // Conversion
-        match mla_fsread.convert_to_archive(&mut mla_w).unwrap() {
-            FailSafeReadError::UnfinishedFiles {
-                filenames,
-                stopping_error,
-            } => {
-                // We expect to ends with a HashDiffers on first file
-                assert_eq!(filenames, vec![files[0].0.to_string()]);
-                match *stopping_error {
-                    FailSafeReadError::HashDiffers { .. } => {}
-                    _ => {
-                        panic!("Unexpected stopping_error: {}", stopping_error);
-                    }
-                }
-            }
-            status => {
-                panic!("Unexpected status: {}", status);
-            }
-        };
+        let mut config = ArchiveReaderConfig::new();
+        config.set_max_entries(files.len() as u64 - 1);
+        assert!(matches!(open(&dest, config), Err(Error::TooManyEntries)));
+        let mut config = ArchiveReaderConfig::new();
+        config.set_max_filename_size(7);
+        assert!(matches!(open(&dest, config), Err(Error::FilenameTooLong)));
+        let mut config = ArchiveReaderConfig::new();
+        config.set_max_footer_size(16);
+        assert!(matches!(open(&dest, config), Err(Error::FooterTooLarge)));
+
+        // Forge the number of entries of the footer, at its start
+        let len = dest.len();
+        let footer_len = u32::from_le_bytes(dest[len - 4..].try_into().unwrap()) as usize;
+        let start = len - 4 - footer_len;
+        dest[start..start + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        let mut config = ArchiveReaderConfig::new();
+        config.set_strict(true);
+        assert!(matches!(
+            open(&dest, config),
+            Err(Error::DeserializationError)
+        ));
     }
 
     #[test]
-    fn get_hash() {
-        // Build an archive with 3 files
-        let (mla, key, files) = build_archive(None, false);
-
-        // Prepare the reader
+    fn memory_budget() {
+        // Many files, one of them copying another one content
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let mut base = vec![0u8; 64 * 1024];
+        rng.fill_bytes(&mut base);
+        let mut config = ArchiveWriterConfig::new();
+        config.set_layers(Layers::COMPRESS);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        let mut files = Vec::new();
+        for i in 0..100 {
+            let content = format!("content of file {}", i).into_bytes();
+            let fname = format!("file{}", i);
+            mla.add_file(&fname, content.len() as u64, content.as_slice())
+                .unwrap();
+            files.push((fname, content));
+        }
+        mla.add_file("base", base.len() as u64, base.as_slice())
+            .unwrap();
+        mla.add_file_delta(
+            "copy",
+            base.len() as u64,
+            base.as_slice(),
+            "base",
+            base.as_slice(),
+        )
+        .unwrap();
+        files.push(("base".to_string(), base.clone()));
+        files.push(("copy".to_string(), base));
+        mla.finalize().unwrap();
         let dest = mla.into_raw();
-        let buf = Cursor::new(dest.as_slice());
-        let mut config = ArchiveReaderConfig::new();
-        config.add_private_keys(std::slice::from_ref(&key));
-        let mut mla_read = ArchiveReader::from_config(buf, config).unwrap();
 
-        // Get hashes and compare
-        for (filename, content) in files {
-            let hash = mla_read.get_hash(&filename).unwrap().unwrap();
+        // With the index of the entries (32 bytes per entry), or without it
+        for budget in &[102 * 32, 1024] {
+            let mut config = ArchiveReaderConfig::new();
+            config.set_memory_budget(*budget);
+            let mut mla_read =
+                ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).unwrap();
+            assert!(mla_read.metadata.is_none());
+            assert_eq!(
+                mla_read.lazy_footer.as_ref().unwrap().index.is_some(),
+                *budget == 102 * 32
+            );
+            assert!(matches!(mla_read.list_files(), Err(Error::MissingMetadata)));
+
+            for (fname, content) in &files {
+                let mut file = mla_read.get_file(fname.clone()).unwrap().unwrap();
+                let mut rez = Vec::new();
+                file.data.read_to_end(&mut rez).unwrap();
+                assert_eq!(&rez, content);
+                let hash = mla_read.get_hash(fname).unwrap().unwrap();
+                assert_eq!(hash, Sha256::digest(content).to_vec());
+            }
+            assert!(mla_read.get_file("unknown".to_string()).unwrap().is_none());
 
-            let mut hasher = Sha256::new();
-            hasher.update(content);
-            let result = hasher.finalize();
-            assert_eq!(result.as_slice(), hash);
+            let mut names = Vec::new();
+            mla_read
+                .for_each_entry(|entry| {
+                    names.push(entry.name);
+                    Ok(())
+                })
+                .unwrap();
+            names.sort();
+            let mut expected: Vec<String> = files.iter().map(|(fname, _)| fname.clone()).collect();
+            expected.sort();
+            assert_eq!(names, expected);
         }
+
+        // The footer is loaded if it fits in the budget
+        let mut config = ArchiveReaderConfig::new();
+        config.set_memory_budget(1024 * 1024);
+        let mla_read = ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).unwrap();
+        assert!(mla_read.metadata.is_some());
     }
 
-    fn make_format_regression_files() -> HashMap<String, Vec<u8>> {
-        // Build files easily scriptables and checkable
-        let mut files: HashMap<String, Vec<u8>> = HashMap::new();
+    #[test]
+    fn vectored_writes() {
+        // Many small slices, going through the layers at once
+        let slices: Vec<Vec<u8>> = (0..1000u32)
+            .map(|i| i.to_le_bytes().repeat((i % 7) as usize))
+            .collect();
+        let content = slices.concat();
 
-        // One simple file
-        let mut simple: Vec<u8> = Vec::new();
-        for i in 0..=255 {
-            simple.push(i);
-        }
-        let pattern = simple.clone();
-        files.insert("simple".to_string(), simple);
+        // Use a deterministic RNG in tests, for reproductability. DO NOT DO THIS IS IN ANY RELEASED BINARY!
+        let mut rng = ChaChaRng::seed_from_u64(0);
 
-        // One big file (10 MB)
-        let big: Vec<u8> = pattern
-            .iter()
-            .cycle()
-            .take(10 * 1024 * 1024)
-            .cloned()
-            .collect();
-        files.insert("big".to_string(), big);
+        for layering in &[
+            Layers::DEBUG,
+            Layers::ENCRYPT,
+            Layers::COMPRESS,
+            Layers::default(),
+            Layers::default() | Layers::FEC,
+        ] {
+            println!("Layering: {:?}", layering);
 
-        // Some constant files
-        for i in 0..=255 {
-            files.insert(
-                format!("file_{}", i).to_string(),
-                std::iter::repeat(i).take(0x1000).collect::<Vec<u8>>(),
-            );
-        }
+            let key = StaticSecret::new(&mut rng);
+            let mut config = ArchiveWriterConfig::new();
+            config
+                .set_layers(*layering)
+                .add_public_keys(std::slice::from_ref(&PublicKey::from(&key)));
+            let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+            let id = mla.start_file("my_file").unwrap();
+            let io_slices: Vec<IoSlice> = slices.iter().map(|slice| IoSlice::new(slice)).collect();
+            let written = StreamWriter::new(&mut mla, id)
+                .write_vectored(&io_slices)
+                .unwrap();
+            assert_eq!(written, content.len());
+            mla.end_file(id).unwrap();
+            mla.finalize().unwrap();
 
-        // sha256 sum of them
-        let mut sha256sum: Vec<u8> = Vec::new();
-        let mut info: Vec<(&String, &Vec<_>)> = files.iter().collect();
-        info.sort_by(|i1, i2| Ord::cmp(&i1.0, &i2.0));
-        for (fname, content) in info.iter() {
-            let mut hasher = Sha256::new();
-            hasher.update(content);
-            sha256sum.extend_from_slice(hex::encode(hasher.finalize()).as_bytes());
-            sha256sum.push(0x20);
-            sha256sum.push(0x20);
-            sha256sum.extend(fname.as_bytes());
-            sha256sum.push(0x0a);
+            let dest = mla.into_raw();
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(std::slice::from_ref(&key));
+            let mut mla_read =
+                ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).unwrap();
+            let mut file = mla_read.get_file("my_file".to_string()).unwrap().unwrap();
+            let mut rez = Vec::new();
+            file.data.read_to_end(&mut rez).unwrap();
+            assert_eq!(rez, content);
         }
-        files.insert("sha256sum".to_string(), sha256sum);
-        files
     }
 
     #[test]
-    fn create_archive_format_version() {
-        // Build an archive to be committed, for format regression
-        let file = Vec::new();
-
-        // Use committed keys
-        let pem_pub: &'static [u8] = include_bytes!("../../samples/test_x25519_archive_v1_pub.pem");
-        let pub_key = parse_openssl_25519_pubkey(pem_pub).unwrap();
-
-        let mut config = ArchiveWriterConfig::new();
-        config
-            .set_layers(Layers::default())
-            .add_public_keys(&[pub_key]);
-        let mut mla = ArchiveWriter::from_config(file, config).expect("Writer init failed");
-
-        let files = make_format_regression_files();
-        // First, add a simple file
-        let fname_simple = "simple".to_string();
-        mla.add_file(
-            &fname_simple,
-            files.get(&fname_simple).unwrap().len() as u64,
-            files.get(&fname_simple).unwrap().as_slice(),
-        )
-        .unwrap();
+    fn append_to_archive() {
+        // Use a deterministic RNG in tests, for reproductability. DO NOT DO THIS IS IN ANY RELEASED BINARY!
+        let mut rng = ChaChaRng::seed_from_u64(0);
 
-        // Second, add interleaved files
-        let fnames: Vec<String> = (0..=255).map(|i| format!("file_{}", i)).collect();
-        let mut name2id: HashMap<_, _> = HashMap::new();
+        for layering in &[
+            Layers::DEBUG,
+            Layers::ENCRYPT,
+            Layers::COMPRESS,
+            Layers::default(),
+        ] {
+            println!("Layering: {:?}", layering);
 
-        // Start files in normal order
-        (0..=255)
-            .map(|i| {
-                let id = mla.start_file(&fnames[i]).unwrap();
-                name2id.insert(&fnames[i], id);
-            })
-            .for_each(drop);
+            // Build an initial archive, with a file spanning on several
+            // compressed blocks and encrypted chunks
+            let key = StaticSecret::new(&mut rng);
+            let mut config = ArchiveWriterConfig::new();
+            config
+                .set_layers(*layering)
+                .add_public_keys(&[PublicKey::from(&key)]);
+            let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+            let big_file: Vec<u8> = (0..(UNCOMPRESSED_DATA_SIZE as usize + 200 * 1024))
+                .map(|i| ((i * 7) % 251) as u8)
+                .collect();
+            mla.add_file("big_file", big_file.len() as u64, big_file.as_slice())
+                .unwrap();
+            mla.add_file("small_file", 3, vec![1, 2, 3].as_slice())
+                .unwrap();
+            mla.finalize().unwrap();
+            let dest = mla.into_raw();
 
-        // Add some parts in reverse order
-        (0..=255)
-            .rev()
-            .map(|i| {
-                let id = name2id.get(&fnames[i]).unwrap();
-                mla.append_file_content(id.clone(), 32, &files.get(&fnames[i]).unwrap()[..32])
-                    .unwrap();
-            })
-            .for_each(drop);
+            // Reopen it and add interleaved files
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(std::slice::from_ref(&key));
+            let mut mla = ArchiveWriter::from_archive(
+                Vec::new(),
+                ArchiveWriterConfig::new(),
+                Cursor::new(dest.as_slice()),
+                config,
+            )
+            .unwrap();
+            assert!(mla.start_file("small_file").is_err());
+            let id = mla.start_file("new_file1").unwrap();
+            mla.append_file_content(id, 2, vec![4, 5].as_slice())
+                .unwrap();
+            mla.add_file("new_file2", 2, vec![6, 7].as_slice()).unwrap();
+            mla.append_file_content(id, 2, vec![8, 9].as_slice())
+                .unwrap();
+            mla.end_file(id).unwrap();
+            mla.finalize().unwrap();
+            let dest = mla.into_raw();
 
-        // Add the rest of files in normal order
-        (0..=255)
-            .map(|i| {
-                let id = name2id.get(&fnames[i]).unwrap();
-                let data = &files.get(&fnames[i]).unwrap()[32..];
-                mla.append_file_content(id.clone(), data.len() as u64, data)
-                    .unwrap();
-            })
-            .for_each(drop);
+            // Check the whole content
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(std::slice::from_ref(&key));
+            let mut mla_read =
+                ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).unwrap();
+            let mut sorted_list: Vec<String> = mla_read.list_files().unwrap().cloned().collect();
+            sorted_list.sort();
+            assert_eq!(
+                sorted_list,
+                vec!["big_file", "new_file1", "new_file2", "small_file"]
+            );
+            for (fname, content) in &[
+                ("big_file", big_file.clone()),
+                ("small_file", vec![1, 2, 3]),
+                ("new_file1", vec![4, 5, 8, 9]),
+                ("new_file2", vec![6, 7]),
+            ] {
+                let mut file = mla_read.get_file(fname.to_string()).unwrap().unwrap();
+                let mut rez = Vec::new();
+                file.data.read_to_end(&mut rez).unwrap();
+                assert_eq!(&rez, content);
+                drop(file);
+                assert_eq!(
+                    mla_read.get_hash(fname).unwrap().unwrap()[..],
+                    Sha256::digest(content)[..]
+                );
+            }
 
-        // Finish files in reverse order
-        (0..=255)
-            .rev()
-            .map(|i| {
-                let id = name2id.get(&fnames[i]).unwrap();
-                mla.end_file(id.clone()).unwrap();
-            })
-            .for_each(drop);
+            // ArchiveFileIDs must stay unique, for linear reading
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(std::slice::from_ref(&key));
+            let mut mla_fsread =
+                ArchiveFailSafeReader::from_config(dest.as_slice(), config).unwrap();
+            let mut config = ArchiveWriterConfig::new();
+            config.set_layers(Layers::EMPTY);
+            let mut mla_w = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+            match mla_fsread.convert_to_archive(&mut mla_w).unwrap() {
+                FailSafeReadError::EndOfOriginalArchiveData => {}
+                status => {
+                    panic!("Unexpected status: {}", status);
+                }
+            };
+            let dest2 = mla_w.into_raw();
+            let mut mla_read = ArchiveReader::from_config(
+                Cursor::new(dest2.as_slice()),
+                ArchiveReaderConfig::new(),
+            )
+            .unwrap();
+            let mut file = mla_read.get_file("new_file1".to_string()).unwrap().unwrap();
+            let mut rez = Vec::new();
+            file.data.read_to_end(&mut rez).unwrap();
+            assert_eq!(rez, vec![4, 5, 8, 9]);
+        }
+    }
 
-        // Add a big file
-        let fname_big = "big".to_string();
-        mla.add_file(
-            &fname_big,
-            files.get(&fname_big).unwrap().len() as u64,
-            files.get(&fname_big).unwrap().as_slice(),
-        )
-        .unwrap();
+    #[test]
+    fn new_archive_from_archive_config() {
+        let (mla, key, _files) = build_archive(None, false);
+        let dest = mla.into_raw();
 
-        // Add sha256sum file
-        let fname_sha256sum = "sha256sum".to_string();
-        mla.add_file(
-            &fname_sha256sum,
-            files.get(&fname_sha256sum).unwrap().len() as u64,
-            files.get(&fname_sha256sum).unwrap().as_slice(),
+        // Build a new archive with the same recipients
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla = ArchiveWriter::from_archive_config(
+            Vec::new(),
+            ArchiveWriterConfig::new(),
+            Cursor::new(dest.as_slice()),
+            config,
         )
         .unwrap();
+        mla.add_file("new_file", 2, vec![1, 2].as_slice()).unwrap();
         mla.finalize().unwrap();
+        let dest = mla.into_raw();
 
-        // UNCOMMENT THESE LINES TO UPDATE THE FILE
-        // UPDATE THE VERSION NUMBER
-        /*
-        std::fs::File::create(std::path::Path::new(&format!(
-            "../samples/archive_v{}.mla",
-            MLA_FORMAT_VERSION
-        )))
-        .unwrap()
-        .write(&mla.into_raw())
-        .unwrap();
-         */
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).unwrap();
+        assert_eq!(mla_read.config.layers_enabled, Layers::default());
+        let files: Vec<String> = mla_read.list_files().unwrap().cloned().collect();
+        assert_eq!(files, vec!["new_file".to_string()]);
+        let mut rez = Vec::new();
+        mla_read
+            .get_file("new_file".to_string())
+            .unwrap()
+            .unwrap()
+            .data
+            .read_to_end(&mut rez)
+            .unwrap();
+        assert_eq!(rez, vec![1, 2]);
     }
 
     #[test]
-    fn check_archive_format_v1() {
-        let pem_priv: &'static [u8] = include_bytes!("../../samples/test_x25519_archive_v1.pem");
+    fn threshold_recipients() {
+        // Use a deterministic RNG in tests, for reproductability. DO NOT DO THIS IS IN ANY RELEASED BINARY!
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let keys: Vec<StaticSecret> = (0..3).map(|_| StaticSecret::new(&mut rng)).collect();
+        let public_keys: Vec<PublicKey> = keys.iter().map(PublicKey::from).collect();
 
-        let mla_data: &'static [u8] = include_bytes!("../../samples/archive_v1.mla");
-        let files = make_format_regression_files();
+        // Invalid thresholds
+        for threshold in [0, 4].iter() {
+            let mut config = ArchiveWriterConfig::new();
+            config
+                .set_layers(Layers::default())
+                .add_public_keys(&public_keys)
+                .set_threshold(*threshold);
+            assert!(ArchiveWriter::from_config(Vec::new(), config).is_err());
+        }
+        // A password would bypass the threshold
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .set_layers(Layers::default())
+            .add_public_keys(&public_keys)
+            .set_threshold(2)
+            .set_password(b"password");
+        assert!(ArchiveWriter::from_config(Vec::new(), config).is_err());
 
-        // Build Reader
-        let buf = Cursor::new(mla_data);
-        let mut config = ArchiveReaderConfig::new();
-        config.add_private_keys(&[parse_openssl_25519_privkey(pem_priv).unwrap()]);
-        let mut mla_read = ArchiveReader::from_config(buf, config).unwrap();
+        // 2-of-3 recipients
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .set_layers(Layers::default())
+            .add_public_keys(&public_keys)
+            .set_threshold(2);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        mla.add_file("my_file", 2, vec![1, 2].as_slice()).unwrap();
+        mla.finalize().unwrap();
+        let dest = mla.into_raw();
 
-        // Build FailSafeReader
-        let mut config = ArchiveReaderConfig::new();
-        config.add_private_keys(&[parse_openssl_25519_privkey(pem_priv).unwrap()]);
-        let mut mla_fsread = ArchiveFailSafeReader::from_config(mla_data, config).unwrap();
+        // One key, even given twice, is not enough
+        for private_keys in [&keys[..1], &[keys[1].clone(), keys[1].clone()][..]].iter() {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(private_keys);
+            assert!(ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).is_err());
+        }
 
-        // Repair the archive (without any damage, but trigger the corresponding code)
-        let dest_w = Vec::new();
-        let mut mla_w = ArchiveWriter::from_config(dest_w, ArchiveWriterConfig::new())
-            .expect("Writer init failed");
-        if let FailSafeReadError::EndOfOriginalArchiveData =
-            mla_fsread.convert_to_archive(&mut mla_w).unwrap()
+        // Any two keys are
+        for private_keys in [
+            &keys[..2],
+            &keys[1..],
+            &[keys[2].clone(), keys[0].clone()][..],
+        ]
+        .iter()
         {
-            // Everything runs as expected
-        } else {
-            panic!();
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(private_keys);
+            let mut mla_read =
+                ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).unwrap();
+            let mut rez = Vec::new();
+            mla_read
+                .get_file("my_file".to_string())
+                .unwrap()
+                .unwrap()
+                .data
+                .read_to_end(&mut rez)
+                .unwrap();
+            assert_eq!(rez, vec![1, 2]);
         }
-        // Get a reader on the repaired archive
-        let buf2 = Cursor::new(mla_w.into_raw());
-        let mut mla_repread = ArchiveReader::from_config(buf2, ArchiveReaderConfig::new()).unwrap();
 
-        assert_eq!(files.len(), mla_read.list_files().unwrap().count());
-        assert_eq!(files.len(), mla_repread.list_files().unwrap().count());
+        // A new archive with the same recipients keeps the threshold
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(&keys[..2]);
+        let mut mla = ArchiveWriter::from_archive_config(
+            Vec::new(),
+            ArchiveWriterConfig::new(),
+            Cursor::new(dest.as_slice()),
+            config,
+        )
+        .unwrap();
+        mla.finalize().unwrap();
+        let dest = mla.into_raw();
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(&keys[..1]);
+        assert!(ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).is_err());
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(&keys[1..]);
+        assert!(ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).is_ok());
+    }
 
-        // Get and check file per file
-        for (fname, content) in files.iter() {
-            let mut mla_file = mla_read.get_file(fname.clone()).unwrap().unwrap();
-            let mut mla_rep_file = mla_repread.get_file(fname.clone()).unwrap().unwrap();
-            assert_eq!(mla_file.filename, fname.clone());
-            assert_eq!(mla_rep_file.filename, fname.clone());
-            let mut buf = Vec::new();
-            mla_file.data.read_to_end(&mut buf).unwrap();
-            assert_eq!(buf.as_slice(), content.as_slice());
-            let mut buf = Vec::new();
-            mla_rep_file.data.read_to_end(&mut buf).unwrap();
-            assert_eq!(buf.as_slice(), content.as_slice());
+    #[test]
+    fn escrow_recipient() {
+        // Use a deterministic RNG in tests, for reproductability. DO NOT DO THIS IS IN ANY RELEASED BINARY!
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let keys: Vec<StaticSecret> = (0..2).map(|_| StaticSecret::new(&mut rng)).collect();
+        let public_keys: Vec<PublicKey> = keys.iter().map(PublicKey::from).collect();
+        let escrow = StaticSecret::new(&mut rng);
+
+        // An escrow key alone is not enough
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .set_layers(Layers::default())
+            .add_escrow_keys(&[PublicKey::from(&escrow)]);
+        assert!(ArchiveWriter::from_config(Vec::new(), config).is_err());
+
+        // 2-of-2 recipients, and an escrow key
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .set_layers(Layers::default())
+            .add_public_keys(&public_keys)
+            .set_threshold(2)
+            .add_escrow_keys(&[PublicKey::from(&escrow)]);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        mla.add_file("my_file", 2, vec![1, 2].as_slice()).unwrap();
+        mla.finalize().unwrap();
+        let dest = mla.into_raw();
+
+        // The escrow key is recorded in the header
+        let header = ArchiveHeader::from(&mut Cursor::new(dest.as_slice())).unwrap();
+        let escrow_keys = header.config.encrypt.unwrap().escrow.unwrap().public_keys;
+        assert_eq!(escrow_keys, vec![*PublicKey::from(&escrow).as_bytes()]);
+
+        // The escrow key bypasses the threshold
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(&keys[..1]);
+        assert!(ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).is_err());
+        for private_keys in [&[escrow.clone()][..], &keys[..]].iter() {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(private_keys);
+            let mut mla_read =
+                ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).unwrap();
+            let mut rez = Vec::new();
+            mla_read
+                .get_file("my_file".to_string())
+                .unwrap()
+                .unwrap()
+                .data
+                .read_to_end(&mut rez)
+                .unwrap();
+            assert_eq!(rez, vec![1, 2]);
+        }
+    }
+
+    #[test]
+    fn anonymous_recipients() {
+        // Use a deterministic RNG in tests, for reproductability. DO NOT DO THIS IS IN ANY RELEASED BINARY!
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let keys: Vec<StaticSecret> = (0..5).map(|_| StaticSecret::new(&mut rng)).collect();
+        let public_keys: Vec<PublicKey> = keys.iter().map(PublicKey::from).collect();
+        let escrow = StaticSecret::new(&mut rng);
+
+        // 3-of-5 recipients, and an escrow key
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .set_layers(Layers::default())
+            .add_public_keys(&public_keys)
+            .set_threshold(3)
+            .add_escrow_keys(&[PublicKey::from(&escrow)])
+            .set_anonymous_recipients(true);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        mla.add_file("my_file", 2, vec![1, 2].as_slice()).unwrap();
+        mla.finalize().unwrap();
+        let dest = mla.into_raw();
+
+        // The escrow key is not recorded in the header
+        let header = ArchiveHeader::from(&mut Cursor::new(dest.as_slice())).unwrap();
+        let escrow_keys = header.config.encrypt.unwrap().escrow.unwrap().public_keys;
+        assert!(escrow_keys.is_empty());
+
+        // Shuffled key slots still give the matching shares
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(&keys[..2]);
+        assert!(ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).is_err());
+        for private_keys in [&[escrow.clone()][..], &keys[..3], &keys[2..]].iter() {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(private_keys);
+            let mut mla_read =
+                ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).unwrap();
+            let mut rez = Vec::new();
+            mla_read
+                .get_file("my_file".to_string())
+                .unwrap()
+                .unwrap()
+                .data
+                .read_to_end(&mut rez)
+                .unwrap();
+            assert_eq!(rez, vec![1, 2]);
+        }
+    }
+
+    #[test]
+    fn recipient_hints() {
+        // Use a deterministic RNG in tests, for reproductability. DO NOT DO THIS IS IN ANY RELEASED BINARY!
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let keys: Vec<StaticSecret> = (0..3).map(|_| StaticSecret::new(&mut rng)).collect();
+        let public_keys: Vec<PublicKey> = keys.iter().map(PublicKey::from).collect();
+        let escrow = StaticSecret::new(&mut rng);
+        let others: Vec<StaticSecret> = (0..10).map(|_| StaticSecret::new(&mut rng)).collect();
+
+        // Hints can't be stored for anonymous recipients
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .set_layers(Layers::default())
+            .add_public_keys(&public_keys)
+            .set_recipient_hints(true)
+            .set_anonymous_recipients(true);
+        assert!(ArchiveWriter::from_config(Vec::new(), config).is_err());
+
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .set_layers(Layers::default())
+            .add_public_keys(&public_keys[..2])
+            .add_escrow_keys(&[PublicKey::from(&escrow)])
+            .set_recipient_hints(true);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        mla.add_file("my_file", 2, vec![1, 2].as_slice()).unwrap();
+        mla.finalize().unwrap();
+        let dest = mla.into_raw();
+
+        // One hint per recipient, escrow included
+        let header = ArchiveHeader::from(&mut Cursor::new(dest.as_slice())).unwrap();
+        let key_hints = header.config.encrypt.unwrap().key_hints.unwrap();
+        assert_eq!(key_hints.len(), 3);
+        assert!(key_hints.contains(&key_hint(public_keys[1].as_bytes())));
+        assert!(!key_hints.contains(&key_hint(public_keys[2].as_bytes())));
+
+        // The matching key is found among others
+        for private_key in [&keys[0], &keys[1], &escrow].iter() {
+            let mut keyring = others.clone();
+            keyring.push((*private_key).clone());
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(&keyring);
+            let mut mla_read =
+                ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).unwrap();
+            let mut rez = Vec::new();
+            mla_read
+                .get_file("my_file".to_string())
+                .unwrap()
+                .unwrap()
+                .data
+                .read_to_end(&mut rez)
+                .unwrap();
+            assert_eq!(rez, vec![1, 2]);
+        }
+
+        // Without a matching key, the archive is reported as such
+        let mut keyring = others.clone();
+        keyring.push(keys[2].clone());
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(&keyring);
+        match ArchiveReader::from_config(Cursor::new(dest.as_slice()), config) {
+            Err(Error::ConfigError(ConfigError::NoMatchingRecipientKey)) => {}
+            _ => panic!("Expected a NoMatchingRecipientKey error"),
+        };
+    }
+
+    #[test]
+    fn hybrid_recipient() {
+        // Use a deterministic RNG in tests, for reproductability. DO NOT DO THIS IS IN ANY RELEASED BINARY!
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let key = StaticSecret::new(&mut rng);
+        let hybrid_key = HybridPrivateKey::generate(&mut rng);
+
+        // A hybrid recipient bypasses a threshold
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .set_layers(Layers::default())
+            .add_public_keys(&[PublicKey::from(&key)])
+            .set_threshold(1)
+            .add_hybrid_public_keys(&[hybrid_key.public_key()]);
+        assert!(ArchiveWriter::from_config(Vec::new(), config).is_err());
+
+        // A hybrid recipient, and a X25519 one
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .set_layers(Layers::default())
+            .add_public_keys(&[PublicKey::from(&key)])
+            .add_hybrid_public_keys(&[hybrid_key.public_key()]);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        mla.add_file("my_file", 2, vec![1, 2].as_slice()).unwrap();
+        mla.finalize().unwrap();
+        let dest = mla.into_raw();
+
+        // The KEM is recorded in the header
+        let header = ArchiveHeader::from(&mut Cursor::new(dest.as_slice())).unwrap();
+        let hybrid = header.config.encrypt.unwrap().hybrid.unwrap();
+        assert_eq!(hybrid.kem, HybridKem::X25519MlKem768);
+        assert_eq!(hybrid.count_keys(), 1);
+
+        // Both recipients can read the archive, but not another hybrid key
+        let mut config = ArchiveReaderConfig::new();
+        config.add_hybrid_private_keys(&[HybridPrivateKey::generate(&mut rng)]);
+        assert!(ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).is_err());
+        for use_hybrid_key in [true, false].iter() {
+            let mut config = ArchiveReaderConfig::new();
+            if *use_hybrid_key {
+                config.add_hybrid_private_keys(std::slice::from_ref(&hybrid_key));
+            } else {
+                config.add_private_keys(std::slice::from_ref(&key));
+            }
+            let mut mla_read =
+                ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).unwrap();
+            let mut rez = Vec::new();
+            mla_read
+                .get_file("my_file".to_string())
+                .unwrap()
+                .unwrap()
+                .data
+                .read_to_end(&mut rez)
+                .unwrap();
+            assert_eq!(rez, vec![1, 2]);
+        }
+    }
+
+    #[test]
+    fn chacha20poly1305_cipher() {
+        // Use a deterministic RNG in tests, for reproductability. DO NOT DO THIS IS IN ANY RELEASED BINARY!
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let key = StaticSecret::new(&mut rng);
+        let mut data = vec![0u8; 300 * 1024];
+        rng.fill_bytes(&mut data);
+
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .set_layers(Layers::default())
+            .add_public_keys(&[PublicKey::from(&key)])
+            .set_cipher(Cipher::ChaCha20Poly1305);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        mla.add_file("my_file", data.len() as u64, data.as_slice())
+            .unwrap();
+        mla.finalize().unwrap();
+        let dest = mla.into_raw();
+
+        // The cipher is recorded in the header
+        let header = ArchiveHeader::from(&mut Cursor::new(dest.as_slice())).unwrap();
+        assert_eq!(
+            header.config.encrypt.unwrap().cipher,
+            Cipher::ChaCha20Poly1305
+        );
+
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).unwrap();
+        assert_eq!(mla_read.config.get_cipher(), Cipher::ChaCha20Poly1305);
+        let mut rez = Vec::new();
+        mla_read
+            .get_file("my_file".to_string())
+            .unwrap()
+            .unwrap()
+            .data
+            .read_to_end(&mut rez)
+            .unwrap();
+        assert_eq!(rez, data);
+
+        // The fail-safe reader also uses the recorded cipher
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_fsread = ArchiveFailSafeReader::from_config(dest.as_slice(), config).unwrap();
+        let mut mla_w = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new())
+            .expect("Writer init failed");
+        match mla_fsread.convert_to_archive(&mut mla_w).unwrap() {
+            FailSafeReadError::EndOfOriginalArchiveData => {}
+            status => panic!("Unexpected status: {}", status),
+        };
+        let mut mla_repread =
+            ArchiveReader::from_config(Cursor::new(mla_w.into_raw()), ArchiveReaderConfig::new())
+                .unwrap();
+        let mut rez = Vec::new();
+        mla_repread
+            .get_file("my_file".to_string())
+            .unwrap()
+            .unwrap()
+            .data
+            .read_to_end(&mut rez)
+            .unwrap();
+        assert_eq!(rez, data);
+    }
+
+    #[test]
+    fn metadata_encrypted() {
+        // Use a deterministic RNG in tests, for reproductability. DO NOT DO THIS IS IN ANY RELEASED BINARY!
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let key = StaticSecret::new(&mut rng);
+        let fname = "confidential_evidence.txt";
+
+        let build = |layers| {
+            let mut config = ArchiveWriterConfig::new();
+            config
+                .set_layers(layers)
+                .add_public_keys(&[PublicKey::from(&key)]);
+            let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+            mla.add_file(fname, 4, &[0, 1, 2, 3][..]).unwrap();
+            mla.finalize().unwrap();
+            mla.into_raw()
+        };
+        let contains_fname = |data: &[u8]| {
+            data.windows(fname.len())
+                .any(|window| window == fname.as_bytes())
+        };
+
+        // Without encryption, the name appears in the file blocks and the footer
+        assert!(contains_fname(&build(Layers::EMPTY)));
+
+        // With encryption, neither the blocks nor the footer leak it
+        let dest = build(Layers::ENCRYPT);
+        assert!(!contains_fname(&dest));
+
+        // Listing the files requires a private key
+        match ArchiveReader::from_config(Cursor::new(dest.as_slice()), ArchiveReaderConfig::new()) {
+            Err(Error::PrivateKeyNeeded) => {}
+            _ => panic!("Expected a PrivateKeyNeeded error"),
+        }
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mla_read = ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).unwrap();
+        assert_eq!(
+            mla_read.list_files().unwrap().collect::<Vec<_>>(),
+            vec![fname]
+        );
+    }
+
+    #[test]
+    fn padding() {
+        // Use a deterministic RNG in tests, for reproductability. DO NOT DO THIS IS IN ANY RELEASED BINARY!
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let key = StaticSecret::new(&mut rng);
+
+        // A bucket size of 0 is refused
+        let mut config = ArchiveWriterConfig::new();
+        config.set_padding(0);
+        match ArchiveWriter::from_config(Vec::new(), config) {
+            Err(Error::ConfigError(ConfigError::InvalidPaddingSize)) => {}
+            _ => panic!("Expected an InvalidPaddingSize error"),
+        }
+
+        for layering in &[Layers::EMPTY, Layers::ENCRYPT] {
+            let build = |size: usize| {
+                let mut config = ArchiveWriterConfig::new();
+                config
+                    .set_layers(*layering)
+                    .add_public_keys(&[PublicKey::from(&key)])
+                    .set_padding(64 * 1024);
+                let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+                mla.add_file("my_file", size as u64, vec![1u8; size].as_slice())
+                    .unwrap();
+                mla.finalize().unwrap();
+                mla.into_raw()
+            };
+
+            // Archives whose content fits in the same bucket have the same size
+            let small = build(10);
+            let big = build(40 * 1024);
+            assert_eq!(small.len(), big.len());
+            assert_ne!(build(100 * 1024).len(), small.len());
+
+            // Padding is transparently skipped by readers
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(std::slice::from_ref(&key));
+            let mut mla_read =
+                ArchiveReader::from_config(Cursor::new(big.as_slice()), config).unwrap();
+            assert_eq!(
+                mla_read.list_files().unwrap().collect::<Vec<_>>(),
+                vec!["my_file"]
+            );
+            let mut rez = Vec::new();
+            mla_read
+                .get_file("my_file".to_string())
+                .unwrap()
+                .unwrap()
+                .data
+                .read_to_end(&mut rez)
+                .unwrap();
+            assert_eq!(rez, vec![1u8; 40 * 1024]);
+
+            let fname = "my_file".to_string();
+            let mut export: HashMap<&String, Vec<u8>> = HashMap::new();
+            export.insert(&fname, Vec::new());
+            helpers::linear_extract(&mut mla_read, &mut export).unwrap();
+            assert_eq!(export.get(&fname).unwrap(), &vec![1u8; 40 * 1024]);
+
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(std::slice::from_ref(&key));
+            let mut mla_fsread =
+                ArchiveFailSafeReader::from_config(big.as_slice(), config).unwrap();
+            let mut mla_w = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new())
+                .expect("Writer init failed");
+            match mla_fsread.convert_to_archive(&mut mla_w).unwrap() {
+                FailSafeReadError::EndOfOriginalArchiveData => {}
+                status => panic!("Unexpected status: {}", status),
+            };
+
+            // Once reopened, the former padding stays in the middle of the data
+            let mut writer_config = ArchiveWriterConfig::new();
+            writer_config.set_padding(64 * 1024);
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(std::slice::from_ref(&key));
+            let mut mla =
+                ArchiveWriter::from_archive(Vec::new(), writer_config, Cursor::new(&small), config)
+                    .unwrap();
+            mla.add_file("new_file", 2, vec![4, 5].as_slice()).unwrap();
+            mla.finalize().unwrap();
+            let dest = mla.into_raw();
+
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(std::slice::from_ref(&key));
+            let mut mla_read =
+                ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).unwrap();
+            let mut sorted_list: Vec<String> = mla_read.list_files().unwrap().cloned().collect();
+            sorted_list.sort();
+            assert_eq!(sorted_list, vec!["my_file", "new_file"]);
+            let mut rez = Vec::new();
+            mla_read
+                .get_file("new_file".to_string())
+                .unwrap()
+                .unwrap()
+                .data
+                .read_to_end(&mut rez)
+                .unwrap();
+            assert_eq!(rez, vec![4, 5]);
+        }
+    }
+
+    #[test]
+    fn deterministic() {
+        // Use a deterministic RNG in tests, for reproductability. DO NOT DO THIS IS IN ANY RELEASED BINARY!
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let key = StaticSecret::new(&mut rng);
+        let hybrid_key = HybridPrivateKey::generate(&mut rng);
+
+        let build = |seed: Option<[u8; 32]>| {
+            let mut config = ArchiveWriterConfig::new();
+            config
+                .set_layers(Layers::COMPRESS | Layers::ENCRYPT)
+                .add_public_keys(&[PublicKey::from(&key)])
+                .add_hybrid_public_keys(&[hybrid_key.public_key()])
+                .set_password(b"password")
+                .set_padding(64 * 1024);
+            config
+                .with_checkpoint_interval(CheckpointInterval::Entries(2))
+                .unwrap();
+            if let Some(seed) = seed {
+                config.set_deterministic_seed(seed);
+            }
+            let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+            for index in 0..20u8 {
+                let name = format!("file{}", index);
+                let content = vec![index; 1000 * index as usize];
+                mla.add_file(&name, content.len() as u64, content.as_slice())
+                    .unwrap();
+            }
+            mla.finalize().unwrap();
+            mla.into_raw()
+        };
+
+        // The same seed gives the same archive, another seed or none a
+        // different one
+        let archive = build(Some([1u8; 32]));
+        assert_eq!(archive, build(Some([1u8; 32])));
+        assert_ne!(archive, build(Some([2u8; 32])));
+        assert_ne!(build(None), build(None));
+
+        // It is read as any other archive
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(archive.as_slice()), config).unwrap();
+        let mut rez = Vec::new();
+        mla_read
+            .get_file("file19".to_string())
+            .unwrap()
+            .unwrap()
+            .data
+            .read_to_end(&mut rez)
+            .unwrap();
+        assert_eq!(rez, vec![19u8; 19000]);
+
+        // Reopening it with a seed would reuse its key with the same nonce
+        let mut config = ArchiveWriterConfig::new();
+        config.set_deterministic_seed([1u8; 32]);
+        let mut reader_config = ArchiveReaderConfig::new();
+        reader_config.add_private_keys(std::slice::from_ref(&key));
+        match ArchiveWriter::from_archive(
+            Vec::new(),
+            config,
+            Cursor::new(archive.as_slice()),
+            reader_config,
+        ) {
+            Err(Error::ConfigError(ConfigError::DeterministicSeedWithExistingKey)) => {}
+            _ => panic!("A seed must not be used with an existing key"),
         }
     }
 
+    #[test]
+    fn signature() {
+        // Use a deterministic RNG in tests, for reproductability. DO NOT DO THIS IS IN ANY RELEASED BINARY!
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let key = StaticSecret::new(&mut rng);
+        let signing_key = ed25519_dalek::SecretKey::generate(&mut rng);
+        let signer = ed25519_dalek::PublicKey::from(&signing_key);
+        let other_signer =
+            ed25519_dalek::PublicKey::from(&ed25519_dalek::SecretKey::generate(&mut rng));
+
+        // The signature layer requires a signing key
+        let mut config = ArchiveWriterConfig::new();
+        config.set_layers(Layers::SIGN);
+        match ArchiveWriter::from_config(Vec::new(), config) {
+            Err(Error::ConfigError(ConfigError::SigningKeyIsMissing)) => {}
+            _ => panic!("Expected a SigningKeyIsMissing error"),
+        }
+
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .set_layers(Layers::DEFAULT | Layers::SIGN)
+            .add_public_keys(&[PublicKey::from(&key)])
+            .set_signature_key(&signing_key);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        mla.add_file("my_file", 3, vec![1, 2, 3].as_slice())
+            .unwrap();
+        mla.finalize().unwrap();
+        let dest = mla.into_raw();
+
+        let open = |data: &[u8], trusted_keys: &[ed25519_dalek::PublicKey]| {
+            let mut config = ArchiveReaderConfig::new();
+            config
+                .add_private_keys(std::slice::from_ref(&key))
+                .add_trusted_public_keys(trusted_keys);
+            ArchiveReader::from_config(Cursor::new(data.to_vec()), config)
+        };
+
+        // Any valid signature is accepted without trusted keys
+        let mut mla_read = open(&dest, &[]).unwrap();
+        assert_eq!(mla_read.get_info().signer, Some(signer));
+        let mut rez = Vec::new();
+        mla_read
+            .get_file("my_file".to_string())
+            .unwrap()
+            .unwrap()
+            .data
+            .read_to_end(&mut rez)
+            .unwrap();
+        assert_eq!(rez, vec![1, 2, 3]);
+
+        // Trusted keys
+        assert!(open(&dest, &[other_signer, signer]).is_ok());
+        match open(&dest, &[other_signer]) {
+            Err(Error::UntrustedSignature) => {}
+            _ => panic!("Expected an UntrustedSignature error"),
+        }
+
+        // Modifying the archive breaks the signature
+        for offset in &[dest.len() / 2, dest.len() - 100] {
+            let mut tampered = dest.clone();
+            tampered[*offset] ^= 1;
+            match open(&tampered, &[]) {
+                Err(Error::InvalidSignature) => {}
+                _ => panic!("Expected an InvalidSignature error"),
+            }
+        }
+
+        // An unsigned archive is refused once trusted keys are set
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .set_layers(Layers::DEFAULT)
+            .add_public_keys(&[PublicKey::from(&key)]);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        mla.finalize().unwrap();
+        match open(&mla.into_raw(), &[signer]) {
+            Err(Error::ConfigError(ConfigError::SignatureNotFound)) => {}
+            _ => panic!("Expected a SignatureNotFound error"),
+        }
+
+        // The signature is not checked while repairing
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_fsread = ArchiveFailSafeReader::from_config(dest.as_slice(), config).unwrap();
+        let mut mla_w = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new())
+            .expect("Writer init failed");
+        match mla_fsread.convert_to_archive(&mut mla_w).unwrap() {
+            FailSafeReadError::EndOfOriginalArchiveData => {}
+            status => panic!("Unexpected status: {}", status),
+        };
+
+        // Once reopened, the archive is signed again
+        let mut writer_config = ArchiveWriterConfig::new();
+        writer_config.set_signature_key(&signing_key);
+        let mut config = ArchiveReaderConfig::new();
+        config
+            .add_private_keys(std::slice::from_ref(&key))
+            .add_trusted_public_keys(&[signer]);
+        let mut mla =
+            ArchiveWriter::from_archive(Vec::new(), writer_config, Cursor::new(&dest), config)
+                .unwrap();
+        mla.add_file("new_file", 2, vec![4, 5].as_slice()).unwrap();
+        mla.finalize().unwrap();
+        let mla_read = open(&mla.into_raw(), &[signer]).unwrap();
+        let mut sorted_list: Vec<String> = mla_read.list_files().unwrap().cloned().collect();
+        sorted_list.sort();
+        assert_eq!(sorted_list, vec!["my_file", "new_file"]);
+    }
+
+    #[test]
+    fn forward_error_correction() {
+        // Use a deterministic RNG in tests, for reproductability. DO NOT DO THIS IS IN ANY RELEASED BINARY!
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let key = StaticSecret::new(&mut rng);
+        // Random data, to span several groups once compressed
+        let mut data = vec![0u8; 300 * 1024];
+        rng.fill_bytes(&mut data);
+
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .set_layers(Layers::DEFAULT | Layers::FEC)
+            .add_public_keys(&[PublicKey::from(&key)]);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        mla.add_file("my_file", data.len() as u64, data.as_slice())
+            .unwrap();
+        mla.finalize().unwrap();
+        let dest = mla.into_raw();
+
+        // Damage a few bytes here and there, including the layers footers
+        let mut damaged = dest.clone();
+        for offset in &[dest.len() / 3, dest.len() / 2, dest.len() - 50] {
+            damaged[*offset] ^= 0xFF;
+        }
+
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(damaged.as_slice()), config).unwrap();
+        let mut rez = Vec::new();
+        mla_read
+            .get_file("my_file".to_string())
+            .unwrap()
+            .unwrap()
+            .data
+            .read_to_end(&mut rez)
+            .unwrap();
+        assert!(rez == data);
+
+        // The fail-safe reader rebuilds the damaged data as well
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_fsread =
+            ArchiveFailSafeReader::from_config(damaged.as_slice(), config).unwrap();
+        let mut mla_w = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new())
+            .expect("Writer init failed");
+        match mla_fsread.convert_to_archive(&mut mla_w).unwrap() {
+            FailSafeReadError::EndOfOriginalArchiveData => {}
+            status => panic!("Unexpected status: {}", status),
+        };
+
+        // Once reopened, the archive keeps its layers
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla = ArchiveWriter::from_archive(
+            Vec::new(),
+            ArchiveWriterConfig::new(),
+            Cursor::new(&damaged),
+            config,
+        )
+        .unwrap();
+        mla.add_file("new_file", 2, vec![4, 5].as_slice()).unwrap();
+        mla.finalize().unwrap();
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mla_read = ArchiveReader::from_config(Cursor::new(mla.into_raw()), config).unwrap();
+        assert!(mla_read.get_info().layers_enabled.contains(Layers::FEC));
+        let mut sorted_list: Vec<String> = mla_read.list_files().unwrap().cloned().collect();
+        sorted_list.sort();
+        assert_eq!(sorted_list, vec!["my_file", "new_file"]);
+    }
+
+    #[test]
+    fn rekey_interval() {
+        // Use a deterministic RNG in tests, for reproductability. DO NOT DO THIS IS IN ANY RELEASED BINARY!
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let key = StaticSecret::new(&mut rng);
+        let mut data = vec![0u8; 700 * 1024];
+        rng.fill_bytes(&mut data);
+
+        // An interval of 0 is refused
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .set_layers(Layers::ENCRYPT)
+            .add_public_keys(&[PublicKey::from(&key)])
+            .set_rekey_interval(0);
+        match ArchiveWriter::from_config(Vec::new(), config) {
+            Err(Error::ConfigError(ConfigError::InvalidRekeyInterval)) => {}
+            _ => panic!("Expected an InvalidRekeyInterval error"),
+        }
+
+        // Without compression, the file spans several encryption chunks,
+        // each one with its own key
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .set_layers(Layers::ENCRYPT)
+            .add_public_keys(&[PublicKey::from(&key)])
+            .set_rekey_interval(200 * 1024);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        mla.add_file("my_file", data.len() as u64, data.as_slice())
+            .unwrap();
+        mla.finalize().unwrap();
+        let dest = mla.into_raw();
+
+        // The schedule is recorded in the header, rounded up to whole chunks
+        let header = ArchiveHeader::from(&mut Cursor::new(dest.as_slice())).unwrap();
+        assert_eq!(header.config.encrypt.unwrap().rekey_interval, Some(2));
+
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).unwrap();
+        let mut file = mla_read.get_file("my_file".to_string()).unwrap().unwrap();
+        let mut rez = Vec::new();
+        file.data.read_to_end(&mut rez).unwrap();
+        assert_eq!(rez, data);
+
+        // Seek in a later epoch, then back in the first one
+        let mut buf = [0u8; 1024];
+        file.data.seek(SeekFrom::Start(600 * 1024)).unwrap();
+        file.data.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf[..], &data[600 * 1024..601 * 1024]);
+        file.data.seek(SeekFrom::Start(10)).unwrap();
+        file.data.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf[..], &data[10..1034]);
+
+        // The fail-safe reader also uses the recorded schedule
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_fsread = ArchiveFailSafeReader::from_config(dest.as_slice(), config).unwrap();
+        let mut mla_w = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new())
+            .expect("Writer init failed");
+        match mla_fsread.convert_to_archive(&mut mla_w).unwrap() {
+            FailSafeReadError::EndOfOriginalArchiveData => {}
+            status => panic!("Unexpected status: {}", status),
+        };
+        let mut mla_repread =
+            ArchiveReader::from_config(Cursor::new(mla_w.into_raw()), ArchiveReaderConfig::new())
+                .unwrap();
+        let mut rez = Vec::new();
+        mla_repread
+            .get_file("my_file".to_string())
+            .unwrap()
+            .unwrap()
+            .data
+            .read_to_end(&mut rez)
+            .unwrap();
+        assert_eq!(rez, data);
+    }
+
+    #[test]
+    fn seek_in_file() {
+        // Build an archive with a file made of several blocks, interleaved
+        // with another file
+        for layering in &[Layers::EMPTY, Layers::COMPRESS] {
+            let mut config = ArchiveWriterConfig::new();
+            config.set_layers(*layering);
+            let mut mla =
+                ArchiveWriter::from_config(Vec::new(), config).expect("Writer init failed");
+            let content: Vec<u8> = (0..1000u32).map(|i| (i % 251) as u8).collect();
+            let id = mla.start_file("file").unwrap();
+            let id_other = mla.start_file("other").unwrap();
+            for chunk in content.chunks(100) {
+                mla.append_file_content(id, chunk.len() as u64, chunk)
+                    .unwrap();
+                mla.append_file_content(id_other, 3, &[1, 2, 3][..])
+                    .unwrap();
+            }
+            mla.end_file(id).unwrap();
+            mla.end_file(id_other).unwrap();
+            mla.finalize().unwrap();
+
+            let dest = mla.into_raw();
+            let mut mla_read = ArchiveReader::from_config(
+                Cursor::new(dest.as_slice()),
+                ArchiveReaderConfig::new(),
+            )
+            .unwrap();
+            let mut file = mla_read.get_file("file".to_string()).unwrap().unwrap();
+
+            // Forward, backward, inside a block and across blocks
+            for (pos, expected_pos) in &[
+                (SeekFrom::Start(550), 550),
+                (SeekFrom::Start(10), 10),
+                (SeekFrom::Current(185), 200),
+                (SeekFrom::End(-5), 995),
+                (SeekFrom::Start(99), 99),
+            ] {
+                assert_eq!(file.data.seek(*pos).unwrap(), *expected_pos);
+                let mut buf = vec![0u8; 5];
+                file.data.read_exact(&mut buf).unwrap();
+                assert_eq!(
+                    buf,
+                    &content[*expected_pos as usize..*expected_pos as usize + 5]
+                );
+            }
+
+            // Read from the middle until the end
+            file.data.seek(SeekFrom::Start(420)).unwrap();
+            let mut rez = Vec::new();
+            file.data.read_to_end(&mut rez).unwrap();
+            assert_eq!(rez, &content[420..]);
+
+            // After the end
+            assert_eq!(file.data.seek(SeekFrom::Start(2000)).unwrap(), 2000);
+            assert_eq!(file.data.read(&mut [0u8; 5]).unwrap(), 0);
+            assert!(file.data.seek(SeekFrom::Current(-3000)).is_err());
+        }
+    }
+
+    #[test]
+    fn reuse_located_blocks() {
+        // A file made of several blocks, interleaved with another file
+        let mut mla = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new())
+            .expect("Writer init failed");
+        let content: Vec<u8> = (0..1000u32).map(|i| (i % 251) as u8).collect();
+        let id = mla.start_file("file").unwrap();
+        let id_other = mla.start_file("other").unwrap();
+        for chunk in content.chunks(100) {
+            mla.append_file_content(id, chunk.len() as u64, chunk)
+                .unwrap();
+            mla.append_file_content(id_other, 3, &[1, 2, 3][..])
+                .unwrap();
+        }
+        mla.end_file(id).unwrap();
+        mla.end_file(id_other).unwrap();
+        mla.finalize().unwrap();
+
+        let dest = mla.into_raw();
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(dest.as_slice()), ArchiveReaderConfig::new())
+                .unwrap();
+        let mut file = mla_read.get_file("file".to_string()).unwrap().unwrap();
+        file.data.seek(SeekFrom::Start(550)).unwrap();
+        let located = file.data.into_located_blocks();
+        assert_eq!(located.blocks.len(), 6);
+
+        // A new reader of the same file starts from the located blocks
+        let mut file = mla_read.get_file("file".to_string()).unwrap().unwrap();
+        file.data.reuse_located_blocks(located);
+        assert_eq!(file.data.known_blocks.len(), 6);
+        for pos in &[720, 420, 10] {
+            file.data.seek(SeekFrom::Start(*pos)).unwrap();
+            let mut buf = vec![0u8; 5];
+            file.data.read_exact(&mut buf).unwrap();
+            assert_eq!(buf, &content[*pos as usize..*pos as usize + 5]);
+        }
+
+        // Blocks located in another file are ignored
+        let located = file.data.into_located_blocks();
+        let mut other = mla_read.get_file("other".to_string()).unwrap().unwrap();
+        other.data.reuse_located_blocks(located);
+        assert!(other.data.known_blocks.is_empty());
+        let mut rez = Vec::new();
+        other.data.read_to_end(&mut rez).unwrap();
+        assert_eq!(rez, [1, 2, 3].repeat(10));
+    }
+
+    #[test]
+    fn seek_in_big_file() {
+        // A file spanning several compressed blocks, then read from arbitrary
+        // offsets
+        let mut config = ArchiveWriterConfig::new();
+        config.set_layers(Layers::COMPRESS);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        let content: Vec<u8> = (0..(UNCOMPRESSED_DATA_SIZE as usize * 2 + 1234))
+            .map(|i| ((i * 7) % 251) as u8)
+            .collect();
+        mla.add_file("big_file", content.len() as u64, content.as_slice())
+            .unwrap();
+        mla.finalize().unwrap();
+
+        let dest = mla.into_raw();
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(dest.as_slice()), ArchiveReaderConfig::new())
+                .unwrap();
+        let mut file = mla_read.get_file("big_file".to_string()).unwrap().unwrap();
+        let block_size = UNCOMPRESSED_DATA_SIZE as u64;
+        for pos in &[
+            2 * block_size + 1000,
+            10,
+            block_size - 3,
+            block_size + 42,
+            content.len() as u64 - 8,
+        ] {
+            assert_eq!(file.data.seek(SeekFrom::Start(*pos)).unwrap(), *pos);
+            let mut buf = vec![0u8; 8];
+            file.data.read_exact(&mut buf).unwrap();
+            assert_eq!(buf, &content[*pos as usize..*pos as usize + 8]);
+        }
+    }
+
+    #[test]
+    fn add_file_unknown_size() {
+        let mut mla = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+        let content: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        // A reader giving its content by small parts, as a pipe
+        let src = content
+            .chunks(1000)
+            .fold(Box::new(io::empty()) as Box<dyn Read>, |src, chunk| {
+                Box::new(src.chain(chunk))
+            });
+        mla.add_file_unknown_size("stream", src).unwrap();
+        mla.add_file_unknown_size("empty", io::empty()).unwrap();
+        mla.finalize().unwrap();
+
+        let dest = mla.into_raw();
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(dest.as_slice()), ArchiveReaderConfig::new())
+                .unwrap();
+        let mut file = mla_read.get_file("stream".to_string()).unwrap().unwrap();
+        assert_eq!(file.size, content.len() as u64);
+        let mut read = Vec::new();
+        file.data.read_to_end(&mut read).unwrap();
+        assert_eq!(read, content);
+        let file = mla_read.get_file("empty".to_string()).unwrap().unwrap();
+        assert_eq!(file.size, 0);
+    }
+
+    #[test]
+    fn buffered_file() {
+        use std::io::BufRead;
+
+        let mut config = ArchiveWriterConfig::new();
+        config.set_layers(Layers::COMPRESS);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        let content: String = (0..10000).map(|i| format!("line {}\n", i)).collect();
+        mla.add_file("log", content.len() as u64, content.as_bytes())
+            .unwrap();
+        mla.finalize().unwrap();
+
+        let dest = mla.into_raw();
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(dest.as_slice()), ArchiveReaderConfig::new())
+                .unwrap();
+        let file = mla_read.get_file("log".to_string()).unwrap().unwrap();
+        let mut reader = file.into_buf_reader();
+        let lines: Vec<String> = (&mut reader).lines().map(|line| line.unwrap()).collect();
+        assert_eq!(lines.len(), 10000);
+        assert_eq!(lines[1234], "line 1234");
+
+        // Seeking discards the buffer
+        reader.seek(SeekFrom::Start(7)).unwrap();
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "line 1\n");
+    }
+
+    #[test]
+    fn files_archive_order() {
+        let (mla, key, files) = build_archive(None, true);
+        let dest = mla.into_raw();
+
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mla_read = ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).unwrap();
+        let expected: Vec<&String> = files.iter().map(|(fname, _)| fname).collect();
+        assert_eq!(mla_read.list_files_archive_order().unwrap(), expected);
+    }
+
+    #[test]
+    fn archive_info() {
+        for layering in &[Layers::DEFAULT, Layers::EMPTY] {
+            let (mla, key, files) = build_archive(Some(*layering), false);
+            let dest = mla.into_raw();
+
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(std::slice::from_ref(&key));
+            let mla_read =
+                ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).unwrap();
+            let info = mla_read.get_info();
+            assert_eq!(info.format_version, MLA_FORMAT_VERSION);
+            assert_eq!(info.layers_enabled, *layering);
+            if *layering == Layers::DEFAULT {
+                assert_eq!(info.recipients_count, Some(1));
+                // Files are small enough to fit in one block
+                assert_eq!(info.compressed_sizes.as_ref().unwrap().len(), 1);
+                assert!(info.compressed_size().unwrap() > 0);
+            } else {
+                assert_eq!(info.recipients_count, None);
+                assert_eq!(info.compressed_size(), None);
+            }
+            assert_eq!(
+                mla_read.get_files_size().unwrap(),
+                files.iter().map(|(_, data)| data.len() as u64).sum::<u64>()
+            );
+        }
+    }
+
+    #[test]
+    fn writer_progress() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        let mut mla = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new())
+            .expect("Writer init failed");
+        let reported = Rc::new(Cell::new(0));
+        let reported_cb = reported.clone();
+        mla.set_progress_callback(move |count| reported_cb.set(reported_cb.get() + count));
+
+        let data = vec![42u8; 1000];
+        mla.add_file("file1", data.len() as u64, data.as_slice())
+            .unwrap();
+        assert_eq!(reported.get(), 1000);
+        let id = mla.start_file("file2").unwrap();
+        mla.append_file_content(id, 10, &data[..10]).unwrap();
+        mla.append_file_content(id, 20, &data[..20]).unwrap();
+        mla.end_file(id).unwrap();
+        assert_eq!(reported.get(), 1030);
+        mla.finalize().unwrap();
+    }
+
+    #[test]
+    fn file_offset_and_compressed_size() {
+        for layering in &[Layers::DEFAULT, Layers::EMPTY] {
+            let (mla, key, files) = build_archive(Some(*layering), false);
+            let dest = mla.into_raw();
+
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(std::slice::from_ref(&key));
+            let mla_read =
+                ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).unwrap();
+
+            // Files are not interleaved, so offsets follow the archive order
+            let offsets: Vec<u64> = mla_read
+                .list_files_archive_order()
+                .unwrap()
+                .iter()
+                .map(|fname| mla_read.get_file_offset(fname).unwrap().unwrap())
+                .collect();
+            let mut sorted = offsets.clone();
+            sorted.sort_unstable();
+            sorted.dedup();
+            assert_eq!(offsets, sorted);
+
+            let mut total = 0;
+            for (fname, data) in &files {
+                let compressed_size = mla_read.get_compressed_size(fname).unwrap().unwrap();
+                if *layering == Layers::EMPTY {
+                    assert_eq!(compressed_size, data.len() as u64);
+                }
+                total += compressed_size;
+            }
+            if let Some(compressed_size) = mla_read.get_info().compressed_size() {
+                assert!(total <= compressed_size);
+            }
+            assert_eq!(mla_read.get_file_offset("unknown").unwrap(), None);
+            assert_eq!(mla_read.get_compressed_size("unknown").unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn file_ranges() {
+        for interleaved in &[false, true] {
+            let (mla, _key, files) = build_archive(Some(Layers::EMPTY), *interleaved);
+            let dest = mla.into_raw();
+            let mut mla_read = ArchiveReader::from_config(
+                Cursor::new(dest.as_slice()),
+                ArchiveReaderConfig::new(),
+            )
+            .unwrap();
+            for (fname, data) in &files {
+                let ranges = mla_read.get_file_ranges(fname).unwrap().unwrap();
+                // Interleaved files are split
+                if !*interleaved {
+                    assert_eq!(ranges.len(), 1);
+                }
+                let mut content = Vec::new();
+                for (start, end) in ranges {
+                    content.extend_from_slice(&dest[start as usize..end as usize]);
+                }
+                assert_eq!(&content, data);
+            }
+            assert_eq!(mla_read.get_file_ranges("unknown").unwrap(), None);
+        }
+
+        // Layers enabled: the content is not stored as is
+        let (mla, key, files) = build_archive(None, false);
+        let dest = mla.into_raw();
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).unwrap();
+        assert_eq!(mla_read.get_file_ranges(&files[0].0).unwrap(), None);
+    }
+
+    #[test]
+    fn files_extents() {
+        for interleaved in &[false, true] {
+            let (mla, key, files) = build_archive(None, *interleaved);
+            let dest = mla.into_raw();
+
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(std::slice::from_ref(&key));
+            let mla_read =
+                ArchiveReader::from_config(Cursor::new(dest.as_slice()), config).unwrap();
+            let extents = mla_read.get_files_extents().unwrap();
+            assert_eq!(extents.len(), files.len());
+
+            let mut all: Vec<(u64, u64)> = Vec::new();
+            for (fname, data) in &files {
+                let file_extents = &extents[fname];
+                // Start with the first block of the file, and hold its
+                // content
+                assert_eq!(
+                    file_extents[0].0,
+                    mla_read.get_file_offset(fname).unwrap().unwrap()
+                );
+                let length: u64 = file_extents.iter().map(|(start, end)| end - start).sum();
+                assert!(length > data.len() as u64);
+                // Interleaved files are split
+                if !*interleaved {
+                    assert_eq!(file_extents.len(), 1);
+                }
+                all.extend(file_extents);
+
+                let compressed_size = mla_read
+                    .get_info()
+                    .compressed_size_of(file_extents)
+                    .unwrap();
+                assert!(compressed_size > 0);
+            }
+            // Extents of different files do not overlap
+            all.sort_unstable();
+            for pair in all.windows(2) {
+                assert!(pair[0].1 <= pair[1].0);
+            }
+        }
+    }
+
+    #[test]
+    fn check_file_size() {
+        // Build an archive with 3 non-interleaved files and another with
+        // interleaved files
+        for interleaved in &[false, true] {
+            let (mla, key, files) = build_archive(None, *interleaved);
+
+            for (fname, data) in &files {
+                let id = mla.files_info.get(fname).unwrap();
+                let size = mla.ids_info.get(id).unwrap().size;
+                assert_eq!(size, data.len() as u64);
+            }
+
+            let dest = mla.into_raw();
+            let buf = Cursor::new(dest.as_slice());
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(std::slice::from_ref(&key));
+            let mut mla_read = ArchiveReader::from_config(buf, config).unwrap();
+
+            for (fname, data) in &files {
+                let mla_file = mla_read.get_file(fname.to_string()).unwrap().unwrap();
+                assert_eq!(mla_file.size, data.len() as u64);
+            }
+        }
+    }
+
+    #[test]
+    fn failsafe_detect_integrity() {
+        // Build an archive with 3 files
+        let (mla, _key, files) = build_archive(Some(Layers::DEBUG), false);
+
+        // Swap the first 2 bytes of file1
+        let mut dest = mla.into_raw();
+        let expect = files[0].1.as_slice();
+        let pos: Vec<usize> = dest
+            .iter()
+            .enumerate()
+            .filter_map(|e| {
+                if e.0 + expect.len() < dest.len() && &dest[e.0..e.0 + expect.len()] == expect {
+                    Some(e.0)
+                } else {
+                    None
+                }
+            })
+            .collect();
+        dest.swap(pos[0], pos[0] + 1);
+
+        // Prepare the failsafe reader
+        let mut mla_fsread =
+            ArchiveFailSafeReader::from_config(dest.as_slice(), ArchiveReaderConfig::new())
+                .unwrap();
+
+        // Prepare the writer
+        let dest_w = Vec::new();
+        let mut mla_w = ArchiveWriter::from_config(dest_w, ArchiveWriterConfig::new())
+            .expect("Writer init failed");
+
+        // Conversion
+        match mla_fsread.convert_to_archive(&mut mla_w).unwrap() {
+            FailSafeReadError::UnfinishedFiles {
+                filenames,
+                stopping_error,
+            } => {
+                // We expect to ends with a HashDiffers on first file
+                assert_eq!(filenames, vec![files[0].0.to_string()]);
+                match *stopping_error {
+                    FailSafeReadError::HashDiffers { .. } => {}
+                    _ => {
+                        panic!("Unexpected stopping_error: {}", stopping_error);
+                    }
+                }
+            }
+            status => {
+                panic!("Unexpected status: {}", status);
+            }
+        };
+    }
+
+    #[test]
+    fn failsafe_skip_damaged_block() {
+        // Build an archive with 3 interleaved files
+        let (mla, _key, files) = build_archive(Some(Layers::DEBUG), true);
+        let mut dest = mla.into_raw();
+        let find = |dest: &[u8], pattern: &[u8]| {
+            dest.windows(pattern.len())
+                .position(|window| window == pattern)
+                .unwrap()
+        };
+
+        // Damage the header of the second file start, and the content of the
+        // third file
+        let pos = find(&dest, files[1].0.as_bytes());
+        dest[pos] ^= 1;
+        let pos = find(&dest, &files[2].1);
+        dest[pos] ^= 1;
+
+        let mut mla_fsread =
+            ArchiveFailSafeReader::from_config(dest.as_slice(), ArchiveReaderConfig::new())
+                .unwrap();
+        let mut config = ArchiveWriterConfig::new();
+        config.set_layers(Layers::DEBUG);
+        let mut mla_w = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+
+        // Blocks following the damaged ones are still used
+        match mla_fsread.convert_to_archive(&mut mla_w).unwrap() {
+            FailSafeReadError::UnfinishedFiles {
+                filenames,
+                stopping_error,
+            } => {
+                assert_eq!(filenames, vec![files[2].0.to_string()]);
+                match *stopping_error {
+                    FailSafeReadError::ErrorOnNextBlock(Error::WrongBlockTag) => {}
+                    _ => {
+                        panic!("Unexpected stopping_error: {}", stopping_error);
+                    }
+                }
+            }
+            status => {
+                panic!("Unexpected status: {}", status);
+            }
+        };
+
+        // The second file is lost, the first one and the end of the archive
+        // are kept
+        let mut mla_read = ArchiveReader::new(Cursor::new(mla_w.into_raw())).unwrap();
+        let mut sorted_list: Vec<String> = mla_read.list_files().unwrap().cloned().collect();
+        sorted_list.sort();
+        assert_eq!(sorted_list, vec![files[0].0.clone(), files[2].0.clone()]);
+        let mut rez = Vec::new();
+        mla_read
+            .get_file(files[0].0.clone())
+            .unwrap()
+            .unwrap()
+            .data
+            .read_to_end(&mut rez)
+            .unwrap();
+        assert_eq!(rez, files[0].1);
+    }
+
+    #[test]
+    fn failsafe_recovery_report() {
+        // Build an archive with 3 interleaved files
+        let (mla, _key, files) = build_archive(Some(Layers::DEBUG), true);
+        let mut dest = mla.into_raw();
+
+        // Truncate in the middle of the last block of the first file
+        let pos = dest.windows(5).position(|w| w == [4, 5, 6, 7, 8]).unwrap();
+        dest.truncate(pos + 2);
+
+        let mut mla_fsread =
+            ArchiveFailSafeReader::from_config(dest.as_slice(), ArchiveReaderConfig::new())
+                .unwrap();
+        let mut config = ArchiveWriterConfig::new();
+        config.set_layers(Layers::DEBUG);
+        let mut mla_w = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        let mut report = mla_fsread
+            .convert_to_archive_with_report(&mut mla_w)
+            .unwrap();
+        match report.status {
+            FailSafeReadError::UnfinishedFiles { .. } => {}
+            status => {
+                panic!("Unexpected status: {}", status);
+            }
+        }
+
+        // Files from an index, if any, are reported as missing
+        let other = "other".to_string();
+        report.add_missing(&[files[2].0.clone(), other.clone()]);
+        assert_eq!(
+            report.files,
+            vec![
+                (files[0].0.clone(), FileRecoveryStatus::Truncated(5)),
+                (files[1].0.clone(), FileRecoveryStatus::Truncated(4)),
+                (files[2].0.clone(), FileRecoveryStatus::Recovered),
+                (other, FileRecoveryStatus::Missing),
+            ]
+        );
+    }
+
+    #[test]
+    fn failsafe_filtered() {
+        // Build an archive with 3 interleaved files
+        let (mla, key, files) = build_archive(None, true);
+        let dest = mla.into_raw();
+
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_fsread = ArchiveFailSafeReader::from_config(dest.as_slice(), config).unwrap();
+        let mut config = ArchiveWriterConfig::new();
+        config.set_layers(Layers::DEBUG);
+        let mut mla_w = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+
+        // Only keep the first and the last files
+        let selected = [files[0].0.as_str(), files[2].0.as_str()];
+        let report = mla_fsread
+            .convert_to_archive_filtered(&mut mla_w, |fname| selected.contains(&fname))
+            .unwrap();
+        match report.status {
+            FailSafeReadError::EndOfOriginalArchiveData => {}
+            status => {
+                panic!("Unexpected status: {}", status);
+            }
+        }
+        assert_eq!(
+            report.files,
+            vec![
+                (files[0].0.clone(), FileRecoveryStatus::Recovered),
+                (files[2].0.clone(), FileRecoveryStatus::Recovered),
+            ]
+        );
+
+        // Read the obtained stream
+        let dest_w = mla_w.into_raw();
+        let buf = Cursor::new(dest_w.as_slice());
+        let mut mla_read = ArchiveReader::from_config(buf, ArchiveReaderConfig::new()).unwrap();
+        let mut fnames: Vec<String> = mla_read.list_files().unwrap().cloned().collect();
+        fnames.sort();
+        assert_eq!(fnames, vec![files[0].0.clone(), files[2].0.clone()]);
+        for (fname, content) in &[&files[0], &files[2]] {
+            let mut rez = Vec::new();
+            let mut file = mla_read.get_file(fname.clone()).unwrap().unwrap();
+            file.data.read_to_end(&mut rez).unwrap();
+            assert_eq!(&rez, content);
+        }
+    }
+
+    #[test]
+    fn failsafe_extract() {
+        // Build an archive with 3 interleaved files
+        let (mla, key, files) = build_archive(None, true);
+        let dest = mla.into_raw();
+
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_fsread = ArchiveFailSafeReader::from_config(dest.as_slice(), config).unwrap();
+
+        // Extract all files but the second one, in the order they start
+        let mut names = Vec::new();
+        let mut contents = vec![Vec::new(); 2];
+        let mut writers = contents.iter_mut();
+        let report = mla_fsread
+            .extract_with_report(|fname| {
+                if fname == files[1].0 {
+                    return Ok(None);
+                }
+                names.push(fname.to_string());
+                Ok(writers.next())
+            })
+            .unwrap();
+        match report.status {
+            FailSafeReadError::EndOfOriginalArchiveData => {}
+            status => {
+                panic!("Unexpected status: {}", status);
+            }
+        }
+        assert_eq!(
+            report.files,
+            vec![
+                (files[0].0.clone(), FileRecoveryStatus::Recovered),
+                (files[2].0.clone(), FileRecoveryStatus::Recovered),
+            ]
+        );
+        assert_eq!(names, vec![files[0].0.clone(), files[2].0.clone()]);
+        assert_eq!(contents, vec![files[0].1.clone(), files[2].1.clone()]);
+    }
+
+    #[test]
+    fn stream_extract() {
+        // Build an archive with 3 interleaved files
+        let (mla, key, files) = build_archive(None, true);
+        let dest = mla.into_raw();
+
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_stream = ArchiveStreamReader::from_config(dest.as_slice(), config).unwrap();
+
+        // Extract all files but the second one, in the order they start
+        let mut contents = vec![Vec::new(); 2];
+        let mut writers = contents.iter_mut();
+        let mut closed = Vec::new();
+        let extracted = mla_stream
+            .extract(
+                |fname| {
+                    if fname == files[1].0 {
+                        return Ok(None);
+                    }
+                    Ok(writers.next())
+                },
+                |fname, _, _| {
+                    closed.push(fname.to_string());
+                    Ok(())
+                },
+            )
+            .unwrap();
+        assert_eq!(closed, extracted);
+        let mut sorted = extracted.clone();
+        sorted.sort();
+        assert_eq!(sorted, vec![files[0].0.clone(), files[2].0.clone()]);
+        assert_eq!(contents, vec![files[0].1.clone(), files[2].1.clone()]);
+
+        // A truncated or altered stream is an error, not a partial success,
+        // and no file is closed
+        let mut altered = dest.clone();
+        let middle = altered.len() / 2;
+        altered[middle] ^= 0xFF;
+        for src in [&dest[..dest.len() / 2], altered.as_slice()] {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(std::slice::from_ref(&key));
+            let mut mla_stream = ArchiveStreamReader::from_config(src, config).unwrap();
+            assert!(mla_stream
+                .extract(
+                    |_| Ok(Some(io::sink())),
+                    |_, _, _| panic!("An altered file must not be closed")
+                )
+                .is_err());
+        }
+
+        // The signature of a signed archive can only be checked at its end
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .set_layers(Layers::SIGN)
+            .set_signature_key(&ed25519_dalek::SecretKey::generate(&mut rng));
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        mla.finalize().unwrap();
+        assert!(ArchiveStreamReader::new(mla.into_raw().as_slice()).is_err());
+    }
+
+    /// Build an archive with 2 interleaved files, and a checkpoint after each
+    /// file content block
+    fn build_checkpointed_archive(layers: Layers) -> (Vec<u8>, Vec<(String, Vec<u8>)>) {
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .set_layers(layers)
+            .with_checkpoint_interval(CheckpointInterval::Bytes(1))
+            .unwrap();
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        let fname1 = "my_file1".to_string();
+        let fname2 = "my_file2".to_string();
+        let id1 = mla.start_file(&fname1).unwrap();
+        mla.append_file_content(id1, 3, &[1, 2, 3][..]).unwrap();
+        mla.add_file(&fname2, 4, &[9, 10, 11, 12][..]).unwrap();
+        mla.append_file_content(id1, 5, &[4, 5, 6, 7, 8][..])
+            .unwrap();
+        mla.append_file_content(id1, 2, &[9, 10][..]).unwrap();
+        mla.end_file(id1).unwrap();
+        mla.finalize().unwrap();
+        (
+            mla.into_raw(),
+            vec![
+                (fname1, vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10]),
+                (fname2, vec![9, 10, 11, 12]),
+            ],
+        )
+    }
+
+    #[test]
+    fn checkpoint_read() {
+        let (dest, files) = build_checkpointed_archive(Layers::COMPRESS);
+
+        // Checkpoints are transparently skipped by readers
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(dest.as_slice()), ArchiveReaderConfig::new())
+                .unwrap();
+        for (fname, content) in &files {
+            let mut rez = Vec::new();
+            let mut file = mla_read.get_file(fname.clone()).unwrap().unwrap();
+            file.data.read_to_end(&mut rez).unwrap();
+            assert_eq!(&rez, content);
+            // Seek over the checkpoints in the middle of the file
+            file.data.seek(SeekFrom::Start(4)).unwrap();
+            let mut rez = Vec::new();
+            file.data.read_to_end(&mut rez).unwrap();
+            assert_eq!(rez, content[4..]);
+        }
+
+        let mut export: HashMap<&String, Vec<u8>> = HashMap::new();
+        for (fname, _content) in &files {
+            export.insert(fname, Vec::new());
+        }
+        helpers::linear_extract(&mut mla_read, &mut export).unwrap();
+        for (fname, content) in &files {
+            assert_eq!(export.get(fname).unwrap(), content);
+        }
+
+        let mut mla_fsread =
+            ArchiveFailSafeReader::from_config(dest.as_slice(), ArchiveReaderConfig::new())
+                .unwrap();
+        let mut mla_w = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new())
+            .expect("Writer init failed");
+        match mla_fsread.convert_to_archive(&mut mla_w).unwrap() {
+            FailSafeReadError::EndOfOriginalArchiveData => {}
+            status => panic!("Unexpected status: {}", status),
+        };
+    }
+
+    #[test]
+    fn failsafe_checkpoint() {
+        let (mut dest, files) = build_checkpointed_archive(Layers::DEBUG);
+
+        // Damage the first file start, and truncate the archive as an
+        // interrupted writer would
+        let pos = dest
+            .windows(files[0].0.len())
+            .position(|window| window == files[0].0.as_bytes())
+            .unwrap();
+        dest[pos] ^= 1;
+        let pos = dest
+            .windows(2)
+            .position(|window| window == [9, 10])
+            .unwrap();
+        let pos = pos
+            + 2
+            + dest[pos + 2..]
+                .windows(2)
+                .position(|window| window == [9, 10])
+                .unwrap();
+        dest.truncate(pos);
+
+        let mut mla_fsread =
+            ArchiveFailSafeReader::from_config(dest.as_slice(), ArchiveReaderConfig::new())
+                .unwrap();
+        let mut config = ArchiveWriterConfig::new();
+        config.set_layers(Layers::DEBUG);
+        let mut mla_w = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        let report = mla_fsread
+            .convert_to_archive_with_report(&mut mla_w)
+            .unwrap();
+
+        // The first file is named from the first checkpoint, its lost
+        // beginning being replaced by zeros
+        assert_eq!(
+            report.files,
+            vec![
+                (files[0].0.clone(), FileRecoveryStatus::Corrupted),
+                (files[1].0.clone(), FileRecoveryStatus::Recovered),
+            ]
+        );
+        let mut mla_read = ArchiveReader::new(Cursor::new(mla_w.into_raw())).unwrap();
+        let mut rez = Vec::new();
+        mla_read
+            .get_file(files[0].0.clone())
+            .unwrap()
+            .unwrap()
+            .data
+            .read_to_end(&mut rez)
+            .unwrap();
+        assert_eq!(rez, vec![0, 0, 0, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn lz4_archive() {
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .set_layers(Layers::COMPRESS)
+            .set_compression_algorithm(CompressionAlgorithm::Lz4);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        let files = vec![
+            ("my_file1".to_string(), vec![1u8; 5000]),
+            ("my_file2".to_string(), (0..=255).collect::<Vec<u8>>()),
+        ];
+        for (fname, content) in &files {
+            mla.add_file(fname, content.len() as u64, content.as_slice())
+                .unwrap();
+        }
+        mla.finalize().unwrap();
+        let dest = mla.into_raw();
+
+        // The algorithm is recorded in the header
+        let header = ArchiveHeader::from(&mut dest.as_slice()).unwrap();
+        assert_eq!(
+            header.config.compress.unwrap().algorithm,
+            CompressionAlgorithm::Lz4
+        );
+
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(dest.as_slice()), ArchiveReaderConfig::new())
+                .unwrap();
+        for (fname, content) in &files {
+            let mut rez = Vec::new();
+            mla_read
+                .get_file(fname.clone())
+                .unwrap()
+                .unwrap()
+                .data
+                .read_to_end(&mut rez)
+                .unwrap();
+            assert_eq!(&rez, content);
+        }
+
+        let mut mla_fsread =
+            ArchiveFailSafeReader::from_config(dest.as_slice(), ArchiveReaderConfig::new())
+                .unwrap();
+        let mut mla_w = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new())
+            .expect("Writer init failed");
+        let report = mla_fsread
+            .convert_to_archive_with_report(&mut mla_w)
+            .unwrap();
+        for (fname, status) in report.files {
+            assert_eq!(status, FileRecoveryStatus::Recovered, "{}", fname);
+        }
+    }
+
+    #[test]
+    fn store_file_option() {
+        let mut config = ArchiveWriterConfig::new();
+        config.set_layers(Layers::COMPRESS);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        let stored = vec![0u8; UNCOMPRESSED_DATA_SIZE as usize * 2 + 100];
+        let mut options = FileOptions::new();
+        options.set_store(true);
+        mla.add_file_with_options("stored", stored.len() as u64, stored.as_slice(), &options)
+            .unwrap();
+        let compressed = vec![0u8; UNCOMPRESSED_DATA_SIZE as usize * 2];
+        mla.add_file("compressed", compressed.len() as u64, compressed.as_slice())
+            .unwrap();
+        mla.finalize().unwrap();
+        let dest = mla.into_raw();
+
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(dest.as_slice()), ArchiveReaderConfig::new())
+                .unwrap();
+        // The first block starts before the stored content, but the next one
+        // is stored as is, with its kind. The following ones are compressed
+        let compressed_sizes = mla_read.get_info().compressed_sizes.clone().unwrap();
+        assert!(compressed_sizes[0] < UNCOMPRESSED_DATA_SIZE / 2);
+        assert_eq!(compressed_sizes[1], UNCOMPRESSED_DATA_SIZE + 1);
+        assert!(compressed_sizes[3] < UNCOMPRESSED_DATA_SIZE / 2);
+        for (fname, content) in &[("stored", stored), ("compressed", compressed)] {
+            let mut rez = Vec::new();
+            mla_read
+                .get_file(fname.to_string())
+                .unwrap()
+                .unwrap()
+                .data
+                .read_to_end(&mut rez)
+                .unwrap();
+            assert_eq!(&rez, content);
+        }
+    }
+
+    #[test]
+    fn sync_recoverable() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        /// Destination keeping a copy of the data written before the last
+        /// flush, as it would be found after a crash
+        struct FlushedCopy {
+            data: Vec<u8>,
+            flushed: Rc<RefCell<Vec<u8>>>,
+        }
+
+        impl Write for FlushedCopy {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.data.write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                *self.flushed.borrow_mut() = self.data.clone();
+                Ok(())
+            }
+        }
+
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let key = StaticSecret::new(&mut rng);
+        let mut config = ArchiveWriterConfig::new();
+        config.add_public_keys(&[PublicKey::from(&key)]);
+        let flushed = Rc::new(RefCell::new(Vec::new()));
+        let dest = FlushedCopy {
+            data: Vec::new(),
+            flushed: flushed.clone(),
+        };
+        let mut mla = ArchiveWriter::from_config(dest, config).unwrap();
+        let fname1 = "my_file1".to_string();
+        let fname2 = "my_file2".to_string();
+        mla.add_file(&fname1, 3, &[1, 2, 3][..]).unwrap();
+        let id2 = mla.start_file(&fname2).unwrap();
+        mla.append_file_content(id2, 4, &[9, 10, 11, 12][..])
+            .unwrap();
+        mla.sync().unwrap();
+        // Not synced, so lost on crash
+        mla.append_file_content(id2, 2, &[13, 14][..]).unwrap();
+
+        // Everything added before the sync is recovered, despite the
+        // compressed block in progress
+        let synced = flushed.borrow().clone();
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_fsread = ArchiveFailSafeReader::from_config(synced.as_slice(), config).unwrap();
+        let mut config = ArchiveWriterConfig::new();
+        config.set_layers(Layers::DEBUG);
+        let mut mla_w = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        let report = mla_fsread
+            .convert_to_archive_with_report(&mut mla_w)
+            .unwrap();
+        assert_eq!(
+            report.files,
+            vec![
+                (fname1.clone(), FileRecoveryStatus::Recovered),
+                (fname2.clone(), FileRecoveryStatus::Truncated(4)),
+            ]
+        );
+        let mut mla_read = ArchiveReader::new(Cursor::new(mla_w.into_raw())).unwrap();
+        for (fname, content) in &[(fname1, vec![1, 2, 3]), (fname2, vec![9, 10, 11, 12])] {
+            let mut rez = Vec::new();
+            mla_read
+                .get_file(fname.clone())
+                .unwrap()
+                .unwrap()
+                .data
+                .read_to_end(&mut rez)
+                .unwrap();
+            assert_eq!(&rez, content);
+        }
+
+        // Incomplete forward error correction groups cannot be written
+        let mut config = ArchiveWriterConfig::new();
+        config.set_layers(Layers::FEC);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        mla.add_file("my_file", 3, &[1, 2, 3][..]).unwrap();
+        assert!(mla.sync().is_err());
+    }
+
+    #[test]
+    fn get_hash() {
+        // Build an archive with 3 files
+        let (mla, key, files) = build_archive(None, false);
+
+        // Prepare the reader
+        let dest = mla.into_raw();
+        let buf = Cursor::new(dest.as_slice());
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_read = ArchiveReader::from_config(buf, config).unwrap();
+
+        // Get hashes and compare
+        for (filename, content) in files {
+            let hash = mla_read.get_hash(&filename).unwrap().unwrap();
+
+            let mut hasher = Sha256::new();
+            hasher.update(content);
+            let result = hasher.finalize();
+            assert_eq!(result.as_slice(), hash);
+        }
+    }
+
+    #[test]
+    fn get_hashes() {
+        // Build an archive with 3 files
+        let (mla, key, files) = build_archive(None, false);
+
+        // Prepare the reader
+        let dest = mla.into_raw();
+        let buf = Cursor::new(dest.as_slice());
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_read = ArchiveReader::from_config(buf, config).unwrap();
+
+        // Hashes read at once are the ones read one by one
+        let hashes = mla_read.get_hashes().unwrap();
+        assert_eq!(hashes.len(), files.len());
+        for (filename, _content) in files {
+            let hash = mla_read.get_hash(&filename).unwrap().unwrap();
+            assert_eq!(hashes[&filename], hash);
+        }
+    }
+
+    #[test]
+    fn iter_entries() {
+        // Build an archive with 3 interleaved files
+        let (mla, key, files) = build_archive(None, true);
+
+        // Prepare the reader
+        let dest = mla.into_raw();
+        let buf = Cursor::new(dest.as_slice());
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_read = ArchiveReader::from_config(buf, config).unwrap();
+
+        let hashes = mla_read.get_hashes().unwrap();
+        let entries: Vec<EntryInfo> = mla_read.iter_entries().unwrap().collect();
+        assert_eq!(entries.len(), files.len());
+        // In the archive order
+        let names: Vec<&String> = entries.iter().map(|entry| &entry.name).collect();
+        assert_eq!(names, mla_read.list_files_archive_order().unwrap());
+        for entry in entries {
+            let (_, content) = files
+                .iter()
+                .find(|(filename, _)| *filename == entry.name)
+                .unwrap();
+            assert_eq!(entry.size, content.len() as u64);
+            assert_eq!(entry.hash, hashes[&entry.name]);
+            assert_eq!(
+                entry.offsets.first().copied(),
+                mla_read.get_file_offset(&entry.name).unwrap()
+            );
+            assert_eq!(entry.metadata, None);
+            let info = mla_read.get_entry_info(&entry.name).unwrap().unwrap();
+            assert_eq!(info.size, entry.size);
+            assert_eq!(info.hash, entry.hash);
+            assert_eq!(info.offsets, entry.offsets);
+        }
+        assert!(mla_read.get_entry_info("unknown").unwrap().is_none());
+    }
+
+    #[test]
+    fn reader_pool() {
+        // Build an archive with 3 interleaved files
+        let (mla, key, files) = build_archive(None, true);
+
+        let dest = mla.into_raw();
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let pool = ArchiveReaderPool::new(|| Ok(Cursor::new(dest.as_slice())), config);
+
+        // Each file is read by its own thread, at the same time
+        let pool = &pool;
+        std::thread::scope(|scope| {
+            for (filename, content) in &files {
+                scope.spawn(move || {
+                    let mut mla_read = pool.reader().unwrap();
+                    let mut file = mla_read.get_file(filename.clone()).unwrap().unwrap();
+                    let mut rez = Vec::new();
+                    file.data.read_to_end(&mut rez).unwrap();
+                    assert_eq!(&rez, content);
+                });
+            }
+        });
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn open_mmap() {
+        // Build an archive with 3 interleaved files
+        let (mla, key, files) = build_archive(None, true);
+
+        let path = std::env::temp_dir().join(format!("mla-mmap-{}.mla", std::process::id()));
+        std::fs::write(&path, mla.into_raw()).unwrap();
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_read = ArchiveReader::open_mmap(&path, config).unwrap();
+        for (filename, content) in &files {
+            let mut file = mla_read.get_file(filename.clone()).unwrap().unwrap();
+            let mut rez = Vec::new();
+            file.data.read_to_end(&mut rez).unwrap();
+            assert_eq!(&rez, content);
+        }
+        drop(mla_read);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn hash_files() {
+        // Build an archive with 3 interleaved files
+        let (mla, key, files) = build_archive(None, true);
+
+        // Prepare the reader
+        let dest = mla.into_raw();
+        let buf = Cursor::new(dest.as_slice());
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_read = ArchiveReader::from_config(buf, config).unwrap();
+
+        // Computed hashes match the recorded ones
+        let hashes = mla_read.hash_files(HashAlgorithm::Sha256).unwrap();
+        assert_eq!(hashes, mla_read.get_hashes().unwrap());
+
+        // With another algorithm, hashes computed at once are the ones
+        // computed one by one
+        let hashes = mla_read.hash_files(HashAlgorithm::Blake3).unwrap();
+        assert_eq!(hashes.len(), files.len());
+        for (filename, content) in files {
+            let hash = mla_read
+                .hash_file(&filename, HashAlgorithm::Blake3)
+                .unwrap()
+                .unwrap();
+            let mut hasher = FileHasher::new(HashAlgorithm::Blake3);
+            hasher.update(&content);
+            assert_eq!(hash, hasher.finalize());
+            assert_eq!(hashes[&filename], hash);
+        }
+        assert!(mla_read
+            .hash_file("unknown", HashAlgorithm::Blake3)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn read_file_head() {
+        // Build an archive with 3 interleaved files
+        let (mla, key, files) = build_archive(None, true);
+
+        let dest = mla.into_raw();
+        let buf = Cursor::new(dest.as_slice());
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_read = ArchiveReader::from_config(buf, config).unwrap();
+
+        for (filename, content) in files {
+            let head = mla_read.read_file_head(&filename, 2).unwrap().unwrap();
+            assert_eq!(head, &content[..2]);
+            // Shorter files are read entirely
+            let head = mla_read
+                .read_file_head(&filename, content.len() + 10)
+                .unwrap()
+                .unwrap();
+            assert_eq!(head, content);
+        }
+        assert!(mla_read.read_file_head("unknown", 10).unwrap().is_none());
+    }
+
+    #[test]
+    fn read_file_tail() {
+        // Build an archive with 3 interleaved files
+        let (mla, key, files) = build_archive(None, true);
+
+        let dest = mla.into_raw();
+        let buf = Cursor::new(dest.as_slice());
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_read = ArchiveReader::from_config(buf, config).unwrap();
+
+        for (filename, content) in files {
+            let tail = mla_read.read_file_tail(&filename, 2).unwrap().unwrap();
+            assert_eq!(tail, &content[content.len() - 2..]);
+            // Shorter files are read entirely
+            let tail = mla_read
+                .read_file_tail(&filename, content.len() + 10)
+                .unwrap()
+                .unwrap();
+            assert_eq!(tail, content);
+            assert!(mla_read
+                .read_file_tail(&filename, 0)
+                .unwrap()
+                .unwrap()
+                .is_empty());
+        }
+        assert!(mla_read.read_file_tail("unknown", 10).unwrap().is_none());
+    }
+
+    #[test]
+    fn deduplication() {
+        let content1 = (0..=255).collect::<Vec<u8>>();
+        let content2 = vec![42u8; 100];
+        let mut sizes = Vec::new();
+        for deduplication in &[false, true] {
+            let mut config = ArchiveWriterConfig::new();
+            config
+                .set_layers(Layers::EMPTY)
+                .set_deduplication(*deduplication);
+            let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+            let added = std::rc::Rc::new(std::cell::Cell::new(0));
+            let added_callback = added.clone();
+            mla.set_progress_callback(move |count| {
+                added_callback.set(added_callback.get() + count)
+            });
+            for (fname, content) in &[("a", &content1), ("b", &content2), ("c", &content1)] {
+                mla.add_file_seekable(
+                    fname,
+                    content.len() as u64,
+                    Cursor::new(content.as_slice()),
+                    &FileOptions::default(),
+                )
+                .unwrap();
+            }
+            assert!(matches!(
+                mla.add_file_seekable(
+                    "a",
+                    0,
+                    Cursor::new(content1.as_slice()),
+                    &FileOptions::default()
+                ),
+                Err(Error::DuplicateFilename)
+            ));
+            // Shared content is reported as added
+            assert_eq!(
+                added.get(),
+                2 * content1.len() as u64 + content2.len() as u64
+            );
+            assert_eq!(mla.files_info["a"] == mla.files_info["c"], *deduplication);
+            mla.finalize().unwrap();
+            let dest = mla.into_raw();
+            sizes.push(dest.len());
+
+            // Files sharing their content are read as the others
+            let mut mla_read = ArchiveReader::from_config(
+                Cursor::new(dest.as_slice()),
+                ArchiveReaderConfig::new(),
+            )
+            .unwrap();
+            assert_eq!(mla_read.list_files().unwrap().count(), 3);
+            for (fname, content) in &[("a", &content1), ("b", &content2), ("c", &content1)] {
+                let mut file = mla_read.get_file(fname.to_string()).unwrap().unwrap();
+                assert_eq!(file.size, content.len() as u64);
+                let mut rez = Vec::new();
+                file.data.read_to_end(&mut rez).unwrap();
+                assert_eq!(&&rez, content);
+            }
+            assert_eq!(
+                mla_read.get_hash("a").unwrap(),
+                mla_read.get_hash("c").unwrap()
+            );
+        }
+        // The shared content, and its EndOfFile block, are stored once
+        assert!(sizes[1] + content1.len() < sizes[0]);
+    }
+
+    #[test]
+    fn hash_algorithms() {
+        for algorithm in &[
+            HashAlgorithm::Sha256,
+            HashAlgorithm::Sha512,
+            HashAlgorithm::Blake3,
+        ] {
+            let mut config = ArchiveWriterConfig::new();
+            config
+                .set_layers(Layers::COMPRESS)
+                .set_hash_algorithm(*algorithm);
+            let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+            let content = (0..=255).collect::<Vec<u8>>();
+            mla.add_file("my_file", content.len() as u64, content.as_slice())
+                .unwrap();
+            mla.finalize().unwrap();
+            let dest = mla.into_raw();
+
+            // The algorithm is recorded in the header
+            let header = ArchiveHeader::from(&mut dest.as_slice()).unwrap();
+            assert_eq!(header.config.hash_algorithm, *algorithm);
+
+            let mut mla_read = ArchiveReader::from_config(
+                Cursor::new(dest.as_slice()),
+                ArchiveReaderConfig::new(),
+            )
+            .unwrap();
+            assert_eq!(mla_read.get_info().hash_algorithm, *algorithm);
+            let mut hasher = FileHasher::new(*algorithm);
+            hasher.update(&content);
+            let hash = mla_read.get_hash("my_file").unwrap().unwrap();
+            assert_eq!(hash.len(), algorithm.hash_size());
+            assert_eq!(hash, hasher.finalize());
+
+            // The fail-safe reader checks the hash with the same algorithm
+            let mut mla_fsread =
+                ArchiveFailSafeReader::from_config(dest.as_slice(), ArchiveReaderConfig::new())
+                    .unwrap();
+            let mut mla_w = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new())
+                .expect("Writer init failed");
+            let report = mla_fsread
+                .convert_to_archive_with_report(&mut mla_w)
+                .unwrap();
+            for (fname, status) in report.files {
+                assert_eq!(status, FileRecoveryStatus::Recovered, "{}", fname);
+            }
+        }
+    }
+
+    #[test]
+    fn delta() {
+        // "b" is mostly copied from "a", and "c" from "b", as rotated logs
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let mut content_a = vec![0u8; 200 * 1024];
+        rng.fill_bytes(&mut content_a);
+        let mut content_b = vec![0u8; 1000];
+        rng.fill_bytes(&mut content_b);
+        content_b.extend_from_slice(&content_a[..150 * 1024]);
+        let mut content_c = vec![0u8; 3000];
+        rng.fill_bytes(&mut content_c);
+        content_c.extend_from_slice(&content_b);
+        let files = vec![("a", &content_a), ("b", &content_b), ("c", &content_c)];
+
+        for layers in &[Layers::EMPTY, Layers::COMPRESS] {
+            let mut config = ArchiveWriterConfig::new();
+            config.set_layers(*layers);
+            let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+            mla.add_file("a", content_a.len() as u64, content_a.as_slice())
+                .unwrap();
+            mla.add_file_delta(
+                "b",
+                content_b.len() as u64,
+                content_b.as_slice(),
+                "a",
+                content_a.as_slice(),
+            )
+            .unwrap();
+            mla.add_file_delta(
+                "c",
+                content_c.len() as u64,
+                content_c.as_slice(),
+                "b",
+                content_b.as_slice(),
+            )
+            .unwrap();
+            assert!(matches!(
+                mla.add_file_delta("d", 0, std::io::empty(), "unknown", std::io::empty()),
+                Err(Error::BadAPIArgument(_))
+            ));
+            mla.finalize().unwrap();
+            let dest = mla.into_raw();
+            // Random content is not compressed: only copies make it smaller
+            assert!(dest.len() < content_a.len() + 10 * 1024);
+
+            let mut mla_read = ArchiveReader::from_config(
+                Cursor::new(dest.as_slice()),
+                ArchiveReaderConfig::new(),
+            )
+            .unwrap();
+            for (fname, content) in &files {
+                let mut file = mla_read.get_file(fname.to_string()).unwrap().unwrap();
+                assert_eq!(file.size, content.len() as u64);
+                let mut rez = Vec::new();
+                file.data.read_to_end(&mut rez).unwrap();
+                assert_eq!(&&rez, content);
+
+                // Seek in copied content, and back
+                for pos in &[100_000usize, 10, 145_000, 0] {
+                    file.data.seek(SeekFrom::Start(*pos as u64)).unwrap();
+                    let mut rez = [0u8; 5000];
+                    file.data.read_exact(&mut rez).unwrap();
+                    assert_eq!(&rez[..], &content[*pos..*pos + 5000]);
+                }
+
+                let hash = mla_read.get_hash(fname).unwrap().unwrap();
+                assert_eq!(hash, Sha256::digest(content).to_vec());
+            }
+
+            // A repair does not read the copied file again: only the content
+            // before the first copy is recovered
+            let mut mla_fsread =
+                ArchiveFailSafeReader::from_config(dest.as_slice(), ArchiveReaderConfig::new())
+                    .unwrap();
+            let mut mla_w = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new())
+                .expect("Writer init failed");
+            let report = mla_fsread
+                .convert_to_archive_with_report(&mut mla_w)
+                .unwrap();
+            let status: HashMap<String, FileRecoveryStatus> = report.files.into_iter().collect();
+            assert_eq!(status["a"], FileRecoveryStatus::Recovered);
+            assert_eq!(status["b"], FileRecoveryStatus::Truncated(1000));
+            assert_eq!(status["c"], FileRecoveryStatus::Truncated(3000));
+        }
+    }
+
+    #[test]
+    fn file_metadata() {
+        let metadata = FileMetadata {
+            mtime: Some(1_600_000_000),
+            mode: Some(0o640),
+            uid: Some(1000),
+            gid: None,
+            xattrs: vec![(b"user.key".to_vec(), b"value".to_vec())]
+                .into_iter()
+                .collect(),
+            btime: Some(1_500_000_000),
+            attributes: Some(0x22),
+        };
+        let mut options = FileOptions::new();
+        options.set_metadata(metadata.clone());
+
+        // "a" and "b" are interleaved, "c" has no metadata
+        let mut config = ArchiveWriterConfig::new();
+        config.set_layers(Layers::COMPRESS);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        let id_a = mla.start_file_with_options("a", &options).unwrap();
+        mla.append_file_content(id_a, 3, &[1, 2, 3][..]).unwrap();
+        let id_b = mla.start_file_with_options("b", &options).unwrap();
+        mla.append_file_content(id_b, 2, &[4, 5][..]).unwrap();
+        mla.append_file_content(id_a, 2, &[6, 7][..]).unwrap();
+        mla.end_file(id_a).unwrap();
+        mla.end_file(id_b).unwrap();
+        mla.add_file("c", 1, &[8][..]).unwrap();
+        // Only right after the start of the file
+        let id_d = mla.start_file("d").unwrap();
+        mla.append_file_content(id_d, 1, &[9][..]).unwrap();
+        mla.add_file("e", 0, std::io::empty()).unwrap();
+        assert!(matches!(
+            mla.append_file_metadata(id_d, &metadata),
+            Err(Error::WrongWriterState(_))
+        ));
+        mla.end_file(id_d).unwrap();
+        mla.finalize().unwrap();
+        let dest = mla.into_raw();
+
+        let files: Vec<(&str, Vec<u8>, Option<FileMetadata>)> = vec![
+            ("a", vec![1, 2, 3, 6, 7], Some(metadata.clone())),
+            ("b", vec![4, 5], Some(metadata.clone())),
+            ("c", vec![8], None),
+            ("d", vec![9], None),
+        ];
+        let check = |archive: &[u8]| {
+            let mut mla_read =
+                ArchiveReader::from_config(Cursor::new(archive), ArchiveReaderConfig::new())
+                    .unwrap();
+            for (fname, content, expected) in &files {
+                assert_eq!(&mla_read.get_file_metadata(fname).unwrap(), expected);
+                // The content is unchanged
+                let mut file = mla_read.get_file(fname.to_string()).unwrap().unwrap();
+                let mut rez = Vec::new();
+                file.data.read_to_end(&mut rez).unwrap();
+                assert_eq!(&rez, content);
+                file.data.seek(SeekFrom::Start(1)).unwrap();
+                let mut rez = Vec::new();
+                file.data.read_to_end(&mut rez).unwrap();
+                assert_eq!(&rez, &content[1..]);
+            }
+            assert_eq!(mla_read.get_file_metadata("unknown").unwrap(), None);
+            for entry in mla_read.iter_entries().unwrap() {
+                let expected = files
+                    .iter()
+                    .find(|(fname, _, _)| *fname == entry.name)
+                    .map(|(_, _, expected)| expected.clone())
+                    .unwrap_or(None);
+                assert_eq!(entry.metadata, expected);
+            }
+        };
+        check(&dest);
+
+        // Metadata are kept by a copy, and by a repair
+        let mut mla_read =
+            ArchiveReader::from_config(Cursor::new(dest.as_slice()), ArchiveReaderConfig::new())
+                .unwrap();
+        let mut mla_w = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+        helpers::linear_copy(&mut mla_read, &mut mla_w, |fname| Some(fname.to_string())).unwrap();
+        mla_w.finalize().unwrap();
+        check(&mla_w.into_raw());
+
+        let mut mla_fsread =
+            ArchiveFailSafeReader::from_config(dest.as_slice(), ArchiveReaderConfig::new())
+                .unwrap();
+        let mut mla_w = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+        match mla_fsread.convert_to_archive(&mut mla_w).unwrap() {
+            FailSafeReadError::EndOfOriginalArchiveData => {}
+            status => panic!("Unexpected status: {}", status),
+        };
+        check(&mla_w.into_raw());
+
+        // Blocks written before the later fields are still read
+        let mut bytes =
+            bincode::serialize(&(metadata.mtime, metadata.mode, metadata.uid, metadata.gid))
+                .unwrap();
+        assert_eq!(
+            FileMetadata::from_bytes(&bytes).unwrap(),
+            FileMetadata {
+                xattrs: BTreeMap::new(),
+                btime: None,
+                attributes: None,
+                ..metadata.clone()
+            }
+        );
+        bytes.extend(bincode::serialize(&metadata.xattrs).unwrap());
+        assert_eq!(
+            FileMetadata::from_bytes(&bytes).unwrap(),
+            FileMetadata {
+                btime: None,
+                attributes: None,
+                ..metadata
+            }
+        );
+    }
+
+    #[test]
+    fn archive_comment() {
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .set_layers(Layers::COMPRESS)
+            .set_comment(r#"{"case": 42}"#);
+        let mut mla = ArchiveWriter::from_config(Vec::new(), config).unwrap();
+        mla.add_file("a", 1, &[1][..]).unwrap();
+        mla.finalize().unwrap();
+        let dest = mla.into_raw();
+        let mla_read =
+            ArchiveReader::from_config(Cursor::new(dest.as_slice()), ArchiveReaderConfig::new())
+                .unwrap();
+        assert_eq!(mla_read.comment(), Some(r#"{"case": 42}"#));
+
+        // The comment is kept when adding files, unless a new one is given
+        let mut mla = ArchiveWriter::from_archive(
+            Vec::new(),
+            ArchiveWriterConfig::new(),
+            Cursor::new(dest.as_slice()),
+            ArchiveReaderConfig::new(),
+        )
+        .unwrap();
+        mla.finalize().unwrap();
+        let kept = mla.into_raw();
+        let mla_read =
+            ArchiveReader::from_config(Cursor::new(kept.as_slice()), ArchiveReaderConfig::new())
+                .unwrap();
+        assert_eq!(mla_read.comment(), Some(r#"{"case": 42}"#));
+
+        let mut config = ArchiveWriterConfig::new();
+        config.set_comment("case 43");
+        let mut mla = ArchiveWriter::from_archive(
+            Vec::new(),
+            config,
+            Cursor::new(dest.as_slice()),
+            ArchiveReaderConfig::new(),
+        )
+        .unwrap();
+        mla.finalize().unwrap();
+        let replaced = mla.into_raw();
+        let mla_read = ArchiveReader::from_config(
+            Cursor::new(replaced.as_slice()),
+            ArchiveReaderConfig::new(),
+        )
+        .unwrap();
+        assert_eq!(mla_read.comment(), Some("case 43"));
+
+        // No comment by default
+        let mut mla = ArchiveWriter::from_config(Vec::new(), ArchiveWriterConfig::new()).unwrap();
+        mla.finalize().unwrap();
+        let dest = mla.into_raw();
+        let mla_read =
+            ArchiveReader::from_config(Cursor::new(dest.as_slice()), ArchiveReaderConfig::new())
+                .unwrap();
+        assert_eq!(mla_read.comment(), None);
+    }
+
+    fn make_format_regression_files() -> HashMap<String, Vec<u8>> {
+        // Build files easily scriptables and checkable
+        let mut files: HashMap<String, Vec<u8>> = HashMap::new();
+
+        // One simple file
+        let mut simple: Vec<u8> = Vec::new();
+        for i in 0..=255 {
+            simple.push(i);
+        }
+        let pattern = simple.clone();
+        files.insert("simple".to_string(), simple);
+
+        // One big file (10 MB)
+        let big: Vec<u8> = pattern
+            .iter()
+            .cycle()
+            .take(10 * 1024 * 1024)
+            .cloned()
+            .collect();
+        files.insert("big".to_string(), big);
+
+        // Some constant files
+        for i in 0..=255 {
+            files.insert(
+                format!("file_{}", i).to_string(),
+                std::iter::repeat(i).take(0x1000).collect::<Vec<u8>>(),
+            );
+        }
+
+        // sha256 sum of them
+        let mut sha256sum: Vec<u8> = Vec::new();
+        let mut info: Vec<(&String, &Vec<_>)> = files.iter().collect();
+        info.sort_by(|i1, i2| Ord::cmp(&i1.0, &i2.0));
+        for (fname, content) in info.iter() {
+            let mut hasher = Sha256::new();
+            hasher.update(content);
+            sha256sum.extend_from_slice(hex::encode(hasher.finalize()).as_bytes());
+            sha256sum.push(0x20);
+            sha256sum.push(0x20);
+            sha256sum.extend(fname.as_bytes());
+            sha256sum.push(0x0a);
+        }
+        files.insert("sha256sum".to_string(), sha256sum);
+        files
+    }
+
+    /// Password of the committed archive, since format version 2 (its escrow
+    /// key, since format version 4, is `samples/test_x25519_pub.pem`, and its
+    /// hybrid key, since format version 5, is `samples/test_hybrid_pub.pem`).
+    /// Since format version 6, it is encrypted with ChaCha20-Poly1305, and since
+    /// format version 7, with a new key for each chunk. Since format version 8,
+    /// its content is padded, and since format version 9, it is signed with
+    /// `samples/test_ed25519.pem`
+    const FORMAT_REGRESSION_PASSWORD: &[u8] = b"format regression";
+
+    #[test]
+    fn create_archive_format_version() {
+        // Build an archive to be committed, for format regression
+        let file = Vec::new();
+
+        // Use committed keys
+        let pem_pub: &'static [u8] = include_bytes!("../../samples/test_x25519_archive_v1_pub.pem");
+        let pub_key = parse_openssl_25519_pubkey(pem_pub).unwrap();
+        let escrow_pem_pub: &'static [u8] = include_bytes!("../../samples/test_x25519_pub.pem");
+        let escrow_pub_key = parse_openssl_25519_pubkey(escrow_pem_pub).unwrap();
+        let hybrid_pem_pub: &'static [u8] = include_bytes!("../../samples/test_hybrid_pub.pem");
+        let hybrid_pub_key =
+            HybridPublicKey::from_bytes(&pem::parse(hybrid_pem_pub).unwrap().contents).unwrap();
+        let signing_pem: &'static [u8] = include_bytes!("../../samples/test_ed25519.pem");
+        let signing_key = ed25519_dalek::SecretKey::from_bytes(
+            &parse_openssl_ed25519_privkey(signing_pem).unwrap(),
+        )
+        .unwrap();
+
+        let mut config = ArchiveWriterConfig::new();
+        config
+            .set_layers(Layers::default() | Layers::SIGN | Layers::FEC)
+            .add_public_keys(&[pub_key])
+            .add_escrow_keys(&[escrow_pub_key])
+            .add_hybrid_public_keys(&[hybrid_pub_key])
+            .set_password(FORMAT_REGRESSION_PASSWORD)
+            .set_cipher(Cipher::ChaCha20Poly1305)
+            .set_rekey_interval(CHUNK_SIZE)
+            .set_padding(64 * 1024)
+            .set_signature_key(&signing_key);
+        let mut mla = ArchiveWriter::from_config(file, config).expect("Writer init failed");
+
+        let files = make_format_regression_files();
+        // First, add a simple file
+        let fname_simple = "simple".to_string();
+        mla.add_file(
+            &fname_simple,
+            files.get(&fname_simple).unwrap().len() as u64,
+            files.get(&fname_simple).unwrap().as_slice(),
+        )
+        .unwrap();
+
+        // Second, add interleaved files
+        let fnames: Vec<String> = (0..=255).map(|i| format!("file_{}", i)).collect();
+        let mut name2id: HashMap<_, _> = HashMap::new();
+
+        // Start files in normal order
+        (0..=255)
+            .map(|i| {
+                let id = mla.start_file(&fnames[i]).unwrap();
+                name2id.insert(&fnames[i], id);
+            })
+            .for_each(drop);
+
+        // Add some parts in reverse order
+        (0..=255)
+            .rev()
+            .map(|i| {
+                let id = name2id.get(&fnames[i]).unwrap();
+                mla.append_file_content(id.clone(), 32, &files.get(&fnames[i]).unwrap()[..32])
+                    .unwrap();
+            })
+            .for_each(drop);
+
+        // Add the rest of files in normal order
+        (0..=255)
+            .map(|i| {
+                let id = name2id.get(&fnames[i]).unwrap();
+                let data = &files.get(&fnames[i]).unwrap()[32..];
+                mla.append_file_content(id.clone(), data.len() as u64, data)
+                    .unwrap();
+            })
+            .for_each(drop);
+
+        // Finish files in reverse order
+        (0..=255)
+            .rev()
+            .map(|i| {
+                let id = name2id.get(&fnames[i]).unwrap();
+                mla.end_file(id.clone()).unwrap();
+            })
+            .for_each(drop);
+
+        // Add a big file
+        let fname_big = "big".to_string();
+        mla.add_file(
+            &fname_big,
+            files.get(&fname_big).unwrap().len() as u64,
+            files.get(&fname_big).unwrap().as_slice(),
+        )
+        .unwrap();
+
+        // Add sha256sum file
+        let fname_sha256sum = "sha256sum".to_string();
+        mla.add_file(
+            &fname_sha256sum,
+            files.get(&fname_sha256sum).unwrap().len() as u64,
+            files.get(&fname_sha256sum).unwrap().as_slice(),
+        )
+        .unwrap();
+        mla.finalize().unwrap();
+
+        // UNCOMMENT THESE LINES TO UPDATE THE FILE
+        // UPDATE THE VERSION NUMBER
+        /*
+        std::fs::File::create(std::path::Path::new(&format!(
+            "../samples/archive_v{}.mla",
+            MLA_FORMAT_VERSION
+        )))
+        .unwrap()
+        .write(&mla.into_raw())
+        .unwrap();
+         */
+    }
+
+    /// Check the committed archive `mla_data`, read with `new_config`
+    fn check_archive_format(mla_data: &'static [u8], new_config: impl Fn() -> ArchiveReaderConfig) {
+        let files = make_format_regression_files();
+
+        // Build Reader
+        let buf = Cursor::new(mla_data);
+        let mut mla_read = ArchiveReader::from_config(buf, new_config()).unwrap();
+
+        // Build FailSafeReader
+        let mut mla_fsread = ArchiveFailSafeReader::from_config(mla_data, new_config()).unwrap();
+
+        // Repair the archive (without any damage, but trigger the corresponding code)
+        let dest_w = Vec::new();
+        let mut mla_w = ArchiveWriter::from_config(dest_w, ArchiveWriterConfig::new())
+            .expect("Writer init failed");
+        if let FailSafeReadError::EndOfOriginalArchiveData =
+            mla_fsread.convert_to_archive(&mut mla_w).unwrap()
+        {
+            // Everything runs as expected
+        } else {
+            panic!();
+        }
+        // Get a reader on the repaired archive
+        let buf2 = Cursor::new(mla_w.into_raw());
+        let mut mla_repread = ArchiveReader::from_config(buf2, ArchiveReaderConfig::new()).unwrap();
+
+        assert_eq!(files.len(), mla_read.list_files().unwrap().count());
+        assert_eq!(files.len(), mla_repread.list_files().unwrap().count());
+
+        // Get and check file per file
+        for (fname, content) in files.iter() {
+            let mut mla_file = mla_read.get_file(fname.clone()).unwrap().unwrap();
+            let mut mla_rep_file = mla_repread.get_file(fname.clone()).unwrap().unwrap();
+            assert_eq!(mla_file.filename, fname.clone());
+            assert_eq!(mla_rep_file.filename, fname.clone());
+            let mut buf = Vec::new();
+            mla_file.data.read_to_end(&mut buf).unwrap();
+            assert_eq!(buf.as_slice(), content.as_slice());
+            let mut buf = Vec::new();
+            mla_rep_file.data.read_to_end(&mut buf).unwrap();
+            assert_eq!(buf.as_slice(), content.as_slice());
+        }
+    }
+
+    #[test]
+    fn check_archive_format_v1() {
+        let pem_priv: &'static [u8] = include_bytes!("../../samples/test_x25519_archive_v1.pem");
+        let mla_data: &'static [u8] = include_bytes!("../../samples/archive_v1.mla");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(&[parse_openssl_25519_privkey(pem_priv).unwrap()]);
+            config
+        });
+    }
+
+    #[test]
+    fn check_archive_format_v2() {
+        let pem_priv: &'static [u8] = include_bytes!("../../samples/test_x25519_archive_v1.pem");
+        let mla_data: &'static [u8] = include_bytes!("../../samples/archive_v2.mla");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(&[parse_openssl_25519_privkey(pem_priv).unwrap()]);
+            config
+        });
+        // The archive is also protected by a password
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.set_password(FORMAT_REGRESSION_PASSWORD);
+            config
+        });
+    }
+
+    #[test]
+    fn check_archive_format_v3() {
+        let pem_priv: &'static [u8] = include_bytes!("../../samples/test_x25519_archive_v1.pem");
+        let mla_data: &'static [u8] = include_bytes!("../../samples/archive_v3.mla");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(&[parse_openssl_25519_privkey(pem_priv).unwrap()]);
+            config
+        });
+        // The archive is also protected by a password
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.set_password(FORMAT_REGRESSION_PASSWORD);
+            config
+        });
+    }
+
+    #[test]
+    fn check_archive_format_v4() {
+        let pem_priv: &'static [u8] = include_bytes!("../../samples/test_x25519_archive_v1.pem");
+        let mla_data: &'static [u8] = include_bytes!("../../samples/archive_v4.mla");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(&[parse_openssl_25519_privkey(pem_priv).unwrap()]);
+            config
+        });
+        // The archive is also protected by a password
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.set_password(FORMAT_REGRESSION_PASSWORD);
+            config
+        });
+        // And by an escrow key
+        let escrow_pem_priv: &'static [u8] = include_bytes!("../../samples/test_x25519.pem");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(&[parse_openssl_25519_privkey(escrow_pem_priv).unwrap()]);
+            config
+        });
+    }
+
+    #[test]
+    fn check_archive_format_v5() {
+        let pem_priv: &'static [u8] = include_bytes!("../../samples/test_x25519_archive_v1.pem");
+        let mla_data: &'static [u8] = include_bytes!("../../samples/archive_v5.mla");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(&[parse_openssl_25519_privkey(pem_priv).unwrap()]);
+            config
+        });
+        // The archive is also protected by a password
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.set_password(FORMAT_REGRESSION_PASSWORD);
+            config
+        });
+        // And by an escrow key
+        let escrow_pem_priv: &'static [u8] = include_bytes!("../../samples/test_x25519.pem");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(&[parse_openssl_25519_privkey(escrow_pem_priv).unwrap()]);
+            config
+        });
+        // And by a hybrid key
+        let hybrid_pem_priv: &'static [u8] = include_bytes!("../../samples/test_hybrid.pem");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_hybrid_private_keys(&[HybridPrivateKey::from_bytes(
+                &pem::parse(hybrid_pem_priv).unwrap().contents,
+            )
+            .unwrap()]);
+            config
+        });
+    }
+
+    #[test]
+    fn check_archive_format_v6() {
+        let pem_priv: &'static [u8] = include_bytes!("../../samples/test_x25519_archive_v1.pem");
+        let mla_data: &'static [u8] = include_bytes!("../../samples/archive_v6.mla");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(&[parse_openssl_25519_privkey(pem_priv).unwrap()]);
+            config
+        });
+        // The archive is also protected by a password
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.set_password(FORMAT_REGRESSION_PASSWORD);
+            config
+        });
+        // And by an escrow key
+        let escrow_pem_priv: &'static [u8] = include_bytes!("../../samples/test_x25519.pem");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(&[parse_openssl_25519_privkey(escrow_pem_priv).unwrap()]);
+            config
+        });
+        // And by a hybrid key
+        let hybrid_pem_priv: &'static [u8] = include_bytes!("../../samples/test_hybrid.pem");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_hybrid_private_keys(&[HybridPrivateKey::from_bytes(
+                &pem::parse(hybrid_pem_priv).unwrap().contents,
+            )
+            .unwrap()]);
+            config
+        });
+    }
+
+    #[test]
+    fn check_archive_format_v7() {
+        let pem_priv: &'static [u8] = include_bytes!("../../samples/test_x25519_archive_v1.pem");
+        let mla_data: &'static [u8] = include_bytes!("../../samples/archive_v7.mla");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(&[parse_openssl_25519_privkey(pem_priv).unwrap()]);
+            config
+        });
+        // The archive is also protected by a password
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.set_password(FORMAT_REGRESSION_PASSWORD);
+            config
+        });
+        // And by an escrow key
+        let escrow_pem_priv: &'static [u8] = include_bytes!("../../samples/test_x25519.pem");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(&[parse_openssl_25519_privkey(escrow_pem_priv).unwrap()]);
+            config
+        });
+        // And by a hybrid key
+        let hybrid_pem_priv: &'static [u8] = include_bytes!("../../samples/test_hybrid.pem");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_hybrid_private_keys(&[HybridPrivateKey::from_bytes(
+                &pem::parse(hybrid_pem_priv).unwrap().contents,
+            )
+            .unwrap()]);
+            config
+        });
+    }
+
+    #[test]
+    fn check_archive_format_v8() {
+        let pem_priv: &'static [u8] = include_bytes!("../../samples/test_x25519_archive_v1.pem");
+        let mla_data: &'static [u8] = include_bytes!("../../samples/archive_v8.mla");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(&[parse_openssl_25519_privkey(pem_priv).unwrap()]);
+            config
+        });
+        // The archive is also protected by a password
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.set_password(FORMAT_REGRESSION_PASSWORD);
+            config
+        });
+        // And by an escrow key
+        let escrow_pem_priv: &'static [u8] = include_bytes!("../../samples/test_x25519.pem");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(&[parse_openssl_25519_privkey(escrow_pem_priv).unwrap()]);
+            config
+        });
+        // And by a hybrid key
+        let hybrid_pem_priv: &'static [u8] = include_bytes!("../../samples/test_hybrid.pem");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_hybrid_private_keys(&[HybridPrivateKey::from_bytes(
+                &pem::parse(hybrid_pem_priv).unwrap().contents,
+            )
+            .unwrap()]);
+            config
+        });
+    }
+
+    #[test]
+    fn check_archive_format_v9() {
+        let pem_priv: &'static [u8] = include_bytes!("../../samples/test_x25519_archive_v1.pem");
+        let mla_data: &'static [u8] = include_bytes!("../../samples/archive_v9.mla");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(&[parse_openssl_25519_privkey(pem_priv).unwrap()]);
+            config
+        });
+        // The archive is also protected by a password
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.set_password(FORMAT_REGRESSION_PASSWORD);
+            config
+        });
+        // And by an escrow key
+        let escrow_pem_priv: &'static [u8] = include_bytes!("../../samples/test_x25519.pem");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(&[parse_openssl_25519_privkey(escrow_pem_priv).unwrap()]);
+            config
+        });
+        // And by a hybrid key
+        let hybrid_pem_priv: &'static [u8] = include_bytes!("../../samples/test_hybrid.pem");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_hybrid_private_keys(&[HybridPrivateKey::from_bytes(
+                &pem::parse(hybrid_pem_priv).unwrap().contents,
+            )
+            .unwrap()]);
+            config
+        });
+        // The archive is signed
+        let signing_pem_pub: &'static [u8] = include_bytes!("../../samples/test_ed25519_pub.pem");
+        let signer = ed25519_dalek::PublicKey::from_bytes(
+            &parse_openssl_ed25519_pubkey(signing_pem_pub).unwrap(),
+        )
+        .unwrap();
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config
+                .set_password(FORMAT_REGRESSION_PASSWORD)
+                .add_trusted_public_keys(&[signer]);
+            config
+        });
+    }
+
+    #[test]
+    fn check_archive_format_v10() {
+        let pem_priv: &'static [u8] = include_bytes!("../../samples/test_x25519_archive_v1.pem");
+        let mla_data: &'static [u8] = include_bytes!("../../samples/archive_v10.mla");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(&[parse_openssl_25519_privkey(pem_priv).unwrap()]);
+            config
+        });
+        // The archive is also protected by a password
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.set_password(FORMAT_REGRESSION_PASSWORD);
+            config
+        });
+        // And by an escrow key
+        let escrow_pem_priv: &'static [u8] = include_bytes!("../../samples/test_x25519.pem");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(&[parse_openssl_25519_privkey(escrow_pem_priv).unwrap()]);
+            config
+        });
+        // And by a hybrid key
+        let hybrid_pem_priv: &'static [u8] = include_bytes!("../../samples/test_hybrid.pem");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_hybrid_private_keys(&[HybridPrivateKey::from_bytes(
+                &pem::parse(hybrid_pem_priv).unwrap().contents,
+            )
+            .unwrap()]);
+            config
+        });
+        // The archive is signed
+        let signing_pem_pub: &'static [u8] = include_bytes!("../../samples/test_ed25519_pub.pem");
+        let signer = ed25519_dalek::PublicKey::from_bytes(
+            &parse_openssl_ed25519_pubkey(signing_pem_pub).unwrap(),
+        )
+        .unwrap();
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config
+                .set_password(FORMAT_REGRESSION_PASSWORD)
+                .add_trusted_public_keys(&[signer]);
+            config
+        });
+    }
+
+    #[test]
+    fn check_archive_format_v11() {
+        let pem_priv: &'static [u8] = include_bytes!("../../samples/test_x25519_archive_v1.pem");
+        let mla_data: &'static [u8] = include_bytes!("../../samples/archive_v11.mla");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(&[parse_openssl_25519_privkey(pem_priv).unwrap()]);
+            config
+        });
+        // The archive is also protected by a password
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.set_password(FORMAT_REGRESSION_PASSWORD);
+            config
+        });
+        // And by an escrow key
+        let escrow_pem_priv: &'static [u8] = include_bytes!("../../samples/test_x25519.pem");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(&[parse_openssl_25519_privkey(escrow_pem_priv).unwrap()]);
+            config
+        });
+        // And by a hybrid key
+        let hybrid_pem_priv: &'static [u8] = include_bytes!("../../samples/test_hybrid.pem");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_hybrid_private_keys(&[HybridPrivateKey::from_bytes(
+                &pem::parse(hybrid_pem_priv).unwrap().contents,
+            )
+            .unwrap()]);
+            config
+        });
+        // The archive is signed
+        let signing_pem_pub: &'static [u8] = include_bytes!("../../samples/test_ed25519_pub.pem");
+        let signer = ed25519_dalek::PublicKey::from_bytes(
+            &parse_openssl_ed25519_pubkey(signing_pem_pub).unwrap(),
+        )
+        .unwrap();
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config
+                .set_password(FORMAT_REGRESSION_PASSWORD)
+                .add_trusted_public_keys(&[signer]);
+            config
+        });
+    }
+
+    #[test]
+    fn check_archive_format_v12() {
+        let pem_priv: &'static [u8] = include_bytes!("../../samples/test_x25519_archive_v1.pem");
+        let mla_data: &'static [u8] = include_bytes!("../../samples/archive_v12.mla");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(&[parse_openssl_25519_privkey(pem_priv).unwrap()]);
+            config
+        });
+        // The archive is also protected by a password
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.set_password(FORMAT_REGRESSION_PASSWORD);
+            config
+        });
+        // And by an escrow key
+        let escrow_pem_priv: &'static [u8] = include_bytes!("../../samples/test_x25519.pem");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(&[parse_openssl_25519_privkey(escrow_pem_priv).unwrap()]);
+            config
+        });
+        // And by a hybrid key
+        let hybrid_pem_priv: &'static [u8] = include_bytes!("../../samples/test_hybrid.pem");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_hybrid_private_keys(&[HybridPrivateKey::from_bytes(
+                &pem::parse(hybrid_pem_priv).unwrap().contents,
+            )
+            .unwrap()]);
+            config
+        });
+        // The archive is signed
+        let signing_pem_pub: &'static [u8] = include_bytes!("../../samples/test_ed25519_pub.pem");
+        let signer = ed25519_dalek::PublicKey::from_bytes(
+            &parse_openssl_ed25519_pubkey(signing_pem_pub).unwrap(),
+        )
+        .unwrap();
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config
+                .set_password(FORMAT_REGRESSION_PASSWORD)
+                .add_trusted_public_keys(&[signer]);
+            config
+        });
+    }
+
+    #[test]
+    fn check_archive_format_v13() {
+        let pem_priv: &'static [u8] = include_bytes!("../../samples/test_x25519_archive_v1.pem");
+        let mla_data: &'static [u8] = include_bytes!("../../samples/archive_v13.mla");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(&[parse_openssl_25519_privkey(pem_priv).unwrap()]);
+            config
+        });
+        // The archive is also protected by a password
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.set_password(FORMAT_REGRESSION_PASSWORD);
+            config
+        });
+        // And by an escrow key
+        let escrow_pem_priv: &'static [u8] = include_bytes!("../../samples/test_x25519.pem");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(&[parse_openssl_25519_privkey(escrow_pem_priv).unwrap()]);
+            config
+        });
+        // And by a hybrid key
+        let hybrid_pem_priv: &'static [u8] = include_bytes!("../../samples/test_hybrid.pem");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_hybrid_private_keys(&[HybridPrivateKey::from_bytes(
+                &pem::parse(hybrid_pem_priv).unwrap().contents,
+            )
+            .unwrap()]);
+            config
+        });
+        // The archive is signed
+        let signing_pem_pub: &'static [u8] = include_bytes!("../../samples/test_ed25519_pub.pem");
+        let signer = ed25519_dalek::PublicKey::from_bytes(
+            &parse_openssl_ed25519_pubkey(signing_pem_pub).unwrap(),
+        )
+        .unwrap();
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config
+                .set_password(FORMAT_REGRESSION_PASSWORD)
+                .add_trusted_public_keys(&[signer]);
+            config
+        });
+    }
+
+    #[test]
+    fn check_archive_format_v14() {
+        let pem_priv: &'static [u8] = include_bytes!("../../samples/test_x25519_archive_v1.pem");
+        let mla_data: &'static [u8] = include_bytes!("../../samples/archive_v14.mla");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(&[parse_openssl_25519_privkey(pem_priv).unwrap()]);
+            config
+        });
+        // The archive is also protected by a password
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.set_password(FORMAT_REGRESSION_PASSWORD);
+            config
+        });
+        // And by an escrow key
+        let escrow_pem_priv: &'static [u8] = include_bytes!("../../samples/test_x25519.pem");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(&[parse_openssl_25519_privkey(escrow_pem_priv).unwrap()]);
+            config
+        });
+        // And by a hybrid key
+        let hybrid_pem_priv: &'static [u8] = include_bytes!("../../samples/test_hybrid.pem");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_hybrid_private_keys(&[HybridPrivateKey::from_bytes(
+                &pem::parse(hybrid_pem_priv).unwrap().contents,
+            )
+            .unwrap()]);
+            config
+        });
+        // The archive is signed
+        let signing_pem_pub: &'static [u8] = include_bytes!("../../samples/test_ed25519_pub.pem");
+        let signer = ed25519_dalek::PublicKey::from_bytes(
+            &parse_openssl_ed25519_pubkey(signing_pem_pub).unwrap(),
+        )
+        .unwrap();
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config
+                .set_password(FORMAT_REGRESSION_PASSWORD)
+                .add_trusted_public_keys(&[signer]);
+            config
+        });
+    }
+
+    #[test]
+    fn check_archive_format_v15() {
+        let pem_priv: &'static [u8] = include_bytes!("../../samples/test_x25519_archive_v1.pem");
+        let mla_data: &'static [u8] = include_bytes!("../../samples/archive_v15.mla");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(&[parse_openssl_25519_privkey(pem_priv).unwrap()]);
+            config
+        });
+        // The archive is also protected by a password
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.set_password(FORMAT_REGRESSION_PASSWORD);
+            config
+        });
+        // And by an escrow key
+        let escrow_pem_priv: &'static [u8] = include_bytes!("../../samples/test_x25519.pem");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(&[parse_openssl_25519_privkey(escrow_pem_priv).unwrap()]);
+            config
+        });
+        // And by a hybrid key
+        let hybrid_pem_priv: &'static [u8] = include_bytes!("../../samples/test_hybrid.pem");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_hybrid_private_keys(&[HybridPrivateKey::from_bytes(
+                &pem::parse(hybrid_pem_priv).unwrap().contents,
+            )
+            .unwrap()]);
+            config
+        });
+        // The archive is signed
+        let signing_pem_pub: &'static [u8] = include_bytes!("../../samples/test_ed25519_pub.pem");
+        let signer = ed25519_dalek::PublicKey::from_bytes(
+            &parse_openssl_ed25519_pubkey(signing_pem_pub).unwrap(),
+        )
+        .unwrap();
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config
+                .set_password(FORMAT_REGRESSION_PASSWORD)
+                .add_trusted_public_keys(&[signer]);
+            config
+        });
+    }
+
+    #[test]
+    fn check_archive_format_v16() {
+        let pem_priv: &'static [u8] = include_bytes!("../../samples/test_x25519_archive_v1.pem");
+        let mla_data: &'static [u8] = include_bytes!("../../samples/archive_v16.mla");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(&[parse_openssl_25519_privkey(pem_priv).unwrap()]);
+            config
+        });
+        // The archive is also protected by a password
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.set_password(FORMAT_REGRESSION_PASSWORD);
+            config
+        });
+        // And by an escrow key
+        let escrow_pem_priv: &'static [u8] = include_bytes!("../../samples/test_x25519.pem");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(&[parse_openssl_25519_privkey(escrow_pem_priv).unwrap()]);
+            config
+        });
+        // And by a hybrid key
+        let hybrid_pem_priv: &'static [u8] = include_bytes!("../../samples/test_hybrid.pem");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_hybrid_private_keys(&[HybridPrivateKey::from_bytes(
+                &pem::parse(hybrid_pem_priv).unwrap().contents,
+            )
+            .unwrap()]);
+            config
+        });
+        // The archive is signed
+        let signing_pem_pub: &'static [u8] = include_bytes!("../../samples/test_ed25519_pub.pem");
+        let signer = ed25519_dalek::PublicKey::from_bytes(
+            &parse_openssl_ed25519_pubkey(signing_pem_pub).unwrap(),
+        )
+        .unwrap();
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config
+                .set_password(FORMAT_REGRESSION_PASSWORD)
+                .add_trusted_public_keys(&[signer]);
+            config
+        });
+    }
+
+    #[test]
+    fn check_archive_format_v17() {
+        let pem_priv: &'static [u8] = include_bytes!("../../samples/test_x25519_archive_v1.pem");
+        let mla_data: &'static [u8] = include_bytes!("../../samples/archive_v17.mla");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(&[parse_openssl_25519_privkey(pem_priv).unwrap()]);
+            config
+        });
+        // The archive is also protected by a password
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.set_password(FORMAT_REGRESSION_PASSWORD);
+            config
+        });
+        // And by an escrow key
+        let escrow_pem_priv: &'static [u8] = include_bytes!("../../samples/test_x25519.pem");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(&[parse_openssl_25519_privkey(escrow_pem_priv).unwrap()]);
+            config
+        });
+        // And by a hybrid key
+        let hybrid_pem_priv: &'static [u8] = include_bytes!("../../samples/test_hybrid.pem");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_hybrid_private_keys(&[HybridPrivateKey::from_bytes(
+                &pem::parse(hybrid_pem_priv).unwrap().contents,
+            )
+            .unwrap()]);
+            config
+        });
+        // The archive is signed
+        let signing_pem_pub: &'static [u8] = include_bytes!("../../samples/test_ed25519_pub.pem");
+        let signer = ed25519_dalek::PublicKey::from_bytes(
+            &parse_openssl_ed25519_pubkey(signing_pem_pub).unwrap(),
+        )
+        .unwrap();
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config
+                .set_password(FORMAT_REGRESSION_PASSWORD)
+                .add_trusted_public_keys(&[signer]);
+            config
+        });
+    }
+
+    #[test]
+    fn check_archive_format_v18() {
+        let pem_priv: &'static [u8] = include_bytes!("../../samples/test_x25519_archive_v1.pem");
+        let mla_data: &'static [u8] = include_bytes!("../../samples/archive_v18.mla");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(&[parse_openssl_25519_privkey(pem_priv).unwrap()]);
+            config
+        });
+        // The archive is also protected by a password
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.set_password(FORMAT_REGRESSION_PASSWORD);
+            config
+        });
+        // And by an escrow key
+        let escrow_pem_priv: &'static [u8] = include_bytes!("../../samples/test_x25519.pem");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(&[parse_openssl_25519_privkey(escrow_pem_priv).unwrap()]);
+            config
+        });
+        // And by a hybrid key
+        let hybrid_pem_priv: &'static [u8] = include_bytes!("../../samples/test_hybrid.pem");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_hybrid_private_keys(&[HybridPrivateKey::from_bytes(
+                &pem::parse(hybrid_pem_priv).unwrap().contents,
+            )
+            .unwrap()]);
+            config
+        });
+        // The archive is signed
+        let signing_pem_pub: &'static [u8] = include_bytes!("../../samples/test_ed25519_pub.pem");
+        let signer = ed25519_dalek::PublicKey::from_bytes(
+            &parse_openssl_ed25519_pubkey(signing_pem_pub).unwrap(),
+        )
+        .unwrap();
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config
+                .set_password(FORMAT_REGRESSION_PASSWORD)
+                .add_trusted_public_keys(&[signer]);
+            config
+        });
+    }
+
+    #[test]
+    fn check_archive_format_v19() {
+        let pem_priv: &'static [u8] = include_bytes!("../../samples/test_x25519_archive_v1.pem");
+        let mla_data: &'static [u8] = include_bytes!("../../samples/archive_v19.mla");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(&[parse_openssl_25519_privkey(pem_priv).unwrap()]);
+            config
+        });
+        // The archive is also protected by a password
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.set_password(FORMAT_REGRESSION_PASSWORD);
+            config
+        });
+        // And by an escrow key
+        let escrow_pem_priv: &'static [u8] = include_bytes!("../../samples/test_x25519.pem");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_private_keys(&[parse_openssl_25519_privkey(escrow_pem_priv).unwrap()]);
+            config
+        });
+        // And by a hybrid key
+        let hybrid_pem_priv: &'static [u8] = include_bytes!("../../samples/test_hybrid.pem");
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config.add_hybrid_private_keys(&[HybridPrivateKey::from_bytes(
+                &pem::parse(hybrid_pem_priv).unwrap().contents,
+            )
+            .unwrap()]);
+            config
+        });
+        // The archive is signed
+        let signing_pem_pub: &'static [u8] = include_bytes!("../../samples/test_ed25519_pub.pem");
+        let signer = ed25519_dalek::PublicKey::from_bytes(
+            &parse_openssl_ed25519_pubkey(signing_pem_pub).unwrap(),
+        )
+        .unwrap();
+        check_archive_format(mla_data, || {
+            let mut config = ArchiveReaderConfig::new();
+            config
+                .set_password(FORMAT_REGRESSION_PASSWORD)
+                .add_trusted_public_keys(&[signer]);
+            config
+        });
+    }
+
     #[test]
     fn empty_blocks() {
         // Add a file with containning an empty block - it should works
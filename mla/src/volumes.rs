@@ -0,0 +1,374 @@
+//! Multi-volume support: an archive can be split into several volumes, for
+//! instance to fit on size-limited medias
+//!
+//! Each volume starts with a `VolumeHeader`, followed by the corresponding
+//! slice of the archive:
+//! ```ascii-art
+//! [MLV magic][format version][set ID][volume index][volumes count][length][archive slice]
+//! ```
+//! The set ID, randomly generated on split, ties volumes together, avoiding
+//! mixing volumes from different splits.
+
+use std::convert::TryFrom;
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaChaRng;
+
+use crate::Error;
+
+const VOLUME_MAGIC: &[u8; 3] = b"MLV";
+const VOLUME_FORMAT_VERSION: u32 = 1;
+const SET_ID_SIZE: usize = 16;
+/// Size of a serialized `VolumeHeader`
+const VOLUME_HEADER_SIZE: u64 = 3 + 4 + SET_ID_SIZE as u64 + 4 + 4 + 8;
+
+pub struct VolumeHeader {
+    /// Identifier shared by all the volumes of a split
+    pub set_id: [u8; SET_ID_SIZE],
+    /// Position of the volume in the set, starting at 0
+    pub index: u32,
+    /// Number of volumes in the set
+    pub count: u32,
+    /// Length of the archive slice contained in this volume
+    pub length: u64,
+}
+
+impl VolumeHeader {
+    pub fn from<T: Read>(src: &mut T) -> Result<Self, Error> {
+        let mut buf = vec![00u8; VOLUME_MAGIC.len()];
+        src.read_exact(buf.as_mut_slice())?;
+        if buf != VOLUME_MAGIC {
+            return Err(Error::WrongMagic);
+        }
+        let format_version = src.read_u32::<LittleEndian>()?;
+        if format_version != VOLUME_FORMAT_VERSION {
+            return Err(Error::UnsupportedVersion);
+        }
+        let mut set_id = [0u8; SET_ID_SIZE];
+        src.read_exact(&mut set_id)?;
+        let index = src.read_u32::<LittleEndian>()?;
+        let count = src.read_u32::<LittleEndian>()?;
+        let length = src.read_u64::<LittleEndian>()?;
+        Ok(VolumeHeader {
+            set_id,
+            index,
+            count,
+            length,
+        })
+    }
+
+    fn dump<T: Write>(&self, dest: &mut T) -> Result<(), Error> {
+        dest.write_all(VOLUME_MAGIC)?;
+        dest.write_u32::<LittleEndian>(VOLUME_FORMAT_VERSION)?;
+        dest.write_all(&self.set_id)?;
+        dest.write_u32::<LittleEndian>(self.index)?;
+        dest.write_u32::<LittleEndian>(self.count)?;
+        dest.write_u64::<LittleEndian>(self.length)?;
+        Ok(())
+    }
+}
+
+/// Return true if `src` starts with a volume header
+///
+/// The position of `src` is restored afterward
+pub fn is_volume<R: Read + Seek>(src: &mut R) -> Result<bool, Error> {
+    let pos = src.stream_position()?;
+    let mut buf = vec![00u8; VOLUME_MAGIC.len()];
+    let is_volume = match src.read_exact(buf.as_mut_slice()) {
+        Ok(()) => buf == VOLUME_MAGIC,
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => false,
+        Err(err) => return Err(err.into()),
+    };
+    src.seek(SeekFrom::Start(pos))?;
+    Ok(is_volume)
+}
+
+/// Split `src` in volumes of at most `volume_size` bytes, headers included
+///
+/// `new_volume` is called with the index of each volume, starting at 0, and
+/// the number of volumes. It returns the Write object receiving this volume.
+/// The number of volumes is returned.
+pub fn split<R: Read + Seek, W: Write, F: FnMut(u32, u32) -> Result<W, Error>>(
+    mut src: R,
+    volume_size: u64,
+    mut new_volume: F,
+) -> Result<u32, Error> {
+    if volume_size <= VOLUME_HEADER_SIZE {
+        return Err(Error::BadAPIArgument(format!(
+            "Volumes must be bigger than their header ({} bytes)",
+            VOLUME_HEADER_SIZE
+        )));
+    }
+    let slice_size = volume_size - VOLUME_HEADER_SIZE;
+    let total_size = src.seek(SeekFrom::End(0))?;
+    src.seek(SeekFrom::Start(0))?;
+
+    // Always produce at least one volume
+    let count = std::cmp::max(1, total_size.div_ceil(slice_size));
+    let count =
+        u32::try_from(count).map_err(|_| Error::BadAPIArgument("Too many volumes".to_string()))?;
+
+    let mut set_id = [0u8; SET_ID_SIZE];
+    ChaChaRng::from_entropy().fill_bytes(&mut set_id);
+
+    for index in 0..count {
+        let offset = index as u64 * slice_size;
+        let length = std::cmp::min(slice_size, total_size - offset);
+        let mut dest = new_volume(index, count)?;
+        VolumeHeader {
+            set_id,
+            index,
+            count,
+            length,
+        }
+        .dump(&mut dest)?;
+        let copied = io::copy(&mut (&mut src).take(length), &mut dest)?;
+        if copied != length {
+            return Err(Error::IOError(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Source ended before its expected size",
+            )));
+        }
+        dest.flush()?;
+    }
+    Ok(count)
+}
+
+/// Provides a Read + Seek interface on a volume set, as if it were the
+/// original archive
+///
+/// This allows a volume set to be directly opened with an `ArchiveReader`.
+pub struct VolumesReader<R: Read + Seek> {
+    /// Volumes, ordered by index
+    volumes: Vec<R>,
+    /// Offset, in the original archive, of each volume's slice
+    offsets: Vec<u64>,
+    /// Size of the original archive
+    total_size: u64,
+    /// Current position in the original archive
+    pos: u64,
+}
+
+impl<R: Read + Seek> VolumesReader<R> {
+    /// Open a volume set. `volumes` can be given in any order, but the set
+    /// must be complete, and each volume must hold the length announced by
+    /// its header
+    pub fn new(volumes: Vec<R>) -> Result<Self, Error> {
+        let mut indexed = Vec::with_capacity(volumes.len());
+        for mut volume in volumes {
+            volume.seek(SeekFrom::Start(0))?;
+            let header = VolumeHeader::from(&mut volume)?;
+            let size = volume.seek(SeekFrom::End(0))?;
+            if Some(size) != VOLUME_HEADER_SIZE.checked_add(header.length) {
+                return Err(Error::BadAPIArgument(format!(
+                    "Volume {} is {} bytes long, instead of {}",
+                    header.index,
+                    size,
+                    VOLUME_HEADER_SIZE.saturating_add(header.length)
+                )));
+            }
+            indexed.push((header, volume));
+        }
+        indexed.sort_by_key(|(header, _)| header.index);
+
+        let (set_id, count) = match indexed.first() {
+            Some((header, _)) => (header.set_id, header.count),
+            None => {
+                return Err(Error::BadAPIArgument(
+                    "At least one volume is required".to_string(),
+                ))
+            }
+        };
+        let mut offsets = Vec::with_capacity(indexed.len());
+        let mut total_size = 0;
+        let mut volumes = Vec::with_capacity(indexed.len());
+        for (expected_index, (header, volume)) in indexed.into_iter().enumerate() {
+            if header.set_id != set_id || header.count != count {
+                return Err(Error::BadAPIArgument(
+                    "Volumes belong to different sets".to_string(),
+                ));
+            }
+            if header.index as usize != expected_index {
+                return Err(Error::BadAPIArgument(format!(
+                    "Missing or duplicated volume {}",
+                    expected_index
+                )));
+            }
+            offsets.push(total_size);
+            total_size += header.length;
+            volumes.push(volume);
+        }
+        if volumes.len() != count as usize {
+            return Err(Error::BadAPIArgument(format!(
+                "Missing volumes, {} out of {} given",
+                volumes.len(),
+                count
+            )));
+        }
+
+        Ok(VolumesReader {
+            volumes,
+            offsets,
+            total_size,
+            pos: 0,
+        })
+    }
+}
+
+impl<R: Read + Seek> Read for VolumesReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.total_size {
+            return Ok(0);
+        }
+        // Find the volume holding the current position
+        let index = match self.offsets.binary_search(&self.pos) {
+            Ok(index) => index,
+            Err(index) => index - 1,
+        };
+        let end = match self.offsets.get(index + 1) {
+            Some(next_offset) => *next_offset,
+            None => self.total_size,
+        };
+        if end == self.pos {
+            // Empty volume, only possible for an empty archive
+            return Ok(0);
+        }
+
+        let volume = &mut self.volumes[index];
+        volume.seek(SeekFrom::Start(
+            VOLUME_HEADER_SIZE + self.pos - self.offsets[index],
+        ))?;
+        let max = std::cmp::min(buf.len() as u64, end - self.pos) as usize;
+        let count = volume.read(&mut buf[..max])?;
+        self.pos += count as u64;
+        Ok(count)
+    }
+}
+
+impl<R: Read + Seek> Seek for VolumesReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(pos) => pos as i128,
+            SeekFrom::Current(delta) => self.pos as i128 + delta as i128,
+            SeekFrom::End(delta) => self.total_size as i128 + delta as i128,
+        };
+        if new_pos < 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Seek before the start of the volume set",
+            ));
+        }
+        self.pos = new_pos as u64;
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::build_archive;
+    use crate::*;
+    use std::cell::RefCell;
+    use std::io::Cursor;
+    use std::rc::Rc;
+
+    /// Write to a buffer shared with the test
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn split_in_memory(data: &[u8], volume_size: u64) -> Vec<Vec<u8>> {
+        let mut volumes = Vec::new();
+        split(Cursor::new(data), volume_size, |index, _count| {
+            assert_eq!(index as usize, volumes.len());
+            let volume = Rc::new(RefCell::new(Vec::new()));
+            volumes.push(volume.clone());
+            Ok(SharedBuffer(volume))
+        })
+        .unwrap();
+        volumes.into_iter().map(|v| v.borrow().clone()).collect()
+    }
+
+    #[test]
+    fn split_and_read() {
+        let (mla, key, files) = build_archive(None, false);
+        let data = mla.into_raw();
+
+        let volume_size = VOLUME_HEADER_SIZE + 100;
+        let mut volumes = split_in_memory(&data, volume_size);
+        let expected_count = data.len().div_ceil(100);
+        assert_eq!(volumes.len(), expected_count);
+        for volume in &volumes {
+            assert!(volume.len() as u64 <= volume_size);
+        }
+
+        // Volumes can be given in any order
+        volumes.reverse();
+        let mut reader =
+            VolumesReader::new(volumes.iter().map(|v| Cursor::new(v.as_slice())).collect())
+                .unwrap();
+        let mut joined = Vec::new();
+        reader.read_to_end(&mut joined).unwrap();
+        assert_eq!(joined, data);
+
+        // The volume set can be directly opened as an archive
+        let mut config = ArchiveReaderConfig::new();
+        config.add_private_keys(std::slice::from_ref(&key));
+        let mut mla_read = ArchiveReader::from_config(reader, config).unwrap();
+        for (fname, content) in &files {
+            let mut rez = Vec::new();
+            mla_read
+                .get_file(fname.to_string())
+                .unwrap()
+                .unwrap()
+                .data
+                .read_to_end(&mut rez)
+                .unwrap();
+            assert_eq!(&rez, content);
+        }
+    }
+
+    #[test]
+    fn incomplete_or_mixed_set() {
+        let (mla, _key, _files) = build_archive(None, false);
+        let data = mla.into_raw();
+        let volumes = split_in_memory(&data, VOLUME_HEADER_SIZE + 100);
+        let others = split_in_memory(&data, VOLUME_HEADER_SIZE + 100);
+
+        // Missing volume
+        let reader = VolumesReader::new(
+            volumes[1..]
+                .iter()
+                .map(|v| Cursor::new(v.as_slice()))
+                .collect(),
+        );
+        assert!(reader.is_err());
+
+        // Truncated volume
+        let mut truncated: Vec<Cursor<&[u8]>> =
+            volumes.iter().map(|v| Cursor::new(v.as_slice())).collect();
+        truncated[1] = Cursor::new(&volumes[1][..volumes[1].len() - 1]);
+        assert!(VolumesReader::new(truncated).is_err());
+
+        // Volume from another split
+        let mut mixed: Vec<Cursor<&[u8]>> =
+            volumes.iter().map(|v| Cursor::new(v.as_slice())).collect();
+        mixed[0] = Cursor::new(others[0].as_slice());
+        assert!(VolumesReader::new(mixed).is_err());
+
+        // Not a volume
+        assert!(is_volume(&mut Cursor::new(&volumes[0])).unwrap());
+        assert!(!is_volume(&mut Cursor::new(&data)).unwrap());
+    }
+}
@@ -0,0 +1,82 @@
+//! Optional configuration file, providing defaults for command-line arguments
+//!
+//! Example:
+//! ```toml
+//! public_keys = ["/etc/mlar/archive.pub", "/etc/mlar/client1.pub"]
+//! escrow_public_keys = ["/etc/mlar/recovery.pub"]
+//! layers = ["compress", "encrypt"]
+//! compression_level = 5
+//! output_dir = "/srv/collect"
+//! ```
+
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    /// Public keys used when no `-p` argument is given
+    pub public_keys: Option<Vec<PathBuf>>,
+    /// Escrow public keys, always used in addition to `--escrow-pubkey` ones
+    pub escrow_public_keys: Option<Vec<PathBuf>>,
+    /// Layers used when no `-l` argument is given
+    pub layers: Option<Vec<String>>,
+    /// Compression level used when no `-q` argument is given
+    pub compression_level: Option<u32>,
+    /// Directory in which relative output paths are resolved
+    pub output_dir: Option<PathBuf>,
+}
+
+static CONFIG: OnceLock<ConfigFile> = OnceLock::new();
+
+/// Default configuration file path, `$XDG_CONFIG_HOME/mlar/config.toml` or
+/// `~/.config/mlar/config.toml`
+fn default_path() -> Option<PathBuf> {
+    let config_home = match env::var_os("XDG_CONFIG_HOME") {
+        Some(config_home) if !config_home.is_empty() => PathBuf::from(config_home),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_home.join("mlar").join("config.toml"))
+}
+
+/// Load the configuration from `path`, or from the default path if any
+///
+/// A missing default configuration file is not an error, unlike an explicit
+/// `path`
+pub fn load(path: Option<&Path>) {
+    let (path, explicit) = match path {
+        Some(path) => (path.to_path_buf(), true),
+        None => match default_path() {
+            Some(path) => (path, false),
+            None => return,
+        },
+    };
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(_) if !explicit && !path.exists() => return,
+        Err(err) => panic!(
+            "[ERROR] Unable to read the configuration file {} ({})",
+            path.display(),
+            err
+        ),
+    };
+    let config: ConfigFile = match toml::from_str(&content) {
+        Ok(config) => config,
+        Err(err) => panic!(
+            "[ERROR] Invalid configuration file {} ({})",
+            path.display(),
+            err
+        ),
+    };
+    if CONFIG.set(config).is_err() {
+        panic!("[ERROR] Configuration loaded twice");
+    }
+}
+
+/// Return the loaded configuration, which is empty if there is none
+pub fn get() -> &'static ConfigFile {
+    CONFIG.get_or_init(ConfigFile::default)
+}
@@ -0,0 +1,83 @@
+//! Detection of the type of a file from its first bytes (magic numbers)
+//!
+//! Only well-known and unambiguous signatures are recognized, as a hint for
+//! triage; `file` or libmagic remain the reference
+
+/// Number of bytes needed by `detect_type`, up to the `tar` signature
+pub const DETECT_SIZE: usize = 512;
+
+/// Magic number, at a given offset, and the corresponding MIME type
+///
+/// More specific signatures come first
+const SIGNATURES: &[(usize, &[u8], &str)] = &[
+    (0, b"\x7fELF", "application/x-elf"),
+    (0, b"MZ", "application/vnd.microsoft.portable-executable"),
+    (0, b"\xfe\xed\xfa\xce", "application/x-mach-binary"),
+    (0, b"\xfe\xed\xfa\xcf", "application/x-mach-binary"),
+    (0, b"\xce\xfa\xed\xfe", "application/x-mach-binary"),
+    (0, b"\xcf\xfa\xed\xfe", "application/x-mach-binary"),
+    (0, b"%PDF-", "application/pdf"),
+    (0, b"PK\x03\x04", "application/zip"),
+    (0, b"PK\x05\x06", "application/zip"),
+    (0, b"\x1f\x8b", "application/gzip"),
+    (0, b"BZh", "application/x-bzip2"),
+    (0, b"\xfd7zXZ\x00", "application/x-xz"),
+    (0, b"\x28\xb5\x2f\xfd", "application/zstd"),
+    (0, b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+    (0, b"Rar!\x1a\x07", "application/vnd.rar"),
+    (257, b"ustar", "application/x-tar"),
+    (0, b"MLA", "application/x-mla"),
+    (0, b"SQLite format 3\x00", "application/vnd.sqlite3"),
+    (
+        0,
+        b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1",
+        "application/x-ole-storage",
+    ),
+    (0, b"\x89PNG\r\n\x1a\n", "image/png"),
+    (0, b"\xff\xd8\xff", "image/jpeg"),
+    (0, b"GIF87a", "image/gif"),
+    (0, b"GIF89a", "image/gif"),
+    (0, b"\x00\x00\x01\x00", "image/vnd.microsoft.icon"),
+    (0, b"OggS", "audio/ogg"),
+    (0, b"ID3", "audio/mpeg"),
+    (0, b"\x1aE\xdf\xa3", "video/webm"),
+    (0, b"{\\rtf", "application/rtf"),
+    (0, b"<?xml", "text/xml"),
+    (0, b"#!", "text/x-script"),
+];
+
+/// MIME type of a file starting with `head`, of up to `DETECT_SIZE` bytes
+///
+/// Files without a known signature are reported as `text/plain` if `head` is
+/// valid UTF-8 without control characters, `application/octet-stream`
+/// otherwise
+pub fn detect_type(head: &[u8]) -> &'static str {
+    if head.is_empty() {
+        return "application/x-empty";
+    }
+    for (offset, magic, mime) in SIGNATURES {
+        if head.len() >= offset + magic.len() && &head[*offset..offset + magic.len()] == *magic {
+            return mime;
+        }
+    }
+    if is_text(head) {
+        "text/plain"
+    } else {
+        "application/octet-stream"
+    }
+}
+
+fn is_text(head: &[u8]) -> bool {
+    let text = match std::str::from_utf8(head) {
+        Ok(text) => text,
+        // `head` may end in the middle of a character
+        Err(err) if err.error_len().is_none() => {
+            // Safe to unwrap, as valid up to this point
+            std::str::from_utf8(&head[..err.valid_up_to()]).unwrap()
+        }
+        Err(_) => return false,
+    };
+    !text
+        .chars()
+        .any(|c| c.is_control() && !matches!(c, '\n' | '\r' | '\t' | '\x0c'))
+}
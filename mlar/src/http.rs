@@ -0,0 +1,156 @@
+//! Reading of archives over HTTP(S), through Range requests
+//!
+//! The archive is fetched by blocks of `BLOCK_SIZE` bytes, only when needed,
+//! and the last `CACHE_BLOCKS` blocks are kept in memory. Listing an archive,
+//! or extracting some of its files, then only downloads the footer, the
+//! index and the blocks of these files
+
+use log::debug;
+use mla::errors::Error;
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::time::Duration;
+
+/// Prefixes of the inputs read over HTTP
+const HTTP_PREFIXES: &[&str] = &["http://", "https://"];
+
+/// Amount of data fetched by each request
+const BLOCK_SIZE: u64 = 1024 * 1024;
+/// Number of blocks kept in memory
+const CACHE_BLOCKS: usize = 64;
+const HTTP_TIMEOUT: Duration = Duration::from_secs(60);
+
+fn http_error(msg: &str) -> io::Error {
+    io::Error::other(format!("HTTP: {}", msg))
+}
+
+/// Return true if `input` is to be read over HTTP
+pub fn is_url(input: &str) -> bool {
+    HTTP_PREFIXES.iter().any(|prefix| input.starts_with(prefix))
+}
+
+/// Remote file, read and seeked through HTTP Range requests
+pub struct HttpReader {
+    agent: ureq::Agent,
+    url: String,
+    /// Size of the remote file
+    size: u64,
+    /// Current position
+    pos: u64,
+    /// Blocks fetched, by index
+    cache: HashMap<u64, Vec<u8>>,
+    /// Indexes of the blocks in `cache`, the least recently used first
+    lru: VecDeque<u64>,
+}
+
+impl HttpReader {
+    /// Open the file at `url`, whose server must support Range requests
+    pub fn open(url: &str) -> Result<Self, Error> {
+        let mut reader = HttpReader {
+            agent: ureq::AgentBuilder::new().timeout(HTTP_TIMEOUT).build(),
+            url: url.to_string(),
+            size: 0,
+            pos: 0,
+            cache: HashMap::new(),
+            lru: VecDeque::new(),
+        };
+        // The first block gives the size of the file
+        let (block, size) = reader.fetch(0)?;
+        reader.size = size;
+        reader.insert(0, block);
+        Ok(reader)
+    }
+
+    /// Fetch the block `index`, returning it with the size of the file
+    fn fetch(&self, index: u64) -> io::Result<(Vec<u8>, u64)> {
+        let start = index * BLOCK_SIZE;
+        let end = start + BLOCK_SIZE - 1;
+        debug!("Fetching bytes {}-{} of {}", start, end, self.url);
+        let response = match self
+            .agent
+            .get(&self.url)
+            .set("Range", &format!("bytes={}-{}", start, end))
+            .call()
+        {
+            Ok(response) => response,
+            Err(ureq::Error::Status(code, _)) => {
+                return Err(http_error(&format!("the server answered with {}", code)))
+            }
+            Err(err) => return Err(http_error(&err.to_string())),
+        };
+        if response.status() != 206 {
+            return Err(http_error("the server does not support Range requests"));
+        }
+        // Content-Range: bytes <start>-<end>/<size>
+        let size = response
+            .header("Content-Range")
+            .and_then(|range| range.rsplit_once('/'))
+            .and_then(|(_, size)| size.parse::<u64>().ok())
+            .ok_or_else(|| http_error("invalid Content-Range"))?;
+        let mut block = Vec::with_capacity(BLOCK_SIZE as usize);
+        response
+            .into_reader()
+            .take(BLOCK_SIZE)
+            .read_to_end(&mut block)?;
+        Ok((block, size))
+    }
+
+    fn insert(&mut self, index: u64, block: Vec<u8>) {
+        if self.lru.len() == CACHE_BLOCKS {
+            if let Some(oldest) = self.lru.pop_front() {
+                self.cache.remove(&oldest);
+            }
+        }
+        self.cache.insert(index, block);
+        self.lru.push_back(index);
+    }
+
+    /// Block `index`, fetched if not cached
+    fn block(&mut self, index: u64) -> io::Result<&[u8]> {
+        if self.cache.contains_key(&index) {
+            self.lru.retain(|cached| *cached != index);
+            self.lru.push_back(index);
+        } else {
+            let (block, _size) = self.fetch(index)?;
+            self.insert(index, block);
+        }
+        Ok(&self.cache[&index])
+    }
+}
+
+impl Read for HttpReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.size || buf.is_empty() {
+            return Ok(0);
+        }
+        let offset = (self.pos % BLOCK_SIZE) as usize;
+        let block = self.block(self.pos / BLOCK_SIZE)?;
+        if offset >= block.len() {
+            return Err(http_error("truncated response"));
+        }
+        let count = std::cmp::min(buf.len(), block.len() - offset);
+        buf[..count].copy_from_slice(&block[offset..offset + count]);
+        self.pos += count as u64;
+        Ok(count)
+    }
+}
+
+impl Seek for HttpReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::End(offset) => self.size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+        match pos {
+            Some(pos) => {
+                self.pos = pos;
+                Ok(pos)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative position",
+            )),
+        }
+    }
+}
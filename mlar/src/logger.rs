@@ -0,0 +1,65 @@
+//! Logger writing mlar messages on stderr, as text or as JSON lines
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use serde::Serialize;
+use std::io::{self, Write};
+
+struct Logger {
+    json: bool,
+}
+
+/// Log line of `--log-json`
+#[derive(Serialize)]
+struct JsonRecord {
+    level: &'static str,
+    message: String,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        // Only warnings and errors of dependencies are of interest
+        metadata.level() <= log::max_level()
+            && (metadata.level() <= Level::Warn || metadata.target().starts_with("mlar"))
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = if self.json {
+            let record = JsonRecord {
+                level: match record.level() {
+                    Level::Error => "error",
+                    Level::Warn => "warning",
+                    Level::Info => "info",
+                    Level::Debug => "debug",
+                    Level::Trace => "trace",
+                },
+                message: record.args().to_string(),
+            };
+            serde_json::to_string(&record).expect("Unable to serialize the log record")
+        } else {
+            match record.level() {
+                // Informational messages, such as processed file names, are
+                // printed as-is
+                Level::Info => record.args().to_string(),
+                Level::Error => format!("[ERROR] {}", record.args()),
+                Level::Warn => format!("[WARNING] {}", record.args()),
+                Level::Debug => format!("[DEBUG] {}", record.args()),
+                Level::Trace => format!("[TRACE] {}", record.args()),
+            }
+        };
+        // Ignore failures, as there is no other place to report them
+        let _ = writeln!(io::stderr(), "{}", line);
+    }
+
+    fn flush(&self) {
+        let _ = io::stderr().flush();
+    }
+}
+
+/// Install the logger, showing messages up to `level`
+pub fn init(level: LevelFilter, json: bool) {
+    log::set_boxed_logger(Box::new(Logger { json })).expect("Logger already initialized");
+    log::set_max_level(level);
+}
@@ -1,28 +1,73 @@
-use clap::{App, Arg, ArgMatches, SubCommand};
+use clap::{App, Arg, ArgMatches, SubCommand, Values};
 use curve25519_parser::{
-    generate_keypair, parse_openssl_25519_privkey, parse_openssl_25519_pubkey,
+    generate_ed25519_keypair, generate_keypair, parse_openssl_25519_privkey,
+    parse_openssl_25519_privkey_encrypted, parse_openssl_25519_pubkey,
+    parse_openssl_ed25519_privkey, parse_openssl_ed25519_pubkey, Curve25519ParserError, KeyPair,
 };
-use glob::Pattern;
+use glob::{MatchOptions, Pattern};
 use humansize::{file_size_opts, FileSize};
-use mla::config::{ArchiveReaderConfig, ArchiveWriterConfig};
-use mla::errors::{Error, FailSafeReadError};
-use mla::helpers::linear_extract;
-use mla::layers::compress::CompressionLayerReader;
-use mla::layers::encrypt::EncryptionLayerReader;
-use mla::layers::raw::RawLayerReader;
-use mla::layers::traits::LayerReader;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{debug, error, info, warn, LevelFilter};
+use mla::config::{ArchiveReaderConfig, ArchiveWriterConfig, CheckpointInterval};
+use mla::crypto::derivation::derive_private_key;
+use mla::crypto::hash::{FileHasher, HashAlgorithm};
+use mla::crypto::hybrid::{HybridPrivateKey, HybridPublicKey};
+use mla::errors::{ConfigError, Error, FailSafeReadError};
+use mla::helpers::{linear_copy, linear_extract_with_progress};
+use mla::layers::compress::{CompressionAlgorithm, UNCOMPRESSED_DATA_SIZE};
+use mla::layers::encrypt::{Cipher, CHUNK_SIZE};
+use mla::volumes::{is_volume, split as split_volumes, VolumeHeader, VolumesReader};
 use mla::{
-    ArchiveFailSafeReader, ArchiveFile, ArchiveFooter, ArchiveHeader, ArchiveReader, ArchiveWriter,
+    ArchiveFailSafeReader, ArchiveFile, ArchiveHeader, ArchiveReader, ArchiveReaderPool,
+    ArchiveStreamReader, ArchiveWriter, EntryInfo, FileMetadata, FileOptions, FileRecoveryStatus,
     Layers,
 };
 use rand::SeedableRng;
 use rand_chacha::ChaChaRng;
-use std::collections::{HashMap, HashSet};
+use regex::{Regex, RegexBuilder};
+use serde::Serialize;
+use sha2::{Digest, Sha256, Sha512};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::env;
+use std::ffi::{OsStr, OsString};
 use std::fs::{self, File};
 use std::io;
-use std::io::{Read, Seek, SeekFrom, Write};
+use std::io::{BufRead, Read, Seek, SeekFrom, Write};
 use std::path::{Component, Path, PathBuf};
-use tar::{Builder, Header};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tar::{Archive, Builder, EntryType, Header};
+use zip::write::FileOptions as ZipFileOptions;
+use zip::{AesMode, ZipArchive, ZipWriter};
+
+mod config_file;
+mod detect;
+#[cfg(feature = "http")]
+mod http;
+mod logger;
+#[cfg(feature = "mount")]
+mod mount;
+#[cfg(feature = "s3")]
+mod s3;
+mod serve;
+mod timestamp;
+#[cfg(all(target_os = "linux", feature = "uring"))]
+mod uring;
+
+// PEM tags of hybrid (X25519 + ML-KEM-768) keys
+const HYBRID_PRIVATE_TAG: &str = "MLA HYBRID PRIVATE KEY";
+const HYBRID_PUBLIC_TAG: &str = "MLA HYBRID PUBLIC KEY";
+
+// PEM tag of detached signatures
+const SIGNATURE_TAG: &str = "MLA SIGNATURE";
+// Ed25519ph context of detached signatures, distinct from the signature layer
+// one
+const DETACHED_SIGNATURE_CONTEXT: &[u8] = b"MLA DETACHED SIGNATURE";
 
 // ----- Utils ------
 
@@ -31,7 +76,32 @@ use tar::{Builder, Header};
 /// can't coexist in the same code path.
 enum OutputTypes {
     Stdout,
-    File { file: File },
+    File {
+        file: File,
+    },
+    #[cfg(feature = "s3")]
+    S3 {
+        upload: s3::MultipartUpload,
+    },
+    #[cfg(all(target_os = "linux", feature = "uring"))]
+    Uring {
+        writer: uring::UringWriter,
+    },
+}
+
+impl OutputTypes {
+    /// Complete the output, once everything is written
+    fn finish(self) -> Result<(), Error> {
+        #[cfg(feature = "s3")]
+        if let OutputTypes::S3 { upload } = self {
+            return upload.complete();
+        }
+        #[cfg(all(target_os = "linux", feature = "uring"))]
+        if let OutputTypes::Uring { mut writer } = self {
+            writer.flush()?;
+        }
+        Ok(())
+    }
 }
 
 impl Write for OutputTypes {
@@ -39,6 +109,10 @@ impl Write for OutputTypes {
         match self {
             OutputTypes::Stdout => io::stdout().write(buf),
             OutputTypes::File { file } => file.write(buf),
+            #[cfg(feature = "s3")]
+            OutputTypes::S3 { upload } => upload.write(buf),
+            #[cfg(all(target_os = "linux", feature = "uring"))]
+            OutputTypes::Uring { writer } => writer.write(buf),
         }
     }
 
@@ -46,45 +120,249 @@ impl Write for OutputTypes {
         match self {
             OutputTypes::Stdout => io::stdout().flush(),
             OutputTypes::File { file } => file.flush(),
+            #[cfg(feature = "s3")]
+            OutputTypes::S3 { upload } => upload.flush(),
+            #[cfg(all(target_os = "linux", feature = "uring"))]
+            OutputTypes::Uring { writer } => writer.flush(),
+        }
+    }
+}
+
+/// Environment variables holding a private or a public key
+const PRIVATE_KEY_ENV: &str = "MLAR_PRIVATE_KEY";
+const PUBLIC_KEY_ENV: &str = "MLAR_PUBLIC_KEY";
+/// Environment variable holding the archive password
+const PASSWORD_ENV: &str = "MLAR_PASSWORD";
+
+/// Password, once given or prompted, as it is used both to read and to write
+/// by some commands
+static PASSWORD: OnceLock<String> = OnceLock::new();
+
+/// Return true if keys are given through `path_arg`, `fd_arg` or `env_var`
+fn keys_given(matches: &ArgMatches, path_arg: &str, fd_arg: &str, env_var: &str) -> bool {
+    matches.is_present(path_arg) || matches.is_present(fd_arg) || env::var_os(env_var).is_some()
+}
+
+#[cfg(unix)]
+fn read_fd(fd: &str) -> Result<Vec<u8>, Error> {
+    use std::os::unix::io::FromRawFd;
+
+    let fd: i32 = match fd.parse() {
+        Ok(fd) => fd,
+        Err(_) => panic!("[ERROR] Invalid file descriptor {}", fd),
+    };
+    // The descriptor is inherited from the caller and only used here, so
+    // taking its ownership (and closing it once read) is expected
+    let mut file = unsafe { File::from_raw_fd(fd) };
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+#[cfg(not(unix))]
+fn read_fd(_fd: &str) -> Result<Vec<u8>, Error> {
+    panic!("[ERROR] Keys can be read from file descriptors only on Unix systems");
+}
+
+/// Read the keys given as paths in `path_arg`, inherited file descriptors in
+/// `fd_arg`, and in the `env_var` environment variable
+fn read_keys(
+    matches: &ArgMatches,
+    path_arg: &str,
+    fd_arg: &str,
+    env_var: &str,
+) -> Result<Vec<Vec<u8>>, Error> {
+    let mut keys = Vec::new();
+    if let Some(paths) = matches.values_of_os(path_arg) {
+        for path in paths {
+            keys.push(fs::read(path)?);
+        }
+    }
+    if let Some(fds) = matches.values_of(fd_arg) {
+        for fd in fds {
+            keys.push(read_fd(fd)?);
+        }
+    }
+    if let Some(key) = env::var_os(env_var) {
+        match key.into_string() {
+            Ok(key) => keys.push(key.into_bytes()),
+            Err(_) => panic!("[ERROR] {} must be valid UTF-8", env_var),
+        }
+    }
+    Ok(keys)
+}
+
+/// Return true if a password is given through `--password` or `MLAR_PASSWORD`
+fn password_given(matches: &ArgMatches) -> bool {
+    matches.is_present("password") || env::var_os(PASSWORD_ENV).is_some()
+}
+
+/// Get the password given with `--password=<PASSWORD>`, in `MLAR_PASSWORD`,
+/// or prompt for it on `--password`. A prompted password is asked twice if
+/// `confirm` is set
+fn get_password(matches: &ArgMatches, confirm: bool) -> &'static str {
+    PASSWORD.get_or_init(|| {
+        if let Some(password) = matches.value_of("password") {
+            return password.to_string();
+        }
+        if !matches.is_present("password") {
+            match env::var(PASSWORD_ENV) {
+                Ok(password) => return password,
+                Err(_) => panic!("[ERROR] {} must be valid UTF-8", PASSWORD_ENV),
+            }
         }
+        let password = match rpassword::prompt_password("Password: ") {
+            Ok(password) => password,
+            Err(err) => panic!("[ERROR] Unable to read the password ({})", err),
+        };
+        if confirm {
+            match rpassword::prompt_password("Confirm password: ") {
+                Ok(confirmation) if confirmation == password => {}
+                Ok(_) => panic!("[ERROR] Passwords do not match"),
+                Err(err) => panic!("[ERROR] Unable to read the password ({})", err),
+            }
+        }
+        password
+    })
+}
+
+/// Get the passphrase of private keys, from `--key-passphrase-file` or else
+/// prompted for
+fn get_key_passphrase(matches: &ArgMatches) -> Result<String, Error> {
+    if let Some(passphrase_file) = matches.value_of("key_passphrase_file") {
+        return read_password_file(passphrase_file);
+    }
+    match rpassword::prompt_password("Private key passphrase: ") {
+        Ok(passphrase) => Ok(passphrase),
+        Err(err) => panic!("[ERROR] Unable to read the passphrase ({})", err),
     }
 }
 
-fn open_ecc_private_keys(matches: &ArgMatches) -> Result<Vec<x25519_dalek::StaticSecret>, Error> {
+/// Return the content of the PEM block tagged with `tag` in `data`, if any
+fn find_pem_block(data: &[u8], tag: &str) -> Option<Vec<u8>> {
+    pem::parse_many(data)
+        .into_iter()
+        .find(|block| block.tag == tag)
+        .map(|block| block.contents)
+}
+
+/// Open private keys given as arguments, both X25519 and hybrid ones
+fn open_ecc_private_keys(
+    matches: &ArgMatches,
+) -> Result<(Vec<x25519_dalek::StaticSecret>, Vec<HybridPrivateKey>), Error> {
     let mut private_keys = Vec::new();
-    if let Some(private_key_args) = matches.values_of_os("private_keys") {
-        for private_key_arg in private_key_args {
-            let mut file = File::open(private_key_arg)?;
-            // Load the the ECC key in-memory and parse it
-            let mut buf = Vec::new();
-            file.read_to_end(&mut buf)?;
-            match parse_openssl_25519_privkey(&buf) {
-                Err(_) => return Err(Error::InvalidECCKeyFormat),
-                Ok(private_key) => private_keys.push(private_key),
-            };
+    let mut hybrid_keys = Vec::new();
+    // Only get the passphrase once, for the first encrypted key
+    let mut passphrase = None;
+    for buf in read_keys(matches, "private_keys", "private_key_fd", PRIVATE_KEY_ENV)? {
+        if let Some(contents) = find_pem_block(&buf, HYBRID_PRIVATE_TAG) {
+            hybrid_keys.push(HybridPrivateKey::from_bytes(&contents)?);
+            continue;
         }
-    };
-    Ok(private_keys)
+        // Parse the ECC key loaded in-memory
+        let private_key = match parse_openssl_25519_privkey(&buf) {
+            Err(Curve25519ParserError::EncryptedPrivateKey) => {
+                if passphrase.is_none() {
+                    passphrase = Some(get_key_passphrase(matches)?);
+                }
+                // Safe to use unwrap() because of the is_none() test
+                let passphrase = passphrase.as_ref().unwrap();
+                parse_openssl_25519_privkey_encrypted(&buf, passphrase.as_bytes())
+            }
+            parsed => parsed,
+        };
+        match private_key {
+            Err(_) => return Err(Error::InvalidECCKeyFormat),
+            Ok(private_key) => private_keys.push(private_key),
+        };
+    }
+    Ok((private_keys, hybrid_keys))
 }
 
-fn open_ecc_public_keys(matches: &ArgMatches) -> Result<Vec<x25519_dalek::PublicKey>, Error> {
+/// Open public keys given as arguments, or else from the configuration file,
+/// both X25519 and hybrid ones
+fn open_ecc_public_keys(
+    matches: &ArgMatches,
+) -> Result<(Vec<x25519_dalek::PublicKey>, Vec<HybridPublicKey>), Error> {
+    let keys = if keys_given(matches, "public_keys", "public_key_fd", PUBLIC_KEY_ENV) {
+        read_keys(matches, "public_keys", "public_key_fd", PUBLIC_KEY_ENV)?
+    } else {
+        let mut keys = Vec::new();
+        if let Some(paths) = &config_file::get().public_keys {
+            for path in paths {
+                keys.push(fs::read(path)?);
+            }
+        }
+        keys
+    };
     let mut public_keys = Vec::new();
-    if let Some(public_key_args) = matches.values_of_os("public_keys") {
-        for public_key_arg in public_key_args {
-            let mut file = File::open(public_key_arg)?;
-            // Load the the ECC key in-memory and parse it
-            let mut buf = Vec::new();
-            file.read_to_end(&mut buf)?;
-            match parse_openssl_25519_pubkey(&buf) {
-                Err(_) => return Err(Error::InvalidECCKeyFormat),
-                Ok(public_key) => public_keys.push(public_key),
-            };
+    let mut hybrid_keys = Vec::new();
+    for buf in keys {
+        if let Some(contents) = find_pem_block(&buf, HYBRID_PUBLIC_TAG) {
+            hybrid_keys.push(HybridPublicKey::from_bytes(&contents)?);
+            continue;
+        }
+        // Parse the ECC key loaded in-memory
+        match parse_openssl_25519_pubkey(&buf) {
+            Err(_) => return Err(Error::InvalidECCKeyFormat),
+            Ok(public_key) => public_keys.push(public_key),
+        };
+    }
+    Ok((public_keys, hybrid_keys))
+}
+
+/// Open escrow public keys, given as arguments and from the configuration file
+///
+/// Unlike other public keys, both are used, so an organization can enforce its
+/// recovery key through the configuration file
+fn open_ecc_escrow_keys(matches: &ArgMatches) -> Result<Vec<x25519_dalek::PublicKey>, Error> {
+    let mut keys = Vec::new();
+    if let Some(paths) = &config_file::get().escrow_public_keys {
+        for path in paths {
+            keys.push(fs::read(path)?);
+        }
+    }
+    if let Some(paths) = matches.values_of_os("escrow_public_keys") {
+        for path in paths {
+            keys.push(fs::read(path)?);
+        }
+    }
+    let mut escrow_keys = Vec::new();
+    for buf in keys {
+        match parse_openssl_25519_pubkey(&buf) {
+            Err(_) => return Err(Error::InvalidECCKeyFormat),
+            Ok(public_key) => escrow_keys.push(public_key),
+        };
+    }
+    Ok(escrow_keys)
+}
+
+/// Open the Ed25519 private key used to sign the archive
+fn open_signing_key(path: &OsStr) -> Result<ed25519_dalek::SecretKey, Error> {
+    let buf = fs::read(path)?;
+    match parse_openssl_ed25519_privkey(&buf) {
+        // Safe to use unwrap() because the seed has the expected length
+        Ok(seed) => Ok(ed25519_dalek::SecretKey::from_bytes(&seed).unwrap()),
+        Err(_) => Err(Error::InvalidECCKeyFormat),
+    }
+}
+
+/// Open the Ed25519 public keys whose signatures are trusted
+fn open_trusted_keys(matches: &ArgMatches) -> Result<Vec<ed25519_dalek::PublicKey>, Error> {
+    let mut trusted_keys = Vec::new();
+    if let Some(paths) = matches.values_of_os("trusted_keys") {
+        for path in paths {
+            let buf = fs::read(path)?;
+            let public_key = parse_openssl_ed25519_pubkey(&buf)
+                .ok()
+                .and_then(|bytes| ed25519_dalek::PublicKey::from_bytes(&bytes).ok())
+                .ok_or(Error::InvalidECCKeyFormat)?;
+            trusted_keys.push(public_key);
         }
     }
-    Ok(public_keys)
+    Ok(trusted_keys)
 }
 
-/// Return the ArchiveWriterConfig corresponding to provided arguments
 fn config_from_matches(matches: &ArgMatches) -> ArchiveWriterConfig {
     let mut config = ArchiveWriterConfig::new();
 
@@ -95,64 +373,270 @@ fn config_from_matches(matches: &ArgMatches) -> ArchiveWriterConfig {
         for layer in matches.values_of("layers").unwrap() {
             layers.push(layer);
         }
+    } else if let Some(config_layers) = &config_file::get().layers {
+        for layer in config_layers {
+            layers.push(layer.as_str());
+        }
     } else {
         // Default
         layers.push("compress");
         layers.push("encrypt");
     };
 
+    debug!("Enabled layers: {:?}", layers);
     for layer in layers {
         if layer == "compress" {
             config.enable_layer(Layers::COMPRESS);
         } else if layer == "encrypt" {
             config.enable_layer(Layers::ENCRYPT);
+        } else if layer == "fec" {
+            config.enable_layer(Layers::FEC);
         } else {
             panic!("[ERROR] Unknown layer {}", layer);
         }
     }
 
-    // Encryption specifics
-    if matches.is_present("public_keys") {
+    // Encryption specifics. Keys from the configuration file are silently
+    // ignored if the layer is disabled
+    let public_keys_given = keys_given(matches, "public_keys", "public_key_fd", PUBLIC_KEY_ENV);
+    if public_keys_given || config_file::get().public_keys.is_some() {
         if !config.is_layers_enabled(Layers::ENCRYPT) {
-            eprintln!(
-                "[WARNING] 'public_keys' argument ignored, because 'encrypt' layer is not enabled"
-            );
+            if public_keys_given {
+                warn!("'public_keys' argument ignored, because 'encrypt' layer is not enabled");
+            }
         } else {
-            let public_keys = match open_ecc_public_keys(matches) {
+            let (public_keys, hybrid_keys) = match open_ecc_public_keys(matches) {
                 Ok(public_keys) => public_keys,
                 Err(error) => {
                     panic!("[ERROR] Unable to open public keys: {}", error);
                 }
             };
             config.add_public_keys(&public_keys);
+            config.add_hybrid_public_keys(&hybrid_keys);
+        }
+    }
+    let escrow_keys_given = matches.is_present("escrow_public_keys");
+    if escrow_keys_given || config_file::get().escrow_public_keys.is_some() {
+        if !config.is_layers_enabled(Layers::ENCRYPT) {
+            if escrow_keys_given {
+                warn!("'escrow_pubkey' argument ignored, because 'encrypt' layer is not enabled");
+            }
+        } else {
+            let escrow_keys = match open_ecc_escrow_keys(matches) {
+                Ok(escrow_keys) => escrow_keys,
+                Err(error) => {
+                    panic!("[ERROR] Unable to open escrow public keys: {}", error);
+                }
+            };
+            config.add_escrow_keys(&escrow_keys);
+        }
+    }
+    if matches.is_present("anonymous_recipients") {
+        if !config.is_layers_enabled(Layers::ENCRYPT) {
+            warn!(
+                "'anonymous-recipients' argument ignored, because 'encrypt' layer is not enabled"
+            );
+        } else {
+            config.set_anonymous_recipients(true);
+        }
+    }
+    if matches.is_present("recipient_hints") {
+        if !config.is_layers_enabled(Layers::ENCRYPT) {
+            warn!("'recipient-hints' argument ignored, because 'encrypt' layer is not enabled");
+        } else {
+            config.set_recipient_hints(true);
+        }
+    }
+    if password_given(matches) {
+        if !config.is_layers_enabled(Layers::ENCRYPT) {
+            warn!("'password' argument ignored, because 'encrypt' layer is not enabled");
+        } else {
+            config.set_password(get_password(matches, true).as_bytes());
+        }
+    }
+    if let Some(threshold) = matches.value_of("threshold") {
+        if !config.is_layers_enabled(Layers::ENCRYPT) {
+            warn!("'threshold' argument ignored, because 'encrypt' layer is not enabled");
+        } else {
+            let threshold: u8 = threshold
+                .parse()
+                .expect("threshold must be an int in [1 .. 255]");
+            config.set_threshold(threshold);
+        }
+    }
+    if let Some(cipher) = matches.value_of("cipher") {
+        if !config.is_layers_enabled(Layers::ENCRYPT) {
+            warn!("'cipher' argument ignored, because 'encrypt' layer is not enabled");
+        } else {
+            config.set_cipher(match cipher {
+                "chacha20-poly1305" => Cipher::ChaCha20Poly1305,
+                _ => Cipher::AesGcm256,
+            });
+        }
+    }
+    if let Some(rekey_interval) = matches.value_of("rekey_interval") {
+        if !config.is_layers_enabled(Layers::ENCRYPT) {
+            warn!("'rekey-interval' argument ignored, because 'encrypt' layer is not enabled");
+        } else {
+            let rekey_interval = parse_size(rekey_interval);
+            if rekey_interval == 0 {
+                panic!("[ERROR] The rekeying interval must not be 0");
+            }
+            config.set_rekey_interval(rekey_interval);
+        }
+    }
+    if let Some(padding) = matches.value_of("padding") {
+        let padding = parse_size(padding);
+        if padding == 0 {
+            panic!("[ERROR] The padding size must not be 0");
+        }
+        config.set_padding(padding);
+    }
+    if matches.is_present("dedup") {
+        config.set_deduplication(true);
+    }
+    if let Some(seed) = matches.value_of("deterministic") {
+        config.set_deterministic_seed(Sha256::digest(seed.as_bytes()).into());
+    }
+    if let Some(comment) = matches.value_of("comment") {
+        config.set_comment(comment);
+    }
+    if let Some(algorithm) = matches.value_of("hash") {
+        config.set_hash_algorithm(match algorithm {
+            "sha512" => HashAlgorithm::Sha512,
+            "blake3" => HashAlgorithm::Blake3,
+            _ => HashAlgorithm::Sha256,
+        });
+    }
+    if let Some(checkpoint) = matches.value_of("checkpoint") {
+        let checkpoint = parse_size(checkpoint);
+        if config
+            .with_checkpoint_interval(CheckpointInterval::Bytes(checkpoint))
+            .is_err()
+        {
+            panic!("[ERROR] The checkpoint interval must not be 0");
+        }
+    }
+    if let Some(checkpoint) = matches.value_of("checkpoint_entries") {
+        let checkpoint: u64 = checkpoint
+            .parse()
+            .expect("[ERROR] The checkpoint interval must be an int");
+        if config
+            .with_checkpoint_interval(CheckpointInterval::Entries(checkpoint))
+            .is_err()
+        {
+            panic!("[ERROR] The checkpoint interval must not be 0");
         }
     }
 
-    // Compression specifics
-    if matches.is_present("compression_level") {
+    // Signature specifics
+    if let Some(path) = matches.value_of_os("sign_key") {
+        let signing_key = match open_signing_key(path) {
+            Ok(signing_key) => signing_key,
+            Err(error) => {
+                panic!("[ERROR] Unable to open the signing key: {}", error);
+            }
+        };
+        config
+            .enable_layer(Layers::SIGN)
+            .set_signature_key(&signing_key);
+    }
+
+    // Compression specifics. As for keys, a level from the configuration file
+    // is silently ignored if the layer is disabled
+    let config_level = config_file::get().compression_level;
+    if matches.is_present("compression_level") || config_level.is_some() {
         if !config.is_layers_enabled(Layers::COMPRESS) {
-            eprintln!("[WARNING] 'compression_level' argument ignored, because 'compress' layer is not enabled");
+            if matches.is_present("compression_level") {
+                warn!(
+                    "'compression_level' argument ignored, because 'compress' layer is not enabled"
+                );
+            }
         } else {
-            let comp_level: u32 = matches
-                .value_of("compression_level")
-                .unwrap()
-                .parse()
-                .expect("compression_level must be an int");
+            let comp_level: u32 = match matches.value_of("compression_level") {
+                Some(level) => level.parse().expect("compression_level must be an int"),
+                // Safe to use unwrap() because of the is_some() test
+                None => config_level.unwrap(),
+            };
             if comp_level > 11 {
                 panic!("compression_level must be in [0 .. 11]");
             }
             config.with_compression_level(comp_level).unwrap();
         }
     }
+    if let Some(algorithm) = matches.value_of("compression") {
+        if !config.is_layers_enabled(Layers::COMPRESS) {
+            warn!("'compression' argument ignored, because 'compress' layer is not enabled");
+        } else {
+            config.set_compression_algorithm(match algorithm {
+                "lz4" => CompressionAlgorithm::Lz4,
+                "xz" => CompressionAlgorithm::Xz,
+                _ => CompressionAlgorithm::Brotli,
+            });
+        }
+    }
+    if matches.is_present("store_incompressible") {
+        if !config.is_layers_enabled(Layers::COMPRESS) {
+            warn!(
+                "'store-incompressible' argument ignored, because 'compress' layer is not enabled"
+            );
+        } else {
+            config.set_store_incompressible(true);
+        }
+    }
+    if let Some(threads) = matches.value_of("threads") {
+        if !config.is_layers_enabled(Layers::COMPRESS) {
+            warn!("'threads' argument ignored, because 'compress' layer is not enabled");
+        } else {
+            let threads: usize = threads
+                .parse()
+                .expect("[ERROR] The number of threads must be an int");
+            if config.with_compression_threads(threads).is_err() {
+                panic!("[ERROR] The number of threads must not be 0");
+            }
+        }
+    }
+
+    // Forward error correction specifics
+    if let Some(mut shards) = matches.values_of("fec_shards") {
+        if !config.is_layers_enabled(Layers::FEC) {
+            warn!("'fec_shards' argument ignored, because 'fec' layer is not enabled");
+        } else {
+            // Safe to use unwrap() because of number_of_values(2)
+            let data_shards: u8 = shards
+                .next()
+                .unwrap()
+                .parse()
+                .expect("[ERROR] The number of data shards must be an int");
+            let parity_shards: u8 = shards
+                .next()
+                .unwrap()
+                .parse()
+                .expect("[ERROR] The number of parity shards must be an int");
+            if config.with_fec_shards(data_shards, parity_shards).is_err() {
+                panic!("[ERROR] There must be at least one data shard and one parity shard, and at most 256 shards");
+            }
+        }
+    }
 
     config
 }
 
+/// Resolve a relative output path in the configured output directory, if any
+fn output_path<P: AsRef<Path>>(output: P) -> PathBuf {
+    match &config_file::get().output_dir {
+        Some(output_dir) => output_dir.join(output),
+        None => output.as_ref().to_path_buf(),
+    }
+}
+
 fn destination_from_output_argument(output_argument: &str) -> Result<OutputTypes, Error> {
+    if output_argument.starts_with("s3://") {
+        panic!("[ERROR] Only 'mlar create', built with the \"s3\" feature, can upload to S3");
+    }
     let destination = if output_argument != "-" {
-        let path = Path::new(&output_argument);
         OutputTypes::File {
-            file: File::create(&path)?,
+            file: File::create(output_path(output_argument))?,
         }
     } else {
         OutputTypes::Stdout
@@ -160,6 +644,51 @@ fn destination_from_output_argument(output_argument: &str) -> Result<OutputTypes
     Ok(destination)
 }
 
+/// Destination of `mlar create`, which may also be a S3 object, as
+/// `s3://<bucket>/<key>`, or a file written through io_uring
+fn create_destination(matches: &ArgMatches) -> Result<OutputTypes, Error> {
+    // Safe to use unwrap() because the option is required()
+    let output_argument = matches.value_of("output").unwrap();
+    #[cfg(feature = "s3")]
+    if output_argument.starts_with(s3::S3_PREFIX) {
+        return Ok(OutputTypes::S3 {
+            upload: s3::MultipartUpload::create(output_argument)?,
+        });
+    }
+    if output_argument != "-" && uring_from_matches(matches) {
+        return uring_output(output_argument);
+    }
+    destination_from_output_argument(output_argument)
+}
+
+/// Whether files are to be read or written through io_uring, with
+/// `--io-backend uring`
+fn uring_from_matches(matches: &ArgMatches) -> bool {
+    matches.value_of("io_backend") == Some("uring")
+}
+
+#[cfg(all(target_os = "linux", feature = "uring"))]
+fn uring_output(output_argument: &str) -> Result<OutputTypes, Error> {
+    Ok(OutputTypes::Uring {
+        writer: uring::UringWriter::create(output_path(output_argument))?,
+    })
+}
+
+#[cfg(not(all(target_os = "linux", feature = "uring")))]
+fn uring_output(_output_argument: &str) -> Result<OutputTypes, Error> {
+    panic!("[ERROR] mlar must be built with the \"uring\" feature, on Linux, to use --io-backend uring");
+}
+
+#[cfg(all(target_os = "linux", feature = "uring"))]
+fn uring_input(path: &Path) -> Result<Box<dyn ReadSeek>, Error> {
+    Ok(Box::new(uring::UringReader::open(path)?))
+}
+
+#[cfg(not(all(target_os = "linux", feature = "uring")))]
+fn uring_input(_path: &Path) -> Result<Box<dyn ReadSeek>, Error> {
+    panic!("[ERROR] mlar must be built with the \"uring\" feature, on Linux, to use --io-backend uring");
+}
+
 /// Return an ArchiveWriter corresponding to provided arguments
 fn writer_from_matches<'a>(matches: &ArgMatches) -> Result<ArchiveWriter<'a, OutputTypes>, Error> {
     let config = config_from_matches(matches);
@@ -173,30 +702,270 @@ fn writer_from_matches<'a>(matches: &ArgMatches) -> Result<ArchiveWriter<'a, Out
     ArchiveWriter::from_config(destination, config)
 }
 
+/// Return the ArchiveWriterConfig corresponding to provided arguments, for
+/// commands keeping the layers and recipients of the input archive
+fn keepconfig_from_matches(matches: &ArgMatches) -> Result<ArchiveWriterConfig, Error> {
+    let mut config = ArchiveWriterConfig::new();
+    if let Some(comp_level) = matches.value_of("compression_level") {
+        let comp_level: u32 = comp_level
+            .parse()
+            .expect("compression_level must be an int");
+        config.with_compression_level(comp_level)?;
+    }
+    Ok(config)
+}
+
+/// Call `build` with the destination of an archive based on the input one
+///
+/// Without output argument, the input archive is replaced, through a
+/// temporary file, only if `build` succeeds. If the temporary file already
+/// exists, for instance left by an interrupted run, it is not overwritten and
+/// an error is returned
+fn rewrite_input_archive<F>(matches: &ArgMatches, build: F) -> Result<(), Error>
+where
+    F: FnOnce(OutputTypes) -> Result<(), Error>,
+{
+    if let Some(output) = matches.value_of("output") {
+        return build(destination_from_output_argument(output)?);
+    }
+
+    // Safe to use unwrap() because the option is required()
+    let mla_file = matches.value_of("input").unwrap();
+    let tmp_path = PathBuf::from(format!("{}.tmp", mla_file));
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&tmp_path)
+        .map_err(|err| {
+            error!("Unable to create \"{}\" ({:?})", tmp_path.display(), err);
+            err
+        })?;
+    let res = build(OutputTypes::File { file });
+    if res.is_ok() {
+        fs::rename(&tmp_path, mla_file)?;
+    } else {
+        fs::remove_file(&tmp_path)?;
+    }
+    res
+}
+
+/// Add to `files` the (archive name, path) of `path`, or, if it is a
+/// directory, of the regular files it contains, recursively. Files found in a
+/// directory are named relatively to its parent (`/var/log` -> `log/...`).
+/// Paths matched by `exclude` are skipped, with their content for directories
+fn collect_files(
+    path: &Path,
+    exclude: Option<&FileNameMatcher>,
+    files: &mut Vec<(String, PathBuf)>,
+) -> io::Result<()> {
+    if let Some(exclude) = exclude {
+        if exclude.match_path(path) {
+            return Ok(());
+        }
+    }
+    // Given paths are followed, to support symlinks and process substitutions
+    if fs::metadata(path)?.is_dir() {
+        let root = path.parent().unwrap_or(path);
+        collect_dir_files(path, root, exclude, files)
+    } else {
+        match path.to_str() {
+            Some(fname) => files.push((fname.to_string(), path.to_path_buf())),
+            None => warn!("{:?} ignored, only UTF-8 paths are supported", path),
+        }
+        Ok(())
+    }
+}
+
+/// Add to `files` the regular files in `dir`, recursively, named relatively
+/// to `root`. Symlinks and special files are skipped
+fn collect_dir_files(
+    dir: &Path,
+    root: &Path,
+    exclude: Option<&FileNameMatcher>,
+    files: &mut Vec<(String, PathBuf)>,
+) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<PathBuf>>>()?;
+    entries.sort();
+    for entry in entries {
+        if let Some(exclude) = exclude {
+            if exclude.match_path(&entry) {
+                continue;
+            }
+        }
+        let file_type = fs::symlink_metadata(&entry)?.file_type();
+        if file_type.is_dir() {
+            collect_dir_files(&entry, root, exclude, files)?;
+        } else if !file_type.is_file() {
+            warn!("{:?} ignored, as it is not a regular file", entry);
+        } else {
+            // Safe to use unwrap() because `entry` is in `root`
+            let name = entry.strip_prefix(root).unwrap();
+            match name.to_str() {
+                Some(fname) => files.push((fname.to_string(), entry.clone())),
+                None => warn!("{:?} ignored, only UTF-8 paths are supported", entry),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Read a list of paths from `files_from` (- for stdin), one per line or, if
+/// `null_separated`, separated by NUL characters
+fn read_files_from(files_from: &str, null_separated: bool) -> Result<Vec<String>, Error> {
+    let mut data = Vec::new();
+    if files_from == "-" {
+        io::stdin().read_to_end(&mut data)?;
+    } else {
+        File::open(files_from)?.read_to_end(&mut data)?;
+    }
+    let separator = if null_separated { b'\0' } else { b'\n' };
+
+    let mut paths = Vec::new();
+    for path in data.split(|c| *c == separator) {
+        if path.is_empty() {
+            continue;
+        }
+        paths.push(String::from_utf8(path.to_vec())?);
+    }
+    Ok(paths)
+}
+
+/// Read a password from the first line of `password_file` (- for stdin)
+fn read_password_file(password_file: &str) -> Result<String, Error> {
+    let mut content = String::new();
+    if password_file == "-" {
+        io::stdin().read_to_string(&mut content)?;
+    } else {
+        File::open(password_file)?.read_to_string(&mut content)?;
+    }
+    // Only keep the first line, without its line ending
+    let password = content.lines().next().unwrap_or("").to_string();
+    if password.is_empty() {
+        panic!("[ERROR] Empty password in {}", password_file);
+    }
+    Ok(password)
+}
+
+/// Parse a size, in bytes, with an optional K, M, G or T suffix (powers of 1024)
+fn parse_size(size: &str) -> u64 {
+    let (number, multiplier) = match size.chars().last() {
+        Some('K') | Some('k') => (&size[..size.len() - 1], 1 << 10),
+        Some('M') | Some('m') => (&size[..size.len() - 1], 1 << 20),
+        Some('G') | Some('g') => (&size[..size.len() - 1], 1 << 30),
+        Some('T') | Some('t') => (&size[..size.len() - 1], 1 << 40),
+        _ => (size, 1),
+    };
+    let number = match number.parse::<u64>() {
+        Ok(number) => number,
+        Err(_) => panic!("[ERROR] Invalid size {}", size),
+    };
+    match number.checked_mul(multiplier) {
+        Some(size) => size,
+        None => panic!("[ERROR] Size {} is too big", size),
+    }
+}
+
 /// Return the ArchiveReaderConfig corresponding to provided arguments
+/// Return a progress bar for `total` bytes, drawn on stderr
+///
+/// The bar is hidden with `--no-progress`, or if stderr is not a terminal
+fn progress_bar_from_matches(matches: &ArgMatches, total: u64) -> ProgressBar {
+    if matches.is_present("no_progress") {
+        return ProgressBar::hidden();
+    }
+    let progress = ProgressBar::new(total);
+    progress.set_style(
+        ProgressStyle::default_bar()
+            .template("[{elapsed_precise}] [{bar:40}] {bytes}/{total_bytes} (ETA {eta}) {wide_msg}")
+            .expect("Invalid progress bar template")
+            .progress_chars("=> "),
+    );
+    progress
+}
+
 fn readerconfig_from_matches(matches: &ArgMatches) -> ArchiveReaderConfig {
     let mut config = ArchiveReaderConfig::new();
 
-    if matches.is_present("private_keys") {
-        let private_keys = match open_ecc_private_keys(matches) {
+    if keys_given(matches, "private_keys", "private_key_fd", PRIVATE_KEY_ENV) {
+        let (private_keys, hybrid_keys) = match open_ecc_private_keys(matches) {
             Ok(private_keys) => private_keys,
             Err(error) => {
                 panic!("[ERROR] Unable to open private keys: {}", error);
             }
         };
         config.add_private_keys(&private_keys);
+        config.add_hybrid_private_keys(&hybrid_keys);
+    }
+    if password_given(matches) {
+        config.set_password(get_password(matches, false).as_bytes());
+    }
+    if matches.is_present("trusted_keys") {
+        match open_trusted_keys(matches) {
+            Ok(trusted_keys) => config.add_trusted_public_keys(&trusted_keys),
+            Err(error) => {
+                panic!("[ERROR] Unable to open trusted keys: {}", error);
+            }
+        };
     }
 
     config
 }
 
-fn open_mla_file<'a>(matches: &ArgMatches) -> Result<ArchiveReader<'a, File>, Error> {
+/// Source of an archive: either a single file, or a volume set
+trait ReadSeek: Read + Seek {}
+impl<T: Read + Seek> ReadSeek for T {}
+
+/// Open the archive at `path`. If it is a volume, such as `archive.mla.001`,
+/// the other volumes of its set are opened too, from their numbering
+///
+/// With the "http" feature, `path` can also be a http:// or https:// URL,
+/// the archive being then fetched only where it is read
+fn open_input(path: &Path) -> Result<Box<dyn ReadSeek>, Error> {
+    #[cfg(feature = "http")]
+    if let Some(url) = path.to_str().filter(|input| http::is_url(input)) {
+        return Ok(Box::new(http::HttpReader::open(url)?));
+    }
+    #[cfg(not(feature = "http"))]
+    if matches!(path.to_str(), Some(input) if input.starts_with("http://") || input.starts_with("https://"))
+    {
+        panic!("[ERROR] mlar must be built with the \"http\" feature to read archives over HTTP");
+    }
+    let mut file = File::open(path)?;
+    if !is_volume(&mut file)? {
+        return Ok(Box::new(file));
+    }
+
+    let count = VolumeHeader::from(&mut file)?.count;
+    // Volumes are expected to be named <stem>.001, <stem>.002, ...
+    let stem = match (path.file_stem(), path.extension()) {
+        (Some(stem), Some(ext)) if ext.to_string_lossy().parse::<u32>().is_ok() => stem,
+        _ => return Ok(Box::new(VolumesReader::new(vec![file])?)),
+    };
+    let mut volumes = Vec::new();
+    for number in 1..=count {
+        let volume_path = path.with_file_name(volume_file_name(stem, number));
+        debug!("Opening volume {}", volume_path.display());
+        volumes.push(File::open(volume_path)?);
+    }
+    Ok(Box::new(VolumesReader::new(volumes)?))
+}
+
+/// Name of the volume `number`, starting at 1, for an archive named `stem`
+fn volume_file_name(stem: &OsStr, number: u32) -> OsString {
+    let mut name = stem.to_os_string();
+    name.push(format!(".{:03}", number));
+    name
+}
+
+fn open_mla_file<'a>(matches: &ArgMatches) -> Result<ArchiveReader<'a, Box<dyn ReadSeek>>, Error> {
     let config = readerconfig_from_matches(matches);
 
     // Safe to use unwrap() because the option is required()
     let mla_file = matches.value_of("input").unwrap();
     let path = Path::new(&mla_file);
-    let file = File::open(&path)?;
+    let file = open_input(path)?;
 
     // Instantiate reader
     ArchiveReader::from_config(file, config)
@@ -205,13 +974,13 @@ fn open_mla_file<'a>(matches: &ArgMatches) -> Result<ArchiveReader<'a, File>, Er
 // Utils: common code to load a mla_file from arguments, fail-safe mode
 fn open_failsafe_mla_file<'a>(
     matches: &ArgMatches,
-) -> Result<ArchiveFailSafeReader<'a, File>, Error> {
+) -> Result<ArchiveFailSafeReader<'a, Box<dyn ReadSeek>>, Error> {
     let config = readerconfig_from_matches(matches);
 
     // Safe to use unwrap() because the option is required()
     let mla_file = matches.value_of("input").unwrap();
     let path = Path::new(&mla_file);
-    let file = File::open(&path)?;
+    let file = open_input(path)?;
 
     // Instantiate reader
     ArchiveFailSafeReader::from_config(file, config)
@@ -245,52 +1014,173 @@ fn add_file_to_tar<R: Read, W: Write>(
     Ok(())
 }
 
-/// Arguments for action 'extract' to match file names in the archive
-enum ExtractFileNameMatcher {
-    /// Match a list of files, where the order does not matter
-    Files(HashSet<String>),
-    /// Match a list of glob patterns
-    GlobPatterns(Vec<Pattern>),
-    /// No matching argument has been provided, so match all files
-    Anything,
-}
-impl ExtractFileNameMatcher {
-    fn from_matches(matches: &ArgMatches) -> Self {
-        let files = match matches.values_of("files") {
-            Some(values) => values,
-            None => return ExtractFileNameMatcher::Anything,
-        };
+fn add_file_to_zip<R: Read, W: Write + Seek>(
+    zip_file: &mut ZipWriter<W>,
+    mut sub_file: ArchiveFile<R>,
+    options: ZipFileOptions<()>,
+) -> Result<(), Error> {
+    // Zip64 extensions are required for files bigger than 4GB
+    let options = options.large_file(sub_file.size >= u32::MAX as u64);
+
+    // Force relative path, as ZIP archives do not support absolute ones
+    let filename = sub_file.filename.trim_start_matches('/');
+
+    if let Err(why) = zip_file.start_file(filename, options) {
+        panic!(
+            "Error while adding file \"{}\" to zip archive: {}",
+            filename, why
+        );
+    }
+    io::copy(&mut sub_file.data, zip_file)?;
+    Ok(())
+}
+
+/// How the file names given as arguments are interpreted
+#[derive(Clone, Copy)]
+enum NameSyntax {
+    /// As is
+    Names,
+    /// As glob patterns, with `--glob`
+    Glob,
+    /// As regular expressions, with `--regex`
+    Regex,
+}
+
+impl NameSyntax {
+    fn from_matches(matches: &ArgMatches) -> Self {
         if matches.is_present("glob") {
-            // Use glob patterns
-            ExtractFileNameMatcher::GlobPatterns(
-                files
+            NameSyntax::Glob
+        } else if matches.is_present("regex") {
+            NameSyntax::Regex
+        } else {
+            NameSyntax::Names
+        }
+    }
+}
+
+/// Regular expression matching file names, ignoring the case of letters if
+/// `ignore_case`
+fn name_regex(pattern: &str, ignore_case: bool) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(ignore_case)
+        .build()
+}
+
+/// Arguments to match file names, such as files to extract from the archive or
+/// files to exclude from its creation
+enum FileNameMatcher {
+    /// Match a list of files, where the order does not matter. If the case is
+    /// ignored, the names are kept in lowercase
+    Files {
+        names: HashSet<String>,
+        ignore_case: bool,
+    },
+    /// Match a list of glob patterns
+    GlobPatterns {
+        patterns: Vec<Pattern>,
+        ignore_case: bool,
+    },
+    /// Match a list of regular expressions, found anywhere in the names
+    RegexPatterns(Vec<Regex>),
+    /// No matching argument has been provided, so match all files
+    Anything,
+}
+impl FileNameMatcher {
+    fn from_matches(matches: &ArgMatches) -> Self {
+        match matches.values_of("files") {
+            Some(files) => FileNameMatcher::from_values(
+                files,
+                NameSyntax::from_matches(matches),
+                matches.is_present("ignore_case"),
+            ),
+            None => FileNameMatcher::Anything,
+        }
+    }
+    fn from_values(files: Values, syntax: NameSyntax, ignore_case: bool) -> Self {
+        match syntax {
+            NameSyntax::Glob => FileNameMatcher::GlobPatterns {
+                patterns: files
                     .map(|pat| {
                         Pattern::new(pat)
                             .map_err(|err| {
-                                eprintln!("[!] Invalid glob pattern {:?} ({:?})", pat, err);
+                                error!("Invalid glob pattern {:?} ({:?})", pat, err);
                             })
                             .expect("Invalid glob pattern")
                     })
                     .collect(),
-            )
-        } else {
-            // Use file names
-            ExtractFileNameMatcher::Files(files.map(|s| s.to_string()).collect())
+                ignore_case,
+            },
+            NameSyntax::Regex => FileNameMatcher::RegexPatterns(
+                files
+                    .map(|pat| {
+                        name_regex(pat, ignore_case)
+                            .map_err(|err| {
+                                error!("Invalid regular expression {:?} ({})", pat, err);
+                            })
+                            .expect("Invalid regular expression")
+                    })
+                    .collect(),
+            ),
+            NameSyntax::Names => FileNameMatcher::Files {
+                names: files
+                    .map(|s| {
+                        if ignore_case {
+                            s.to_lowercase()
+                        } else {
+                            s.to_string()
+                        }
+                    })
+                    .collect(),
+                ignore_case,
+            },
         }
     }
     fn match_file_name(&self, file_name: &str) -> bool {
         match self {
-            ExtractFileNameMatcher::Files(ref files) => {
-                files.is_empty() || files.contains(file_name)
+            FileNameMatcher::Files { names, ignore_case } => {
+                names.is_empty()
+                    || if *ignore_case {
+                        names.contains(&file_name.to_lowercase())
+                    } else {
+                        names.contains(file_name)
+                    }
             }
-            ExtractFileNameMatcher::GlobPatterns(ref patterns) => {
-                patterns.is_empty() || patterns.iter().any(|pat| pat.matches(&file_name))
+            FileNameMatcher::GlobPatterns {
+                patterns,
+                ignore_case,
+            } => {
+                let options = glob_match_options(*ignore_case);
+                patterns.is_empty()
+                    || patterns
+                        .iter()
+                        .any(|pat| pat.matches_with(file_name, options))
             }
-            ExtractFileNameMatcher::Anything => true,
+            FileNameMatcher::RegexPatterns(patterns) => {
+                patterns.is_empty() || patterns.iter().any(|pat| pat.is_match(file_name))
+            }
+            FileNameMatcher::Anything => true,
+        }
+    }
+    /// Match either the whole `path`, or only its last component
+    fn match_path(&self, path: &Path) -> bool {
+        if self.match_file_name(&path.to_string_lossy()) {
+            return true;
+        }
+        match path.file_name() {
+            Some(name) => self.match_file_name(&name.to_string_lossy()),
+            None => false,
         }
     }
 }
 
+/// Options of glob patterns, ignoring the case of letters if `ignore_case`
+fn glob_match_options(ignore_case: bool) -> MatchOptions {
+    MatchOptions {
+        case_sensitive: !ignore_case,
+        ..MatchOptions::new()
+    }
+}
+
 /// Compute the full path of the final file, using defensive measures
 /// similar as what tar-rs does for `Entry::unpack_in`:
 /// https://github.com/alexcrichton/tar-rs/blob/0.4.26/src/entry.rs#L344
@@ -308,10 +1198,7 @@ fn get_extracted_path(output_dir: &Path, file_name: &str) -> Option<PathBuf> {
             // security issues.  See, e.g.: CVE-2001-1267,
             // CVE-2002-0399, CVE-2005-1918, CVE-2007-4131
             Component::ParentDir => {
-                eprintln!(
-                    "[!] Skipping file \"{}\" because it contains \"..\"",
-                    file_name
-                );
+                warn!("Skipping file \"{}\" because it contains \"..\"", file_name);
                 return None;
             }
 
@@ -321,10 +1208,234 @@ fn get_extracted_path(output_dir: &Path, file_name: &str) -> Option<PathBuf> {
     Some(file_dst)
 }
 
+/// Create the output directory, if it does not exist, and return its
+/// canonical path
+fn create_output_dir(output_dir: &Path) -> Result<PathBuf, Error> {
+    if !output_dir.exists() {
+        fs::create_dir(output_dir).map_err(|err| {
+            error!(
+                "Error while creating output directory \"{}\" ({:?})",
+                output_dir.display(),
+                err
+            );
+            err
+        })?;
+    }
+    Ok(fs::canonicalize(output_dir).map_err(|err| {
+        error!(
+            "Error while canonicalizing output directory path \"{}\" ({:?})",
+            output_dir.display(),
+            err
+        );
+        err
+    })?)
+}
+
+/// Behavior of `create_file` for the files which already exist
+#[derive(Clone, Copy, PartialEq)]
+enum Overwrite {
+    /// Keep the existing file, the new one is skipped
+    Never,
+    /// Replace the existing file
+    Always,
+    /// Replace the existing file only if it was last modified before the new
+    /// one
+    Older,
+    /// Ask the user, for each existing file
+    Prompt,
+}
+
+/// How `create_file` handles the files which already exist
+#[derive(Clone)]
+struct OverwritePolicy {
+    overwrite: Overwrite,
+    /// If set, existing files are renamed with this suffix instead of being
+    /// removed, when they are replaced
+    backup_suffix: Option<String>,
+}
+
+impl OverwritePolicy {
+    fn from_matches(matches: &ArgMatches) -> Self {
+        // Safe to use unwrap() because the option has a default value
+        let overwrite = match matches.value_of("overwrite").unwrap() {
+            "never" => Overwrite::Never,
+            "always" => Overwrite::Always,
+            "older" => Overwrite::Older,
+            "prompt" => Overwrite::Prompt,
+            _ => panic!("[ERROR] Unknown overwrite policy"),
+        };
+        OverwritePolicy {
+            overwrite,
+            backup_suffix: matches.value_of("backup_suffix").map(String::from),
+        }
+    }
+
+    /// Whether the existing `path` can be replaced by the file `fname`, last
+    /// modified at `mtime`
+    fn allows_replacing(
+        &self,
+        path: &Path,
+        fname: &str,
+        mtime: Option<i64>,
+    ) -> Result<bool, Error> {
+        Ok(match self.overwrite {
+            Overwrite::Never => {
+                warn!(
+                    "Skipping file \"{}\" because \"{}\" already exists",
+                    fname,
+                    path.display()
+                );
+                false
+            }
+            Overwrite::Always => true,
+            Overwrite::Older => {
+                let existing_mtime = fs::symlink_metadata(path)?.modified().ok().map(unix_time);
+                // Without a recorded time, the files can not be compared
+                let older = match (existing_mtime, mtime) {
+                    (Some(existing), Some(mtime)) => existing < mtime,
+                    _ => false,
+                };
+                if !older {
+                    info!(
+                        "Skipping file \"{}\" because \"{}\" is not older than it",
+                        fname,
+                        path.display()
+                    );
+                }
+                older
+            }
+            Overwrite::Prompt => prompt_overwrite(path)?,
+        })
+    }
+
+    /// Rename the existing `path` with the backup suffix, if any. Returns
+    /// whether it has been backed up
+    fn back_up(&self, path: &Path) -> Result<bool, Error> {
+        let suffix = match &self.backup_suffix {
+            Some(suffix) => suffix,
+            None => return Ok(false),
+        };
+        let mut backup = path.as_os_str().to_os_string();
+        backup.push(suffix);
+        fs::rename(path, &backup).map_err(|err| {
+            error!("Unable to back up \"{}\" ({:?})", path.display(), err);
+            err
+        })?;
+        Ok(true)
+    }
+
+    /// Back up, or remove, the existing `path` before it is replaced
+    ///
+    /// The existing file is removed rather than truncated. A symbolic link is
+    /// then replaced instead of followed
+    fn set_aside(&self, path: &Path) -> Result<(), Error> {
+        if !self.back_up(path)? {
+            fs::remove_file(path).map_err(|err| {
+                error!("Unable to remove \"{}\" ({:?})", path.display(), err);
+                err
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// Ask the user whether the existing `path` must be overwritten
+fn prompt_overwrite(path: &Path) -> Result<bool, Error> {
+    // Extraction threads ask one after the other
+    static PROMPT: Mutex<()> = Mutex::new(());
+    let _guard = PROMPT
+        .lock()
+        .expect("[ERROR] An extraction thread panicked");
+    eprint!("Overwrite \"{}\"? [y/N] ", path.display());
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Suffix of the files being extracted atomically, until they are complete
+const PARTIAL_SUFFIX: &str = ".mla-partial";
+
+/// Path where `path` is written, until complete, when extracting atomically
+fn partial_path(path: &Path) -> PathBuf {
+    let mut partial = path.as_os_str().to_os_string();
+    partial.push(PARTIAL_SUFFIX);
+    PathBuf::from(partial)
+}
+
+/// Move the complete file written in `partial_path(path)` to `path`
+///
+/// An existing `path` is replaced by the rename, once backed up according to
+/// `overwrite`. Until then, it is left untouched
+fn commit_partial(path: &Path, overwrite: &OverwritePolicy) -> Result<(), Error> {
+    if fs::symlink_metadata(path).is_ok() {
+        overwrite.back_up(path)?;
+    }
+    fs::rename(partial_path(path), path).map_err(|err| {
+        error!("Unable to rename \"{}\" ({:?})", path.display(), err);
+        err
+    })?;
+    Ok(())
+}
+
+/// File being extracted by `stream_extract`, written at `partial_path(path)`
+/// until its content is checked
+///
+/// The partial file is removed if dropped before being committed
+struct PartialFile {
+    /// Closed once committed
+    file: Option<File>,
+    path: PathBuf,
+    committed: bool,
+}
+
+impl PartialFile {
+    /// Move the complete file to its path, see `commit_partial`
+    fn commit(mut self, overwrite: &OverwritePolicy) -> Result<PathBuf, Error> {
+        self.file = None;
+        commit_partial(&self.path, overwrite)?;
+        self.committed = true;
+        Ok(self.path.clone())
+    }
+}
+
+impl Write for PartialFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.file {
+            Some(file) => file.write(buf),
+            None => Err(io::Error::other("Partial file already committed")),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.file {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for PartialFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            self.file = None;
+            let _ = fs::remove_file(partial_path(&self.path));
+        }
+    }
+}
+
 /// Create a file and associate parent directories in a given output directory
+///
+/// If the file already exists, it is handled according to `overwrite`,
+/// `mtime` being the last modification time of `fname` in the archive.
+/// If `atomic`, the returned file is created at `partial_path(path)`, `path`
+/// being the returned path, and must be moved with `commit_partial` once
+/// complete. The existing file is then only replaced at that time
 fn create_file<P1: AsRef<Path>>(
     output_dir: P1,
     fname: &str,
+    overwrite: &OverwritePolicy,
+    mtime: Option<i64>,
+    atomic: bool,
 ) -> Result<Option<(File, PathBuf)>, Error> {
     let extracted_path = match get_extracted_path(output_dir.as_ref(), &fname) {
         Some(p) => p,
@@ -334,8 +1445,8 @@ fn create_file<P1: AsRef<Path>>(
     let containing_directory = match extracted_path.parent() {
         Some(p) => p,
         None => {
-            eprintln!(
-                "[!] Skipping file \"{}\" because it does not have a parent (from {})",
+            warn!(
+                "Skipping file \"{}\" because it does not have a parent (from {})",
                 &fname,
                 extracted_path.display()
             );
@@ -344,8 +1455,8 @@ fn create_file<P1: AsRef<Path>>(
     };
     if !containing_directory.exists() {
         fs::create_dir_all(&containing_directory).map_err(|err| {
-            eprintln!(
-                " [!] Error while creating output directory path for \"{}\" ({:?})",
+            error!(
+                "Error while creating output directory path for \"{}\" ({:?})",
                 output_dir.as_ref().display(),
                 err
             );
@@ -355,46 +1466,110 @@ fn create_file<P1: AsRef<Path>>(
 
     // Ensure that the containing directory is in the output dir
     let containing_directory = fs::canonicalize(&containing_directory).map_err(|err| {
-        eprintln!(
-            " [!] Error while canonicalizing extracted file output directory path \"{}\" ({:?})",
+        error!(
+            "Error while canonicalizing extracted file output directory path \"{}\" ({:?})",
             containing_directory.display(),
             err
         );
         err
     })?;
     if !containing_directory.starts_with(output_dir) {
-        eprintln!(
-            " [!] Skipping file \"{}\" because it would be extracted outside of the output directory, in {}",
+        warn!(
+            "Skipping file \"{}\" because it would be extracted outside of the output directory, in {}",
             fname, containing_directory.display()
         );
         return Ok(None);
     }
+    if fs::symlink_metadata(&extracted_path).is_ok() {
+        if !overwrite.allows_replacing(&extracted_path, fname, mtime)? {
+            return Ok(None);
+        }
+        if !atomic {
+            overwrite.set_aside(&extracted_path)?;
+        }
+    }
+    let created_path = if atomic {
+        partial_path(&extracted_path)
+    } else {
+        extracted_path.clone()
+    };
     Ok(Some((
-        File::create(&extracted_path).map_err(|err| {
-            eprintln!(" [!] Unable to create \"{}\" ({:?})", fname, err);
+        File::create(&created_path).map_err(|err| {
+            error!("Unable to create \"{}\" ({:?})", fname, err);
             err
         })?,
         extracted_path,
     )))
 }
 
+/// Default number of files kept open by a `FilePool`
+const DEFAULT_MAX_OPEN_FILES: usize = 64;
+
+/// Files opened for appending, shared by `FileWriter`s
+///
+/// At most `capacity` files are kept open, to avoid reaching the filesystem
+/// limit. When another one is needed, the least recently used one is closed
+struct FilePool {
+    capacity: usize,
+    /// Open files, with the tick of their last use
+    files: HashMap<PathBuf, (File, u64)>,
+    /// Incremented on each use of a file
+    tick: u64,
+}
+
+impl FilePool {
+    fn new(capacity: usize) -> Self {
+        FilePool {
+            capacity: std::cmp::max(capacity, 1),
+            files: HashMap::new(),
+            tick: 0,
+        }
+    }
+
+    /// Open file for `path`, opened if needed
+    fn get(&mut self, path: &Path) -> io::Result<&mut File> {
+        self.tick += 1;
+        if !self.files.contains_key(path) {
+            if self.files.len() >= self.capacity {
+                // Only looked for once the pool is full, and a file is missing
+                let least_recent = self
+                    .files
+                    .iter()
+                    .min_by_key(|(_, (_, tick))| *tick)
+                    .map(|(open, _)| open.clone());
+                if let Some(least_recent) = least_recent {
+                    self.files.remove(&least_recent);
+                }
+            }
+            let file = fs::OpenOptions::new().append(true).open(path)?;
+            self.files.insert(path.to_path_buf(), (file, 0));
+        }
+        // Safe to unwrap, as the entry has just been checked or inserted
+        let (file, tick) = self.files.get_mut(path).unwrap();
+        *tick = self.tick;
+        Ok(file)
+    }
+
+    /// Close every file
+    fn close_all(&mut self) {
+        self.files.clear();
+    }
+}
+
 /// Wrapper with Write, to append data to a file
 ///
 /// This wrapper is used to avoid opening all files simultaneously, potentially
-/// reaching the filesystem limit, but rather appending to file on-demand
-/// This could be enhanced with a limited pool of active file, but this
-/// optimisation doesn't seems necessary for now
+/// reaching the filesystem limit, but rather appending to file on-demand,
+/// through a limited pool of open files
 struct FileWriter {
     /// Target file for data appending
     path: PathBuf,
+    pool: Rc<RefCell<FilePool>>,
 }
 
 impl Write for FileWriter {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        fs::OpenOptions::new()
-            .append(true)
-            .open(&self.path)?
-            .write(buf)
+        self.pool.borrow_mut().get(&self.path)?.write(buf)
     }
 
     fn flush(&mut self) -> io::Result<()> {
@@ -405,173 +1580,1511 @@ impl Write for FileWriter {
 // ----- Commands ------
 
 fn create(matches: &ArgMatches) -> Result<(), Error> {
-    let mut mla = writer_from_matches(matches)?;
+    // Safe to use unwrap() because the option is required()
+    let output = matches.value_of("output").unwrap();
+    if output.starts_with("s3://") && matches.is_present("timestamp_url") {
+        panic!("[ERROR] Archives uploaded to S3 can not be timestamped");
+    }
+    let mut mla =
+        ArchiveWriter::from_config(create_destination(matches)?, config_from_matches(matches))?;
 
-    if let Some(files) = matches.values_of("files") {
-        for filename in files {
-            eprintln!("{}", filename);
-            let file = File::open(&Path::new(&filename))?;
-            let length = file.metadata()?.len();
-            mla.add_file(filename, length, file)?;
-        }
+    let mut paths: Vec<String> = match matches.values_of("files") {
+        Some(files) => files.map(|s| s.to_string()).collect(),
+        None => Vec::new(),
     };
+    if let Some(files_from) = matches.value_of("files_from") {
+        if files_from == "-" && paths.iter().any(|p| p == "-") {
+            panic!("[ERROR] stdin can not be used both for --files-from and as a file");
+        }
+        paths.extend(read_files_from(files_from, matches.is_present("null"))?);
+    }
 
-    mla.finalize()?;
-    Ok(())
-}
+    let exclude = matches
+        .values_of("exclude")
+        .map(|patterns| FileNameMatcher::from_values(patterns, NameSyntax::Glob, false));
+    let store = matches
+        .values_of("store_glob")
+        .map(|patterns| FileNameMatcher::from_values(patterns, NameSyntax::Glob, false));
+    let default_options = FileOptions::new();
+    let mut store_options = FileOptions::new();
+    store_options.set_store(true);
 
-fn list(matches: &ArgMatches) -> Result<(), Error> {
-    let mut mla = open_mla_file(matches)?;
+    // Directories are walked recursively
+    let mut files = Vec::new();
+    for path in &paths {
+        if path == "-" {
+            files.push((path.clone(), PathBuf::from(path)));
+        } else {
+            collect_files(Path::new(path), exclude.as_ref(), &mut files)?;
+        }
+    }
+    if matches.is_present("deterministic") {
+        // Independent of the order of the arguments
+        files.sort();
+    }
+    // Data from stdin, and from pipes, is not accounted, as its length is
+    // unknown
+    let mut total = 0;
+    for (_, path) in files.iter().filter(|(filename, _)| filename != "-") {
+        total += fs::metadata(path)?.len();
+    }
+    let progress = progress_bar_from_matches(matches, total);
+    let progress_callback = progress.clone();
+    mla.set_progress_callback(move |count| progress_callback.inc(count));
 
-    let mut iter: Vec<String> = mla.list_files()?.cloned().collect();
-    iter.sort();
-    for fname in iter {
-        if matches.is_present("verbose") {
-            let mla_file = mla.get_file(fname)?.expect("Unable to get the file");
-            let filename = mla_file.filename;
-            let size = mla_file
-                .size
-                .file_size(file_size_opts::CONVENTIONAL)
-                .unwrap();
-            if matches.occurrences_of("verbose") == 1 {
-                println!("{} - {}", filename, size);
-            } else if matches.occurrences_of("verbose") >= 2 {
-                let hash = mla.get_hash(&filename)?.expect("Unable to get the hash");
-                println!("{} - {} ({})", filename, size, hex::encode(hash),);
+    // Name without digits -> Last file added with this name, with --delta
+    let mut delta_bases: HashMap<String, (&String, &PathBuf)> = HashMap::new();
+    for (filename, path) in &files {
+        if filename == "-" {
+            // Data from stdin, whose length is unknown, is streamed
+            let name = match matches.value_of("stdin_name") {
+                Some(name) => name,
+                None => panic!("[ERROR] --stdin-name is required to add data from stdin"),
+            };
+            progress.suspend(|| info!("{}", name));
+            progress.set_message(name.to_string());
+            mla.add_file_unknown_size(name, io::stdin())?;
+            continue;
+        }
+        progress.suspend(|| info!("{}", filename));
+        progress.set_message(filename.clone());
+        let file = File::open(path)?;
+        let fs_metadata = file.metadata()?;
+        let length = fs_metadata.len();
+        let mut options = match &store {
+            Some(store) if store.match_path(path) => &store_options,
+            _ => &default_options,
+        }
+        .clone();
+        let mut metadata = file_metadata(&fs_metadata);
+        if matches.is_present("xattrs") {
+            metadata.xattrs = file_xattrs(path);
+        }
+        options.set_metadata(metadata);
+        if !fs_metadata.is_file() {
+            // Such as a named pipe or a process substitution, whose length is
+            // only known once read
+            mla.add_file_unknown_size_with_options(filename, file, &options)?;
+            continue;
+        }
+        if matches.is_present("delta") {
+            let family = delta_family(filename);
+            if let Some((base, base_path)) = delta_bases.insert(family, (filename, path)) {
+                // Only the differences with the previous version are stored
+                let base_src = File::open(base_path)?;
+                mla.add_file_delta_with_options(filename, length, file, base, base_src, &options)?;
+                continue;
+            }
+        }
+        // Identical files are only stored once with --dedup
+        mla.add_file_seekable(filename, length, file, &options)?;
+        if matches.is_present("ads") {
+            // Right after their file, to be extracted after it
+            for (stream, size) in alternate_streams(path)? {
+                let name = format!("{}:{}", filename, stream);
+                progress.suspend(|| info!("{}", name));
+                let mut stream_path = path.clone().into_os_string();
+                stream_path.push(format!(":{}", stream));
+                mla.add_file(&name, size, File::open(stream_path)?)?;
             }
-        } else {
-            println!("{}", fname);
         }
     }
+
+    mla.finalize()?;
+    progress.finish_and_clear();
+    // The archive must be complete on disk before being timestamped
+    mla.into_raw().finish()?;
+    if let Some(url) = matches.value_of("timestamp_url") {
+        // Safe to use unwrap() because the option is required()
+        timestamp_output(matches.value_of("output").unwrap(), url)?;
+    }
     Ok(())
 }
 
-fn extract(matches: &ArgMatches) -> Result<(), Error> {
-    let file_name_matcher = ExtractFileNameMatcher::from_matches(&matches);
-    let output_dir = Path::new(matches.value_of_os("outputdir").unwrap());
-    let verbose = matches.is_present("verbose");
+/// Seconds since the Unix epoch of `time`
+fn unix_time(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(elapsed) => elapsed.as_secs() as i64,
+        Err(err) => -(err.duration().as_secs() as i64),
+    }
+}
 
-    let mut mla = open_mla_file(matches)?;
+/// Filesystem metadata of a file to archive
+#[cfg(unix)]
+fn file_metadata(fs_metadata: &fs::Metadata) -> FileMetadata {
+    use std::os::unix::fs::MetadataExt;
 
-    // Create the output directory, if it does not exist
-    if !output_dir.exists() {
-        fs::create_dir(&output_dir).map_err(|err| {
-            eprintln!(
-                " [!] Error while creating output directory \"{}\" ({:?})",
-                output_dir.display(),
-                err
-            );
-            err
-        })?;
+    FileMetadata {
+        mtime: Some(fs_metadata.mtime()),
+        // Permission bits only, the file type is implied
+        mode: Some(fs_metadata.mode() & 0o7777),
+        uid: Some(fs_metadata.uid()),
+        gid: Some(fs_metadata.gid()),
+        // Not provided by every filesystem
+        btime: fs_metadata.created().ok().map(unix_time),
+        ..FileMetadata::default()
     }
-    let output_dir = fs::canonicalize(&output_dir).map_err(|err| {
-        eprintln!(
-            " [!] Error while canonicalizing output directory path \"{}\" ({:?})",
-            output_dir.display(),
-            err
-        );
-        err
-    })?;
+}
 
-    let mut iter: Vec<String> = mla.list_files()?.cloned().collect();
-    iter.sort();
+#[cfg(not(unix))]
+fn file_metadata(fs_metadata: &fs::Metadata) -> FileMetadata {
+    #[cfg(windows)]
+    let attributes = {
+        use std::os::windows::fs::MetadataExt;
+        Some(fs_metadata.file_attributes())
+    };
+    #[cfg(not(windows))]
+    let attributes = None;
+    FileMetadata {
+        mtime: fs_metadata.modified().ok().map(unix_time),
+        btime: fs_metadata.created().ok().map(unix_time),
+        attributes,
+        ..FileMetadata::default()
+    }
+}
 
-    if let ExtractFileNameMatcher::Anything = file_name_matcher {
-        // Optimisation: use linear extraction
-        if verbose {
-            println!("Extracting the whole archive using a linear extraction");
+/// Alternate data streams of a file to archive, as (name, size)
+///
+/// The unnamed stream, holding the content of the file, is not included
+#[cfg(windows)]
+fn alternate_streams(path: &Path) -> Result<Vec<(String, u64)>, Error> {
+    use std::os::windows::ffi::{OsStrExt, OsStringExt};
+    use windows_sys::Win32::Foundation::{ERROR_HANDLE_EOF, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::Storage::FileSystem::{
+        FindClose, FindFirstStreamW, FindNextStreamW, FindStreamInfoStandard,
+        WIN32_FIND_STREAM_DATA,
+    };
+
+    let wide_path: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    // Safe, as a plain C struct
+    let mut data: WIN32_FIND_STREAM_DATA = unsafe { std::mem::zeroed() };
+    let handle = unsafe {
+        FindFirstStreamW(
+            wide_path.as_ptr(),
+            FindStreamInfoStandard,
+            &mut data as *mut WIN32_FIND_STREAM_DATA as *mut _,
+            0,
+        )
+    };
+    if handle == INVALID_HANDLE_VALUE {
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() == Some(ERROR_HANDLE_EOF as i32) {
+            // No stream at all
+            return Ok(Vec::new());
         }
-        let mut export: HashMap<&String, FileWriter> = HashMap::new();
-        for fname in &iter {
-            match create_file(&output_dir, fname)? {
-                Some((_file, path)) => {
-                    export.insert(fname, FileWriter { path });
-                }
-                None => continue,
+        return Err(err.into());
+    }
+    let mut streams = Vec::new();
+    loop {
+        let length = data
+            .cStreamName
+            .iter()
+            .position(|c| *c == 0)
+            .unwrap_or(data.cStreamName.len());
+        let name = OsString::from_wide(&data.cStreamName[..length]);
+        // Named ":name:$DATA", the unnamed one being "::$DATA"
+        if let Some(name) = name
+            .to_str()
+            .and_then(|name| name.strip_prefix(':'))
+            .and_then(|name| name.strip_suffix(":$DATA"))
+        {
+            if !name.is_empty() {
+                streams.push((name.to_string(), data.StreamSize as u64));
             }
         }
-        return linear_extract(&mut mla, &mut export);
+        if unsafe { FindNextStreamW(handle, &mut data as *mut WIN32_FIND_STREAM_DATA as *mut _) }
+            == 0
+        {
+            break;
+        }
     }
+    unsafe { FindClose(handle) };
+    Ok(streams)
+}
 
-    for fname in iter {
-        // Filter files according to glob patterns or files given as parameters
-        if !file_name_matcher.match_file_name(&fname) {
-            continue;
-        }
+#[cfg(not(windows))]
+fn alternate_streams(_path: &Path) -> Result<Vec<(String, u64)>, Error> {
+    Ok(Vec::new())
+}
 
-        // Look for the file in the archive
-        let mut sub_file = match mla.get_file(fname.clone()) {
-            Err(err) => {
-                eprintln!(
-                    " [!] Error while looking up subfile \"{}\" ({:?})",
-                    fname, err
-                );
-                continue;
-            }
-            Ok(None) => {
-                eprintln!(
-                    " [!] Subfile \"{}\" indexed in metadata could not be found",
-                    fname
-                );
-                continue;
-            }
-            Ok(Some(subfile)) => subfile,
-        };
-        let (mut extracted_file, _path) = match create_file(&output_dir, &fname)? {
-            Some(file) => file,
-            None => continue,
-        };
+/// Extended attributes of a file to archive, such as SELinux labels
+///
+/// The ones which can not be read, for instance without the required
+/// privileges, are skipped with a warning
+#[cfg(unix)]
+fn file_xattrs(path: &Path) -> BTreeMap<Vec<u8>, Vec<u8>> {
+    use std::os::unix::ffi::OsStrExt;
 
-        if verbose {
-            println!("{}", fname);
+    let mut xattrs = BTreeMap::new();
+    let names = match xattr::list(path) {
+        Ok(names) => names,
+        Err(err) => {
+            warn!(
+                "Unable to list the extended attributes of {:?} ({})",
+                path, err
+            );
+            return xattrs;
+        }
+    };
+    for name in names {
+        match xattr::get(path, &name) {
+            Ok(Some(value)) => {
+                xattrs.insert(name.as_bytes().to_vec(), value);
+            }
+            // Removed in the meantime
+            Ok(None) => {}
+            Err(err) => warn!(
+                "Unable to read the extended attribute {:?} of {:?} ({})",
+                name, path, err
+            ),
         }
-        io::copy(&mut sub_file.data, &mut extracted_file).map_err(|err| {
-            eprintln!(" [!] Unable to extract \"{}\" ({:?})", fname, err);
-            err
-        })?;
     }
-    Ok(())
+    xattrs
 }
 
-fn cat(matches: &ArgMatches) -> Result<(), Error> {
-    let files_values = matches.values_of("files").unwrap();
-    let output = matches.value_of("output").unwrap();
-    let mut destination = destination_from_output_argument(output)?;
+#[cfg(not(unix))]
+fn file_xattrs(path: &Path) -> BTreeMap<Vec<u8>, Vec<u8>> {
+    warn!(
+        "Extended attributes of {:?} ignored, as they are only supported on Unix",
+        path
+    );
+    BTreeMap::new()
+}
 
-    let mut mla = open_mla_file(matches)?;
-    if matches.is_present("glob") {
-        // For each glob patterns, enumerate matching files and display them
-        let mut archive_files: Vec<String> = mla.list_files()?.cloned().collect();
-        archive_files.sort();
-        for arg_pattern in files_values {
-            let pat = match Pattern::new(arg_pattern) {
-                Ok(pat) => pat,
-                Err(err) => {
-                    eprintln!(" [!] Invalid glob pattern {:?} ({:?})", arg_pattern, err);
-                    continue;
+/// Name shared by the successive versions of a file, such as rotated logs
+/// (`syslog.1`, `syslog.2`): the name without its digits
+fn delta_family(filename: &str) -> String {
+    filename.chars().filter(|c| !c.is_ascii_digit()).collect()
+}
+
+fn append(matches: &ArgMatches) -> Result<(), Error> {
+    let config = keepconfig_from_matches(matches)?;
+    let reader_config = readerconfig_from_matches(matches);
+    // Safe to use unwrap() because the option is required()
+    let src = File::open(matches.value_of("input").unwrap())?;
+
+    rewrite_input_archive(matches, |destination| {
+        let mut mla = ArchiveWriter::from_archive(destination, config, src, reader_config)?;
+        if let Some(files) = matches.values_of("files") {
+            for filename in files {
+                info!("{}", filename);
+                let file = File::open(filename)?;
+                let length = file.metadata()?.len();
+                mla.add_file(filename, length, file)?;
+            }
+        };
+        mla.finalize()
+    })
+}
+
+fn update(matches: &ArgMatches) -> Result<(), Error> {
+    let config = keepconfig_from_matches(matches)?;
+
+    // Current state of the given paths
+    let mut files = Vec::new();
+    // Safe to use unwrap() because the option is required()
+    for path in matches.values_of("files").unwrap() {
+        collect_files(Path::new(path), None, &mut files)?;
+    }
+
+    // Find files already in the archive with the same content
+    let mut mla = open_mla_file(matches)?;
+    let mut unchanged = HashSet::new();
+    let hash_algorithm = mla.get_info().hash_algorithm;
+    for (fname, path) in &files {
+        let entry = match mla.get_entry_info(fname)? {
+            Some(entry) => entry,
+            None => continue,
+        };
+        if entry.size != fs::metadata(path)?.len() {
+            continue;
+        }
+        let mut hasher = FileHasher::new(hash_algorithm);
+        io::copy(&mut File::open(path)?, &mut hasher)?;
+        if entry.hash == hasher.finalize() {
+            unchanged.insert(fname.clone());
+        }
+    }
+    // If files are only added, the existing data can be kept
+    let only_added = mla.list_files()?.all(|fname| unchanged.contains(fname));
+
+    let reader_config = readerconfig_from_matches(matches);
+    // Safe to use unwrap() because the option is required()
+    let src = File::open(matches.value_of("input").unwrap())?;
+
+    // Move the reader in, so the input archive is closed before being replaced
+    rewrite_input_archive(matches, move |destination| {
+        let mut mla_out = if only_added {
+            ArchiveWriter::from_archive(destination, config, src, reader_config)?
+        } else {
+            let mut mla_out =
+                ArchiveWriter::from_archive_config(destination, config, src, reader_config)?;
+            linear_copy(&mut mla, &mut mla_out, |fname| {
+                if unchanged.contains(fname) {
+                    Some(fname.to_string())
+                } else {
+                    None
+                }
+            })?;
+            mla_out
+        };
+
+        // Add new and modified files
+        for (fname, path) in &files {
+            if unchanged.contains(fname) {
+                continue;
+            }
+            info!("{}", fname);
+            let file = File::open(path)?;
+            let length = file.metadata()?.len();
+            mla_out.add_file(fname, length, file)?;
+        }
+        mla_out.finalize()
+    })
+}
+
+/// Make `value` usable as a CSV (`,` separator) or TSV (`\t` separator) field
+fn escape_field(value: &str, separator: char) -> String {
+    if separator == '\t' {
+        // TSV has no quoting; escape the characters that would break the row
+        value
+            .replace('\\', "\\\\")
+            .replace('\t', "\\t")
+            .replace('\n', "\\n")
+            .replace('\r', "\\r")
+    } else if value.contains([separator, '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Entry of `list --format json`
+#[derive(Serialize)]
+struct ListEntry {
+    name: String,
+    size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hash_algorithm: Option<&'static str>,
+    /// MIME type, with --detect
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    content_type: Option<&'static str>,
+}
+
+fn list(matches: &ArgMatches) -> Result<(), Error> {
+    let mut mla = open_mla_file(matches)?;
+    let file_name_matcher = FileNameMatcher::from_matches(matches);
+
+    let mut iter: Vec<String> = mla
+        .list_files()?
+        .filter(|fname| file_name_matcher.match_file_name(fname))
+        .cloned()
+        .collect();
+    iter.sort();
+
+    let separator = match matches.value_of("format") {
+        Some("csv") => Some(','),
+        Some("tsv") => Some('\t'),
+        _ => None,
+    };
+    // Safe to use unwrap() because the option has a default value
+    let fields: Vec<&str> = matches.values_of("fields").unwrap().collect();
+    if let Some(separator) = separator {
+        println!("{}", fields.join(&separator.to_string()));
+    }
+
+    // Sizes, hashes and metadata are read at once, much faster than one by
+    // one
+    let entries_needed = separator.is_some()
+        || matches.value_of("format") == Some("json")
+        || matches.is_present("verbose");
+    let entries: HashMap<String, EntryInfo> = if entries_needed {
+        mla.iter_entries()?
+            .map(|entry| (entry.name.clone(), entry))
+            .collect()
+    } else {
+        HashMap::new()
+    };
+    let get_entry = |fname: &str| entries.get(fname).expect("Unable to get the file");
+    let get_hash = |fname: &str| hex::encode(&get_entry(fname).hash);
+    let hash_algorithm = mla.get_info().hash_algorithm;
+    // Only the first bytes of each file are read
+    let detect = match separator {
+        Some(_) => fields.contains(&"type"),
+        None => matches.is_present("detect"),
+    };
+
+    for fname in iter {
+        let content_type = if detect {
+            let head = mla
+                .read_file_head(&fname, detect::DETECT_SIZE)?
+                .expect("Unable to get the file");
+            Some(detect::detect_type(&head))
+        } else {
+            None
+        };
+        if let Some(separator) = separator {
+            // Fields of unrecorded metadata are left empty
+            let metadata = get_entry(&fname).metadata.clone().unwrap_or_default();
+            let mut row = Vec::with_capacity(fields.len());
+            for field in &fields {
+                let value = match *field {
+                    "name" => fname.clone(),
+                    "size" => get_entry(&fname).size.to_string(),
+                    "compressed_size" => mla
+                        .get_compressed_size(&fname)?
+                        .expect("Unable to get the compressed size")
+                        .to_string(),
+                    "hash" => get_hash(&fname),
+                    "offset" => get_entry(&fname)
+                        .offsets
+                        .first()
+                        .map(|offset| offset.to_string())
+                        .unwrap_or_default(),
+                    "mtime" => metadata
+                        .mtime
+                        .map(|mtime| mtime.to_string())
+                        .unwrap_or_default(),
+                    "mode" => metadata
+                        .mode
+                        .map(|mode| format!("{:o}", mode))
+                        .unwrap_or_default(),
+                    "uid" => metadata.uid.map(|uid| uid.to_string()).unwrap_or_default(),
+                    "gid" => metadata.gid.map(|gid| gid.to_string()).unwrap_or_default(),
+                    "btime" => metadata
+                        .btime
+                        .map(|btime| btime.to_string())
+                        .unwrap_or_default(),
+                    "attributes" => metadata
+                        .attributes
+                        .map(|attributes| format!("{:x}", attributes))
+                        .unwrap_or_default(),
+                    // Safe to unwrap, as detected when this field is requested
+                    "type" => content_type.unwrap().to_string(),
+                    _ => unreachable!("Unknown field, refused by clap"),
+                };
+                row.push(escape_field(&value, separator));
+            }
+            println!("{}", row.join(&separator.to_string()));
+        } else if matches.value_of("format") == Some("json") {
+            // One object per line, the hash being included as for -vv
+            let size = get_entry(&fname).size;
+            let (hash, hash_algorithm) = if matches.occurrences_of("verbose") >= 2 {
+                (Some(get_hash(&fname)), Some(hash_algorithm.name()))
+            } else {
+                (None, None)
+            };
+            let entry = ListEntry {
+                name: fname,
+                size,
+                hash,
+                hash_algorithm,
+                content_type,
+            };
+            println!(
+                "{}",
+                serde_json::to_string(&entry).expect("Unable to serialize the entry")
+            );
+        } else if matches.is_present("verbose") {
+            let size = get_entry(&fname)
+                .size
+                .file_size(file_size_opts::CONVENTIONAL)
+                .unwrap();
+            let size = match content_type {
+                Some(content_type) => format!("{} - {}", size, content_type),
+                None => size,
+            };
+            if matches.occurrences_of("verbose") == 1 {
+                println!("{} - {}", fname, size);
+            } else if matches.occurrences_of("verbose") >= 2 {
+                println!(
+                    "{} - {} ({}:{})",
+                    fname,
+                    size,
+                    hash_algorithm.name(),
+                    get_hash(&fname)
+                );
+            }
+        } else if let Some(content_type) = content_type {
+            println!("{} - {}", fname, content_type);
+        } else {
+            println!("{}", fname);
+        }
+    }
+    Ok(())
+}
+
+/// Number of worker threads given with `--threads`, 1 by default
+fn threads_from_matches(matches: &ArgMatches) -> usize {
+    let threads = match matches.value_of("threads") {
+        Some(threads) => threads
+            .parse()
+            .expect("[ERROR] The number of threads must be an int"),
+        None => 1,
+    };
+    if threads == 0 {
+        panic!("[ERROR] The number of threads must not be 0");
+    }
+    threads
+}
+
+/// Options of `mlar extract` applying to each file
+#[derive(Clone)]
+struct ExtractOptions {
+    /// Print the name of each file extracted
+    verbose: bool,
+    /// Restore the recorded permissions of each file, see
+    /// `restore_permissions`
+    preserve_permissions: bool,
+    /// Restore the recorded extended attributes of each file, see
+    /// `restore_xattrs`
+    xattrs: bool,
+    /// Number of leading components removed from the file names, as with
+    /// `tar --strip-components`
+    strip_components: usize,
+    /// Handling of the files already existing in the output directory
+    overwrite: OverwritePolicy,
+    /// If set, files are extracted directly in the output directory, under
+    /// these names (see `flat_names`)
+    flat_names: Option<HashMap<String, String>>,
+    /// Write each file under a temporary name, renamed once complete (see
+    /// `create_file`)
+    atomic: bool,
+}
+
+impl ExtractOptions {
+    /// Name, relative to the output directory, under which `fname` is
+    /// extracted, if any
+    ///
+    /// Files with no more than `strip_components` components are skipped
+    fn extracted_name(&self, fname: &str) -> Option<String> {
+        if let Some(flat_names) = &self.flat_names {
+            return flat_names.get(fname).cloned();
+        }
+        if self.strip_components == 0 {
+            return Some(fname.to_string());
+        }
+        let stripped: PathBuf = Path::new(fname)
+            .components()
+            .filter(|part| {
+                !matches!(
+                    part,
+                    Component::Prefix(..) | Component::RootDir | Component::CurDir
+                )
+            })
+            .skip(self.strip_components)
+            .collect();
+        if stripped.as_os_str().is_empty() {
+            debug!(
+                "Skipping file \"{}\" as it has no more than {} components",
+                fname, self.strip_components
+            );
+            return None;
+        }
+        Some(stripped.to_string_lossy().into_owned())
+    }
+
+    /// Last modification time of `fname` in `mla`, if needed to decide
+    /// whether an existing file is replaced
+    fn archived_mtime<'a, R: 'a + Read + Seek>(
+        &self,
+        mla: &mut ArchiveReader<'a, R>,
+        fname: &str,
+    ) -> Result<Option<i64>, Error> {
+        if self.overwrite.overwrite != Overwrite::Older {
+            return Ok(None);
+        }
+        Ok(mla
+            .get_file_metadata(fname)?
+            .and_then(|metadata| metadata.mtime))
+    }
+}
+
+/// Name of each file of `fnames` once extracted without its directories
+///
+/// Files with the same name get a `_N` suffix, before their extension, in the
+/// order of `fnames`. Files without a name, such as `..`, are skipped
+fn flat_names(fnames: &[String]) -> HashMap<String, String> {
+    let mut used = HashSet::new();
+    let mut flat_names = HashMap::new();
+    for fname in fnames {
+        let path = Path::new(fname);
+        let name = match path.file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => {
+                warn!("Skipping file \"{}\" because it has no name", fname);
+                continue;
+            }
+        };
+        let mut flat_name = name.clone();
+        let mut index = 1;
+        while used.contains(&flat_name) {
+            flat_name = match (path.file_stem(), path.extension()) {
+                (Some(stem), Some(extension)) => format!(
+                    "{}_{}.{}",
+                    stem.to_string_lossy(),
+                    index,
+                    extension.to_string_lossy()
+                ),
+                _ => format!("{}_{}", name, index),
+            };
+            index += 1;
+        }
+        used.insert(flat_name.clone());
+        flat_names.insert(fname.clone(), flat_name);
+    }
+    flat_names
+}
+
+/// Restore on the extracted `path` the metadata recorded for `fname`, as
+/// requested in `options`
+fn restore_metadata<'a, R: 'a + Read + Seek>(
+    mla: &mut ArchiveReader<'a, R>,
+    fname: &str,
+    path: &Path,
+    options: &ExtractOptions,
+) -> Result<(), Error> {
+    if !options.preserve_permissions && !options.xattrs {
+        return Ok(());
+    }
+    match mla.get_file_metadata(fname)? {
+        Some(metadata) => apply_metadata(&metadata, path, options),
+        None => Ok(()),
+    }
+}
+
+/// Restore the recorded `metadata` on the extracted `path`, as requested in
+/// `options`
+fn apply_metadata(
+    metadata: &FileMetadata,
+    path: &Path,
+    options: &ExtractOptions,
+) -> Result<(), Error> {
+    // Before the permissions, which may forbid writing them
+    if options.xattrs {
+        restore_xattrs(path, &metadata.xattrs);
+    }
+    if options.preserve_permissions {
+        if let Some(mode) = metadata.mode {
+            restore_permissions(path, mode)?;
+        }
+        if let Some(attributes) = metadata.attributes {
+            restore_attributes(path, attributes)?;
+        }
+    }
+    Ok(())
+}
+
+/// Set the Windows file `attributes` on the extracted `path`
+///
+/// Only the ones a user can set are restored: read-only, hidden, system,
+/// archive and not content indexed
+#[cfg(windows)]
+fn restore_attributes(path: &Path, attributes: u32) -> Result<(), Error> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::{
+        SetFileAttributesW, FILE_ATTRIBUTE_ARCHIVE, FILE_ATTRIBUTE_HIDDEN, FILE_ATTRIBUTE_NORMAL,
+        FILE_ATTRIBUTE_NOT_CONTENT_INDEXED, FILE_ATTRIBUTE_READONLY, FILE_ATTRIBUTE_SYSTEM,
+    };
+
+    let attributes = attributes
+        & (FILE_ATTRIBUTE_READONLY
+            | FILE_ATTRIBUTE_HIDDEN
+            | FILE_ATTRIBUTE_SYSTEM
+            | FILE_ATTRIBUTE_ARCHIVE
+            | FILE_ATTRIBUTE_NOT_CONTENT_INDEXED);
+    // "Normal" is only valid alone
+    let attributes = if attributes == 0 {
+        FILE_ATTRIBUTE_NORMAL
+    } else {
+        attributes
+    };
+    let wide_path: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    if unsafe { SetFileAttributesW(wide_path.as_ptr(), attributes) } == 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+/// Windows file attributes are only restored on Windows
+#[cfg(not(windows))]
+fn restore_attributes(_path: &Path, _attributes: u32) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Set the permissions `mode` on the extracted `path`
+///
+/// The setuid and setgid bits are never restored, as the extracted files
+/// belong to the user extracting them
+#[cfg(unix)]
+fn restore_permissions(path: &Path, mode: u32) -> Result<(), Error> {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o1777))?;
+    Ok(())
+}
+
+/// Set the permissions `mode` on the extracted `path`
+///
+/// Only the read-only flag can be restored
+#[cfg(not(unix))]
+fn restore_permissions(path: &Path, mode: u32) -> Result<(), Error> {
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_readonly(mode & 0o200 == 0);
+    fs::set_permissions(path, permissions)?;
+    Ok(())
+}
+
+/// Set the extended attributes `xattrs` on the extracted `path`
+///
+/// The ones which can not be set, for instance without the required
+/// privileges or on a filesystem without their support, are skipped with a
+/// warning
+#[cfg(unix)]
+fn restore_xattrs(path: &Path, xattrs: &BTreeMap<Vec<u8>, Vec<u8>>) {
+    use std::os::unix::ffi::OsStrExt;
+
+    for (name, value) in xattrs {
+        let name = OsStr::from_bytes(name);
+        if let Err(err) = xattr::set(path, name, value) {
+            warn!(
+                "Unable to set the extended attribute {:?} of {:?} ({})",
+                name, path, err
+            );
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn restore_xattrs(path: &Path, xattrs: &BTreeMap<Vec<u8>, Vec<u8>>) {
+    if !xattrs.is_empty() {
+        warn!(
+            "Extended attributes of {:?} ignored, as they are only supported on Unix",
+            path
+        );
+    }
+}
+
+/// Whether the content of the files of `mla`, opened from `path`, can be
+/// copied straight from the archive file: the archive has no layer, and is a
+/// single local file
+fn is_raw_input<'a, R: 'a + Read + Seek>(
+    mla: &ArchiveReader<'a, R>,
+    path: &Path,
+) -> Result<bool, Error> {
+    if !mla.get_info().layers_enabled.is_empty() {
+        return Ok(false);
+    }
+    match File::open(path) {
+        Ok(mut file) => Ok(!is_volume(&mut file)?),
+        // Such as a remote archive
+        Err(_) => Ok(false),
+    }
+}
+
+/// Copy the `ranges` of the archive file `src` to `dest`
+///
+/// On Linux, `io::copy` between files relies on `copy_file_range` or
+/// `sendfile`, so the content is not copied through userspace buffers
+fn copy_ranges(
+    src: &File,
+    ranges: &[(u64, u64)],
+    dest: &mut File,
+    progress: &ProgressBar,
+) -> io::Result<()> {
+    let mut src = src;
+    for (start, end) in ranges {
+        src.seek(SeekFrom::Start(*start))?;
+        let copied = io::copy(&mut src.take(end - start), dest)?;
+        if copied != end - start {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "archive file is smaller than expected",
+            ));
+        }
+        progress.inc(copied);
+    }
+    Ok(())
+}
+
+/// Extract the file `fname` of `mla` in `output_dir`
+///
+/// If `raw_src` is given, the archive file `mla` reads, the content of the
+/// file is copied straight from it when possible (see `is_raw_input`)
+fn extract_file<'a, R: 'a + Read + Seek>(
+    mla: &mut ArchiveReader<'a, R>,
+    fname: &str,
+    output_dir: &Path,
+    progress: &ProgressBar,
+    options: &ExtractOptions,
+    raw_src: Option<&File>,
+) -> Result<(), Error> {
+    // On error, the file is extracted, or reported, through `get_file`
+    let ranges = raw_src.and_then(|_| mla.get_file_ranges(fname).ok().flatten());
+    let mtime = options.archived_mtime(mla, fname)?;
+    // Look for the file in the archive
+    let mut sub_file = match mla.get_file(fname.to_string()) {
+        Err(err) => {
+            error!("Error while looking up subfile \"{}\" ({:?})", fname, err);
+            return Ok(());
+        }
+        Ok(None) => {
+            error!(
+                "Subfile \"{}\" indexed in metadata could not be found",
+                fname
+            );
+            return Ok(());
+        }
+        Ok(Some(subfile)) => subfile,
+    };
+    let extracted_name = match options.extracted_name(fname) {
+        Some(name) => name,
+        None => return Ok(()),
+    };
+    let (mut extracted_file, path) = match create_file(
+        output_dir,
+        &extracted_name,
+        &options.overwrite,
+        mtime,
+        options.atomic,
+    )? {
+        Some(file) => file,
+        None => return Ok(()),
+    };
+
+    if options.verbose {
+        progress.suspend(|| println!("{}", fname));
+    }
+    progress.set_message(fname.to_string());
+    match (raw_src, ranges) {
+        (Some(src), Some(ranges)) => copy_ranges(src, &ranges, &mut extracted_file, progress),
+        _ => io::copy(
+            &mut progress.wrap_read(&mut sub_file.data),
+            &mut extracted_file,
+        )
+        .map(|_| ()),
+    }
+    .map_err(|err| {
+        error!("Unable to extract \"{}\" ({:?})", fname, err);
+        err
+    })?;
+    // Once written, as the file may become read-only
+    drop(sub_file);
+    drop(extracted_file);
+    if options.atomic {
+        commit_partial(&path, &options.overwrite)?;
+    }
+    restore_metadata(mla, fname, &path, options)?;
+    Ok(())
+}
+
+/// Extract the files `fnames` in `output_dir` with `threads` workers, each
+/// with its own reader from `readers`, and its own handle on `raw_input` if
+/// given (see `extract_file`)
+fn parallel_extract<F: Fn() -> Result<Box<dyn ReadSeek>, Error> + Sync>(
+    readers: &ArchiveReaderPool<F>,
+    fnames: &[String],
+    output_dir: &Path,
+    progress: &ProgressBar,
+    options: &ExtractOptions,
+    threads: usize,
+    raw_input: Option<&Path>,
+) -> Result<(), Error> {
+    // Index of the next file to extract, shared by the workers
+    let next = &AtomicUsize::new(0);
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(move || -> Result<(), Error> {
+                    let mut mla = readers.reader()?;
+                    let raw_src = raw_input.map(File::open).transpose()?;
+                    while let Some(fname) = fnames.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        extract_file(
+                            &mut mla,
+                            fname,
+                            output_dir,
+                            progress,
+                            options,
+                            raw_src.as_ref(),
+                        )?;
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+        workers.into_iter().try_for_each(|worker| {
+            worker
+                .join()
+                .expect("[ERROR] An extraction thread panicked")
+        })
+    })
+}
+
+/// Report where each file of `fnames` would be extracted in `output_dir`,
+/// with `options`, and which ones would be skipped, without writing anything
+///
+/// Unlike an actual extraction, directories of the output directory which
+/// are symbolic links are not resolved
+fn dry_run_extract(fnames: &[String], output_dir: &Path, options: &ExtractOptions) {
+    // Extracted path -> archived name
+    let mut extracted_paths: HashMap<PathBuf, &String> = HashMap::new();
+    let mut skipped = 0;
+    for fname in fnames {
+        let path = options
+            .extracted_name(fname)
+            .ok_or("no name left")
+            .and_then(|name| {
+                get_extracted_path(output_dir, &name).ok_or("outside of the output directory")
+            });
+        let path = match path {
+            Ok(path) => path,
+            Err(reason) => {
+                println!("{} skipped: {}", fname, reason);
+                skipped += 1;
+                continue;
+            }
+        };
+        if let Some(other) = extracted_paths.get(&path) {
+            println!(
+                "{} skipped: extracted as {} by {}",
+                fname,
+                path.display(),
+                other
+            );
+            skipped += 1;
+            continue;
+        }
+        if fs::symlink_metadata(&path).is_ok() {
+            if options.overwrite.overwrite == Overwrite::Never {
+                println!("{} skipped: {} already exists", fname, path.display());
+                skipped += 1;
+                continue;
+            }
+            println!("{} -> {} (already exists)", fname, path.display());
+        } else {
+            println!("{} -> {}", fname, path.display());
+        }
+        extracted_paths.insert(path, fname);
+    }
+    println!(
+        "{} files would be extracted, {} skipped",
+        extracted_paths.len(),
+        skipped
+    );
+}
+
+fn extract(matches: &ArgMatches) -> Result<(), Error> {
+    let file_name_matcher = FileNameMatcher::from_matches(matches);
+    let output_dir = output_path(matches.value_of_os("outputdir").unwrap());
+    let verbose = matches.is_present("verbose");
+    let mut options = ExtractOptions {
+        verbose,
+        preserve_permissions: matches.is_present("preserve_permissions"),
+        xattrs: matches.is_present("xattrs"),
+        strip_components: matches
+            .value_of("strip_components")
+            .map(|count| {
+                count
+                    .parse()
+                    .expect("[ERROR] Invalid --strip-components value")
+            })
+            .unwrap_or(0),
+        overwrite: OverwritePolicy::from_matches(matches),
+        flat_names: None,
+        atomic: matches.is_present("atomic"),
+    };
+    if matches.is_present("stream") {
+        return stream_extract(matches, &file_name_matcher, &output_dir, &options);
+    }
+    let threads = threads_from_matches(matches);
+
+    // Safe to use unwrap() because the option is required()
+    let input = Path::new(matches.value_of("input").unwrap());
+    // A reader is opened per thread
+    let uring = uring_from_matches(matches);
+    let readers = ArchiveReaderPool::new(
+        move || {
+            if uring {
+                uring_input(input)
+            } else {
+                open_input(input)
+            }
+        },
+        readerconfig_from_matches(matches),
+    );
+    let mut mla = readers.reader()?;
+
+    // Filter files according to glob patterns or files given as parameters
+    let mut iter: Vec<String> = mla
+        .list_files()?
+        .filter(|fname| file_name_matcher.match_file_name(fname))
+        .cloned()
+        .collect();
+    iter.sort();
+    if matches.is_present("flat") {
+        options.flat_names = Some(flat_names(&iter));
+    }
+
+    if matches.is_present("dry_run") {
+        dry_run_extract(&iter, &output_dir, &options);
+        return Ok(());
+    }
+    let output_dir = create_output_dir(&output_dir)?;
+
+    // Without layers, the content of the files is copied straight from the
+    // archive file, which is faster than a linear extraction
+    let raw_input = if !uring && is_raw_input(&mla, input)? {
+        if verbose {
+            println!("Copying the files content straight from the archive file");
+        }
+        Some(input)
+    } else {
+        None
+    };
+
+    if let (FileNameMatcher::Anything, 1, None) = (&file_name_matcher, threads, raw_input) {
+        // Optimisation: use linear extraction
+        if verbose {
+            println!("Extracting the whole archive using a linear extraction");
+        }
+        let pool = Rc::new(RefCell::new(FilePool::new(
+            matches
+                .value_of("max_open_files")
+                .map(|count| {
+                    count
+                        .parse()
+                        .expect("[ERROR] Invalid --max-open-files value")
+                })
+                .unwrap_or(DEFAULT_MAX_OPEN_FILES),
+        )));
+        let mut export: HashMap<&String, FileWriter> = HashMap::new();
+        // Path of each file, once complete
+        let mut extracted_paths: Vec<(&String, PathBuf)> = Vec::new();
+        // As the writers append to their file, two files with the same
+        // extracted name would be mixed
+        let mut extracted_names = HashSet::new();
+        for fname in &iter {
+            let extracted_name = match options.extracted_name(fname) {
+                Some(name) => name,
+                None => continue,
+            };
+            if !extracted_names.insert(extracted_name.clone()) {
+                warn!(
+                    "Skipping file \"{}\" because another file is already extracted as \"{}\"",
+                    fname, extracted_name
+                );
+                continue;
+            }
+            let mtime = options.archived_mtime(&mut mla, fname)?;
+            match create_file(
+                &output_dir,
+                &extracted_name,
+                &options.overwrite,
+                mtime,
+                options.atomic,
+            )? {
+                Some((_file, path)) => {
+                    let written_path = if options.atomic {
+                        partial_path(&path)
+                    } else {
+                        path.clone()
+                    };
+                    export.insert(
+                        fname,
+                        FileWriter {
+                            path: written_path,
+                            pool: pool.clone(),
+                        },
+                    );
+                    extracted_paths.push((fname, path));
                 }
+                None => continue,
+            }
+        }
+        let progress = progress_bar_from_matches(matches, mla.get_files_size()?);
+        linear_extract_with_progress(&mut mla, &mut export, |fname, count| {
+            progress.set_message(fname.to_string());
+            progress.inc(count);
+        })?;
+        progress.finish_and_clear();
+        // Once every file is written and closed, as they may become read-only
+        pool.borrow_mut().close_all();
+        for (fname, path) in &extracted_paths {
+            if options.atomic {
+                commit_partial(path, &options.overwrite)?;
+            }
+            restore_metadata(&mut mla, fname, path, &options)?;
+        }
+        return Ok(());
+    }
+
+    let mut total = 0;
+    for fname in &iter {
+        if let Some(sub_file) = mla.get_file(fname.clone())? {
+            total += sub_file.size;
+        }
+    }
+    let progress = if options.overwrite.overwrite == Overwrite::Prompt {
+        // It would be drawn over the questions
+        ProgressBar::hidden()
+    } else {
+        progress_bar_from_matches(matches, total)
+    };
+
+    if threads > 1 {
+        // Files are extracted in any order, each one by a single worker
+        parallel_extract(
+            &readers,
+            &iter,
+            &output_dir,
+            &progress,
+            &options,
+            threads,
+            raw_input,
+        )?;
+    } else {
+        let raw_src = raw_input.map(File::open).transpose()?;
+        for fname in &iter {
+            extract_file(
+                &mut mla,
+                fname,
+                &output_dir,
+                &progress,
+                &options,
+                raw_src.as_ref(),
+            )?;
+        }
+    }
+    progress.finish_and_clear();
+    Ok(())
+}
+
+/// Extract the archive read sequentially from `input`, `-` being stdin, each
+/// file being written as soon as it appears in the archive
+///
+/// The archive index is never read, so it can come from a pipe or a socket
+fn stream_extract(
+    matches: &ArgMatches,
+    file_name_matcher: &FileNameMatcher,
+    output_dir: &Path,
+    options: &ExtractOptions,
+) -> Result<(), Error> {
+    // Safe to use unwrap() because the option is required()
+    let input = matches.value_of("input").unwrap();
+    let src: Box<dyn Read> = if input == "-" {
+        Box::new(io::stdin())
+    } else {
+        Box::new(File::open(input)?)
+    };
+    let mut mla = ArchiveStreamReader::from_config(src, readerconfig_from_matches(matches))?;
+    let output_dir = create_output_dir(output_dir)?;
+
+    // The modification time of a file is only known once it is read: the
+    // files are then compared when committed
+    let older = options.overwrite.overwrite == Overwrite::Older;
+    let open_overwrite = if older {
+        OverwritePolicy {
+            overwrite: Overwrite::Always,
+            ..options.overwrite.clone()
+        }
+    } else {
+        options.overwrite.clone()
+    };
+
+    // As the writers append to their file, two files with the same extracted
+    // name would be mixed
+    let mut extracted_names = HashSet::new();
+    // Files are written atomically, to only replace existing ones once
+    // checked
+    mla.extract(
+        |fname| {
+            if !file_name_matcher.match_file_name(fname) {
+                return Ok(None);
+            }
+            let extracted_name = match options.extracted_name(fname) {
+                Some(name) => name,
+                None => return Ok(None),
+            };
+            if !extracted_names.insert(extracted_name.clone()) {
+                warn!(
+                    "Skipping file \"{}\" because another file is already extracted as \"{}\"",
+                    fname, extracted_name
+                );
+                return Ok(None);
+            }
+            if options.verbose {
+                println!("{}", fname);
+            }
+            Ok(
+                create_file(&output_dir, &extracted_name, &open_overwrite, None, true)?.map(
+                    |(file, path)| PartialFile {
+                        file: Some(file),
+                        path,
+                        committed: false,
+                    },
+                ),
+            )
+        },
+        |fname, partial, metadata| {
+            if older
+                && fs::symlink_metadata(&partial.path).is_ok()
+                && !options.overwrite.allows_replacing(
+                    &partial.path,
+                    fname,
+                    metadata.and_then(|metadata| metadata.mtime),
+                )?
+            {
+                return Ok(());
+            }
+            let path = partial.commit(&options.overwrite)?;
+            if let Some(metadata) = metadata {
+                apply_metadata(metadata, &path, options)?;
+            }
+            Ok(())
+        },
+    )?;
+    Ok(())
+}
+
+/// Part of a file displayed by `mlar cat`
+#[derive(Clone, Copy)]
+enum CatRange {
+    All,
+    HeadBytes(u64),
+    HeadLines(u64),
+    TailBytes(u64),
+    TailLines(u64),
+}
+
+impl CatRange {
+    fn from_matches(matches: &ArgMatches) -> Self {
+        let count = |name| {
+            matches.value_of(name).map(|count| {
+                count
+                    .parse::<u64>()
+                    .unwrap_or_else(|_| panic!("[ERROR] Invalid --{} value {:?}", name, count))
+            })
+        };
+        let bytes = matches.is_present("bytes");
+        match (count("head"), count("tail")) {
+            (Some(count), _) if bytes => CatRange::HeadBytes(count),
+            (Some(count), _) => CatRange::HeadLines(count),
+            (None, Some(count)) if bytes => CatRange::TailBytes(count),
+            (None, Some(count)) => CatRange::TailLines(count),
+            (None, None) => CatRange::All,
+        }
+    }
+}
+
+/// Amount of data read at once by `tail_lines_start`, backward from the end
+/// of the file
+const TAIL_CHUNK_SIZE: u64 = 64 * 1024;
+
+/// Offset, in `data` of `size` bytes, of the start of its last `count` lines
+///
+/// The file is read backward from its end, by chunks, until enough lines are
+/// found. A final newline does not start an empty line, as with `tail`
+fn tail_lines_start<R: Read + Seek>(data: &mut R, size: u64, count: u64) -> io::Result<u64> {
+    if count == 0 {
+        return Ok(size);
+    }
+    let mut found = 0;
+    let mut chunk = Vec::new();
+    let mut end = size;
+    while end > 0 {
+        let start = end.saturating_sub(TAIL_CHUNK_SIZE);
+        data.seek(io::SeekFrom::Start(start))?;
+        chunk.resize((end - start) as usize, 0);
+        data.read_exact(&mut chunk)?;
+        for (index, byte) in chunk.iter().enumerate().rev() {
+            let offset = start + index as u64;
+            if *byte == b'\n' && offset != size - 1 {
+                found += 1;
+                if found == count {
+                    return Ok(offset + 1);
+                }
+            }
+        }
+        end = start;
+    }
+    Ok(0)
+}
+
+/// Write the `range` part of `data`, of `size` bytes, to `destination`
+fn cat_range<R: Read + Seek, W: Write>(
+    mut data: R,
+    size: u64,
+    range: CatRange,
+    destination: &mut W,
+) -> io::Result<()> {
+    match range {
+        CatRange::All => {
+            io::copy(&mut data, destination)?;
+        }
+        CatRange::HeadBytes(count) => {
+            io::copy(&mut data.take(count), destination)?;
+        }
+        CatRange::HeadLines(count) => {
+            let mut reader = io::BufReader::new(data);
+            let mut line = Vec::new();
+            for _ in 0..count {
+                line.clear();
+                if reader.read_until(b'\n', &mut line)? == 0 {
+                    break;
+                }
+                destination.write_all(&line)?;
+            }
+        }
+        CatRange::TailBytes(count) => {
+            // Seeking near the end only walks the headers of the preceding
+            // blocks, instead of decompressing them
+            data.seek(io::SeekFrom::Start(size.saturating_sub(count)))?;
+            io::copy(&mut data, destination)?;
+        }
+        CatRange::TailLines(count) => {
+            let start = tail_lines_start(&mut data, size, count)?;
+            data.seek(io::SeekFrom::Start(start))?;
+            io::copy(&mut data, destination)?;
+        }
+    }
+    Ok(())
+}
+
+/// Delimitation of the files displayed one after the other by `mlar cat`
+struct CatFraming {
+    /// Write a `==> NAME <==` line before each file
+    filename_header: bool,
+    /// Written between two files
+    separator: Option<Vec<u8>>,
+    /// At least one file has already been written
+    started: bool,
+}
+
+impl CatFraming {
+    fn from_matches(matches: &ArgMatches) -> Self {
+        CatFraming {
+            filename_header: matches.is_present("filename_header"),
+            separator: matches.value_of("separator").map(unescape_separator),
+            started: false,
+        }
+    }
+
+    /// Write what comes before the content of `fname`
+    fn begin<W: Write>(&mut self, destination: &mut W, fname: &str) -> io::Result<()> {
+        if self.started {
+            if let Some(separator) = &self.separator {
+                destination.write_all(separator)?;
+            }
+        }
+        self.started = true;
+        if self.filename_header {
+            writeln!(destination, "==> {} <==", fname)?;
+        }
+        Ok(())
+    }
+}
+
+/// Bytes of a `--separator` value, in which `\n`, `\t`, `\0` and `\\` stand
+/// for a newline, a tab, a NUL byte and a backslash
+fn unescape_separator(separator: &str) -> Vec<u8> {
+    let mut unescaped = String::with_capacity(separator.len());
+    let mut chars = separator.chars();
+    while let Some(c) = chars.next() {
+        let c = if c == '\\' {
+            match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('0') => '\0',
+                Some('\\') => '\\',
+                other => panic!(
+                    "[ERROR] Invalid escape sequence \"\\{}\" in separator",
+                    other.map(String::from).unwrap_or_default()
+                ),
+            }
+        } else {
+            c
+        };
+        unescaped.push(c);
+    }
+    unescaped.into_bytes()
+}
+
+/// Name in `archive_files`, sorted, equal to `fname` when ignoring the case
+///
+/// `fname` itself is preferred, and is returned if there is no such name
+fn find_ignoring_case<'a>(archive_files: &'a [String], fname: &'a str) -> &'a str {
+    if archive_files
+        .binary_search_by(|name| name.as_str().cmp(fname))
+        .is_ok()
+    {
+        return fname;
+    }
+    let lowercase = fname.to_lowercase();
+    archive_files
+        .iter()
+        .find(|name| name.to_lowercase() == lowercase)
+        .map(|name| name.as_str())
+        .unwrap_or(fname)
+}
+
+fn cat(matches: &ArgMatches) -> Result<(), Error> {
+    let files_values = matches.values_of("files").unwrap();
+    let output = matches.value_of("output").unwrap();
+    let range = CatRange::from_matches(matches);
+    let mut framing = CatFraming::from_matches(matches);
+    let ignore_case = matches.is_present("ignore_case");
+    let mut destination = destination_from_output_argument(output)?;
+
+    let mut mla = open_mla_file(matches)?;
+    let mut archive_files: Vec<String> = mla.list_files()?.cloned().collect();
+    archive_files.sort();
+    let syntax = NameSyntax::from_matches(matches);
+    if let NameSyntax::Glob | NameSyntax::Regex = syntax {
+        // For each patterns, enumerate matching files and display them
+        let options = glob_match_options(ignore_case);
+        for arg_pattern in files_values {
+            let pat: Box<dyn Fn(&str) -> bool> = match syntax {
+                NameSyntax::Regex => match name_regex(arg_pattern, ignore_case) {
+                    Ok(pat) => Box::new(move |fname| pat.is_match(fname)),
+                    Err(err) => {
+                        error!("Invalid regular expression {:?} ({})", arg_pattern, err);
+                        continue;
+                    }
+                },
+                _ => match Pattern::new(arg_pattern) {
+                    Ok(pat) => Box::new(move |fname| pat.matches_with(fname, options)),
+                    Err(err) => {
+                        error!("Invalid glob pattern {:?} ({:?})", arg_pattern, err);
+                        continue;
+                    }
+                },
             };
             for fname in archive_files.iter() {
-                if !pat.matches(fname) {
+                if !pat(fname) {
                     continue;
                 }
                 match mla.get_file(fname.to_string()) {
                     Err(err) => {
-                        eprintln!(" [!] Error while looking up file \"{}\" ({:?})", fname, err);
+                        error!("Error while looking up file \"{}\" ({:?})", fname, err);
                         continue;
                     }
                     Ok(None) => {
-                        eprintln!(
-                            " [!] Subfile \"{}\" indexed in metadata could not be found",
+                        error!(
+                            "Subfile \"{}\" indexed in metadata could not be found",
                             fname
                         );
                         continue;
                     }
-                    Ok(Some(mut subfile)) => {
-                        io::copy(&mut subfile.data, &mut destination).map_err(|err| {
-                            eprintln!(" [!] Unable to extract \"{}\" ({:?})", fname, err);
-                            err
-                        })?;
+                    Ok(Some(subfile)) => {
+                        framing.begin(&mut destination, fname)?;
+                        cat_range(subfile.data, subfile.size, range, &mut destination).map_err(
+                            |err| {
+                                error!("Unable to extract \"{}\" ({:?})", fname, err);
+                                err
+                            },
+                        )?;
                     }
                 }
             }
@@ -579,20 +3092,28 @@ fn cat(matches: &ArgMatches) -> Result<(), Error> {
     } else {
         // Retrieve all the files that are specified
         for fname in files_values {
+            let fname = if ignore_case {
+                find_ignoring_case(&archive_files, fname)
+            } else {
+                fname
+            };
             match mla.get_file(fname.to_string()) {
                 Err(err) => {
-                    eprintln!(" [!] Error while looking up file \"{}\" ({:?})", fname, err);
+                    error!("Error while looking up file \"{}\" ({:?})", fname, err);
                     continue;
                 }
                 Ok(None) => {
-                    eprintln!(" [!] File not found: \"{}\"", fname);
+                    error!("File not found: \"{}\"", fname);
                     continue;
                 }
-                Ok(Some(mut subfile)) => {
-                    io::copy(&mut subfile.data, &mut destination).map_err(|err| {
-                        eprintln!(" [!] Unable to extract \"{}\" ({:?})", fname, err);
-                        err
-                    })?;
+                Ok(Some(subfile)) => {
+                    framing.begin(&mut destination, fname)?;
+                    cat_range(subfile.data, subfile.size, range, &mut destination).map_err(
+                        |err| {
+                            error!("Unable to extract \"{}\" ({:?})", fname, err);
+                            err
+                        },
+                    )?;
                 }
             }
         }
@@ -600,6 +3121,215 @@ fn cat(matches: &ArgMatches) -> Result<(), Error> {
     Ok(())
 }
 
+/// Lines longer than this size, such as in binary files, are searched by
+/// `mlar grep` in chunks of this size
+const GREP_CHUNK_SIZE: u64 = 1024 * 1024;
+
+fn grep(matches: &ArgMatches) -> Result<(), Error> {
+    // Safe to use unwrap() because the argument is required()
+    let pattern = matches.value_of("pattern").unwrap();
+    let hex = matches.is_present("hex");
+    let pattern = if hex {
+        let bytes = hex::decode(pattern).expect("[ERROR] Invalid hexadecimal pattern");
+        bytes
+            .iter()
+            .map(|byte| format!("\\x{:02x}", byte))
+            .collect()
+    } else if matches.is_present("fixed_strings") {
+        regex::escape(pattern)
+    } else {
+        pattern.to_string()
+    };
+    let regex = regex::bytes::RegexBuilder::new(&pattern)
+        .case_insensitive(matches.is_present("ignore_case"))
+        // Escaped bytes are raw bytes, not Unicode code points
+        .unicode(!hex)
+        .build()
+        .unwrap_or_else(|err| panic!("[ERROR] Invalid pattern ({})", err));
+
+    let mut mla = open_mla_file(matches)?;
+    let matcher = FileNameMatcher::from_matches(matches);
+    // In the archive order, to avoid seeking back and forth
+    let fnames: Vec<String> = mla
+        .list_files_archive_order()?
+        .into_iter()
+        .filter(|fname| matcher.match_file_name(fname))
+        .cloned()
+        .collect();
+
+    let stdout = io::stdout();
+    let mut output = stdout.lock();
+    let mut found = false;
+    let mut line = Vec::new();
+    for fname in fnames {
+        let file = match mla.get_file(fname.clone())? {
+            Some(file) => file,
+            None => {
+                error!(
+                    "Subfile \"{}\" indexed in metadata could not be found",
+                    fname
+                );
+                continue;
+            }
+        };
+        let mut reader = file.into_buf_reader();
+        // Offset of `line` in the file
+        let mut offset = 0;
+        loop {
+            line.clear();
+            let length = (&mut reader)
+                .take(GREP_CHUNK_SIZE)
+                .read_until(b'\n', &mut line)?;
+            if length == 0 {
+                break;
+            }
+            for found_match in regex.find_iter(&line) {
+                found = true;
+                writeln!(
+                    output,
+                    "{}:{}:{}",
+                    fname,
+                    offset + found_match.start() as u64,
+                    String::from_utf8_lossy(found_match.as_bytes())
+                )?;
+            }
+            offset += length as u64;
+        }
+    }
+    if !found {
+        // As grep
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Store alongside the archive written to `output` a timestamp token of its
+/// content, obtained from the TSA at `url`
+fn timestamp_output(output: &str, url: &str) -> Result<(), Error> {
+    if output == "-" {
+        warn!("An archive written to stdout can not be timestamped");
+        return Ok(());
+    }
+    let path = output_path(output);
+    let token = timestamp::request(url, &timestamp::file_digest(&path)?)?;
+    let token_path = timestamp::token_path(&path);
+    fs::write(&token_path, token)?;
+    info!("Timestamp token written to {}", token_path.display());
+    Ok(())
+}
+
+/// Check the timestamp token of the input archive, given with `--timestamp`
+/// or stored alongside it
+///
+/// Return None if there is no token to check
+fn check_timestamp(matches: &ArgMatches) -> Option<Result<timestamp::TimestampInfo, String>> {
+    // Safe to use unwrap() because the option is required()
+    let input = Path::new(matches.value_of("input").unwrap());
+    let token_path = match matches.value_of("timestamp") {
+        Some(token_path) => PathBuf::from(token_path),
+        None => {
+            let token_path = timestamp::token_path(input);
+            if !token_path.is_file() {
+                return None;
+            }
+            token_path
+        }
+    };
+    let check = || {
+        let token = fs::read(&token_path)
+            .map_err(|err| format!("unable to read {}: {}", token_path.display(), err))?;
+        let info = timestamp::parse(&token).map_err(|err| format!("invalid token: {:?}", err))?;
+        let digest = timestamp::file_digest(input)
+            .map_err(|err| format!("unable to read the archive: {:?}", err))?;
+        if info.digest != digest {
+            return Err("hash mismatch".to_string());
+        }
+        Ok(info)
+    };
+    Some(check())
+}
+
+fn verify(matches: &ArgMatches) -> Result<(), Error> {
+    let mut mla = open_mla_file(matches)?;
+
+    let mut fnames: Vec<String> = mla.list_files()?.cloned().collect();
+    fnames.sort();
+
+    // Read back each file, and compare it to its stored hash
+    let hash_algorithm = mla.get_info().hash_algorithm;
+    let mut failures = 0;
+    for fname in &fnames {
+        let expected_hash = match mla.get_hash(fname) {
+            Ok(Some(hash)) => hash,
+            Ok(None) => {
+                println!("{}: FAIL (hash not found)", fname);
+                failures += 1;
+                continue;
+            }
+            Err(err) => {
+                println!("{}: FAIL (unable to read the hash: {:?})", fname, err);
+                failures += 1;
+                continue;
+            }
+        };
+        let mut subfile = match mla.get_file(fname.to_string()) {
+            Ok(Some(subfile)) => subfile,
+            Ok(None) => {
+                println!("{}: FAIL (file not found)", fname);
+                failures += 1;
+                continue;
+            }
+            Err(err) => {
+                println!("{}: FAIL (unable to open the file: {:?})", fname, err);
+                failures += 1;
+                continue;
+            }
+        };
+        let mut hasher = FileHasher::new(hash_algorithm);
+        if let Err(err) = io::copy(&mut subfile.data, &mut hasher) {
+            println!("{}: FAIL (unable to read the file: {:?})", fname, err);
+            failures += 1;
+            continue;
+        }
+        if hasher.finalize() == expected_hash {
+            println!("{}: OK", fname);
+        } else {
+            println!("{}: FAIL (hash mismatch)", fname);
+            failures += 1;
+        }
+    }
+
+    // The timestamp token, if any, must refer to the whole archive
+    let timestamp_failed = match check_timestamp(matches) {
+        Some(Ok(info)) => {
+            println!("Timestamp: OK ({})", info.time());
+            false
+        }
+        Some(Err(err)) => {
+            println!("Timestamp: FAIL ({})", err);
+            true
+        }
+        None => false,
+    };
+
+    if failures > 0 {
+        warn!("{} of {} files failed verification", failures, fnames.len());
+    }
+    if timestamp_failed {
+        warn!("The timestamp token failed verification");
+    }
+    if failures > 0 || timestamp_failed {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Convert the archive to TAR, written sequentially so that it can be
+/// streamed
+///
+/// As a TAR entry must be written at once, files are read one after the other
+/// with `get_file` rather than through `linear_extract`. The archive is then
+/// only read without seeking back if its files are not interleaved
 fn to_tar(matches: &ArgMatches) -> Result<(), Error> {
     let mut mla = open_mla_file(matches)?;
 
@@ -608,46 +3338,219 @@ fn to_tar(matches: &ArgMatches) -> Result<(), Error> {
     let destination = destination_from_output_argument(output)?;
     let mut tar_file = Builder::new(destination);
 
+    // Follow the archive order, so that the archive is read linearly, unless
+    // files are interleaved
+    let archive_files: Vec<String> = mla
+        .list_files_archive_order()?
+        .into_iter()
+        .cloned()
+        .collect();
+    for fname in archive_files {
+        let sub_file = match mla.get_file(fname.clone()) {
+            Err(err) => {
+                error!("Error while looking up subfile \"{}\" ({:?})", fname, err);
+                continue;
+            }
+            Ok(None) => {
+                error!(
+                    "Subfile \"{}\" indexed in metadata could not be found",
+                    fname
+                );
+                continue;
+            }
+            Ok(Some(subfile)) => subfile,
+        };
+        if let Err(err) = add_file_to_tar(&mut tar_file, sub_file) {
+            error!("Unable to add subfile \"{}\" ({:?})", fname, err);
+        }
+    }
+    Ok(())
+}
+
+fn to_zip(matches: &ArgMatches) -> Result<(), Error> {
+    let mut mla = open_mla_file(matches)?;
+
+    let password;
+    let mut options: ZipFileOptions<()> = ZipFileOptions::default();
+    if let Some(password_file) = matches.value_of("password_file") {
+        password = read_password_file(password_file)?;
+        options = options.with_aes_encryption(AesMode::Aes256, &password);
+    }
+
+    // Safe to use unwrap() because the option is required()
+    let output = matches.value_of("output").unwrap();
+    let mut zip_file = ZipWriter::new(File::create(output_path(output))?);
+
     let mut archive_files: Vec<String> = mla.list_files()?.cloned().collect();
     archive_files.sort();
     for fname in archive_files {
         let sub_file = match mla.get_file(fname.clone()) {
             Err(err) => {
-                eprintln!(
-                    " [!] Error while looking up subfile \"{}\" ({:?})",
-                    fname, err
-                );
+                error!("Error while looking up subfile \"{}\" ({:?})", fname, err);
                 continue;
             }
             Ok(None) => {
-                eprintln!(
-                    " [!] Subfile \"{}\" indexed in metadata could not be found",
+                error!(
+                    "Subfile \"{}\" indexed in metadata could not be found",
                     fname
                 );
                 continue;
             }
             Ok(Some(subfile)) => subfile,
         };
-        if let Err(err) = add_file_to_tar(&mut tar_file, sub_file) {
-            eprintln!(" [!] Unable to add subfile \"{}\" ({:?})", fname, err);
+        if let Err(err) = add_file_to_zip(&mut zip_file, sub_file, options) {
+            error!("Unable to add subfile \"{}\" ({:?})", fname, err);
+        }
+    }
+    if let Err(why) = zip_file.finish() {
+        panic!("Error while finishing zip archive: {}", why);
+    }
+    Ok(())
+}
+
+fn from_tar(matches: &ArgMatches) -> Result<(), Error> {
+    // Safe to use unwrap() because the option is required()
+    let input = matches.value_of("input").unwrap();
+    let src: Box<dyn Read> = if input == "-" {
+        Box::new(io::stdin())
+    } else {
+        Box::new(File::open(input)?)
+    };
+    let mut tar_file = Archive::new(src);
+    let mut mla = writer_from_matches(matches)?;
+
+    // Entries are streamed in the TAR order, without seeking
+    for entry in tar_file.entries()? {
+        let entry = entry?;
+        let entry_type = entry.header().entry_type();
+        if entry_type == EntryType::Directory {
+            // Directories are implied by the files they contain
+            continue;
+        }
+        let path = entry.path()?.into_owned();
+        let filename = match path.to_str() {
+            Some(filename) => filename.to_string(),
+            None => {
+                warn!("{:?} ignored, only UTF-8 paths are supported", path);
+                continue;
+            }
+        };
+        if !entry_type.is_file() {
+            warn!("{} ignored, only regular files are supported", filename);
+            continue;
+        }
+        info!("{}", filename);
+        let size = entry.size();
+        mla.add_file(&filename, size, entry)?;
+    }
+
+    mla.finalize()?;
+    Ok(())
+}
+
+fn from_zip(matches: &ArgMatches) -> Result<(), Error> {
+    // Safe to use unwrap() because the option is required()
+    let input = matches.value_of("input").unwrap();
+    let mut zip_file = match ZipArchive::new(File::open(input)?) {
+        Ok(zip_file) => zip_file,
+        Err(why) => panic!("[ERROR] Unable to open zip archive {}: {}", input, why),
+    };
+    let password = match matches.value_of("password_file") {
+        Some(password_file) => Some(read_password_file(password_file)?),
+        None => None,
+    };
+    let mut mla = writer_from_matches(matches)?;
+
+    for index in 0..zip_file.len() {
+        let entry = match &password {
+            Some(password) => zip_file.by_index_decrypt(index, password.as_bytes()),
+            None => zip_file.by_index(index),
+        };
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(why) => panic!("[ERROR] Unable to read zip entry {}: {}", index, why),
+        };
+        if entry.is_dir() {
+            // Directories are implied by the files they contain
+            continue;
+        }
+        let filename = entry.name().to_string();
+        if !entry.is_file() {
+            warn!("{} ignored, only regular files are supported", filename);
+            continue;
         }
+        info!("{}", filename);
+        let size = entry.size();
+        mla.add_file(&filename, size, entry)?;
     }
+
+    mla.finalize()?;
     Ok(())
 }
 
 fn repair(matches: &ArgMatches) -> Result<(), Error> {
     let mut mla = open_failsafe_mla_file(matches)?;
-    let mut mla_out = writer_from_matches(matches)?;
+    let file_name_matcher = FileNameMatcher::from_matches(matches);
 
-    // Convert
-    let status = mla.convert_to_archive(&mut mla_out)?;
+    // Recover only the matching files, skipping the content of the others
+    let mut report = if matches.is_present("extract") {
+        // Safe to use unwrap() because the option is required()
+        let output_dir = create_output_dir(&output_path(matches.value_of_os("output").unwrap()))?;
+        // Recovered files replace the existing ones
+        let overwrite = OverwritePolicy {
+            overwrite: Overwrite::Always,
+            backup_suffix: None,
+        };
+        mla.extract_with_report(|fname| {
+            if !file_name_matcher.match_file_name(fname) {
+                return Ok(None);
+            }
+            Ok(create_file(&output_dir, fname, &overwrite, None, false)?.map(|(file, _path)| file))
+        })?
+    } else {
+        let mut mla_out = writer_from_matches(matches)?;
+        mla.convert_to_archive_filtered(&mut mla_out, |fname| {
+            file_name_matcher.match_file_name(fname)
+        })?
+    };
+
+    // Files of the archive index, if it is still readable, and not found are
+    // missing. Keys given through file descriptors can only be read once
+    if matches.values_of("private_key_fd").is_none() {
+        match open_mla_file(matches).and_then(|mla| {
+            let mut fnames: Vec<String> = mla
+                .list_files()?
+                .filter(|fname| file_name_matcher.match_file_name(fname))
+                .cloned()
+                .collect();
+            fnames.sort();
+            Ok(fnames)
+        }) {
+            Ok(fnames) => report.add_missing(&fnames),
+            Err(err) => debug!("Unable to read the archive index ({})", err),
+        }
+    }
+
+    // Report what survived, file per file
+    for (fname, status) in &report.files {
+        match status {
+            FileRecoveryStatus::Recovered => println!("{}: OK", fname),
+            FileRecoveryStatus::Truncated(size) => {
+                println!("{}: TRUNCATED (at byte {})", fname, size)
+            }
+            FileRecoveryStatus::Corrupted => println!("{}: CORRUPTED (hash mismatch)", fname),
+            FileRecoveryStatus::Missing => println!("{}: MISSING", fname),
+        }
+    }
+
+    let status = report.status;
     match status {
         FailSafeReadError::NoError => {}
         FailSafeReadError::EndOfOriginalArchiveData => {
-            eprintln!("[WARNING] The whole archive has been recovered");
+            warn!("The whole archive has been recovered");
         }
         _ => {
-            eprintln!("[WARNING] Conversion ends with {}", status);
+            warn!("Conversion ends with {}", status);
         }
     };
     Ok(())
@@ -664,17 +3567,21 @@ fn convert(matches: &ArgMatches) -> Result<(), Error> {
     fnames.sort();
 
     let mut mla_out = writer_from_matches(matches)?;
+    let progress = progress_bar_from_matches(matches, mla.get_files_size()?);
+    let progress_callback = progress.clone();
+    mla_out.set_progress_callback(move |count| progress_callback.inc(count));
 
     // Convert
     for fname in fnames {
-        eprintln!("{}", fname);
+        progress.suspend(|| info!("{}", fname));
+        progress.set_message(fname.clone());
         let sub_file = match mla.get_file(fname.clone()) {
             Err(err) => {
-                eprintln!("Error while adding {} ({:?})", fname, err);
+                error!("Error while adding {} ({:?})", fname, err);
                 continue;
             }
             Ok(None) => {
-                eprintln!("Unable to found {}", fname);
+                error!("Unable to found {}", fname);
                 continue;
             }
             Ok(Some(mla)) => mla,
@@ -682,94 +3589,370 @@ fn convert(matches: &ArgMatches) -> Result<(), Error> {
         mla_out.add_file(&sub_file.filename, sub_file.size, sub_file.data)?;
     }
     mla_out.finalize().expect("Finalization error");
+    progress.finish_and_clear();
+
+    Ok(())
+}
+
+fn remove(matches: &ArgMatches) -> Result<(), Error> {
+    let mut mla = open_mla_file(matches)?;
+    let mut mla_out = writer_from_matches(matches)?;
+    let file_name_matcher = FileNameMatcher::from_matches(matches);
+
+    // Copy every files, except the matching ones
+    linear_copy(&mut mla, &mut mla_out, |fname| {
+        if file_name_matcher.match_file_name(fname) {
+            None
+        } else {
+            info!("{}", fname);
+            Some(fname.to_string())
+        }
+    })?;
+    mla_out.finalize()?;
+    Ok(())
+}
+
+fn merge(matches: &ArgMatches) -> Result<(), Error> {
+    let mut mla_out = writer_from_matches(matches)?;
+    // Safe to use unwrap() because the option has a default value
+    let on_collision = matches.value_of("on_collision").unwrap();
+
+    // Names already used in the output archive
+    let mut used_names = HashSet::new();
+    // Safe to use unwrap() because the option is required()
+    for mla_file in matches.values_of("input").unwrap() {
+        let path = Path::new(mla_file);
+        let mut mla =
+            ArchiveReader::from_config(File::open(path)?, readerconfig_from_matches(matches))?;
+
+        // Resolve collisions before copying anything
+        let mut fnames: Vec<String> = mla.list_files()?.cloned().collect();
+        fnames.sort();
+        let mut old2new: HashMap<String, String> = HashMap::new();
+        for fname in fnames {
+            let new_name = if !used_names.contains(&fname) {
+                fname.clone()
+            } else {
+                match on_collision {
+                    "skip" => {
+                        warn!(
+                            "{} from {} skipped, already in the archive",
+                            fname, mla_file
+                        );
+                        continue;
+                    }
+                    "prefix" => {
+                        // Prefix with the archive name, without its extension
+                        let prefix = path.file_stem().unwrap_or_default().to_string_lossy();
+                        format!("{}/{}", prefix, fname)
+                    }
+                    _ => {
+                        error!("{} from {} is already in the archive", fname, mla_file);
+                        return Err(Error::DuplicateFilename);
+                    }
+                }
+            };
+            if !used_names.insert(new_name.clone()) {
+                error!("{} from {} is already in the archive", new_name, mla_file);
+                return Err(Error::DuplicateFilename);
+            }
+            old2new.insert(fname, new_name);
+        }
+
+        linear_copy(&mut mla, &mut mla_out, |fname| {
+            let new_name = old2new.get(fname)?;
+            info!("{}", new_name);
+            Some(new_name.clone())
+        })?;
+    }
+    mla_out.finalize()?;
+    Ok(())
+}
+
+fn split(matches: &ArgMatches) -> Result<(), Error> {
+    // Safe to use unwrap() because the options are required()
+    let src = File::open(matches.value_of("input").unwrap())?;
+    let output = output_path(matches.value_of_os("output").unwrap());
+    let volume_size = parse_size(matches.value_of("size").unwrap());
+    let stem = match output.file_name() {
+        Some(stem) => stem,
+        None => panic!("[ERROR] Invalid output path {}", output.display()),
+    };
+
+    split_volumes(src, volume_size, |index, _count| {
+        let path = output.with_file_name(volume_file_name(stem, index + 1));
+        info!("{}", path.display());
+        Ok(File::create(path)?)
+    })?;
+    Ok(())
+}
+
+fn join(matches: &ArgMatches) -> Result<(), Error> {
+    // Safe to use unwrap() because the options are required()
+    let mut volumes = Vec::new();
+    for path in matches.values_of_os("volumes").unwrap() {
+        volumes.push(File::open(path)?);
+    }
+    let mut destination = destination_from_output_argument(matches.value_of("output").unwrap())?;
+
+    io::copy(&mut VolumesReader::new(volumes)?, &mut destination)?;
+    Ok(())
+}
+
+#[cfg(feature = "mount")]
+fn mount(matches: &ArgMatches) -> Result<(), Error> {
+    let mla = open_mla_file(matches)?;
+    // Safe to use unwrap() because the option is required()
+    let mountpoint = Path::new(matches.value_of_os("mountpoint").unwrap());
+    mount::mount(mla, mountpoint)
+}
+
+#[cfg(not(feature = "mount"))]
+fn mount(_matches: &ArgMatches) -> Result<(), Error> {
+    panic!("[ERROR] mlar must be built with the \"mount\" feature to mount archives");
+}
+
+fn serve(matches: &ArgMatches) -> Result<(), Error> {
+    // Safe to use unwrap() because the option is required()
+    let input = Path::new(matches.value_of("input").unwrap());
+    // A reader is opened per thread
+    let readers = ArchiveReaderPool::new(|| open_input(input), readerconfig_from_matches(matches));
+    // Safe to use unwrap() because the option has a default value
+    serve::serve(
+        &readers,
+        matches.value_of("bind").unwrap(),
+        threads_from_matches(matches),
+    )
+}
+
+fn rename(matches: &ArgMatches) -> Result<(), Error> {
+    // Safe to use unwrap() because the option is required()
+    let names: Vec<&str> = matches.values_of("names").unwrap().collect();
+    let mut old2new: HashMap<&str, &str> = HashMap::new();
+    for pair in names.chunks(2) {
+        if pair.len() != 2 {
+            panic!("[ERROR] Names must be given as pairs: old_name new_name");
+        }
+        old2new.insert(pair[0], pair[1]);
+    }
+
+    let mut mla = open_mla_file(matches)?;
+    for old_name in old2new.keys() {
+        if !mla.list_files()?.any(|fname| fname == old_name) {
+            warn!("{} is not in the archive", old_name);
+        }
+    }
+    let mut mla_out = writer_from_matches(matches)?;
 
+    // Copy every files, with their new name if any
+    linear_copy(&mut mla, &mut mla_out, |fname| {
+        let new_name = old2new.get(fname).copied().unwrap_or(fname);
+        info!("{}", new_name);
+        Some(new_name.to_string())
+    })?;
+    mla_out.finalize()?;
     Ok(())
 }
 
+/// Get the keypair output format, from `--format` and `--combined`
+fn keypair_format<'a>(matches: &'a ArgMatches) -> (&'a str, bool) {
+    let combined = matches.is_present("combined");
+    // A combined file is made of text, so the private key defaults to PEM
+    let format = match matches.value_of("format") {
+        Some(format) => format,
+        None if combined => "pem",
+        None => "der",
+    };
+    if combined && (format == "der" || format == "raw") {
+        panic!("[ERROR] --combined requires a text format for the private key (pem or openssh)");
+    }
+    (format, combined)
+}
+
+/// Encode `key_pair` in `format` ("der", "pem" or "raw"), and return the
+/// private and public keys
+fn encode_keypair(key_pair: &KeyPair, format: &str) -> (Vec<u8>, Vec<u8>) {
+    match format {
+        // Raw X25519 keys, for interoperability with other ECDH tools
+        "raw" => (
+            key_pair.private_as_raw().to_vec(),
+            key_pair.public_as_raw().to_vec(),
+        ),
+        // Output the public key in PEM format, to ease integration in text
+        // based configs
+        "pem" => (
+            key_pair.private_as_pem().into_bytes(),
+            key_pair.public_as_pem().into_bytes(),
+        ),
+        // Output the private key in DER format by default, to avoid common
+        // mistakes
+        _ => (
+            key_pair.private_der.to_vec(),
+            key_pair.public_as_pem().into_bytes(),
+        ),
+    }
+}
+
+/// Write the `private` key in `output_base`, and the `public` key in
+/// `output_base`.pub, or after the private key if `combined`
+fn write_keypair(output_base: &OsStr, private: &[u8], public: &[u8], combined: bool) {
+    // The private key must only be readable by its owner (OpenSSH refuses it
+    // otherwise)
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut output_priv = options
+        .open(output_base)
+        .expect("Unable to create the private file");
+    // The mode only applies to new files: an existing one is restricted before
+    // the key is written to it
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        output_priv
+            .set_permissions(fs::Permissions::from_mode(0o600))
+            .expect("Unable to restrict the private file permissions");
+    }
+    output_priv
+        .write_all(private)
+        .expect("Error writing the private key");
+    let mut output_pub = if combined {
+        output_priv
+    } else {
+        File::create(Path::new(output_base).with_extension("pub"))
+            .expect("Unable to create the public file")
+    };
+    output_pub
+        .write_all(public)
+        .expect("Error writing the public key");
+}
+
 fn keygen(matches: &ArgMatches) -> Result<(), Error> {
     // Safe to use unwrap() because of the requirement
     let output_base = matches.value_of_os("output").unwrap();
-
-    let mut output_pub = File::create(Path::new(output_base).with_extension("pub"))
-        .expect("Unable to create the public file");
-    let mut output_priv = File::create(output_base).expect("Unable to create the private file");
+    let (format, combined) = keypair_format(matches);
 
     let mut csprng = ChaChaRng::from_entropy();
-    let key_pair = generate_keypair(&mut csprng).expect("Error while generating the key-pair");
+    let (private, public) = if format == "hybrid" {
+        let private_key = HybridPrivateKey::generate(&mut csprng);
+        let private = pem::Pem {
+            tag: HYBRID_PRIVATE_TAG.to_string(),
+            contents: private_key.to_bytes().to_vec(),
+        };
+        let public = pem::Pem {
+            tag: HYBRID_PUBLIC_TAG.to_string(),
+            contents: private_key.public_key().to_bytes(),
+        };
+        (
+            pem::encode(&private).into_bytes(),
+            pem::encode(&public).into_bytes(),
+        )
+    } else if format == "openssh" {
+        // OpenSSH only supports Ed25519 keys
+        let key_pair = generate_ed25519_keypair(&mut csprng);
+        (
+            key_pair.private_as_openssh().into_bytes(),
+            key_pair.public_as_openssh().into_bytes(),
+        )
+    } else {
+        let key_pair = generate_keypair(&mut csprng).expect("Error while generating the key-pair");
+        encode_keypair(&key_pair, format)
+    };
+    write_keypair(output_base, &private, &public, combined);
+    Ok(())
+}
 
-    // Output the public key in PEM format, to ease integration in text based
-    // configs
-    output_pub
-        .write_all(&key_pair.public_as_pem().as_bytes())
-        .expect("Error writing the public key");
+fn keyderive(matches: &ArgMatches) -> Result<(), Error> {
+    // Safe to use unwrap() because of the requirement
+    let output_base = matches.value_of_os("output").unwrap();
+    let path: Vec<&str> = matches.value_of("path").unwrap().split('/').collect();
+    let (format, combined) = keypair_format(matches);
 
-    // Output the private key in DER format, to avoid common mistakes
-    output_priv
-        .write_all(&key_pair.private_der)
-        .expect("Error writing the private key");
+    let (mut private_keys, hybrid_keys) = open_ecc_private_keys(matches)?;
+    if private_keys.len() != 1 || !hybrid_keys.is_empty() {
+        panic!("[ERROR] Exactly one root private key is expected");
+    }
+    // Safe to use unwrap() because of the length check
+    let root = private_keys.pop().unwrap();
+
+    let derived = derive_private_key(&root, &path)?;
+    let (private, public) = encode_keypair(&KeyPair::from(&derived), format);
+    write_keypair(output_base, &private, &public, combined);
     Ok(())
 }
 
-pub struct ArchiveInfoReader {
-    /// MLA Archive format Reader
+/// Path of the detached signature of `input`: the "signature" argument, or
+/// else `{input}.sig`
+fn signature_path(matches: &ArgMatches, input: &OsStr) -> OsString {
+    match matches.value_of_os("signature") {
+        Some(path) => path.to_os_string(),
+        None => {
+            let mut path = input.to_os_string();
+            path.push(".sig");
+            path
+        }
+    }
+}
 
-    /// User's reading configuration
-    pub config: ArchiveReaderConfig,
-    /// Compressed sizes from CompressionLayer
-    pub compressed_size: Option<u64>,
-    /// Metadata (from footer if any)
-    metadata: Option<ArchiveFooter>,
+/// Hash of the whole archive file, as signed in detached signatures
+fn detached_signature_hash(input: &OsStr) -> Result<Sha512, Error> {
+    let mut hash = Sha512::new();
+    io::copy(&mut File::open(input)?, &mut hash)?;
+    Ok(hash)
 }
 
-impl ArchiveInfoReader {
-    pub fn from_config<'a, R>(mut src: R, mut config: ArchiveReaderConfig) -> Result<Self, Error>
-    where
-        R: 'a + Read + Seek,
-    {
-        // Make sure we read the archive header from the start
-        src.seek(SeekFrom::Start(0))?;
-        let header = ArchiveHeader::from(&mut src)?;
-        config.load_persistent(header.config)?;
-
-        // Pin the current position (after header) as the new 0
-        let mut raw_src = Box::new(RawLayerReader::new(src));
-        raw_src.reset_position()?;
-
-        // Enable layers depending on user option. Order is relevant
-        let mut src: Box<dyn 'a + LayerReader<'a, R>> = raw_src;
-        if config.layers_enabled.contains(Layers::ENCRYPT) {
-            src = Box::new(EncryptionLayerReader::new(src, &config.encrypt)?)
-        }
-        let compressed_size = if config.layers_enabled.contains(Layers::COMPRESS) {
-            let mut src_compress = Box::new(CompressionLayerReader::new(src)?);
-            src_compress.initialize()?;
-            let size = src_compress
-                .sizes_info
-                .as_ref()
-                .map(|v| v.get_compressed_size());
-            src = src_compress;
-            size
-        } else {
-            src.initialize()?;
-            None
-        };
+fn sign(matches: &ArgMatches) -> Result<(), Error> {
+    // Safe to use unwrap() because the options are required()
+    let input = matches.value_of_os("input").unwrap();
+    let secret = open_signing_key(matches.value_of_os("sign_key").unwrap())?;
+    let public = ed25519_dalek::PublicKey::from(&secret);
+    let keypair = ed25519_dalek::Keypair { secret, public };
 
-        let metadata = Some(ArchiveFooter::deserialize_from(&mut src)?);
+    let signature = keypair
+        .sign_prehashed(
+            detached_signature_hash(input)?,
+            Some(DETACHED_SIGNATURE_CONTEXT),
+        )
+        .map_err(|_| Error::InvalidSignature)?;
+    let signature = pem::Pem {
+        tag: SIGNATURE_TAG.to_string(),
+        contents: signature.to_bytes().to_vec(),
+    };
+    fs::write(signature_path(matches, input), pem::encode(&signature))?;
+    Ok(())
+}
 
-        src.seek(SeekFrom::Start(0))?;
-        Ok(ArchiveInfoReader {
-            config,
-            compressed_size,
-            metadata,
-        })
-    }
+fn verify_signature(matches: &ArgMatches) -> Result<(), Error> {
+    // Safe to use unwrap() because the option is required()
+    let input = matches.value_of_os("input").unwrap();
+    let trusted_keys = open_trusted_keys(matches)?;
 
-    pub fn get_files_size(&self) -> Result<u64, Error> {
-        if let Some(ArchiveFooter { files_info, .. }) = &self.metadata {
-            Ok(files_info.values().map(|f| f.size).sum())
-        } else {
-            Err(Error::MissingMetadata)
+    let buf = fs::read(signature_path(matches, input))?;
+    let signature = find_pem_block(&buf, SIGNATURE_TAG)
+        .and_then(|contents| ed25519_dalek::Signature::try_from(contents.as_slice()).ok())
+        .ok_or(Error::InvalidSignature)?;
+
+    // The archive is only read once, whatever the number of trusted keys
+    let hash = detached_signature_hash(input)?;
+    let signer = trusted_keys.iter().find(|public_key| {
+        public_key
+            .verify_prehashed(hash.clone(), Some(DETACHED_SIGNATURE_CONTEXT), &signature)
+            .is_ok()
+    });
+    match signer {
+        Some(public_key) => {
+            println!(
+                "{}: OK (signed by {})",
+                input.to_string_lossy(),
+                hex::encode(public_key.as_bytes())
+            );
+            Ok(())
         }
+        None => Err(Error::InvalidSignature),
     }
 }
 
@@ -777,18 +3960,22 @@ fn info(matches: &ArgMatches) -> Result<(), Error> {
     // Safe to use unwrap() because the option is required()
     let mla_file = matches.value_of("input").unwrap();
     let path = Path::new(&mla_file);
-    let mut file = File::open(&path)?;
+    let mut file = open_input(path)?;
 
     // Get Header
     let header = ArchiveHeader::from(&mut file)?;
 
     let encryption = header.config.layers_enabled.contains(Layers::ENCRYPT);
     let compression = header.config.layers_enabled.contains(Layers::COMPRESS);
+    let signature = header.config.layers_enabled.contains(Layers::SIGN);
+    let fec = header.config.layers_enabled.contains(Layers::FEC);
 
-    // Instantiate reader as needed
-    let mla = if compression {
-        let config = readerconfig_from_matches(matches);
-        Some(ArchiveInfoReader::from_config(file, config)?)
+    // Layers details and files are only reachable if the archive can be read
+    let mla = if !encryption
+        || keys_given(matches, "private_keys", "private_key_fd", PRIVATE_KEY_ENV)
+        || password_given(matches)
+    {
+        Some(open_mla_file(matches)?)
     } else {
         None
     };
@@ -796,6 +3983,11 @@ fn info(matches: &ArgMatches) -> Result<(), Error> {
     // Format Version
     println!("Format version: {}", header.format_version);
 
+    // Comment, only reported if there is one
+    if let Some(comment) = &header.config.comment {
+        println!("Comment: {}", comment);
+    }
+
     // Encryption config
     println!("Encryption: {}", encryption);
     if encryption && matches.is_present("verbose") {
@@ -804,52 +3996,289 @@ fn info(matches: &ArgMatches) -> Result<(), Error> {
             "  Recipients: {}",
             encrypt_config.multi_recipient.count_keys()
         );
+        if let Some(threshold) = encrypt_config.threshold {
+            println!("  Threshold: {}", threshold);
+        }
+        if let Some(hybrid) = &encrypt_config.hybrid {
+            println!(
+                "  Hybrid recipients: {} ({:?})",
+                hybrid.count_keys(),
+                hybrid.kem
+            );
+        }
+        if let Some(escrow) = &encrypt_config.escrow {
+            for public_key in &escrow.public_keys {
+                println!("  Escrow key: {}", hex::encode(public_key));
+            }
+        }
+        if let Some(key_hints) = &encrypt_config.key_hints {
+            for key_hint in key_hints {
+                println!("  Key hint: {}", hex::encode(key_hint));
+            }
+        }
+        if encrypt_config.password.is_some() {
+            println!("  Password: yes");
+        }
+        println!(
+            "  Cipher: {}",
+            match encrypt_config.cipher {
+                Cipher::AesGcm256 => "aes-256-gcm",
+                Cipher::ChaCha20Poly1305 => "chacha20-poly1305",
+            }
+        );
+        if let Some(rekey_interval) = encrypt_config.rekey_interval {
+            println!(
+                "  Rekey interval: {}",
+                (rekey_interval as u64 * CHUNK_SIZE)
+                    .file_size(file_size_opts::CONVENTIONAL)
+                    .unwrap()
+            );
+        }
+    }
+
+    // Signature config. The signer is known once the signature is checked
+    println!("Signature: {}", signature);
+    if let Some(signer) = mla.as_ref().and_then(|mla_| mla_.get_info().signer) {
+        println!("  Signer: {}", hex::encode(signer.as_bytes()));
+    }
+
+    // Timestamp token, only reported if there is one
+    match check_timestamp(matches) {
+        Some(Ok(info)) => println!("Timestamp: {}", info.time()),
+        Some(Err(err)) => println!("Timestamp: FAIL ({})", err),
+        None => (),
     }
 
     // Compression config
     println!("Compression: {}", compression);
     if compression && matches.is_present("verbose") {
-        let mla_ = mla.expect("MLA is required for verbose compression info");
+        // Archives without the algorithm are compressed with Brotli
+        let algorithm = header
+            .config
+            .compress
+            .map(|compress| compress.algorithm)
+            .unwrap_or_default();
+        println!(
+            "  Algorithm: {}",
+            match algorithm {
+                CompressionAlgorithm::Brotli => "brotli",
+                CompressionAlgorithm::Lz4 => "lz4",
+                CompressionAlgorithm::Xz => "xz",
+            }
+        );
+        let mla_ = mla
+            .as_ref()
+            .expect("MLA is required for verbose compression info");
+        let compressed_sizes = mla_
+            .get_info()
+            .compressed_sizes
+            .as_ref()
+            .expect("Missing compression sizes");
         let output_size = mla_.get_files_size()?;
-        let compressed_size: u64 = mla_.compressed_size.expect("Missing compression size");
+        let compressed_size: u64 = compressed_sizes.iter().map(|v| *v as u64).sum();
         let compression_rate = output_size as f64 / compressed_size as f64;
+        println!(
+            "  Blocks: {} (of {} uncompressed)",
+            compressed_sizes.len(),
+            UNCOMPRESSED_DATA_SIZE
+                .file_size(file_size_opts::CONVENTIONAL)
+                .unwrap()
+        );
+        println!(
+            "  Compressed size: {}",
+            compressed_size
+                .file_size(file_size_opts::CONVENTIONAL)
+                .unwrap()
+        );
         println!("  Compression rate: {:.2}", compression_rate);
     }
 
+    // Forward error correction config
+    println!("Error correction: {}", fec);
+
+    // Hash of the files, recorded in the header
+    if matches.is_present("verbose") {
+        println!("Hash: {}", header.config.hash_algorithm.name());
+    }
+
+    // Files
+    if let Some(mla_) = mla {
+        println!("Files: {}", mla_.list_files()?.count());
+        println!(
+            "Total size: {}",
+            mla_.get_files_size()?
+                .file_size(file_size_opts::CONVENTIONAL)
+                .unwrap()
+        );
+    }
+
+    Ok(())
+}
+
+/// Compression rate, as uncompressed over compressed size
+fn compression_rate(size: u64, compressed_size: u64) -> String {
+    if compressed_size == 0 {
+        "-".to_string()
+    } else {
+        format!("{:.2}", size as f64 / compressed_size as f64)
+    }
+}
+
+fn stat(matches: &ArgMatches) -> Result<(), Error> {
+    let mut mla = open_mla_file(matches)?;
+    let human = |size: u64| size.file_size(file_size_opts::CONVENTIONAL).unwrap();
+
+    // Compressed sizes are estimated from the footer, without reading the
+    // files content
+    let extents = mla.get_files_extents()?;
+    let mut fnames: Vec<&String> = extents.keys().collect();
+    fnames.sort();
+    let mut total_size = 0;
+    for fname in fnames {
+        let size = mla
+            .get_entry_info(fname)?
+            .expect("Unable to get the file")
+            .size;
+        let compressed_size = mla
+            .get_info()
+            .compressed_size_of(&extents[fname])
+            .unwrap_or(size);
+        total_size += size;
+        println!(
+            "{} - {} -> {} ({})",
+            fname,
+            human(size),
+            human(compressed_size),
+            compression_rate(size, compressed_size)
+        );
+    }
+
+    println!("Files: {}", extents.len());
+    println!("Total size: {}", human(total_size));
+    if let Some(compressed_size) = mla.get_info().compressed_size() {
+        // Including the archive footer, and the other blocks
+        println!("Compressed size: {}", human(compressed_size));
+        println!(
+            "Compression rate: {}",
+            compression_rate(total_size, compressed_size)
+        );
+    }
+    // Safe to use unwrap() because the option is required()
+    if let Ok(metadata) = fs::metadata(matches.value_of("input").unwrap()) {
+        println!("Archive size: {}", human(metadata.len()));
+    }
     Ok(())
 }
 
 fn main() {
-    // Common arguments list, for homogeneity
-    let input_args = vec![
-        Arg::with_name("input")
-            .help("Archive path")
-            .long("input")
-            .short("i")
-            .number_of_values(1)
-            .required(true),
+    // Arguments list, for homogeneity. The password is common to input and
+    // output archives
+    let password_arg = Arg::with_name("password")
+        .long("password")
+        .help("Use a password, given as --password=<PASSWORD>, or prompted for if no value is given. It can also be given in the MLAR_PASSWORD environment variable. On commands writing an archive, it protects the output archive")
+        .takes_value(true)
+        .min_values(0)
+        .require_equals(true);
+    let private_key_args = vec![
         Arg::with_name("private_keys")
             .long("private_keys")
             .short("k")
-            .help("Candidates ED25519 private key paths (DER or PEM format)")
+            .help("Candidates ED25519 or X25519 private key paths (DER, PEM, OpenSSH or raw format)")
             .number_of_values(1)
             .multiple(true)
             .takes_value(true),
+        Arg::with_name("private_key_fd")
+            .long("private-key-fd")
+            .help("Inherited file descriptor to read a candidate private key from. A private key can also be given in the MLAR_PRIVATE_KEY environment variable")
+            .number_of_values(1)
+            .multiple(true)
+            .takes_value(true),
+        Arg::with_name("key_passphrase_file")
+            .long("key-passphrase-file")
+            .help("Read the passphrase of encrypted private keys from the first line of this file. Use - for stdin. Otherwise, it is prompted for")
+            .number_of_values(1),
     ];
-    let layers = ["compress", "encrypt"];
+    let mut input_args = vec![Arg::with_name("input")
+        .help("Archive path")
+        .long("input")
+        .short("i")
+        .number_of_values(1)
+        .required(true)];
+    input_args.extend(private_key_args.iter().cloned());
+    input_args.push(password_arg.clone());
+    input_args.push(
+        Arg::with_name("trusted_keys")
+            .long("trusted-key")
+            .help("Only accept archives signed by one of these Ed25519 public key paths (DER, PEM or OpenSSH format). Otherwise, the signature of a signed archive is checked, whoever the signer is")
+            .number_of_values(1)
+            .multiple(true),
+    );
+    let layers = ["compress", "encrypt", "fec"];
     let output_args = vec![
         Arg::with_name("output")
-            .help("Output file path. Use - for stdout")
+            .help("Output file path. Use - for stdout. With the \"s3\" feature, 'create' also accepts s3://<bucket>/<key>, uploading the archive as it is written")
             .long("output")
             .short("o")
             .takes_value(true)
             .required(true),
         Arg::with_name("public_keys")
-            .help("ED25519 Public key paths (DER or PEM format)")
+            .help("ED25519 or X25519 Public key paths (DER, PEM, OpenSSH or raw format)")
             .long("pubkey")
             .short("p")
             .number_of_values(1)
             .multiple(true),
+        Arg::with_name("public_key_fd")
+            .long("public-key-fd")
+            .help("Inherited file descriptor to read a public key from. A public key can also be given in the MLAR_PUBLIC_KEY environment variable")
+            .number_of_values(1)
+            .multiple(true)
+            .takes_value(true),
+        Arg::with_name("escrow_public_keys")
+            .help("Escrow (recovery) public key paths, always able to read the archive, regardless of the threshold. They are recorded in the archive, and added to the ones of the configuration file")
+            .long("escrow-pubkey")
+            .number_of_values(1)
+            .multiple(true),
+        Arg::with_name("recipient_hints")
+            .long("recipient-hints")
+            .help("Record a short hint of each recipient public key in the archive, so that readers only try their matching private keys, and can tell when the archive is not encrypted to any of them")
+            .conflicts_with("anonymous_recipients"),
+        Arg::with_name("anonymous_recipients")
+            .long("anonymous-recipients")
+            .help("Do not record the escrow public keys in the archive, and shuffle the recipient key slots, so that the archive does not tell who it is encrypted to. Readers try each slot with their keys"),
+        Arg::with_name("threshold")
+            .long("threshold")
+            .help("Number of recipients (given with -p) whose private keys are all required to read the archive. By default, any one of them is enough. It can't be used with a password")
+            .number_of_values(1),
+        Arg::with_name("cipher")
+            .long("cipher")
+            .help("Cipher used to encrypt the data. Default is 'aes-256-gcm'. It is recorded in the archive, so readers do not need it")
+            .possible_values(&["aes-256-gcm", "chacha20-poly1305"])
+            .number_of_values(1),
+        Arg::with_name("rekey_interval")
+            .long("rekey-interval")
+            .help("Derive a new encryption key every SIZE bytes (suffixes K, M, G and T are supported), rounded up to the 128 KiB chunks. It is recorded in the archive")
+            .value_name("SIZE")
+            .number_of_values(1),
+        Arg::with_name("padding")
+            .long("padding")
+            .help("Pad the archive content to a multiple of SIZE bytes (suffixes K, M, G and T are supported), to hide the size of the files. With compression, the archive size is only approximately a multiple of SIZE")
+            .value_name("SIZE")
+            .number_of_values(1),
+        Arg::with_name("checkpoint")
+            .long("checkpoint")
+            .help("Write an index checkpoint every SIZE bytes of file content (suffixes K, M, G and T are supported). Checkpoints let 'repair' recover files whose beginning is damaged")
+            .value_name("SIZE")
+            .conflicts_with("checkpoint_entries")
+            .number_of_values(1),
+        Arg::with_name("checkpoint_entries")
+            .long("checkpoint-entries")
+            .help("Write an index checkpoint every N files added to the archive. Checkpoints let 'repair' recover files whose beginning is damaged")
+            .value_name("N")
+            .number_of_values(1),
+        Arg::with_name("sign_key")
+            .long("sign-key")
+            .help("Sign the archive with this Ed25519 private key path (DER, PEM or OpenSSH format). The signer public key is recorded in the archive")
+            .number_of_values(1),
         Arg::with_name("layers")
             .long("layers")
             .short("l")
@@ -864,69 +4293,471 @@ fn main() {
             .long("compression_level")
             .help("Compression level (0-11); ; bigger values cause denser, but slower compression")
             .takes_value(true),
+        Arg::with_name("compression")
+            .long("compression")
+            .help("Compression algorithm. Default is 'brotli'. 'lz4' is much faster, with a lower compression ratio, and ignores the compression level. 'xz' is slower, with a higher compression ratio, and caps the compression level to 9. It is recorded in the archive, so readers do not need it")
+            .possible_values(&["brotli", "lz4", "xz"])
+            .number_of_values(1),
+        Arg::with_name("store_incompressible")
+            .long("store-incompressible")
+            .help("Store as is the data which barely compresses, such as already compressed or encrypted files, saving the compression time"),
+        Arg::with_name("threads")
+            .long("threads")
+            .help("Number of threads compressing the data. Default is 1. Each thread keeps up to two blocks of 4 MiB in memory")
+            .value_name("N")
+            .number_of_values(1),
+        Arg::with_name("fec_shards")
+            .long("fec-shards")
+            .help("With the 'fec' layer, number of data shards of 4 KiB in each group, and of parity shards protecting them: up to PARITY damaged shards can be rebuilt in each group. Default is 32 and 4. It is recorded in the archive")
+            .value_names(&["DATA", "PARITY"])
+            .number_of_values(2),
+        Arg::with_name("hash")
+            .long("hash")
+            .help("Hash algorithm of the files content. Default is 'sha256'. 'blake3' is much faster on big files. It is recorded in the archive, so readers do not need it")
+            .possible_values(&["sha256", "sha512", "blake3"])
+            .number_of_values(1),
     ];
 
+    let no_progress_arg = Arg::with_name("no_progress")
+        .long("no-progress")
+        .takes_value(false)
+        .help("Do not display a progress bar");
+    let ignore_case_arg = Arg::with_name("ignore_case")
+        .long("ignore-case")
+        .takes_value(false)
+        .help("Match the given files, or patterns, ignoring the case of letters");
+    let regex_arg = Arg::with_name("regex")
+        .long("regex")
+        .takes_value(false)
+        .conflicts_with("glob")
+        .help("Treat specified files as regular expressions, such as '(?i)/Users/[^/]+/AppData/.*\\.evtx$'. They match anywhere in the file names, unless anchored with ^ and $");
+
     // Main parsing
     let mut app = App::new(env!("CARGO_PKG_NAME"))
         .version(env!("CARGO_PKG_VERSION"))
         .about(env!("CARGO_PKG_DESCRIPTION"))
+        .arg(
+            Arg::with_name("verbose")
+                .long("verbose")
+                .short("v")
+                .multiple(true)
+                .takes_value(false)
+                .help("Show debug messages (-vv for trace messages). Must be given before the command"),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .long("quiet")
+                .short("q")
+                .takes_value(false)
+                .conflicts_with("verbose")
+                .help("Only show warnings and errors. Must be given before the command"),
+        )
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .takes_value(true)
+                .help("Configuration file providing defaults for public keys, layers, compression level and output directory, and escrow public keys. Default is ~/.config/mlar/config.toml, if it exists. Must be given before the command"),
+        )
+        .arg(
+            Arg::with_name("log_json")
+                .long("log-json")
+                .takes_value(false)
+                .help("Log messages as JSON objects, one per line. Must be given before the command"),
+        )
         .subcommand(
             SubCommand::with_name("create")
                 .about("Create a new MLA Archive")
                 .args(&output_args)
+                .arg(&password_arg)
+                .arg(&no_progress_arg)
+                .arg(
+                    Arg::with_name("files")
+                        .help("Files to add. Directories are added recursively")
+                        .multiple(true),
+                )
+                .arg(
+                    Arg::with_name("files_from")
+                        .long("files-from")
+                        .short("T")
+                        .help("Read the list of files to add from the given file. Use - for stdin")
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::with_name("io_backend")
+                        .long("io-backend")
+                        .possible_values(&["std", "uring"])
+                        .default_value("std")
+                        .help("I/O backend used for the archive file. 'uring' batches and overlaps reads and writes through io_uring, for large archives on fast storage (Linux only, requires the \"uring\" feature)"),
+                )
+                .arg(
+                    Arg::with_name("null")
+                        .long("null")
+                        .short("0")
+                        .takes_value(false)
+                        .requires("files_from")
+                        .help("Files listed with --files-from are separated by NUL characters instead of newlines (as with find -print0)"),
+                )
+                .arg(
+                    Arg::with_name("exclude")
+                        .long("exclude")
+                        .short("x")
+                        .help("Skip files and directories matching the given glob pattern, on their whole path or their name")
+                        .number_of_values(1)
+                        .multiple(true),
+                )
+                .arg(
+                    Arg::with_name("store_glob")
+                        .long("store-glob")
+                        .help("Store as is, without compressing them, the files matching the given glob pattern, on their whole path or their name (for instance, '*.zip')")
+                        .number_of_values(1)
+                        .multiple(true),
+                )
+                .arg(
+                    Arg::with_name("dedup")
+                        .long("dedup")
+                        .help("Store only once the content of identical files. Files are then read twice, to be hashed first"),
+                )
+                .arg(
+                    Arg::with_name("delta")
+                        .long("delta")
+                        .help("Store only the differences of each file with the previous one of the same name, digits aside (such as rotated logs syslog.1, syslog.2)"),
+                )
+                .arg(
+                    Arg::with_name("deterministic")
+                        .long("deterministic")
+                        .value_name("SEED")
+                        .help("Produce the same archive, byte for byte, from the same files, options and SEED. Files are added sorted by name, and the encryption key, nonce and padding are derived from SEED, which must then be kept secret and not reused for other files")
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::with_name("xattrs")
+                        .long("xattrs")
+                        .takes_value(false)
+                        .help("Record the extended attributes of each file, such as SELinux labels and capabilities (Unix only)"),
+                )
+                .arg(
+                    Arg::with_name("comment")
+                        .long("comment")
+                        .value_name("TEXT")
+                        .help("Store a comment in the archive, such as a case ID or a JSON object, shown by `mlar info`. It is not encrypted")
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::with_name("ads")
+                        .long("ads")
+                        .takes_value(false)
+                        .help("Also archive the alternate data streams of each file, as files named FILE:STREAM, extracted back as streams on Windows (Windows only)"),
+                )
+                .arg(
+                    Arg::with_name("stdin_name")
+                        .long("stdin-name")
+                        .help("Name of the entry holding data read from stdin, given as -")
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::with_name("timestamp_url")
+                        .long("timestamp-url")
+                        .help("Obtain a RFC 3161 timestamp token for the finished archive from this TSA (http:// only), stored as <output>.tsr")
+                        .number_of_values(1),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("append")
+                .about("Add files to an existing MLA Archive")
+                .args(&input_args)
+                .arg(
+                    Arg::with_name("output")
+                        .help("Output file path, instead of updating the archive in place. Use - for stdout")
+                        .long("output")
+                        .short("o")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("compression_level")
+                        .short("-q")
+                        .long("compression_level")
+                        .help("Compression level (0-11) for new data, if the archive is compressed")
+                        .takes_value(true),
+                )
                 .arg(Arg::with_name("files").help("Files to add").multiple(true)),
         )
+        .subcommand(
+            SubCommand::with_name("update")
+                .about("Update an existing MLA Archive to the current state of the given files and directories. Unless files are only added, the kept files are decompressed and compressed again, their blocks not being reused")
+                .args(&input_args)
+                .arg(
+                    Arg::with_name("output")
+                        .help("Output file path, instead of updating the archive in place. Use - for stdout")
+                        .long("output")
+                        .short("o")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("compression_level")
+                        .short("-q")
+                        .long("compression_level")
+                        .help("Compression level (0-11) for new data, if the archive is compressed")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("files")
+                        .help("Files and directories to synchronize, recursively")
+                        .required(true)
+                        .multiple(true),
+                ),
+        )
         .subcommand(
             SubCommand::with_name("list")
                 .about("List files inside a MLA Archive")
                 .args(&input_args)
                 .arg(
-                    Arg::with_name("verbose")
-                        .short("-v")
-                        .multiple(true)
+                    Arg::with_name("verbose")
+                        .short("-v")
+                        .multiple(true)
+                        .takes_value(false)
+                        .help("Verbose listing, with additional information"),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .help("Output format. 'json' prints one object per line, with the file name, size and, with -vv, hash. 'csv' and 'tsv' print the selected --fields, after a header row")
+                        .possible_values(&["text", "json", "csv", "tsv"])
+                        .default_value("text"),
+                )
+                .arg(
+                    Arg::with_name("fields")
+                        .long("fields")
+                        .help("Comma-separated columns to print for the csv and tsv formats. The compressed size is estimated from the compression rate of the blocks holding the file. The modification time (in seconds since the Unix epoch), mode (in octal), and owner are empty if not recorded. The type is detected from the first bytes of the file")
+                        .possible_values(&["name", "size", "compressed_size", "hash", "offset", "mtime", "mode", "uid", "gid", "btime", "attributes", "type"])
+                        .use_delimiter(true)
+                        .multiple(true)
+                        .default_value("name,size"),
+                )
+                .arg(
+                    Arg::with_name("detect")
+                        .long("detect")
+                        .takes_value(false)
+                        .help("Report the type of each file (such as application/pdf), detected from its first bytes. For the csv and tsv formats, use the 'type' field instead"),
+                )
+                .arg(
+                    Arg::with_name("glob")
+                        .long("glob")
+                        .short("-g")
+                        .takes_value(false)
+                        .help("Treat specified files as glob patterns"),
+                )
+                .arg(&regex_arg)
+                .arg(&ignore_case_arg)
+                .arg(
+                    Arg::with_name("files")
+                        .multiple(true)
+                        .help("List of listed files (all if none given)"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("extract")
+                .about("Extract files from a MLA Archive")
+                .args(&input_args)
+                .arg(&no_progress_arg)
+                .arg(
+                    Arg::with_name("outputdir")
+                        .help("Output directory where files are extracted")
+                        .long("output")
+                        .short("o")
+                        .number_of_values(1)
+                        .default_value("."),
+                )
+                .arg(
+                    Arg::with_name("glob")
+                        .long("glob")
+                        .short("-g")
+                        .takes_value(false)
+                        .help("Treat specified files as glob patterns"),
+                )
+                .arg(&regex_arg)
+                .arg(&ignore_case_arg)
+                .arg(Arg::with_name("files").help("List of extracted files (all if none given)"))
+                .arg(
+                    Arg::with_name("verbose")
+                        .long("verbose")
+                        .short("-v")
+                        .takes_value(false)
+                        .help("List files as they are extracted"),
+                )
+                .arg(
+                    Arg::with_name("preserve_permissions")
+                        .long("preserve-permissions")
+                        .takes_value(false)
+                        .help("Restore the permissions recorded in the archive, except the setuid and setgid bits. On Windows, restore the read-only, hidden, system, archive and not content indexed attributes. Otherwise, files are created with the default permissions"),
+                )
+                .arg(
+                    Arg::with_name("xattrs")
+                        .long("xattrs")
+                        .takes_value(false)
+                        .help("Restore the extended attributes recorded in the archive (Unix only). The ones which can not be set are skipped with a warning"),
+                )
+                .arg(
+                    Arg::with_name("overwrite")
+                        .long("overwrite")
+                        .possible_values(&["never", "always", "older", "prompt"])
+                        .default_value("never")
+                        .help("Handling of the files already existing in the output directory: keep them, skipping the archived ones with a warning (never), replace them (always), replace them if they were last modified before the archived ones (older) or ask for each one (prompt)"),
+                )
+                .arg(
+                    Arg::with_name("backup_suffix")
+                        .long("backup-suffix")
+                        .value_name("SUFFIX")
+                        .number_of_values(1)
+                        .help("Rename the replaced files by appending SUFFIX to their name, such as '.orig', instead of removing them"),
+                )
+                .arg(
+                    Arg::with_name("dry_run")
+                        .long("dry-run")
+                        .takes_value(false)
+                        .help("Only report where each file would be extracted, and which ones would be skipped, such as because of another file extracted at the same place, without writing anything"),
+                )
+                .arg(
+                    Arg::with_name("atomic")
+                        .long("atomic")
+                        .takes_value(false)
+                        .help("Write each file under a temporary name, ending with '.mla-partial', and rename it once complete. An interrupted extraction then never leaves incomplete files under their final name"),
+                )
+                .arg(
+                    Arg::with_name("flat")
+                        .long("flat")
+                        .takes_value(false)
+                        .conflicts_with("strip_components")
+                        .help("Extract the files directly in the output directory, under their name without directories. Files with the same name get a _N suffix, such as 'report_1.pdf'"),
+                )
+                .arg(
+                    Arg::with_name("strip_components")
+                        .long("strip-components")
+                        .value_name("N")
+                        .number_of_values(1)
+                        .help("Remove the N leading components of the file names on extraction, as with tar. Files with no more than N components are skipped. Files are still selected by their full name"),
+                )
+                .arg(
+                    Arg::with_name("threads")
+                        .long("threads")
+                        .value_name("N")
+                        .number_of_values(1)
+                        .help("Number of files extracted at the same time, each thread reading the archive on its own. Default is 1"),
+                )
+                .arg(
+                    Arg::with_name("max_open_files")
+                        .long("max-open-files")
+                        .value_name("N")
+                        .number_of_values(1)
+                        .help("Number of files kept open while extracting the whole archive, whose content may be interleaved. Default is 64"),
+                )
+                .arg(
+                    Arg::with_name("stream")
+                        .long("stream")
                         .takes_value(false)
-                        .help("Verbose listing, with additional information"),
+                        .conflicts_with_all(&["flat", "threads", "dry_run", "trusted_keys"])
+                        .help("Read the archive sequentially, from a pipe or a socket, such as stdin with '-i -', extracting the files as they appear. Each file is written under a temporary name, renamed once checked. The archive must be complete and intact: any error stops the extraction. Signed and deduplicated archives are not supported"),
+                )
+                .arg(
+                    Arg::with_name("io_backend")
+                        .long("io-backend")
+                        .possible_values(&["std", "uring"])
+                        .default_value("std")
+                        .help("I/O backend used for the archive file. 'uring' batches and overlaps reads and writes through io_uring, for large archives on fast storage (Linux only, requires the \"uring\" feature)"),
                 ),
         )
         .subcommand(
-            SubCommand::with_name("extract")
-                .about("Extract files from a MLA Archive")
+            SubCommand::with_name("cat")
+                .about("Display files from a MLA Archive, like 'cat'")
                 .args(&input_args)
                 .arg(
-                    Arg::with_name("outputdir")
-                        .help("Output directory where files are extracted")
+                    Arg::with_name("output")
+                        .help("Output file where files are displayed")
                         .long("output")
                         .short("o")
                         .number_of_values(1)
-                        .default_value("."),
+                        .default_value("-"),
                 )
                 .arg(
                     Arg::with_name("glob")
                         .long("glob")
                         .short("-g")
                         .takes_value(false)
-                        .help("Treat specified files as glob patterns"),
+                        .help("Treat given files as glob patterns"),
                 )
-                .arg(Arg::with_name("files").help("List of extracted files (all if none given)"))
+                .arg(&regex_arg)
+                .arg(&ignore_case_arg)
                 .arg(
-                    Arg::with_name("verbose")
-                        .long("verbose")
-                        .short("-v")
+                    Arg::with_name("head")
+                        .long("head")
+                        .value_name("N")
+                        .number_of_values(1)
+                        .conflicts_with("tail")
+                        .help("Only display the first N lines (or bytes, with --bytes) of each file"),
+                )
+                .arg(
+                    Arg::with_name("tail")
+                        .long("tail")
+                        .value_name("N")
+                        .number_of_values(1)
+                        .help("Only display the last N lines (or bytes, with --bytes) of each file. The end of the file is reached without reading all of it"),
+                )
+                .arg(
+                    Arg::with_name("bytes")
+                        .long("bytes")
+                        .short("c")
                         .takes_value(false)
-                        .help("List files as they are extracted"),
+                        .help("Count --head and --tail in bytes instead of lines"),
+                )
+                .arg(
+                    Arg::with_name("raw")
+                        .long("raw")
+                        .takes_value(false)
+                        .conflicts_with_all(&["filename_header", "separator"])
+                        .help("Display the files one after the other, as is (default)"),
+                )
+                .arg(
+                    Arg::with_name("filename_header")
+                        .long("filename-header")
+                        .takes_value(false)
+                        .help("Display a '==> NAME <==' line before each file"),
+                )
+                .arg(
+                    Arg::with_name("separator")
+                        .long("separator")
+                        .value_name("STR")
+                        .number_of_values(1)
+                        .help("Display STR between two files. '\\n', '\\t', '\\0' and '\\\\' stand for a newline, a tab, a NUL byte and a backslash"),
+                )
+                .arg(
+                    Arg::with_name("files")
+                        .required(true)
+                        .multiple(true)
+                        .help("List of displayed files"),
                 ),
         )
         .subcommand(
-            SubCommand::with_name("cat")
-                .about("Display files from a MLA Archive, like 'cat'")
+            SubCommand::with_name("grep")
+                .about("Search a pattern in the files of a MLA Archive, without extracting them. Each match is printed as FILE:OFFSET:MATCH, OFFSET being in bytes from the start of the file")
                 .args(&input_args)
                 .arg(
-                    Arg::with_name("output")
-                        .help("Output file where files are displayed")
-                        .long("output")
-                        .short("o")
-                        .number_of_values(1)
-                        .default_value("-"),
+                    Arg::with_name("fixed_strings")
+                        .long("fixed-strings")
+                        .short("F")
+                        .takes_value(false)
+                        .conflicts_with("hex")
+                        .help("Search PATTERN as is, instead of as a regular expression"),
+                )
+                .arg(
+                    Arg::with_name("hex")
+                        .long("hex")
+                        .takes_value(false)
+                        .help("Search the bytes given in hexadecimal by PATTERN, such as 4d5a9000"),
+                )
+                .arg(
+                    Arg::with_name("ignore_case")
+                        .long("ignore-case")
+                        .takes_value(false)
+                        .help("Ignore the case of letters, in PATTERN and in the given files"),
                 )
                 .arg(
                     Arg::with_name("glob")
@@ -936,29 +4767,116 @@ fn main() {
                         .help("Treat given files as glob patterns"),
                 )
                 .arg(
-                    Arg::with_name("files")
+                    Arg::with_name("pattern")
                         .required(true)
-                        .help("List of displayed files"),
+                        .help("Regular expression to search. Matches do not span lines"),
+                )
+                .arg(
+                    Arg::with_name("files")
+                        .multiple(true)
+                        .help("List of searched files (all if none given)"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("verify")
+                .about("Check the integrity of each file of a MLA Archive against its stored hash")
+                .args(&input_args)
+                .arg(
+                    Arg::with_name("timestamp")
+                        .long("timestamp")
+                        .help("RFC 3161 timestamp token of the archive (default: <input>.tsr, if it exists)")
+                        .number_of_values(1),
                 ),
         )
         .subcommand(
             SubCommand::with_name("to-tar")
-                .about("Convert a MLA Archive to a TAR Archive")
+                .about("Convert a MLA Archive to a TAR Archive. The input archive is read in order, except for interleaved files, which require seeking")
+                .args(&input_args)
+                .arg(
+                    Arg::with_name("output")
+                        .help("Tar Archive path. Use - for stdout")
+                        .long("output")
+                        .short("o")
+                        .number_of_values(1)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("to-zip")
+                .about("Convert a MLA Archive to a ZIP Archive")
                 .args(&input_args)
                 .arg(
                     Arg::with_name("output")
-                        .help("Tar Archive path")
+                        .help("Zip Archive path")
                         .long("output")
                         .short("o")
                         .number_of_values(1)
                         .required(true),
+                )
+                .arg(
+                    Arg::with_name("password_file")
+                        .help("Encrypt files with AES-256, using the password on the first line of this file. Use - for stdin")
+                        .long("password-file")
+                        .number_of_values(1),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("from-tar")
+                .about("Convert a TAR Archive to a MLA Archive")
+                .args(&output_args)
+                .arg(&password_arg)
+                .arg(
+                    Arg::with_name("input")
+                        .help("Tar Archive path. Use - for stdin")
+                        .long("input")
+                        .short("i")
+                        .number_of_values(1)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("from-zip")
+                .about("Convert a ZIP Archive to a MLA Archive")
+                .args(&output_args)
+                .arg(&password_arg)
+                .arg(
+                    Arg::with_name("input")
+                        .help("Zip Archive path")
+                        .long("input")
+                        .short("i")
+                        .number_of_values(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("password_file")
+                        .help("Decrypt files using the password on the first line of this file. Use - for stdin")
+                        .long("password-file")
+                        .number_of_values(1),
                 ),
         )
         .subcommand(
             SubCommand::with_name("repair")
                 .about("Try to repair a MLA Archive into a fresh MLA Archive")
                 .args(&input_args)
-                .args(&output_args),
+                .args(&output_args)
+                .arg(
+                    Arg::with_name("glob")
+                        .long("glob")
+                        .short("-g")
+                        .takes_value(false)
+                        .help("Treat specified files as glob patterns"),
+                )
+                .arg(
+                    Arg::with_name("extract")
+                        .long("extract")
+                        .takes_value(false)
+                        .help("Write the recovered files to the output directory, instead of a new archive"),
+                )
+                .arg(
+                    Arg::with_name("files")
+                        .multiple(true)
+                        .help("List of files to recover (all if none given)"),
+                ),
         )
         .subcommand(
             SubCommand::with_name("convert")
@@ -966,12 +4884,141 @@ fn main() {
                     "Convert a MLA Archive to a fresh new one, with potentially different options",
                 )
                 .args(&input_args)
-                .args(&output_args),
+                .args(&output_args)
+                .arg(&no_progress_arg),
+        )
+        .subcommand(
+            SubCommand::with_name("remove")
+                .about("Copy a MLA Archive to a fresh new one, without the given files")
+                .args(&input_args)
+                .args(&output_args)
+                .arg(
+                    Arg::with_name("glob")
+                        .long("glob")
+                        .short("-g")
+                        .takes_value(false)
+                        .help("Treat specified files as glob patterns"),
+                )
+                .arg(
+                    Arg::with_name("files")
+                        .required(true)
+                        .multiple(true)
+                        .help("List of removed files"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("merge")
+                .about("Merge several MLA Archives into a new one")
+                .arg(
+                    Arg::with_name("input")
+                        .help("Archive paths")
+                        .long("input")
+                        .short("i")
+                        .number_of_values(1)
+                        .multiple(true)
+                        .required(true),
+                )
+                .args(&input_args[1..])
+                .args(&output_args)
+                .arg(
+                    Arg::with_name("on_collision")
+                        .long("on-collision")
+                        .help("What to do with a file whose name is already in the merged archive: fail, skip it, or prefix it with its archive name")
+                        .possible_values(&["fail", "skip", "prefix"])
+                        .default_value("fail"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("split")
+                .about("Split a MLA Archive into volumes, named <output>.001, <output>.002, ...")
+                .arg(
+                    Arg::with_name("input")
+                        .help("Archive path")
+                        .long("input")
+                        .short("i")
+                        .number_of_values(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .help("Output path prefix of the volumes")
+                        .long("output")
+                        .short("o")
+                        .number_of_values(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("size")
+                        .help("Maximum size of a volume, with an optional K, M, G or T suffix (ie. 4G)")
+                        .long("size")
+                        .short("s")
+                        .number_of_values(1)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("join")
+                .about("Rebuild a MLA Archive from its volumes")
+                .arg(
+                    Arg::with_name("output")
+                        .help("Output file path. Use - for stdout")
+                        .long("output")
+                        .short("o")
+                        .number_of_values(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("volumes")
+                        .help("Volumes paths, in any order")
+                        .multiple(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("mount")
+                .about("Mount a MLA Archive as a read-only filesystem (requires the \"mount\" feature)")
+                .args(&input_args)
+                .arg(
+                    Arg::with_name("mountpoint")
+                        .help("Directory on which the archive is mounted, until unmounted")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("serve")
+                .about("Serve a MLA Archive over HTTP: GET / for the JSON list of files, GET /files/<name> for a file content")
+                .args(&input_args)
+                .arg(
+                    Arg::with_name("bind")
+                        .long("bind")
+                        .short("b")
+                        .help("Address to listen on")
+                        .default_value("127.0.0.1:8000"),
+                )
+                .arg(
+                    Arg::with_name("threads")
+                        .long("threads")
+                        .value_name("N")
+                        .number_of_values(1)
+                        .help("Number of requests handled at the same time, each thread reading the archive on its own. Default is 1"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("rename")
+                .about("Copy a MLA Archive to a fresh new one, renaming the given files")
+                .args(&input_args)
+                .args(&output_args)
+                .arg(
+                    Arg::with_name("names")
+                        .required(true)
+                        .multiple(true)
+                        .help("Pairs of current and new file names: old_name1 new_name1 [old_name2 new_name2 ...]"),
+                ),
         )
         .subcommand(
             SubCommand::with_name("keygen")
                 .about(
-                    "Generate a public/private keypair, in OpenSSL Ed25519 format, to be used by mlar",
+                    "Generate a public/private keypair, in OpenSSL X25519 format, to be used by mlar",
                 )
                 .arg(
                     Arg::with_name("output")
@@ -979,6 +5026,109 @@ fn main() {
                         .number_of_values(1)
                         .required(true)
                 )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .short("f")
+                        .help("Format of the private key: PKCS#8 DER (der, default), PKCS#8 PEM (pem), OpenSSH (openssh, generating an Ed25519 key, with an OpenSSH public key), raw X25519 (raw, 32 bytes, for both keys), or hybrid (hybrid, generating a post-quantum X25519 + ML-KEM-768 key, in PEM format for both keys)")
+                        .possible_values(&["der", "pem", "openssh", "raw", "hybrid"])
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::with_name("combined")
+                        .long("combined")
+                        .takes_value(false)
+                        .help("Write the public key after the private key, in {output}, instead of {output}.pub. The private key is then in PEM format by default"),
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("keyderive")
+                .about(
+                    "Derive a public/private keypair from a root private key (given with -k), through a path of labels. The root private key owner can then derive the private key again, to read archives made with the derived public key",
+                )
+                .args(&private_key_args)
+                .arg(
+                    Arg::with_name("output")
+                        .help("Output file for the derived private key. The public key is in {output}.pub")
+                        .number_of_values(1)
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("path")
+                        .long("path")
+                        .help("Derivation path, made of labels separated by '/' (for instance, case-42/host-1)")
+                        .number_of_values(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .short("f")
+                        .help("Format of the private key: PKCS#8 DER (der, default), PKCS#8 PEM (pem), or raw X25519 (raw, 32 bytes, for both keys)")
+                        .possible_values(&["der", "pem", "raw"])
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::with_name("combined")
+                        .long("combined")
+                        .takes_value(false)
+                        .help("Write the public key after the private key, in {output}, instead of {output}.pub. The private key is then in PEM format by default"),
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("sign")
+                .about("Produce a detached signature of a finished MLA Archive, with an Ed25519 private key")
+                .arg(
+                    Arg::with_name("input")
+                        .help("Archive path")
+                        .long("input")
+                        .short("i")
+                        .number_of_values(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("sign_key")
+                        .help("Ed25519 private key path (DER, PEM or OpenSSH format), such as the ones made by keygen -f openssh")
+                        .long("key")
+                        .short("k")
+                        .number_of_values(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("signature")
+                        .help("Output signature path. Default is {input}.sig")
+                        .long("output")
+                        .short("o")
+                        .number_of_values(1),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("verify-signature")
+                .about("Check the detached signature of a MLA Archive, without decrypting it")
+                .arg(
+                    Arg::with_name("input")
+                        .help("Archive path")
+                        .long("input")
+                        .short("i")
+                        .number_of_values(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("trusted_keys")
+                        .help("Ed25519 public key paths of the accepted signers (DER, PEM or OpenSSH format)")
+                        .long("pubkey")
+                        .short("p")
+                        .number_of_values(1)
+                        .multiple(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("signature")
+                        .help("Signature path. Default is {input}.sig")
+                        .long("signature")
+                        .short("s")
+                        .number_of_values(1),
+                ),
         )
         .subcommand(
             SubCommand::with_name("info")
@@ -990,31 +5140,90 @@ fn main() {
                         .short("-v")
                         .takes_value(false)
                         .help("Get extra info for encryption and compression layers"),
+                )
+                .arg(
+                    Arg::with_name("timestamp")
+                        .long("timestamp")
+                        .help("RFC 3161 timestamp token of the archive (default: <input>.tsr, if it exists)")
+                        .number_of_values(1),
                 ),
+        )
+        .subcommand(
+            SubCommand::with_name("stat")
+                .about("Report the size, compressed size and compression rate of each file of a MLA Archive, then of the whole archive")
+                .args(&input_args),
         );
 
     // Launch sub-command
     let mut help = Vec::new();
     app.write_long_help(&mut help).unwrap();
     let matches = app.get_matches();
+    let level = if matches.is_present("quiet") {
+        LevelFilter::Warn
+    } else {
+        match matches.occurrences_of("verbose") {
+            0 => LevelFilter::Info,
+            1 => LevelFilter::Debug,
+            _ => LevelFilter::Trace,
+        }
+    };
+    logger::init(level, matches.is_present("log_json"));
+    config_file::load(matches.value_of_os("config").map(Path::new));
+
     let res = if let Some(matches) = matches.subcommand_matches("create") {
         create(matches)
+    } else if let Some(matches) = matches.subcommand_matches("append") {
+        append(matches)
+    } else if let Some(matches) = matches.subcommand_matches("update") {
+        update(matches)
     } else if let Some(matches) = matches.subcommand_matches("list") {
         list(matches)
     } else if let Some(matches) = matches.subcommand_matches("extract") {
         extract(matches)
     } else if let Some(matches) = matches.subcommand_matches("cat") {
         cat(matches)
+    } else if let Some(matches) = matches.subcommand_matches("grep") {
+        grep(matches)
+    } else if let Some(matches) = matches.subcommand_matches("verify") {
+        verify(matches)
     } else if let Some(matches) = matches.subcommand_matches("to-tar") {
         to_tar(matches)
+    } else if let Some(matches) = matches.subcommand_matches("to-zip") {
+        to_zip(matches)
+    } else if let Some(matches) = matches.subcommand_matches("from-tar") {
+        from_tar(matches)
+    } else if let Some(matches) = matches.subcommand_matches("from-zip") {
+        from_zip(matches)
     } else if let Some(matches) = matches.subcommand_matches("repair") {
         repair(matches)
     } else if let Some(matches) = matches.subcommand_matches("convert") {
         convert(matches)
+    } else if let Some(matches) = matches.subcommand_matches("remove") {
+        remove(matches)
+    } else if let Some(matches) = matches.subcommand_matches("merge") {
+        merge(matches)
+    } else if let Some(matches) = matches.subcommand_matches("split") {
+        split(matches)
+    } else if let Some(matches) = matches.subcommand_matches("join") {
+        join(matches)
+    } else if let Some(matches) = matches.subcommand_matches("mount") {
+        mount(matches)
+    } else if let Some(matches) = matches.subcommand_matches("serve") {
+        serve(matches)
+    } else if let Some(matches) = matches.subcommand_matches("rename") {
+        rename(matches)
     } else if let Some(matches) = matches.subcommand_matches("keygen") {
         keygen(matches)
+    } else if let Some(matches) = matches.subcommand_matches("keyderive") {
+        keyderive(matches)
+    } else if let Some(matches) = matches.subcommand_matches("sign") {
+        sign(matches)
+    } else if let Some(matches) = matches.subcommand_matches("verify-signature") {
+        verify_signature(matches)
     } else if let Some(matches) = matches.subcommand_matches("info") {
         info(matches)
+    } else if let Some(matches) = matches.subcommand_matches("stat") {
+        stat(matches)
     } else {
         eprintln!("Error: at least one command required.");
         eprintln!("{}", std::str::from_utf8(&help).unwrap());
@@ -1022,7 +5231,11 @@ fn main() {
     };
 
     if let Err(err) = res {
-        eprintln!("[!] Command ended with error: {:?}", err);
+        if let Error::ConfigError(ConfigError::NoMatchingRecipientKey) = err {
+            error!("This archive is not encrypted to any of your keys");
+        } else {
+            error!("Command ended with error: {:?}", err);
+        }
         std::process::exit(1);
     }
 }
@@ -0,0 +1,340 @@
+//! Read-only FUSE filesystem exposing the content of a MLA Archive
+
+use fuser::{
+    FileAttr, FileType, Filesystem, MountOption, ReplyAttr, ReplyData, ReplyDirectory, ReplyEmpty,
+    ReplyEntry, ReplyOpen, Request, FUSE_ROOT_ID,
+};
+use libc::{EIO, EISDIR, ENOENT, ENOTDIR};
+use log::{error, warn};
+use mla::errors::Error;
+use mla::{ArchiveReader, LocatedBlocks};
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::{OsStr, OsString};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Component, Path};
+use std::time::{Duration, SystemTime};
+
+use crate::{get_extracted_path, ReadSeek};
+
+// The archive is read-only, so attributes never change
+const TTL: Duration = Duration::from_secs(3600);
+const BLOCK_SIZE: u32 = 4096;
+
+enum NodeKind {
+    Directory {
+        children: BTreeMap<OsString, u64>,
+    },
+    File {
+        /// Name of the file in the archive
+        filename: String,
+        size: u64,
+    },
+}
+
+struct Node {
+    parent: u64,
+    kind: NodeKind,
+}
+
+/// Directory tree of an archive, indexed by inode number
+///
+/// Inode numbers start at `FUSE_ROOT_ID`, the root directory
+struct Tree {
+    nodes: Vec<Node>,
+}
+
+impl Tree {
+    fn new() -> Self {
+        Tree {
+            nodes: vec![Node {
+                parent: FUSE_ROOT_ID,
+                kind: NodeKind::Directory {
+                    children: BTreeMap::new(),
+                },
+            }],
+        }
+    }
+
+    fn get(&self, ino: u64) -> Option<&Node> {
+        self.nodes.get(ino.checked_sub(FUSE_ROOT_ID)? as usize)
+    }
+
+    fn push(&mut self, parent: u64, name: &OsStr, kind: NodeKind) -> u64 {
+        let ino = self.nodes.len() as u64 + FUSE_ROOT_ID;
+        self.nodes.push(Node { parent, kind });
+        if let NodeKind::Directory { children } =
+            &mut self.nodes[(parent - FUSE_ROOT_ID) as usize].kind
+        {
+            children.insert(name.to_os_string(), ino);
+        }
+        ino
+    }
+
+    /// Add `filename` to the tree, creating intermediate directories
+    ///
+    /// Return false if the path conflicts with an already existing entry
+    fn add_file(&mut self, filename: &str, path: &Path, size: u64) -> bool {
+        let parts: Vec<&OsStr> = path
+            .components()
+            .filter_map(|part| match part {
+                Component::Normal(part) => Some(part),
+                _ => None,
+            })
+            .collect();
+        let (last, dirs) = match parts.split_last() {
+            Some(split) => split,
+            None => return false,
+        };
+
+        let mut current = FUSE_ROOT_ID;
+        for dir in dirs {
+            let existing = match &self.get(current).unwrap().kind {
+                NodeKind::Directory { children } => children.get(*dir).copied(),
+                NodeKind::File { .. } => return false,
+            };
+            current = match existing {
+                Some(ino) => ino,
+                None => self.push(
+                    current,
+                    dir,
+                    NodeKind::Directory {
+                        children: BTreeMap::new(),
+                    },
+                ),
+            };
+        }
+        match &self.get(current).unwrap().kind {
+            NodeKind::Directory { children } if !children.contains_key(*last) => {}
+            _ => return false,
+        }
+        self.push(
+            current,
+            last,
+            NodeKind::File {
+                filename: filename.to_string(),
+                size,
+            },
+        );
+        true
+    }
+}
+
+struct MLAFilesystem<'a> {
+    mla: ArchiveReader<'a, Box<dyn ReadSeek>>,
+    tree: Tree,
+    mount_time: SystemTime,
+    /// Blocks located by the previous reads of each open file handle, so
+    /// that sequential reads do not walk again through the whole file
+    located: HashMap<u64, LocatedBlocks>,
+    next_fh: u64,
+}
+
+impl<'a> MLAFilesystem<'a> {
+    fn new(mut mla: ArchiveReader<'a, Box<dyn ReadSeek>>) -> Result<Self, Error> {
+        let mut fnames: Vec<String> = mla.list_files()?.cloned().collect();
+        fnames.sort();
+
+        let mut tree = Tree::new();
+        for fname in fnames {
+            let size = match mla.get_entry_info(&fname)? {
+                Some(entry) => entry.size,
+                None => continue,
+            };
+            let path = match get_extracted_path(Path::new(""), &fname) {
+                Some(path) => path,
+                None => continue,
+            };
+            if !tree.add_file(&fname, &path, size) {
+                warn!("{} skipped, its path conflicts with another file", fname);
+            }
+        }
+
+        Ok(MLAFilesystem {
+            mla,
+            tree,
+            mount_time: SystemTime::now(),
+            located: HashMap::new(),
+            next_fh: 0,
+        })
+    }
+
+    fn attr(&self, req: &Request<'_>, ino: u64, node: &Node) -> FileAttr {
+        let (kind, perm, size) = match &node.kind {
+            NodeKind::Directory { .. } => (FileType::Directory, 0o555, 0),
+            NodeKind::File { size, .. } => (FileType::RegularFile, 0o444, *size),
+        };
+        FileAttr {
+            ino,
+            size,
+            blocks: size.div_ceil(512),
+            atime: self.mount_time,
+            mtime: self.mount_time,
+            ctime: self.mount_time,
+            crtime: self.mount_time,
+            kind,
+            perm,
+            nlink: 1,
+            uid: req.uid(),
+            gid: req.gid(),
+            rdev: 0,
+            blksize: BLOCK_SIZE,
+            flags: 0,
+        }
+    }
+
+    /// Read at most `size` bytes of `filename`, opened as `fh`, starting at
+    /// `offset`
+    fn read_file(
+        &mut self,
+        filename: &str,
+        fh: u64,
+        offset: u64,
+        size: usize,
+    ) -> Result<Vec<u8>, Error> {
+        let mut file = match self.mla.get_file(filename.to_string())? {
+            Some(file) => file,
+            None => return Err(Error::BadAPIArgument(format!("Unknown file {}", filename))),
+        };
+        if let Some(located) = self.located.remove(&fh) {
+            file.data.reuse_located_blocks(located);
+        }
+        file.data.seek(SeekFrom::Start(offset))?;
+        let mut data = Vec::with_capacity(size);
+        (&mut file.data).take(size as u64).read_to_end(&mut data)?;
+        self.located.insert(fh, file.data.into_located_blocks());
+        Ok(data)
+    }
+}
+
+impl<'a> Filesystem for MLAFilesystem<'a> {
+    fn lookup(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        let ino = match self.tree.get(parent) {
+            Some(Node {
+                kind: NodeKind::Directory { children },
+                ..
+            }) => children.get(name).copied(),
+            _ => None,
+        };
+        match ino.and_then(|ino| self.tree.get(ino).map(|node| (ino, node))) {
+            Some((ino, node)) => reply.entry(&TTL, &self.attr(req, ino, node), 0),
+            None => reply.error(ENOENT),
+        }
+    }
+
+    fn getattr(&mut self, req: &Request<'_>, ino: u64, _fh: Option<u64>, reply: ReplyAttr) {
+        match self.tree.get(ino) {
+            Some(node) => reply.attr(&TTL, &self.attr(req, ino, node)),
+            None => reply.error(ENOENT),
+        }
+    }
+
+    fn open(&mut self, _req: &Request<'_>, ino: u64, _flags: i32, reply: ReplyOpen) {
+        match self.tree.get(ino) {
+            Some(Node {
+                kind: NodeKind::File { .. },
+                ..
+            }) => {
+                let fh = self.next_fh;
+                self.next_fh += 1;
+                reply.opened(fh, 0)
+            }
+            Some(_) => reply.error(EISDIR),
+            None => reply.error(ENOENT),
+        }
+    }
+
+    fn release(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        fh: u64,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        _flush: bool,
+        reply: ReplyEmpty,
+    ) {
+        self.located.remove(&fh);
+        reply.ok();
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        let filename = match self.tree.get(ino) {
+            Some(Node {
+                kind: NodeKind::File { filename, .. },
+                ..
+            }) => filename.clone(),
+            Some(_) => return reply.error(EISDIR),
+            None => return reply.error(ENOENT),
+        };
+        match self.read_file(&filename, fh, offset as u64, size as usize) {
+            Ok(data) => reply.data(&data),
+            Err(err) => {
+                error!("Unable to read {}: {:?}", filename, err);
+                reply.error(EIO)
+            }
+        }
+    }
+
+    fn readdir(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        mut reply: ReplyDirectory,
+    ) {
+        let node = match self.tree.get(ino) {
+            Some(node) => node,
+            None => return reply.error(ENOENT),
+        };
+        let children = match &node.kind {
+            NodeKind::Directory { children } => children,
+            NodeKind::File { .. } => return reply.error(ENOTDIR),
+        };
+
+        let mut entries = vec![
+            (ino, FileType::Directory, OsStr::new(".")),
+            (node.parent, FileType::Directory, OsStr::new("..")),
+        ];
+        for (name, child) in children {
+            let kind = match self.tree.get(*child).map(|child| &child.kind) {
+                Some(NodeKind::Directory { .. }) => FileType::Directory,
+                _ => FileType::RegularFile,
+            };
+            entries.push((*child, kind, name.as_os_str()));
+        }
+        // `offset` is the one of the last returned entry, if any
+        for (index, (ino, kind, name)) in entries.into_iter().enumerate().skip(offset as usize) {
+            if reply.add(ino, (index + 1) as i64, kind, name) {
+                // Buffer full
+                break;
+            }
+        }
+        reply.ok();
+    }
+}
+
+/// Mount `mla` on `mountpoint`, until the filesystem is unmounted
+pub fn mount<'a>(
+    mla: ArchiveReader<'a, Box<dyn ReadSeek>>,
+    mountpoint: &Path,
+) -> Result<(), Error> {
+    let filesystem = MLAFilesystem::new(mla)?;
+    let options = [
+        MountOption::RO,
+        MountOption::FSName("mlar".to_string()),
+        MountOption::DefaultPermissions,
+    ];
+    fuser::mount2(filesystem, mountpoint, &options)?;
+    Ok(())
+}
@@ -0,0 +1,385 @@
+//! Output of archives to object storage, through a S3 multipart upload
+//!
+//! The archive is uploaded part per part as it is written, so no local space
+//! is needed. Any S3 compatible service can be used, such as Google Cloud
+//! Storage through its XML API and HMAC keys.
+//!
+//! Configuration comes from the usual environment variables:
+//! - `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY`, and `AWS_SESSION_TOKEN`
+//!   for temporary credentials
+//! - `AWS_REGION` (or `AWS_DEFAULT_REGION`), `us-east-1` by default
+//! - `AWS_ENDPOINT_URL`, for another service than AWS, such as
+//!   `https://storage.googleapis.com`
+//!
+//! Each part is kept in memory until it is acknowledged, so a failed request
+//! is sent again, up to `MAX_ATTEMPTS` times, without restarting the upload.
+//! As the archive being written only lives in memory, an upload interrupted
+//! with `mlar` can not be resumed: it is then aborted, for its parts not to
+//! be kept (and billed) by the service
+
+use hmac::{Hmac, Mac};
+use log::{debug, warn};
+use mla::errors::Error;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::io::{self, Write};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Prefix of the outputs to upload, as `s3://<bucket>/<key>`
+pub const S3_PREFIX: &str = "s3://";
+
+/// Size of the first parts. Every part but the last must be at least 5 MiB
+const INITIAL_PART_SIZE: u64 = 16 * 1024 * 1024;
+/// The part size doubles every `PARTS_PER_SIZE` parts, so the 10000 parts
+/// allowed are enough for archives of any size, while keeping small the
+/// memory used for the usual ones
+const PARTS_PER_SIZE: usize = 1000;
+const MAX_PART_SIZE: u64 = 5 * 1024 * 1024 * 1024;
+const MAX_PARTS: usize = 10000;
+
+/// Attempts of each request, before giving up
+const MAX_ATTEMPTS: u32 = 5;
+/// Delay before the first retry, doubling on each attempt
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+const HTTP_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Characters kept as-is in URI components, as required by the signature
+const URI_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+fn s3_error(msg: &str) -> Error {
+    Error::IOError(io::Error::other(format!("S3: {}", msg)))
+}
+
+fn hex_sha256(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    // Safe to unwrap, HMAC accepting keys of any size
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// `(YYYYMMDD, YYYYMMDDTHHMMSSZ)` of `time`, in UTC
+fn amz_date(time: SystemTime) -> (String, String) {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0);
+    let (days, secs) = ((secs / 86400) as i64, secs % 86400);
+    // Civil date from the number of days since 1970-01-01
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    let date = format!("{:04}{:02}{:02}", year, month, day);
+    let datetime = format!(
+        "{}T{:02}{:02}{:02}Z",
+        date,
+        secs / 3600,
+        (secs / 60) % 60,
+        secs % 60
+    );
+    (date, datetime)
+}
+
+/// Value between `<tag>` and `</tag>` in a XML response
+fn xml_value<'a>(body: &'a str, tag: &str) -> Option<&'a str> {
+    let start = body.find(&format!("<{}>", tag))? + tag.len() + 2;
+    let end = body[start..].find(&format!("</{}>", tag))? + start;
+    Some(&body[start..end])
+}
+
+struct Credentials {
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+}
+
+impl Credentials {
+    fn from_env() -> Result<Self, Error> {
+        let var = |name| env::var(name).map_err(|_| s3_error(&format!("{} is not set", name)));
+        Ok(Credentials {
+            access_key: var("AWS_ACCESS_KEY_ID")?,
+            secret_key: var("AWS_SECRET_ACCESS_KEY")?,
+            session_token: env::var("AWS_SESSION_TOKEN").ok(),
+        })
+    }
+}
+
+/// Destination of an archive, uploaded as the parts of a S3 multipart upload
+pub struct MultipartUpload {
+    agent: ureq::Agent,
+    credentials: Credentials,
+    region: String,
+    /// Scheme and host, such as `https://bucket.s3.eu-west-3.amazonaws.com`
+    endpoint: String,
+    host: String,
+    /// URI-encoded path of the object
+    path: String,
+    upload_id: String,
+    /// ETag of each uploaded part, in order
+    etags: Vec<String>,
+    /// Data of the part being written
+    part: Vec<u8>,
+    /// Whether the upload is over, completed or aborted
+    done: bool,
+}
+
+impl MultipartUpload {
+    /// Start the upload of the object `output`, as `s3://<bucket>/<key>`
+    pub fn create(output: &str) -> Result<Self, Error> {
+        let (bucket, key) = match output
+            .strip_prefix(S3_PREFIX)
+            .and_then(|o| o.split_once('/'))
+        {
+            Some((bucket, key)) if !bucket.is_empty() && !key.is_empty() => (bucket, key),
+            _ => return Err(s3_error("the output must be s3://<bucket>/<key>")),
+        };
+        let credentials = Credentials::from_env()?;
+        let region = env::var("AWS_REGION")
+            .or_else(|_| env::var("AWS_DEFAULT_REGION"))
+            .unwrap_or_else(|_| "us-east-1".to_string());
+        let key_path: String = key
+            .split('/')
+            .map(|part| utf8_percent_encode(part, URI_ENCODE_SET).to_string())
+            .collect::<Vec<String>>()
+            .join("/");
+        // Virtual-hosted style on AWS, path style elsewhere
+        let (endpoint, path) = match env::var("AWS_ENDPOINT_URL") {
+            Ok(endpoint) => (
+                endpoint.trim_end_matches('/').to_string(),
+                format!("/{}/{}", bucket, key_path),
+            ),
+            Err(_) => (
+                format!("https://{}.s3.{}.amazonaws.com", bucket, region),
+                format!("/{}", key_path),
+            ),
+        };
+        let host = match endpoint.split_once("://") {
+            Some((_, host)) => host.to_string(),
+            None => return Err(s3_error("AWS_ENDPOINT_URL must start with http(s)://")),
+        };
+
+        let mut upload = MultipartUpload {
+            agent: ureq::AgentBuilder::new().timeout(HTTP_TIMEOUT).build(),
+            credentials,
+            region,
+            endpoint,
+            host,
+            path,
+            upload_id: String::new(),
+            etags: Vec::new(),
+            part: Vec::new(),
+            // Nothing to abort yet
+            done: true,
+        };
+        let body = upload
+            .send("POST", &[("uploads", "")], &[])?
+            .into_string()?;
+        upload.upload_id = match xml_value(&body, "UploadId") {
+            Some(upload_id) => upload_id.to_string(),
+            None => {
+                return Err(s3_error(
+                    "no UploadId in the CreateMultipartUpload response",
+                ))
+            }
+        };
+        upload.done = false;
+        debug!("S3 multipart upload {} started", upload.upload_id);
+        Ok(upload)
+    }
+
+    /// Size of the part being written
+    fn part_size(&self) -> usize {
+        let shift = (self.etags.len() / PARTS_PER_SIZE) as u32;
+        std::cmp::min(INITIAL_PART_SIZE << shift, MAX_PART_SIZE) as usize
+    }
+
+    /// `Authorization` and `x-amz-*` headers of a request, as specified by
+    /// AWS Signature Version 4
+    fn sign(&self, method: &str, query: &str, payload_hash: &str) -> Vec<(&'static str, String)> {
+        let (date, datetime) = amz_date(SystemTime::now());
+        let mut headers = vec![
+            ("host", self.host.clone()),
+            ("x-amz-content-sha256", payload_hash.to_string()),
+            ("x-amz-date", datetime.clone()),
+        ];
+        if let Some(token) = &self.credentials.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+            .collect();
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<&str>>()
+            .join(";");
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            method, self.path, query, canonical_headers, signed_headers, payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            datetime,
+            scope,
+            hex_sha256(canonical_request.as_bytes())
+        );
+        let mut key = format!("AWS4{}", self.credentials.secret_key).into_bytes();
+        for data in &[date.as_str(), self.region.as_str(), "s3", "aws4_request"] {
+            key = hmac_sha256(&key, data);
+        }
+        let signature = hex::encode(hmac_sha256(&key, &string_to_sign));
+        headers.push((
+            "authorization",
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                self.credentials.access_key, scope, signed_headers, signature
+            ),
+        ));
+        // Set by the HTTP client
+        headers.retain(|(name, _)| *name != "host");
+        headers
+    }
+
+    /// Send a signed request on the object, retrying on network errors, on
+    /// throttling and on server errors
+    fn send(
+        &self,
+        method: &str,
+        query: &[(&str, &str)],
+        body: &[u8],
+    ) -> Result<ureq::Response, Error> {
+        // Sorted by name, as required by the signature
+        let mut query: Vec<String> = query
+            .iter()
+            .map(|(name, value)| {
+                format!(
+                    "{}={}",
+                    utf8_percent_encode(name, URI_ENCODE_SET),
+                    utf8_percent_encode(value, URI_ENCODE_SET)
+                )
+            })
+            .collect();
+        query.sort();
+        let query = query.join("&");
+        let url = format!("{}{}?{}", self.endpoint, self.path, query);
+        let payload_hash = hex_sha256(body);
+
+        let mut attempt = 1;
+        loop {
+            let mut request = self.agent.request(method, &url);
+            for (name, value) in self.sign(method, &query, &payload_hash) {
+                request = request.set(name, &value);
+            }
+            match request.send_bytes(body) {
+                Ok(response) => return Ok(response),
+                Err(ureq::Error::Status(code, response)) if code != 429 && code < 500 => {
+                    let body = response.into_string().unwrap_or_default();
+                    let reason = xml_value(&body, "Message").unwrap_or_default();
+                    return Err(s3_error(&format!("HTTP {} {}", code, reason)));
+                }
+                Err(err) if attempt < MAX_ATTEMPTS => {
+                    warn!(
+                        "S3 request failed ({}), attempt {}/{}",
+                        err, attempt, MAX_ATTEMPTS
+                    );
+                    thread::sleep(RETRY_DELAY * 2u32.pow(attempt - 1));
+                    attempt += 1;
+                }
+                Err(err) => return Err(s3_error(&err.to_string())),
+            }
+        }
+    }
+
+    /// Upload the part being written
+    fn upload_part(&mut self) -> Result<(), Error> {
+        if self.etags.len() == MAX_PARTS {
+            return Err(s3_error("too many parts"));
+        }
+        let part_number = (self.etags.len() + 1).to_string();
+        let response = self.send(
+            "PUT",
+            &[("partNumber", &part_number), ("uploadId", &self.upload_id)],
+            &self.part,
+        )?;
+        match response.header("ETag") {
+            Some(etag) => self.etags.push(etag.to_string()),
+            None => return Err(s3_error("no ETag in the UploadPart response")),
+        }
+        debug!("S3 part {} uploaded", part_number);
+        self.part.clear();
+        Ok(())
+    }
+
+    /// Upload the last part, and complete the upload, making the object
+    /// available. Without this call, the upload is aborted on drop
+    pub fn complete(mut self) -> Result<(), Error> {
+        // Even an empty object has a part
+        if !self.part.is_empty() || self.etags.is_empty() {
+            self.upload_part()?;
+        }
+        let mut body = String::from("<CompleteMultipartUpload>");
+        for (index, etag) in self.etags.iter().enumerate() {
+            body.push_str(&format!(
+                "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+                index + 1,
+                etag
+            ));
+        }
+        body.push_str("</CompleteMultipartUpload>");
+        let response = self
+            .send("POST", &[("uploadId", &self.upload_id)], body.as_bytes())?
+            .into_string()?;
+        // Errors may come with a 200 status
+        if response.contains("<Error>") {
+            let reason = xml_value(&response, "Message").unwrap_or_default();
+            return Err(s3_error(&format!("upload not completed ({})", reason)));
+        }
+        self.done = true;
+        Ok(())
+    }
+}
+
+impl Write for MultipartUpload {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let size = std::cmp::min(buf.len(), self.part_size() - self.part.len());
+        self.part.extend_from_slice(&buf[..size]);
+        if self.part.len() == self.part_size() {
+            self.upload_part()?;
+        }
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // Only whole parts can be uploaded
+        Ok(())
+    }
+}
+
+impl Drop for MultipartUpload {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        warn!("S3 upload not completed, aborting it");
+        if let Err(err) = self.send("DELETE", &[("uploadId", &self.upload_id)], &[]) {
+            warn!("Unable to abort the S3 upload {} ({})", self.upload_id, err);
+        }
+    }
+}
@@ -0,0 +1,231 @@
+//! Read-only HTTP interface on a MLA Archive
+//!
+//! - `GET /` returns the JSON list of files, with their size, hash and URL
+//! - `GET /files/<name>` returns the content of a file, supporting the `Range`
+//!   header for partial downloads
+
+use log::{error, info};
+use mla::errors::Error;
+use mla::{ArchiveReader, ArchiveReaderPool};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::Serialize;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::thread;
+use tiny_http::{Header, Method, Request, Response, Server, StatusCode};
+
+use crate::ReadSeek;
+
+const FILES_PREFIX: &str = "/files/";
+/// Characters kept as-is in file URLs
+const URL_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'/')
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+#[derive(Serialize)]
+struct IndexEntry {
+    name: String,
+    size: u64,
+    hash: String,
+    url: String,
+}
+
+/// Parse a `Range` header value, for a file of `size` bytes
+///
+/// Return `Ok(None)` if the range is not supported (it is then ignored, as
+/// allowed by RFC 7233), `Err(())` if it is not satisfiable, or the inclusive
+/// range of bytes to send
+fn parse_range(value: &str, size: u64) -> Result<Option<(u64, u64)>, ()> {
+    if size == 0 {
+        // No byte to send
+        return Err(());
+    }
+    let spec = match value.trim().strip_prefix("bytes=") {
+        // Multiple ranges are not supported
+        Some(spec) if !spec.contains(',') => spec.trim(),
+        _ => return Ok(None),
+    };
+    let (start, end) = match spec.split_once('-') {
+        Some(bounds) => bounds,
+        None => return Ok(None),
+    };
+    let (start, end) = match (start.parse::<u64>(), end.parse::<u64>()) {
+        // bytes=start-end
+        (Ok(start), Ok(end)) if start <= end => (start, std::cmp::min(end, size - 1)),
+        // bytes=start-
+        (Ok(start), Err(_)) if end.is_empty() => (start, size - 1),
+        // bytes=-suffix_length
+        (Err(_), Ok(suffix)) if start.is_empty() => {
+            if suffix == 0 {
+                return Err(());
+            }
+            (size.saturating_sub(suffix), size - 1)
+        }
+        _ => return Ok(None),
+    };
+    if start >= size {
+        return Err(());
+    }
+    Ok(Some((start, end)))
+}
+
+fn header(field: &str, value: &str) -> Header {
+    // Only called with valid ASCII fields and values
+    Header::from_bytes(field, value).unwrap()
+}
+
+fn build_index<'a>(mla: &mut ArchiveReader<'a, Box<dyn ReadSeek>>) -> Result<Vec<u8>, Error> {
+    let mut fnames: Vec<String> = mla.list_files()?.cloned().collect();
+    fnames.sort();
+    let mut index = Vec::with_capacity(fnames.len());
+    for name in fnames {
+        let entry = match mla.get_entry_info(&name)? {
+            Some(entry) => entry,
+            None => continue,
+        };
+        let size = entry.size;
+        let hash = hex::encode(entry.hash);
+        let url = format!(
+            "{}{}",
+            FILES_PREFIX,
+            utf8_percent_encode(&name, URL_ENCODE_SET)
+        );
+        index.push(IndexEntry {
+            name,
+            size,
+            hash,
+            url,
+        });
+    }
+    serde_json::to_vec_pretty(&index).map_err(|err| Error::IOError(io::Error::other(err)))
+}
+
+fn send_file<'a>(
+    mla: &mut ArchiveReader<'a, Box<dyn ReadSeek>>,
+    request: Request,
+    name: &str,
+) -> Result<(), Error> {
+    let mut file = match mla.get_file(name.to_string())? {
+        Some(file) => file,
+        None => {
+            request.respond(Response::empty(404))?;
+            return Ok(());
+        }
+    };
+    let size = file.size;
+
+    let range = request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Range"))
+        .map(|h| parse_range(h.value.as_str(), size));
+    let (status, start, length, content_range) = match range {
+        Some(Ok(Some((start, end)))) => (
+            206,
+            start,
+            end - start + 1,
+            Some(format!("bytes {}-{}/{}", start, end, size)),
+        ),
+        Some(Err(())) => {
+            let response = Response::empty(416)
+                .with_header(header("Content-Range", &format!("bytes */{}", size)));
+            request.respond(response)?;
+            return Ok(());
+        }
+        _ => (200, 0, size, None),
+    };
+
+    file.data.seek(SeekFrom::Start(start))?;
+    let mut headers = vec![
+        header("Content-Type", "application/octet-stream"),
+        header("Accept-Ranges", "bytes"),
+    ];
+    if let Some(content_range) = content_range {
+        headers.push(header("Content-Range", &content_range));
+    }
+    // The size is known, so always announce it instead of using chunks
+    let response = Response::new(
+        StatusCode(status),
+        headers,
+        file.data.take(length),
+        Some(length as usize),
+        None,
+    )
+    .with_chunked_threshold(usize::MAX);
+    request.respond(response)?;
+    Ok(())
+}
+
+fn handle<'a>(
+    mla: &mut ArchiveReader<'a, Box<dyn ReadSeek>>,
+    index: &[u8],
+    request: Request,
+) -> Result<(), Error> {
+    if request.method() != &Method::Get && request.method() != &Method::Head {
+        request.respond(Response::empty(405))?;
+        return Ok(());
+    }
+    // Ignore the query string, if any
+    let path = request.url().split('?').next().unwrap_or("").to_string();
+    if path == "/" {
+        let response =
+            Response::from_data(index).with_header(header("Content-Type", "application/json"));
+        request.respond(response)?;
+        return Ok(());
+    }
+    let name = match path
+        .strip_prefix(FILES_PREFIX)
+        .map(|name| percent_decode_str(name).decode_utf8())
+    {
+        Some(Ok(name)) => name.to_string(),
+        _ => {
+            request.respond(Response::empty(404))?;
+            return Ok(());
+        }
+    };
+    info!("{}", name);
+    send_file(mla, request, &name)
+}
+
+/// Handle the requests received by `server`, one at a time, with `mla`
+fn handle_requests<'a>(
+    mla: &mut ArchiveReader<'a, Box<dyn ReadSeek>>,
+    server: &Server,
+    index: &[u8],
+) {
+    for request in server.incoming_requests() {
+        if let Err(err) = handle(mla, index, request) {
+            // A failed request must not stop the server
+            error!("{:?}", err);
+        }
+    }
+}
+
+/// Serve the archive of `readers` on `address` (such as "127.0.0.1:8000"),
+/// until interrupted
+///
+/// Requests are handled by `threads` threads, each with its own reader
+pub fn serve<F: Fn() -> Result<Box<dyn ReadSeek>, Error> + Sync>(
+    readers: &ArchiveReaderPool<F>,
+    address: &str,
+    threads: usize,
+) -> Result<(), Error> {
+    let mut mla = readers.reader()?;
+    // The archive is read-only, so the index never changes
+    let index = build_index(&mut mla)?;
+    let server = Server::http(address).map_err(|err| Error::IOError(io::Error::other(err)))?;
+    info!("Serving on http://{}/", server.server_addr());
+
+    thread::scope(|scope| {
+        for _ in 1..threads {
+            scope.spawn(|| match readers.reader() {
+                Ok(mut mla) => handle_requests(&mut mla, &server, &index),
+                Err(err) => error!("{:?}", err),
+            });
+        }
+        handle_requests(&mut mla, &server, &index);
+    });
+    Ok(())
+}
@@ -0,0 +1,359 @@
+//! RFC 3161 trusted timestamping of archives
+//!
+//! A token is requested from a Time-Stamp Authority (TSA) for the SHA-256 hash
+//! of the finished archive file, and the raw `TimeStampResp` is stored
+//! alongside the archive. `mlar` only checks the token refers to the archive
+//! content: the TSA signature and certificate chain are left to OpenSSL, with
+//! `openssl ts -verify -in <archive>.tsr -data <archive> -CAfile <tsa.pem>`
+
+use mla::errors::Error;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Extension of the token file, stored alongside the archive
+pub const TOKEN_EXTENSION: &str = "tsr";
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+// DER tags
+const TAG_BOOLEAN: u8 = 0x01;
+const TAG_INTEGER: u8 = 0x02;
+const TAG_OCTET_STRING: u8 = 0x04;
+const TAG_NULL: u8 = 0x05;
+const TAG_OID: u8 = 0x06;
+const TAG_GENERALIZED_TIME: u8 = 0x18;
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_SET: u8 = 0x31;
+const TAG_CONTEXT_0: u8 = 0xA0;
+
+/// 2.16.840.1.101.3.4.2.1
+const OID_SHA256: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01];
+/// 1.2.840.113549.1.7.2
+const OID_SIGNED_DATA: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x07, 0x02];
+/// 1.2.840.113549.1.9.16.1.4
+const OID_TST_INFO: &[u8] = &[
+    0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x09, 0x10, 0x01, 0x04,
+];
+
+/// PKIStatus values meaning a token is included
+const STATUS_GRANTED: &[u8] = &[0];
+const STATUS_GRANTED_WITH_MODS: &[u8] = &[1];
+
+/// Content of a timestamp token relevant to the archive
+pub struct TimestampInfo {
+    /// GeneralizedTime, as given by the TSA (e.g. `20240102030405Z`)
+    pub gen_time: String,
+    /// SHA-256 of the timestamped data
+    pub digest: Vec<u8>,
+    pub nonce: Option<Vec<u8>>,
+}
+
+impl TimestampInfo {
+    /// Human readable `gen_time`, falling back on the raw value
+    pub fn time(&self) -> String {
+        let t = &self.gen_time;
+        if t.len() < 15 || !t.ends_with('Z') || !t[..14].bytes().all(|c| c.is_ascii_digit()) {
+            return t.clone();
+        }
+        format!(
+            "{}-{}-{} {}:{}:{}{} UTC",
+            &t[0..4],
+            &t[4..6],
+            &t[6..8],
+            &t[8..10],
+            &t[10..12],
+            &t[12..14],
+            &t[14..t.len() - 1]
+        )
+    }
+}
+
+/// Path of the token stored alongside `archive`
+pub fn token_path(archive: &Path) -> PathBuf {
+    let mut name = archive.as_os_str().to_os_string();
+    name.push(format!(".{}", TOKEN_EXTENSION));
+    PathBuf::from(name)
+}
+
+/// SHA-256 of the file at `path`, as timestamped
+pub fn file_digest(path: &Path) -> Result<Vec<u8>, Error> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().to_vec())
+}
+
+/// Request a timestamp token for `digest` from the TSA at `url`
+///
+/// Return the raw `TimeStampResp`, once checked to refer to `digest`
+pub fn request(url: &str, digest: &[u8]) -> Result<Vec<u8>, Error> {
+    // Positive and minimally encoded INTEGER
+    let mut nonce: [u8; 8] = rand::random();
+    nonce[0] &= 0x7F;
+    nonce[0] |= 0x01;
+
+    let response = http_post(url, &timestamp_request(digest, &nonce))?;
+    let info = parse(&response)?;
+    if info.digest != digest {
+        return Err(tsa_error("the token does not match the archive hash"));
+    }
+    if info.nonce.as_deref() != Some(&nonce[..]) {
+        return Err(tsa_error("the token does not match the request nonce"));
+    }
+    Ok(response)
+}
+
+fn tsa_error(msg: &str) -> Error {
+    Error::IOError(io::Error::other(format!("timestamp: {}", msg)))
+}
+
+// ----- Encoding ------
+
+fn der_length(len: usize, out: &mut Vec<u8>) {
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes = len.to_be_bytes();
+        let skip = bytes.iter().take_while(|b| **b == 0).count();
+        out.push(0x80 | (bytes.len() - skip) as u8);
+        out.extend_from_slice(&bytes[skip..]);
+    }
+}
+
+fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    der_length(content.len(), &mut out);
+    out.extend_from_slice(content);
+    out
+}
+
+/// DER encoded `TimeStampReq`, for a SHA-256 `digest`
+fn timestamp_request(digest: &[u8], nonce: &[u8]) -> Vec<u8> {
+    let algorithm = der(
+        TAG_SEQUENCE,
+        &[der(TAG_OID, OID_SHA256), der(TAG_NULL, &[])].concat(),
+    );
+    let imprint = der(
+        TAG_SEQUENCE,
+        &[algorithm, der(TAG_OCTET_STRING, digest)].concat(),
+    );
+    der(
+        TAG_SEQUENCE,
+        &[
+            // version v1
+            der(TAG_INTEGER, &[1]),
+            imprint,
+            der(TAG_INTEGER, nonce),
+            // certReq, to ease the verification with OpenSSL
+            der(TAG_BOOLEAN, &[0xFF]),
+        ]
+        .concat(),
+    )
+}
+
+// ----- Decoding ------
+
+/// Minimal DER reader, over a sequence of TLV
+struct DerReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> DerReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        DerReader { data }
+    }
+
+    fn peek_tag(&self) -> Option<u8> {
+        self.data.first().copied()
+    }
+
+    /// Return the next tag and its content
+    fn read(&mut self) -> Result<(u8, &'a [u8]), Error> {
+        let (&tag, rest) = self.data.split_first().ok_or(Error::DeserializationError)?;
+        let (&first, mut rest) = rest.split_first().ok_or(Error::DeserializationError)?;
+        let len = if first < 0x80 {
+            first as usize
+        } else {
+            // Indefinite lengths are not allowed in DER
+            let count = (first & 0x7F) as usize;
+            if count == 0 || count > 4 || rest.len() < count {
+                return Err(Error::DeserializationError);
+            }
+            let len = rest[..count]
+                .iter()
+                .fold(0usize, |acc, b| (acc << 8) | *b as usize);
+            rest = &rest[count..];
+            len
+        };
+        if rest.len() < len {
+            return Err(Error::DeserializationError);
+        }
+        let (content, rest) = rest.split_at(len);
+        self.data = rest;
+        Ok((tag, content))
+    }
+
+    /// Return the content of the next element, which must be tagged `tag`
+    fn expect(&mut self, tag: u8) -> Result<&'a [u8], Error> {
+        match self.read()? {
+            (found, content) if found == tag => Ok(content),
+            _ => Err(Error::DeserializationError),
+        }
+    }
+
+    /// Skip the next element if it is tagged `tag`
+    fn skip_optional(&mut self, tag: u8) -> Result<(), Error> {
+        if self.peek_tag() == Some(tag) {
+            self.read()?;
+        }
+        Ok(())
+    }
+}
+
+/// Extract the `TSTInfo` of a `TimeStampResp`
+pub fn parse(data: &[u8]) -> Result<TimestampInfo, Error> {
+    let mut resp = DerReader::new(DerReader::new(data).expect(TAG_SEQUENCE)?);
+
+    // PKIStatusInfo
+    let status = DerReader::new(resp.expect(TAG_SEQUENCE)?).expect(TAG_INTEGER)?;
+    if status != STATUS_GRANTED && status != STATUS_GRANTED_WITH_MODS {
+        return Err(tsa_error(&format!(
+            "the request has been rejected by the TSA (status {})",
+            hex::encode(status)
+        )));
+    }
+
+    // ContentInfo
+    let mut content_info = DerReader::new(resp.expect(TAG_SEQUENCE)?);
+    if content_info.expect(TAG_OID)? != OID_SIGNED_DATA {
+        return Err(Error::DeserializationError);
+    }
+    let signed_data = DerReader::new(content_info.expect(TAG_CONTEXT_0)?).expect(TAG_SEQUENCE)?;
+
+    // SignedData, up to the encapsulated content
+    let mut signed_data = DerReader::new(signed_data);
+    signed_data.expect(TAG_INTEGER)?;
+    signed_data.expect(TAG_SET)?;
+    let mut encap = DerReader::new(signed_data.expect(TAG_SEQUENCE)?);
+    if encap.expect(TAG_OID)? != OID_TST_INFO {
+        return Err(Error::DeserializationError);
+    }
+    let tst_info = DerReader::new(encap.expect(TAG_CONTEXT_0)?).expect(TAG_OCTET_STRING)?;
+
+    // TSTInfo
+    let mut tst_info = DerReader::new(DerReader::new(tst_info).expect(TAG_SEQUENCE)?);
+    tst_info.expect(TAG_INTEGER)?;
+    tst_info.expect(TAG_OID)?;
+    let mut imprint = DerReader::new(tst_info.expect(TAG_SEQUENCE)?);
+    let mut algorithm = DerReader::new(imprint.expect(TAG_SEQUENCE)?);
+    if algorithm.expect(TAG_OID)? != OID_SHA256 {
+        return Err(tsa_error("only SHA-256 tokens are supported"));
+    }
+    let digest = imprint.expect(TAG_OCTET_STRING)?.to_vec();
+    tst_info.expect(TAG_INTEGER)?;
+    let gen_time = String::from_utf8(tst_info.expect(TAG_GENERALIZED_TIME)?.to_vec())
+        .map_err(|_| Error::DeserializationError)?;
+    // accuracy and ordering are optional
+    tst_info.skip_optional(TAG_SEQUENCE)?;
+    tst_info.skip_optional(TAG_BOOLEAN)?;
+    let nonce = match tst_info.peek_tag() {
+        Some(TAG_INTEGER) => Some(tst_info.expect(TAG_INTEGER)?.to_vec()),
+        _ => None,
+    };
+
+    Ok(TimestampInfo {
+        gen_time,
+        digest,
+        nonce,
+    })
+}
+
+// ----- Transport ------
+
+/// POST `body` as a timestamp query to `url`, returning the response body
+///
+/// Only `http://` is supported: the token is signed by the TSA, so it does
+/// not rely on the transport security
+fn http_post(url: &str, body: &[u8]) -> Result<Vec<u8>, Error> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| tsa_error("only http:// TSA URLs are supported"))?;
+    let (authority, path) = match rest.find('/') {
+        Some(index) => rest.split_at(index),
+        None => (rest, "/"),
+    };
+    let address = if authority.contains(':') {
+        authority.to_string()
+    } else {
+        format!("{}:80", authority)
+    };
+
+    let mut stream = TcpStream::connect(address)?;
+    stream.set_read_timeout(Some(HTTP_TIMEOUT))?;
+    stream.set_write_timeout(Some(HTTP_TIMEOUT))?;
+    let request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/timestamp-query\r\nAccept: application/timestamp-reply\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        path,
+        authority,
+        body.len()
+    );
+    stream.write_all(request.as_bytes())?;
+    stream.write_all(body)?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+
+    let split = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| tsa_error("invalid HTTP response"))?;
+    let headers = String::from_utf8_lossy(&response[..split]).to_lowercase();
+    let body = &response[split + 4..];
+    let status = headers.split(' ').nth(1).unwrap_or_default();
+    if status != "200" {
+        return Err(tsa_error(&format!("the TSA answered with HTTP {}", status)));
+    }
+
+    let header = |name: &str| {
+        headers
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(key, _)| key.trim() == name)
+            .map(|(_, value)| value.trim().to_string())
+    };
+    if header("transfer-encoding").as_deref() == Some("chunked") {
+        return dechunk(body);
+    }
+    match header("content-length").and_then(|len| len.parse::<usize>().ok()) {
+        Some(len) if len <= body.len() => Ok(body[..len].to_vec()),
+        Some(_) => Err(tsa_error("truncated HTTP response")),
+        None => Ok(body.to_vec()),
+    }
+}
+
+/// Decode a `chunked` HTTP body
+fn dechunk(mut data: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut body = Vec::new();
+    loop {
+        let eol = data
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or_else(|| tsa_error("invalid chunked HTTP response"))?;
+        let size = String::from_utf8_lossy(&data[..eol]);
+        // Ignore chunk extensions
+        let size = size.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16)
+            .map_err(|_| tsa_error("invalid chunked HTTP response"))?;
+        data = &data[eol + 2..];
+        if size == 0 {
+            return Ok(body);
+        }
+        if data.len() < size {
+            return Err(tsa_error("truncated HTTP response"));
+        }
+        body.extend_from_slice(&data[..size]);
+        data = data.get(size + 2..).unwrap_or_default();
+    }
+}
@@ -0,0 +1,353 @@
+//! io_uring based file I/O, on Linux
+//!
+//! Instead of a system call per read or write, waiting for each one, up to
+//! `QUEUE_DEPTH` requests of `BLOCK_SIZE` bytes are queued and processed by
+//! the kernel while the archive is being computed:
+//! - `UringWriter` submits each full buffer without waiting for the previous
+//!   ones to be written
+//! - `UringReader` reads ahead the blocks following the current position,
+//!   which suits linear extraction
+//!
+//! Buffers are owned by the reader or the writer until their request
+//! completes, including on drop
+
+use io_uring::{opcode, types, IoUring};
+use log::error;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+/// Size of each request
+const BLOCK_SIZE: usize = 1024 * 1024;
+/// Maximum number of requests in flight
+const QUEUE_DEPTH: usize = 8;
+
+/// Buffer of a request, at `offset` in the file
+struct Block {
+    offset: u64,
+    buf: Vec<u8>,
+    /// Result of the request, once completed: the number of bytes read or
+    /// written, or a negated errno
+    result: Option<i32>,
+}
+
+/// Requests in flight on a file
+struct Queue {
+    ring: IoUring,
+    file: File,
+    blocks: VecDeque<Block>,
+}
+
+impl Queue {
+    fn new(file: File) -> io::Result<Self> {
+        Ok(Queue {
+            ring: IoUring::new(QUEUE_DEPTH as u32)?,
+            file,
+            blocks: VecDeque::new(),
+        })
+    }
+
+    /// Queue the read of `len` bytes at `offset`
+    fn submit_read(&mut self, offset: u64, len: usize) -> io::Result<()> {
+        let mut buf = vec![0u8; len];
+        let entry = opcode::Read::new(
+            types::Fd(self.file.as_raw_fd()),
+            buf.as_mut_ptr(),
+            len as u32,
+        )
+        .offset(offset)
+        .build()
+        .user_data(offset);
+        self.push(
+            entry,
+            Block {
+                offset,
+                buf,
+                result: None,
+            },
+        )
+    }
+
+    /// Queue the write of `buf` at `offset`
+    fn submit_write(&mut self, offset: u64, buf: Vec<u8>) -> io::Result<()> {
+        let entry = opcode::Write::new(
+            types::Fd(self.file.as_raw_fd()),
+            buf.as_ptr(),
+            buf.len() as u32,
+        )
+        .offset(offset)
+        .build()
+        .user_data(offset);
+        self.push(
+            entry,
+            Block {
+                offset,
+                buf,
+                result: None,
+            },
+        )
+    }
+
+    fn push(&mut self, entry: io_uring::squeue::Entry, block: Block) -> io::Result<()> {
+        while self.in_flight() == QUEUE_DEPTH {
+            self.wait()?;
+        }
+        // SAFETY: the buffer of the request is kept in `self.blocks`, and
+        // its heap allocation does not move, until the request completes
+        unsafe {
+            self.ring
+                .submission()
+                .push(&entry)
+                .map_err(|_| io::Error::other("io_uring submission queue full"))?;
+        }
+        self.blocks.push_back(block);
+        self.ring.submit()?;
+        Ok(())
+    }
+
+    fn in_flight(&self) -> usize {
+        self.blocks
+            .iter()
+            .filter(|block| block.result.is_none())
+            .count()
+    }
+
+    /// Wait for at least one request to complete, and record the results
+    fn wait(&mut self) -> io::Result<()> {
+        loop {
+            match self.ring.submit_and_wait(1) {
+                Ok(_) => break,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        let completed: Vec<(u64, i32)> = self
+            .ring
+            .completion()
+            .map(|cqe| (cqe.user_data(), cqe.result()))
+            .collect();
+        for (offset, result) in completed {
+            if let Some(block) = self
+                .blocks
+                .iter_mut()
+                .find(|block| block.offset == offset && block.result.is_none())
+            {
+                block.result = Some(result);
+            }
+        }
+        Ok(())
+    }
+
+    /// Wait for every request in flight to complete
+    fn wait_all(&mut self) -> io::Result<()> {
+        while self.in_flight() > 0 {
+            self.wait()?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Queue {
+    fn drop(&mut self) {
+        if let Err(err) = self.wait_all() {
+            error!("Unable to wait for the pending I/O ({})", err);
+            // The kernel may still use the buffers
+            std::mem::forget(std::mem::take(&mut self.blocks));
+        }
+    }
+}
+
+/// Number of bytes of a completed request, or the error it failed with
+fn completed_len(result: i32) -> io::Result<usize> {
+    if result < 0 {
+        Err(io::Error::from_raw_os_error(-result))
+    } else {
+        Ok(result as usize)
+    }
+}
+
+/// File written through io_uring, from its start
+pub struct UringWriter {
+    queue: Queue,
+    /// Offset of `buf` in the file
+    offset: u64,
+    /// Data not submitted yet
+    buf: Vec<u8>,
+}
+
+impl UringWriter {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(UringWriter {
+            queue: Queue::new(File::create(path)?)?,
+            offset: 0,
+            buf: Vec::with_capacity(BLOCK_SIZE),
+        })
+    }
+
+    /// Check the completed writes, and release their buffer
+    fn check_completed(&mut self) -> io::Result<()> {
+        while let Some(block) = self.queue.blocks.front() {
+            let written = match block.result {
+                Some(result) => completed_len(result)?,
+                None => break,
+            };
+            // Safe to unwrap, as checked just above
+            let block = self.queue.blocks.pop_front().unwrap();
+            if written < block.buf.len() {
+                // Short write, completed synchronously
+                self.queue
+                    .file
+                    .write_all_at(&block.buf[written..], block.offset + written as u64)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn submit(&mut self) -> io::Result<()> {
+        let buf = std::mem::replace(&mut self.buf, Vec::with_capacity(BLOCK_SIZE));
+        let len = buf.len() as u64;
+        self.queue.submit_write(self.offset, buf)?;
+        self.offset += len;
+        self.check_completed()
+    }
+}
+
+impl Write for UringWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let size = std::cmp::min(buf.len(), BLOCK_SIZE - self.buf.len());
+        self.buf.extend_from_slice(&buf[..size]);
+        if self.buf.len() == BLOCK_SIZE {
+            self.submit()?;
+        }
+        Ok(size)
+    }
+
+    /// Wait for every write to complete
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.buf.is_empty() {
+            self.submit()?;
+        }
+        self.queue.wait_all()?;
+        self.check_completed()
+    }
+}
+
+impl Drop for UringWriter {
+    fn drop(&mut self) {
+        if let Err(err) = self.flush() {
+            error!("Unable to write the end of the file ({})", err);
+        }
+    }
+}
+
+/// File read through io_uring, reading ahead from the current position
+pub struct UringReader {
+    queue: Queue,
+    size: u64,
+    pos: u64,
+}
+
+impl UringReader {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let size = file.metadata()?.len();
+        Ok(UringReader {
+            queue: Queue::new(file)?,
+            size,
+            pos: 0,
+        })
+    }
+
+    /// Expected size of the block at `offset`
+    fn block_len(&self, offset: u64) -> usize {
+        std::cmp::min(BLOCK_SIZE as u64, self.size - offset) as usize
+    }
+
+    /// Make the block holding `self.pos` the first one, queuing the reads
+    /// of the following ones
+    fn read_ahead(&mut self) -> io::Result<()> {
+        let start = self.pos - self.pos % BLOCK_SIZE as u64;
+        let in_window = match (self.queue.blocks.front(), self.queue.blocks.back()) {
+            (Some(front), Some(back)) => front.offset <= start && start <= back.offset,
+            _ => false,
+        };
+        if !in_window {
+            // Blocks not needed anymore can only be dropped once read
+            self.queue.wait_all()?;
+            self.queue.blocks.clear();
+        }
+        while let Some(front) = self.queue.blocks.front() {
+            if front.offset == start {
+                break;
+            }
+            if front.result.is_none() {
+                self.queue.wait()?;
+                continue;
+            }
+            self.queue.blocks.pop_front();
+        }
+
+        let mut next = match self.queue.blocks.back() {
+            Some(back) => back.offset + BLOCK_SIZE as u64,
+            None => start,
+        };
+        while self.queue.blocks.len() < QUEUE_DEPTH && next < self.size {
+            self.queue.submit_read(next, self.block_len(next))?;
+            next += BLOCK_SIZE as u64;
+        }
+        Ok(())
+    }
+}
+
+impl Read for UringReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.size || buf.is_empty() {
+            return Ok(0);
+        }
+        self.read_ahead()?;
+        while self.queue.blocks[0].result.is_none() {
+            self.queue.wait()?;
+        }
+        let expected = self.block_len(self.queue.blocks[0].offset);
+        // Safe to unwrap, as checked just above
+        let read = completed_len(self.queue.blocks[0].result.unwrap())?;
+        if read < expected {
+            // Short read, completed synchronously
+            let block = &mut self.queue.blocks[0];
+            self.queue
+                .file
+                .read_exact_at(&mut block.buf[read..expected], block.offset + read as u64)?;
+            block.result = Some(expected as i32);
+        }
+
+        let block = &self.queue.blocks[0];
+        let start = (self.pos - block.offset) as usize;
+        let count = std::cmp::min(buf.len(), expected - start);
+        buf[..count].copy_from_slice(&block.buf[start..start + count]);
+        self.pos += count as u64;
+        Ok(count)
+    }
+}
+
+impl Seek for UringReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::End(offset) => self.size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+        match pos {
+            Some(pos) => {
+                self.pos = pos;
+                Ok(pos)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative position",
+            )),
+        }
+    }
+}
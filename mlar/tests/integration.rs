@@ -5,11 +5,18 @@ use permutate::Permutator;
 use rand::distributions::{Alphanumeric, Distribution, Standard};
 use rand::rngs::StdRng;
 use rand::SeedableRng;
+use sha2::{Digest, Sha256};
 use std::collections::{HashMap, HashSet};
 use std::fs::{metadata, File};
 use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
 use std::path::{Path, PathBuf};
-use tar::Archive;
+use std::process::Child;
+use std::thread::sleep;
+use std::time::Duration;
+use tar::{Archive, Builder};
+use zip::write::SimpleFileOptions;
+use zip::{AesMode, ZipArchive, ZipWriter};
 
 const SIZE_FILE1: usize = 10 * 1024 * 1024;
 const SIZE_FILE2: usize = 10 * 1024 * 1024;
@@ -247,7 +254,12 @@ fn test_truncated_repair_list_tar() {
 
     println!("{:?}", cmd);
     let assert = cmd.assert();
-    assert.success();
+    // Files before the truncation are fully recovered, the second one being
+    // cut in its data
+    let stdout = String::from_utf8(assert.success().get_output().stdout.clone()).unwrap();
+    let order = &testfs.files_archive_order;
+    assert!(stdout.contains(&format!("{}: OK\n", order[0].to_string_lossy())));
+    assert!(stdout.contains(&format!("{}: TRUNCATED", order[1].to_string_lossy())));
 
     // `mlar list -i repaired.mla -k samples/test_x25519.pem`
     let mut cmd = Command::cargo_bin(UTIL).unwrap();
@@ -315,6 +327,111 @@ fn test_truncated_repair_list_tar() {
     assert_eq!(fname2content.len(), 0);
 }
 
+#[test]
+fn test_repair_selected() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let mlar_repaired_file = NamedTempFile::new("repaired.mla").unwrap();
+    let ecc_public = Path::new("../samples/test_x25519_pub.pem");
+    let ecc_private = Path::new("../samples/test_x25519.pem");
+
+    // Create files
+    let testfs = setup();
+
+    // `mlar create -o output.mla -p samples/test_x25519_pub.pem file1.bin file2.bin file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg("-p")
+        .arg(ecc_public);
+    for path in &testfs.files_archive_order {
+        cmd.arg(path);
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    // `mlar repair -i output.mla -k samples/test_x25519.pem -p samples/test_x25519_pub.pem -o repaired.mla --glob *file2.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("repair")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-k")
+        .arg(ecc_private)
+        .arg("-p")
+        .arg(ecc_public)
+        .arg("-o")
+        .arg(mlar_repaired_file.path())
+        .arg("--glob")
+        .arg("*file2.bin");
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    // Only the selected file is reported
+    let file2 = testfs.files_archive_order[1].to_string_lossy();
+    assert.success().stdout(format!("{}: OK\n", file2));
+
+    // `mlar list -i repaired.mla -k samples/test_x25519.pem`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("list")
+        .arg("-i")
+        .arg(mlar_repaired_file.path())
+        .arg("-k")
+        .arg(ecc_private);
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stdout(format!("{}\n", file2));
+}
+
+#[test]
+fn test_repair_extract() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let output_dir = TempDir::new().unwrap();
+    let ecc_public = Path::new("../samples/test_x25519_pub.pem");
+    let ecc_private = Path::new("../samples/test_x25519.pem");
+
+    // Create files
+    let testfs = setup();
+
+    // `mlar create -o output.mla -p samples/test_x25519_pub.pem file1.bin file2.bin file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg("-p")
+        .arg(ecc_public);
+    for path in &testfs.files_archive_order {
+        cmd.arg(path);
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    // `mlar repair -i output.mla -k samples/test_x25519.pem --extract -o output_dir`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("repair")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-k")
+        .arg(ecc_private)
+        .arg("--extract")
+        .arg("-o")
+        .arg(output_dir.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    let stdout = String::from_utf8(assert.success().get_output().stdout.clone()).unwrap();
+    for path in &testfs.files_archive_order {
+        assert!(stdout.contains(&format!("{}: OK\n", path.to_string_lossy())));
+    }
+
+    // Recovered files are directly written to the output directory
+    ensure_directory_content(output_dir.path(), &testfs.files);
+}
+
 #[test]
 fn test_multiple_keys() {
     // Key parsing is common for each subcommands, so test only one: `list`
@@ -395,6 +512,89 @@ fn test_multiple_keys() {
     assert.failure();
 }
 
+#[test]
+fn test_threshold() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let ecc_publics = vec![
+        Path::new("../samples/test_x25519_pub.pem"),
+        Path::new("../samples/test_x25519_2_pub.pem"),
+        Path::new("../samples/test_x25519_3_pub.pem"),
+    ];
+    let ecc_privates = [
+        Path::new("../samples/test_x25519.pem"),
+        Path::new("../samples/test_x25519_2.pem"),
+        Path::new("../samples/test_x25519_3.pem"),
+    ];
+
+    // Create files
+    let testfs = setup();
+
+    // `mlar create -o output.mla --threshold 2 -p samples/test_x25519_pub.pem -p samples/test_x25519_2_pub.pem -p samples/test_x25519_3_pub.pem file1.bin file2.bin file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg("--threshold")
+        .arg("2");
+    for ecc_public in &ecc_publics {
+        cmd.arg("-p").arg(ecc_public);
+    }
+
+    let mut file_list = String::new();
+    for file in &testfs.files {
+        cmd.arg(file.path());
+        file_list.push_str(format!("{}\n", file.path().to_string_lossy()).as_str());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stderr(String::from(&file_list));
+
+    // One private key is not enough
+    // `mlar list -i output.mla -k samples/test_x25519_2.pem`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("list")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-k")
+        .arg(ecc_privates[1]);
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.failure();
+
+    // `mlar list -i output.mla -k samples/test_x25519_3.pem -k samples/test_x25519.pem`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("list")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-k")
+        .arg(ecc_privates[2])
+        .arg("-k")
+        .arg(ecc_privates[0]);
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stdout(String::from(&file_list));
+
+    // The threshold can't exceed the number of recipients
+    // `mlar create -o output.mla --threshold 4 -p samples/test_x25519_pub.pem -p samples/test_x25519_2_pub.pem -p samples/test_x25519_3_pub.pem file1.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg("--threshold")
+        .arg("4");
+    for ecc_public in &ecc_publics {
+        cmd.arg("-p").arg(ecc_public);
+    }
+    cmd.arg(testfs.files[0].path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.failure();
+}
+
 #[test]
 fn test_multiple_compression_level() {
     let mlar_file_q0 = NamedTempFile::new("output_q0.mla").unwrap();
@@ -525,127 +725,4241 @@ fn test_convert() {
 }
 
 #[test]
-fn test_stdio() {
-    // Create an archive on stdout, and check it
+fn test_create_directory() {
     let mlar_file = NamedTempFile::new("output.mla").unwrap();
-    let tar_file = NamedTempFile::new("output.tar").unwrap();
-    let ecc_public = Path::new("../samples/test_x25519_pub.pem");
-    let ecc_private = Path::new("../samples/test_x25519.pem");
-
-    // Create files
+    let output_dir = TempDir::new().unwrap();
     let testfs = setup();
 
-    // `mlar create -o - -p samples/test_x25519_pub.pem file1.bin file2.bin file3.bin`
+    // Build a directory tree from the test files: file1 and file2 at the root,
+    // file3 in a sub-directory
+    let directory = TempDir::new().unwrap();
+    std::fs::create_dir(directory.path().join("subdir")).unwrap();
+    let paths = vec![
+        directory.path().join("file1.bin"),
+        directory.path().join("file2.bin"),
+        directory.path().join("subdir").join("file3.bin"),
+    ];
+    for (src, dst) in testfs.files_archive_order.iter().zip(paths.iter()) {
+        std::fs::copy(src, dst).unwrap();
+    }
+
+    // `mlar create -l -o output.mla directory`
     let mut cmd = Command::cargo_bin(UTIL).unwrap();
     cmd.arg("create")
+        .arg("-l")
         .arg("-o")
-        .arg("-")
-        .arg("-p")
-        .arg(ecc_public);
+        .arg(mlar_file.path())
+        .arg(directory.path());
 
+    // Files are named relatively to the directory's parent
+    let root = directory.path().parent().unwrap();
     let mut file_list = String::new();
-    for file in &testfs.files {
-        cmd.arg(file.path());
-        file_list.push_str(format!("{}\n", file.path().to_string_lossy()).as_str());
+    for path in &paths {
+        let name = path.strip_prefix(root).unwrap();
+        file_list.push_str(format!("{}\n", name.to_string_lossy()).as_str());
     }
 
     println!("{:?}", cmd);
     let assert = cmd.assert();
-    let archive_data = assert.get_output().stdout.clone();
     assert.success().stderr(String::from(&file_list));
 
-    File::create(mlar_file.path())
-        .unwrap()
-        .write_all(&archive_data)
-        .unwrap();
-    // `mlar to-tar -i output.mla -k samples/test_x25519.pem -o output.tar`
+    // `mlar list -i output.mla`
     let mut cmd = Command::cargo_bin(UTIL).unwrap();
-    cmd.arg("to-tar")
+    cmd.arg("list").arg("-i").arg(mlar_file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stdout(file_list);
+
+    // `mlar extract -i output.mla -o output_dir`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("extract")
         .arg("-i")
         .arg(mlar_file.path())
-        .arg("-k")
-        .arg(ecc_private)
         .arg("-o")
-        .arg(tar_file.path());
+        .arg(output_dir.path());
 
     println!("{:?}", cmd);
     let assert = cmd.assert();
     assert.success();
 
-    // Inspect the created TAR file
-    ensure_tar_content(&tar_file.path(), &testfs.files);
+    ensure_directory_content(output_dir.path(), &testfs.files);
 }
 
 #[test]
-fn test_multi_fileorders() {
-    // Create several archive with all possible file order. Result should be the same
+fn test_create_exclude() {
     let mlar_file = NamedTempFile::new("output.mla").unwrap();
-    let tar_file = NamedTempFile::new("output.tar").unwrap();
-    let ecc_public = Path::new("../samples/test_x25519_pub.pem");
-    let ecc_private = Path::new("../samples/test_x25519.pem");
+    let directory = TempDir::new().unwrap();
+    std::fs::create_dir(directory.path().join("cache")).unwrap();
+    std::fs::create_dir(directory.path().join("subdir")).unwrap();
+    let kept = directory.path().join("subdir").join("kept.txt");
+    std::fs::write(&kept, b"kept").unwrap();
+    std::fs::write(directory.path().join("cache").join("a.txt"), b"cache").unwrap();
+    std::fs::write(directory.path().join("subdir").join("b.log"), b"log").unwrap();
+
+    // `mlar create -l -o output.mla --exclude cache --exclude *.log directory`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-l")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg("--exclude")
+        .arg("cache")
+        .arg("--exclude")
+        .arg("*.log")
+        .arg(directory.path());
 
-    // Create files
-    let testfs = setup();
-    let path_array: &[&Path] = &[
-        testfs.files[0].path(),
-        testfs.files[1].path(),
-        testfs.files[2].path(),
-    ];
-    let path_array = [path_array];
-    let permutator = Permutator::new(&path_array[..]);
+    let root = directory.path().parent().unwrap();
+    let file_list = format!("{}\n", kept.strip_prefix(root).unwrap().to_string_lossy());
 
-    for list in permutator {
-        let set: HashSet<_> = list.iter().collect(); // dedup
-        if set.len() != list.len() {
-            // Duplicate, avoid
-            continue;
-        }
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stderr(String::from(&file_list));
 
-        // `mlar create -o output.mla -p samples/test_x25519_pub.pem file1.bin file2.bin file3.bin`
-        let mut cmd = Command::cargo_bin(UTIL).unwrap();
-        cmd.arg("create")
-            .arg("-o")
-            .arg(mlar_file.path())
-            .arg("-p")
-            .arg(ecc_public);
+    // `mlar list -i output.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("list").arg("-i").arg(mlar_file.path());
 
-        let mut file_list = String::new();
-        for file in list {
-            cmd.arg(file);
-            file_list.push_str(format!("{}\n", file.to_string_lossy()).as_str());
-        }
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stdout(file_list);
+}
 
-        println!("{:?}", cmd);
-        let assert = cmd.assert();
-        assert.success().stderr(String::from(&file_list));
+#[cfg(unix)]
+#[test]
+fn test_create_directory_symlink() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let directory = TempDir::new().unwrap();
+    let kept = directory.path().join("kept.txt");
+    std::fs::write(&kept, b"kept").unwrap();
+    std::os::unix::fs::symlink("/etc/passwd", directory.path().join("link")).unwrap();
 
-        // `mlar to-tar -i convert.mla -k samples/test_x25519.pem -o output.tar`
-        let mut cmd = Command::cargo_bin(UTIL).unwrap();
-        cmd.arg("to-tar")
-            .arg("-i")
-            .arg(mlar_file.path())
-            .arg("-k")
-            .arg(ecc_private)
+    // `mlar create -l -o output.mla directory`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-l")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg(directory.path());
+
+    // The symlink is skipped, with a warning
+    let root = directory.path().parent().unwrap();
+    let kept_name = kept.strip_prefix(root).unwrap().to_string_lossy();
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    let stderr = String::from_utf8(assert.success().get_output().stderr.clone()).unwrap();
+    assert!(stderr.contains("not a regular file"));
+
+    // `mlar list -i output.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("list").arg("-i").arg(mlar_file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stdout(format!("{}\n", kept_name));
+}
+
+#[test]
+fn test_create_files_from() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let list_file = NamedTempFile::new("list.txt").unwrap();
+    let testfs = setup();
+
+    // First file from the command line, second one from a list file, third
+    // one from a NUL separated list on stdin
+    std::fs::write(
+        list_file.path(),
+        format!("{}\n", testfs.files_archive_order[1].to_string_lossy()),
+    )
+    .unwrap();
+
+    // `mlar create -l -o output.mla -T list.txt file1`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-l")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg("--files-from")
+        .arg(list_file.path())
+        .arg(&testfs.files_archive_order[0]);
+
+    let mut file_list = String::new();
+    for file in &testfs.files_archive_order[..2] {
+        file_list.push_str(format!("{}\n", file.to_string_lossy()).as_str());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stderr(String::from(&file_list));
+
+    // `find file2 file3 -print0 | mlar create -l -o output.mla -0 -T -`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-l")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg("-0")
+        .arg("--files-from")
+        .arg("-");
+    cmd.write_stdin(format!(
+        "{}\0{}\0",
+        testfs.files_archive_order[1].to_string_lossy(),
+        testfs.files_archive_order[2].to_string_lossy()
+    ));
+
+    let mut file_list = String::new();
+    for file in &testfs.files_archive_order[1..] {
+        file_list.push_str(format!("{}\n", file.to_string_lossy()).as_str());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stderr(file_list);
+
+    // `mlar list -i output.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("list").arg("-i").arg(mlar_file.path());
+
+    println!("{:?}", cmd);
+    let output = cmd.output().unwrap();
+    assert!(output.status.success());
+    let mut listed: Vec<String> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|s| s.to_string())
+        .collect();
+    listed.sort();
+    let mut expected: Vec<String> = testfs.files_archive_order[1..]
+        .iter()
+        .map(|p| p.to_string_lossy().to_string())
+        .collect();
+    expected.sort();
+    assert_eq!(listed, expected);
+}
+
+#[test]
+fn test_create_stdin_name() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let testfs = setup();
+
+    // Use data bigger than what is read from stdin at once
+    let stdin_data: Vec<u8> = (0..(5 * 1024 * 1024 / 2))
+        .map(|i| (i % 251) as u8)
+        .collect();
+
+    // `mlar create -l -o output.mla --stdin-name memory.dmp file1 -`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-l")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg("--stdin-name")
+        .arg("memory.dmp")
+        .arg(&testfs.files_archive_order[0])
+        .arg("-");
+    cmd.write_stdin(stdin_data.clone());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stderr(format!(
+        "{}\nmemory.dmp\n",
+        testfs.files_archive_order[0].to_string_lossy()
+    ));
+
+    // `mlar cat -i output.mla memory.dmp`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("cat")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("memory.dmp");
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert_eq!(assert.success().get_output().stdout, stdin_data);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_create_pipe() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+
+    // Data given through a pipe, whose length is unknown
+    let stdin_data: Vec<u8> = (0..(3 * 1024 * 1024)).map(|i| (i % 251) as u8).collect();
+
+    // `mlar create -l -o output.mla /dev/stdin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-l")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg("/dev/stdin");
+    cmd.write_stdin(stdin_data.clone());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    // `mlar cat -i output.mla /dev/stdin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("cat")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("/dev/stdin");
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert_eq!(assert.success().get_output().stdout, stdin_data);
+}
+
+#[test]
+fn test_append() {
+    // Create an archive with two files, append the last one in place, then
+    // verify the content of the archive
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let tar_file = NamedTempFile::new("output.tar").unwrap();
+    let ecc_public = Path::new("../samples/test_x25519_pub.pem");
+    let ecc_private = Path::new("../samples/test_x25519.pem");
+
+    // Create files
+    let testfs = setup();
+
+    // `mlar create -o output.mla -p samples/test_x25519_pub.pem file1.bin file2.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg("-p")
+        .arg(ecc_public);
+
+    let mut file_list = String::new();
+    for file in &testfs.files[..2] {
+        cmd.arg(file.path());
+        file_list.push_str(format!("{}\n", file.path().to_string_lossy()).as_str());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stderr(String::from(&file_list));
+
+    // `mlar append -i output.mla -k samples/test_x25519.pem file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("append")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-k")
+        .arg(ecc_private)
+        .arg(testfs.files[2].path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert
+        .success()
+        .stderr(format!("{}\n", testfs.files[2].path().to_string_lossy()));
+
+    // Re-adding an already existing file must fail, without altering the archive
+    // `mlar append -i output.mla -k samples/test_x25519.pem file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("append")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-k")
+        .arg(ecc_private)
+        .arg(testfs.files[2].path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.failure();
+
+    // `mlar to-tar -i output.mla -k samples/test_x25519.pem -o output.tar`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("to-tar")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-k")
+        .arg(ecc_private)
+        .arg("-o")
+        .arg(tar_file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    // Inspect the created TAR file
+    ensure_tar_content(tar_file.path(), &testfs.files);
+}
+
+#[test]
+fn test_remove() {
+    // Create an archive with three files, remove the first one, then verify
+    // the content of the new archive
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let mlar_file_removed = NamedTempFile::new("removed.mla").unwrap();
+    let tar_file = NamedTempFile::new("output.tar").unwrap();
+    let ecc_public = Path::new("../samples/test_x25519_pub.pem");
+    let ecc_private = Path::new("../samples/test_x25519.pem");
+
+    // Create files
+    let testfs = setup();
+
+    // `mlar create -o output.mla -p samples/test_x25519_pub.pem file1.bin file2.bin file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg("-p")
+        .arg(ecc_public);
+
+    let mut file_list = String::new();
+    let mut file_list_kept = String::new();
+    for (i, file) in testfs.files.iter().enumerate() {
+        cmd.arg(file.path());
+        file_list.push_str(format!("{}\n", file.path().to_string_lossy()).as_str());
+        if i != 0 {
+            file_list_kept.push_str(format!("{}\n", file.path().to_string_lossy()).as_str());
+        }
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stderr(String::from(&file_list));
+
+    // `mlar remove -i output.mla -k samples/test_x25519.pem -o removed.mla -p samples/test_x25519_pub.pem file1.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("remove")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-k")
+        .arg(ecc_private)
+        .arg("-o")
+        .arg(mlar_file_removed.path())
+        .arg("-p")
+        .arg(ecc_public)
+        .arg(testfs.files[0].path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stderr(file_list_kept);
+
+    // `mlar to-tar -i removed.mla -k samples/test_x25519.pem -o output.tar`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("to-tar")
+        .arg("-i")
+        .arg(mlar_file_removed.path())
+        .arg("-k")
+        .arg(ecc_private)
+        .arg("-o")
+        .arg(tar_file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    // Inspect the created TAR file
+    ensure_tar_content(tar_file.path(), &testfs.files[1..]);
+}
+
+#[test]
+fn test_rename() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let mlar_file_renamed = NamedTempFile::new("renamed.mla").unwrap();
+    let testfs = setup();
+
+    // `mlar create -l -o output.mla file1.bin file2.bin file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create").arg("-l").arg("-o").arg(mlar_file.path());
+
+    let mut file_list = String::new();
+    for file in &testfs.files {
+        cmd.arg(file.path());
+        file_list.push_str(format!("{}\n", file.path().to_string_lossy()).as_str());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stderr(String::from(&file_list));
+
+    // `mlar rename -i output.mla -l -o renamed.mla file3.bin renamed.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("rename")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-l")
+        .arg("-o")
+        .arg(mlar_file_renamed.path())
+        .arg(&testfs.files_archive_order[2])
+        .arg("renamed.bin");
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    // `mlar list -i renamed.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("list").arg("-i").arg(mlar_file_renamed.path());
+
+    let mut file_list = String::new();
+    for file in &testfs.files {
+        if file.path() != testfs.files_archive_order[2] {
+            file_list.push_str(format!("{}\n", file.path().to_string_lossy()).as_str());
+        }
+    }
+    file_list.push_str("renamed.bin\n");
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stdout(file_list);
+
+    // `mlar cat -i renamed.mla renamed.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("cat")
+        .arg("-i")
+        .arg(mlar_file_renamed.path())
+        .arg("renamed.bin");
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert_eq!(assert.success().get_output().stdout, b"ABCDEFGHIJ");
+}
+
+#[test]
+fn test_merge() {
+    let mlar_file_a = NamedTempFile::new("a.mla").unwrap();
+    let mlar_file_b = NamedTempFile::new("b.mla").unwrap();
+    let mlar_file_merged = NamedTempFile::new("merged.mla").unwrap();
+    let testfs = setup();
+    let (file1, file2, file3) = (
+        &testfs.files_archive_order[0],
+        &testfs.files_archive_order[1],
+        &testfs.files_archive_order[2],
+    );
+
+    // `mlar create -l -o a.mla file1.bin file2.bin`
+    // `mlar create -l -o b.mla file2.bin file3.bin`
+    for (mlar_file, files) in &[
+        (&mlar_file_a, [file1, file2]),
+        (&mlar_file_b, [file2, file3]),
+    ] {
+        let mut cmd = Command::cargo_bin(UTIL).unwrap();
+        cmd.arg("create")
+            .arg("-l")
+            .arg("-o")
+            .arg(mlar_file.path())
+            .args(files);
+
+        println!("{:?}", cmd);
+        let assert = cmd.assert();
+        assert.success();
+    }
+
+    // `mlar merge -l -i a.mla -i b.mla -o merged.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("merge")
+        .arg("-l")
+        .arg("-i")
+        .arg(mlar_file_a.path())
+        .arg("-i")
+        .arg(mlar_file_b.path())
+        .arg("-o")
+        .arg(mlar_file_merged.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.failure();
+
+    // `mlar merge -l -i a.mla -i b.mla -o merged.mla --on-collision skip`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("merge")
+        .arg("-l")
+        .arg("-i")
+        .arg(mlar_file_a.path())
+        .arg("-i")
+        .arg(mlar_file_b.path())
+        .arg("-o")
+        .arg(mlar_file_merged.path())
+        .arg("--on-collision")
+        .arg("skip");
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stderr(format!(
+        "{}\n{}\n[WARNING] {} from {} skipped, already in the archive\n{}\n",
+        file1.to_string_lossy(),
+        file2.to_string_lossy(),
+        file2.to_string_lossy(),
+        mlar_file_b.path().to_string_lossy(),
+        file3.to_string_lossy(),
+    ));
+
+    // `mlar cat -i merged.mla file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("cat")
+        .arg("-i")
+        .arg(mlar_file_merged.path())
+        .arg(file3);
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert_eq!(assert.success().get_output().stdout, b"ABCDEFGHIJ");
+
+    // `mlar merge -l -i a.mla -i b.mla -o merged.mla --on-collision prefix`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("merge")
+        .arg("-l")
+        .arg("-i")
+        .arg(mlar_file_a.path())
+        .arg("-i")
+        .arg(mlar_file_b.path())
+        .arg("-o")
+        .arg(mlar_file_merged.path())
+        .arg("--on-collision")
+        .arg("prefix");
+
+    let prefixed = format!("b/{}", file2.to_string_lossy());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stderr(format!(
+        "{}\n{}\n{}\n{}\n",
+        file1.to_string_lossy(),
+        file2.to_string_lossy(),
+        prefixed,
+        file3.to_string_lossy(),
+    ));
+
+    // `mlar cat -i merged.mla b/file2.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("cat")
+        .arg("-i")
+        .arg(mlar_file_merged.path())
+        .arg(&prefixed);
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert_eq!(
+        assert.success().get_output().stdout,
+        std::fs::read(file2).unwrap()
+    );
+}
+
+#[test]
+fn test_split_join() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let mlar_file_joined = NamedTempFile::new("joined.mla").unwrap();
+    let volumes_dir = TempDir::new().unwrap();
+    let output_dir = TempDir::new().unwrap();
+    let testfs = setup();
+
+    // `mlar create -l -o output.mla file1.bin file2.bin file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create").arg("-l").arg("-o").arg(mlar_file.path());
+    for file in &testfs.files {
+        cmd.arg(file.path());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    // `mlar split -i output.mla -o volumes/output.mla -s 8M`
+    // 20MB of data, in 8MB volumes
+    let prefix = volumes_dir.path().join("output.mla");
+    let volumes: Vec<PathBuf> = (1..=3)
+        .map(|i| volumes_dir.path().join(format!("output.mla.{:03}", i)))
+        .collect();
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("split")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-o")
+        .arg(&prefix)
+        .arg("-s")
+        .arg("8M");
+
+    let mut volume_list = String::new();
+    for volume in &volumes {
+        volume_list.push_str(format!("{}\n", volume.to_string_lossy()).as_str());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stderr(volume_list);
+    for volume in &volumes {
+        assert!(std::fs::metadata(volume).unwrap().len() <= 8 * 1024 * 1024);
+    }
+
+    // The volume set can be read directly
+    // `mlar extract -i volumes/output.mla.001 -o output_dir`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("extract")
+        .arg("-i")
+        .arg(&volumes[0])
+        .arg("-o")
+        .arg(output_dir.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    ensure_directory_content(output_dir.path(), &testfs.files);
+
+    // `mlar join -o joined.mla volumes/output.mla.003 volumes/output.mla.001 volumes/output.mla.002`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("join")
+        .arg("-o")
+        .arg(mlar_file_joined.path())
+        .arg(&volumes[2])
+        .arg(&volumes[0])
+        .arg(&volumes[1]);
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+    assert_eq!(
+        std::fs::read(mlar_file.path()).unwrap(),
+        std::fs::read(mlar_file_joined.path()).unwrap()
+    );
+
+    // A missing volume is detected
+    // `mlar join -o joined.mla volumes/output.mla.001 volumes/output.mla.002`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("join")
+        .arg("-o")
+        .arg(mlar_file_joined.path())
+        .arg(&volumes[0])
+        .arg(&volumes[1]);
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.failure();
+
+    // A size overflowing 64 bits is refused
+    // `mlar split -i output.mla -o volumes/output.mla -s 16777216T`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("split")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-o")
+        .arg(&prefix)
+        .arg("-s")
+        .arg("16777216T");
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.failure();
+}
+
+#[test]
+fn test_update() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let directory = TempDir::new().unwrap();
+    let path_a = directory.path().join("a.txt");
+    let path_b = directory.path().join("subdir").join("b.txt");
+    let path_c = directory.path().join("c.txt");
+    std::fs::create_dir(directory.path().join("subdir")).unwrap();
+    std::fs::write(&path_a, b"content A").unwrap();
+    std::fs::write(&path_b, b"content B").unwrap();
+    // Files are named relatively to the directory's parent
+    let root = directory.path().parent().unwrap();
+    let name_b = path_b.strip_prefix(root).unwrap().to_string_lossy();
+    let name_c = path_c.strip_prefix(root).unwrap().to_string_lossy();
+
+    // `mlar create -l -o output.mla directory`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-l")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg(directory.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    // Only add a file
+    std::fs::write(&path_c, b"content C").unwrap();
+
+    // `mlar update -i output.mla directory`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("update")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg(directory.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stderr(format!("{}\n", name_c));
+
+    // Modify and remove files
+    std::fs::write(&path_b, b"new content B").unwrap();
+    std::fs::remove_file(&path_a).unwrap();
+
+    // `mlar update -i output.mla directory`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("update")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg(directory.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stderr(format!("{}\n", name_b));
+
+    // `mlar list -i output.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("list").arg("-i").arg(mlar_file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stdout(format!("{}\n{}\n", name_c, name_b));
+
+    // `mlar cat -i output.mla directory/subdir/b.txt`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("cat").arg("-i").arg(mlar_file.path()).arg(&*name_b);
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert_eq!(assert.success().get_output().stdout, b"new content B");
+
+    // A leftover temporary archive is not overwritten
+    let mut tmp_path = mlar_file.path().as_os_str().to_os_string();
+    tmp_path.push(".tmp");
+    std::fs::write(&tmp_path, b"leftover").unwrap();
+    std::fs::write(&path_c, b"new content C").unwrap();
+
+    // `mlar update -i output.mla directory`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("update")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg(directory.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.failure();
+    assert_eq!(std::fs::read(&tmp_path).unwrap(), b"leftover");
+}
+
+#[test]
+fn test_verify() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let testfs = setup();
+
+    // `mlar create -l -o output.mla file1.bin file2.bin file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create").arg("-l").arg("-o").arg(mlar_file.path());
+    for file in &testfs.files {
+        cmd.arg(file.path());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    // `mlar verify -i output.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("verify").arg("-i").arg(mlar_file.path());
+
+    let mut expected = String::new();
+    for file in &testfs.files {
+        expected.push_str(format!("{}: OK\n", file.path().to_string_lossy()).as_str());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stdout(expected);
+
+    // Corrupt the content of file3.bin, which is stored as is without layers
+    let mut data = std::fs::read(mlar_file.path()).unwrap();
+    let pos = data
+        .windows(10)
+        .position(|window| window == b"ABCDEFGHIJ")
+        .unwrap();
+    data[pos] = b'Z';
+    std::fs::write(mlar_file.path(), data).unwrap();
+
+    // `mlar verify -i output.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("verify").arg("-i").arg(mlar_file.path());
+
+    let mut expected = String::new();
+    for file in &testfs.files {
+        let status = if file.path() == testfs.files_archive_order[2] {
+            "FAIL (hash mismatch)"
+        } else {
+            "OK"
+        };
+        expected.push_str(format!("{}: {}\n", file.path().to_string_lossy(), status).as_str());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.failure().stdout(expected);
+}
+
+#[test]
+fn test_hash_algorithm() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let testfs = setup();
+
+    // `mlar create -l compress --hash blake3 -o output.mla file1.bin file2.bin file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-l")
+        .arg("compress")
+        .arg("--hash")
+        .arg("blake3")
+        .arg("-o")
+        .arg(mlar_file.path());
+    for file in &testfs.files {
+        cmd.arg(file.path());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    // `mlar info -v -i output.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("info").arg("-v").arg("-i").arg(mlar_file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    let stdout = String::from_utf8(assert.success().get_output().stdout.clone()).unwrap();
+    assert!(stdout.lines().any(|line| line == "Hash: blake3"));
+
+    // `mlar list -vv -i output.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("list").arg("-vv").arg("-i").arg(mlar_file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    let stdout = String::from_utf8(assert.success().get_output().stdout.clone()).unwrap();
+    for line in stdout.lines() {
+        assert!(line.contains("(blake3:"), "{}", line);
+    }
+
+    // `mlar verify -i output.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("verify").arg("-i").arg(mlar_file.path());
+
+    let mut expected = String::new();
+    for file in &testfs.files {
+        expected.push_str(format!("{}: OK\n", file.path().to_string_lossy()).as_str());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stdout(expected);
+}
+
+#[test]
+fn test_to_tar_stdout() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let tar_file = NamedTempFile::new("output.tar").unwrap();
+    let testfs = setup();
+
+    // `mlar create -l -o output.mla file1.bin file2.bin file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create").arg("-l").arg("-o").arg(mlar_file.path());
+    for file in &testfs.files_archive_order {
+        cmd.arg(file);
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    // `mlar to-tar -i output.mla -o -`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("to-tar")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-o")
+        .arg("-");
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    let tar_data = assert.success().get_output().stdout.clone();
+    File::create(tar_file.path())
+        .unwrap()
+        .write_all(&tar_data)
+        .unwrap();
+
+    ensure_tar_content(tar_file.path(), &testfs.files);
+
+    // Entries follow the archive order
+    let mut arch = Archive::new(tar_data.as_slice());
+    let names: Vec<String> = arch
+        .entries()
+        .unwrap()
+        .map(|entry| {
+            let entry = entry.unwrap();
+            let name = entry.path().unwrap();
+            name.file_name().unwrap().to_string_lossy().to_string()
+        })
+        .collect();
+    assert_eq!(names, vec!["file1.bin", "file2.bin", "file3.bin"]);
+}
+
+#[test]
+fn test_to_zip() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let zip_file = NamedTempFile::new("output.zip").unwrap();
+    let password_file = NamedTempFile::new("password.txt").unwrap();
+    password_file.write_binary(b"secret\n").unwrap();
+    let testfs = setup();
+
+    // `mlar create -l -o output.mla file1.bin file2.bin file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create").arg("-l").arg("-o").arg(mlar_file.path());
+    for file in &testfs.files {
+        cmd.arg(file.path());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    for password in &[None, Some("secret")] {
+        // `mlar to-zip -i output.mla -o output.zip [--password-file password.txt]`
+        let mut cmd = Command::cargo_bin(UTIL).unwrap();
+        cmd.arg("to-zip")
+            .arg("-i")
+            .arg(mlar_file.path())
+            .arg("-o")
+            .arg(zip_file.path());
+        if password.is_some() {
+            cmd.arg("--password-file").arg(password_file.path());
+        }
+
+        println!("{:?}", cmd);
+        let assert = cmd.assert();
+        assert.success();
+
+        // Inspect the created ZIP file; absolute paths are made relative
+        let mut arch = ZipArchive::new(File::open(zip_file.path()).unwrap()).unwrap();
+        assert_eq!(arch.len(), testfs.files.len());
+        for file in &testfs.files {
+            let fname = file.path().to_string_lossy();
+            let fname = fname.trim_start_matches('/');
+            let mut content = Vec::new();
+            match password {
+                None => arch.by_name(fname),
+                Some(password) => arch.by_name_decrypt(fname, password.as_bytes()),
+            }
+            .unwrap()
+            .read_to_end(&mut content)
+            .unwrap();
+            assert_eq!(content, std::fs::read(file.path()).unwrap());
+        }
+    }
+}
+
+#[test]
+fn test_from_zip() {
+    let zip_file = NamedTempFile::new("input.zip").unwrap();
+    let password_file = NamedTempFile::new("password.txt").unwrap();
+    password_file.write_binary(b"secret\n").unwrap();
+    let testfs = setup();
+
+    for password in &[None, Some("secret")] {
+        let mlar_file = NamedTempFile::new("output.mla").unwrap();
+        let output_dir = TempDir::new().unwrap();
+
+        // Build a ZIP archive, with a directory and the test files
+        let mut zip = ZipWriter::new(File::create(zip_file.path()).unwrap());
+        let mut options = SimpleFileOptions::default();
+        if let Some(password) = password {
+            options = options.with_aes_encryption(AesMode::Aes256, password);
+        }
+        zip.add_directory("subdir", options).unwrap();
+        let names = ["file1.bin", "file2.bin", "subdir/file3.bin"];
+        for (path, name) in testfs.files_archive_order.iter().zip(names.iter()) {
+            zip.start_file(*name, options).unwrap();
+            zip.write_all(&std::fs::read(path).unwrap()).unwrap();
+        }
+        zip.finish().unwrap();
+
+        // `mlar from-zip -l -i input.zip -o output.mla [--password-file password.txt]`
+        let mut cmd = Command::cargo_bin(UTIL).unwrap();
+        cmd.arg("from-zip")
+            .arg("-l")
+            .arg("-i")
+            .arg(zip_file.path())
+            .arg("-o")
+            .arg(mlar_file.path());
+        if password.is_some() {
+            cmd.arg("--password-file").arg(password_file.path());
+        }
+
+        println!("{:?}", cmd);
+        let assert = cmd.assert();
+        assert
+            .success()
+            .stderr("file1.bin\nfile2.bin\nsubdir/file3.bin\n");
+
+        // `mlar extract -i output.mla -o output_dir`
+        let mut cmd = Command::cargo_bin(UTIL).unwrap();
+        cmd.arg("extract")
+            .arg("-i")
+            .arg(mlar_file.path())
+            .arg("-o")
+            .arg(output_dir.path());
+
+        println!("{:?}", cmd);
+        let assert = cmd.assert();
+        assert.success();
+
+        ensure_directory_content(output_dir.path(), &testfs.files);
+    }
+}
+
+#[test]
+fn test_from_tar() {
+    let tar_file = NamedTempFile::new("input.tar").unwrap();
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let output_dir = TempDir::new().unwrap();
+    let testfs = setup();
+
+    // Build a TAR archive, with a directory and the test files
+    let mut tar = Builder::new(File::create(tar_file.path()).unwrap());
+    tar.append_dir("subdir", ".").unwrap();
+    let names = ["file1.bin", "file2.bin", "subdir/file3.bin"];
+    for (path, name) in testfs.files_archive_order.iter().zip(names.iter()) {
+        tar.append_path_with_name(path, name).unwrap();
+    }
+    tar.finish().unwrap();
+    drop(tar);
+
+    // `cat input.tar | mlar from-tar -l -i - -o output.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("from-tar")
+        .arg("-l")
+        .arg("-i")
+        .arg("-")
+        .arg("-o")
+        .arg(mlar_file.path());
+    cmd.write_stdin(std::fs::read(tar_file.path()).unwrap());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert
+        .success()
+        .stderr("file1.bin\nfile2.bin\nsubdir/file3.bin\n");
+
+    // `mlar extract -i output.mla -o output_dir`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("extract")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-o")
+        .arg(output_dir.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    ensure_directory_content(output_dir.path(), &testfs.files);
+}
+
+#[test]
+fn test_stdio() {
+    // Create an archive on stdout, and check it
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let tar_file = NamedTempFile::new("output.tar").unwrap();
+    let ecc_public = Path::new("../samples/test_x25519_pub.pem");
+    let ecc_private = Path::new("../samples/test_x25519.pem");
+
+    // Create files
+    let testfs = setup();
+
+    // `mlar create -o - -p samples/test_x25519_pub.pem file1.bin file2.bin file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-o")
+        .arg("-")
+        .arg("-p")
+        .arg(ecc_public);
+
+    let mut file_list = String::new();
+    for file in &testfs.files {
+        cmd.arg(file.path());
+        file_list.push_str(format!("{}\n", file.path().to_string_lossy()).as_str());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    let archive_data = assert.get_output().stdout.clone();
+    assert.success().stderr(String::from(&file_list));
+
+    File::create(mlar_file.path())
+        .unwrap()
+        .write_all(&archive_data)
+        .unwrap();
+    // `mlar to-tar -i output.mla -k samples/test_x25519.pem -o output.tar`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("to-tar")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-k")
+        .arg(ecc_private)
+        .arg("-o")
+        .arg(tar_file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    // Inspect the created TAR file
+    ensure_tar_content(&tar_file.path(), &testfs.files);
+}
+
+#[test]
+fn test_multi_fileorders() {
+    // Create several archive with all possible file order. Result should be the same
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let tar_file = NamedTempFile::new("output.tar").unwrap();
+    let ecc_public = Path::new("../samples/test_x25519_pub.pem");
+    let ecc_private = Path::new("../samples/test_x25519.pem");
+
+    // Create files
+    let testfs = setup();
+    let path_array: &[&Path] = &[
+        testfs.files[0].path(),
+        testfs.files[1].path(),
+        testfs.files[2].path(),
+    ];
+    let path_array = [path_array];
+    let permutator = Permutator::new(&path_array[..]);
+
+    for list in permutator {
+        let set: HashSet<_> = list.iter().collect(); // dedup
+        if set.len() != list.len() {
+            // Duplicate, avoid
+            continue;
+        }
+
+        // `mlar create -o output.mla -p samples/test_x25519_pub.pem file1.bin file2.bin file3.bin`
+        let mut cmd = Command::cargo_bin(UTIL).unwrap();
+        cmd.arg("create")
+            .arg("-o")
+            .arg(mlar_file.path())
+            .arg("-p")
+            .arg(ecc_public);
+
+        let mut file_list = String::new();
+        for file in list {
+            cmd.arg(file);
+            file_list.push_str(format!("{}\n", file.to_string_lossy()).as_str());
+        }
+
+        println!("{:?}", cmd);
+        let assert = cmd.assert();
+        assert.success().stderr(String::from(&file_list));
+
+        // `mlar to-tar -i convert.mla -k samples/test_x25519.pem -o output.tar`
+        let mut cmd = Command::cargo_bin(UTIL).unwrap();
+        cmd.arg("to-tar")
+            .arg("-i")
+            .arg(mlar_file.path())
+            .arg("-k")
+            .arg(ecc_private)
+            .arg("-o")
+            .arg(tar_file.path());
+
+        println!("{:?}", cmd);
+        let assert = cmd.assert();
+        assert.success();
+
+        // Inspect the created TAR file
+        ensure_tar_content(&tar_file.path(), &testfs.files);
+    }
+}
+
+#[test]
+fn test_verbose_listing() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let testfs = setup();
+
+    // `mlar create -l -o output.mla
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create").arg("-l").arg("-o").arg(mlar_file.path());
+
+    let mut file_list = String::new();
+    for file in &testfs.files {
+        cmd.arg(file.path());
+        file_list.push_str(format!("{}\n", file.path().to_string_lossy()).as_str());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stderr(String::from(&file_list));
+
+    // `mlar list -i output.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("list").arg("-i").arg(mlar_file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stdout(file_list);
+
+    // `mlar list -v -i output.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("list").arg("-v").arg("-i").arg(mlar_file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    // `mlar list -vv -i output.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("list").arg("-vv").arg("-i").arg(mlar_file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+}
+
+#[test]
+fn test_extract() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let mut testfs = setup();
+
+    // Compressed, as files of archives without layers are copied straight
+    // instead of being extracted
+    // `mlar create -l compress -o output.mla
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-l")
+        .arg("compress")
+        .arg("-o")
+        .arg(mlar_file.path());
+
+    let mut file_list = String::new();
+    for file in &testfs.files {
+        cmd.arg(file.path());
+        file_list.push_str(format!("{}\n", file.path().to_string_lossy()).as_str());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stderr(String::from(&file_list));
+
+    let mut file_list = String::new();
+    for file in &testfs.files {
+        file_list.push_str(format!("{}\n", file.path().to_string_lossy()).as_str());
+    }
+
+    // Test global (with all files)
+
+    // `mlar extract -v -i output.mla -o ouput_dir -g '*'`
+    let output_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("extract")
+        .arg("-v")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-o")
+        .arg(output_dir.path())
+        .arg("-g")
+        .arg("*");
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stdout(file_list);
+
+    ensure_directory_content(output_dir.path(), &testfs.files);
+
+    // Test linear extraction of all files
+
+    // `mlar extract -v -i output.mla -o output_dir`
+    let output_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("extract")
+        .arg("-v")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-o")
+        .arg(output_dir.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert
+        .success()
+        .stdout("Extracting the whole archive using a linear extraction\n");
+
+    ensure_directory_content(output_dir.path(), &testfs.files);
+
+    // Test linear extraction, with a single file open at a time
+    // `mlar extract --max-open-files 1 -i output.mla -o output_dir`
+    let output_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("extract")
+        .arg("--max-open-files")
+        .arg("1")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-o")
+        .arg(output_dir.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    ensure_directory_content(output_dir.path(), &testfs.files);
+
+    // Test extraction of one file explicitly
+    // `mlar extract -v -i output.mla -o ouput_dir file1`
+    let one_filename = &testfs.files_archive_order[0];
+    let mut one_file = Vec::new();
+    loop {
+        match testfs.files.pop() {
+            None => {
+                break;
+            }
+            Some(ntf) => {
+                if ntf.path() == one_filename {
+                    one_file.push(ntf);
+                }
+            }
+        }
+    }
+    let output_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("extract")
+        .arg("-v")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-o")
+        .arg(output_dir.path())
+        .arg(one_filename);
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert
+        .success()
+        .stdout(format!("{}\n", one_filename.to_string_lossy()));
+
+    ensure_directory_content(output_dir.path(), &one_file);
+
+    // Test extraction of one file through glob
+    // `mlar extract -v -i output.mla -o ouput_dir -g *1*`
+    let output_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("extract")
+        .arg("-v")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-o")
+        .arg(output_dir.path())
+        .arg("-g")
+        .arg("*file1*");
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert
+        .success()
+        .stdout(format!("{}\n", one_filename.to_string_lossy()));
+
+    ensure_directory_content(output_dir.path(), &one_file);
+}
+
+#[test]
+fn test_extract_stream() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let ecc_public = Path::new("../samples/test_x25519_pub.pem");
+    let ecc_private = Path::new("../samples/test_x25519.pem");
+    let testfs = setup();
+
+    // `mlar create -o output.mla -p samples/test_x25519_pub.pem file1.bin file2.bin file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg("-p")
+        .arg(ecc_public);
+    for file in &testfs.files {
+        cmd.arg(file.path());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    // `cat output.mla | mlar extract --stream -i - -k samples/test_x25519.pem -o output_dir`
+    let output_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("extract")
+        .arg("--stream")
+        .arg("-i")
+        .arg("-")
+        .arg("-k")
+        .arg(ecc_private)
+        .arg("-o")
+        .arg(output_dir.path());
+    cmd.write_stdin(std::fs::read(mlar_file.path()).unwrap());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    ensure_directory_content(output_dir.path(), &testfs.files);
+
+    // A truncated stream is an error
+    let data = std::fs::read(mlar_file.path()).unwrap();
+    let output_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("extract")
+        .arg("--stream")
+        .arg("-i")
+        .arg("-")
+        .arg("-k")
+        .arg(ecc_private)
+        .arg("-o")
+        .arg(output_dir.path());
+    cmd.write_stdin(data[..data.len() / 2].to_vec());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.failure();
+
+    // Unfinished files are removed
+    let pattern = output_dir.path().to_string_lossy() + "/**/*.mla-partial";
+    assert_eq!(glob::glob(&pattern).unwrap().count(), 0);
+}
+
+#[test]
+fn test_extract_raw() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let testfs = setup();
+
+    // `mlar create -l -o output.mla file1.bin file2.bin file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create").arg("-l").arg("-o").arg(mlar_file.path());
+    for file in &testfs.files {
+        cmd.arg(file.path());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    // Without layers, the content is copied straight from the archive file
+    for threads in &["1", "2"] {
+        // `mlar extract -i output.mla -o output_dir --threads <threads>`
+        let output_dir = TempDir::new().unwrap();
+        let mut cmd = Command::cargo_bin(UTIL).unwrap();
+        cmd.arg("extract")
+            .arg("-i")
+            .arg(mlar_file.path())
+            .arg("-o")
+            .arg(output_dir.path())
+            .arg("--threads")
+            .arg(threads);
+
+        println!("{:?}", cmd);
+        let assert = cmd.assert();
+        assert.success();
+
+        ensure_directory_content(output_dir.path(), &testfs.files);
+    }
+}
+
+#[test]
+fn test_cat() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let testfs = setup();
+
+    // `mlar create -l -o output.mla
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create").arg("-l").arg("-o").arg(mlar_file.path());
+
+    let mut file_list = String::new();
+    for file in &testfs.files {
+        cmd.arg(file.path());
+        file_list.push_str(format!("{}\n", file.path().to_string_lossy()).as_str());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stderr(String::from(&file_list));
+
+    // `mlar cat -i output.mla file1`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("cat")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg(&testfs.files_archive_order[2]);
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+
+    let mut expected_content = Vec::new();
+    File::open(&testfs.files_archive_order[2])
+        .unwrap()
+        .read_to_end(&mut expected_content)
+        .unwrap();
+    assert_eq!(assert.success().get_output().stdout, expected_content);
+}
+
+#[test]
+fn test_cat_head_tail() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let file1 = NamedTempFile::new("file1.log").unwrap();
+    let mut content = String::new();
+    for i in 0..100_000 {
+        content.push_str(&format!("line {}\n", i));
+    }
+    file1.write_binary(content.as_bytes()).unwrap();
+
+    // `mlar create -l compress -o output.mla file1.log`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-l")
+        .arg("compress")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg(file1.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    let cases: &[(&[&str], &str)] = &[
+        (&["--head", "2"], "line 0\nline 1\n"),
+        (&["--tail", "2"], "line 99998\nline 99999\n"),
+        (&["--head", "3", "--bytes"], "lin"),
+        (&["--tail", "3", "-c"], "99\n"),
+        (&["--tail", "0"], ""),
+        (&["--head", "200000"], &content),
+        (&["--tail", "200000"], &content),
+    ];
+    for (options, expected) in cases {
+        // `mlar cat -i output.mla --tail 2 file1.log`
+        let mut cmd = Command::cargo_bin(UTIL).unwrap();
+        cmd.arg("cat")
+            .arg("-i")
+            .arg(mlar_file.path())
+            .args(*options)
+            .arg(file1.path());
+
+        println!("{:?}", cmd);
+        let assert = cmd.assert();
+        assert.success().stdout(String::from(*expected));
+    }
+}
+
+#[test]
+fn test_cat_separator() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let file1 = NamedTempFile::new("file1.txt").unwrap();
+    file1.write_binary(b"content1\n").unwrap();
+    let file2 = NamedTempFile::new("file2.txt").unwrap();
+    file2.write_binary(b"content2").unwrap();
+
+    // `mlar create -l -o output.mla file1.txt file2.txt`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-l")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg(file1.path())
+        .arg(file2.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    let name1 = file1.path().to_string_lossy();
+    let name2 = file2.path().to_string_lossy();
+    let cases: &[(&[&str], String)] = &[
+        (&[], "content1\ncontent2".to_string()),
+        (&["--raw"], "content1\ncontent2".to_string()),
+        (
+            &["--filename-header"],
+            format!("==> {} <==\ncontent1\n==> {} <==\ncontent2", name1, name2),
+        ),
+        (&["--separator", r"\0"], "content1\n\0content2".to_string()),
+        (
+            &["--filename-header", "--separator", r"\n"],
+            format!("==> {} <==\ncontent1\n\n==> {} <==\ncontent2", name1, name2),
+        ),
+    ];
+    for (options, expected) in cases {
+        // `mlar cat -i output.mla --filename-header file1.txt file2.txt`
+        let mut cmd = Command::cargo_bin(UTIL).unwrap();
+        cmd.arg("cat")
+            .arg("-i")
+            .arg(mlar_file.path())
+            .args(*options)
+            .arg(file1.path())
+            .arg(file2.path());
+
+        println!("{:?}", cmd);
+        let assert = cmd.assert();
+        assert.success().stdout(expected.clone());
+    }
+
+    // `mlar cat -i output.mla --raw --separator , file1.txt file2.txt`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("cat")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("--raw")
+        .arg("--separator")
+        .arg(",")
+        .arg(file1.path())
+        .arg(file2.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.failure();
+}
+
+#[test]
+fn test_keygen() {
+    // Gen a keypair, create and list an archive using them
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let output_dir = TempDir::new().unwrap();
+    let base_name = output_dir.path().join("key");
+    let testfs = setup();
+
+    // `mlar keygen tempdir/key`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("keygen").arg(&base_name);
+    cmd.assert().success();
+
+    // `mlar create -p tempdir/key.pub -o output.mla file1 file2 file3`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-p")
+        .arg(base_name.with_extension("pub"))
+        .arg("-o")
+        .arg(mlar_file.path());
+
+    let mut file_list = String::new();
+    for file in &testfs.files {
+        cmd.arg(file.path());
+        file_list.push_str(format!("{}\n", file.path().to_string_lossy()).as_str());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stderr(String::from(&file_list));
+
+    // `mlar list -k tempdir/key -i output.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("list")
+        .arg("-k")
+        .arg(base_name)
+        .arg("-i")
+        .arg(mlar_file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stdout(file_list);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_keygen_existing_file() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // An existing file, readable by everyone, is restricted before the
+    // private key is written to it
+    let output_dir = TempDir::new().unwrap();
+    let base_name = output_dir.path().join("key");
+    std::fs::write(&base_name, b"").unwrap();
+    std::fs::set_permissions(&base_name, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+    // `mlar keygen tempdir/key`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("keygen").arg(&base_name);
+    cmd.assert().success();
+
+    let mode = metadata(&base_name).unwrap().permissions().mode() & 0o777;
+    assert_eq!(mode, 0o600);
+}
+
+#[test]
+fn test_keygen_formats() {
+    // Gen keypairs in each output format, create and list an archive using them
+    let output_dir = TempDir::new().unwrap();
+    let testfs = setup();
+
+    let mut file_list = String::new();
+    for file in &testfs.files {
+        file_list.push_str(format!("{}\n", file.path().to_string_lossy()).as_str());
+    }
+
+    for (name, args) in [
+        ("key_pem", &["-f", "pem"][..]),
+        ("key_openssh", &["-f", "openssh"][..]),
+        ("key_raw", &["-f", "raw"][..]),
+        ("key_combined", &["--combined"][..]),
+        ("key_openssh_combined", &["-f", "openssh", "--combined"][..]),
+        ("key_hybrid", &["-f", "hybrid"][..]),
+        ("key_hybrid_combined", &["-f", "hybrid", "--combined"][..]),
+    ] {
+        let mlar_file = NamedTempFile::new("output.mla").unwrap();
+        let base_name = output_dir.path().join(name);
+        let combined = args.contains(&"--combined");
+
+        // `mlar keygen [args] tempdir/name`
+        let mut cmd = Command::cargo_bin(UTIL).unwrap();
+        cmd.arg("keygen").args(args).arg(&base_name);
+        println!("{:?}", cmd);
+        cmd.assert().success();
+        assert_eq!(base_name.with_extension("pub").exists(), !combined);
+
+        let public_key = if combined {
+            base_name.clone()
+        } else {
+            base_name.with_extension("pub")
+        };
+
+        // `mlar create -p public_key -o output.mla file1 file2 file3`
+        let mut cmd = Command::cargo_bin(UTIL).unwrap();
+        cmd.arg("create")
+            .arg("-p")
+            .arg(&public_key)
+            .arg("-o")
+            .arg(mlar_file.path());
+        for file in &testfs.files {
+            cmd.arg(file.path());
+        }
+
+        println!("{:?}", cmd);
+        let assert = cmd.assert();
+        assert.success().stderr(String::from(&file_list));
+
+        // `mlar list -k tempdir/name -i output.mla`
+        let mut cmd = Command::cargo_bin(UTIL).unwrap();
+        cmd.arg("list")
+            .arg("-k")
+            .arg(&base_name)
+            .arg("-i")
+            .arg(mlar_file.path());
+
+        println!("{:?}", cmd);
+        let assert = cmd.assert();
+        assert.success().stdout(String::from(&file_list));
+    }
+}
+
+#[test]
+fn test_keyderive() {
+    // Derive a keypair from a root key, create an archive with the derived
+    // public key, and list it with the private key derived again
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let output_dir = TempDir::new().unwrap();
+    let root = Path::new("../samples/test_x25519.pem");
+    let derived = output_dir.path().join("derived");
+    let intermediate = output_dir.path().join("intermediate");
+    let derived_again = output_dir.path().join("derived_again");
+    let testfs = setup();
+
+    // `mlar keyderive -k samples/test_x25519.pem --path case/host tempdir/derived`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("keyderive")
+        .arg("-k")
+        .arg(root)
+        .arg("--path")
+        .arg("case/host")
+        .arg(&derived);
+    println!("{:?}", cmd);
+    cmd.assert().success();
+
+    // `mlar create -p tempdir/derived.pub -o output.mla file1 file2 file3`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-p")
+        .arg(derived.with_extension("pub"))
+        .arg("-o")
+        .arg(mlar_file.path());
+
+    let mut file_list = String::new();
+    for file in &testfs.files {
+        cmd.arg(file.path());
+        file_list.push_str(format!("{}\n", file.path().to_string_lossy()).as_str());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stderr(String::from(&file_list));
+
+    // Derive the same key again, through an intermediate key
+    // `mlar keyderive -k samples/test_x25519.pem --path case tempdir/intermediate`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("keyderive")
+        .arg("-k")
+        .arg(root)
+        .arg("--path")
+        .arg("case")
+        .arg(&intermediate);
+    println!("{:?}", cmd);
+    cmd.assert().success();
+
+    // `mlar keyderive -k tempdir/intermediate --path host tempdir/derived_again`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("keyderive")
+        .arg("-k")
+        .arg(&intermediate)
+        .arg("--path")
+        .arg("host")
+        .arg(&derived_again);
+    println!("{:?}", cmd);
+    cmd.assert().success();
+    assert_eq!(
+        std::fs::read(derived.with_extension("pub")).unwrap(),
+        std::fs::read(derived_again.with_extension("pub")).unwrap()
+    );
+
+    // `mlar list -k tempdir/derived_again -i output.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("list")
+        .arg("-k")
+        .arg(&derived_again)
+        .arg("-i")
+        .arg(mlar_file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stdout(String::from(&file_list));
+
+    // The root key alone can't read the archive
+    // `mlar list -k samples/test_x25519.pem -i output.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("list")
+        .arg("-k")
+        .arg(root)
+        .arg("-i")
+        .arg(mlar_file.path());
+
+    println!("{:?}", cmd);
+    cmd.assert().failure();
+}
+
+#[test]
+fn test_list_detect() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let pdf = NamedTempFile::new("file.pdf").unwrap();
+    pdf.write_binary(b"%PDF-1.4\n\x00\x01").unwrap();
+    let text = NamedTempFile::new("file.txt").unwrap();
+    text.write_binary(b"Some text\n").unwrap();
+
+    // `mlar create -l compress -o output.mla file.pdf file.txt`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-l")
+        .arg("compress")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg(pdf.path())
+        .arg(text.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    // `mlar list --detect -i output.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("list")
+        .arg("--detect")
+        .arg("-i")
+        .arg(mlar_file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    let stdout = String::from_utf8(assert.success().get_output().stdout.clone()).unwrap();
+    let mut lines: Vec<&str> = stdout.lines().collect();
+    lines.sort();
+    let mut expected = vec![
+        format!("{} - application/pdf", pdf.path().to_string_lossy()),
+        format!("{} - text/plain", text.path().to_string_lossy()),
+    ];
+    expected.sort();
+    assert_eq!(lines, expected);
+
+    // `mlar list --format csv --fields name,type -i output.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("list")
+        .arg("--format")
+        .arg("csv")
+        .arg("--fields")
+        .arg("name,type")
+        .arg("-i")
+        .arg(mlar_file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    let stdout = String::from_utf8(assert.success().get_output().stdout.clone()).unwrap();
+    let mut lines: Vec<&str> = stdout.lines().collect();
+    lines.sort();
+    let mut expected = vec![
+        "name,type".to_string(),
+        format!("{},application/pdf", pdf.path().to_string_lossy()),
+        format!("{},text/plain", text.path().to_string_lossy()),
+    ];
+    expected.sort();
+    assert_eq!(lines, expected);
+}
+
+#[test]
+fn test_grep() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let file1 = NamedTempFile::new("file1.log").unwrap();
+    file1
+        .write_binary(b"first line\nevil.example.com was here\n")
+        .unwrap();
+    let file2 = NamedTempFile::new("file2.bin").unwrap();
+    file2.write_binary(b"\x00\x01MZ\x90\x00").unwrap();
+
+    // `mlar create -l compress -o output.mla file1.log file2.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-l")
+        .arg("compress")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg(file1.path())
+        .arg(file2.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    // `mlar grep -i output.mla 'evil\.[a-z]+\.com'`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("grep")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg(r"evil\.[a-z]+\.com");
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stdout(format!(
+        "{}:11:evil.example.com\n",
+        file1.path().to_string_lossy()
+    ));
+
+    // `mlar grep -i output.mla --hex 4d5a90`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("grep")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("--hex")
+        .arg("4d5a90");
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    // The match is printed lossily, as UTF-8
+    assert.success().stdout(format!(
+        "{}:2:MZ{}\n",
+        file2.path().to_string_lossy(),
+        char::REPLACEMENT_CHARACTER
+    ));
+
+    // Only in the given files
+    // `mlar grep -i output.mla evil file2.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("grep")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("evil")
+        .arg(file2.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.failure().stdout("");
+}
+
+#[test]
+fn test_comment() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let ecc_public = Path::new("../samples/test_x25519_pub.pem");
+    let testfs = setup();
+
+    // `mlar create -o output.mla -p samples/test_x25519_pub.pem --comment '{"case": 42}' file1.bin file2.bin file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg("-p")
+        .arg(ecc_public)
+        .arg("--comment")
+        .arg(r#"{"case": 42}"#);
+    for file in &testfs.files {
+        cmd.arg(file.path());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    // The comment is readable without the key
+    // `mlar info -i output.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("info").arg("-i").arg(mlar_file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    let stdout = String::from_utf8(assert.success().get_output().stdout.clone()).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines[0], "Format version: 19");
+    assert_eq!(lines[1], r#"Comment: {"case": 42}"#);
+}
+
+#[test]
+fn test_stat() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let ecc_public = Path::new("../samples/test_x25519_pub.pem");
+    let ecc_private = Path::new("../samples/test_x25519.pem");
+    let testfs = setup();
+
+    // `mlar create -o output.mla -p samples/test_x25519_pub.pem file1.bin file2.bin file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg("-p")
+        .arg(ecc_public);
+    for file in &testfs.files {
+        cmd.arg(file.path());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    // `mlar stat -k samples/test_x25519.pem -i output.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("stat")
+        .arg("-k")
+        .arg(ecc_private)
+        .arg("-i")
+        .arg(mlar_file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    let stdout = String::from_utf8(assert.success().get_output().stdout.clone()).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), testfs.files.len() + 5);
+    for file in &testfs.files {
+        let prefix = format!("{} - ", file.path().to_string_lossy());
+        assert!(lines.iter().any(|line| line.starts_with(&prefix)));
+    }
+    assert_eq!(lines[3], "Files: 3");
+    assert_eq!(lines[4], "Total size: 20.00 MB");
+    assert!(lines[5].starts_with("Compressed size: "));
+    assert!(lines[6].starts_with("Compression rate: "));
+    assert!(lines[7].starts_with("Archive size: "));
+}
+
+#[test]
+fn test_verbose_info() {
+    let ecc_public = Path::new("../samples/test_x25519_pub.pem");
+    let ecc_private = Path::new("../samples/test_x25519.pem");
+    let ecc_public_2 = Path::new("../samples/test_x25519_2_pub.pem");
+
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let testfs = setup();
+
+    // `mlar create -l -o output.mla
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create").arg("-o").arg(mlar_file.path());
+    cmd.arg("-l").arg("compress");
+    cmd.arg("-l").arg("encrypt");
+    cmd.arg("-p").arg(ecc_public);
+    cmd.arg("-p").arg(ecc_public_2);
+
+    let mut file_list = String::new();
+    for file in &testfs.files {
+        cmd.arg(file.path());
+        file_list.push_str(format!("{}\n", file.path().to_string_lossy()).as_str());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stderr(String::from(&file_list));
+
+    // `mlar info -k <key> -i output.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("info")
+        .arg("-k")
+        .arg(ecc_private)
+        .arg("-i")
+        .arg(mlar_file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stdout(
+        "Format version: 19
+Encryption: true
+Signature: false
+Compression: true
+Error correction: false
+Files: 3
+Total size: 20.00 MB
+",
+    );
+
+    // `mlar info -k <key> -v -i output.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("info")
+        .arg("-k")
+        .arg(ecc_private)
+        .arg("-v")
+        .arg("-i")
+        .arg(mlar_file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    let stdout = String::from_utf8(assert.success().get_output().stdout.clone()).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines[0], "Format version: 19");
+    assert_eq!(lines[1], "Encryption: true");
+    assert_eq!(lines[2], "  Recipients: 2");
+    assert_eq!(lines[3], "  Cipher: aes-256-gcm");
+    assert_eq!(lines[4], "Signature: false");
+    assert_eq!(lines[5], "Compression: true");
+    assert_eq!(lines[6], "  Algorithm: brotli");
+    // 20MB of data, in 4MB blocks
+    assert_eq!(lines[7], "  Blocks: 6 (of 4 MB uncompressed)");
+    assert!(lines[8].starts_with("  Compressed size: "));
+    assert!(lines[9].starts_with("  Compression rate: "));
+    assert_eq!(lines[10], "Error correction: false");
+    assert_eq!(lines[11], "Hash: sha256");
+    assert_eq!(lines[12], "Files: 3");
+    assert_eq!(lines[13], "Total size: 20.00 MB");
+}
+
+/// Kill the wrapped process on drop, even if the test fails
+struct KillOnDrop(Child);
+
+impl Drop for KillOnDrop {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// Perform a GET request on `path`, returning the status code, the headers
+/// and the body
+fn http_get(port: u16, path: &str, range: Option<&str>) -> (u16, String, Vec<u8>) {
+    // The server might still be starting
+    let mut stream = None;
+    for _ in 0..100 {
+        if let Ok(s) = TcpStream::connect(("127.0.0.1", port)) {
+            stream = Some(s);
+            break;
+        }
+        sleep(Duration::from_millis(100));
+    }
+    let mut stream = stream.expect("Unable to connect to mlar serve");
+
+    let mut request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n", path);
+    if let Some(range) = range {
+        request.push_str(&format!("Range: {}\r\n", range));
+    }
+    request.push_str("Connection: close\r\n\r\n");
+    stream.write_all(request.as_bytes()).unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).unwrap();
+
+    let split = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .expect("Invalid HTTP response");
+    let headers = String::from_utf8(response[..split].to_vec()).unwrap();
+    let status = headers.split(' ').nth(1).unwrap().parse().unwrap();
+    (status, headers, response[split + 4..].to_vec())
+}
+
+#[test]
+fn test_serve() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let testfs = setup();
+
+    // `mlar create -l -o output.mla file1.bin file2.bin file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create").arg("-l").arg("-o").arg(mlar_file.path());
+    for file in &testfs.files {
+        cmd.arg(file.path());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    // Get a free port
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+
+    // `mlar serve -i output.mla -b 127.0.0.1:port`
+    let mut cmd = std::process::Command::new(assert_cmd::cargo::cargo_bin(UTIL));
+    cmd.arg("serve")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-b")
+        .arg(format!("127.0.0.1:{}", port));
+    println!("{:?}", cmd);
+    let _server = KillOnDrop(cmd.spawn().unwrap());
+
+    // Index
+    let (status, headers, body) = http_get(port, "/", None);
+    assert_eq!(status, 200);
+    assert!(headers.contains("application/json"));
+    let index: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let entries = index.as_array().unwrap();
+    assert_eq!(entries.len(), testfs.files.len());
+    for (entry, file) in entries.iter().zip(&testfs.files) {
+        let content = std::fs::read(file.path()).unwrap();
+        assert_eq!(entry["name"], file.path().to_string_lossy().as_ref());
+        assert_eq!(entry["size"], content.len() as u64);
+        let url = entry["url"].as_str().unwrap();
+
+        // Whole file
+        let (status, headers, body) = http_get(port, url, None);
+        assert_eq!(status, 200);
+        assert!(headers.contains(&format!("Content-Length: {}", content.len())));
+        // Avoid printing whole files on failure
+        assert!(body == content);
+
+        // Partial content
+        let (status, headers, body) = http_get(port, url, Some("bytes=2-5"));
+        assert_eq!(status, 206);
+        assert!(headers.contains(&format!("bytes 2-5/{}", content.len())));
+        assert_eq!(body, &content[2..6]);
+        let (status, _headers, body) = http_get(port, url, Some("bytes=-3"));
+        assert_eq!(status, 206);
+        assert_eq!(body, &content[content.len() - 3..]);
+
+        // Out of the file
+        let (status, _headers, _body) =
+            http_get(port, url, Some(&format!("bytes={}-", content.len())));
+        assert_eq!(status, 416);
+    }
+
+    // Unknown file
+    let (status, _headers, _body) = http_get(port, "/files/unknown", None);
+    assert_eq!(status, 404);
+}
+
+#[cfg(feature = "http")]
+#[test]
+fn test_http_input() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let ecc_public = Path::new("../samples/test_x25519_pub.pem");
+    let ecc_private = Path::new("../samples/test_x25519.pem");
+    let testfs = setup();
+
+    // `mlar create -o output.mla -p samples/test_x25519_pub.pem file1.bin file2.bin file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg("-p")
+        .arg(ecc_public);
+    for file in &testfs.files {
+        cmd.arg(file.path());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/output.mla", listener.local_addr().unwrap());
+    let data = std::fs::read(mlar_file.path()).unwrap();
+    std::thread::spawn(move || serve_ranges(listener, &data));
+
+    // `mlar list -i http://127.0.0.1:port/output.mla -k samples/test_x25519.pem`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("list")
+        .arg("-i")
+        .arg(&url)
+        .arg("-k")
+        .arg(ecc_private);
+
+    let mut file_list = String::new();
+    for file in &testfs.files {
+        file_list.push_str(format!("{}\n", file.path().to_string_lossy()).as_str());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stdout(file_list);
+
+    // `mlar cat -i http://127.0.0.1:port/output.mla -k samples/test_x25519.pem file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("cat")
+        .arg("-i")
+        .arg(&url)
+        .arg("-k")
+        .arg(ecc_private)
+        .arg(testfs.files[2].path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stdout("ABCDEFGHIJ");
+}
+
+#[test]
+fn test_list_json() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let testfs = setup();
+
+    // `mlar create -l -o output.mla file1.bin file2.bin file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create").arg("-l").arg("-o").arg(mlar_file.path());
+    for file in &testfs.files {
+        cmd.arg(file.path());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    for verbose in &[false, true] {
+        // `mlar list --format json [-vv] -i output.mla`
+        let mut cmd = Command::cargo_bin(UTIL).unwrap();
+        cmd.arg("list").arg("--format").arg("json");
+        if *verbose {
+            cmd.arg("-vv");
+        }
+        cmd.arg("-i").arg(mlar_file.path());
+
+        println!("{:?}", cmd);
+        let assert = cmd.assert();
+        let stdout = assert.success().get_output().stdout.clone();
+        let lines: Vec<&str> = std::str::from_utf8(&stdout).unwrap().lines().collect();
+        assert_eq!(lines.len(), testfs.files.len());
+        for (line, file) in lines.iter().zip(&testfs.files) {
+            let entry: serde_json::Value = serde_json::from_str(line).unwrap();
+            let content = std::fs::read(file.path()).unwrap();
+            assert_eq!(entry["name"], file.path().to_string_lossy().as_ref());
+            assert_eq!(entry["size"], content.len() as u64);
+            if *verbose {
+                assert_eq!(entry["hash"], hex::encode(Sha256::digest(&content)));
+            } else {
+                assert!(entry.get("hash").is_none());
+            }
+        }
+    }
+}
+
+#[test]
+fn test_list_csv() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let testfs = setup();
+
+    // `mlar create -l compress -o output.mla file1.bin file2.bin file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-l")
+        .arg("compress")
+        .arg("-o")
+        .arg(mlar_file.path());
+    for file in &testfs.files {
+        cmd.arg(file.path());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    for (format, separator) in &[("csv", ","), ("tsv", "\t")] {
+        // `mlar list --format {format} --fields name,size,compressed_size,hash,offset -i output.mla`
+        let mut cmd = Command::cargo_bin(UTIL).unwrap();
+        cmd.arg("list")
+            .arg("--format")
+            .arg(format)
+            .arg("--fields")
+            .arg("name,size,compressed_size,hash,offset")
+            .arg("-i")
+            .arg(mlar_file.path());
+
+        println!("{:?}", cmd);
+        let assert = cmd.assert();
+        let stdout = assert.success().get_output().stdout.clone();
+        let lines: Vec<&str> = std::str::from_utf8(&stdout).unwrap().lines().collect();
+        assert_eq!(
+            lines[0],
+            ["name", "size", "compressed_size", "hash", "offset"].join(separator)
+        );
+        assert_eq!(lines.len(), testfs.files.len() + 1);
+
+        let mut offsets = HashSet::new();
+        for (line, file) in lines[1..].iter().zip(&testfs.files) {
+            let content = std::fs::read(file.path()).unwrap();
+            let row: Vec<&str> = line.split(separator).collect();
+            assert_eq!(row.len(), 5);
+            assert_eq!(row[0], file.path().to_string_lossy());
+            assert_eq!(row[1], content.len().to_string());
+            let compressed_size: u64 = row[2].parse().unwrap();
+            assert!(compressed_size > 0);
+            assert_eq!(row[3], hex::encode(Sha256::digest(&content)));
+            offsets.insert(row[4].parse::<u64>().unwrap());
+        }
+        // Each file starts at its own offset
+        assert_eq!(offsets.len(), testfs.files.len());
+    }
+
+    // Default fields are name and size
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("list")
+        .arg("--format")
+        .arg("csv")
+        .arg("-i")
+        .arg(mlar_file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    let stdout = assert.success().get_output().stdout.clone();
+    let stdout = String::from_utf8(stdout).unwrap();
+    assert_eq!(stdout.lines().next(), Some("name,size"));
+}
+
+#[test]
+fn test_no_progress() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let mlar_file_converted = NamedTempFile::new("convert.mla").unwrap();
+    let testfs = setup();
+
+    // `mlar create --no-progress -l -o output.mla file1.bin file2.bin file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("--no-progress")
+        .arg("-l")
+        .arg("-o")
+        .arg(mlar_file.path());
+
+    let mut file_list = String::new();
+    for file in &testfs.files {
+        cmd.arg(file.path());
+        file_list.push_str(format!("{}\n", file.path().to_string_lossy()).as_str());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stderr(String::from(&file_list));
+
+    // `mlar convert --no-progress -i output.mla -l compress -o convert.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("convert")
+        .arg("--no-progress")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-l")
+        .arg("compress")
+        .arg("-o")
+        .arg(mlar_file_converted.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stderr(String::from(&file_list));
+
+    // `mlar extract --no-progress -i convert.mla -o output_dir`
+    let output_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("extract")
+        .arg("--no-progress")
+        .arg("-i")
+        .arg(mlar_file_converted.path())
+        .arg("-o")
+        .arg(output_dir.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stderr("");
+
+    ensure_directory_content(output_dir.path(), &testfs.files);
+}
+
+#[test]
+fn test_log_options() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let mlar_file_renamed = NamedTempFile::new("renamed.mla").unwrap();
+    let testfs = setup();
+
+    // `mlar -q create -l -o output.mla file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("-q")
+        .arg("create")
+        .arg("-l")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg(&testfs.files_archive_order[2]);
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stderr("");
+
+    // `mlar rename -i output.mla -l -o renamed.mla file3.bin ../file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("rename")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-l")
+        .arg("-o")
+        .arg(mlar_file_renamed.path())
+        .arg(&testfs.files_archive_order[2])
+        .arg("../file3.bin");
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    // `mlar --log-json extract -i renamed.mla -o output_dir`
+    let output_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("--log-json")
+        .arg("extract")
+        .arg("-i")
+        .arg(mlar_file_renamed.path())
+        .arg("-o")
+        .arg(output_dir.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    let stderr = assert.success().get_output().stderr.clone();
+    let lines: Vec<&str> = std::str::from_utf8(&stderr).unwrap().lines().collect();
+    assert_eq!(lines.len(), 1);
+    let record: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+    assert_eq!(record["level"], "warning");
+    assert_eq!(
+        record["message"],
+        "Skipping file \"../file3.bin\" because it contains \"..\""
+    );
+}
+
+#[test]
+fn test_config_file() {
+    let config_file = NamedTempFile::new("config.toml").unwrap();
+    let output_dir = TempDir::new().unwrap();
+    let ecc_public = std::fs::canonicalize("../samples/test_x25519_pub.pem").unwrap();
+    let ecc_private = Path::new("../samples/test_x25519.pem");
+    let testfs = setup();
+
+    config_file
+        .write_binary(
+            format!(
+                "public_keys = [{:?}]\nlayers = [\"encrypt\"]\noutput_dir = {:?}\n",
+                ecc_public,
+                output_dir.path()
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+
+    // `mlar --config config.toml create -o output.mla file1.bin file2.bin file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("--config")
+        .arg(config_file.path())
+        .arg("create")
+        .arg("-o")
+        .arg("output.mla");
+
+    let mut file_list = String::new();
+    for file in &testfs.files {
+        cmd.arg(file.path());
+        file_list.push_str(format!("{}\n", file.path().to_string_lossy()).as_str());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stderr(String::from(&file_list));
+
+    // The archive is in the output directory, encrypted for the configured key
+    let mlar_file = output_dir.path().join("output.mla");
+
+    // `mlar info -i output.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("info").arg("-i").arg(&mlar_file);
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    let stdout = assert.success().get_output().stdout.clone();
+    let stdout = String::from_utf8(stdout).unwrap();
+    assert!(stdout.contains("Encryption: true"));
+    assert!(stdout.contains("Compression: false"));
+
+    // `mlar list -i output.mla -k samples/test_x25519.pem`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("list")
+        .arg("-i")
+        .arg(&mlar_file)
+        .arg("-k")
+        .arg(ecc_private);
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stdout(file_list);
+
+    // An explicit configuration file must exist
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("--config")
+        .arg(output_dir.path().join("missing.toml"))
+        .arg("list")
+        .arg("-i")
+        .arg(&mlar_file);
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.failure();
+}
+
+#[test]
+fn test_escrow() {
+    let config_file = NamedTempFile::new("config.toml").unwrap();
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let ecc_public = Path::new("../samples/test_x25519_pub.pem");
+    let escrow_public = Path::new("../samples/test_x25519_2_pub.pem");
+    let config_escrow_public = std::fs::canonicalize("../samples/test_x25519_3_pub.pem").unwrap();
+    let testfs = setup();
+
+    config_file
+        .write_binary(format!("escrow_public_keys = [{:?}]\n", config_escrow_public).as_bytes())
+        .unwrap();
+
+    // `mlar --config config.toml create -o output.mla -p samples/test_x25519_pub.pem --escrow-pubkey samples/test_x25519_2_pub.pem file1.bin file2.bin file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("--config")
+        .arg(config_file.path())
+        .arg("create")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg("-p")
+        .arg(ecc_public)
+        .arg("--escrow-pubkey")
+        .arg(escrow_public);
+
+    let mut file_list = String::new();
+    for file in &testfs.files {
+        cmd.arg(file.path());
+        file_list.push_str(format!("{}\n", file.path().to_string_lossy()).as_str());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stderr(String::from(&file_list));
+
+    // Both escrow keys are recorded
+    // `mlar info -v -k samples/test_x25519.pem -i output.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("info")
+        .arg("-v")
+        .arg("-k")
+        .arg("../samples/test_x25519.pem")
+        .arg("-i")
+        .arg(mlar_file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    let stdout = String::from_utf8(assert.success().get_output().stdout.clone()).unwrap();
+    assert_eq!(
+        stdout
+            .lines()
+            .filter(|line| line.starts_with("  Escrow key: "))
+            .count(),
+        2
+    );
+
+    // Each private key can read the archive
+    for ecc_private in &[
+        "../samples/test_x25519.pem",
+        "../samples/test_x25519_2.pem",
+        "../samples/test_x25519_3.pem",
+    ] {
+        // `mlar list -i output.mla -k <ecc_private>`
+        let mut cmd = Command::cargo_bin(UTIL).unwrap();
+        cmd.arg("list")
+            .arg("-i")
+            .arg(mlar_file.path())
+            .arg("-k")
+            .arg(ecc_private);
+
+        println!("{:?}", cmd);
+        let assert = cmd.assert();
+        assert.success().stdout(String::from(&file_list));
+    }
+}
+
+#[test]
+fn test_anonymous_recipients() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let ecc_public = Path::new("../samples/test_x25519_pub.pem");
+    let ecc_public2 = Path::new("../samples/test_x25519_2_pub.pem");
+    let escrow_public = Path::new("../samples/test_x25519_3_pub.pem");
+    let testfs = setup();
+
+    // `mlar create -o output.mla -p samples/test_x25519_pub.pem -p samples/test_x25519_2_pub.pem --escrow-pubkey samples/test_x25519_3_pub.pem --anonymous-recipients file1.bin file2.bin file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg("-p")
+        .arg(ecc_public)
+        .arg("-p")
+        .arg(ecc_public2)
+        .arg("--escrow-pubkey")
+        .arg(escrow_public)
+        .arg("--anonymous-recipients");
+
+    let mut file_list = String::new();
+    for file in &testfs.files {
+        cmd.arg(file.path());
+        file_list.push_str(format!("{}\n", file.path().to_string_lossy()).as_str());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stderr(String::from(&file_list));
+
+    // The escrow key is not recorded
+    // `mlar info -v -k samples/test_x25519.pem -i output.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("info")
+        .arg("-v")
+        .arg("-k")
+        .arg("../samples/test_x25519.pem")
+        .arg("-i")
+        .arg(mlar_file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    let stdout = String::from_utf8(assert.success().get_output().stdout.clone()).unwrap();
+    assert!(!stdout.contains("Escrow key: "));
+
+    // Each private key can still read the archive
+    for ecc_private in &[
+        "../samples/test_x25519.pem",
+        "../samples/test_x25519_2.pem",
+        "../samples/test_x25519_3.pem",
+    ] {
+        // `mlar list -i output.mla -k <ecc_private>`
+        let mut cmd = Command::cargo_bin(UTIL).unwrap();
+        cmd.arg("list")
+            .arg("-i")
+            .arg(mlar_file.path())
+            .arg("-k")
+            .arg(ecc_private);
+
+        println!("{:?}", cmd);
+        let assert = cmd.assert();
+        assert.success().stdout(String::from(&file_list));
+    }
+}
+
+#[test]
+fn test_recipient_hints() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let ecc_public = Path::new("../samples/test_x25519_pub.pem");
+    let ecc_public2 = Path::new("../samples/test_x25519_2_pub.pem");
+    let testfs = setup();
+
+    // `mlar create -o output.mla -p samples/test_x25519_pub.pem -p samples/test_x25519_2_pub.pem --recipient-hints file1.bin file2.bin file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg("-p")
+        .arg(ecc_public)
+        .arg("-p")
+        .arg(ecc_public2)
+        .arg("--recipient-hints");
+
+    let mut file_list = String::new();
+    for file in &testfs.files {
+        cmd.arg(file.path());
+        file_list.push_str(format!("{}\n", file.path().to_string_lossy()).as_str());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stderr(String::from(&file_list));
+
+    // A hint is recorded for each recipient
+    // `mlar info -v -k samples/test_x25519.pem -i output.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("info")
+        .arg("-v")
+        .arg("-k")
+        .arg("../samples/test_x25519.pem")
+        .arg("-i")
+        .arg(mlar_file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    let stdout = String::from_utf8(assert.success().get_output().stdout.clone()).unwrap();
+    assert_eq!(
+        stdout
+            .lines()
+            .filter(|line| line.starts_with("  Key hint: "))
+            .count(),
+        2
+    );
+
+    // The matching private key is picked among the ones given
+    // `mlar list -i output.mla -k samples/test_x25519_3.pem -k samples/test_x25519_2.pem`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("list")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-k")
+        .arg("../samples/test_x25519_3.pem")
+        .arg("-k")
+        .arg("../samples/test_x25519_2.pem");
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stdout(String::from(&file_list));
+
+    // Without a matching private key, it is reported as such
+    // `mlar list -i output.mla -k samples/test_x25519_3.pem`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("list")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-k")
+        .arg("../samples/test_x25519_3.pem");
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    let stderr = String::from_utf8(assert.failure().get_output().stderr.clone()).unwrap();
+    assert!(stderr.contains("This archive is not encrypted to any of your keys"));
+}
+
+#[test]
+fn test_hybrid() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let ecc_public = Path::new("../samples/test_x25519_pub.pem");
+    let hybrid_public = Path::new("../samples/test_hybrid_pub.pem");
+    let testfs = setup();
+
+    // `mlar create -o output.mla -p samples/test_x25519_pub.pem -p samples/test_hybrid_pub.pem file1.bin file2.bin file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg("-p")
+        .arg(ecc_public)
+        .arg("-p")
+        .arg(hybrid_public);
+
+    let mut file_list = String::new();
+    for file in &testfs.files {
+        cmd.arg(file.path());
+        file_list.push_str(format!("{}\n", file.path().to_string_lossy()).as_str());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stderr(String::from(&file_list));
+
+    // The KEM is recorded
+    // `mlar info -v -k samples/test_hybrid.pem -i output.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("info")
+        .arg("-v")
+        .arg("-k")
+        .arg("../samples/test_hybrid.pem")
+        .arg("-i")
+        .arg(mlar_file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    let stdout = String::from_utf8(assert.success().get_output().stdout.clone()).unwrap();
+    assert!(stdout
+        .lines()
+        .any(|line| line == "  Hybrid recipients: 1 (X25519MlKem768)"));
+
+    // Each private key can read the archive
+    for ecc_private in &["../samples/test_x25519.pem", "../samples/test_hybrid.pem"] {
+        // `mlar list -i output.mla -k <ecc_private>`
+        let mut cmd = Command::cargo_bin(UTIL).unwrap();
+        cmd.arg("list")
+            .arg("-i")
+            .arg(mlar_file.path())
+            .arg("-k")
+            .arg(ecc_private);
+
+        println!("{:?}", cmd);
+        let assert = cmd.assert();
+        assert.success().stdout(String::from(&file_list));
+    }
+
+    // But not another one
+    // `mlar list -i output.mla -k samples/test_x25519_2.pem`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("list")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-k")
+        .arg("../samples/test_x25519_2.pem");
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.failure();
+}
+
+#[test]
+fn test_cipher() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let ecc_public = Path::new("../samples/test_x25519_pub.pem");
+    let ecc_private = Path::new("../samples/test_x25519.pem");
+    let testfs = setup();
+
+    // `mlar create -o output.mla -p samples/test_x25519_pub.pem --cipher chacha20-poly1305 file1.bin file2.bin file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg("-p")
+        .arg(ecc_public)
+        .arg("--cipher")
+        .arg("chacha20-poly1305");
+
+    let mut file_list = String::new();
+    for file in &testfs.files {
+        cmd.arg(file.path());
+        file_list.push_str(format!("{}\n", file.path().to_string_lossy()).as_str());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stderr(String::from(&file_list));
+
+    // The cipher is recorded
+    // `mlar info -v -k samples/test_x25519.pem -i output.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("info")
+        .arg("-v")
+        .arg("-k")
+        .arg(ecc_private)
+        .arg("-i")
+        .arg(mlar_file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    let stdout = String::from_utf8(assert.success().get_output().stdout.clone()).unwrap();
+    assert!(stdout
+        .lines()
+        .any(|line| line == "  Cipher: chacha20-poly1305"));
+
+    // So reading does not need it
+    // `mlar list -i output.mla -k samples/test_x25519.pem`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("list")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-k")
+        .arg(ecc_private);
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stdout(String::from(&file_list));
+}
+
+#[test]
+fn test_compression_algorithm() {
+    for algorithm in &["lz4", "xz"] {
+        let mlar_file = NamedTempFile::new("output.mla").unwrap();
+        let ecc_public = Path::new("../samples/test_x25519_pub.pem");
+        let ecc_private = Path::new("../samples/test_x25519.pem");
+        let testfs = setup();
+
+        // `mlar create -o output.mla -p samples/test_x25519_pub.pem --compression ALGORITHM file1.bin file2.bin file3.bin`
+        let mut cmd = Command::cargo_bin(UTIL).unwrap();
+        cmd.arg("create")
+            .arg("-o")
+            .arg(mlar_file.path())
+            .arg("-p")
+            .arg(ecc_public)
+            .arg("--compression")
+            .arg(algorithm);
+
+        let mut file_list = String::new();
+        for file in &testfs.files {
+            cmd.arg(file.path());
+            file_list.push_str(format!("{}\n", file.path().to_string_lossy()).as_str());
+        }
+
+        println!("{:?}", cmd);
+        let assert = cmd.assert();
+        assert.success().stderr(String::from(&file_list));
+
+        // The algorithm is recorded
+        // `mlar info -v -k samples/test_x25519.pem -i output.mla`
+        let mut cmd = Command::cargo_bin(UTIL).unwrap();
+        cmd.arg("info")
+            .arg("-v")
+            .arg("-k")
+            .arg(ecc_private)
+            .arg("-i")
+            .arg(mlar_file.path());
+
+        println!("{:?}", cmd);
+        let assert = cmd.assert();
+        let stdout = String::from_utf8(assert.success().get_output().stdout.clone()).unwrap();
+        assert!(stdout
+            .lines()
+            .any(|line| line == format!("  Algorithm: {}", algorithm)));
+
+        // So extracting does not need it
+        // `mlar extract -i output.mla -k samples/test_x25519.pem -o output_dir`
+        let output_dir = TempDir::new().unwrap();
+        let mut cmd = Command::cargo_bin(UTIL).unwrap();
+        cmd.arg("extract")
+            .arg("-i")
+            .arg(mlar_file.path())
+            .arg("-k")
+            .arg(ecc_private)
+            .arg("-o")
+            .arg(output_dir.path());
+
+        println!("{:?}", cmd);
+        let assert = cmd.assert();
+        assert.success();
+        ensure_directory_content(output_dir.path(), &testfs.files);
+    }
+}
+
+#[test]
+fn test_store() {
+    for args in &[
+        &["--store-incompressible"][..],
+        &["--store-glob", "file2.bin"][..],
+    ] {
+        let mlar_file = NamedTempFile::new("output.mla").unwrap();
+        let ecc_public = Path::new("../samples/test_x25519_pub.pem");
+        let ecc_private = Path::new("../samples/test_x25519.pem");
+        let testfs = setup();
+
+        // `mlar create -o output.mla -p samples/test_x25519_pub.pem ARGS file1.bin file2.bin file3.bin`
+        let mut cmd = Command::cargo_bin(UTIL).unwrap();
+        cmd.arg("create")
+            .arg("-o")
+            .arg(mlar_file.path())
+            .arg("-p")
+            .arg(ecc_public)
+            .args(args.iter());
+
+        let mut file_list = String::new();
+        for file in &testfs.files {
+            cmd.arg(file.path());
+            file_list.push_str(format!("{}\n", file.path().to_string_lossy()).as_str());
+        }
+
+        println!("{:?}", cmd);
+        let assert = cmd.assert();
+        assert.success().stderr(String::from(&file_list));
+
+        // Stored blocks are transparent to readers
+        // `mlar extract -i output.mla -k samples/test_x25519.pem -o output_dir`
+        let output_dir = TempDir::new().unwrap();
+        let mut cmd = Command::cargo_bin(UTIL).unwrap();
+        cmd.arg("extract")
+            .arg("-i")
+            .arg(mlar_file.path())
+            .arg("-k")
+            .arg(ecc_private)
+            .arg("-o")
+            .arg(output_dir.path());
+
+        println!("{:?}", cmd);
+        let assert = cmd.assert();
+        assert.success();
+        ensure_directory_content(output_dir.path(), &testfs.files);
+    }
+}
+
+#[test]
+fn test_dedup() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let testfs = setup();
+    let mut files = testfs.files;
+
+    // `copy.bin` has the same content than `file1.bin`
+    let copy = NamedTempFile::new("copy.bin").unwrap();
+    copy.write_binary(
+        std::fs::read(&testfs.files_archive_order[0])
+            .unwrap()
+            .as_slice(),
+    )
+    .unwrap();
+    files.push(copy);
+
+    // `mlar create -l -o output.mla --dedup file1.bin file2.bin file3.bin copy.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-l")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg("--dedup");
+    for file in &files {
+        cmd.arg(file.path());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    // Without any layer, the content of `copy.bin` would double the size of
+    // `file1.bin`
+    let total_size: u64 = files
+        .iter()
+        .map(|file| metadata(file.path()).unwrap().len())
+        .sum();
+    assert!(metadata(mlar_file.path()).unwrap().len() < total_size - SIZE_FILE1 as u64 / 2);
+
+    // `mlar extract -i output.mla -o output_dir`
+    let output_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("extract")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-o")
+        .arg(output_dir.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+    ensure_directory_content(output_dir.path(), &files);
+}
+
+#[test]
+fn test_delta() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let testfs = setup();
+
+    // `syslog.2` is `syslog.1` with a few more lines, as a rotated log
+    let logs = TempDir::new().unwrap();
+    let older = std::fs::read(&testfs.files_archive_order[1]).unwrap();
+    let mut newer = b"New line\nAnother new line\n".to_vec();
+    newer.extend_from_slice(&older[..older.len() / 2]);
+    let paths = [logs.path().join("syslog.1"), logs.path().join("syslog.2")];
+    std::fs::write(&paths[0], &older).unwrap();
+    std::fs::write(&paths[1], &newer).unwrap();
+
+    // `mlar create -l -o output.mla --delta syslog.1 syslog.2`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-l")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg("--delta")
+        .arg(&paths[0])
+        .arg(&paths[1]);
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    // Without any layer, `syslog.2` would add half of the size of `syslog.1`
+    assert!(metadata(mlar_file.path()).unwrap().len() < (older.len() + newer.len() / 10) as u64);
+
+    // `mlar extract -i output.mla -o output_dir`
+    let output_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("extract")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-o")
+        .arg(output_dir.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+    for (name, content) in &[("syslog.1", &older), ("syslog.2", &newer)] {
+        let pattern = format!("{}/**/{}", output_dir.path().to_string_lossy(), name);
+        let extracted = glob::glob(&pattern).unwrap().next().unwrap().unwrap();
+        assert_eq!(&&std::fs::read(extracted).unwrap(), content);
+    }
+}
+
+#[test]
+fn test_deterministic() {
+    let ecc_public = Path::new("../samples/test_x25519_pub.pem");
+    let ecc_private = Path::new("../samples/test_x25519.pem");
+    let testfs = setup();
+
+    // `mlar create -o output.mla -p samples/test_x25519_pub.pem --padding 64K --deterministic <seed> <files>`
+    let create = |seed: &str, files: &[&NamedTempFile]| {
+        let mlar_file = NamedTempFile::new("output.mla").unwrap();
+        let mut cmd = Command::cargo_bin(UTIL).unwrap();
+        cmd.arg("create")
+            .arg("-o")
+            .arg(mlar_file.path())
+            .arg("-p")
+            .arg(ecc_public)
+            .arg("--padding")
+            .arg("64K")
+            .arg("--deterministic")
+            .arg(seed);
+        for file in files {
+            cmd.arg(file.path());
+        }
+
+        println!("{:?}", cmd);
+        let assert = cmd.assert();
+        assert.success();
+        mlar_file
+    };
+
+    // The same files and seed give the same archive, whatever the order of
+    // the arguments
+    let files: Vec<&NamedTempFile> = testfs.files.iter().collect();
+    let reversed: Vec<&NamedTempFile> = testfs.files.iter().rev().collect();
+    let mlar_file = create("my seed", &files);
+    let content = std::fs::read(mlar_file.path()).unwrap();
+    assert_eq!(
+        content,
+        std::fs::read(create("my seed", &reversed).path()).unwrap()
+    );
+    assert_ne!(
+        content,
+        std::fs::read(create("another seed", &files).path()).unwrap()
+    );
+
+    // `mlar extract -i output.mla -k samples/test_x25519.pem -o output_dir`
+    let output_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("extract")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-k")
+        .arg(ecc_private)
+        .arg("-o")
+        .arg(output_dir.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+    ensure_directory_content(output_dir.path(), &testfs.files);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_file_metadata() {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let file = NamedTempFile::new("file.bin").unwrap();
+    file.write_binary(b"content").unwrap();
+    std::fs::set_permissions(file.path(), std::fs::Permissions::from_mode(0o640)).unwrap();
+    let fs_metadata = metadata(file.path()).unwrap();
+
+    // `mlar create -l -o output.mla file.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-l")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg(file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    // `mlar list --format csv --fields name,mtime,mode,uid,gid -i output.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("list")
+        .arg("--format")
+        .arg("csv")
+        .arg("--fields")
+        .arg("name,mtime,mode,uid,gid")
+        .arg("-i")
+        .arg(mlar_file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stdout(format!(
+        "name,mtime,mode,uid,gid\n{},{},640,{},{}\n",
+        file.path().to_string_lossy(),
+        fs_metadata.mtime(),
+        fs_metadata.uid(),
+        fs_metadata.gid()
+    ));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_extract_preserve_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let file = NamedTempFile::new("file.bin").unwrap();
+    file.write_binary(b"content").unwrap();
+    std::fs::set_permissions(file.path(), std::fs::Permissions::from_mode(0o4750)).unwrap();
+
+    // `mlar create -l -o output.mla file.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-l")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg(file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    for (preserve, linear) in &[(false, false), (true, false), (true, true)] {
+        // `mlar extract [--preserve-permissions] -i output.mla -o output_dir [-g '*']`
+        let output_dir = TempDir::new().unwrap();
+        let mut cmd = Command::cargo_bin(UTIL).unwrap();
+        cmd.arg("extract");
+        if *preserve {
+            cmd.arg("--preserve-permissions");
+        }
+        cmd.arg("-i")
+            .arg(mlar_file.path())
+            .arg("-o")
+            .arg(output_dir.path());
+        if !*linear {
+            // Extract by name, instead of linearly
+            cmd.arg("-g").arg("*");
+        }
+
+        println!("{:?}", cmd);
+        let assert = cmd.assert();
+        assert.success();
+
+        let extracted = glob::glob(&(output_dir.path().to_string_lossy() + "/**/file.bin"))
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap();
+        let mode = metadata(&extracted).unwrap().permissions().mode() & 0o7777;
+        if *preserve {
+            // setuid is never restored
+            assert_eq!(mode, 0o750);
+        } else {
+            assert_ne!(mode, 0o750);
+        }
+    }
+}
+
+#[test]
+fn test_extract_strip_components() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let file = NamedTempFile::new("file.bin").unwrap();
+    file.write_binary(b"content").unwrap();
+    // Components of the stored name, such as "tmp", ".tmpXXXXXX", "file.bin"
+    let components = file
+        .path()
+        .components()
+        .filter(|part| matches!(part, std::path::Component::Normal(_)))
+        .count();
+
+    // `mlar create -l -o output.mla file.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-l")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg(file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    for linear in &[false, true] {
+        for strip in &[components - 1, components] {
+            // `mlar extract --strip-components N -i output.mla -o output_dir [-g '*']`
+            let output_dir = TempDir::new().unwrap();
+            let mut cmd = Command::cargo_bin(UTIL).unwrap();
+            cmd.arg("extract")
+                .arg("--strip-components")
+                .arg(strip.to_string())
+                .arg("-i")
+                .arg(mlar_file.path())
+                .arg("-o")
+                .arg(output_dir.path());
+            if !*linear {
+                // Extract by name, instead of linearly
+                cmd.arg("-g").arg("*");
+            }
+
+            println!("{:?}", cmd);
+            let assert = cmd.assert();
+            assert.success();
+
+            let extracted: Vec<PathBuf> =
+                glob::glob(&(output_dir.path().to_string_lossy() + "/**/*"))
+                    .unwrap()
+                    .map(|entry| entry.unwrap())
+                    .collect();
+            if *strip < components {
+                // Only the file name is left
+                assert_eq!(extracted, vec![output_dir.path().join("file.bin")]);
+                assert_eq!(std::fs::read(&extracted[0]).unwrap(), b"content");
+            } else {
+                // Nothing is left, the file is skipped
+                assert!(extracted.is_empty());
+            }
+        }
+    }
+}
+
+#[test]
+fn test_extract_overwrite() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let file = NamedTempFile::new("file.bin").unwrap();
+    file.write_binary(b"content").unwrap();
+
+    // `mlar create -l -o output.mla file.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-l")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg(file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    let output_dir = TempDir::new().unwrap();
+    let extract = |options: &[&str]| {
+        // `mlar extract -i output.mla -o output_dir --overwrite always`
+        let mut cmd = Command::cargo_bin(UTIL).unwrap();
+        cmd.arg("extract")
+            .arg("-i")
+            .arg(mlar_file.path())
+            .arg("-o")
+            .arg(output_dir.path())
+            .args(options);
+
+        println!("{:?}", cmd);
+        let assert = cmd.assert();
+        assert.success();
+    };
+    extract(&[]);
+    let extracted = glob::glob(&(output_dir.path().to_string_lossy() + "/**/file.bin"))
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap();
+    assert_eq!(std::fs::read(&extracted).unwrap(), b"content");
+
+    // Existing files are kept by default, or if they are not older
+    std::fs::write(&extracted, b"modified").unwrap();
+    extract(&[]);
+    assert_eq!(std::fs::read(&extracted).unwrap(), b"modified");
+    extract(&["--overwrite", "older"]);
+    assert_eq!(std::fs::read(&extracted).unwrap(), b"modified");
+
+    // Replaced files can be backed up
+    extract(&["--overwrite", "always", "--backup-suffix", ".orig"]);
+    assert_eq!(std::fs::read(&extracted).unwrap(), b"content");
+    let mut backup = extracted.clone().into_os_string();
+    backup.push(".orig");
+    assert_eq!(std::fs::read(&backup).unwrap(), b"modified");
+
+    // With --atomic, the existing file is replaced once the new one is complete
+    std::fs::write(&extracted, b"modified again").unwrap();
+    extract(&[
+        "--atomic",
+        "--overwrite",
+        "always",
+        "--backup-suffix",
+        ".orig",
+    ]);
+    assert_eq!(std::fs::read(&extracted).unwrap(), b"content");
+    assert_eq!(std::fs::read(&backup).unwrap(), b"modified again");
+}
+
+#[test]
+fn test_ignore_case() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let file1 = NamedTempFile::new("FILE1.TXT").unwrap();
+    file1.write_binary(b"content1").unwrap();
+    let file2 = NamedTempFile::new("file2.txt").unwrap();
+    file2.write_binary(b"content2").unwrap();
+
+    // `mlar create -l -o output.mla FILE1.TXT file2.txt`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-l")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg(file1.path())
+        .arg(file2.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    // `mlar list -i output.mla -g '*file1.txt'`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("list")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-g")
+        .arg("*file1.txt");
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stdout("");
+
+    // `mlar list -i output.mla --ignore-case -g '*file1.txt'`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("list")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("--ignore-case")
+        .arg("-g")
+        .arg("*file1.txt");
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert
+        .success()
+        .stdout(format!("{}\n", file1.path().to_string_lossy()));
+
+    // Explicit names, in another case
+    let name1 = file1.path().to_string_lossy().to_lowercase();
+
+    // `mlar cat -i output.mla --ignore-case file1.txt`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("cat")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("--ignore-case")
+        .arg(&name1);
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stdout("content1");
+
+    // `mlar extract -i output.mla -o output_dir --ignore-case file1.txt`
+    let output_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("extract")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-o")
+        .arg(output_dir.path())
+        .arg("--ignore-case")
+        .arg(&name1);
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+    ensure_directory_content(output_dir.path(), std::slice::from_ref(&file1));
+}
+
+#[test]
+fn test_regex() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let file1 = NamedTempFile::new("file1.evtx").unwrap();
+    file1.write_binary(b"content1").unwrap();
+    let file2 = NamedTempFile::new("file2.evtx.txt").unwrap();
+    file2.write_binary(b"content2").unwrap();
+
+    // `mlar create -l -o output.mla file1.evtx file2.evtx.txt`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-l")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg(file1.path())
+        .arg(file2.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    // `mlar list -i output.mla --regex '/file[0-9]\.EVTX$' --ignore-case`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("list")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("--regex")
+        .arg(r"/file[0-9]\.EVTX$")
+        .arg("--ignore-case");
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert
+        .success()
+        .stdout(format!("{}\n", file1.path().to_string_lossy()));
+
+    // `mlar cat -i output.mla --regex 'file2'`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("cat")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("--regex")
+        .arg("file2");
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stdout("content2");
+
+    // `mlar extract -i output.mla -o output_dir --regex '\.evtx$'`
+    let output_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("extract")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-o")
+        .arg(output_dir.path())
+        .arg("--regex")
+        .arg(r"\.evtx$");
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+    ensure_directory_content(output_dir.path(), std::slice::from_ref(&file1));
+}
+
+#[test]
+fn test_extract_flat() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    // Files with the same name, in different directories
+    let dir1 = TempDir::new().unwrap();
+    let file1 = dir1.path().join("report.pdf");
+    std::fs::write(&file1, b"content1").unwrap();
+    let dir2 = TempDir::new().unwrap();
+    let file2 = dir2.path().join("report.pdf");
+    std::fs::write(&file2, b"content2").unwrap();
+
+    // `mlar create -l -o output.mla dir1/report.pdf dir2/report.pdf`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-l")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg(&file1)
+        .arg(&file2);
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    for linear in &[false, true] {
+        // `mlar extract --flat -i output.mla -o output_dir [-g '*']`
+        let output_dir = TempDir::new().unwrap();
+        let mut cmd = Command::cargo_bin(UTIL).unwrap();
+        cmd.arg("extract")
+            .arg("--flat")
+            .arg("-i")
+            .arg(mlar_file.path())
             .arg("-o")
-            .arg(tar_file.path());
+            .arg(output_dir.path());
+        if !*linear {
+            // Extract by name, instead of linearly
+            cmd.arg("-g").arg("*");
+        }
+
+        println!("{:?}", cmd);
+        let assert = cmd.assert();
+        assert.success();
+
+        let mut extracted: Vec<(String, Vec<u8>)> = std::fs::read_dir(output_dir.path())
+            .unwrap()
+            .map(|entry| {
+                let entry = entry.unwrap();
+                (
+                    entry.file_name().to_string_lossy().into_owned(),
+                    std::fs::read(entry.path()).unwrap(),
+                )
+            })
+            .collect();
+        extracted.sort();
+        assert_eq!(extracted.len(), 2);
+        assert_eq!(extracted[0].0, "report.pdf");
+        assert_eq!(extracted[1].0, "report_1.pdf");
+        let mut contents: Vec<&[u8]> = extracted
+            .iter()
+            .map(|(_, content)| content.as_slice())
+            .collect();
+        contents.sort();
+        assert_eq!(contents, vec![b"content1".as_ref(), b"content2".as_ref()]);
+    }
+}
+
+#[test]
+fn test_extract_atomic() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let testfs = setup();
+
+    // `mlar create -l -o output.mla file1 file2 file3`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create").arg("-l").arg("-o").arg(mlar_file.path());
+    for file in &testfs.files {
+        cmd.arg(file.path());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    for linear in &[false, true] {
+        // `mlar extract --atomic -i output.mla -o output_dir [-g '*']`
+        let output_dir = TempDir::new().unwrap();
+        let mut cmd = Command::cargo_bin(UTIL).unwrap();
+        cmd.arg("extract")
+            .arg("--atomic")
+            .arg("-i")
+            .arg(mlar_file.path())
+            .arg("-o")
+            .arg(output_dir.path());
+        if !*linear {
+            // Extract by name, instead of linearly
+            cmd.arg("-g").arg("*");
+        }
+
+        println!("{:?}", cmd);
+        let assert = cmd.assert();
+        assert.success();
+
+        // No temporary file is left
+        ensure_directory_content(output_dir.path(), &testfs.files);
+    }
+}
+
+#[test]
+fn test_extract_dry_run() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    // Files with the same name, in different directories
+    let dir1 = TempDir::new().unwrap();
+    let file1 = dir1.path().join("file.txt");
+    std::fs::write(&file1, b"content1").unwrap();
+    let dir2 = TempDir::new().unwrap();
+    let file2 = dir2.path().join("file.txt");
+    std::fs::write(&file2, b"content2").unwrap();
+
+    // `mlar create -l -o output.mla dir1/file.txt dir2/file.txt`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-l")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg(&file1)
+        .arg(&file2);
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    // `mlar extract --dry-run --flat -i output.mla -o output_dir/new`
+    let output_dir = TempDir::new().unwrap();
+    let new_dir = output_dir.path().join("new");
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("extract")
+        .arg("--dry-run")
+        .arg("--flat")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-o")
+        .arg(&new_dir);
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    let stdout = String::from_utf8(assert.success().get_output().stdout.clone()).unwrap();
+    let mut lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines.pop().unwrap(),
+        "2 files would be extracted, 0 skipped"
+    );
+    lines.sort();
+    // Suffixes are given in the order of the names
+    let mut names = [file1.to_string_lossy(), file2.to_string_lossy()];
+    names.sort();
+    let mut expected = vec![
+        format!("{} -> {}", names[0], new_dir.join("file.txt").display()),
+        format!("{} -> {}", names[1], new_dir.join("file_1.txt").display()),
+    ];
+    expected.sort();
+    assert_eq!(lines, expected);
+    // Nothing is written
+    assert!(!new_dir.exists());
+
+    // `mlar extract --dry-run --strip-components 100 -i output.mla -o output_dir`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("extract")
+        .arg("--dry-run")
+        .arg("--strip-components")
+        .arg("100")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-o")
+        .arg(output_dir.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    let stdout = String::from_utf8(assert.success().get_output().stdout.clone()).unwrap();
+    assert!(stdout.ends_with("0 files would be extracted, 2 skipped\n"));
+    assert!(stdout.contains(&format!(
+        "{} skipped: no name left",
+        file1.to_string_lossy()
+    )));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_xattrs() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let file = NamedTempFile::new("file.bin").unwrap();
+    file.write_binary(b"content").unwrap();
+    if xattr::set(file.path(), "user.mla", b"value").is_err() {
+        // Not supported by the filesystem
+        return;
+    }
+
+    // `mlar create -l --xattrs -o output.mla file.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-l")
+        .arg("--xattrs")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg(file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    for restore in &[false, true] {
+        // `mlar extract [--xattrs] -i output.mla -o output_dir`
+        let output_dir = TempDir::new().unwrap();
+        let mut cmd = Command::cargo_bin(UTIL).unwrap();
+        cmd.arg("extract");
+        if *restore {
+            cmd.arg("--xattrs");
+        }
+        cmd.arg("-i")
+            .arg(mlar_file.path())
+            .arg("-o")
+            .arg(output_dir.path());
+
+        println!("{:?}", cmd);
+        let assert = cmd.assert();
+        assert.success();
+
+        let extracted = glob::glob(&(output_dir.path().to_string_lossy() + "/**/file.bin"))
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap();
+        let value = xattr::get(&extracted, "user.mla").unwrap();
+        if *restore {
+            assert_eq!(value, Some(b"value".to_vec()));
+        } else {
+            assert_eq!(value, None);
+        }
+    }
+}
+
+#[test]
+fn test_threads() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let ecc_public = Path::new("../samples/test_x25519_pub.pem");
+    let ecc_private = Path::new("../samples/test_x25519.pem");
+    let testfs = setup();
+
+    // `mlar create -o output.mla -p samples/test_x25519_pub.pem --threads 4 file1.bin file2.bin file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg("-p")
+        .arg(ecc_public)
+        .arg("--threads")
+        .arg("4");
+
+    let mut file_list = String::new();
+    for file in &testfs.files {
+        cmd.arg(file.path());
+        file_list.push_str(format!("{}\n", file.path().to_string_lossy()).as_str());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stderr(String::from(&file_list));
+
+    // `mlar extract -i output.mla -k samples/test_x25519.pem -o output_dir`
+    let output_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("extract")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-k")
+        .arg(ecc_private)
+        .arg("-o")
+        .arg(output_dir.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+    ensure_directory_content(output_dir.path(), &testfs.files);
+}
+
+#[test]
+fn test_extract_threads() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let ecc_public = Path::new("../samples/test_x25519_pub.pem");
+    let ecc_private = Path::new("../samples/test_x25519.pem");
+    let testfs = setup();
+
+    // `mlar create -o output.mla -p samples/test_x25519_pub.pem file1.bin file2.bin file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg("-p")
+        .arg(ecc_public);
+
+    let mut file_list = String::new();
+    for file in &testfs.files {
+        cmd.arg(file.path());
+        file_list.push_str(format!("{}\n", file.path().to_string_lossy()).as_str());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stderr(String::from(&file_list));
+
+    // `mlar extract -i output.mla -k samples/test_x25519.pem -o output_dir --threads 2`
+    let output_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("extract")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-k")
+        .arg(ecc_private)
+        .arg("-o")
+        .arg(output_dir.path())
+        .arg("--threads")
+        .arg("2");
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+    ensure_directory_content(output_dir.path(), &testfs.files);
+}
+
+#[test]
+fn test_rekey_interval() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let ecc_public = Path::new("../samples/test_x25519_pub.pem");
+    let ecc_private = Path::new("../samples/test_x25519.pem");
+    let testfs = setup();
+
+    // `mlar create -o output.mla -p samples/test_x25519_pub.pem --rekey-interval 200K file1.bin file2.bin file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg("-p")
+        .arg(ecc_public)
+        .arg("--rekey-interval")
+        .arg("200K");
+
+    let mut file_list = String::new();
+    for file in &testfs.files {
+        cmd.arg(file.path());
+        file_list.push_str(format!("{}\n", file.path().to_string_lossy()).as_str());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stderr(String::from(&file_list));
+
+    // The schedule is recorded, rounded up to whole chunks
+    // `mlar info -v -k samples/test_x25519.pem -i output.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("info")
+        .arg("-v")
+        .arg("-k")
+        .arg(ecc_private)
+        .arg("-i")
+        .arg(mlar_file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    let stdout = String::from_utf8(assert.success().get_output().stdout.clone()).unwrap();
+    assert!(stdout
+        .lines()
+        .any(|line| line == "  Rekey interval: 256 KB"));
+
+    // The file spanning several key epochs is read back
+    // `mlar cat -i output.mla -k samples/test_x25519.pem file2.bin`
+    let file2 = &testfs.files[1];
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("cat")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-k")
+        .arg(ecc_private)
+        .arg(file2.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert_eq!(
+        assert.success().get_output().stdout,
+        std::fs::read(file2.path()).unwrap()
+    );
+}
+
+#[test]
+fn test_padding() {
+    let mlar_small = NamedTempFile::new("small.mla").unwrap();
+    let mlar_big = NamedTempFile::new("big.mla").unwrap();
+    let ecc_public = Path::new("../samples/test_x25519_pub.pem");
+    let ecc_private = Path::new("../samples/test_x25519.pem");
+    let testfs = setup();
+
+    // `mlar create -l encrypt -o small.mla -p samples/test_x25519_pub.pem --padding 32M file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-l")
+        .arg("encrypt")
+        .arg("-o")
+        .arg(mlar_small.path())
+        .arg("-p")
+        .arg(ecc_public)
+        .arg("--padding")
+        .arg("32M")
+        .arg(testfs.files[2].path());
 
-        println!("{:?}", cmd);
-        let assert = cmd.assert();
-        assert.success();
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
 
-        // Inspect the created TAR file
-        ensure_tar_content(&tar_file.path(), &testfs.files);
+    // `mlar create -l encrypt -o big.mla -p samples/test_x25519_pub.pem --padding 32M file1.bin file2.bin file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-l")
+        .arg("encrypt")
+        .arg("-o")
+        .arg(mlar_big.path())
+        .arg("-p")
+        .arg(ecc_public)
+        .arg("--padding")
+        .arg("32M");
+
+    let mut file_list = String::new();
+    for file in &testfs.files {
+        cmd.arg(file.path());
+        file_list.push_str(format!("{}\n", file.path().to_string_lossy()).as_str());
     }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stderr(String::from(&file_list));
+
+    // Both contents fit in the same bucket, so archives have the same size
+    assert_eq!(
+        std::fs::metadata(mlar_small.path()).unwrap().len(),
+        std::fs::metadata(mlar_big.path()).unwrap().len()
+    );
+
+    // Padding is not visible to readers
+    // `mlar list -i big.mla -k samples/test_x25519.pem`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("list")
+        .arg("-i")
+        .arg(mlar_big.path())
+        .arg("-k")
+        .arg(ecc_private);
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stdout(String::from(&file_list));
 }
 
 #[test]
-fn test_verbose_listing() {
+fn test_checkpoint() {
     let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let mlar_repaired_file = NamedTempFile::new("repaired.mla").unwrap();
+    let ecc_public = Path::new("../samples/test_x25519_pub.pem");
+    let ecc_private = Path::new("../samples/test_x25519.pem");
     let testfs = setup();
 
-    // `mlar create -l -o output.mla
+    // `mlar create -o output.mla -p samples/test_x25519_pub.pem --checkpoint 64K file1.bin file2.bin file3.bin`
     let mut cmd = Command::cargo_bin(UTIL).unwrap();
-    cmd.arg("create").arg("-l").arg("-o").arg(mlar_file.path());
+    cmd.arg("create")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg("-p")
+        .arg(ecc_public)
+        .arg("--checkpoint")
+        .arg("64K");
+
+    let mut report = String::new();
+    for path in &testfs.files_archive_order {
+        cmd.arg(path);
+        report.push_str(format!("{}: OK\n", path.to_string_lossy()).as_str());
+    }
+    // Files are listed by name
+    let mut file_list = String::new();
+    for file in &testfs.files {
+        file_list.push_str(format!("{}\n", file.path().to_string_lossy()).as_str());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    // Checkpoints are not visible to readers
+    // `mlar list -i output.mla -k samples/test_x25519.pem`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("list")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-k")
+        .arg(ecc_private);
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stdout(String::from(&file_list));
+
+    // `mlar repair -i output.mla -k samples/test_x25519.pem -p samples/test_x25519_pub.pem -o repaired.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("repair")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-k")
+        .arg(ecc_private)
+        .arg("-p")
+        .arg(ecc_public)
+        .arg("-o")
+        .arg(mlar_repaired_file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stdout(report);
+}
+
+#[test]
+fn test_signature() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let ecc_public = Path::new("../samples/test_x25519_pub.pem");
+    let ecc_private = Path::new("../samples/test_x25519.pem");
+    let sign_private = Path::new("../samples/test_ed25519.pem");
+    let sign_public = Path::new("../samples/test_ed25519_pub.pem");
+    let other_public = Path::new("../samples/test_ed25519_openssh.pub");
+    let testfs = setup();
+
+    // `mlar create -o output.mla -p samples/test_x25519_pub.pem --sign-key samples/test_ed25519.pem file1.bin file2.bin file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg("-p")
+        .arg(ecc_public)
+        .arg("--sign-key")
+        .arg(sign_private);
 
     let mut file_list = String::new();
     for file in &testfs.files {
@@ -657,39 +4971,276 @@ fn test_verbose_listing() {
     let assert = cmd.assert();
     assert.success().stderr(String::from(&file_list));
 
-    // `mlar list -i output.mla`
+    // `mlar list -i output.mla -k samples/test_x25519.pem --trusted-key samples/test_ed25519_pub.pem`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("list")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-k")
+        .arg(ecc_private)
+        .arg("--trusted-key")
+        .arg(sign_public);
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stdout(String::from(&file_list));
+
+    // `mlar info -i output.mla -k samples/test_x25519.pem`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("info")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-k")
+        .arg(ecc_private);
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    let stdout = String::from_utf8(assert.success().get_output().stdout.clone()).unwrap();
+    assert!(stdout.contains("Signature: true\n  Signer: "));
+
+    // Archives signed by another key are refused
+    // `mlar list -i output.mla -k samples/test_x25519.pem --trusted-key samples/test_ed25519_openssh.pub`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("list")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-k")
+        .arg(ecc_private)
+        .arg("--trusted-key")
+        .arg(other_public);
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.failure();
+}
+
+#[test]
+fn test_detached_signature() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let signature_file = NamedTempFile::new("output.sig").unwrap();
+    let ecc_public = Path::new("../samples/test_x25519_pub.pem");
+    let sign_private = Path::new("../samples/test_ed25519.pem");
+    let sign_public = Path::new("../samples/test_ed25519_pub.pem");
+    let other_public = Path::new("../samples/test_ed25519_openssh.pub");
+    let testfs = setup();
+
+    // `mlar create -o output.mla -p samples/test_x25519_pub.pem file1.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg("-p")
+        .arg(ecc_public)
+        .arg(testfs.files[0].path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    // `mlar sign -i output.mla -k samples/test_ed25519.pem -o output.sig`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("sign")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-k")
+        .arg(sign_private)
+        .arg("-o")
+        .arg(signature_file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    // No private key is needed to check the signature
+    // `mlar verify-signature -i output.mla -p samples/test_ed25519_openssh.pub -p samples/test_ed25519_pub.pem -s output.sig`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("verify-signature")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-p")
+        .arg(other_public)
+        .arg("-p")
+        .arg(sign_public)
+        .arg("-s")
+        .arg(signature_file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    let stdout = String::from_utf8(assert.success().get_output().stdout.clone()).unwrap();
+    assert!(stdout.contains(": OK (signed by "));
+
+    // Signatures of other keys are refused
+    // `mlar verify-signature -i output.mla -p samples/test_ed25519_openssh.pub -s output.sig`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("verify-signature")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-p")
+        .arg(other_public)
+        .arg("-s")
+        .arg(signature_file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.failure();
+
+    // As are modified archives
+    let mut data = std::fs::read(mlar_file.path()).unwrap();
+    let last = data.len() - 1;
+    data[last] ^= 1;
+    std::fs::write(mlar_file.path(), data).unwrap();
+    // `mlar verify-signature -i output.mla -p samples/test_ed25519_pub.pem -s output.sig`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("verify-signature")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-p")
+        .arg(sign_public)
+        .arg("-s")
+        .arg(signature_file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.failure();
+}
+
+#[test]
+fn test_keys_env_and_fd() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let ecc_public = std::fs::read_to_string("../samples/test_x25519_pub.pem").unwrap();
+    let ecc_private = std::fs::read("../samples/test_x25519.pem").unwrap();
+    let testfs = setup();
+
+    // `MLAR_PUBLIC_KEY=<key> mlar create -o output.mla file1.bin file2.bin file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.env("MLAR_PUBLIC_KEY", &ecc_public)
+        .arg("create")
+        .arg("-o")
+        .arg(mlar_file.path());
+
+    let mut file_list = String::new();
+    for file in &testfs.files {
+        cmd.arg(file.path());
+        file_list.push_str(format!("{}\n", file.path().to_string_lossy()).as_str());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stderr(String::from(&file_list));
+
+    // `MLAR_PRIVATE_KEY=<key> mlar list -i output.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.env(
+        "MLAR_PRIVATE_KEY",
+        std::str::from_utf8(&ecc_private).unwrap(),
+    )
+    .arg("list")
+    .arg("-i")
+    .arg(mlar_file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stdout(String::from(&file_list));
+
+    // `mlar list -i output.mla --private-key-fd 0 < key`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("list")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("--private-key-fd")
+        .arg("0")
+        .write_stdin(ecc_private);
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stdout(file_list);
+
+    // Without any key, the archive can't be read
     let mut cmd = Command::cargo_bin(UTIL).unwrap();
     cmd.arg("list").arg("-i").arg(mlar_file.path());
 
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.failure();
+}
+
+#[test]
+fn test_password() {
+    let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let testfs = setup();
+
+    // `MLAR_PASSWORD=<password> mlar create -l encrypt -o output.mla file1.bin file2.bin file3.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.env("MLAR_PASSWORD", "my password")
+        .arg("create")
+        .arg("-l")
+        .arg("encrypt")
+        .arg("-o")
+        .arg(mlar_file.path());
+
+    let mut file_list = String::new();
+    for file in &testfs.files {
+        cmd.arg(file.path());
+        file_list.push_str(format!("{}\n", file.path().to_string_lossy()).as_str());
+    }
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stderr(String::from(&file_list));
+
+    // `mlar list --password=<password> -i output.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("list")
+        .arg("--password=my password")
+        .arg("-i")
+        .arg(mlar_file.path());
+
     println!("{:?}", cmd);
     let assert = cmd.assert();
     assert.success().stdout(file_list);
 
-    // `mlar list -v -i output.mla`
+    // `mlar info -v --password=<password> -i output.mla`
     let mut cmd = Command::cargo_bin(UTIL).unwrap();
-    cmd.arg("list").arg("-v").arg("-i").arg(mlar_file.path());
+    cmd.arg("info")
+        .arg("-v")
+        .arg("--password=my password")
+        .arg("-i")
+        .arg(mlar_file.path());
 
     println!("{:?}", cmd);
     let assert = cmd.assert();
-    assert.success();
+    let stdout = assert.success().get_output().stdout.clone();
+    let stdout = String::from_utf8(stdout).unwrap();
+    assert!(stdout.contains("  Recipients: 0\n  Password: yes\n"));
 
-    // `mlar list -vv -i output.mla`
+    // A wrong password can't decrypt the archive
     let mut cmd = Command::cargo_bin(UTIL).unwrap();
-    cmd.arg("list").arg("-vv").arg("-i").arg(mlar_file.path());
+    cmd.arg("list")
+        .arg("--password=wrong password")
+        .arg("-i")
+        .arg(mlar_file.path());
 
     println!("{:?}", cmd);
     let assert = cmd.assert();
-    assert.success();
+    assert.failure();
 }
 
 #[test]
-fn test_extract() {
+fn test_encrypted_private_key() {
     let mlar_file = NamedTempFile::new("output.mla").unwrap();
-    let mut testfs = setup();
+    let passphrase_file = NamedTempFile::new("passphrase.txt").unwrap();
+    let ecc_public = Path::new("../samples/test_x25519_pub.pem");
+    // Encrypted version of samples/test_x25519.pem
+    let ecc_private = Path::new("../samples/test_x25519_encrypted.pem");
+    let testfs = setup();
 
-    // `mlar create -l -o output.mla
+    // `mlar create -o output.mla -p samples/test_x25519_pub.pem file1.bin file2.bin file3.bin`
     let mut cmd = Command::cargo_bin(UTIL).unwrap();
-    cmd.arg("create").arg("-l").arg("-o").arg(mlar_file.path());
+    cmd.arg("create")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg("-p")
+        .arg(ecc_public);
 
     let mut file_list = String::new();
     for file in &testfs.files {
@@ -701,115 +5252,66 @@ fn test_extract() {
     let assert = cmd.assert();
     assert.success().stderr(String::from(&file_list));
 
-    let mut file_list = String::new();
-    for file in &testfs.files {
-        file_list.push_str(format!("{}\n", file.path().to_string_lossy()).as_str());
-    }
-
-    // Test global (with all files)
-
-    // `mlar extract -v -i output.mla -o ouput_dir -g '*'`
-    let output_dir = TempDir::new().unwrap();
-    let mut cmd = Command::cargo_bin(UTIL).unwrap();
-    cmd.arg("extract")
-        .arg("-v")
-        .arg("-i")
-        .arg(mlar_file.path())
-        .arg("-o")
-        .arg(output_dir.path())
-        .arg("-g")
-        .arg("*");
-
-    println!("{:?}", cmd);
-    let assert = cmd.assert();
-    assert.success().stdout(file_list);
-
-    ensure_directory_content(output_dir.path(), &testfs.files);
-
-    // Test linear extraction of all files
-
-    // `mlar extract -v -i output.mla -o ouput_dir`
-    let output_dir = TempDir::new().unwrap();
+    // `mlar list -i output.mla -k samples/test_x25519_encrypted.pem --key-passphrase-file passphrase.txt`
+    passphrase_file.write_binary(b"passphrase\n").unwrap();
     let mut cmd = Command::cargo_bin(UTIL).unwrap();
-    cmd.arg("extract")
-        .arg("-v")
+    cmd.arg("list")
         .arg("-i")
         .arg(mlar_file.path())
-        .arg("-o")
-        .arg(output_dir.path());
+        .arg("-k")
+        .arg(ecc_private)
+        .arg("--key-passphrase-file")
+        .arg(passphrase_file.path());
 
     println!("{:?}", cmd);
     let assert = cmd.assert();
-    assert
-        .success()
-        .stdout("Extracting the whole archive using a linear extraction\n");
-
-    ensure_directory_content(output_dir.path(), &testfs.files);
+    assert.success().stdout(String::from(&file_list));
 
-    // Test extraction of one file explicitly
-    // `mlar extract -v -i output.mla -o ouput_dir file1`
-    let one_filename = &testfs.files_archive_order[0];
-    let mut one_file = Vec::new();
-    loop {
-        match testfs.files.pop() {
-            None => {
-                break;
-            }
-            Some(ntf) => {
-                if ntf.path() == one_filename {
-                    one_file.push(ntf);
-                }
-            }
-        }
-    }
-    let output_dir = TempDir::new().unwrap();
+    // The passphrase can also be given on stdin
     let mut cmd = Command::cargo_bin(UTIL).unwrap();
-    cmd.arg("extract")
-        .arg("-v")
+    cmd.arg("list")
         .arg("-i")
         .arg(mlar_file.path())
-        .arg("-o")
-        .arg(output_dir.path())
-        .arg(one_filename);
+        .arg("-k")
+        .arg(ecc_private)
+        .arg("--key-passphrase-file")
+        .arg("-")
+        .write_stdin("passphrase\n");
 
     println!("{:?}", cmd);
     let assert = cmd.assert();
-    assert
-        .success()
-        .stdout(format!("{}\n", one_filename.to_string_lossy()));
-
-    ensure_directory_content(output_dir.path(), &one_file);
+    assert.success().stdout(file_list);
 
-    // Test extraction of one file through glob
-    // `mlar extract -v -i output.mla -o ouput_dir -g *1*`
-    let output_dir = TempDir::new().unwrap();
+    // Wrong passphrase
+    passphrase_file.write_binary(b"wrong\n").unwrap();
     let mut cmd = Command::cargo_bin(UTIL).unwrap();
-    cmd.arg("extract")
-        .arg("-v")
+    cmd.arg("list")
         .arg("-i")
         .arg(mlar_file.path())
-        .arg("-o")
-        .arg(output_dir.path())
-        .arg("-g")
-        .arg("*file1*");
+        .arg("-k")
+        .arg(ecc_private)
+        .arg("--key-passphrase-file")
+        .arg(passphrase_file.path());
 
     println!("{:?}", cmd);
     let assert = cmd.assert();
-    assert
-        .success()
-        .stdout(format!("{}\n", one_filename.to_string_lossy()));
-
-    ensure_directory_content(output_dir.path(), &one_file);
+    assert.failure();
 }
 
 #[test]
-fn test_cat() {
+fn test_openssh_keys() {
     let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let ssh_public = Path::new("../samples/test_ed25519_openssh.pub");
+    let ssh_private = Path::new("../samples/test_ed25519_openssh");
     let testfs = setup();
 
-    // `mlar create -l -o output.mla
+    // `mlar create -o output.mla -p samples/test_ed25519_openssh.pub file1.bin file2.bin file3.bin`
     let mut cmd = Command::cargo_bin(UTIL).unwrap();
-    cmd.arg("create").arg("-l").arg("-o").arg(mlar_file.path());
+    cmd.arg("create")
+        .arg("-o")
+        .arg(mlar_file.path())
+        .arg("-p")
+        .arg(ssh_public);
 
     let mut file_list = String::new();
     for file in &testfs.files {
@@ -821,98 +5323,293 @@ fn test_cat() {
     let assert = cmd.assert();
     assert.success().stderr(String::from(&file_list));
 
-    // `mlar cat -i output.mla file1`
+    // `mlar list -i output.mla -k samples/test_ed25519_openssh`
     let mut cmd = Command::cargo_bin(UTIL).unwrap();
-    cmd.arg("cat")
+    cmd.arg("list")
         .arg("-i")
         .arg(mlar_file.path())
-        .arg(&testfs.files_archive_order[2]);
+        .arg("-k")
+        .arg(ssh_private);
 
     println!("{:?}", cmd);
     let assert = cmd.assert();
+    assert.success().stdout(file_list);
+}
 
-    let mut expected_content = Vec::new();
-    File::open(&testfs.files_archive_order[2])
-        .unwrap()
-        .read_to_end(&mut expected_content)
+/// DER encoding of `parts`, tagged with `tag`
+fn der(tag: u8, parts: &[&[u8]]) -> Vec<u8> {
+    let content = parts.concat();
+    let mut out = vec![tag];
+    if content.len() < 0x80 {
+        out.push(content.len() as u8);
+    } else {
+        out.push(0x82);
+        out.extend_from_slice(&(content.len() as u16).to_be_bytes());
+    }
+    out.extend_from_slice(&content);
+    out
+}
+
+/// Answer HTTP Range requests (`bytes=<start>-<end>`) on `data`, forever
+#[cfg(feature = "http")]
+fn serve_ranges(listener: TcpListener, data: &[u8]) {
+    for stream in listener.incoming() {
+        let mut stream = stream.unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0u8; 4096];
+        while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+            let n = stream.read(&mut buf).unwrap();
+            if n == 0 {
+                break;
+            }
+            request.extend_from_slice(&buf[..n]);
+        }
+        let headers = String::from_utf8_lossy(&request).to_lowercase();
+        let (start, end) = headers
+            .lines()
+            .find_map(|line| line.strip_prefix("range: bytes="))
+            .and_then(|range| range.trim().split_once('-'))
+            .map(|(start, end)| {
+                let start: usize = start.parse().unwrap();
+                let end: usize = end.parse().unwrap();
+                (start, std::cmp::min(end, data.len() - 1))
+            })
+            .unwrap();
+        let response = format!(
+            "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-{}/{}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            start,
+            end,
+            data.len(),
+            end + 1 - start
+        );
+        stream.write_all(response.as_bytes()).unwrap();
+        stream.write_all(&data[start..=end]).unwrap();
+    }
+}
+
+/// Answer one timestamp request, with an unsigned token issued at
+/// `gen_time`, and return the requested digest
+fn fake_tsa(listener: TcpListener, gen_time: &str) -> Vec<u8> {
+    let (mut stream, _) = listener.accept().unwrap();
+    let mut request = Vec::new();
+    let mut buf = [0u8; 4096];
+    let body = loop {
+        let n = stream.read(&mut buf).unwrap();
+        assert!(n > 0, "Truncated timestamp request");
+        request.extend_from_slice(&buf[..n]);
+        if let Some(split) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+            let headers = String::from_utf8_lossy(&request[..split]).to_lowercase();
+            assert!(headers.starts_with("post "));
+            assert!(headers.contains("content-type: application/timestamp-query"));
+            let length: usize = headers
+                .lines()
+                .find_map(|line| line.strip_prefix("content-length:"))
+                .unwrap()
+                .trim()
+                .parse()
+                .unwrap();
+            if request.len() >= split + 4 + length {
+                break request[split + 4..split + 4 + length].to_vec();
+            }
+        }
+    };
+
+    // The SHA-256 imprint is followed by the nonce
+    let pos = body.windows(2).position(|w| w == [0x04, 0x20]).unwrap();
+    let digest = body[pos + 2..pos + 34].to_vec();
+    assert_eq!(body[pos + 34], 0x02);
+    let nonce_len = body[pos + 35] as usize;
+    let nonce = &body[pos + 36..pos + 36 + nonce_len];
+
+    let sha256 = der(
+        0x30,
+        &[
+            &der(
+                0x06,
+                &[&[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01]],
+            ),
+            &der(0x05, &[]),
+        ],
+    );
+    let tst_info = der(
+        0x30,
+        &[
+            &der(0x02, &[&[1]]),
+            &der(0x06, &[&[0x2A, 0x03, 0x04]]),
+            &der(0x30, &[&sha256, &der(0x04, &[&digest])]),
+            &der(0x02, &[&[42]]),
+            &der(0x18, &[gen_time.as_bytes()]),
+            &der(0x02, &[nonce]),
+        ],
+    );
+    let encap = der(
+        0x30,
+        &[
+            &der(
+                0x06,
+                &[&[
+                    0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x09, 0x10, 0x01, 0x04,
+                ]],
+            ),
+            &der(0xA0, &[&der(0x04, &[&tst_info])]),
+        ],
+    );
+    let signed_data = der(
+        0x30,
+        &[
+            &der(0x02, &[&[3]]),
+            &der(0x31, &[]),
+            &encap,
+            &der(0x31, &[]),
+        ],
+    );
+    let token = der(
+        0x30,
+        &[
+            &der(
+                0x06,
+                &[&[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x07, 0x02]],
+            ),
+            &der(0xA0, &[&signed_data]),
+        ],
+    );
+    let response = der(0x30, &[&der(0x30, &[&der(0x02, &[&[0]])]), &token]);
+
+    stream
+        .write_all(
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/timestamp-reply\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                response.len()
+            )
+            .as_bytes(),
+        )
         .unwrap();
-    assert_eq!(assert.success().get_output().stdout, expected_content);
+    stream.write_all(&response).unwrap();
+    digest
 }
 
 #[test]
-fn test_keygen() {
-    // Gen a keypair, create and list an archive using them
+fn test_timestamp() {
     let mlar_file = NamedTempFile::new("output.mla").unwrap();
-    let output_dir = TempDir::new().unwrap();
-    let base_name = output_dir.path().join("key");
+    let other_file = NamedTempFile::new("other.mla").unwrap();
     let testfs = setup();
 
-    // `mlar keygen tempdir/key`
-    let mut cmd = Command::cargo_bin(UTIL).unwrap();
-    cmd.arg("keygen").arg(&base_name);
-    cmd.assert().success();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/tsa", listener.local_addr().unwrap());
+    let tsa = std::thread::spawn(move || fake_tsa(listener, "20261014120000Z"));
 
-    // `mlar create -p tempdir/key.pub -o output.mla file1 file2 file3`
+    // `mlar create -l -o output.mla --timestamp-url http://127.0.0.1:port/tsa file1.bin file2.bin file3.bin`
     let mut cmd = Command::cargo_bin(UTIL).unwrap();
     cmd.arg("create")
-        .arg("-p")
-        .arg(base_name.with_extension("pub"))
+        .arg("-l")
         .arg("-o")
-        .arg(mlar_file.path());
-
-    let mut file_list = String::new();
+        .arg(mlar_file.path())
+        .arg("--timestamp-url")
+        .arg(&url);
     for file in &testfs.files {
         cmd.arg(file.path());
-        file_list.push_str(format!("{}\n", file.path().to_string_lossy()).as_str());
     }
 
     println!("{:?}", cmd);
     let assert = cmd.assert();
-    assert.success().stderr(String::from(&file_list));
+    assert.success();
 
-    // `mlar list -k tempdir/key -i output.mla`
+    // The token is requested for the whole archive, and stored alongside
+    let digest = tsa.join().unwrap();
+    let content = std::fs::read(mlar_file.path()).unwrap();
+    assert_eq!(digest, Sha256::digest(&content).to_vec());
+    let token_path = format!("{}.tsr", mlar_file.path().display());
+    assert!(Path::new(&token_path).is_file());
+
+    // `mlar info -i output.mla`
     let mut cmd = Command::cargo_bin(UTIL).unwrap();
-    cmd.arg("list")
-        .arg("-k")
-        .arg(base_name)
+    cmd.arg("info").arg("-i").arg(mlar_file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    let stdout = String::from_utf8(assert.success().get_output().stdout.clone()).unwrap();
+    assert!(stdout.contains("Timestamp: 2026-10-14 12:00:00 UTC"));
+
+    // `mlar verify -i output.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("verify").arg("-i").arg(mlar_file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    let stdout = String::from_utf8(assert.success().get_output().stdout.clone()).unwrap();
+    assert!(stdout.contains("Timestamp: OK (2026-10-14 12:00:00 UTC)"));
+
+    // The token does not match another archive
+    // `mlar create -l -o other.mla file1.bin`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("create")
+        .arg("-l")
+        .arg("-o")
+        .arg(other_file.path())
+        .arg(testfs.files[0].path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success();
+
+    // `mlar verify -i other.mla --timestamp output.mla.tsr`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("verify")
         .arg("-i")
-        .arg(mlar_file.path());
+        .arg(other_file.path())
+        .arg("--timestamp")
+        .arg(&token_path);
 
     println!("{:?}", cmd);
     let assert = cmd.assert();
-    assert.success().stdout(file_list);
+    let stdout = String::from_utf8(assert.failure().get_output().stdout.clone()).unwrap();
+    assert!(stdout.contains("Timestamp: FAIL (hash mismatch)"));
 }
 
 #[test]
-fn test_verbose_info() {
+fn test_fec() {
     let ecc_public = Path::new("../samples/test_x25519_pub.pem");
     let ecc_private = Path::new("../samples/test_x25519.pem");
-    let ecc_public_2 = Path::new("../samples/test_x25519_2_pub.pem");
-
     let mlar_file = NamedTempFile::new("output.mla").unwrap();
+    let mlar_repaired_file = NamedTempFile::new("repaired.mla").unwrap();
     let testfs = setup();
 
-    // `mlar create -l -o output.mla
+    // `mlar create -l compress -l encrypt -l fec -p samples/test_x25519_pub.pem -o output.mla file1.bin file2.bin file3.bin`
     let mut cmd = Command::cargo_bin(UTIL).unwrap();
-    cmd.arg("create").arg("-o").arg(mlar_file.path());
-    cmd.arg("-l").arg("compress");
-    cmd.arg("-l").arg("encrypt");
-    cmd.arg("-p").arg(ecc_public);
-    cmd.arg("-p").arg(ecc_public_2);
-
-    let mut file_list = String::new();
+    cmd.arg("create")
+        .arg("-l")
+        .arg("compress")
+        .arg("-l")
+        .arg("encrypt")
+        .arg("-l")
+        .arg("fec")
+        .arg("-p")
+        .arg(ecc_public)
+        .arg("-o")
+        .arg(mlar_file.path());
+    let mut expected = String::new();
     for file in &testfs.files {
         cmd.arg(file.path());
-        file_list.push_str(format!("{}\n", file.path().to_string_lossy()).as_str());
+        expected.push_str(format!("{}: OK\n", file.path().to_string_lossy()).as_str());
     }
 
     println!("{:?}", cmd);
     let assert = cmd.assert();
-    assert.success().stderr(String::from(&file_list));
+    assert.success();
 
-    // `mlar info -k <key> -i output.mla`
+    // Damage a 8 KiB area, and a few bytes elsewhere
+    let mut data = std::fs::read(mlar_file.path()).unwrap();
+    let len = data.len();
+    for byte in &mut data[len / 3..len / 3 + 8 * 1024] {
+        *byte = 0;
+    }
+    data[len / 2] ^= 0xFF;
+    data[len - 100] ^= 0xFF;
+    std::fs::write(mlar_file.path(), data).unwrap();
+
+    // `mlar verify -k samples/test_x25519.pem -i output.mla`
     let mut cmd = Command::cargo_bin(UTIL).unwrap();
-    cmd.arg("info")
+    cmd.arg("verify")
         .arg("-k")
         .arg(ecc_private)
         .arg("-i")
@@ -920,23 +5617,46 @@ fn test_verbose_info() {
 
     println!("{:?}", cmd);
     let assert = cmd.assert();
-    assert.success().stdout(
-        "Format version: 1
-Encryption: true
-Compression: true
-",
-    );
+    assert.success().stdout(expected.clone());
 
-    // `mlar info -k <key> -v -i output.mla`
+    // `mlar info -k samples/test_x25519.pem -i output.mla`
     let mut cmd = Command::cargo_bin(UTIL).unwrap();
     cmd.arg("info")
         .arg("-k")
         .arg(ecc_private)
-        .arg("-v")
         .arg("-i")
         .arg(mlar_file.path());
 
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    let stdout = String::from_utf8(assert.success().get_output().stdout.clone()).unwrap();
+    assert!(stdout.contains("Error correction: true"));
+
+    // `mlar repair -i output.mla -k samples/test_x25519.pem -p samples/test_x25519_pub.pem -o repaired.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("repair")
+        .arg("-i")
+        .arg(mlar_file.path())
+        .arg("-k")
+        .arg(ecc_private)
+        .arg("-p")
+        .arg(ecc_public)
+        .arg("-o")
+        .arg(mlar_repaired_file.path());
+
     println!("{:?}", cmd);
     let assert = cmd.assert();
     assert.success();
+
+    // `mlar verify -k samples/test_x25519.pem -i repaired.mla`
+    let mut cmd = Command::cargo_bin(UTIL).unwrap();
+    cmd.arg("verify")
+        .arg("-k")
+        .arg(ecc_private)
+        .arg("-i")
+        .arg(mlar_repaired_file.path());
+
+    println!("{:?}", cmd);
+    let assert = cmd.assert();
+    assert.success().stdout(expected);
 }